@@ -0,0 +1,122 @@
+//! Throughput of GET with a growing number of concurrent clients, alone and next to a client
+//! writing continuously. Reads only take shared locks, so throughput should scale with the
+//! number of readers rather than stay flat, and a writer shouldn't hold them back.
+//!
+//! Run with `cargo bench --bench concurrent_reads`.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use walrus::client::Client;
+
+const KEYS: usize = 10_000;
+const RUN_FOR: Duration = Duration::from_secs(2);
+const CONCURRENCY: [usize; 5] = [1, 2, 4, 8, 16];
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let addr = start_server().await;
+
+        let mut client = connect(&addr).await;
+        for i in 0..KEYS {
+            client
+                .set(key(i), Bytes::from(format!("value:{i}")), None)
+                .await
+                .unwrap();
+        }
+
+        println!(
+            "{:>8} {:>14} {:>14}",
+            "clients", "reads ops/s", "+writer ops/s"
+        );
+        for clients in CONCURRENCY {
+            let alone = measure(&addr, clients, false).await;
+            let with_writer = measure(&addr, clients, true).await;
+            println!("{clients:>8} {alone:>14.0} {with_writer:>14.0}");
+        }
+    });
+}
+
+/// GETs per second over `RUN_FOR` across `clients` connections.
+async fn measure(addr: &str, clients: usize, writer: bool) -> f64 {
+    let stop = Arc::new(AtomicBool::new(false));
+    let ops = Arc::new(AtomicU64::new(0));
+
+    let writer = writer.then(|| {
+        let stop = stop.clone();
+        let addr = addr.to_string();
+        tokio::spawn(async move {
+            let mut client = connect(&addr).await;
+            let mut i = 0;
+            while !stop.load(Ordering::Relaxed) {
+                client
+                    .set(key(i % KEYS), Bytes::from("rewritten"), None)
+                    .await
+                    .unwrap();
+                i += 1;
+            }
+        })
+    });
+
+    let mut readers = Vec::with_capacity(clients);
+    for n in 0..clients {
+        let stop = stop.clone();
+        let ops = ops.clone();
+        let mut client = connect(addr).await;
+        readers.push(tokio::spawn(async move {
+            let mut i = n;
+            let mut done = 0;
+            while !stop.load(Ordering::Relaxed) {
+                client.get(key(i % KEYS)).await.unwrap();
+                i += clients;
+                done += 1;
+            }
+            ops.fetch_add(done, Ordering::Relaxed);
+        }));
+    }
+
+    let start = Instant::now();
+    tokio::time::sleep(RUN_FOR).await;
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.await.unwrap();
+    }
+    let elapsed = start.elapsed();
+    if let Some(writer) = writer {
+        writer.await.unwrap();
+    }
+
+    ops.load(Ordering::Relaxed) as f64 / elapsed.as_secs_f64()
+}
+
+async fn start_server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(walrus::server::run(listener, addr.port(), None, None));
+    addr.to_string()
+}
+
+async fn connect(addr: &str) -> Client {
+    for _ in 0..100 {
+        if let Ok(client) = Client::connect(addr, None, None).await {
+            return client;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("server at {addr} isn't accepting connections");
+}
+
+fn key(i: usize) -> Bytes {
+    Bytes::from(format!("key:{i}"))
+}
@@ -0,0 +1,240 @@
+//! Audit log of the write commands executed by the server, for deployments that must record
+//! who changed what.
+//!
+//! Write commands sent by clients are recorded once executed, commands streamed from a master
+//! or replayed from the append only file are not. They are appended to a file rotated once it
+//! reaches a maximum size, see `Builder::audit_log`, and sent as `AuditEntry` values to a
+//! channel, see `Builder::audit_channel`. Each line of the file is an entry such as
+//!
+//! ```text
+//! 1700000000.123456 id=5 addr=127.0.0.1:50000 name=app cmd=set keys="key" result=ok "set" "key" "value"
+//! ```
+
+use bytes::Bytes;
+use std::{
+    fmt::{self, Write as _},
+    io,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::Sender;
+use tracing::warn;
+
+use crate::{frame::Frame, log::rotate::RotatingFile, monitor::quote};
+
+/// Size the audit log file is rotated at by default, 64MB.
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Number of rotated audit log files kept by default.
+pub const DEFAULT_MAX_FILES: usize = 4;
+
+/// A write command executed by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    /// Time the command completed.
+    pub time: SystemTime,
+    /// Id of the connection of the client, as reported by `CLIENT ID`.
+    pub client_id: u64,
+    /// Address of the client, `None` for Unix sockets.
+    pub addr: Option<SocketAddr>,
+    /// Name the client set with `CLIENT SETNAME`.
+    pub client_name: Option<Bytes>,
+    /// Name of the command, the original one if renamed.
+    pub command: &'static str,
+    /// Keys of the command.
+    pub keys: Vec<Bytes>,
+    /// Arguments of the command, its name included.
+    pub args: Vec<Bytes>,
+    /// Whether the command replied with an error, such as a write refused by a read only
+    /// server.
+    pub failed: bool,
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut line = String::new();
+
+        let _ = write!(
+            line,
+            "{}.{:06} id={} addr=",
+            time.as_secs(),
+            time.subsec_micros(),
+            self.client_id
+        );
+        match self.addr {
+            Some(addr) => {
+                let _ = write!(line, "{addr}");
+            }
+            None => line.push_str("unix"),
+        }
+        line.push_str(" name=");
+        if let Some(name) = &self.client_name {
+            line.push_str(&String::from_utf8_lossy(name));
+        }
+        let _ = write!(line, " cmd={} keys=", self.command);
+        for (i, key) in self.keys.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+            quote(&mut line, key);
+        }
+        line.push_str(if self.failed {
+            " result=err"
+        } else {
+            " result=ok"
+        });
+        for arg in &self.args {
+            line.push(' ');
+            quote(&mut line, arg);
+        }
+
+        f.write_str(&line)
+    }
+}
+
+/// Destinations of the audit log of a server, none if disabled.
+pub(crate) struct AuditLog {
+    /// Lines to append to the audit log file, written by a thread of its own so commands don't
+    /// wait for the disk.
+    file: Option<mpsc::Sender<String>>,
+    /// Thread writing the file, joined when the log is dropped so pending lines are written.
+    writer: Option<thread::JoinHandle<()>>,
+    /// Channel entries are sent to.
+    channel: Option<Sender<AuditEntry>>,
+    /// Number of entries lost because the channel was full or closed.
+    dropped: AtomicU64,
+}
+
+impl AuditLog {
+    /// Audit log sent to nowhere.
+    pub(crate) fn disabled() -> AuditLog {
+        AuditLog {
+            file: None,
+            writer: None,
+            channel: None,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Audit log written to the file at `path`, rotated when it reaches `max_size` bytes with
+    /// `max_files` rotated files kept, and sent to `channel`.
+    pub(crate) fn new(
+        file: Option<(&Path, u64, usize)>,
+        channel: Option<Sender<AuditEntry>>,
+    ) -> io::Result<AuditLog> {
+        let mut log = AuditLog::disabled();
+        log.channel = channel;
+
+        if let Some((path, max_size, max_files)) = file {
+            let mut file = RotatingFile::open(path, max_size, max_files)?;
+            let (sender, receiver) = mpsc::channel::<String>();
+
+            let writer = thread::Builder::new()
+                .name("walrus-audit".into())
+                .spawn(move || {
+                    let mut failed = false;
+                    let mut write = |result: io::Result<()>| {
+                        // Failures are only reported once, the disk may stay full for a while.
+                        if let Err(err) = result
+                            && !failed
+                        {
+                            warn!(%err, "Failed to write to the audit log");
+                            failed = true;
+                        }
+                    };
+
+                    while let Ok(line) = receiver.recv() {
+                        write(file.write(line.as_bytes()));
+                        // Lines are flushed once no more are waiting.
+                        while let Ok(line) = receiver.try_recv() {
+                            write(file.write(line.as_bytes()));
+                        }
+                        write(file.flush());
+                    }
+                })?;
+
+            log.file = Some(sender);
+            log.writer = Some(writer);
+        }
+
+        Ok(log)
+    }
+
+    /// Returns `true` if write commands are recorded.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.file.is_some() || self.channel.is_some()
+    }
+
+    /// Record the write command `frame` executed by the connection `client_id`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &self,
+        client_id: u64,
+        addr: Option<SocketAddr>,
+        client_name: Option<Bytes>,
+        command: &'static str,
+        keys: Vec<Bytes>,
+        frame: &Frame,
+        failed: bool,
+    ) {
+        let args = match frame {
+            Frame::Array(args) => args
+                .iter()
+                .map(|arg| match arg {
+                    Frame::Bulk(bytes) | Frame::Simple(bytes) => bytes.clone(),
+                    other => Bytes::from(other.to_string()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let entry = AuditEntry {
+            time: SystemTime::now(),
+            client_id,
+            addr,
+            client_name,
+            command,
+            keys,
+            args,
+            failed,
+        };
+
+        if let Some(file) = &self.file {
+            // The writer only stops once the log is dropped.
+            let _ = file.send(format!("{entry}\n"));
+        }
+
+        if let Some(channel) = &self.channel {
+            // Commands never wait for a slow consumer.
+            if channel.try_send(entry).is_err() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Number of entries lost because the channel was full or closed.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Reset the number of entries lost, with `CONFIG RESETSTAT`.
+    pub(crate) fn reset_dropped(&self) {
+        self.dropped.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once it wrote every line.
+        self.file.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
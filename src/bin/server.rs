@@ -1,21 +1,47 @@
 use clap::Parser;
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 use tokio::io::{self};
 use tokio::net::TcpListener;
-use walrus::server;
+use tracing_subscriber::{filter, fmt, prelude::*};
+use walrus::log::{self, LogLevel};
+use walrus::server::{self, Builder, ConfigFile, DebugCommand, Listeners};
 
-#[cfg(not(target_env = "msvc"))]
-use jemallocator::Jemalloc;
+#[cfg(all(
+    feature = "jemalloc",
+    not(feature = "mimalloc"),
+    not(target_env = "msvc")
+))]
+#[global_allocator]
+static GLOBAL: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-#[cfg(not(target_env = "msvc"))]
+#[cfg(feature = "mimalloc")]
 #[global_allocator]
-static GLOBAL: Jemalloc = Jemalloc;
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 #[derive(Parser)]
 #[command(version, about, long_about= None)]
 struct Args {
+    /// Optionally take a configuration file from the user.
+    #[arg(
+        short,
+        long,
+        help = "Reads the configuration from a TOML file, options given on the command line take precedence."
+    )]
+    config: Option<PathBuf>,
+    /// Reload the configuration file when it changes.
+    #[arg(
+        long = "watch-config",
+        requires = "config",
+        help = "Applies the changes made to the configuration file without a restart, for the parameters CONFIG SET can change."
+    )]
+    watch_config: bool,
     /// Optionally take port from the user.
     #[arg(short, long, help = "Sets the port to use for the server.")]
-    port: Option<i16>,
+    port: Option<u16>,
     /// Optionally take initial read buffer size in KB from the user.
     #[arg(
         short,
@@ -30,21 +56,695 @@ struct Args {
         help = "Sets the initial write buffer size for the server in KB."
     )]
     write_buffer_size: Option<u16>,
+    /// Optionally take the maximum number of clients from the user.
+    #[arg(
+        long,
+        help = "Sets the maximum number of connected clients, 10000 by default."
+    )]
+    maxclients: Option<usize>,
+    /// Optionally take the log level from the user.
+    #[arg(
+        long,
+        help = "Sets the least severe tracing events logged: debug (TRACE), verbose (DEBUG), notice (INFO), warning (WARN and ERROR) or nothing. notice by default."
+    )]
+    loglevel: Option<LogLevel>,
+    /// Run in the background.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        help = "Runs in the background, detached from the terminal. Messages are lost unless --logfile is set."
+    )]
+    daemonize: bool,
+    /// Write the process id to a file.
+    #[arg(
+        long,
+        help = "Writes the process id to this file while the server runs, such as for systemd services of Type=forking."
+    )]
+    pidfile: Option<PathBuf>,
+    /// Log to a file instead of stdout.
+    #[arg(
+        long,
+        help = "Logs to this file instead of stdout, appending to it. Changed at runtime with CONFIG SET logfile."
+    )]
+    logfile: Option<PathBuf>,
+    /// Size the log file is rotated at.
+    #[arg(
+        long = "log-max-size",
+        help = "Rotates the log file once it reaches this many bytes. 0, the default, never rotates."
+    )]
+    log_max_size: Option<u64>,
+    /// Number of rotated log files kept.
+    #[arg(
+        long = "log-max-files",
+        help = "Keeps this many rotated log files, 4 by default."
+    )]
+    log_max_files: Option<usize>,
+    /// Interval the log file is rotated at.
+    #[arg(
+        long = "log-rotate-interval",
+        help = "Rotates the log file once it was written to for this many seconds, such as 86400 for daily files. 0, the default, never rotates."
+    )]
+    log_rotate_interval: Option<u64>,
+    /// Optionally take the persistence directory from the user.
+    #[arg(
+        short,
+        long,
+        help = "Sets the directory snapshots are saved to and loaded from."
+    )]
+    dir: Option<PathBuf>,
+    /// Log write commands to the append only file.
+    #[arg(
+        long,
+        help = "Logs write commands to an append only file, loaded on startup instead of the snapshot."
+    )]
+    appendonly: bool,
+    /// Import a Redis dump on startup.
+    #[arg(
+        long = "load-rdb",
+        help = "Imports the dataset from a Redis dump file instead of the persisted one, then saves it."
+    )]
+    load_rdb: Option<PathBuf>,
+    /// Refuse clients on other hosts.
+    #[arg(
+        long = "protected-mode",
+        value_parser = parse_yes_no,
+        help = "Refuses clients on other hosts when listening on an address they can reach: yes or no. yes by default."
+    )]
+    protected_mode: Option<bool>,
+    /// Refuse write commands.
+    #[arg(
+        long = "read-only",
+        help = "Refuses write commands while serving reads, until CONFIG SET read-only no."
+    )]
+    read_only: bool,
+    /// Connections allowed to use DEBUG.
+    #[arg(
+        long = "enable-debug-command",
+        help = "Allows the DEBUG command: no, yes or local for connections from the loopback interface only. no by default."
+    )]
+    enable_debug_command: Option<DebugCommand>,
+    /// Commands to rename or disable.
+    #[arg(
+        long = "rename-command",
+        num_args = 2,
+        value_names = ["COMMAND", "NEW_NAME"],
+        action = clap::ArgAction::Append,
+        help = "Renames a command, clients then only know it by its new name. An empty name (\"\") disables it. May be given several times."
+    )]
+    rename_command: Vec<String>,
+    /// Run as a node of a cluster.
+    #[arg(
+        long = "cluster-enabled",
+        help = "Runs the server as a cluster node, serving only the keys of the slots it owns."
+    )]
+    cluster_enabled: bool,
+    /// Optionally take the cluster bus port from the user.
+    #[arg(
+        long = "cluster-port",
+        help = "Sets the port other cluster nodes link to, the server port plus 10000 by default."
+    )]
+    cluster_port: Option<u16>,
+    /// Optionally take the port of the metrics endpoint from the user.
+    #[arg(
+        long = "metrics-port",
+        help = "Serves Prometheus metrics over HTTP at /metrics on this port, on the first bind address."
+    )]
+    metrics_port: Option<u16>,
+    /// Record write commands in an audit log.
+    #[arg(
+        long = "audit-log",
+        help = "Records every write command executed, with its client and keys, in this file."
+    )]
+    audit_log: Option<PathBuf>,
+    /// Size the audit log is rotated at.
+    #[arg(
+        long = "audit-log-max-size",
+        help = "Rotates the audit log once it reaches this many bytes, 64MB by default. 0 never rotates."
+    )]
+    audit_log_max_size: Option<u64>,
+    /// Number of rotated audit logs kept.
+    #[arg(
+        long = "audit-log-max-files",
+        help = "Keeps this many rotated audit logs, 4 by default."
+    )]
+    audit_log_max_files: Option<usize>,
+    /// Run as a sentinel.
+    #[arg(
+        long,
+        help = "Runs the server as a sentinel, monitoring masters added with SENTINEL MONITOR and failing them over."
+    )]
+    sentinel: bool,
+    /// Addresses to listen on.
+    #[arg(
+        short,
+        long,
+        num_args = 1..,
+        help = "Sets the addresses the server listens on, several may be given. 127.0.0.1 by default."
+    )]
+    bind: Vec<String>,
+    /// Spread accepting connections over the cores.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        help = "Binds one listener per core with SO_REUSEPORT, the kernel balances connections between them."
+    )]
+    reuseport: bool,
+    /// Optionally take a Unix socket path from the user.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        help = "Accepts connections on a Unix socket at this path as well."
+    )]
+    unixsocket: Option<PathBuf>,
+    /// Permissions of the Unix socket.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        requires = "unixsocket",
+        value_parser = parse_octal,
+        help = "Sets the permissions of the Unix socket, in octal such as 700."
+    )]
+    unixsocketperm: Option<u32>,
+    /// Optionally take a dedicated TLS port from the user.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-port",
+        requires = "tls_cert_file",
+        help = "Accepts TLS connections on this port as well. Without it the server port accepts TLS connections only, if a certificate is set."
+    )]
+    tls_port: Option<u16>,
+    /// Certificate of the server, enables TLS.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-cert-file",
+        requires = "tls_key_file",
+        help = "Sets the PEM file with the certificate chain TLS connections are accepted with."
+    )]
+    tls_cert_file: Option<PathBuf>,
+    /// Private key of the server certificate.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-key-file",
+        requires = "tls_cert_file",
+        help = "Sets the PEM file with the private key of the server certificate."
+    )]
+    tls_key_file: Option<PathBuf>,
+    /// CA client certificates are verified against.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-ca-cert-file",
+        requires = "tls_cert_file",
+        help = "Sets the PEM file with the CA certificates client certificates are verified against."
+    )]
+    tls_ca_cert_file: Option<PathBuf>,
+    /// Require clients to present a certificate.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-auth-clients",
+        requires = "tls_ca_cert_file",
+        help = "Refuses TLS clients without a certificate signed by the CA."
+    )]
+    tls_auth_clients: bool,
+    /// Collector the spans of commands are exported to.
+    #[cfg(feature = "otel")]
+    #[arg(
+        long = "otel-endpoint",
+        help = "Exports a span for each command executed over OTLP to the collector at this URL, such as http://localhost:4317."
+    )]
+    otel_endpoint: Option<String>,
+}
+
+/// Tracing set up by `init_tracing`, the spans not exported yet are flushed when dropped.
+struct Tracing {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
 }
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    let args = Args::parse();
-    let port = match args.port {
-        Some(port) => port,
-        // Default port
-        None => 6380,
+fn main() -> io::Result<()> {
+    let mut args = Args::parse();
+    let config_file = match &args.config {
+        Some(path) => {
+            let mut file = ConfigFile::load(path)?;
+            merge_config_file(&mut args, &mut file)?;
+            Some(file)
+        }
+        None => None,
     };
-    let read_buffer_size = args.read_buffer_size;
-    let write_buffer_size = args.write_buffer_size;
+    if args.bind.is_empty() {
+        args.bind.push("127.0.0.1".to_string());
+    }
+
+    // Forked before the runtime starts its threads, only the calling thread is copied.
+    #[cfg(unix)]
+    if args.daemonize {
+        daemonize()?;
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args, config_file))
+}
+
+async fn run(args: Args, config_file: Option<ConfigFile>) -> io::Result<()> {
+    if let Some(level) = args.loglevel {
+        log::set_level(level);
+    }
+    if let Some(size) = args.log_max_size {
+        log::set_max_size(size);
+    }
+    if let Some(files) = args.log_max_files {
+        log::set_max_files(files);
+    }
+    if let Some(secs) = args.log_rotate_interval {
+        log::set_rotate_interval(Duration::from_secs(secs));
+    }
+    if let Some(path) = &args.logfile {
+        log::set_file(Some(path))?;
+    }
+    let _tracing = init_tracing(&args)?;
+    let _pidfile = args.pidfile.as_deref().and_then(Pidfile::create);
+    // Sentinels listen on their own port by default, so one can run next to a server.
+    let mut port = args
+        .port
+        .unwrap_or(if args.sentinel { 26380 } else { 6380 });
+
+    // Sockets passed by systemd replace the addresses to bind.
+    #[cfg(unix)]
+    let activated = activated_listeners(&args)?;
+    #[cfg(not(unix))]
+    let activated = None;
+    let listeners = match activated {
+        Some((listeners, activated_port)) => {
+            port = activated_port.unwrap_or(port);
+            listeners
+        }
+        None => listeners(&args, port).await?,
+    };
+    #[cfg(unix)]
+    let listeners = match &args.unixsocket {
+        Some(path) => listeners.with_unix(server::bind_unix(path, args.unixsocketperm)?),
+        None => listeners,
+    };
+
+    let cluster_bus = if args.cluster_enabled {
+        let bus_port = match args.cluster_port {
+            Some(bus_port) => bus_port,
+            None => port.checked_add(10000).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the default cluster port is out of range, set --cluster-port",
+                )
+            })?,
+        };
+        // The bus is reached on the first address, the one nodes announce.
+        Some(TcpListener::bind((args.bind[0].as_str(), bus_port)).await?)
+    } else {
+        None
+    };
+
+    let metrics = match args.metrics_port {
+        Some(metrics_port) => Some(TcpListener::bind((args.bind[0].as_str(), metrics_port)).await?),
+        None => None,
+    };
+
+    let mut builder = Builder::new(listeners);
+    if let Some(file) = config_file {
+        builder = builder
+            .config_file(file)?
+            .watch_config_file(args.watch_config);
+    }
+    builder = builder.port(port).sentinel(args.sentinel);
+    #[cfg(unix)]
+    {
+        builder = builder.sd_notify(true);
+    }
+    if args.appendonly {
+        builder = builder.appendonly(true);
+    }
+    if let Some(maxclients) = args.maxclients {
+        builder = builder.max_connections(maxclients);
+    }
+    if let Some(size) = args.read_buffer_size {
+        builder = builder.read_buffer_size(size);
+    }
+    if let Some(size) = args.write_buffer_size {
+        builder = builder.write_buffer_size(size);
+    }
+    if let Some(dir) = args.dir {
+        builder = builder.dir(dir);
+    }
+    if let Some(protected) = args.protected_mode {
+        builder = builder.protected_mode(protected);
+    }
+    if args.read_only {
+        builder = builder.read_only(true);
+    }
+    if let Some(mode) = args.enable_debug_command {
+        builder = builder.enable_debug_command(mode);
+    }
+    for rename in args.rename_command.chunks(2) {
+        builder = builder.rename_command(&rename[0], &rename[1]);
+    }
+    if let Some(path) = args.load_rdb {
+        builder = builder.load_rdb(path);
+    }
+    if let Some(bus) = cluster_bus {
+        builder = builder.cluster_bus(bus);
+    }
+    if let Some(listener) = metrics {
+        builder = builder.metrics(listener);
+    }
+    if let Some(path) = args.audit_log {
+        builder = builder.audit_log(path);
+    }
+    if let Some(size) = args.audit_log_max_size {
+        builder = builder.audit_log_max_size(size);
+    }
+    if let Some(files) = args.audit_log_max_files {
+        builder = builder.audit_log_max_files(files);
+    }
+
+    builder.run(shutdown_signal()?).await;
+    Ok(())
+}
+
+/// Detach the process from the terminal, the parent exits while the child goes on as the
+/// server in a session of its own, with stdin, stdout and stderr redirected to `/dev/null`.
+#[cfg(unix)]
+fn daemonize() -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: no other thread runs yet, the child is a complete copy of the process.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => std::process::exit(0),
+    }
+
+    // SAFETY: `setsid` has no preconditions, it fails if the process leads a group already.
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let null = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in 0..=2 {
+        // SAFETY: both file descriptors are open, `fd` is replaced by a copy of `null`.
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// File holding the process id while the server runs, removed when dropped.
+struct Pidfile(PathBuf);
+
+impl Pidfile {
+    /// Write the process id to `path`. Like Redis the server runs even if it can't be written,
+    /// `None` is returned then.
+    fn create(path: &Path) -> Option<Pidfile> {
+        match std::fs::write(path, format!("{}\n", std::process::id())) {
+            Ok(()) => Some(Pidfile(path.to_path_buf())),
+            Err(err) => {
+                tracing::warn!(%err, path = %path.display(), "Failed to write the pidfile");
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Fill the options of the binary not given on the command line from the configuration file.
+/// They are taken out of it, the parameters left are set by the server, see
+/// `Builder::config_file`.
+fn merge_config_file(args: &mut Args, file: &mut ConfigFile) -> io::Result<()> {
+    fill(&mut args.port, file, "port")?;
+    fill(&mut args.loglevel, file, "loglevel")?;
+    fill(&mut args.pidfile, file, "pidfile")?;
+    fill(&mut args.logfile, file, "logfile")?;
+    fill(&mut args.log_max_size, file, "log-max-size")?;
+    fill(&mut args.log_max_files, file, "log-max-files")?;
+    fill(&mut args.log_rotate_interval, file, "log-rotate-interval")?;
+    fill(&mut args.cluster_port, file, "cluster-port")?;
+    fill(&mut args.metrics_port, file, "metrics-port")?;
+    args.cluster_enabled |= file.take_flag("cluster-enabled")?.unwrap_or(false);
+    args.sentinel |= file.take_flag("sentinel")?.unwrap_or(false);
+    let bind = file.take_list("bind")?;
+    if args.bind.is_empty() {
+        args.bind = bind.unwrap_or_default();
+    }
+
+    #[cfg(unix)]
+    {
+        args.daemonize |= file.take_flag("daemonize")?.unwrap_or(false);
+        args.reuseport |= file.take_flag("reuseport")?.unwrap_or(false);
+        fill(&mut args.unixsocket, file, "unixsocket")?;
+        let perm = file
+            .take::<String>("unixsocketperm")?
+            .map(|perm| parse_octal(&perm))
+            .transpose()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        args.unixsocketperm = args.unixsocketperm.or(perm);
+    }
+
+    #[cfg(feature = "tls")]
+    {
+        fill(&mut args.tls_port, file, "tls-port")?;
+        fill(&mut args.tls_cert_file, file, "tls-cert-file")?;
+        fill(&mut args.tls_key_file, file, "tls-key-file")?;
+        fill(&mut args.tls_ca_cert_file, file, "tls-ca-cert-file")?;
+        args.tls_auth_clients |= file.take_flag("tls-auth-clients")?.unwrap_or(false);
+    }
 
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    #[cfg(feature = "otel")]
+    fill(&mut args.otel_endpoint, file, "otel-endpoint")?;
 
-    server::run(listener, port, read_buffer_size, write_buffer_size).await;
     Ok(())
 }
+
+/// Set `arg` to the parameter `name` of the file if it wasn't given on the command line. The
+/// parameter is taken out of the file either way.
+fn fill<T>(arg: &mut Option<T>, file: &mut ConfigFile, name: &str) -> io::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = file.take(name)?;
+    if arg.is_none() {
+        *arg = value;
+    }
+    Ok(())
+}
+
+/// Log the events of the server to stdout, or to the file set with `--logfile` or changed with
+/// `CONFIG SET logfile`, at the level set with `--loglevel` or changed with
+/// `CONFIG SET loglevel`. Events of a connection carry its id and the address of its peer.
+///
+/// With the `otel` feature and `--otel-endpoint`, the spans of commands are exported as well.
+/// With the `console` feature, the tasks of the server are served to `tokio-console`.
+fn init_tracing(args: &Args) -> io::Result<Tracing> {
+    let stdout = fmt::layer()
+        .with_ansi(std::io::stdout().is_terminal())
+        .with_filter(filter::filter_fn(|meta| {
+            !log::to_file() && log::enabled(*meta.level())
+        }));
+    let file = fmt::layer()
+        .with_ansi(false)
+        .with_writer(log::file_writer)
+        .with_filter(filter::filter_fn(|meta| {
+            log::to_file() && log::enabled(*meta.level())
+        }));
+    let registry = tracing_subscriber::registry().with(stdout).with(file);
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+
+    #[cfg(feature = "otel")]
+    {
+        let provider = args
+            .otel_endpoint
+            .as_deref()
+            .map(walrus::otel::tracer_provider)
+            .transpose()
+            .map_err(io::Error::other)?;
+        registry
+            .with(provider.as_ref().map(walrus::otel::layer))
+            .init();
+        Ok(Tracing { provider })
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = args;
+        registry.init();
+        Ok(Tracing {})
+    }
+}
+
+impl Drop for Tracing {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = &self.provider
+            && let Err(err) = provider.shutdown()
+        {
+            eprintln!("Failed to export the pending spans, {err}");
+        }
+    }
+}
+
+/// Completes when the server is asked to stop, with ctrl-c or, on Unix, `SIGTERM`.
+fn shutdown_signal() -> io::Result<impl Future<Output = ()>> {
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+
+    Ok(async move {
+        #[cfg(unix)]
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        #[cfg(not(unix))]
+        let _ = tokio::signal::ctrl_c().await;
+    })
+}
+
+/// Listeners of the server, one on `port` for each of the `--bind` addresses.
+///
+/// With a certificate set they accept TLS connections, unless a dedicated TLS port is set. TLS
+/// connections are then accepted on that port of each address.
+async fn listeners(args: &Args, port: u16) -> io::Result<Listeners> {
+    let mut listeners = None;
+    #[cfg(feature = "tls")]
+    let tls = tls_config(args);
+
+    for addr in &args.bind {
+        for listener in bind(args, addr, port).await? {
+            #[cfg(feature = "tls")]
+            if let Some(config) = &tls
+                && args.tls_port.is_none()
+            {
+                let listener = walrus::tls::TlsListener::new(listener, config)?;
+                listeners = Some(add(listeners, listener, Listeners::with_tls));
+                continue;
+            }
+            listeners = Some(add(listeners, listener, Listeners::with_tcp));
+        }
+
+        #[cfg(feature = "tls")]
+        if let (Some(config), Some(tls_port)) = (&tls, args.tls_port) {
+            for listener in bind(args, addr, tls_port).await? {
+                let listener = walrus::tls::TlsListener::new(listener, config)?;
+                listeners = Some(add(listeners, listener, Listeners::with_tls));
+            }
+        }
+    }
+
+    // Clap always provides at least one address.
+    Ok(listeners.expect("no address to bind"))
+}
+
+/// Listeners of the sockets passed by systemd with the port of the first TCP one, `None` if
+/// the server wasn't socket activated. TCP sockets accept TLS connections if a certificate is
+/// set and `--tls-port` isn't, like the addresses bound otherwise.
+#[cfg(unix)]
+fn activated_listeners(args: &Args) -> io::Result<Option<(Listeners, Option<u16>)>> {
+    use walrus::systemd::{self, ActivatedSocket};
+
+    let mut listeners = None;
+    let mut port = None;
+    #[cfg(feature = "tls")]
+    let tls = tls_config(args).filter(|_| args.tls_port.is_none());
+    #[cfg(not(feature = "tls"))]
+    let _ = args;
+
+    for socket in systemd::listen_fds()? {
+        match socket {
+            ActivatedSocket::Tcp(listener) => {
+                port = port.or(Some(listener.local_addr()?.port()));
+                #[cfg(feature = "tls")]
+                if let Some(config) = &tls {
+                    let listener = walrus::tls::TlsListener::new(listener, config)?;
+                    listeners = Some(add(listeners, listener, Listeners::with_tls));
+                    continue;
+                }
+                listeners = Some(add(listeners, listener, Listeners::with_tcp));
+            }
+            ActivatedSocket::Unix(listener) => {
+                listeners = Some(add(listeners, listener, Listeners::with_unix));
+            }
+        }
+    }
+
+    Ok(listeners.map(|listeners| (listeners, port)))
+}
+
+/// Bind `port` on `addr`, with one listener per core bound with `SO_REUSEPORT` if
+/// `--reuseport` is set.
+#[cfg_attr(not(unix), allow(unused_variables))]
+async fn bind(args: &Args, addr: &str, port: u16) -> io::Result<Vec<TcpListener>> {
+    #[cfg(unix)]
+    if args.reuseport {
+        let resolved = tokio::net::lookup_host((addr, port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{addr} doesn't resolve to an address"),
+                )
+            })?;
+        let cores = std::thread::available_parallelism().map_or(1, usize::from);
+        return server::bind_reuseport(resolved, cores);
+    }
+
+    Ok(vec![TcpListener::bind((addr, port)).await?])
+}
+
+/// Add `listener` to `listeners` with `with`, or start them with it.
+fn add<L: Into<Listeners>>(
+    listeners: Option<Listeners>,
+    listener: L,
+    with: fn(Listeners, L) -> Listeners,
+) -> Listeners {
+    match listeners {
+        Some(listeners) => with(listeners, listener),
+        None => listener.into(),
+    }
+}
+
+/// TLS configuration given on the command line, if a certificate is set.
+#[cfg(feature = "tls")]
+fn tls_config(args: &Args) -> Option<walrus::tls::TlsConfig> {
+    let (Some(cert_file), Some(key_file)) = (&args.tls_cert_file, &args.tls_key_file) else {
+        return None;
+    };
+    Some(walrus::tls::TlsConfig {
+        cert_file: cert_file.clone(),
+        key_file: key_file.clone(),
+        ca_cert_file: args.tls_ca_cert_file.clone(),
+        auth_clients: args.tls_auth_clients,
+    })
+}
+
+#[cfg(unix)]
+fn parse_octal(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8).map_err(|_| format!("invalid octal permissions '{value}'"))
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    if value.eq_ignore_ascii_case("yes") {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case("no") {
+        Ok(false)
+    } else {
+        Err(format!("expected yes or no, got '{value}'"))
+    }
+}
@@ -5,16 +5,47 @@ use tokio::net::{TcpStream, ToSocketAddrs};
 
 use crate::{
     Connection,
-    cmd::{BLPop, Get, LLen, LPop, LPush, LRange, Ping, RPush, Set, Type},
+    cmd::{
+        Asking, BLPop, Bgsave, ClientCmd, ClusterCmd, CommandCmd, ConfigCmd, Failover, Get, Hello,
+        Info, Keys, LLen, LPop, LPush, LRange, LatencyCmd, Lolwut, MemoryCmd, Monitor, Move,
+        ObjectCmd, PTtl, Ping, RPush, ReplicaOf, Save, Scan, Select, SentinelCmd, Set, SlotState,
+        SlowlogCmd, Type,
+    },
+    connection::{Protocol, SocketOptions},
     db::Data,
     errors::WalrusError,
     frame::Frame,
 };
 
+#[cfg(feature = "debug-command")]
+use crate::cmd::DebugCmd;
+
+pub mod blocking;
+
+mod builder;
+pub use builder::ClientBuilder;
+
+mod pipeline;
+pub use pipeline::Pipeline;
+
+pub mod pool;
+pub use pool::{Pool, PooledClient};
+
+mod reconnect;
+pub use reconnect::ReconnectPolicy;
+use reconnect::{Endpoint, Reconnect};
+
+mod shared;
+pub use shared::SharedClient;
+
 /// Contains the connection established with the `walrus` server.
 pub struct Client {
-    /// TCP stream wrapped in `Connection`, which provides frame parsing.
+    /// TCP or Unix stream wrapped in `Connection`, which provides frame parsing.
     connection: Connection,
+    /// Push messages received while waiting for a reply, in order of arrival.
+    pushes: VecDeque<Vec<Frame>>,
+    /// Address to connect to again once the connection breaks, see `set_reconnect`.
+    reconnect: Reconnect,
 }
 
 pub fn int_to_string(val: i64) -> String {
@@ -34,14 +65,95 @@ impl Client {
     /// Establish a connection with Walrus server at `addr`.
     ///
     /// The `addr` passed must be of type that can be asynchronously converted to `SocketAddr`.
+    /// Use `Client::builder` to authenticate, name the connection or set timeouts.
     pub async fn connect<T: ToSocketAddrs>(
         addr: T,
         read_buffer_size: Option<u16>,
         write_buffer_size: Option<u16>,
+    ) -> Result<Client, WalrusError> {
+        Client::connect_with_options(
+            addr,
+            read_buffer_size,
+            write_buffer_size,
+            &SocketOptions::default(),
+        )
+        .await
+    }
+
+    /// Establish a connection with Walrus server at `addr`, with the socket tuned by `options`.
+    pub async fn connect_with_options<T: ToSocketAddrs>(
+        addr: T,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+        options: &SocketOptions,
     ) -> Result<Client, WalrusError> {
         let socket = TcpStream::connect(addr).await?;
+        let endpoint = match socket.peer_addr() {
+            Ok(addr) => Endpoint::Tcp {
+                addr,
+                options: *options,
+            },
+            Err(_) => Endpoint::None,
+        };
+        let connection = Connection::new(socket, read_buffer_size, write_buffer_size);
+        connection.set_socket_options(options)?;
+        Ok(Client {
+            connection,
+            pushes: VecDeque::new(),
+            reconnect: Reconnect::new(endpoint, read_buffer_size, write_buffer_size),
+        })
+    }
+
+    /// Establish a connection with Walrus server listening on the Unix socket at `path`.
+    #[cfg(unix)]
+    pub async fn connect_unix(
+        path: impl AsRef<std::path::Path>,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+    ) -> Result<Client, WalrusError> {
+        let path = path.as_ref();
+        let socket = tokio::net::UnixStream::connect(path).await?;
         let connection = Connection::new(socket, read_buffer_size, write_buffer_size);
-        Ok(Client { connection })
+        Ok(Client {
+            connection,
+            pushes: VecDeque::new(),
+            reconnect: Reconnect::new(
+                Endpoint::Unix(path.to_path_buf()),
+                read_buffer_size,
+                write_buffer_size,
+            ),
+        })
+    }
+
+    /// Read the reply to the last command sent to the server.
+    ///
+    /// Push messages arriving before the reply are queued and can be received with
+    /// `next_push`. Attributes attached to the reply are discarded.
+    async fn read_response(&mut self) -> Result<Frame, WalrusError> {
+        loop {
+            match self.connection.read_frame().await? {
+                Some(Frame::Push(push)) => self.pushes.push_back(push),
+                Some(Frame::Attribute { data, .. }) => return Ok(*data),
+                Some(frame) => return Ok(frame),
+                None => return Err("No response from server".into()),
+            }
+        }
+    }
+
+    /// Receive the next push message sent by the server.
+    ///
+    /// Push messages already received while waiting for a reply are returned first, otherwise
+    /// waits for the server to send one. Push messages are only sent to RESP3 connections.
+    pub async fn next_push(&mut self) -> Result<Vec<Frame>, WalrusError> {
+        if let Some(push) = self.pushes.pop_front() {
+            return Ok(push);
+        }
+
+        match self.connection.read_frame().await? {
+            Some(Frame::Push(push)) => Ok(push),
+            Some(_) => Err("Unexpected reply from server while waiting for push".into()),
+            None => Err("Connection closed by server".into()),
+        }
     }
 
     /// Send `Ping` command to the server.
@@ -49,36 +161,24 @@ impl Client {
     /// Returns the message provided if any given the server is running.
     pub async fn ping(&mut self, msg: Option<Bytes>) -> Result<Bytes, WalrusError> {
         let frame = Ping::new(msg).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Simple(value) => Ok(Bytes::from(value)),
-                Frame::Bulk(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            Frame::Simple(value) => Ok(value),
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
     /// `Get` the `value` associated with the `key`
     pub async fn get(&mut self, key: Bytes) -> Result<Option<Bytes>, WalrusError> {
         let frame = Get::new(key).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Simple(value) => Ok(Some(value.into())),
-                Frame::Bulk(value) => Ok(Some(value)),
-                // `Null` frame is sent by server, if key has no associated value.
-                Frame::Null => Ok(None),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            Frame::Simple(value) => Ok(Some(value)),
+            Frame::Bulk(value) => Ok(Some(value)),
+            // `Null` frame is sent by server, if key has no associated value.
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -91,16 +191,27 @@ impl Client {
         expire: Option<Duration>,
     ) -> Result<Bytes, WalrusError> {
         let frame = Set::new(key, value, expire).into_frame();
-        self.connection.write_frame(&frame);
+        match self.request(&frame).await? {
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
 
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Bulk(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response from server".into())
+    /// `Set` a value for the key only if it doesn't exist, with optional expiration duration.
+    /// Returns `true` if the key was set.
+    pub async fn set_nx(
+        &mut self,
+        key: Bytes,
+        value: Bytes,
+        expire: Option<Duration>,
+    ) -> Result<bool, WalrusError> {
+        let frame = Set::new_nx(key, value, expire).into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(_) => Ok(true),
+            Frame::Null => Ok(false),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -113,16 +224,10 @@ impl Client {
         data: VecDeque<Data>,
     ) -> Result<i64, WalrusError> {
         let frame = RPush::new(list_key, data).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Integer(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -137,16 +242,10 @@ impl Client {
         data: VecDeque<Data>,
     ) -> Result<i64, WalrusError> {
         let frame = LPush::new(list_key, data).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Integer(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -162,17 +261,11 @@ impl Client {
         count: Option<i64>,
     ) -> Result<Option<Vec<Data>>, WalrusError> {
         let frame = LPop::new(list_key, count).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                // Frame::Null case throws error in the frame_to_data_vec function as `Data`
-                // doesn't support `Null` values.
-                Frame::Null => Ok(None),
-                value => Ok(Some(Data::frame_to_data_vec(value)?)),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            // Frame::Null case throws error in the frame_to_data_vec function as `Data`
+            // doesn't support `Null` values.
+            Frame::Null => Ok(None),
+            value => Ok(Some(Data::frame_to_data_vec(value)?)),
         }
     }
 
@@ -197,15 +290,9 @@ impl Client {
         timeout: f64,
     ) -> Result<Option<Vec<Data>>, WalrusError> {
         let frame = BLPop::new(keys, timeout).into_frame();
-        self.connection.write_frame(&frame);
-
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Null => Ok(None),
-                value => Ok(Some(Data::frame_to_data_vec(value)?)),
-            }
-        } else {
-            Err("No response from server".into())
+        match self.request(&frame).await? {
+            Frame::Null => Ok(None),
+            value => Ok(Some(Data::frame_to_data_vec(value)?)),
         }
     }
 
@@ -215,16 +302,21 @@ impl Client {
     /// Returns `0` if no list with `list_key` is found.
     pub async fn llen(&mut self, list_key: Bytes) -> Result<i64, WalrusError> {
         let frame = LLen::new(list_key).into_frame();
-        self.connection.write_frame(&frame);
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
 
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Integer(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
-                _ => Err("Invalid response by server".into()),
-            }
-        } else {
-            Err("No response by server".into())
+    /// `PTtl` command to get the time left before `key` expires, in milliseconds.
+    /// Returns `-1` if the key never expires and `-2` if it doesn't exist.
+    pub async fn pttl(&mut self, key: Bytes) -> Result<i64, WalrusError> {
+        let frame = PTtl::new(key).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -244,15 +336,44 @@ impl Client {
         end_index: i64,
     ) -> Result<Vec<Data>, WalrusError> {
         let frame = LRange::new(list_key, start_index, end_index).into_frame();
-        self.connection.write_frame(&frame);
+        // Handles all types of frames.
+        let frame = self.request(&frame).await?;
+        Data::frame_to_data_vec(frame)
+    }
 
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                // Handles all types of frames.
-                frame => Ok(Data::frame_to_data_vec(frame)?),
-            }
-        } else {
-            Err("No response from server".into())
+    /// Get every key matching the glob-style `pattern`.
+    pub async fn keys(&mut self, pattern: Bytes) -> Result<Vec<Bytes>, WalrusError> {
+        let frame = Keys::new(pattern).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(keys) => bulks(keys),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Visit the keys incrementally from `cursor`, 0 to start. Returns the cursor to continue
+    /// from, 0 once every key was visited, and the keys matching `pattern` if given. About
+    /// `count` keys are visited at a time.
+    pub async fn scan(
+        &mut self,
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<usize>,
+    ) -> Result<(u64, Vec<Bytes>), WalrusError> {
+        let frame = Scan::new(cursor, pattern, count).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(reply) => match <[Frame; 2]>::try_from(reply) {
+                Ok([Frame::Bulk(cursor), Frame::Array(keys)]) => {
+                    let cursor = std::str::from_utf8(&cursor)
+                        .ok()
+                        .and_then(|cursor| cursor.parse().ok())
+                        .ok_or("Invalid response by server")?;
+                    Ok((cursor, bulks(keys)?))
+                }
+                _ => Err("Invalid response by server".into()),
+            },
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
         }
     }
 
@@ -265,17 +386,943 @@ impl Client {
     /// presented is string.
     pub async fn wtype(&mut self, key: Bytes) -> Result<Bytes, WalrusError> {
         let frame = Type::new(key).into_frame();
-        self.connection.write_frame(&frame);
+        match self.request(&frame).await? {
+            Frame::Simple(value) => Ok(value),
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Select` command to run the following commands against the database `index`, selected
+    /// again by a client reconnecting.
+    pub async fn select(&mut self, index: i64) -> Result<(), WalrusError> {
+        let frame = Select::new(index).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => {
+                self.reconnect.database = index;
+                Ok(())
+            }
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Move` command to move `key` from the selected database to the database `db`.
+    ///
+    /// Returns `false` if the key doesn't exist or already exists in `db`.
+    pub async fn wmove(&mut self, key: Bytes, db: i64) -> Result<bool, WalrusError> {
+        let frame = Move::new(key, db).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(moved) => Ok(moved == 1),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Object Encoding` command to get how the value of `key` is stored.
+    ///
+    /// Returns `None` if the key doesn't exist.
+    pub async fn object_encoding(&mut self, key: Bytes) -> Result<Option<Bytes>, WalrusError> {
+        let frame = ObjectCmd::Encoding(key).into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(encoding) => Ok(Some(encoding)),
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Object Freq` command to get the access frequency of `key`, only tracked with an LFU
+    /// `maxmemory-policy`.
+    ///
+    /// Returns `None` if the key doesn't exist.
+    pub async fn object_freq(&mut self, key: Bytes) -> Result<Option<i64>, WalrusError> {
+        let frame = ObjectCmd::Freq(key).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(frequency) => Ok(Some(frequency)),
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Memory Usage` command to get the approximate number of bytes used by `key` and its
+    /// value. `samples` is accepted for compatibility with Redis, sizes are tracked exactly.
+    ///
+    /// Returns `None` if the key doesn't exist.
+    pub async fn memory_usage(
+        &mut self,
+        key: Bytes,
+        samples: Option<u64>,
+    ) -> Result<Option<i64>, WalrusError> {
+        let frame = MemoryCmd::Usage { key, samples }.into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(size) => Ok(Some(size)),
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Memory Stats` command to get the memory statistics of the server.
+    ///
+    /// Returns the name and value of each statistic, an integer or a double. Doubles are
+    /// bulk strings on RESP2.
+    pub async fn memory_stats(&mut self) -> Result<Vec<(Bytes, Frame)>, WalrusError> {
+        let frame = MemoryCmd::Stats.into_frame();
+        let pairs = pairs(self.request(&frame).await?)?;
+        let mut stats = Vec::with_capacity(pairs.len());
+
+        for pair in pairs {
+            match pair {
+                (Frame::Bulk(name), value) => stats.push((name, value)),
+                _ => return Err("Invalid response by server".into()),
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// `Memory Doctor` command to get a report of the memory issues of the server, with advice
+    /// on each of them.
+    pub async fn memory_doctor(&mut self) -> Result<String, WalrusError> {
+        let frame = MemoryCmd::Doctor.into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(report) => Ok(String::from_utf8_lossy(&report).into_owned()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Hello` command to switch the protocol used by the server for this connection.
+    /// `protover` must be 2 or 3, `None` keeps the current protocol.
+    ///
+    /// Returns the server properties as a flat list of alternating names and values.
+    /// `NOPROTO` error is returned if the protocol version is not supported.
+    pub async fn hello(&mut self, protover: Option<i64>) -> Result<Vec<Data>, WalrusError> {
+        let frame = Hello::new(protover).into_frame();
+        match self.request(&frame).await? {
+            Frame::Error(err) => Err(err.into()),
+            frame => {
+                match protover {
+                    Some(3) => self.connection.set_protocol(Protocol::Resp3),
+                    Some(2) => self.connection.set_protocol(Protocol::Resp2),
+                    _ => {}
+                }
+                Ok(Data::frame_to_data_vec(frame)?)
+            }
+        }
+    }
+
+    /// `Client Id` command to get the id of this connection.
+    pub async fn client_id(&mut self) -> Result<i64, WalrusError> {
+        let frame = ClientCmd::Id.into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client SetName` command to set the name of this connection.
+    /// An empty name removes the current name.
+    /// Names must not contain spaces, newlines or special characters.
+    pub async fn client_setname(&mut self, name: Bytes) -> Result<(), WalrusError> {
+        let frame = ClientCmd::SetName(name.clone()).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => {
+                // Kept to name the connection again once re-established.
+                self.connection.set_name((!name.is_empty()).then_some(name));
+                Ok(())
+            }
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client GetName` command to get the name of this connection.
+    /// Returns `None` if no name is set.
+    pub async fn client_getname(&mut self) -> Result<Option<Bytes>, WalrusError> {
+        let frame = ClientCmd::GetName.into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client List` command to describe all connections of the server.
+    /// Returns one line per connection with space separated `field=value` pairs.
+    pub async fn client_list(&mut self) -> Result<Bytes, WalrusError> {
+        let frame = ClientCmd::List.into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client Kill` command to close the connection with the given `id`.
+    /// Returns the number of connections killed.
+    pub async fn client_kill(&mut self, id: u64) -> Result<i64, WalrusError> {
+        let frame = ClientCmd::Kill {
+            id: Some(id),
+            addr: None,
+            skipme: true,
+        }
+        .into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client Pause` command to suspend processing of commands from all connections for
+    /// `timeout`. Only write commands are suspended if `write_only` is set.
+    pub async fn client_pause(
+        &mut self,
+        timeout: Duration,
+        write_only: bool,
+    ) -> Result<(), WalrusError> {
+        let frame = ClientCmd::Pause {
+            timeout,
+            write_only,
+        }
+        .into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client Unpause` command to resume processing of commands paused by `client_pause`.
+    pub async fn client_unpause(&mut self) -> Result<(), WalrusError> {
+        let frame = ClientCmd::Unpause.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client Tracking` command to enable or disable invalidation messages for keys read by
+    /// this connection. In broadcast mode, messages are sent for every key matching one of
+    /// `prefixes` instead. Requires switching to RESP3 with `hello` first.
+    ///
+    /// Invalidation messages are received with `next_push`.
+    pub async fn client_tracking(
+        &mut self,
+        on: bool,
+        bcast: bool,
+        prefixes: Vec<Bytes>,
+        noloop: bool,
+    ) -> Result<(), WalrusError> {
+        let frame = ClientCmd::Tracking {
+            on,
+            bcast,
+            prefixes,
+            noloop,
+        }
+        .into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Client Traceparent` command to set the W3C trace context of the next command, such as
+    /// `00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01`. With the `otel` feature the
+    /// server traces that command as part of the trace of the client.
+    pub async fn client_traceparent(
+        &mut self,
+        traceparent: Bytes,
+        tracestate: Option<Bytes>,
+    ) -> Result<(), WalrusError> {
+        let frame = ClientCmd::TraceParent {
+            traceparent,
+            tracestate,
+        }
+        .into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Config Get` command to get the parameters matching the glob-style `pattern`.
+    ///
+    /// Returns the name and value of each matching parameter.
+    pub async fn config_get(&mut self, pattern: Bytes) -> Result<Vec<(Bytes, Bytes)>, WalrusError> {
+        let frame = ConfigCmd::Get(vec![pattern]).into_frame();
+        let pairs = pairs(self.request(&frame).await?)?;
+
+        let mut params = Vec::with_capacity(pairs.len());
+
+        for pair in pairs {
+            match pair {
+                (Frame::Bulk(name), Frame::Bulk(value)) => params.push((name, value)),
+                _ => return Err("Invalid response by server".into()),
+            }
+        }
 
-        if let Some(response) = self.connection.read_frame().await? {
-            match response {
-                Frame::Simple(value) => Ok(Bytes::from(value)),
-                Frame::Bulk(value) => Ok(value),
-                Frame::Error(err) => Err(err.into()),
+        Ok(params)
+    }
+
+    /// `Config Set` command to set the parameter `name` to `value`.
+    pub async fn config_set(&mut self, name: Bytes, value: Bytes) -> Result<(), WalrusError> {
+        let frame = ConfigCmd::Set(vec![(name, value)]).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Config Rewrite` command to write the parameters changed at runtime to the configuration
+    /// file of the server.
+    pub async fn config_rewrite(&mut self) -> Result<(), WalrusError> {
+        let frame = ConfigCmd::Rewrite.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Config Resetstat` command to reset the statistics reported by `INFO`.
+    pub async fn config_resetstat(&mut self) -> Result<(), WalrusError> {
+        let frame = ConfigCmd::ResetStat.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Command Count` command to get the number of commands implemented by the server.
+    pub async fn command_count(&mut self) -> Result<i64, WalrusError> {
+        let frame = CommandCmd::Count.into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(count) => Ok(count),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Command Info` command to describe the commands `names`, every command if empty.
+    ///
+    /// Each command is described by an array of its name, arity, flags, first key, last key and
+    /// key step, followed by the ACL categories, tips, key specs and subcommands.
+    /// Unknown commands are described by `Frame::Null`.
+    pub async fn command_info(&mut self, names: Vec<Bytes>) -> Result<Vec<Frame>, WalrusError> {
+        let frame = CommandCmd::Info(names).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(info) => Ok(info),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Monitor` command to stream every command processed by the server to this connection.
+    ///
+    /// Commands are received with `next_monitor`.
+    pub async fn monitor(&mut self) -> Result<(), WalrusError> {
+        let frame = Monitor::new().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Receive the next command streamed by the server after `monitor`.
+    ///
+    /// Returns the line describing the command, such as
+    /// `1700000000.123456 [0 127.0.0.1:50000] "set" "key" "value"`.
+    pub async fn next_monitor(&mut self) -> Result<Bytes, WalrusError> {
+        match self.read_response().await? {
+            Frame::Simple(line) => Ok(line),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Slowlog Get` command to get the `count` most recent slow log entries, 10 if `None`.
+    ///
+    /// Each entry is an array of its id, Unix timestamp, execution time in microseconds,
+    /// arguments of the command, client address and client name.
+    pub async fn slowlog_get(&mut self, count: Option<i64>) -> Result<Vec<Frame>, WalrusError> {
+        let frame = SlowlogCmd::Get(count).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(entries) => Ok(entries),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Slowlog Len` command to get the number of slow log entries.
+    pub async fn slowlog_len(&mut self) -> Result<i64, WalrusError> {
+        let frame = SlowlogCmd::Len.into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(len) => Ok(len),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Slowlog Reset` command to remove every slow log entry.
+    pub async fn slowlog_reset(&mut self) -> Result<(), WalrusError> {
+        let frame = SlowlogCmd::Reset.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Latency Latest` command to get the latest latency spike of every event.
+    ///
+    /// Each spike is an array of the event name, Unix timestamp, latency in milliseconds and
+    /// the highest latency of the event.
+    pub async fn latency_latest(&mut self) -> Result<Vec<Frame>, WalrusError> {
+        let frame = LatencyCmd::Latest.into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(spikes) => Ok(spikes),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Latency History` command to get the latency spikes of `event`, oldest first.
+    ///
+    /// Each spike is an array of its Unix timestamp and latency in milliseconds.
+    pub async fn latency_history(&mut self, event: &str) -> Result<Vec<Frame>, WalrusError> {
+        let frame = LatencyCmd::History(Bytes::copy_from_slice(event.as_bytes())).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(spikes) => Ok(spikes),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Latency Reset` command to remove the latency spikes of `events`, of every event if
+    /// empty. Returns the number of events reset.
+    pub async fn latency_reset(&mut self, events: &[&str]) -> Result<i64, WalrusError> {
+        let events = events
+            .iter()
+            .map(|event| Bytes::copy_from_slice(event.as_bytes()))
+            .collect();
+        let frame = LatencyCmd::Reset(events).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(reset) => Ok(reset),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Save` command to write a snapshot of the dataset to disk.
+    pub async fn save(&mut self) -> Result<(), WalrusError> {
+        let frame = Save::new().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `ReplicaOf` command to make the server a replica of the server at `host:port`.
+    pub async fn replicaof(&mut self, host: Bytes, port: u16) -> Result<(), WalrusError> {
+        let frame = ReplicaOf::new(host, port).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `ReplicaOf` command to stop replicating, the server becomes a master.
+    pub async fn replicaof_no_one(&mut self) -> Result<(), WalrusError> {
+        let frame = ReplicaOf::no_one().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Meet` command to join the cluster of the node at `ip:port`, with its cluster
+    /// bus on `bus_port`, the port plus 10000 by default.
+    pub async fn cluster_meet(
+        &mut self,
+        ip: Bytes,
+        port: u16,
+        bus_port: Option<u16>,
+    ) -> Result<(), WalrusError> {
+        let frame = ClusterCmd::Meet { ip, port, bus_port }.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Addslots` command to serve `slots` from the node.
+    pub async fn cluster_addslots(&mut self, slots: Vec<u16>) -> Result<(), WalrusError> {
+        let frame = ClusterCmd::AddSlots(slots).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Delslots` command to stop serving `slots` from the node.
+    pub async fn cluster_delslots(&mut self, slots: Vec<u16>) -> Result<(), WalrusError> {
+        let frame = ClusterCmd::DelSlots(slots).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Setslot Migrating` command to start moving `slot`, owned by the node, to the
+    /// node with id `node_id`.
+    pub async fn cluster_setslot_migrating(
+        &mut self,
+        slot: u16,
+        node_id: Bytes,
+    ) -> Result<(), WalrusError> {
+        self.cluster_setslot(slot, SlotState::Migrating(node_id))
+            .await
+    }
+
+    /// `Cluster Setslot Importing` command to start moving `slot`, owned by the node with id
+    /// `node_id`, to the node.
+    pub async fn cluster_setslot_importing(
+        &mut self,
+        slot: u16,
+        node_id: Bytes,
+    ) -> Result<(), WalrusError> {
+        self.cluster_setslot(slot, SlotState::Importing(node_id))
+            .await
+    }
+
+    /// `Cluster Setslot Node` command to assign `slot` to the node with id `node_id`.
+    pub async fn cluster_setslot_node(
+        &mut self,
+        slot: u16,
+        node_id: Bytes,
+    ) -> Result<(), WalrusError> {
+        self.cluster_setslot(slot, SlotState::Node(node_id)).await
+    }
+
+    /// `Cluster Setslot Stable` command to stop moving `slot`.
+    pub async fn cluster_setslot_stable(&mut self, slot: u16) -> Result<(), WalrusError> {
+        self.cluster_setslot(slot, SlotState::Stable).await
+    }
+
+    /// `Cluster Countkeysinslot` command to get the number of keys of the node in `slot`.
+    pub async fn cluster_countkeysinslot(&mut self, slot: u16) -> Result<i64, WalrusError> {
+        let frame = ClusterCmd::CountKeysInSlot(slot).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(count) => Ok(count),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Getkeysinslot` command to get up to `count` keys of the node in `slot`.
+    pub async fn cluster_getkeysinslot(
+        &mut self,
+        slot: u16,
+        count: u64,
+    ) -> Result<Vec<Bytes>, WalrusError> {
+        let frame = ClusterCmd::GetKeysInSlot { slot, count }.into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(keys) => bulks(keys),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Keyslot` command to get the hash slot of `key`.
+    pub async fn cluster_keyslot(&mut self, key: Bytes) -> Result<i64, WalrusError> {
+        let frame = ClusterCmd::KeySlot(key).into_frame();
+        match self.request(&frame).await? {
+            Frame::Integer(slot) => Ok(slot),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Cluster Myid` command to get the id of the node.
+    pub async fn cluster_myid(&mut self) -> Result<Bytes, WalrusError> {
+        self.cluster_text(ClusterCmd::MyId).await
+    }
+
+    /// `Cluster Info` command to get the state of the cluster as `field:value` lines.
+    pub async fn cluster_info(&mut self) -> Result<Bytes, WalrusError> {
+        self.cluster_text(ClusterCmd::Info).await
+    }
+
+    /// `Cluster Nodes` command to get the description of every node, one per line.
+    pub async fn cluster_nodes(&mut self) -> Result<Bytes, WalrusError> {
+        self.cluster_text(ClusterCmd::Nodes).await
+    }
+
+    /// `Cluster Slots` command to get the ranges of slots with the node serving them.
+    pub async fn cluster_slots(&mut self) -> Result<Vec<Frame>, WalrusError> {
+        self.cluster_array(ClusterCmd::Slots).await
+    }
+
+    /// `Cluster Shards` command to get the nodes with the slots they serve.
+    pub async fn cluster_shards(&mut self) -> Result<Vec<Frame>, WalrusError> {
+        self.cluster_array(ClusterCmd::Shards).await
+    }
+
+    /// `Asking` command to have the next command served for a slot the node is importing.
+    pub async fn asking(&mut self) -> Result<(), WalrusError> {
+        let frame = Asking::new().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Sentinel Monitor` command to start monitoring the master at `host:port` as `name`,
+    /// failing it over once `quorum` sentinels agree it's down.
+    pub async fn sentinel_monitor(
+        &mut self,
+        name: Bytes,
+        host: Bytes,
+        port: u16,
+        quorum: u64,
+    ) -> Result<(), WalrusError> {
+        let frame = SentinelCmd::Monitor {
+            name,
+            host,
+            port,
+            quorum: quorum as i64,
+        }
+        .into_frame();
+        self.sentinel_ok(frame).await
+    }
+
+    /// `Sentinel Remove` command to stop monitoring the master `name`.
+    pub async fn sentinel_remove(&mut self, name: Bytes) -> Result<(), WalrusError> {
+        self.sentinel_ok(SentinelCmd::Remove(name).into_frame())
+            .await
+    }
+
+    /// `Sentinel Set` command to change the `option` of the master `name`, one of
+    /// `down-after-milliseconds`, `failover-timeout` and `quorum`.
+    pub async fn sentinel_set(
+        &mut self,
+        name: Bytes,
+        option: Bytes,
+        value: Bytes,
+    ) -> Result<(), WalrusError> {
+        let frame = SentinelCmd::Set {
+            name,
+            options: vec![(option, value)],
+        }
+        .into_frame();
+        self.sentinel_ok(frame).await
+    }
+
+    /// `Sentinel Master` command to get the description of the master `name`, as field and
+    /// value pairs.
+    pub async fn sentinel_master(&mut self, name: Bytes) -> Result<Vec<Frame>, WalrusError> {
+        self.sentinel_array(SentinelCmd::Master(name)).await
+    }
+
+    /// `Sentinel Replicas` command to get the description of the replicas of the master
+    /// `name`.
+    pub async fn sentinel_replicas(&mut self, name: Bytes) -> Result<Vec<Frame>, WalrusError> {
+        self.sentinel_array(SentinelCmd::Replicas(name)).await
+    }
+
+    /// `Sentinel Sentinels` command to get the description of the other sentinels monitoring
+    /// the master `name`.
+    pub async fn sentinel_sentinels(&mut self, name: Bytes) -> Result<Vec<Frame>, WalrusError> {
+        self.sentinel_array(SentinelCmd::Sentinels(name)).await
+    }
+
+    /// `Sentinel Get-master-addr-by-name` command to get the address of the master `name`,
+    /// `None` if it isn't monitored.
+    pub async fn sentinel_get_master_addr_by_name(
+        &mut self,
+        name: Bytes,
+    ) -> Result<Option<(Bytes, u16)>, WalrusError> {
+        let frame = SentinelCmd::GetMasterAddrByName(name).into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(addr) => match addr.as_slice() {
+                [Frame::Bulk(host), Frame::Bulk(port)] => {
+                    let port = std::str::from_utf8(port)
+                        .ok()
+                        .and_then(|port| port.parse().ok())
+                        .ok_or("Invalid response by server")?;
+                    Ok(Some((host.clone(), port)))
+                }
                 _ => Err("Invalid response by server".into()),
+            },
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Sentinel Is-master-down-by-addr` command to ask a sentinel whether the master at
+    /// `host:port` is down. Unless `runid` is `*`, also asks for a vote for the sentinel with
+    /// that run id to lead the failover of `epoch`.
+    ///
+    /// Returns whether the master is down, and the run id and epoch of the leader voted for.
+    pub async fn sentinel_is_master_down_by_addr(
+        &mut self,
+        host: Bytes,
+        port: u16,
+        epoch: u64,
+        runid: Bytes,
+    ) -> Result<(bool, Bytes, u64), WalrusError> {
+        let frame = SentinelCmd::IsMasterDownByAddr {
+            host,
+            port,
+            epoch,
+            runid,
+        }
+        .into_frame();
+        match self.request(&frame).await? {
+            Frame::Array(reply) => match reply.as_slice() {
+                [
+                    Frame::Integer(down),
+                    Frame::Bulk(leader),
+                    Frame::Integer(leader_epoch),
+                ] => Ok((*down == 1, leader.clone(), *leader_epoch as u64)),
+                _ => Err("Invalid response by server".into()),
+            },
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Sentinel Hello` command to announce to a sentinel the sentinel listening on `port`
+    /// with id `runid`, and its configuration of the master `master_name`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn sentinel_hello(
+        &mut self,
+        port: u16,
+        runid: Bytes,
+        current_epoch: u64,
+        master_name: Bytes,
+        master_host: Bytes,
+        master_port: u16,
+        master_config_epoch: u64,
+    ) -> Result<(), WalrusError> {
+        let frame = SentinelCmd::Hello {
+            port,
+            runid,
+            current_epoch,
+            master_name,
+            master_host,
+            master_port,
+            master_config_epoch,
+        }
+        .into_frame();
+        self.sentinel_ok(frame).await
+    }
+
+    /// `Sentinel Myid` command to get the run id of the sentinel.
+    pub async fn sentinel_myid(&mut self) -> Result<Bytes, WalrusError> {
+        let frame = SentinelCmd::MyId.into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(id) => Ok(id),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Send a `Sentinel` subcommand replying with `OK`.
+    async fn sentinel_ok(&mut self, frame: Frame) -> Result<(), WalrusError> {
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Send a `Sentinel` subcommand replying with an array.
+    async fn sentinel_array(&mut self, cmd: SentinelCmd) -> Result<Vec<Frame>, WalrusError> {
+        match self.request(&cmd.into_frame()).await? {
+            Frame::Array(items) => Ok(items),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Send a `Cluster Setslot` command.
+    async fn cluster_setslot(&mut self, slot: u16, state: SlotState) -> Result<(), WalrusError> {
+        let frame = ClusterCmd::SetSlot { slot, state }.into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Send a `Cluster` subcommand replying with text.
+    async fn cluster_text(&mut self, cmd: ClusterCmd) -> Result<Bytes, WalrusError> {
+        match self.request(&cmd.into_frame()).await? {
+            Frame::Bulk(text) => Ok(text),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Send a `Cluster` subcommand replying with an array.
+    async fn cluster_array(&mut self, cmd: ClusterCmd) -> Result<Vec<Frame>, WalrusError> {
+        match self.request(&cmd.into_frame()).await? {
+            Frame::Array(items) => Ok(items),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Failover` command to hand over the role of master to the replica at `to`, or to any
+    /// replica. The failover is aborted if no replica caught up within `timeout`, unless
+    /// `force` is set. Returns once the failover started.
+    pub async fn failover(
+        &mut self,
+        to: Option<(Bytes, u16)>,
+        force: bool,
+        timeout: Option<Duration>,
+    ) -> Result<(), WalrusError> {
+        let mut failover = Failover::new();
+        if let Some((host, port)) = to {
+            failover = failover.to(host, port);
+        }
+        if force {
+            failover = failover.force();
+        }
+        if let Some(timeout) = timeout {
+            failover = failover.timeout(timeout);
+        }
+        match self.request(&failover.into_frame()).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Failover Abort` command to abort the failover in progress.
+    pub async fn failover_abort(&mut self) -> Result<(), WalrusError> {
+        let frame = Failover::abort().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Bgsave` command to write a snapshot of the dataset to disk in the background.
+    pub async fn bgsave(&mut self) -> Result<(), WalrusError> {
+        let frame = Bgsave::new().into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Info` command to describe the state of the server, every section if `sections` is
+    /// empty.
+    pub async fn info(&mut self, sections: Vec<Bytes>) -> Result<Bytes, WalrusError> {
+        let frame = Info::new(sections).into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(info) => Ok(info),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Debug Object` command to describe the internal representation of the value of `key`.
+    #[cfg(feature = "debug-command")]
+    pub async fn debug_object(&mut self, key: Bytes) -> Result<Bytes, WalrusError> {
+        let frame = DebugCmd::Object(key).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(description) => Ok(description),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Debug Async-Sleep` command to suspend the connection for `duration`.
+    #[cfg(feature = "debug-command")]
+    pub async fn debug_async_sleep(&mut self, duration: Duration) -> Result<(), WalrusError> {
+        let frame = DebugCmd::AsyncSleep(duration).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Debug Set-Active-Expire` command to enable or disable the purging of expired keys.
+    #[cfg(feature = "debug-command")]
+    pub async fn debug_set_active_expire(&mut self, enabled: bool) -> Result<(), WalrusError> {
+        let frame = DebugCmd::SetActiveExpire(enabled).into_frame();
+        match self.request(&frame).await? {
+            Frame::Simple(_) => Ok(()),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Lolwut` command to get a piece of computer art followed by the version of the server.
+    pub async fn lolwut(&mut self) -> Result<Bytes, WalrusError> {
+        let frame = Lolwut::new().into_frame();
+        match self.request(&frame).await? {
+            Frame::Bulk(art) => Ok(art),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+}
+
+/// Strings of an array of bulk strings replied by the server.
+fn bulks(frames: Vec<Frame>) -> Result<Vec<Bytes>, WalrusError> {
+    frames
+        .into_iter()
+        .map(|frame| match frame {
+            Frame::Bulk(bytes) => Ok(bytes),
+            _ => Err("Invalid response by server".into()),
+        })
+        .collect()
+}
+
+/// Pairs of a map reply, a map on RESP3 and a flat array of alternating names and values on
+/// RESP2.
+fn pairs(frame: Frame) -> Result<Vec<(Frame, Frame)>, WalrusError> {
+    match frame {
+        Frame::Map(pairs) => Ok(pairs),
+        Frame::Array(frames) if frames.len() % 2 == 0 => {
+            let mut frames = frames.into_iter();
+            let mut pairs = Vec::with_capacity(frames.len() / 2);
+
+            while let (Some(name), Some(value)) = (frames.next(), frames.next()) {
+                pairs.push((name, value));
             }
-        } else {
-            Err("No response from server".into())
+
+            Ok(pairs)
         }
+        Frame::Error(err) => Err(err.into()),
+        _ => Err("Invalid response by server".into()),
     }
 }
@@ -0,0 +1,261 @@
+//! Blocking client, for applications and command line tools that don't run an async runtime.
+//!
+//! ```no_run
+//! # fn run() -> Result<(), walrus::errors::WalrusError> {
+//! use bytes::Bytes;
+//! use walrus::client::blocking::Client;
+//!
+//! let mut client = Client::connect("127.0.0.1:6380", None, None)?;
+//! client.set(Bytes::from("key"), Bytes::from("value"), None)?;
+//! assert_eq!(client.get(Bytes::from("key"))?, Some(Bytes::from("value")));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::VecDeque, time::Duration};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use tokio::{
+    net::ToSocketAddrs,
+    runtime::{self, Runtime},
+};
+
+use super::{Pipeline, ReconnectPolicy};
+use crate::{db::Data, errors::WalrusError, frame::Frame};
+
+/// Connection to a Walrus server whose commands block the calling thread until replied.
+///
+/// Wraps the async `client::Client`, running it on a current-thread Tokio runtime of its own.
+/// Commands not offered here are sent with `run`. Must not be used from an async runtime,
+/// blocking one of its threads, use the async client there.
+pub struct Client {
+    /// Dropped before the runtime its connection is registered with.
+    inner: super::Client,
+    runtime: Runtime,
+}
+
+impl Client {
+    /// Establish a connection with Walrus server at `addr`, see `client::Client::connect`.
+    pub fn connect<T: ToSocketAddrs>(
+        addr: T,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+    ) -> Result<Client, WalrusError> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let inner = runtime.block_on(super::Client::connect(
+            addr,
+            read_buffer_size,
+            write_buffer_size,
+        ))?;
+        Ok(Client { inner, runtime })
+    }
+
+    /// Establish a connection with Walrus server listening on the Unix socket at `path`.
+    #[cfg(unix)]
+    pub fn connect_unix(
+        path: impl AsRef<std::path::Path>,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+    ) -> Result<Client, WalrusError> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let inner = runtime.block_on(super::Client::connect_unix(
+            path,
+            read_buffer_size,
+            write_buffer_size,
+        ))?;
+        Ok(Client { inner, runtime })
+    }
+
+    /// Run `f` with the async client until the future it returns completes, for the commands
+    /// not offered by the blocking client.
+    ///
+    /// ```no_run
+    /// # fn run(client: &mut walrus::client::blocking::Client) {
+    /// let id = client.run(|client| Box::pin(client.client_id()));
+    /// # }
+    /// ```
+    pub fn run<R>(&mut self, f: impl FnOnce(&mut super::Client) -> BoxFuture<'_, R>) -> R {
+        self.runtime.block_on(f(&mut self.inner))
+    }
+
+    /// Re-establish broken connections following `policy`, see
+    /// `client::Client::set_reconnect`.
+    pub fn set_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.inner.set_reconnect(policy);
+    }
+
+    /// Send the commands added to a pipeline by `build` together, see `Pipeline`.
+    ///
+    /// ```no_run
+    /// # fn run(client: &mut walrus::client::blocking::Client) {
+    /// use bytes::Bytes;
+    ///
+    /// let replies = client.pipeline(|pipeline| {
+    ///     pipeline
+    ///         .set(Bytes::from("key"), Bytes::from("value"), None)
+    ///         .get(Bytes::from("key"))
+    /// });
+    /// # }
+    /// ```
+    pub fn pipeline(
+        &mut self,
+        build: impl FnOnce(Pipeline<'_>) -> Pipeline<'_>,
+    ) -> Result<Vec<Result<Frame, WalrusError>>, WalrusError> {
+        let pipeline = build(self.inner.pipeline());
+        self.runtime.block_on(pipeline.execute())
+    }
+
+    /// Send `Ping` command to the server, see `client::Client::ping`.
+    pub fn ping(&mut self, msg: Option<Bytes>) -> Result<Bytes, WalrusError> {
+        self.runtime.block_on(self.inner.ping(msg))
+    }
+
+    /// `Get` the `value` associated with the `key`, see `client::Client::get`.
+    pub fn get(&mut self, key: Bytes) -> Result<Option<Bytes>, WalrusError> {
+        self.runtime.block_on(self.inner.get(key))
+    }
+
+    /// `Set` a value for the key, see `client::Client::set`.
+    pub fn set(
+        &mut self,
+        key: Bytes,
+        value: Bytes,
+        expire: Option<Duration>,
+    ) -> Result<Bytes, WalrusError> {
+        self.runtime.block_on(self.inner.set(key, value, expire))
+    }
+
+    /// `Set` a value for the key only if it doesn't exist, see `client::Client::set_nx`.
+    pub fn set_nx(
+        &mut self,
+        key: Bytes,
+        value: Bytes,
+        expire: Option<Duration>,
+    ) -> Result<bool, WalrusError> {
+        self.runtime.block_on(self.inner.set_nx(key, value, expire))
+    }
+
+    /// Append `data` to the end of the list with key `list_key`, see `client::Client::rpush`.
+    pub fn rpush(&mut self, list_key: Bytes, data: VecDeque<Data>) -> Result<i64, WalrusError> {
+        self.runtime.block_on(self.inner.rpush(list_key, data))
+    }
+
+    /// Push `data` to the start of the list with key `list_key`, see `client::Client::lpush`.
+    pub fn lpush(&mut self, list_key: Bytes, data: VecDeque<Data>) -> Result<i64, WalrusError> {
+        self.runtime.block_on(self.inner.lpush(list_key, data))
+    }
+
+    /// Remove and return the first `count` elements of the list with key `list_key`, see
+    /// `client::Client::lpop`.
+    pub fn lpop(
+        &mut self,
+        list_key: Bytes,
+        count: Option<i64>,
+    ) -> Result<Option<Vec<Data>>, WalrusError> {
+        self.runtime.block_on(self.inner.lpop(list_key, count))
+    }
+
+    /// Remove and return the first element of the first non empty list of `keys`, waiting up
+    /// to `timeout` seconds for one, see `client::Client::blpop`.
+    pub fn blpop(
+        &mut self,
+        keys: Vec<Bytes>,
+        timeout: f64,
+    ) -> Result<Option<Vec<Data>>, WalrusError> {
+        self.runtime.block_on(self.inner.blpop(keys, timeout))
+    }
+
+    /// Get the length of a list, see `client::Client::llen`.
+    pub fn llen(&mut self, list_key: Bytes) -> Result<i64, WalrusError> {
+        self.runtime.block_on(self.inner.llen(list_key))
+    }
+
+    /// Get the time left before `key` expires, see `client::Client::pttl`.
+    pub fn pttl(&mut self, key: Bytes) -> Result<i64, WalrusError> {
+        self.runtime.block_on(self.inner.pttl(key))
+    }
+
+    /// Fetch items of list with key `list_key` in the range \[`start_index`, `end_index`\], see
+    /// `client::Client::lrange`.
+    pub fn lrange(
+        &mut self,
+        list_key: Bytes,
+        start_index: i64,
+        end_index: i64,
+    ) -> Result<Vec<Data>, WalrusError> {
+        self.runtime
+            .block_on(self.inner.lrange(list_key, start_index, end_index))
+    }
+
+    /// Get every key matching the glob-style `pattern`.
+    pub fn keys(&mut self, pattern: Bytes) -> Result<Vec<Bytes>, WalrusError> {
+        self.runtime.block_on(self.inner.keys(pattern))
+    }
+
+    /// Iterate the keyspace from `cursor`, see `client::Client::scan`.
+    pub fn scan(
+        &mut self,
+        cursor: u64,
+        pattern: Option<Bytes>,
+        count: Option<usize>,
+    ) -> Result<(u64, Vec<Bytes>), WalrusError> {
+        self.runtime
+            .block_on(self.inner.scan(cursor, pattern, count))
+    }
+
+    /// Get the type of the value of `key`, see `client::Client::wtype`.
+    pub fn wtype(&mut self, key: Bytes) -> Result<Bytes, WalrusError> {
+        self.runtime.block_on(self.inner.wtype(key))
+    }
+
+    /// Switch the protocol used by the server for this connection, see
+    /// `client::Client::hello`.
+    pub fn hello(&mut self, protover: Option<i64>) -> Result<Vec<Data>, WalrusError> {
+        self.runtime.block_on(self.inner.hello(protover))
+    }
+
+    /// Get the id of this connection.
+    pub fn client_id(&mut self) -> Result<i64, WalrusError> {
+        self.runtime.block_on(self.inner.client_id())
+    }
+
+    /// Set the name of this connection, see `client::Client::client_setname`.
+    pub fn client_setname(&mut self, name: Bytes) -> Result<(), WalrusError> {
+        self.runtime.block_on(self.inner.client_setname(name))
+    }
+
+    /// Get the name of this connection, `None` if no name is set.
+    pub fn client_getname(&mut self) -> Result<Option<Bytes>, WalrusError> {
+        self.runtime.block_on(self.inner.client_getname())
+    }
+
+    /// Get the configuration parameters matching `pattern`, see `client::Client::config_get`.
+    pub fn config_get(&mut self, pattern: Bytes) -> Result<Vec<(Bytes, Bytes)>, WalrusError> {
+        self.runtime.block_on(self.inner.config_get(pattern))
+    }
+
+    /// Set the configuration parameter `name` to `value`, see `client::Client::config_set`.
+    pub fn config_set(&mut self, name: Bytes, value: Bytes) -> Result<(), WalrusError> {
+        self.runtime.block_on(self.inner.config_set(name, value))
+    }
+
+    /// Write a snapshot of the dataset to disk, see `client::Client::save`.
+    pub fn save(&mut self) -> Result<(), WalrusError> {
+        self.runtime.block_on(self.inner.save())
+    }
+
+    /// Write a snapshot of the dataset to disk in the background, see `client::Client::bgsave`.
+    pub fn bgsave(&mut self) -> Result<(), WalrusError> {
+        self.runtime.block_on(self.inner.bgsave())
+    }
+
+    /// Describe the state of the server, every section if `sections` is empty.
+    pub fn info(&mut self, sections: Vec<Bytes>) -> Result<Bytes, WalrusError> {
+        self.runtime.block_on(self.inner.info(sections))
+    }
+}
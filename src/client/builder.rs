@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::{net::ToSocketAddrs, time};
+
+use super::{Client, ReconnectPolicy, reconnect::handshake};
+use crate::{
+    connection::{Protocol, SocketOptions},
+    errors::WalrusError,
+    frame::Frame,
+};
+
+/// Username authenticating with only a password, like Redis.
+const DEFAULT_USERNAME: &str = "default";
+
+/// Options of a `Client`, created by `Client::builder`.
+///
+/// Credentials, the protocol and the name of the connection are sent with a single `HELLO` once
+/// connected, followed by `SELECT` if another database than 0 is used, and again by a client
+/// reconnecting. Walrus has no users or passwords configured and accepts any credentials, they
+/// matter for servers requiring them, such as Redis.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), walrus::errors::WalrusError> {
+/// use bytes::Bytes;
+/// use std::time::Duration;
+/// use walrus::client::Client;
+///
+/// let mut client = Client::builder("127.0.0.1:6380")
+///     .password(Bytes::from("secret"))
+///     .name(Bytes::from("worker"))
+///     .database(2)
+///     .connect_timeout(Duration::from_secs(1))
+///     .read_timeout(Duration::from_secs(5))
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ClientBuilder<T> {
+    addr: T,
+    username: Option<Bytes>,
+    password: Option<Bytes>,
+    database: i64,
+    name: Option<Bytes>,
+    protocol: Protocol,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    read_buffer_size: Option<u16>,
+    write_buffer_size: Option<u16>,
+    socket_options: SocketOptions,
+    reconnect: Option<ReconnectPolicy>,
+}
+
+impl Client {
+    /// Options of a connection to the server at `addr`, see `ClientBuilder`.
+    pub fn builder<T: ToSocketAddrs>(addr: T) -> ClientBuilder<T> {
+        ClientBuilder {
+            addr,
+            username: None,
+            password: None,
+            database: 0,
+            name: None,
+            protocol: Protocol::Resp2,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            read_buffer_size: None,
+            write_buffer_size: None,
+            socket_options: SocketOptions::default(),
+            reconnect: None,
+        }
+    }
+}
+
+impl<T: ToSocketAddrs> ClientBuilder<T> {
+    /// Authenticate as `username`, `default` if only a password is set. Connecting fails if
+    /// no password is set.
+    pub fn username(mut self, username: Bytes) -> ClientBuilder<T> {
+        self.username = Some(username);
+        self
+    }
+
+    /// Authenticate with `password`. Walrus accepts any password.
+    pub fn password(mut self, password: Bytes) -> ClientBuilder<T> {
+        self.password = Some(password);
+        self
+    }
+
+    /// Index of the database used, 0 by default. Connecting fails with the error of the server
+    /// if the index is out of range.
+    pub fn database(mut self, database: i64) -> ClientBuilder<T> {
+        self.database = database;
+        self
+    }
+
+    /// Name the connection, as `CLIENT SETNAME` does.
+    pub fn name(mut self, name: Bytes) -> ClientBuilder<T> {
+        self.name = Some(name);
+        self
+    }
+
+    /// Protocol of the replies, `Resp2` by default.
+    pub fn protocol(mut self, protocol: Protocol) -> ClientBuilder<T> {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Fail connecting once it took `timeout`, the OS limit applies by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder<T> {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail commands once the server sent nothing of their reply for `timeout`, they wait
+    /// forever by default. The connection isn't used again after a timeout, later commands
+    /// fail unless the client reconnects.
+    pub fn read_timeout(mut self, timeout: Duration) -> ClientBuilder<T> {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Fail commands once sending them took `timeout`, they wait forever by default. Like
+    /// `read_timeout`, later commands then fail unless the client reconnects.
+    pub fn write_timeout(mut self, timeout: Duration) -> ClientBuilder<T> {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Initial capacity in KB of the buffer replies are read into, 16 by default.
+    pub fn read_buffer_size(mut self, size: u16) -> ClientBuilder<T> {
+        self.read_buffer_size = Some(size);
+        self
+    }
+
+    /// Initial capacity in KB of the buffer commands are written into, 16 by default.
+    pub fn write_buffer_size(mut self, size: u16) -> ClientBuilder<T> {
+        self.write_buffer_size = Some(size);
+        self
+    }
+
+    /// Tune the TCP socket with `options`, `SocketOptions::default` by default.
+    pub fn socket_options(mut self, options: SocketOptions) -> ClientBuilder<T> {
+        self.socket_options = options;
+        self
+    }
+
+    /// Re-establish broken connections following `policy`, see `Client::set_reconnect`.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> ClientBuilder<T> {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Connect to the server, then authenticate, name the connection and select the database.
+    pub async fn connect(self) -> Result<Client, WalrusError> {
+        if self.username.is_some() && self.password.is_none() {
+            return Err("A username is set without a password".into());
+        }
+
+        let connect = Client::connect_with_options(
+            self.addr,
+            self.read_buffer_size,
+            self.write_buffer_size,
+            &self.socket_options,
+        );
+        let mut client = match self.connect_timeout {
+            Some(timeout) => time::timeout(timeout, connect)
+                .await
+                .map_err(|_| WalrusError::from("Timed out connecting to the server"))??,
+            None => connect.await?,
+        };
+
+        client.connection.set_read_timeout(self.read_timeout);
+        client.connection.set_write_timeout(self.write_timeout);
+        client.reconnect.connect_timeout = self.connect_timeout;
+        client.reconnect.read_timeout = self.read_timeout;
+        client.reconnect.write_timeout = self.write_timeout;
+        client.reconnect.auth = self.password.map(|password| {
+            let username = self
+                .username
+                .unwrap_or_else(|| Bytes::from(DEFAULT_USERNAME));
+            (username, password)
+        });
+
+        if self.protocol == Protocol::Resp3
+            || client.reconnect.auth.is_some()
+            || self.name.is_some()
+        {
+            let hello = handshake(
+                self.protocol,
+                client.reconnect.auth.clone(),
+                self.name.clone(),
+            );
+            if let Frame::Error(err) = client.request(&hello.into_frame()).await? {
+                return Err(err.into());
+            }
+            client.connection.set_protocol(self.protocol);
+            client.connection.set_name(self.name);
+        }
+        if self.database != 0 {
+            client.select(self.database).await?;
+        }
+
+        client.set_reconnect(self.reconnect);
+        Ok(client)
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bytes::Bytes;
+
+use super::Client;
+use crate::{
+    cmd::{Get, Info, Keys, LLen, LPop, LPush, LRange, PTtl, Ping, RPush, Set, Type},
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+};
+
+/// Batch of commands sent to the server together, created by `Client::pipeline`.
+///
+/// Commands are written with a single flush once `execute` is called, then their replies are
+/// read in order, saving a round trip per command. Commands don't run atomically, commands of
+/// other connections may run between them.
+///
+/// ```no_run
+/// # async fn run(client: &mut walrus::client::Client) -> Result<(), walrus::errors::WalrusError> {
+/// use bytes::Bytes;
+/// use walrus::Frame;
+///
+/// let replies = client
+///     .pipeline()
+///     .set(Bytes::from("key"), Bytes::from("value"), None)
+///     .get(Bytes::from("key"))
+///     .execute()
+///     .await?;
+///
+/// assert_eq!(replies[1].as_ref().ok(), Some(&Frame::Bulk(Bytes::from("value"))));
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "commands are only sent by `execute`"]
+pub struct Pipeline<'a> {
+    client: &'a mut Client,
+    commands: Vec<Frame>,
+}
+
+impl Client {
+    /// Start a batch of commands sent together, see `Pipeline`.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline {
+            client: self,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Add `frame`, a command encoded as an array of bulk strings.
+    pub fn cmd(mut self, frame: Frame) -> Pipeline<'a> {
+        self.commands.push(frame);
+        self
+    }
+
+    /// Add a `Ping` command.
+    pub fn ping(self, msg: Option<Bytes>) -> Pipeline<'a> {
+        self.cmd(Ping::new(msg).into_frame())
+    }
+
+    /// Add a `Get` command.
+    pub fn get(self, key: Bytes) -> Pipeline<'a> {
+        self.cmd(Get::new(key).into_frame())
+    }
+
+    /// Add a `Set` command, with optional expiration duration.
+    pub fn set(self, key: Bytes, value: Bytes, expire: Option<Duration>) -> Pipeline<'a> {
+        self.cmd(Set::new(key, value, expire).into_frame())
+    }
+
+    /// Add a `Set` command setting the key only if it doesn't exist.
+    pub fn set_nx(self, key: Bytes, value: Bytes, expire: Option<Duration>) -> Pipeline<'a> {
+        self.cmd(Set::new_nx(key, value, expire).into_frame())
+    }
+
+    /// Add a `RPush` command.
+    pub fn rpush(self, list_key: Bytes, data: VecDeque<Data>) -> Pipeline<'a> {
+        self.cmd(RPush::new(list_key, data).into_frame())
+    }
+
+    /// Add a `LPush` command.
+    pub fn lpush(self, list_key: Bytes, data: VecDeque<Data>) -> Pipeline<'a> {
+        self.cmd(LPush::new(list_key, data).into_frame())
+    }
+
+    /// Add a `LPop` command.
+    pub fn lpop(self, list_key: Bytes, count: Option<i64>) -> Pipeline<'a> {
+        self.cmd(LPop::new(list_key, count).into_frame())
+    }
+
+    /// Add a `LLen` command.
+    pub fn llen(self, list_key: Bytes) -> Pipeline<'a> {
+        self.cmd(LLen::new(list_key).into_frame())
+    }
+
+    /// Add a `LRange` command.
+    pub fn lrange(self, list_key: Bytes, start_index: i64, end_index: i64) -> Pipeline<'a> {
+        self.cmd(LRange::new(list_key, start_index, end_index).into_frame())
+    }
+
+    /// Add a `PTtl` command.
+    pub fn pttl(self, key: Bytes) -> Pipeline<'a> {
+        self.cmd(PTtl::new(key).into_frame())
+    }
+
+    /// Add a `Keys` command.
+    pub fn keys(self, pattern: Bytes) -> Pipeline<'a> {
+        self.cmd(Keys::new(pattern).into_frame())
+    }
+
+    /// Add a `Type` command.
+    pub fn wtype(self, key: Bytes) -> Pipeline<'a> {
+        self.cmd(Type::new(key).into_frame())
+    }
+
+    /// Add an `Info` command.
+    pub fn info(self, sections: Vec<Bytes>) -> Pipeline<'a> {
+        self.cmd(Info::new(sections).into_frame())
+    }
+
+    /// Number of commands added.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns `true` if no command was added.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Send the commands and read their replies, in the order the commands were added.
+    ///
+    /// Errors replied by the server fail only their command, the replies of the others are
+    /// still returned. An error is returned if the connection fails, some commands may have run
+    /// then. Pipelines are not sent again by a client reconnecting.
+    pub async fn execute(self) -> Result<Vec<Result<Frame, WalrusError>>, WalrusError> {
+        let Pipeline { client, commands } = self;
+        client.check_broken().await?;
+        for frame in &commands {
+            client.connection.write_frame(frame);
+        }
+
+        let mut replies = Vec::with_capacity(commands.len());
+        for _ in 0..commands.len() {
+            let reply = client.read_response().await.inspect_err(|_| {
+                client.reconnect.broken = true;
+            });
+            replies.push(match reply? {
+                Frame::Error(err) => Err(err.into()),
+                frame => Ok(frame),
+            });
+        }
+        Ok(replies)
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::{
+    net::ToSocketAddrs,
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{self, Instant},
+};
+
+use super::Client;
+use crate::{connection::SocketOptions, errors::WalrusError};
+
+/// Largest number of connections of a pool by default.
+pub const DEFAULT_MAX_SIZE: usize = 16;
+
+/// Time a connection can be idle for before it is checked with `PING` by default.
+pub const DEFAULT_HEALTH_CHECK_AFTER: Duration = Duration::from_secs(5);
+
+/// Opens a new connection to the server of a pool.
+type Connector = Box<dyn Fn() -> BoxFuture<'static, Result<Client, WalrusError>> + Send + Sync>;
+
+/// Pool of reusable connections to a Walrus server, so a service handling many requests doesn't
+/// open a connection for each of them.
+///
+/// Connections are checked out with `get` and checked in again when the `PooledClient` is
+/// dropped. New connections are opened when no idle one is left, up to the maximum size of the
+/// pool, after which `get` waits for a connection to be checked in. Connections idle for a while
+/// are checked with `PING` before being handed out, ones that fail are replaced.
+///
+/// The pool is cheap to clone, clones share the connections.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), walrus::errors::WalrusError> {
+/// use walrus::client::Pool;
+///
+/// let pool = Pool::builder("127.0.0.1:6380")
+///     .min_size(2)
+///     .max_size(8)
+///     .build()
+///     .await?;
+///
+/// let mut client = pool.get().await?;
+/// client.ping(None).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+/// State shared by the clones of a pool and its checked out connections.
+struct Shared {
+    connect: Connector,
+    /// Connections checked in, the most recently used last.
+    idle: Mutex<VecDeque<Idle>>,
+    /// One permit per connection that can be checked out, `max_size` in all.
+    permits: Arc<Semaphore>,
+    min_size: usize,
+    max_size: usize,
+    checkout_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    health_check_after: Duration,
+}
+
+/// A connection waiting in the pool.
+struct Idle {
+    client: Client,
+    /// Instant it was checked in.
+    since: Instant,
+}
+
+/// Number of connections of a pool, see `Pool::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStatus {
+    /// Connections waiting to be checked out.
+    pub idle: usize,
+    /// Connections checked out.
+    pub in_use: usize,
+    /// Largest number of connections checked out at once.
+    pub max_size: usize,
+}
+
+/// Options of a `Pool`, created by `Pool::builder`.
+pub struct PoolBuilder {
+    connect: Connector,
+    min_size: usize,
+    max_size: usize,
+    checkout_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    health_check_after: Duration,
+}
+
+impl PoolBuilder {
+    /// Number of connections opened when the pool is built and kept when idle, 0 by default.
+    pub fn min_size(mut self, min_size: usize) -> PoolBuilder {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Largest number of connections, `DEFAULT_MAX_SIZE` by default. At least one connection is
+    /// allowed.
+    pub fn max_size(mut self, max_size: usize) -> PoolBuilder {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// Fail `Pool::get` once it waited for a connection to be checked in for `timeout`, it waits
+    /// as long as needed by default.
+    pub fn checkout_timeout(mut self, timeout: Duration) -> PoolBuilder {
+        self.checkout_timeout = Some(timeout);
+        self
+    }
+
+    /// Close connections idle for `timeout`, as long as `min_size` connections are left. They are
+    /// kept by default.
+    pub fn idle_timeout(mut self, timeout: Duration) -> PoolBuilder {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Check connections idle for `after` with `PING` before handing them out,
+    /// `DEFAULT_HEALTH_CHECK_AFTER` by default. Zero checks every connection checked out.
+    pub fn health_check_after(mut self, after: Duration) -> PoolBuilder {
+        self.health_check_after = after;
+        self
+    }
+
+    /// Build the pool, opening its first `min_size` connections.
+    pub async fn build(self) -> Result<Pool, WalrusError> {
+        let min_size = self.min_size.min(self.max_size);
+        let mut idle = VecDeque::with_capacity(self.max_size);
+        for _ in 0..min_size {
+            idle.push_back(Idle {
+                client: (self.connect)().await?,
+                since: Instant::now(),
+            });
+        }
+
+        Ok(Pool {
+            shared: Arc::new(Shared {
+                connect: self.connect,
+                idle: Mutex::new(idle),
+                permits: Arc::new(Semaphore::new(self.max_size)),
+                min_size,
+                max_size: self.max_size,
+                checkout_timeout: self.checkout_timeout,
+                idle_timeout: self.idle_timeout,
+                health_check_after: self.health_check_after,
+            }),
+        })
+    }
+}
+
+impl Pool {
+    /// Options of a pool of connections to the server at `addr`.
+    pub fn builder<T>(addr: T) -> PoolBuilder
+    where
+        T: ToSocketAddrs + Clone + Send + Sync + 'static,
+    {
+        Pool::builder_with_options(addr, None, None, SocketOptions::default())
+    }
+
+    /// Options of a pool of connections to the server at `addr`, with the buffer sizes of
+    /// `Client::connect` and the sockets tuned by `options`.
+    pub fn builder_with_options<T>(
+        addr: T,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+        options: SocketOptions,
+    ) -> PoolBuilder
+    where
+        T: ToSocketAddrs + Clone + Send + Sync + 'static,
+    {
+        PoolBuilder {
+            connect: Box::new(move || {
+                let addr = addr.clone();
+                Box::pin(async move {
+                    Client::connect_with_options(
+                        addr,
+                        read_buffer_size,
+                        write_buffer_size,
+                        &options,
+                    )
+                    .await
+                })
+            }),
+            min_size: 0,
+            max_size: DEFAULT_MAX_SIZE,
+            checkout_timeout: None,
+            idle_timeout: None,
+            health_check_after: DEFAULT_HEALTH_CHECK_AFTER,
+        }
+    }
+
+    /// Check out a connection, waiting for one to be checked in if `max_size` are already.
+    ///
+    /// The most recently used idle connection is handed out, checked with `PING` first if it
+    /// was idle for longer than `health_check_after`. A new connection is opened if none is
+    /// idle.
+    pub async fn get(&self) -> Result<PooledClient, WalrusError> {
+        let permits = self.shared.permits.clone();
+        let permit = match self.shared.checkout_timeout {
+            Some(timeout) => time::timeout(timeout, permits.acquire_owned())
+                .await
+                .map_err(|_| WalrusError::from("Timed out waiting for a connection of the pool"))?,
+            None => permits.acquire_owned().await,
+        }
+        .expect("the semaphore of the pool is never closed");
+
+        let client = loop {
+            let Some(idle) = self.shared.checkout_idle() else {
+                break (self.shared.connect)().await?;
+            };
+
+            if idle.since.elapsed() < self.shared.health_check_after {
+                break idle.client;
+            }
+            let mut client = idle.client;
+            // A connection closed by the server, such as after its `timeout`, is dropped.
+            if client.ping(None).await.is_ok() {
+                break client;
+            }
+        };
+
+        Ok(PooledClient {
+            client: Some(client),
+            shared: self.shared.clone(),
+            _permit: permit,
+        })
+    }
+
+    /// Number of connections idle and checked out.
+    pub fn status(&self) -> PoolStatus {
+        let idle = self.shared.idle.lock().unwrap().len();
+        PoolStatus {
+            idle,
+            in_use: self.shared.max_size - self.shared.permits.available_permits(),
+            max_size: self.shared.max_size,
+        }
+    }
+}
+
+impl Shared {
+    /// Take the most recently used idle connection, closing the ones idle for longer than
+    /// `idle_timeout` beyond `min_size`.
+    fn checkout_idle(&self) -> Option<Idle> {
+        let mut idle = self.idle.lock().unwrap();
+
+        if let Some(timeout) = self.idle_timeout {
+            // The least recently used connections are first.
+            while idle.len() > self.min_size
+                && idle
+                    .front()
+                    .is_some_and(|oldest| oldest.since.elapsed() >= timeout)
+            {
+                idle.pop_front();
+            }
+        }
+
+        idle.pop_back()
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// A connection checked out of a `Pool`, used as a `Client`. It is checked in again when
+/// dropped, unless `discard` is called.
+pub struct PooledClient {
+    /// `None` once discarded.
+    client: Option<Client>,
+    shared: Arc<Shared>,
+    /// Released after the connection is checked in, so a waiting `get` finds it.
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledClient {
+    /// Close the connection instead of checking it in, such as after an error left it unusable
+    /// or a command was cancelled before its reply was read.
+    pub fn discard(mut self) {
+        self.client.take();
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("the client is only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client
+            .as_mut()
+            .expect("the client is only taken when dropped")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.shared.idle.lock().unwrap().push_back(Idle {
+                client,
+                since: Instant::now(),
+            });
+        }
+    }
+}
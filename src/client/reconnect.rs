@@ -0,0 +1,275 @@
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use rand::RngExt;
+use tokio::{net::TcpStream, time};
+
+use super::Client;
+use crate::{
+    Connection,
+    cmd::{self, Hello, Select},
+    connection::{Protocol, SocketOptions},
+    errors::WalrusError,
+    frame::Frame,
+};
+
+/// How a `Client` re-establishes its connection once broken, set with `Client::set_reconnect`.
+///
+/// Attempts are spaced by an exponential backoff with jitter: the delay before attempt `n`, from
+/// 0, is `base_delay * 2^n` capped at `max_delay`, of which a random half is waited. The jitter
+/// keeps many clients losing the server at once from reconnecting in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Number of attempts to reconnect for one command before its error is returned.
+    pub max_retries: u32,
+    /// Delay before the first attempt.
+    pub base_delay: Duration,
+    /// Longest delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    /// 5 attempts, from 50ms up to 2s apart.
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before attempt `attempt`, from 0.
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        // Equal jitter: half the backoff, plus a random part of the other half.
+        backoff / 2 + backoff.mul_f64(rand::rng().random_range(0.0..0.5))
+    }
+}
+
+/// Where a `Client` connected to, so it can connect again.
+pub(super) enum Endpoint {
+    Tcp {
+        addr: SocketAddr,
+        options: SocketOptions,
+    },
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+    /// The connection can't be re-established, such as a TCP address that couldn't be read.
+    None,
+}
+
+/// Address and options of the connection of a `Client`, applied again to new connections.
+pub(super) struct Reconnect {
+    pub(super) endpoint: Endpoint,
+    pub(super) read_buffer_size: Option<u16>,
+    pub(super) write_buffer_size: Option<u16>,
+    pub(super) connect_timeout: Option<Duration>,
+    pub(super) read_timeout: Option<Duration>,
+    pub(super) write_timeout: Option<Duration>,
+    /// Username and password sent with `HELLO`.
+    pub(super) auth: Option<(Bytes, Bytes)>,
+    /// Database selected with `ClientBuilder::database` or `Client::select`.
+    pub(super) database: i64,
+    /// `None` if the client doesn't reconnect.
+    pub(super) policy: Option<ReconnectPolicy>,
+    /// Set once reading a reply failed, such as on a timeout. A late reply may still arrive on
+    /// the connection and be taken for the reply of the next command, so it isn't used again.
+    pub(super) broken: bool,
+}
+
+impl Reconnect {
+    /// Reconnection state of a client connected to `endpoint`, not reconnecting yet.
+    pub(super) fn new(
+        endpoint: Endpoint,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+    ) -> Reconnect {
+        Reconnect {
+            endpoint,
+            read_buffer_size,
+            write_buffer_size,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            auth: None,
+            database: 0,
+            policy: None,
+            broken: false,
+        }
+    }
+}
+
+impl Client {
+    /// Re-establish broken connections following `policy`, or never if `None`, the default.
+    ///
+    /// When the connection breaks while sending a command or waiting for its reply, the
+    /// client connects again, authenticates with the credentials of `ClientBuilder`, switches
+    /// to RESP3 if `HELLO 3` was sent, restores the name set with `client_setname` and selects
+    /// the database selected again.
+    /// Commands that neither modify the dataset nor administer the server, such as `GET` or
+    /// `INFO`, are then sent again transparently. The others may have been executed before the
+    /// connection broke, their error is returned and the next command uses the new connection.
+    ///
+    /// Without a policy, commands fail once the connection broke or a reply timed out.
+    ///
+    /// `MONITOR`, client tracking and pause are not restored.
+    pub fn set_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect.policy = policy;
+    }
+
+    /// Send `frame` and read its reply, re-establishing the connection if it broke and the
+    /// client reconnects.
+    pub(super) async fn request(&mut self, frame: &Frame) -> Result<Frame, WalrusError> {
+        self.check_broken().await?;
+        self.connection.write_frame(frame);
+        let err = match self.read_response().await {
+            Ok(reply) => return Ok(reply),
+            Err(err) => err,
+        };
+        self.reconnect.broken = true;
+        let Some(policy) = self.reconnect.policy else {
+            return Err(err);
+        };
+
+        let idempotent = command_name(frame).is_some_and(cmd::is_idempotent);
+        let mut err = err;
+        let mut attempt = 0;
+        while attempt < policy.max_retries {
+            time::sleep(policy.delay(attempt)).await;
+            attempt += 1;
+
+            if let Err(reconnect_err) = self.reestablish().await {
+                err = reconnect_err;
+                continue;
+            }
+            if !idempotent {
+                return Err(err);
+            }
+
+            self.connection.write_frame(frame);
+            match self.read_response().await {
+                Ok(reply) => return Ok(reply),
+                Err(retry_err) => {
+                    self.reconnect.broken = true;
+                    err = retry_err;
+                }
+            }
+        }
+
+        Err(err)
+    }
+
+    /// Re-establish the connection if an earlier command broke it and the client reconnects,
+    /// fail otherwise.
+    pub(super) async fn check_broken(&mut self) -> Result<(), WalrusError> {
+        if !self.reconnect.broken {
+            return Ok(());
+        }
+        match self.reconnect.policy {
+            Some(_) => self.reestablish().await,
+            None => Err("Connection broken by an earlier command, connect again".into()),
+        }
+    }
+
+    /// Open a new connection to the endpoint, restoring the options, protocol, name and
+    /// database of the broken one.
+    async fn reestablish(&mut self) -> Result<(), WalrusError> {
+        let mut connection = match &self.reconnect.endpoint {
+            Endpoint::Tcp { addr, options } => {
+                let socket = match self.reconnect.connect_timeout {
+                    Some(timeout) => time::timeout(timeout, TcpStream::connect(addr))
+                        .await
+                        .map_err(|_| WalrusError::from("Timed out connecting to the server"))??,
+                    None => TcpStream::connect(addr).await?,
+                };
+                let connection = Connection::new(
+                    socket,
+                    self.reconnect.read_buffer_size,
+                    self.reconnect.write_buffer_size,
+                );
+                connection.set_socket_options(options)?;
+                connection
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let socket = tokio::net::UnixStream::connect(path).await?;
+                Connection::new(
+                    socket,
+                    self.reconnect.read_buffer_size,
+                    self.reconnect.write_buffer_size,
+                )
+            }
+            Endpoint::None => return Err("Connection can't be re-established".into()),
+        };
+
+        connection.set_read_timeout(self.reconnect.read_timeout);
+        connection.set_write_timeout(self.reconnect.write_timeout);
+
+        let protocol = self.connection.protocol();
+        let name = self.connection.name().cloned();
+        if protocol == Protocol::Resp3 || self.reconnect.auth.is_some() || name.is_some() {
+            let hello = handshake(protocol, self.reconnect.auth.clone(), name.clone());
+            restore(&mut connection, hello.into_frame()).await?;
+            connection.set_protocol(protocol);
+            connection.set_name(name);
+        }
+        if self.reconnect.database != 0 {
+            let select = Select::new(self.reconnect.database);
+            restore(&mut connection, select.into_frame()).await?;
+        }
+
+        self.connection = connection;
+        self.reconnect.broken = false;
+        Ok(())
+    }
+}
+
+/// `HELLO` switching a new connection to `protocol`, authenticating with `auth` and naming
+/// it `name`.
+pub(super) fn handshake(
+    protocol: Protocol,
+    auth: Option<(Bytes, Bytes)>,
+    name: Option<Bytes>,
+) -> Hello {
+    let protover = match protocol {
+        Protocol::Resp2 => 2,
+        Protocol::Resp3 => 3,
+    };
+    let mut hello = Hello::new(Some(protover));
+    if let Some((username, password)) = auth {
+        hello = hello.auth(username, password);
+    }
+    if let Some(name) = name {
+        hello = hello.setname(name);
+    }
+    hello
+}
+
+/// Send `frame` on a new connection, failing if the server replies with an error.
+async fn restore(connection: &mut Connection, frame: Frame) -> Result<(), WalrusError> {
+    connection.write_frame(&frame);
+    loop {
+        match connection.read_frame().await? {
+            Some(Frame::Push(_)) => {}
+            Some(Frame::Error(err)) => return Err(err.into()),
+            Some(_) => return Ok(()),
+            None => return Err("No response from server".into()),
+        }
+    }
+}
+
+/// Name of the command of `frame`, its first element.
+fn command_name(frame: &Frame) -> Option<&[u8]> {
+    match frame {
+        Frame::Array(frames) => match frames.first()? {
+            Frame::Bulk(name) => Some(name.as_ref()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
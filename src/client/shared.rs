@@ -0,0 +1,304 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bytes::Bytes;
+use tokio::{
+    net::ToSocketAddrs,
+    sync::{mpsc, oneshot},
+};
+
+use super::{Client, bulks};
+use crate::{
+    cmd::{Get, Info, Keys, LLen, LPop, LPush, LRange, PTtl, Ping, RPush, Set, Type},
+    connection::{ReadHalf, WriteHalf},
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    task,
+};
+
+/// Number of requests waiting to be written before callers wait for room in the queue.
+const QUEUE_SIZE: usize = 1024;
+
+/// A command waiting to be written, with where to send its reply.
+struct Request {
+    frame: Frame,
+    reply: oneshot::Sender<Frame>,
+}
+
+/// Connection to a Walrus server shared by many tasks, without `&mut self` on every call.
+///
+/// Commands of every clone are queued and written on the one connection in order, those queued
+/// while the connection is busy are flushed together. The server replies in the same order, so
+/// each reply is handed to the task which sent its command. The connection is closed once every
+/// clone is dropped.
+///
+/// Commands that change the state of the connection, such as `HELLO`, `CLIENT SETNAME` or
+/// `MONITOR`, and blocking commands, which would hold up the commands of every other task, are
+/// left to `Client`. Push messages are discarded.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), walrus::errors::WalrusError> {
+/// use bytes::Bytes;
+/// use walrus::client::SharedClient;
+///
+/// let client = SharedClient::connect("127.0.0.1:6380", None, None).await?;
+///
+/// let mut handles = Vec::new();
+/// for i in 0..10 {
+///     let client = client.clone();
+///     handles.push(tokio::spawn(async move {
+///         client.set(Bytes::from(format!("key:{i}")), Bytes::from("value"), None).await
+///     }));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedClient {
+    requests: mpsc::Sender<Request>,
+}
+
+impl SharedClient {
+    /// Establish a connection with Walrus server at `addr`, see `Client::connect`.
+    pub async fn connect<T: ToSocketAddrs>(
+        addr: T,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+    ) -> Result<SharedClient, WalrusError> {
+        Client::connect(addr, read_buffer_size, write_buffer_size)
+            .await?
+            .into_shared()
+    }
+
+    /// Send `frame`, a command encoded as an array of bulk strings, and wait for its reply.
+    /// Errors replied by the server are returned as `Frame::Error`.
+    pub async fn request(&self, frame: Frame) -> Result<Frame, WalrusError> {
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(Request { frame, reply })
+            .await
+            .map_err(|_| WalrusError::from("Connection closed"))?;
+        receiver
+            .await
+            .map_err(|_| "Connection closed before the reply was received".into())
+    }
+
+    /// Send `Ping` command to the server, see `Client::ping`.
+    pub async fn ping(&self, msg: Option<Bytes>) -> Result<Bytes, WalrusError> {
+        let frame = Ping::new(msg).into_frame();
+        match self.request(frame).await? {
+            Frame::Simple(value) => Ok(value),
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Get` the `value` associated with the `key`, see `Client::get`.
+    pub async fn get(&self, key: Bytes) -> Result<Option<Bytes>, WalrusError> {
+        let frame = Get::new(key).into_frame();
+        match self.request(frame).await? {
+            Frame::Simple(value) => Ok(Some(value)),
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Set` a value for the key, see `Client::set`.
+    pub async fn set(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        expire: Option<Duration>,
+    ) -> Result<Bytes, WalrusError> {
+        let frame = Set::new(key, value, expire).into_frame();
+        match self.request(frame).await? {
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Set` a value for the key only if it doesn't exist, see `Client::set_nx`.
+    pub async fn set_nx(
+        &self,
+        key: Bytes,
+        value: Bytes,
+        expire: Option<Duration>,
+    ) -> Result<bool, WalrusError> {
+        let frame = Set::new_nx(key, value, expire).into_frame();
+        match self.request(frame).await? {
+            Frame::Bulk(_) => Ok(true),
+            Frame::Null => Ok(false),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Append `data` to the end of the list with key `list_key`, see `Client::rpush`.
+    pub async fn rpush(&self, list_key: Bytes, data: VecDeque<Data>) -> Result<i64, WalrusError> {
+        let frame = RPush::new(list_key, data).into_frame();
+        match self.request(frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Push `data` to the start of the list with key `list_key`, see `Client::lpush`.
+    pub async fn lpush(&self, list_key: Bytes, data: VecDeque<Data>) -> Result<i64, WalrusError> {
+        let frame = LPush::new(list_key, data).into_frame();
+        match self.request(frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `LPop` command to remove and return the first `count` elements of the list with key
+    /// `list_key`, see `Client::lpop`.
+    pub async fn lpop(
+        &self,
+        list_key: Bytes,
+        count: Option<i64>,
+    ) -> Result<Option<Vec<Data>>, WalrusError> {
+        let frame = LPop::new(list_key, count).into_frame();
+        match self.request(frame).await? {
+            Frame::Null => Ok(None),
+            value => Ok(Some(Data::frame_to_data_vec(value)?)),
+        }
+    }
+
+    /// `LLen` command to get the length of a list, see `Client::llen`.
+    pub async fn llen(&self, list_key: Bytes) -> Result<i64, WalrusError> {
+        let frame = LLen::new(list_key).into_frame();
+        match self.request(frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `PTtl` command to get the time left before `key` expires, see `Client::pttl`.
+    pub async fn pttl(&self, key: Bytes) -> Result<i64, WalrusError> {
+        let frame = PTtl::new(key).into_frame();
+        match self.request(frame).await? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// Fetch items of list with key `list_key` in the range \[`start_index`, `end_index`\], see
+    /// `Client::lrange`.
+    pub async fn lrange(
+        &self,
+        list_key: Bytes,
+        start_index: i64,
+        end_index: i64,
+    ) -> Result<Vec<Data>, WalrusError> {
+        let frame = LRange::new(list_key, start_index, end_index).into_frame();
+        Data::frame_to_data_vec(self.request(frame).await?)
+    }
+
+    /// Get every key matching the glob-style `pattern`.
+    pub async fn keys(&self, pattern: Bytes) -> Result<Vec<Bytes>, WalrusError> {
+        let frame = Keys::new(pattern).into_frame();
+        match self.request(frame).await? {
+            Frame::Array(keys) => bulks(keys),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Type` command to get the type of the value of `key`, see `Client::wtype`.
+    pub async fn wtype(&self, key: Bytes) -> Result<Bytes, WalrusError> {
+        let frame = Type::new(key).into_frame();
+        match self.request(frame).await? {
+            Frame::Simple(value) => Ok(value),
+            Frame::Bulk(value) => Ok(value),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+
+    /// `Info` command to describe the state of the server, see `Client::info`.
+    pub async fn info(&self, sections: Vec<Bytes>) -> Result<Bytes, WalrusError> {
+        let frame = Info::new(sections).into_frame();
+        match self.request(frame).await? {
+            Frame::Bulk(info) => Ok(info),
+            Frame::Error(err) => Err(err.into()),
+            _ => Err("Invalid response by server".into()),
+        }
+    }
+}
+
+impl Client {
+    /// Share the connection between tasks, see `SharedClient`. Push messages already received
+    /// are discarded.
+    ///
+    /// Must be called from a Tokio runtime, the connection is served by tasks of its own.
+    pub fn into_shared(self) -> Result<SharedClient, WalrusError> {
+        let (reader, writer) = self.connection.split()?;
+        let (requests, queue) = mpsc::channel(QUEUE_SIZE);
+        let (pending, replies) = mpsc::unbounded_channel();
+
+        task::spawn(
+            "shared-client-writer",
+            write_requests(writer, queue, pending),
+        );
+        task::spawn("shared-client-reader", read_replies(reader, replies));
+
+        Ok(SharedClient { requests })
+    }
+}
+
+/// Write the requests of `queue`, handing where to send their reply to the reader in the
+/// order they are written. Requests queued while flushing are written together.
+async fn write_requests(
+    mut writer: WriteHalf,
+    mut queue: mpsc::Receiver<Request>,
+    pending: mpsc::UnboundedSender<oneshot::Sender<Frame>>,
+) {
+    while let Some(request) = queue.recv().await {
+        let mut next = Some(request);
+        while let Some(request) = next {
+            writer.write_frame(&request.frame);
+            if pending.send(request.reply).is_err() {
+                // The reader is gone, so is the connection.
+                return;
+            }
+            next = queue.try_recv().ok();
+        }
+
+        // The write half is dropped on failure, closing the connection, so the reader drops
+        // the requests waiting for a reply.
+        if writer.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Read the replies of the server, handing each to the oldest request waiting for one.
+async fn read_replies(
+    mut reader: ReadHalf,
+    mut replies: mpsc::UnboundedReceiver<oneshot::Sender<Frame>>,
+) {
+    loop {
+        let frame = match reader.read_frame().await {
+            Ok(Some(Frame::Push(_))) => continue,
+            Ok(Some(Frame::Attribute { data, .. })) => *data,
+            Ok(Some(frame)) => frame,
+            // Requests still waiting are dropped with `replies`, failing them.
+            Ok(None) | Err(_) => return,
+        };
+
+        let Some(reply) = replies.recv().await else {
+            return;
+        };
+        // The task which sent the command may not wait for its reply anymore.
+        let _ = reply.send(frame);
+    }
+}
@@ -0,0 +1,644 @@
+mod bus;
+
+use bytes::Bytes;
+use rand::RngExt;
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::{
+    db::Db,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    persistence::unix_ms,
+    server::ServerState,
+    slot::{SLOTS, key_slot},
+};
+
+pub(crate) use bus::{cron, listen};
+
+/// Cluster state of a server started in cluster mode.
+///
+/// The keyspace is split into `SLOTS` hash slots, every slot is owned by at most one node and
+/// keys are only served by the node owning their slot. Nodes exchange the slots they own and
+/// the nodes they know over the cluster bus, a slot claimed by several nodes belongs to the one
+/// with the greatest config epoch.
+///
+/// While a slot moves between nodes, it's `migrating` on its owner and `importing` on the
+/// other node. The owner redirects clients with `-ASK` for keys it no longer has, the other
+/// node serves them to clients that sent `ASKING` first.
+pub(crate) struct Cluster {
+    /// Id of this node.
+    myself: String,
+    topology: RwLock<Topology>,
+    /// Bus addresses of the nodes a link is maintained with.
+    links: Mutex<HashSet<SocketAddr>>,
+}
+
+/// Nodes of the cluster and the slots they own, as known by this node.
+struct Topology {
+    /// Map of node id to node, including this node.
+    nodes: HashMap<String, Node>,
+    /// Id of the owner of every slot, `None` for slots not served by any node.
+    slots: Vec<Option<String>>,
+    /// Greatest config epoch seen in the cluster.
+    current_epoch: u64,
+    /// Map of slot owned by this node to the node it's moving to.
+    migrating: HashMap<u16, String>,
+    /// Map of slot moving to this node to its owner.
+    importing: HashMap<u16, String>,
+}
+
+/// A node of the cluster.
+struct Node {
+    ip: IpAddr,
+    /// Port clients connect to.
+    port: u16,
+    /// Port of the cluster bus.
+    bus_port: u16,
+    /// Version of the slots claimed by the node, claims with a greater epoch win.
+    config_epoch: u64,
+    /// `true` while a link with the node is established.
+    link_up: bool,
+    /// Unix time in milliseconds of the last message received from the node.
+    pong_received: u64,
+}
+
+impl Node {
+    fn bus_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.bus_port)
+    }
+}
+
+impl Cluster {
+    /// Create the state of a node owning no slots and knowing no other node, clients connect to
+    /// it on `ip:port` and nodes on `ip:bus_port`.
+    pub(crate) fn new(ip: IpAddr, port: u16, bus_port: u16) -> Cluster {
+        let myself = new_node_id();
+        let node = Node {
+            ip,
+            port,
+            bus_port,
+            config_epoch: 0,
+            link_up: true,
+            pong_received: 0,
+        };
+
+        Cluster {
+            myself: myself.clone(),
+            topology: RwLock::new(Topology {
+                nodes: HashMap::from([(myself, node)]),
+                slots: vec![None; SLOTS],
+                current_epoch: 0,
+                migrating: HashMap::new(),
+                importing: HashMap::new(),
+            }),
+            links: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Check that `keys`, the keys of a command, are served by this node. Returns the error to
+    /// reply with otherwise, redirecting the client to the owner of their slot.
+    ///
+    /// `asking` tells if the client sent `ASKING` right before the command.
+    pub(crate) fn check(&self, keys: &[Bytes], db: &Db, asking: bool) -> Result<(), String> {
+        let Some((first, rest)) = keys.split_first() else {
+            return Ok(());
+        };
+
+        let slot = key_slot(first);
+        if rest.iter().any(|key| key_slot(key) != slot) {
+            return Err("CROSSSLOT Keys in request don't hash to the same slot".to_string());
+        }
+
+        let topology = self.topology.read().unwrap();
+        match &topology.slots[slot as usize] {
+            Some(owner) if *owner == self.myself => {
+                let Some(target) = topology.migrating.get(&slot) else {
+                    return Ok(());
+                };
+
+                // Keys missing here may already be on the node the slot moves to.
+                let missing = keys.iter().filter(|key| db.inspect(key).is_none()).count();
+                if missing == 0 {
+                    Ok(())
+                } else if missing == keys.len() {
+                    let node = &topology.nodes[target];
+                    Err(format!("ASK {slot} {}:{}", node.ip, node.port))
+                } else {
+                    Err("TRYAGAIN Multiple keys request during rehashing of slot".to_string())
+                }
+            }
+            _ if asking && topology.importing.contains_key(&slot) => Ok(()),
+            Some(owner) => {
+                let node = &topology.nodes[owner];
+                Err(format!("MOVED {slot} {}:{}", node.ip, node.port))
+            }
+            None => Err("CLUSTERDOWN Hash slot not served".to_string()),
+        }
+    }
+
+    /// Claim `slots` for this node, none are claimed if one is already owned. Returns the
+    /// error message on failure.
+    pub(crate) fn add_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut topology = self.topology.write().unwrap();
+
+        for (i, &slot) in slots.iter().enumerate() {
+            if slots[..i].contains(&slot) {
+                return Err(format!("Slot {slot} specified multiple times"));
+            }
+            if topology.slots[slot as usize].is_some() {
+                return Err(format!("Slot {slot} is already busy"));
+            }
+        }
+
+        // A new epoch, so the claim wins over stale claims of other nodes.
+        topology.current_epoch += 1;
+        let epoch = topology.current_epoch;
+        topology.nodes.get_mut(&self.myself).unwrap().config_epoch = epoch;
+
+        for &slot in slots {
+            topology.slots[slot as usize] = Some(self.myself.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Stop serving `slots`, none are released if one isn't owned by any node. Returns the
+    /// error message on failure.
+    pub(crate) fn del_slots(&self, slots: &[u16]) -> Result<(), String> {
+        let mut topology = self.topology.write().unwrap();
+
+        for (i, &slot) in slots.iter().enumerate() {
+            if slots[..i].contains(&slot) {
+                return Err(format!("Slot {slot} specified multiple times"));
+            }
+            if topology.slots[slot as usize].is_none() {
+                return Err(format!("Slot {slot} is already unassigned"));
+            }
+        }
+
+        for &slot in slots {
+            topology.slots[slot as usize] = None;
+        }
+
+        Ok(())
+    }
+
+    /// Start moving `slot`, owned by this node, to node `id`. Returns the error message on
+    /// failure.
+    pub(crate) fn set_migrating(&self, slot: u16, id: &str) -> Result<(), String> {
+        let mut topology = self.topology.write().unwrap();
+
+        if topology.slots[slot as usize].as_ref() != Some(&self.myself) {
+            return Err(format!("I'm not the owner of hash slot {slot}"));
+        }
+        if !topology.nodes.contains_key(id) || id == self.myself {
+            return Err(format!("I don't know about node {id}"));
+        }
+
+        topology.migrating.insert(slot, id.to_string());
+        Ok(())
+    }
+
+    /// Start moving `slot`, owned by node `id`, to this node. Returns the error message on
+    /// failure.
+    pub(crate) fn set_importing(&self, slot: u16, id: &str) -> Result<(), String> {
+        let mut topology = self.topology.write().unwrap();
+
+        if topology.slots[slot as usize].as_ref() == Some(&self.myself) {
+            return Err(format!("I'm already the owner of hash slot {slot}"));
+        }
+        if !topology.nodes.contains_key(id) || id == self.myself {
+            return Err(format!("I don't know about node {id}"));
+        }
+
+        topology.importing.insert(slot, id.to_string());
+        Ok(())
+    }
+
+    /// Stop moving `slot`.
+    pub(crate) fn set_stable(&self, slot: u16) {
+        let mut topology = self.topology.write().unwrap();
+        topology.migrating.remove(&slot);
+        topology.importing.remove(&slot);
+    }
+
+    /// Assign `slot` to node `id`, ending its move. Returns the error message on failure.
+    pub(crate) fn set_node(&self, slot: u16, id: &str, db: &Db) -> Result<(), String> {
+        let mut topology = self.topology.write().unwrap();
+
+        if !topology.nodes.contains_key(id) {
+            return Err(format!("Unknown node {id}"));
+        }
+
+        let owned = topology.slots[slot as usize].as_ref() == Some(&self.myself);
+        if owned && id != self.myself && count_keys_in_slot(db, slot) > 0 {
+            return Err(format!(
+                "Can't assign hashslot {slot} to a different node while I still hold keys for this hash slot."
+            ));
+        }
+
+        topology.migrating.remove(&slot);
+        topology.importing.remove(&slot);
+        topology.slots[slot as usize] = Some(id.to_string());
+
+        // A new epoch, so the claim wins over the one of the previous owner.
+        if id == self.myself && !owned {
+            topology.current_epoch += 1;
+            let epoch = topology.current_epoch;
+            topology.nodes.get_mut(&self.myself).unwrap().config_epoch = epoch;
+        }
+
+        Ok(())
+    }
+
+    /// Id of this node.
+    pub(crate) fn myid(&self) -> &str {
+        &self.myself
+    }
+
+    /// Description of the cluster as `field:value` lines, the reply of `CLUSTER INFO`.
+    pub(crate) fn info(&self) -> String {
+        let topology = self.topology.read().unwrap();
+
+        let assigned = topology
+            .slots
+            .iter()
+            .filter(|owner| owner.is_some())
+            .count();
+        let size = topology
+            .nodes
+            .keys()
+            .filter(|id| {
+                topology
+                    .slots
+                    .iter()
+                    .any(|owner| owner.as_ref() == Some(id))
+            })
+            .count();
+        let state = if assigned == SLOTS { "ok" } else { "fail" };
+
+        [
+            ("cluster_state", state.to_string()),
+            ("cluster_slots_assigned", assigned.to_string()),
+            ("cluster_slots_ok", assigned.to_string()),
+            ("cluster_slots_pfail", "0".to_string()),
+            ("cluster_slots_fail", "0".to_string()),
+            ("cluster_known_nodes", topology.nodes.len().to_string()),
+            ("cluster_size", size.to_string()),
+            ("cluster_current_epoch", topology.current_epoch.to_string()),
+            (
+                "cluster_my_epoch",
+                topology.nodes[&self.myself].config_epoch.to_string(),
+            ),
+        ]
+        .iter()
+        .map(|(field, value)| format!("{field}:{value}\r\n"))
+        .collect()
+    }
+
+    /// Description of every node, one per line, the reply of `CLUSTER NODES`.
+    ///
+    /// Each line holds the id, `ip:port@bus-port`, flags, master, ping sent time, pong
+    /// received time, config epoch, link state and slot ranges of a node. Slots moving from or
+    /// to this node follow its ranges as `[slot->-id]` and `[slot-<-id]`.
+    pub(crate) fn nodes(&self) -> String {
+        let topology = self.topology.read().unwrap();
+        let mut nodes = String::new();
+
+        for (id, node) in &topology.nodes {
+            let myself = *id == self.myself;
+            let flags = if myself { "myself,master" } else { "master" };
+            let link = if node.link_up {
+                "connected"
+            } else {
+                "disconnected"
+            };
+
+            nodes.push_str(&format!(
+                "{id} {}:{}@{} {flags} - 0 {} {} {link}",
+                node.ip, node.port, node.bus_port, node.pong_received, node.config_epoch
+            ));
+
+            for (start, end) in slot_ranges(&topology.slots, id) {
+                if start == end {
+                    nodes.push_str(&format!(" {start}"));
+                } else {
+                    nodes.push_str(&format!(" {start}-{end}"));
+                }
+            }
+
+            if myself {
+                for (slot, target) in &topology.migrating {
+                    nodes.push_str(&format!(" [{slot}->-{target}]"));
+                }
+                for (slot, source) in &topology.importing {
+                    nodes.push_str(&format!(" [{slot}-<-{source}]"));
+                }
+            }
+
+            nodes.push('\n');
+        }
+
+        nodes
+    }
+
+    /// Ranges of slots and the node serving them, the reply of `CLUSTER SLOTS`.
+    ///
+    /// Each range is an array of its first slot, last slot and the ip, port and id of the node.
+    pub(crate) fn slots(&self) -> Frame {
+        let topology = self.topology.read().unwrap();
+        let mut ranges = Vec::new();
+
+        for (id, node) in &topology.nodes {
+            for (start, end) in slot_ranges(&topology.slots, id) {
+                ranges.push((start, end, id, node));
+            }
+        }
+        ranges.sort_by_key(|(start, ..)| *start);
+
+        Frame::Array(
+            ranges
+                .into_iter()
+                .map(|(start, end, id, node)| {
+                    Frame::Array(vec![
+                        Frame::Integer(start as i64),
+                        Frame::Integer(end as i64),
+                        Frame::Array(vec![
+                            Frame::Bulk(Bytes::from(node.ip.to_string())),
+                            Frame::Integer(node.port as i64),
+                            Frame::Bulk(Bytes::from(id.clone())),
+                        ]),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    /// Shards of the cluster, a node and its slots each, the reply of `CLUSTER SHARDS`.
+    pub(crate) fn shards(&self) -> Frame {
+        let topology = self.topology.read().unwrap();
+        let bulk = |value: String| Frame::Bulk(Bytes::from(value));
+
+        Frame::Array(
+            topology
+                .nodes
+                .iter()
+                .map(|(id, node)| {
+                    let slots = slot_ranges(&topology.slots, id)
+                        .into_iter()
+                        .flat_map(|(start, end)| {
+                            [Frame::Integer(start as i64), Frame::Integer(end as i64)]
+                        })
+                        .collect();
+                    let health = if node.link_up { "online" } else { "fail" };
+
+                    let node = Frame::Map(vec![
+                        (bulk("id".into()), bulk(id.clone())),
+                        (bulk("port".into()), Frame::Integer(node.port as i64)),
+                        (bulk("ip".into()), bulk(node.ip.to_string())),
+                        (bulk("endpoint".into()), bulk(node.ip.to_string())),
+                        (bulk("role".into()), bulk("master".into())),
+                        (bulk("replication-offset".into()), Frame::Integer(0)),
+                        (bulk("health".into()), bulk(health.into())),
+                    ]);
+
+                    Frame::Map(vec![
+                        (bulk("slots".into()), Frame::Array(slots)),
+                        (bulk("nodes".into()), Frame::Array(vec![node])),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    /// Join the cluster of the node with bus address `addr`, the nodes learn about each other
+    /// over the link.
+    pub(crate) fn meet(&self, addr: SocketAddr, server: &Arc<ServerState>) {
+        bus::connect(self, addr, Arc::downgrade(server));
+    }
+
+    /// Message describing this node and the nodes it knows, sent over the bus.
+    ///
+    /// The message is an array of the kind of message, the id, port, bus port, config epoch
+    /// and current epoch of this node, the bitmap of the slots it owns, then the id, ip, port
+    /// and bus port of every other node it knows.
+    fn message(&self, kind: &'static str) -> Frame {
+        let topology = self.topology.read().unwrap();
+        let myself = &topology.nodes[&self.myself];
+
+        let mut owned = vec![0u8; SLOTS / 8];
+        for (slot, owner) in topology.slots.iter().enumerate() {
+            if owner.as_ref() == Some(&self.myself) {
+                owned[slot / 8] |= 1 << (slot % 8);
+            }
+        }
+
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from(kind));
+        frame.push_bulk(Bytes::from(self.myself.clone()));
+        frame.push_bulk(Bytes::from(myself.port.to_string()));
+        frame.push_bulk(Bytes::from(myself.bus_port.to_string()));
+        frame.push_bulk(Bytes::from(myself.config_epoch.to_string()));
+        frame.push_bulk(Bytes::from(topology.current_epoch.to_string()));
+        frame.push_bulk(Bytes::from(owned));
+
+        for (id, node) in &topology.nodes {
+            if *id == self.myself {
+                continue;
+            }
+
+            frame.push_bulk(Bytes::from(id.clone()));
+            frame.push_bulk(Bytes::from(node.ip.to_string()));
+            frame.push_bulk(Bytes::from(node.port.to_string()));
+            frame.push_bulk(Bytes::from(node.bus_port.to_string()));
+        }
+
+        frame
+    }
+
+    /// Apply the `message` of the node at `ip`, returns the id of the node.
+    ///
+    /// The sender's slots replace the ones it owned, and it takes the slots it claims from
+    /// nodes with a lower config epoch. Nodes it knows are added to the topology.
+    fn receive(&self, message: Frame, ip: IpAddr) -> Result<String, WalrusError> {
+        let mut parse = Parse::new(message)?;
+        parse.next_bytes()?;
+
+        let id = String::from_utf8_lossy(&parse.next_bytes()?).into_owned();
+        let port = next_port(&mut parse)?;
+        let bus_port = next_port(&mut parse)?;
+        let config_epoch = next_epoch(&mut parse)?;
+        let current_epoch = next_epoch(&mut parse)?;
+        let owned = parse.next_bytes()?;
+        if owned.len() != SLOTS / 8 {
+            return Err("invalid slots in cluster message".into());
+        }
+
+        let mut known = Vec::new();
+        loop {
+            let known_id = match parse.next_bytes() {
+                Ok(known_id) => String::from_utf8_lossy(&known_id).into_owned(),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            };
+            let known_ip = String::from_utf8_lossy(&parse.next_bytes()?)
+                .parse::<IpAddr>()
+                .map_err(|_| "invalid ip in cluster message")?;
+
+            known.push((
+                known_id,
+                known_ip,
+                next_port(&mut parse)?,
+                next_port(&mut parse)?,
+            ));
+        }
+
+        // Linked to itself.
+        if id == self.myself {
+            return Ok(id);
+        }
+
+        let mut topology = self.topology.write().unwrap();
+        let topology = &mut *topology;
+        topology.current_epoch = topology.current_epoch.max(current_epoch).max(config_epoch);
+
+        let node = topology.nodes.entry(id.clone()).or_insert(Node {
+            ip,
+            port,
+            bus_port,
+            config_epoch,
+            link_up: false,
+            pong_received: 0,
+        });
+        node.pong_received = unix_ms();
+        node.ip = ip;
+        node.port = port;
+        node.bus_port = bus_port;
+        node.config_epoch = config_epoch;
+
+        for slot in 0..SLOTS {
+            let claimed = owned[slot / 8] & (1 << (slot % 8)) != 0;
+
+            let owner = match &topology.slots[slot] {
+                Some(owner) if *owner == id => {
+                    if !claimed {
+                        topology.slots[slot] = None;
+                    }
+                    continue;
+                }
+                owner => owner,
+            };
+
+            // Equal epochs are settled by the node ids, so every node picks the same owner.
+            let wins = claimed
+                && owner.as_ref().is_none_or(|owner| {
+                    let epoch = topology.nodes[owner].config_epoch;
+                    config_epoch > epoch || (config_epoch == epoch && id < *owner)
+                });
+            if wins {
+                topology.slots[slot] = Some(id.clone());
+            }
+        }
+
+        for (known_id, ip, port, bus_port) in known {
+            if known_id != self.myself {
+                topology.nodes.entry(known_id).or_insert(Node {
+                    ip,
+                    port,
+                    bus_port,
+                    config_epoch: 0,
+                    link_up: false,
+                    pong_received: 0,
+                });
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Bus addresses of the other nodes.
+    fn peers(&self) -> Vec<SocketAddr> {
+        let topology = self.topology.read().unwrap();
+        topology
+            .nodes
+            .iter()
+            .filter(|(id, _)| **id != self.myself)
+            .map(|(_, node)| node.bus_addr())
+            .collect()
+    }
+
+    /// Record that the link with node `id` is established.
+    fn link_up(&self, id: &str) {
+        if let Some(node) = self.topology.write().unwrap().nodes.get_mut(id) {
+            node.link_up = true;
+        }
+    }
+
+    /// Record that the link with the node at bus address `addr` is lost.
+    fn link_down(&self, addr: SocketAddr) {
+        self.links.lock().unwrap().remove(&addr);
+
+        let mut topology = self.topology.write().unwrap();
+        for (id, node) in topology.nodes.iter_mut() {
+            if node.bus_addr() == addr && *id != self.myself {
+                node.link_up = false;
+            }
+        }
+    }
+}
+
+/// Number of keys of `db` in `slot`.
+pub(crate) fn count_keys_in_slot(db: &Db, slot: u16) -> usize {
+    db.entries()
+        .filter(|entry| key_slot(entry.key()) == slot)
+        .count()
+}
+
+/// Up to `count` keys of `db` in `slot`.
+pub(crate) fn keys_in_slot(db: &Db, slot: u16, count: usize) -> Vec<Bytes> {
+    db.entries()
+        .filter(|entry| key_slot(entry.key()) == slot)
+        .take(count)
+        .map(|entry| entry.key().clone())
+        .collect()
+}
+
+/// Ranges of consecutive slots owned by node `id`, as first and last slot.
+fn slot_ranges(slots: &[Option<String>], id: &str) -> Vec<(u16, u16)> {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+
+    for (slot, owner) in slots.iter().enumerate() {
+        if owner.as_deref() != Some(id) {
+            continue;
+        }
+
+        let slot = slot as u16;
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == slot => *end = slot,
+            _ => ranges.push((slot, slot)),
+        }
+    }
+
+    ranges
+}
+
+fn next_port(parse: &mut Parse) -> Result<u16, WalrusError> {
+    u16::try_from(parse.next_int()?).map_err(|_| "invalid port in cluster message".into())
+}
+
+fn next_epoch(parse: &mut Parse) -> Result<u64, WalrusError> {
+    u64::try_from(parse.next_int()?).map_err(|_| "invalid epoch in cluster message".into())
+}
+
+/// Generate a random node id of 40 hex characters.
+fn new_node_id() -> String {
+    let mut rng = rand::rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.random_range(0..16), 16).unwrap())
+        .collect()
+}
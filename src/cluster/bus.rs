@@ -0,0 +1,144 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Weak,
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tracing::{info, warn};
+
+use super::Cluster;
+use crate::{Connection, errors::WalrusError, server::ServerState, task};
+
+/// Interval between messages on a link, and between checks for nodes without a link.
+const PING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time a node has to accept a link or reply to a message before the link is dropped.
+const NODE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Accept links from other nodes until the server shuts down, every message is answered with
+/// the state of this node.
+pub(crate) async fn listen(listener: TcpListener, server: Weak<ServerState>) {
+    loop {
+        let (socket, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!(%err, "Failed to accept a cluster bus link");
+                continue;
+            }
+        };
+
+        if server.strong_count() == 0 {
+            return;
+        }
+
+        task::spawn(
+            "cluster-link",
+            serve_link(socket, addr.ip(), server.clone()),
+        );
+    }
+}
+
+/// Answer the messages of the node at `ip` until it drops the link.
+async fn serve_link(socket: TcpStream, ip: IpAddr, server: Weak<ServerState>) {
+    let mut conn = Connection::new(socket, None, None);
+
+    while let Ok(Some(message)) = conn.read_frame().await {
+        let Some(state) = server.upgrade() else {
+            return;
+        };
+        let Some(cluster) = &state.cluster else {
+            return;
+        };
+
+        if let Err(err) = cluster.receive(message, ip) {
+            warn!(%ip, %err, "Invalid message on the cluster bus");
+            return;
+        }
+        conn.write_frame(&cluster.message("pong"));
+    }
+}
+
+/// Keep a link with every known node until the server shuts down.
+pub(crate) async fn cron(server: Weak<ServerState>) {
+    loop {
+        {
+            let Some(state) = server.upgrade() else {
+                return;
+            };
+            let Some(cluster) = &state.cluster else {
+                return;
+            };
+
+            for addr in cluster.peers() {
+                connect(cluster, addr, server.clone());
+            }
+        }
+
+        time::sleep(PING_INTERVAL).await;
+    }
+}
+
+/// Start a link with the node at bus address `addr`, unless one is maintained already.
+pub(super) fn connect(cluster: &Cluster, addr: SocketAddr, server: Weak<ServerState>) {
+    if cluster.links.lock().unwrap().insert(addr) {
+        task::spawn("cluster-link", link(addr, server));
+    }
+}
+
+/// Exchange messages with the node at bus address `addr` until the link is lost.
+async fn link(addr: SocketAddr, server: Weak<ServerState>) {
+    let result = exchange(addr, &server).await;
+
+    if let Some(state) = server.upgrade()
+        && let Some(cluster) = &state.cluster
+    {
+        cluster.link_down(addr);
+        if let Err(err) = result {
+            info!(node = %addr, %err, "Lost the cluster bus link");
+        }
+    }
+}
+
+async fn exchange(addr: SocketAddr, server: &Weak<ServerState>) -> Result<(), WalrusError> {
+    let socket = time::timeout(NODE_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| WalrusError::from("timed out connecting"))??;
+    let mut conn = Connection::new(socket, None, None);
+
+    loop {
+        {
+            let Some(state) = server.upgrade() else {
+                return Ok(());
+            };
+            let Some(cluster) = &state.cluster else {
+                return Ok(());
+            };
+            conn.write_frame(&cluster.message("ping"));
+        }
+
+        let reply = time::timeout(NODE_TIMEOUT, conn.read_frame())
+            .await
+            .map_err(|_| WalrusError::from("timed out waiting for a reply"))??
+            .ok_or("link closed by the node")?;
+
+        {
+            let Some(state) = server.upgrade() else {
+                return Ok(());
+            };
+            let Some(cluster) = &state.cluster else {
+                return Ok(());
+            };
+
+            let id = cluster.receive(reply, addr.ip())?;
+            if id == cluster.myself {
+                return Ok(());
+            }
+            cluster.link_up(&id);
+        }
+
+        time::sleep(PING_INTERVAL).await;
+    }
+}
@@ -0,0 +1,39 @@
+use bytes::Bytes;
+
+use crate::{Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse};
+
+/// ASKING command, sent by cluster clients following an `-ASK` redirection.
+///
+/// ASKING
+///
+/// The next command of the connection is served even if its keys belong to a slot this node
+/// is importing and doesn't own yet.
+#[derive(Debug, Default)]
+pub struct Asking;
+
+impl Asking {
+    /// Creates a new `ASKING` command.
+    pub fn new() -> Asking {
+        Asking
+    }
+
+    /// Parse an `Asking` instance from an array frame.
+    /// The 'ASKING' string is already consumed.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Asking, WalrusError> {
+        Ok(Asking)
+    }
+
+    /// Execute the `Asking` command.
+    pub(crate) async fn execute(self, conn: &mut Connection) -> Result<(), WalrusError> {
+        conn.set_asking();
+        conn.write_data(&Data::String(Bytes::from("OK")));
+        Ok(())
+    }
+
+    /// Convert `Asking` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("asking"));
+        frame
+    }
+}
@@ -0,0 +1,54 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse, server::ServerState,
+};
+
+/// BGSAVE command, writes a snapshot of the dataset to disk in the background.
+///
+/// BGSAVE
+///
+/// Replies as soon as the save started, the dataset keeps being served meanwhile. The progress
+/// and outcome of the save are reported by `INFO persistence`.
+#[derive(Debug, Default)]
+pub struct Bgsave;
+
+impl Bgsave {
+    /// Creates a new `BGSAVE` command.
+    pub fn new() -> Bgsave {
+        Bgsave
+    }
+
+    /// Parse a `Bgsave` instance from an array frame.
+    /// The 'BGSAVE' string is already consumed.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Bgsave, WalrusError> {
+        Ok(Bgsave)
+    }
+
+    /// Execute the `Bgsave` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        let config = &server.config;
+
+        match server.persistence.bgsave(
+            &server.databases,
+            config.snapshot_path(),
+            config.snapshot_compression(),
+        ) {
+            Ok(()) => conn.write_data(&Data::String(Bytes::from("Background saving started"))),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Bgsave` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("bgsave"));
+        frame
+    }
+}
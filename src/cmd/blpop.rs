@@ -67,19 +67,12 @@ impl BLPop {
         loop {
             // Try LPOP for each key.
             for key in &self.keys {
-                match db.pop_front(key) {
-                    Ok(Some(data)) => {
-                        conn.write_data_array(
-                            vec![&Data::Bytes(key.clone()), &data].into_iter(),
-                            2 as usize,
-                        );
-                        return Ok(());
-                    }
-                    Err(err) => {
-                        conn.write_error_frame(err.get_msg());
-                        return Err(err);
-                    }
-                    _ => {}
+                if let Some(data) = db.pop_front(key)? {
+                    conn.write_data_array(
+                        vec![&Data::Bytes(key.clone()), &data].into_iter(),
+                        2_usize,
+                    );
+                    return Ok(());
                 }
             }
 
@@ -107,12 +100,17 @@ impl BLPop {
         }
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+
     /// Convert the BLPop command into a frame.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
         frame.push(Frame::Bulk(Bytes::from("BLPOP")));
         for key in self.keys {
-            frame.push(Frame::Bulk(Bytes::from(key)));
+            frame.push(Frame::Bulk(key));
         }
         frame.push(Frame::Double(self.timeout));
 
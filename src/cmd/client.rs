@@ -0,0 +1,447 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    connection::{Protocol, TraceContext},
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    pause::PauseMode,
+    registry::KillFilter,
+    server::ServerState,
+    tracking::TrackingOptions,
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// CLIENT command, inspects and manages the connections of the server.
+///
+/// CLIENT ID
+/// CLIENT GETNAME
+/// CLIENT SETNAME name
+/// CLIENT LIST
+/// CLIENT KILL addr:port
+/// CLIENT KILL [ID id] [ADDR addr:port] [SKIPME yes|no]
+/// CLIENT PAUSE timeout [WRITE|ALL]
+/// CLIENT UNPAUSE
+/// CLIENT TRACKING ON|OFF [BCAST] [PREFIX prefix [PREFIX prefix ...]] [NOLOOP]
+/// CLIENT TRACEPARENT traceparent [tracestate]
+#[derive(Debug)]
+pub enum ClientCmd {
+    /// Id of the current connection.
+    Id,
+    /// Name of the current connection.
+    GetName,
+    /// Set the name of the current connection.
+    SetName(Bytes),
+    /// Describe all connections.
+    List,
+    /// Kill the connection with the given address, replies with `OK`.
+    KillAddr(Bytes),
+    /// Kill all connections matching the filter, replies with the number of connections killed.
+    Kill {
+        id: Option<u64>,
+        addr: Option<Bytes>,
+        skipme: bool,
+    },
+    /// Suspend commands of all connections for `timeout`. Only write commands are suspended
+    /// if `write_only` is set.
+    Pause { timeout: Duration, write_only: bool },
+    /// Lift the current pause.
+    Unpause,
+    /// Enable or disable invalidation messages for keys read by the current connection, or
+    /// for every key matching `prefixes` in broadcast mode.
+    Tracking {
+        on: bool,
+        bcast: bool,
+        prefixes: Vec<Bytes>,
+        noloop: bool,
+    },
+    /// Set the W3C trace context of the next command of the current connection, its span
+    /// continues the trace of the client with the `otel` feature.
+    TraceParent {
+        traceparent: Bytes,
+        tracestate: Option<Bytes>,
+    },
+}
+
+impl ClientCmd {
+    /// Parse a `ClientCmd` instance from an array frame.
+    /// The 'CLIENT' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ClientCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"id") {
+            Ok(ClientCmd::Id)
+        } else if subcommand.eq_ignore_ascii_case(b"getname") {
+            Ok(ClientCmd::GetName)
+        } else if subcommand.eq_ignore_ascii_case(b"setname") {
+            Ok(ClientCmd::SetName(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"list") {
+            Ok(ClientCmd::List)
+        } else if subcommand.eq_ignore_ascii_case(b"kill") {
+            ClientCmd::parse_kill(parse)
+        } else if subcommand.eq_ignore_ascii_case(b"pause") {
+            let timeout = u64::try_from(parse.next_int()?)
+                .map_err(|_| WalrusError::SyntaxError("ERR timeout is negative".into()))?;
+
+            // All commands are paused by default.
+            let write_only = match parse.next_bytes() {
+                Ok(mode) if mode.eq_ignore_ascii_case(b"write") => true,
+                Ok(mode) if mode.eq_ignore_ascii_case(b"all") => false,
+                Ok(_) => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+                Err(ParseError::EndOfStream) => false,
+                Err(err) => return Err(err.into()),
+            };
+
+            Ok(ClientCmd::Pause {
+                timeout: Duration::from_millis(timeout),
+                write_only,
+            })
+        } else if subcommand.eq_ignore_ascii_case(b"unpause") {
+            Ok(ClientCmd::Unpause)
+        } else if subcommand.eq_ignore_ascii_case(b"tracking") {
+            ClientCmd::parse_tracking(parse)
+        } else if subcommand.eq_ignore_ascii_case(b"traceparent") {
+            let traceparent = parse.next_bytes()?;
+            let tracestate = match parse.next_bytes() {
+                Ok(tracestate) => Some(tracestate),
+                Err(ParseError::EndOfStream) => None,
+                Err(err) => return Err(err.into()),
+            };
+            Ok(ClientCmd::TraceParent {
+                traceparent,
+                tracestate,
+            })
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Parse the arguments of `CLIENT KILL`.
+    fn parse_kill(parse: &mut Parse) -> Result<ClientCmd, WalrusError> {
+        let first = parse.next_bytes()?;
+
+        // Old form, a single address.
+        if !first.eq_ignore_ascii_case(b"id")
+            && !first.eq_ignore_ascii_case(b"addr")
+            && !first.eq_ignore_ascii_case(b"skipme")
+        {
+            return Ok(ClientCmd::KillAddr(first));
+        }
+
+        let mut id = None;
+        let mut addr = None;
+        let mut skipme = true;
+        let mut filter = Some(first);
+
+        while let Some(name) = filter {
+            if name.eq_ignore_ascii_case(b"id") {
+                let client_id = parse.next_int()?;
+                id = Some(u64::try_from(client_id).map_err(|_| {
+                    WalrusError::SyntaxError("ERR client-id should be greater than 0".into())
+                })?);
+            } else if name.eq_ignore_ascii_case(b"addr") {
+                addr = Some(parse.next_bytes()?);
+            } else if name.eq_ignore_ascii_case(b"skipme") {
+                let value = parse.next_bytes()?;
+                skipme = if value.eq_ignore_ascii_case(b"yes") {
+                    true
+                } else if value.eq_ignore_ascii_case(b"no") {
+                    false
+                } else {
+                    return Err(WalrusError::SyntaxError("ERR syntax error".into()));
+                };
+            } else {
+                return Err(WalrusError::SyntaxError("ERR syntax error".into()));
+            }
+
+            filter = match parse.next_bytes() {
+                Ok(name) => Some(name),
+                Err(ParseError::EndOfStream) => None,
+                Err(err) => return Err(err.into()),
+            };
+        }
+
+        Ok(ClientCmd::Kill { id, addr, skipme })
+    }
+
+    /// Parse the arguments of `CLIENT TRACKING`.
+    fn parse_tracking(parse: &mut Parse) -> Result<ClientCmd, WalrusError> {
+        let status = parse.next_bytes()?;
+
+        let on = if status.eq_ignore_ascii_case(b"on") {
+            true
+        } else if status.eq_ignore_ascii_case(b"off") {
+            false
+        } else {
+            return Err(WalrusError::SyntaxError("ERR syntax error".into()));
+        };
+
+        let mut bcast = false;
+        let mut prefixes = Vec::new();
+        let mut noloop = false;
+
+        loop {
+            match parse.next_bytes() {
+                Ok(option) if option.eq_ignore_ascii_case(b"bcast") => bcast = true,
+                Ok(option) if option.eq_ignore_ascii_case(b"prefix") => {
+                    prefixes.push(parse.next_bytes()?);
+                }
+                Ok(option) if option.eq_ignore_ascii_case(b"noloop") => noloop = true,
+                Ok(_) => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(ClientCmd::Tracking {
+            on,
+            bcast,
+            prefixes,
+            noloop,
+        })
+    }
+
+    /// Execute the `Client` subcommand against the current connection and the registry of
+    /// connections.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            ClientCmd::Id => conn.write_data(&Data::Integer(conn.id() as i64)),
+            ClientCmd::GetName => match conn.name() {
+                Some(name) => {
+                    let name = Data::Bytes(name.clone());
+                    conn.write_data(&name);
+                }
+                None => conn.write_null_frame(),
+            },
+            ClientCmd::SetName(name) => {
+                // Names are listed in a space separated format, so they may not contain spaces
+                // or other special characters.
+                if name.iter().any(|byte| !(b'!'..=b'~').contains(byte)) {
+                    conn.write_error_frame(
+                        "ERR Client names cannot contain spaces, newlines or special characters.",
+                    );
+                    return Ok(());
+                }
+
+                // Empty name removes the name of the connection.
+                let name = if name.is_empty() { None } else { Some(name) };
+                server.clients.set_name(conn.id(), name.clone());
+                conn.set_name(name);
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            ClientCmd::List => {
+                let list = server.clients.list();
+                conn.write_data(&Data::Bytes(Bytes::from(list)));
+            }
+            ClientCmd::KillAddr(addr) => {
+                let filter = KillFilter {
+                    addr: Some(addr),
+                    ..Default::default()
+                };
+
+                if server.clients.kill(&filter) == 0 {
+                    conn.write_error_frame("ERR No such client");
+                } else {
+                    conn.write_data(&Data::String(Bytes::from("OK")));
+                }
+            }
+            ClientCmd::Kill { id, addr, skipme } => {
+                let filter = KillFilter {
+                    id,
+                    addr,
+                    skip: skipme.then(|| conn.id()),
+                };
+
+                let killed = server.clients.kill(&filter);
+                conn.write_data(&Data::Integer(killed as i64));
+            }
+            ClientCmd::Pause {
+                timeout,
+                write_only,
+            } => {
+                let mode = if write_only {
+                    PauseMode::Write
+                } else {
+                    PauseMode::All
+                };
+
+                server.pause.pause(Instant::now() + timeout, mode);
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            ClientCmd::Unpause => {
+                server.pause.unpause();
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            ClientCmd::Tracking {
+                on,
+                bcast,
+                prefixes,
+                noloop,
+            } => {
+                if !on {
+                    db.tracking().disable(conn.id());
+                    conn.write_data(&Data::String(Bytes::from("OK")));
+                    return Ok(());
+                }
+
+                // Invalidation messages are push frames, which RESP2 doesn't have.
+                if conn.protocol() != Protocol::Resp3 {
+                    conn.write_error_frame(
+                        "ERR Client tracking requires the RESP3 protocol, switch with HELLO 3",
+                    );
+                    return Ok(());
+                }
+
+                if !prefixes.is_empty() && !bcast {
+                    conn.write_error_frame("ERR PREFIX option requires BCAST mode to be enabled");
+                    return Ok(());
+                }
+
+                // The connection is registered for as long as its handler runs.
+                if let Some(sender) = server.clients.push_sender(conn.id()) {
+                    let options = TrackingOptions {
+                        bcast,
+                        prefixes,
+                        noloop,
+                    };
+                    db.tracking().enable(conn.id(), options, sender);
+                }
+
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            ClientCmd::TraceParent {
+                traceparent,
+                tracestate,
+            } => {
+                if !is_traceparent(&traceparent) {
+                    conn.write_error_frame(
+                        "ERR Invalid traceparent, expected version-traceid-parentid-flags",
+                    );
+                    return Ok(());
+                }
+
+                conn.set_trace_context(TraceContext {
+                    traceparent,
+                    tracestate,
+                });
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert `ClientCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("client"));
+
+        match self {
+            ClientCmd::Id => frame.push_bulk(Bytes::from("id")),
+            ClientCmd::GetName => frame.push_bulk(Bytes::from("getname")),
+            ClientCmd::SetName(name) => {
+                frame.push_bulk(Bytes::from("setname"));
+                frame.push_bulk(name);
+            }
+            ClientCmd::List => frame.push_bulk(Bytes::from("list")),
+            ClientCmd::KillAddr(addr) => {
+                frame.push_bulk(Bytes::from("kill"));
+                frame.push_bulk(addr);
+            }
+            ClientCmd::Kill { id, addr, skipme } => {
+                frame.push_bulk(Bytes::from("kill"));
+
+                if let Some(id) = id {
+                    frame.push_bulk(Bytes::from("id"));
+                    frame.push_int(id as i64);
+                }
+
+                if let Some(addr) = addr {
+                    frame.push_bulk(Bytes::from("addr"));
+                    frame.push_bulk(addr);
+                }
+
+                frame.push_bulk(Bytes::from("skipme"));
+                frame.push_bulk(Bytes::from(if skipme { "yes" } else { "no" }));
+            }
+            ClientCmd::Pause {
+                timeout,
+                write_only,
+            } => {
+                frame.push_bulk(Bytes::from("pause"));
+                frame.push_int(timeout.as_millis() as i64);
+                frame.push_bulk(Bytes::from(if write_only { "write" } else { "all" }));
+            }
+            ClientCmd::Unpause => frame.push_bulk(Bytes::from("unpause")),
+            ClientCmd::Tracking {
+                on,
+                bcast,
+                prefixes,
+                noloop,
+            } => {
+                frame.push_bulk(Bytes::from("tracking"));
+                frame.push_bulk(Bytes::from(if on { "on" } else { "off" }));
+
+                if bcast {
+                    frame.push_bulk(Bytes::from("bcast"));
+                }
+
+                for prefix in prefixes {
+                    frame.push_bulk(Bytes::from("prefix"));
+                    frame.push_bulk(prefix);
+                }
+
+                if noloop {
+                    frame.push_bulk(Bytes::from("noloop"));
+                }
+            }
+            ClientCmd::TraceParent {
+                traceparent,
+                tracestate,
+            } => {
+                frame.push_bulk(Bytes::from("traceparent"));
+                frame.push_bulk(traceparent);
+
+                if let Some(tracestate) = tracestate {
+                    frame.push_bulk(tracestate);
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+/// Whether `traceparent` is a W3C `traceparent` header, `00-<trace id>-<parent id>-<flags>` in
+/// lowercase hex. Trace and parent ids can't be all zeros.
+fn is_traceparent(traceparent: &[u8]) -> bool {
+    let parts: Vec<&[u8]> = traceparent.split(|&byte| byte == b'-').collect();
+    let [version, trace_id, parent_id, flags] = parts[..] else {
+        return false;
+    };
+    let is_hex = |part: &[u8], len: usize| {
+        part.len() == len
+            && part
+                .iter()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+    };
+
+    is_hex(version, 2)
+        && version != b"ff"
+        && is_hex(trace_id, 32)
+        && trace_id.iter().any(|&byte| byte != b'0')
+        && is_hex(parent_id, 16)
+        && parent_id.iter().any(|&byte| byte != b'0')
+        && is_hex(flags, 2)
+}
@@ -0,0 +1,325 @@
+use bytes::Bytes;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::{
+    Connection, cluster,
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+    slot::{SLOTS, key_slot},
+};
+
+/// CLUSTER command, manages the node in cluster mode.
+///
+/// CLUSTER MEET ip port [cluster-bus-port]
+/// CLUSTER ADDSLOTS slot [slot ...]
+/// CLUSTER DELSLOTS slot [slot ...]
+/// CLUSTER SETSLOT slot MIGRATING node-id | IMPORTING node-id | NODE node-id | STABLE
+/// CLUSTER COUNTKEYSINSLOT slot
+/// CLUSTER GETKEYSINSLOT slot count
+/// CLUSTER KEYSLOT key
+/// CLUSTER MYID | INFO | NODES | SLOTS | SHARDS
+///
+/// The cluster bus port defaults to the port plus 10000.
+///
+/// A slot is moved by marking it `IMPORTING` on the new node and `MIGRATING` on its owner,
+/// moving its keys, then assigning it to the new node with `NODE`. Meanwhile the owner
+/// redirects clients with `-ASK` for the keys already moved.
+#[derive(Debug)]
+pub enum ClusterCmd {
+    /// Join the cluster of the node at the address.
+    Meet {
+        ip: Bytes,
+        port: u16,
+        bus_port: Option<u16>,
+    },
+    /// Serve the slots from this node.
+    AddSlots(Vec<u16>),
+    /// Stop serving the slots.
+    DelSlots(Vec<u16>),
+    /// Change the state of a slot.
+    SetSlot { slot: u16, state: SlotState },
+    /// Number of keys in the slot.
+    CountKeysInSlot(u16),
+    /// Up to `count` keys in the slot.
+    GetKeysInSlot { slot: u16, count: u64 },
+    /// Hash slot of the key.
+    KeySlot(Bytes),
+    /// Id of the node.
+    MyId,
+    /// State of the cluster.
+    Info,
+    /// Description of every node.
+    Nodes,
+    /// Ranges of slots with the node serving them.
+    Slots,
+    /// Nodes with the slots they serve.
+    Shards,
+}
+
+/// New state of a slot set with `CLUSTER SETSLOT`.
+#[derive(Debug)]
+pub enum SlotState {
+    /// The slot, owned by this node, moves to the node.
+    Migrating(Bytes),
+    /// The slot, owned by the node, moves to this node.
+    Importing(Bytes),
+    /// The slot is served by the node.
+    Node(Bytes),
+    /// The slot stops moving.
+    Stable,
+}
+
+impl ClusterCmd {
+    /// Parse a `ClusterCmd` instance from an array frame.
+    /// The 'CLUSTER' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ClusterCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"meet") {
+            let ip = parse.next_bytes()?;
+            let port = parse_port(parse.next_int()?)?;
+            let bus_port = match parse.next_int() {
+                Ok(bus_port) => Some(parse_port(bus_port)?),
+                Err(ParseError::EndOfStream) => None,
+                Err(err) => return Err(err.into()),
+            };
+
+            Ok(ClusterCmd::Meet { ip, port, bus_port })
+        } else if subcommand.eq_ignore_ascii_case(b"addslots") {
+            Ok(ClusterCmd::AddSlots(parse_slots(parse)?))
+        } else if subcommand.eq_ignore_ascii_case(b"delslots") {
+            Ok(ClusterCmd::DelSlots(parse_slots(parse)?))
+        } else if subcommand.eq_ignore_ascii_case(b"setslot") {
+            let slot = parse_slot(parse.next_int()?)?;
+            let state = parse.next_bytes()?;
+
+            let state = if state.eq_ignore_ascii_case(b"migrating") {
+                SlotState::Migrating(parse.next_bytes()?)
+            } else if state.eq_ignore_ascii_case(b"importing") {
+                SlotState::Importing(parse.next_bytes()?)
+            } else if state.eq_ignore_ascii_case(b"node") {
+                SlotState::Node(parse.next_bytes()?)
+            } else if state.eq_ignore_ascii_case(b"stable") {
+                SlotState::Stable
+            } else {
+                return Err(WalrusError::SyntaxError(
+                    "ERR Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP"
+                        .into(),
+                ));
+            };
+
+            Ok(ClusterCmd::SetSlot { slot, state })
+        } else if subcommand.eq_ignore_ascii_case(b"countkeysinslot") {
+            Ok(ClusterCmd::CountKeysInSlot(parse_slot(parse.next_int()?)?))
+        } else if subcommand.eq_ignore_ascii_case(b"getkeysinslot") {
+            let slot = parse_slot(parse.next_int()?)?;
+            let count = u64::try_from(parse.next_int()?)
+                .map_err(|_| WalrusError::SyntaxError("ERR Invalid number of keys".into()))?;
+
+            Ok(ClusterCmd::GetKeysInSlot { slot, count })
+        } else if subcommand.eq_ignore_ascii_case(b"keyslot") {
+            Ok(ClusterCmd::KeySlot(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"myid") {
+            Ok(ClusterCmd::MyId)
+        } else if subcommand.eq_ignore_ascii_case(b"info") {
+            Ok(ClusterCmd::Info)
+        } else if subcommand.eq_ignore_ascii_case(b"nodes") {
+            Ok(ClusterCmd::Nodes)
+        } else if subcommand.eq_ignore_ascii_case(b"slots") {
+            Ok(ClusterCmd::Slots)
+        } else if subcommand.eq_ignore_ascii_case(b"shards") {
+            Ok(ClusterCmd::Shards)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `ClusterCmd` command.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &Arc<ServerState>,
+    ) -> Result<(), WalrusError> {
+        let Some(cluster) = &server.cluster else {
+            conn.write_error_frame("ERR This instance has cluster support disabled");
+            return Ok(());
+        };
+
+        let result = match self {
+            ClusterCmd::Meet { ip, port, bus_port } => {
+                let Ok(ip) = String::from_utf8_lossy(&ip).parse::<IpAddr>() else {
+                    conn.write_error_frame("ERR Invalid node address specified");
+                    return Ok(());
+                };
+                let Some(bus_port) = bus_port.or_else(|| port.checked_add(10000)) else {
+                    conn.write_error_frame("ERR Invalid node address specified");
+                    return Ok(());
+                };
+
+                cluster.meet(SocketAddr::new(ip, bus_port), server);
+                Ok(())
+            }
+            ClusterCmd::AddSlots(slots) => cluster.add_slots(&slots),
+            ClusterCmd::DelSlots(slots) => cluster.del_slots(&slots),
+            ClusterCmd::SetSlot { slot, state } => match state {
+                SlotState::Migrating(id) => {
+                    cluster.set_migrating(slot, &String::from_utf8_lossy(&id))
+                }
+                SlotState::Importing(id) => {
+                    cluster.set_importing(slot, &String::from_utf8_lossy(&id))
+                }
+                SlotState::Node(id) => cluster.set_node(slot, &String::from_utf8_lossy(&id), db),
+                SlotState::Stable => {
+                    cluster.set_stable(slot);
+                    Ok(())
+                }
+            },
+            ClusterCmd::CountKeysInSlot(slot) => {
+                let count = cluster::count_keys_in_slot(db, slot);
+                conn.write_frame(&Frame::Integer(count as i64));
+                return Ok(());
+            }
+            ClusterCmd::GetKeysInSlot { slot, count } => {
+                let keys = cluster::keys_in_slot(db, slot, count as usize);
+                conn.write_frame(&Frame::Array(keys.into_iter().map(Frame::Bulk).collect()));
+                return Ok(());
+            }
+            ClusterCmd::KeySlot(key) => {
+                conn.write_frame(&Frame::Integer(key_slot(&key) as i64));
+                return Ok(());
+            }
+            ClusterCmd::MyId => {
+                conn.write_frame(&Frame::Bulk(Bytes::from(cluster.myid().to_string())));
+                return Ok(());
+            }
+            ClusterCmd::Info => {
+                conn.write_frame(&Frame::Bulk(Bytes::from(cluster.info())));
+                return Ok(());
+            }
+            ClusterCmd::Nodes => {
+                conn.write_frame(&Frame::Bulk(Bytes::from(cluster.nodes())));
+                return Ok(());
+            }
+            ClusterCmd::Slots => {
+                conn.write_frame(&cluster.slots());
+                return Ok(());
+            }
+            ClusterCmd::Shards => {
+                conn.write_frame(&cluster.shards());
+                return Ok(());
+            }
+        };
+
+        match result {
+            Ok(()) => conn.write_data(&Data::String(Bytes::from("OK"))),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `ClusterCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("cluster"));
+
+        match self {
+            ClusterCmd::Meet { ip, port, bus_port } => {
+                frame.push_bulk(Bytes::from("meet"));
+                frame.push_bulk(ip);
+                frame.push_bulk(Bytes::from(port.to_string()));
+                if let Some(bus_port) = bus_port {
+                    frame.push_bulk(Bytes::from(bus_port.to_string()));
+                }
+            }
+            ClusterCmd::AddSlots(slots) => {
+                frame.push_bulk(Bytes::from("addslots"));
+                for slot in slots {
+                    frame.push_bulk(Bytes::from(slot.to_string()));
+                }
+            }
+            ClusterCmd::DelSlots(slots) => {
+                frame.push_bulk(Bytes::from("delslots"));
+                for slot in slots {
+                    frame.push_bulk(Bytes::from(slot.to_string()));
+                }
+            }
+            ClusterCmd::SetSlot { slot, state } => {
+                frame.push_bulk(Bytes::from("setslot"));
+                frame.push_bulk(Bytes::from(slot.to_string()));
+                match state {
+                    SlotState::Migrating(id) => {
+                        frame.push_bulk(Bytes::from("migrating"));
+                        frame.push_bulk(id);
+                    }
+                    SlotState::Importing(id) => {
+                        frame.push_bulk(Bytes::from("importing"));
+                        frame.push_bulk(id);
+                    }
+                    SlotState::Node(id) => {
+                        frame.push_bulk(Bytes::from("node"));
+                        frame.push_bulk(id);
+                    }
+                    SlotState::Stable => frame.push_bulk(Bytes::from("stable")),
+                }
+            }
+            ClusterCmd::CountKeysInSlot(slot) => {
+                frame.push_bulk(Bytes::from("countkeysinslot"));
+                frame.push_bulk(Bytes::from(slot.to_string()));
+            }
+            ClusterCmd::GetKeysInSlot { slot, count } => {
+                frame.push_bulk(Bytes::from("getkeysinslot"));
+                frame.push_bulk(Bytes::from(slot.to_string()));
+                frame.push_bulk(Bytes::from(count.to_string()));
+            }
+            ClusterCmd::KeySlot(key) => {
+                frame.push_bulk(Bytes::from("keyslot"));
+                frame.push_bulk(key);
+            }
+            ClusterCmd::MyId => frame.push_bulk(Bytes::from("myid")),
+            ClusterCmd::Info => frame.push_bulk(Bytes::from("info")),
+            ClusterCmd::Nodes => frame.push_bulk(Bytes::from("nodes")),
+            ClusterCmd::Slots => frame.push_bulk(Bytes::from("slots")),
+            ClusterCmd::Shards => frame.push_bulk(Bytes::from("shards")),
+        }
+
+        frame
+    }
+}
+
+fn parse_port(port: i64) -> Result<u16, WalrusError> {
+    u16::try_from(port)
+        .map_err(|_| WalrusError::SyntaxError("ERR Invalid node address specified".into()))
+}
+
+/// Parse one or more slots.
+fn parse_slots(parse: &mut Parse) -> Result<Vec<u16>, WalrusError> {
+    let mut slots = vec![parse_slot(parse.next_int()?)?];
+
+    loop {
+        match parse.next_int() {
+            Ok(slot) => slots.push(parse_slot(slot)?),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(slots)
+}
+
+fn parse_slot(slot: i64) -> Result<u16, WalrusError> {
+    u16::try_from(slot)
+        .ok()
+        .filter(|slot| (*slot as usize) < SLOTS)
+        .ok_or_else(|| WalrusError::SyntaxError("ERR Invalid or out of range slot".into()))
+}
@@ -0,0 +1,186 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    cmd::table::{COMMANDS, CommandSpec, lookup},
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+};
+
+/// COMMAND command, describes the commands implemented by the server.
+///
+/// COMMAND
+/// COMMAND COUNT
+/// COMMAND LIST
+/// COMMAND INFO [command-name ...]
+/// COMMAND DOCS [command-name ...]
+#[derive(Debug)]
+pub enum CommandCmd {
+    /// Describe every command.
+    All,
+    /// Number of commands.
+    Count,
+    /// Names of every command.
+    List,
+    /// Describe the given commands, every command if empty.
+    Info(Vec<Bytes>),
+    /// Documentation of the given commands, every command if empty.
+    Docs(Vec<Bytes>),
+}
+
+impl CommandCmd {
+    /// Parse a `CommandCmd` instance from an array frame.
+    /// The 'COMMAND' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<CommandCmd, WalrusError> {
+        let subcommand = match parse.next_bytes() {
+            Ok(subcommand) => subcommand,
+            Err(ParseError::EndOfStream) => return Ok(CommandCmd::All),
+            Err(err) => return Err(err.into()),
+        };
+
+        if subcommand.eq_ignore_ascii_case(b"count") {
+            Ok(CommandCmd::Count)
+        } else if subcommand.eq_ignore_ascii_case(b"list") {
+            Ok(CommandCmd::List)
+        } else if subcommand.eq_ignore_ascii_case(b"info") {
+            Ok(CommandCmd::Info(CommandCmd::parse_names(parse)?))
+        } else if subcommand.eq_ignore_ascii_case(b"docs") {
+            Ok(CommandCmd::Docs(CommandCmd::parse_names(parse)?))
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Parse the remaining command names.
+    fn parse_names(parse: &mut Parse) -> Result<Vec<Bytes>, WalrusError> {
+        let mut names = Vec::new();
+
+        loop {
+            match parse.next_bytes() {
+                Ok(name) => names.push(name),
+                Err(ParseError::EndOfStream) => return Ok(names),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Execute the `Command` subcommand using the static command table.
+    pub(crate) async fn execute(self, conn: &mut Connection) -> Result<(), WalrusError> {
+        match self {
+            CommandCmd::All => {
+                let frame = Frame::Array(COMMANDS.iter().map(info_frame).collect());
+                conn.write_frame(&frame);
+            }
+            CommandCmd::Count => conn.write_data(&Data::Integer(COMMANDS.len() as i64)),
+            CommandCmd::List => {
+                let names = COMMANDS
+                    .iter()
+                    .map(|spec| Frame::Bulk(Bytes::from(spec.name)))
+                    .collect();
+                conn.write_frame(&Frame::Array(names));
+            }
+            CommandCmd::Info(names) if names.is_empty() => {
+                let frame = Frame::Array(COMMANDS.iter().map(info_frame).collect());
+                conn.write_frame(&frame);
+            }
+            CommandCmd::Info(names) => {
+                // Unknown commands are described with a null.
+                let frame = names
+                    .iter()
+                    .map(|name| lookup(name).map_or(Frame::Null, info_frame))
+                    .collect();
+                conn.write_frame(&Frame::Array(frame));
+            }
+            CommandCmd::Docs(names) => {
+                // Unknown commands are left out.
+                let specs: Vec<_> = if names.is_empty() {
+                    COMMANDS.iter().collect()
+                } else {
+                    names.iter().filter_map(|name| lookup(name)).collect()
+                };
+
+                let docs = specs
+                    .into_iter()
+                    .map(|spec| (Frame::Bulk(Bytes::from(spec.name)), docs_frame(spec)))
+                    .collect();
+                conn.write_frame(&Frame::Map(docs));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert `CommandCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("command"));
+
+        match self {
+            CommandCmd::All => {}
+            CommandCmd::Count => frame.push_bulk(Bytes::from("count")),
+            CommandCmd::List => frame.push_bulk(Bytes::from("list")),
+            CommandCmd::Info(names) => {
+                frame.push_bulk(Bytes::from("info"));
+
+                for name in names {
+                    frame.push_bulk(name);
+                }
+            }
+            CommandCmd::Docs(names) => {
+                frame.push_bulk(Bytes::from("docs"));
+
+                for name in names {
+                    frame.push_bulk(name);
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+/// Describe a command as in the reply of `COMMAND INFO`:
+/// name, arity, flags, first key, last key, step, ACL categories, tips, key specs and
+/// subcommands.
+fn info_frame(spec: &CommandSpec) -> Frame {
+    let flags = spec
+        .flags
+        .iter()
+        .map(|flag| Frame::Simple(Bytes::from(*flag)))
+        .collect();
+
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(spec.name)),
+        Frame::Integer(spec.arity),
+        Frame::Set(flags),
+        Frame::Integer(spec.first_key),
+        Frame::Integer(spec.last_key),
+        Frame::Integer(spec.step),
+        Frame::Set(vec![]),
+        Frame::Set(vec![]),
+        Frame::Array(vec![]),
+        Frame::Array(vec![]),
+    ])
+}
+
+/// Document a command as in the reply of `COMMAND DOCS`.
+fn docs_frame(spec: &CommandSpec) -> Frame {
+    let field = |name: &'static str, value: &'static str| {
+        (
+            Frame::Bulk(Bytes::from(name)),
+            Frame::Bulk(Bytes::from(value)),
+        )
+    };
+
+    Frame::Map(vec![
+        field("summary", spec.summary),
+        field("since", "0.1.0"),
+        field("group", spec.group),
+        field("complexity", spec.complexity),
+    ])
+}
@@ -0,0 +1,151 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    config::file,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// CONFIG command, reads and changes the configuration of the server at runtime.
+///
+/// CONFIG GET parameter [parameter ...]
+/// CONFIG SET parameter value [parameter value ...]
+/// CONFIG REWRITE
+/// CONFIG RESETSTAT
+///
+/// Parameter names of `CONFIG GET` are glob-style patterns.
+#[derive(Debug)]
+pub enum ConfigCmd {
+    /// Get the parameters matching any of the patterns.
+    Get(Vec<Bytes>),
+    /// Set parameters to the values, all or nothing.
+    Set(Vec<(Bytes, Bytes)>),
+    /// Write the parameters changed at runtime to the configuration file.
+    Rewrite,
+    /// Reset the statistics reported by `INFO`, such as the statistics of each command.
+    ResetStat,
+}
+
+impl ConfigCmd {
+    /// Parse a `ConfigCmd` instance from an array frame.
+    /// The 'CONFIG' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ConfigCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"get") {
+            let mut patterns = vec![parse.next_bytes()?];
+
+            loop {
+                match parse.next_bytes() {
+                    Ok(pattern) => patterns.push(pattern),
+                    Err(ParseError::EndOfStream) => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            Ok(ConfigCmd::Get(patterns))
+        } else if subcommand.eq_ignore_ascii_case(b"set") {
+            let mut pairs = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+
+            loop {
+                match parse.next_bytes() {
+                    Ok(name) => pairs.push((name, parse.next_bytes()?)),
+                    Err(ParseError::EndOfStream) => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            Ok(ConfigCmd::Set(pairs))
+        } else if subcommand.eq_ignore_ascii_case(b"rewrite") {
+            Ok(ConfigCmd::Rewrite)
+        } else if subcommand.eq_ignore_ascii_case(b"resetstat") {
+            Ok(ConfigCmd::ResetStat)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `Config` subcommand against the configuration of the server.
+    ///
+    /// `CONFIG GET` replies with a map of parameter names to values, encoded as a flat array on
+    /// RESP2 connections.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            ConfigCmd::Get(patterns) => {
+                let params = server.config.get(&patterns);
+
+                conn.write_map_header(params.len());
+                for (name, value) in params {
+                    conn.write_data(&Data::Bytes(Bytes::from(name)));
+                    conn.write_data(&Data::Bytes(Bytes::from(value)));
+                }
+            }
+            ConfigCmd::Set(pairs) => match server.config.set(&pairs) {
+                Ok(()) => {
+                    server.update_databases();
+                    conn.write_data(&Data::String(Bytes::from("OK")));
+                }
+                Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+            },
+            ConfigCmd::Rewrite => match &server.config_file {
+                Some(path) => match file::rewrite(path, &server.config) {
+                    Ok(()) => conn.write_data(&Data::String(Bytes::from("OK"))),
+                    Err(err) => {
+                        conn.write_error_frame(&format!("ERR Rewriting config file: {err}"))
+                    }
+                },
+                None => conn.write_error_frame("ERR The server is running without a config file"),
+            },
+            ConfigCmd::ResetStat => {
+                server.reset_stats();
+                server.clients.reset_rejected();
+                server.peers.reset_throttled();
+                server.metrics.reset();
+                server.latency.reset_commands();
+                server.audit.reset_dropped();
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert `ConfigCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("config"));
+
+        match self {
+            ConfigCmd::Get(patterns) => {
+                frame.push_bulk(Bytes::from("get"));
+
+                for pattern in patterns {
+                    frame.push_bulk(pattern);
+                }
+            }
+            ConfigCmd::Set(pairs) => {
+                frame.push_bulk(Bytes::from("set"));
+
+                for (name, value) in pairs {
+                    frame.push_bulk(name);
+                    frame.push_bulk(value);
+                }
+            }
+            ConfigCmd::Rewrite => frame.push_bulk(Bytes::from("rewrite")),
+            ConfigCmd::ResetStat => frame.push_bulk(Bytes::from("resetstat")),
+        }
+
+        frame
+    }
+}
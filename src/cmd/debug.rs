@@ -0,0 +1,219 @@
+use bytes::Bytes;
+use std::time::Duration;
+
+use crate::{
+    Connection,
+    config::DebugCommand,
+    db::{Data, Db, Value},
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, extract_f64},
+    server::ServerState,
+    task,
+};
+
+/// DEBUG command, operational helpers for testing the server.
+///
+/// DEBUG SLEEP seconds
+/// DEBUG ASYNC-SLEEP seconds
+/// DEBUG OBJECT key
+/// DEBUG SET-ACTIVE-EXPIRE 0|1
+/// DEBUG JMAP
+///
+/// Only compiled with the `debug-command` feature, and refused unless allowed by the
+/// `enable-debug-command` configuration parameter.
+#[derive(Debug)]
+pub enum DebugCmd {
+    /// Execute as a command busy for the duration, on a blocking thread so the other
+    /// connections of the worker are still served.
+    Sleep(Duration),
+    /// Suspend only the current connection.
+    AsyncSleep(Duration),
+    /// Describe the internal representation of the value of a key.
+    Object(Bytes),
+    /// Enable or disable the purging of expired keys.
+    SetActiveExpire(bool),
+    /// Statistics of the jemalloc allocator.
+    Jmap,
+}
+
+impl DebugCmd {
+    /// Parse a `DebugCmd` instance from an array frame.
+    /// The 'DEBUG' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<DebugCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"sleep") {
+            Ok(DebugCmd::Sleep(DebugCmd::parse_seconds(parse)?))
+        } else if subcommand.eq_ignore_ascii_case(b"async-sleep") {
+            Ok(DebugCmd::AsyncSleep(DebugCmd::parse_seconds(parse)?))
+        } else if subcommand.eq_ignore_ascii_case(b"object") {
+            Ok(DebugCmd::Object(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"set-active-expire") {
+            Ok(DebugCmd::SetActiveExpire(parse.next_int()? != 0))
+        } else if subcommand.eq_ignore_ascii_case(b"jmap") {
+            Ok(DebugCmd::Jmap)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Parse a non negative number of seconds, fractions are allowed.
+    fn parse_seconds(parse: &mut Parse) -> Result<Duration, WalrusError> {
+        extract_f64(&parse.next_bytes()?)
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| WalrusError::SyntaxError("ERR value is not a valid float".into()))
+    }
+
+    /// Execute the `Debug` subcommand.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        let allowed = match server.config.enable_debug_command() {
+            DebugCommand::Yes => true,
+            DebugCommand::No => false,
+            DebugCommand::Local => conn.is_local(),
+        };
+
+        if !allowed {
+            conn.write_error_frame(
+                "ERR DEBUG command not allowed. If the enable-debug-command option is set to \
+                 \"local\", you can run it from a local connection, otherwise you need to set \
+                 this option in the configuration.",
+            );
+            return Ok(());
+        }
+
+        match self {
+            DebugCmd::Sleep(duration) => {
+                // Simulates a slow command, without stalling the worker thread.
+                task::spawn_blocking("debug-sleep", move || std::thread::sleep(duration))
+                    .await
+                    .map_err(|err| WalrusError::Internal(err.to_string()))?;
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            DebugCmd::AsyncSleep(duration) => {
+                tokio::time::sleep(duration).await;
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            DebugCmd::Object(key) => match describe(db, &key) {
+                Some(description) => conn.write_data(&Data::String(Bytes::from(description))),
+                None => conn.write_error_frame("ERR no such key"),
+            },
+            DebugCmd::SetActiveExpire(enabled) => {
+                for db in &server.databases {
+                    db.set_active_expire(enabled);
+                }
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            DebugCmd::Jmap => match jemalloc_stats() {
+                Some(stats) => conn.write_data(&Data::Bytes(Bytes::from(stats))),
+                None => conn.write_error_frame("ERR jemalloc is not the allocator of this build"),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Convert `DebugCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("debug"));
+
+        match self {
+            DebugCmd::Sleep(duration) => {
+                frame.push_bulk(Bytes::from("sleep"));
+                frame.push_double(duration.as_secs_f64());
+            }
+            DebugCmd::AsyncSleep(duration) => {
+                frame.push_bulk(Bytes::from("async-sleep"));
+                frame.push_double(duration.as_secs_f64());
+            }
+            DebugCmd::Object(key) => {
+                frame.push_bulk(Bytes::from("object"));
+                frame.push_bulk(key);
+            }
+            DebugCmd::SetActiveExpire(enabled) => {
+                frame.push_bulk(Bytes::from("set-active-expire"));
+                frame.push_int(enabled as i64);
+            }
+            DebugCmd::Jmap => frame.push_bulk(Bytes::from("jmap")),
+        }
+
+        frame
+    }
+}
+
+/// Describe the internal representation of the value of `key`, `None` if the key doesn't exist.
+/// Expired keys not purged yet are described too.
+fn describe(db: &Db, key: &Bytes) -> Option<String> {
+    let entry = db.peek(key)?;
+
+    let (kind, len) = match &entry.data {
+        Value::Bytes(bytes) | Value::String(bytes) => ("string", bytes.len()),
+        Value::Inline(inline) => ("string", inline.as_bytes().len()),
+        Value::Integer(_) | Value::Double(_) => ("string", 8),
+        Value::List(list) => ("list", list.len()),
+    };
+    let encoding = entry.data.encoding();
+
+    let ttl = entry
+        .expires_at
+        .map(|when| {
+            when.saturating_duration_since(tokio::time::Instant::now())
+                .as_millis() as i64
+        })
+        .unwrap_or(-1);
+
+    Some(format!(
+        "type:{kind} encoding:{encoding} len:{len} ttl_ms:{ttl} lru_seconds_idle:{}",
+        db.idle_time(&entry).as_secs()
+    ))
+}
+
+/// Statistics printed by `malloc_stats_print` of jemalloc.
+#[cfg(all(
+    feature = "jemalloc",
+    not(feature = "mimalloc"),
+    not(target_env = "msvc")
+))]
+fn jemalloc_stats() -> Option<String> {
+    use std::ffi::{CStr, c_char, c_void};
+
+    unsafe extern "C" fn write(out: *mut c_void, text: *const c_char) {
+        // SAFETY: `out` is the `String` passed to `malloc_stats_print` below and `text` is a
+        // nul terminated string provided by jemalloc.
+        unsafe {
+            let out = &mut *(out as *mut String);
+            out.push_str(&CStr::from_ptr(text).to_string_lossy());
+        }
+    }
+
+    let mut stats = String::new();
+
+    // SAFETY: the callback only runs during the call, while `stats` is borrowed.
+    unsafe {
+        jemalloc_sys::malloc_stats_print(
+            Some(write),
+            &mut stats as *mut String as *mut c_void,
+            std::ptr::null(),
+        );
+    }
+
+    Some(stats)
+}
+
+#[cfg(not(all(
+    feature = "jemalloc",
+    not(feature = "mimalloc"),
+    not(target_env = "msvc")
+)))]
+fn jemalloc_stats() -> Option<String> {
+    None
+}
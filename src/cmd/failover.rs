@@ -0,0 +1,165 @@
+use bytes::Bytes;
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    replication::FailoverTarget,
+    server::ServerState,
+};
+
+/// FAILOVER command, hands over the role of master to one of the replicas.
+///
+/// FAILOVER [TO host port [FORCE]] [TIMEOUT milliseconds] | ABORT
+///
+/// Writes are paused until the replica, the one at `host:port` or the first one to catch up,
+/// applied every write command. The replica is then promoted and the server becomes its
+/// replica. Without a replica caught up within the timeout the failover is aborted, unless
+/// `FORCE` is given. The failover runs in the background, `ABORT` cancels it.
+#[derive(Debug, Default)]
+pub struct Failover {
+    /// Replica to hand over to, any replica if `None`.
+    to: Option<(Bytes, u16)>,
+    force: bool,
+    /// Timeout in milliseconds.
+    timeout: Option<u64>,
+    abort: bool,
+}
+
+impl Failover {
+    /// Creates a new `FAILOVER` command handing over to any replica.
+    pub fn new() -> Failover {
+        Failover::default()
+    }
+
+    /// Hand over to the replica at `host:port`.
+    pub fn to(mut self, host: Bytes, port: u16) -> Failover {
+        self.to = Some((host, port));
+        self
+    }
+
+    /// Hand over once the timeout is reached even if the replica didn't catch up.
+    pub fn force(mut self) -> Failover {
+        self.force = true;
+        self
+    }
+
+    /// Abort the failover if no replica caught up within `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Failover {
+        self.timeout = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Creates a new `FAILOVER ABORT` command.
+    pub fn abort() -> Failover {
+        Failover {
+            abort: true,
+            ..Failover::default()
+        }
+    }
+
+    /// Parse a `Failover` instance from an array frame.
+    /// The 'FAILOVER' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Failover, WalrusError> {
+        let mut failover = Failover::new();
+
+        loop {
+            match parse.next_bytes() {
+                Ok(option) if option.eq_ignore_ascii_case(b"to") && failover.to.is_none() => {
+                    let host = parse.next_bytes()?;
+                    let port = u16::try_from(parse.next_int()?)
+                        .map_err(|_| WalrusError::SyntaxError("ERR Invalid port".into()))?;
+                    failover.to = Some((host, port));
+                }
+                Ok(option) if option.eq_ignore_ascii_case(b"force") => failover.force = true,
+                Ok(option) if option.eq_ignore_ascii_case(b"timeout") => {
+                    let timeout = u64::try_from(parse.next_int()?)
+                        .ok()
+                        .filter(|timeout| *timeout > 0)
+                        .ok_or_else(|| {
+                            WalrusError::SyntaxError(
+                                "ERR FAILOVER timeout must be greater than 0".into(),
+                            )
+                        })?;
+                    failover.timeout = Some(timeout);
+                }
+                Ok(option) if option.eq_ignore_ascii_case(b"abort") => failover.abort = true,
+                Ok(_) => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if failover.abort && (failover.to.is_some() || failover.force || failover.timeout.is_some())
+        {
+            return Err(WalrusError::SyntaxError("ERR syntax error".into()));
+        }
+
+        if failover.force && (failover.to.is_none() || failover.timeout.is_none()) {
+            return Err(WalrusError::SyntaxError(
+                "ERR FAILOVER with force option requires both a timeout and target HOST and IP."
+                    .into(),
+            ));
+        }
+
+        Ok(failover)
+    }
+
+    /// Execute the `Failover` command, the failover goes on in the background.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &Arc<ServerState>,
+    ) -> Result<(), WalrusError> {
+        if self.abort {
+            if !server.replication.abort_failover(server) {
+                conn.write_error_frame("ERR No failover in progress.");
+                return Ok(());
+            }
+
+            conn.write_data(&Data::String(Bytes::from("OK")));
+            return Ok(());
+        }
+
+        let target = self.to.map(|(host, port)| FailoverTarget {
+            host: String::from_utf8_lossy(&host).into_owned(),
+            port,
+            force: self.force,
+        });
+        let timeout = self.timeout.map(Duration::from_millis);
+
+        match server.replication.start_failover(target, timeout, server) {
+            Ok(()) => conn.write_data(&Data::String(Bytes::from("OK"))),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Failover` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("failover"));
+
+        if let Some((host, port)) = self.to {
+            frame.push_bulk(Bytes::from("to"));
+            frame.push_bulk(host);
+            frame.push_bulk(Bytes::from(port.to_string()));
+        }
+        if self.force {
+            frame.push_bulk(Bytes::from("force"));
+        }
+        if let Some(timeout) = self.timeout {
+            frame.push_bulk(Bytes::from("timeout"));
+            frame.push_bulk(Bytes::from(timeout.to_string()));
+        }
+        if self.abort {
+            frame.push_bulk(Bytes::from("abort"));
+        }
+
+        frame
+    }
+}
@@ -2,7 +2,7 @@ use bytes::Bytes;
 
 use crate::{
     Connection,
-    db::{Data, Db, double_to_bytes, int_to_bytes},
+    db::{Data, Db, Value, double_to_bytes},
     errors::WalrusError,
     frame::Frame,
     parse::Parse,
@@ -34,18 +34,18 @@ impl Get {
     /// Execute the `Get` command to fetch the value for the key from the shared db.
     /// The value is written to `conn`.
     pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
-        let maybe_data = db.get(&self.key);
-
-        match maybe_data {
-            Some(data) => match data {
-                Data::Array(_) => {
-                    conn.write_error_frame(WalrusError::WrongType.get_msg());
-                    return Err(WalrusError::WrongType);
+        // The value is written from the entry, inline strings and integers aren't copied to
+        // a buffer of their own first.
+        match db.get_ref(&self.key) {
+            Some(entry) => match &entry.data {
+                Value::List(_) => return Err(WalrusError::WrongType),
+                Value::Bytes(_) | Value::Inline(_) | Value::String(_) => {
+                    conn.write_value(&entry.data)
+                }
+                Value::Integer(integer) => {
+                    conn.write_bulk(itoa::Buffer::new().format(*integer).as_bytes());
                 }
-                Data::Bytes(bytes) => conn.write_data(&Data::Bytes(bytes)),
-                Data::Integer(integer) => conn.write_data(&Data::Bytes(int_to_bytes(integer))),
-                Data::Double(double) => conn.write_data(&Data::Bytes(double_to_bytes(double))),
-                Data::String(string) => conn.write_data(&Data::String(string)),
+                Value::Double(double) => conn.write_data(&Data::Bytes(double_to_bytes(*double))),
             },
             None => conn.write_null_frame(),
         };
@@ -53,6 +53,11 @@ impl Get {
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.key)
+    }
+
     /// Convert `Get` instance to `Frame`.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -0,0 +1,165 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    connection::Protocol,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// HELLO command, switches the protocol of the connection and returns server metadata.
+///
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+///
+/// Without `protover` the protocol of the connection is left unchanged.
+/// Replies with a map of server properties, encoded as a flat array on RESP2 connections.
+#[derive(Debug)]
+pub struct Hello {
+    /// Requested protocol version.
+    protover: Option<i64>,
+    /// Username and password.
+    auth: Option<(Bytes, Bytes)>,
+    /// Name to set for the connection.
+    setname: Option<Bytes>,
+}
+
+impl Hello {
+    /// Creates a new `HELLO` command requesting the `protover` protocol version.
+    pub fn new(protover: Option<i64>) -> Hello {
+        Hello {
+            protover,
+            auth: None,
+            setname: None,
+        }
+    }
+
+    /// Authenticate as `username` with `password`.
+    pub(crate) fn auth(mut self, username: Bytes, password: Bytes) -> Hello {
+        self.auth = Some((username, password));
+        self
+    }
+
+    /// Name the connection `name`.
+    pub(crate) fn setname(mut self, name: Bytes) -> Hello {
+        self.setname = Some(name);
+        self
+    }
+
+    /// Parse a `Hello` instance from an array frame.
+    /// The 'HELLO' string is already consumed.
+    ///
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Hello, WalrusError> {
+        let protover = match parse.next_int() {
+            Ok(protover) => Some(protover),
+            Err(ParseError::EndOfStream) => None,
+            Err(_) => {
+                return Err(WalrusError::SyntaxError(
+                    "ERR Protocol version is not an integer or out of range".into(),
+                ));
+            }
+        };
+
+        let mut hello = Hello::new(protover);
+
+        loop {
+            match parse.next_bytes() {
+                Ok(option) if option.eq_ignore_ascii_case(b"auth") => {
+                    let username = parse.next_bytes()?;
+                    let password = parse.next_bytes()?;
+                    hello.auth = Some((username, password));
+                }
+                Ok(option) if option.eq_ignore_ascii_case(b"setname") => {
+                    hello.setname = Some(parse.next_bytes()?);
+                }
+                Ok(option) => {
+                    return Err(WalrusError::SyntaxError(format!(
+                        "ERR Syntax error in HELLO option '{}'",
+                        String::from_utf8_lossy(&option)
+                    )));
+                }
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(hello)
+    }
+
+    /// Execute the `Hello` command.
+    ///
+    /// Switches the connection to the requested protocol, then writes the server properties
+    /// using the new protocol. `NOPROTO` error is written if the version is not supported.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        let protocol = match self.protover {
+            None => conn.protocol(),
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => {
+                conn.write_error_frame("NOPROTO unsupported protocol version");
+                return Ok(());
+            }
+        };
+
+        // No users or passwords are configured, so any `AUTH` credentials are accepted.
+        if let Some(name) = self.setname {
+            server.clients.set_name(conn.id(), Some(name.clone()));
+            conn.set_name(Some(name));
+        }
+
+        conn.set_protocol(protocol);
+
+        let proto = match protocol {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        };
+
+        conn.write_map_header(7);
+        conn.write_data(&Data::Bytes(Bytes::from("server")));
+        conn.write_data(&Data::Bytes(Bytes::from("walrus")));
+        conn.write_data(&Data::Bytes(Bytes::from("version")));
+        conn.write_data(&Data::Bytes(Bytes::from(env!("CARGO_PKG_VERSION"))));
+        conn.write_data(&Data::Bytes(Bytes::from("proto")));
+        conn.write_data(&Data::Integer(proto));
+        conn.write_data(&Data::Bytes(Bytes::from("id")));
+        conn.write_data(&Data::Integer(conn.id() as i64));
+        conn.write_data(&Data::Bytes(Bytes::from("mode")));
+        conn.write_data(&Data::Bytes(Bytes::from("standalone")));
+        conn.write_data(&Data::Bytes(Bytes::from("role")));
+        conn.write_data(&Data::Bytes(Bytes::from("master")));
+        conn.write_data(&Data::Bytes(Bytes::from("modules")));
+        conn.write_data_array(vec![].into_iter(), 0);
+
+        Ok(())
+    }
+
+    /// Convert `Hello` instance to `Frame` consuming self.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello"));
+
+        if let Some(protover) = self.protover {
+            frame.push_int(protover);
+        }
+
+        if let Some((username, password)) = self.auth {
+            frame.push_bulk(Bytes::from("auth"));
+            frame.push_bulk(username);
+            frame.push_bulk(password);
+        }
+
+        if let Some(name) = self.setname {
+            frame.push_bulk(Bytes::from("setname"));
+            frame.push_bulk(name);
+        }
+
+        frame
+    }
+}
@@ -0,0 +1,271 @@
+use bytes::Bytes;
+use std::{borrow::Cow, fmt::Write};
+
+use crate::{
+    Connection,
+    db::{Data, Db, Kind},
+    errors::WalrusError,
+    frame::Frame,
+    memory::MemoryReport,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// Fields of a section, as `(field, value)` pairs.
+type Fields = fn(&ServerState) -> Vec<(Cow<'static, str>, String)>;
+
+/// Sections of the reply, in order.
+const SECTIONS: &[(&str, Fields)] = &[
+    ("Server", |server| named(server_section(server))),
+    ("Clients", |server| named(clients_section(server))),
+    ("Memory", |server| {
+        let mut fields = MemoryReport::sample(server).info();
+        fields.extend([
+            ("maxmemory", server.config.maxmemory().to_string()),
+            (
+                "maxmemory_policy",
+                server.config.maxmemory_policy().name().to_string(),
+            ),
+            (
+                "lazyfree_pending_objects",
+                server.stats().lazyfree_pending_objects.to_string(),
+            ),
+        ]);
+        named(fields)
+    }),
+    ("Persistence", |server| {
+        let mut fields = server.persistence.info();
+        fields.extend(server.aof.info());
+        fields.extend(server.loading.info());
+        named(fields)
+    }),
+    ("Stats", |server| {
+        let stats = server.stats();
+        named(vec![
+            (
+                "total_commands_processed",
+                stats.total_commands().to_string(),
+            ),
+            (
+                "rejected_connections",
+                server.clients.rejected().to_string(),
+            ),
+            ("throttled_commands", server.peers.throttled().to_string()),
+            ("audit_dropped_entries", server.audit.dropped().to_string()),
+            (
+                "total_net_input_bytes",
+                server.metrics.net_input_bytes().to_string(),
+            ),
+            (
+                "total_net_output_bytes",
+                server.metrics.net_output_bytes().to_string(),
+            ),
+            ("expired_keys", stats.expired_keys.to_string()),
+            ("evicted_keys", stats.evicted_keys.to_string()),
+            ("keyspace_hits", stats.keyspace_hits.to_string()),
+            ("keyspace_misses", stats.keyspace_misses.to_string()),
+            ("lazyfreed_objects", stats.lazyfreed_objects.to_string()),
+        ])
+    }),
+    ("Replication", |server| {
+        server.replication.info(server.config.repl_backlog_size())
+    }),
+    ("Commandstats", |server| {
+        server
+            .stats()
+            .commands
+            .into_iter()
+            .map(|(name, stats)| {
+                let per_call = stats.usec as f64 / stats.calls.max(1) as f64;
+                (
+                    format!("cmdstat_{name}").into(),
+                    format!(
+                        "calls={},usec={},usec_per_call={per_call:.2},max_usec={},failed_calls={}",
+                        stats.calls, stats.usec, stats.max_usec, stats.failed_calls
+                    ),
+                )
+            })
+            .collect()
+    }),
+    ("Latencystats", |server| {
+        let percentiles = server.config.latency_tracking_info_percentiles();
+        server
+            .latency
+            .command_percentiles(&percentiles)
+            .into_iter()
+            .map(|(name, values)| {
+                let values = values
+                    .into_iter()
+                    .map(|(percentile, us)| format!("p{percentile}={:.3}", us as f64))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                (format!("latency_percentiles_usec_{name}").into(), values)
+            })
+            .collect()
+    }),
+    ("Cluster", |server| {
+        vec![(
+            "cluster_enabled".into(),
+            (server.cluster.is_some() as u8).to_string(),
+        )]
+    }),
+    ("Sentinel", |server| match &server.sentinel {
+        Some(sentinel) => sentinel
+            .info()
+            .into_iter()
+            .map(|(field, value)| (field.into(), value))
+            .collect(),
+        None => Vec::new(),
+    }),
+    ("Keyspace", |server| keyspace_section(&server.databases)),
+];
+
+/// INFO command, describes the state of the server.
+///
+/// INFO [section [section ...]]
+///
+/// Replies with a bulk string of `field:value` lines grouped in sections, each starting with a
+/// `# Section` header, sections without fields are left out. Every section is returned if none
+/// is given, or with `all`, `default` and `everything`. Unknown sections are ignored.
+#[derive(Debug, Default)]
+pub struct Info {
+    sections: Vec<Bytes>,
+}
+
+impl Info {
+    /// Creates a new `INFO` command for `sections`, every section if empty.
+    pub fn new(sections: Vec<Bytes>) -> Info {
+        Info { sections }
+    }
+
+    /// Parse an `Info` instance from an array frame.
+    /// The 'INFO' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Info, WalrusError> {
+        let mut sections = Vec::new();
+
+        loop {
+            match parse.next_bytes() {
+                Ok(section) => sections.push(section),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Info { sections })
+    }
+
+    /// Execute the `Info` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        let all = self.sections.is_empty()
+            || self.sections.iter().any(|section| {
+                [&b"all"[..], b"default", b"everything"]
+                    .iter()
+                    .any(|alias| section.eq_ignore_ascii_case(alias))
+            });
+
+        let mut info = String::new();
+
+        for (name, fields) in SECTIONS {
+            let selected = all
+                || self
+                    .sections
+                    .iter()
+                    .any(|section| section.eq_ignore_ascii_case(name.as_bytes()));
+
+            // Sections of modes the server doesn't run in, or of an empty keyspace, have no
+            // fields.
+            let fields = fields(server);
+            if !selected || fields.is_empty() {
+                continue;
+            }
+
+            if !info.is_empty() {
+                info.push_str("\r\n");
+            }
+
+            // Writing to a `String` never fails.
+            let _ = write!(info, "# {name}\r\n");
+            for (field, value) in fields {
+                let _ = write!(info, "{field}:{value}\r\n");
+            }
+        }
+
+        conn.write_data(&Data::Bytes(Bytes::from(info)));
+
+        Ok(())
+    }
+
+    /// Convert `Info` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("info"));
+
+        for section in self.sections {
+            frame.push_bulk(section);
+        }
+
+        frame
+    }
+}
+
+/// Fields whose names are all known at compile time.
+fn named(fields: Vec<(&'static str, String)>) -> Vec<(Cow<'static, str>, String)> {
+    fields
+        .into_iter()
+        .map(|(field, value)| (field.into(), value))
+        .collect()
+}
+
+fn server_section(server: &ServerState) -> Vec<(&'static str, String)> {
+    vec![
+        ("walrus_version", env!("CARGO_PKG_VERSION").to_string()),
+        ("process_id", std::process::id().to_string()),
+        ("tcp_port", server.config.port().to_string()),
+        (
+            "uptime_in_seconds",
+            server.started.elapsed().as_secs().to_string(),
+        ),
+        (
+            "config_file",
+            server
+                .config_file
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+        ),
+    ]
+}
+
+fn clients_section(server: &ServerState) -> Vec<(&'static str, String)> {
+    vec![
+        ("connected_clients", server.clients.len().to_string()),
+        ("maxclients", server.config.maxclients().to_string()),
+    ]
+}
+
+/// Keys of every database, with the number of keys of every type. Empty databases are left
+/// out, like Redis does.
+fn keyspace_section(databases: &[Db]) -> Vec<(Cow<'static, str>, String)> {
+    databases
+        .iter()
+        .enumerate()
+        .filter_map(|(index, db)| {
+            let keys = db.key_count(None);
+            (keys > 0).then(|| {
+                (
+                    format!("db{index}").into(),
+                    format!(
+                        "keys={keys},expires={},avg_ttl=0,strings={},lists={}",
+                        db.expires_count(),
+                        db.key_count(Some(Kind::String)),
+                        db.key_count(Some(Kind::List)),
+                    ),
+                )
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,48 @@
+use bytes::Bytes;
+
+use crate::{Connection, db::Db, errors::WalrusError, frame::Frame, parse::Parse};
+
+/// KEYS command, returns every key matching a glob-style pattern.
+///
+/// KEYS pattern
+///
+/// Every key is visited at once, `SCAN` visits the keyspace incrementally.
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+impl Keys {
+    /// Create a new `Keys` command returning the keys matching `pattern`.
+    pub fn new(pattern: Bytes) -> Keys {
+        Keys { pattern }
+    }
+
+    /// Parse a `Keys` instance from an array frame.
+    /// The 'KEYS' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Keys, WalrusError> {
+        Ok(Keys {
+            pattern: parse.next_bytes()?,
+        })
+    }
+
+    /// Execute the `Keys` command, writing the matching keys to `conn`.
+    pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
+        let mut frame = Frame::array();
+        for key in db.keys(&self.pattern) {
+            frame.push_bulk(key);
+        }
+        conn.write_frame(&frame);
+
+        Ok(())
+    }
+
+    /// Convert `Keys` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("keys"));
+        frame.push_bulk(self.pattern);
+
+        frame
+    }
+}
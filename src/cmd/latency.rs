@@ -0,0 +1,97 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// LATENCY command, reads and resets the latency spikes recorded by the latency monitor for
+/// events taking at least `latency-monitor-threshold` milliseconds.
+///
+/// LATENCY LATEST
+/// LATENCY HISTORY event
+/// LATENCY RESET [event ...]
+#[derive(Debug)]
+pub enum LatencyCmd {
+    /// Latest spike of every event.
+    Latest,
+    /// Every spike of an event kept by the monitor.
+    History(Bytes),
+    /// Remove the spikes of the events, of every event if none is given.
+    Reset(Vec<Bytes>),
+}
+
+impl LatencyCmd {
+    /// Parse a `LatencyCmd` instance from an array frame.
+    /// The 'LATENCY' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LatencyCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"latest") {
+            Ok(LatencyCmd::Latest)
+        } else if subcommand.eq_ignore_ascii_case(b"history") {
+            Ok(LatencyCmd::History(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"reset") {
+            let mut events = Vec::new();
+            loop {
+                match parse.next_bytes() {
+                    Ok(event) => events.push(event),
+                    Err(ParseError::EndOfStream) => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            Ok(LatencyCmd::Reset(events))
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `Latency` subcommand against the latency monitor of the server.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            LatencyCmd::Latest => conn.write_frame(&Frame::Array(server.latency.latest())),
+            LatencyCmd::History(event) => {
+                conn.write_frame(&Frame::Array(server.latency.history(&event)))
+            }
+            LatencyCmd::Reset(events) => {
+                conn.write_data(&Data::Integer(server.latency.reset(&events) as i64))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert `LatencyCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("latency"));
+
+        match self {
+            LatencyCmd::Latest => frame.push_bulk(Bytes::from("latest")),
+            LatencyCmd::History(event) => {
+                frame.push_bulk(Bytes::from("history"));
+                frame.push_bulk(event);
+            }
+            LatencyCmd::Reset(events) => {
+                frame.push_bulk(Bytes::from("reset"));
+                for event in events {
+                    frame.push_bulk(event);
+                }
+            }
+        }
+
+        frame
+    }
+}
@@ -37,19 +37,9 @@ impl LLen {
     /// `list_key` is not a list.
     /// Returns `0` if no list with `list_key` is found.
     pub(crate) async fn execute(&self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
-        let maybe_list = db.get(&self.list_key);
-
-        if let Some(list) = maybe_list {
-            match list {
-                Data::Array(list) => {
-                    let response = Data::Integer(list.len() as i64);
-                    conn.write_data(&response);
-                }
-                // Data associated with the given key is not a list.
-                _ => {
-                    conn.write_error_frame(WalrusError::WrongType.get_msg());
-                }
-            }
+        if let Some(entry) = db.get_ref(&self.list_key) {
+            let list = entry.data.as_list()?;
+            conn.write_data(&Data::Integer(list.len() as i64));
         }
         // No list with given key.
         else {
@@ -59,6 +49,11 @@ impl LLen {
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.list_key)
+    }
+
     /// Convert `LLen` instance to `Frame`.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -0,0 +1,151 @@
+use bytes::Bytes;
+use rand::RngExt;
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError, extract_i64},
+};
+
+/// Default size of the drawing in characters.
+const DEFAULT_WIDTH: i64 = 48;
+const DEFAULT_HEIGHT: i64 = 12;
+/// Upper bound of the drawing size, keeps the reply small.
+const MAX_SIZE: i64 = 1000;
+
+/// LOLWUT command, draws a piece of generative art followed by the version of the server.
+///
+/// LOLWUT [VERSION version] [width [height]]
+///
+/// The drawing is a grid of tiles which are tidy at the top and increasingly scattered towards
+/// the bottom, after Georg Nees' "Schotter". It's different every time.
+#[derive(Debug)]
+pub struct Lolwut {
+    /// Requested art version, only one version exists so it's ignored.
+    version: Option<i64>,
+    width: Option<i64>,
+    height: Option<i64>,
+}
+
+impl Lolwut {
+    /// Creates a new `LOLWUT` command drawing art of the default size.
+    pub fn new() -> Lolwut {
+        Lolwut {
+            version: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Parse a `Lolwut` instance from an array frame.
+    /// The 'LOLWUT' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Lolwut, WalrusError> {
+        let mut lolwut = Lolwut::new();
+        let mut sizes = Vec::new();
+
+        loop {
+            match parse.next_bytes() {
+                Ok(option) if option.eq_ignore_ascii_case(b"version") => {
+                    lolwut.version = Some(parse.next_int()?);
+                }
+                Ok(size) => {
+                    let size = extract_i64(&size).ok_or_else(|| {
+                        WalrusError::SyntaxError(
+                            "ERR value is not an integer or out of range".into(),
+                        )
+                    })?;
+                    sizes.push(size);
+                }
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        match sizes[..] {
+            [] => {}
+            [width] => lolwut.width = Some(width),
+            [width, height] => {
+                lolwut.width = Some(width);
+                lolwut.height = Some(height);
+            }
+            _ => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+        }
+
+        Ok(lolwut)
+    }
+
+    /// Execute the `Lolwut` command, the art is written as a single bulk string.
+    pub(crate) async fn execute(self, conn: &mut Connection) -> Result<(), WalrusError> {
+        let width = self.width.unwrap_or(DEFAULT_WIDTH).clamp(1, MAX_SIZE) as usize;
+        let height = self.height.unwrap_or(DEFAULT_HEIGHT).clamp(1, MAX_SIZE) as usize;
+
+        let mut art = draw(width, height);
+        art.push_str(concat!("\nWalrus ver. ", env!("CARGO_PKG_VERSION"), "\n"));
+
+        conn.write_data(&Data::Bytes(Bytes::from(art)));
+
+        Ok(())
+    }
+
+    /// Convert `Lolwut` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("lolwut"));
+
+        if let Some(version) = self.version {
+            frame.push_bulk(Bytes::from("version"));
+            frame.push_int(version);
+        }
+
+        if let Some(width) = self.width {
+            frame.push_int(width);
+        }
+
+        if let Some(height) = self.height {
+            frame.push_int(height);
+        }
+
+        frame
+    }
+}
+
+impl Default for Lolwut {
+    fn default() -> Self {
+        Lolwut::new()
+    }
+}
+
+/// Draw `height` lines of `width` tiles.
+///
+/// Each line is more disordered than the previous one: tiles are more likely to be tilted or
+/// to have fallen out of the grid.
+fn draw(width: usize, height: usize) -> String {
+    const TIDY: char = '#';
+    const TILTED: [char; 4] = ['/', '\\', '<', '>'];
+    const FALLEN: [char; 3] = ['.', ',', ' '];
+
+    let mut rng = rand::rng();
+    let mut art = String::with_capacity((width + 1) * height);
+
+    for row in 0..height {
+        let disorder = row as f64 / height as f64;
+
+        for _ in 0..width {
+            let tile = if !rng.random_bool(disorder) {
+                TIDY
+            } else if rng.random_bool(0.7) {
+                TILTED[rng.random_range(0..TILTED.len())]
+            } else {
+                FALLEN[rng.random_range(0..FALLEN.len())]
+            };
+
+            art.push(tile);
+        }
+
+        art.push('\n');
+    }
+
+    art
+}
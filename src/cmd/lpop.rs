@@ -2,7 +2,7 @@ use bytes::Bytes;
 
 use crate::{
     Connection,
-    db::{Data, Db},
+    db::{Db, Op},
     errors::WalrusError,
     frame::Frame,
 };
@@ -52,29 +52,42 @@ impl LPop {
     pub(crate) async fn execute(&self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
         let key = &self.list_key;
         if let Some(mut entry) = db.get_mut(key) {
-            match &mut entry.data {
-                Data::Array(list) => {
-                    let len = list.len() as i64;
-                    let mut count = self.count;
-                    // Clamp count to the length of the list.
-                    count = count.min(len);
+            let size = entry.data.size();
+            let mut popped = false;
+            let list = entry.data.as_list_mut()?;
+            let len = list.len() as i64;
+            let mut count = self.count;
+            // Clamp count to the length of the list.
+            count = count.min(len);
 
-                    // If count is negative, then return an error.
-                    if count < 0 {
-                        conn.write_error_frame("value is out of range, must be positive");
-                    } else if count == 0 {
-                        // If count is zero, then return an empty array.
-                        conn.write_data_array(vec![].into_iter(), 0);
-                    } else if count == 1 {
-                        // unwrap is safe as we clamp count to the length of the list.
-                        // Return single element as a single frame instead of an array.
-                        conn.write_data(&list.pop_front().unwrap());
-                    } else {
-                        conn.write_data_array_owned(list.drain(0..count as usize), count as usize);
-                    }
-                }
-                // Data associated with the given key is not a list.
-                _ => conn.write_error_frame(WalrusError::WrongType.get_msg()),
+            // If count is negative, then return an error.
+            if count < 0 {
+                conn.write_error_frame("value is out of range, must be positive");
+            } else if count == 0 {
+                // If count is zero, then return an empty array.
+                conn.write_data_array(vec![].into_iter(), 0);
+            } else if count == 1 {
+                // unwrap is safe as we clamp count to the length of the list.
+                // Return single element as a single frame instead of an array.
+                let data = list.pop_front().unwrap();
+                popped = true;
+                conn.write_data(&data);
+            } else {
+                let elements = std::iter::from_fn(|| list.pop_front()).take(count as usize);
+                conn.write_data_array_owned(elements, count as usize);
+                popped = true;
+            }
+            entry.resize(size);
+            if popped {
+                entry.record(|| Op::Pop {
+                    count: count as usize,
+                    front: true,
+                });
+            }
+            drop(entry);
+
+            if popped {
+                db.popped(key);
             }
         }
         // No Data associated with the given key.
@@ -85,6 +98,11 @@ impl LPop {
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.list_key)
+    }
+
     /// Convert `LPop` instance to `Frame`.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -4,7 +4,7 @@ use bytes::Bytes;
 
 use crate::{
     Connection,
-    db::{Data, Db},
+    db::{Data, Db, List, Value},
     errors::WalrusError,
     frame::Frame,
     parse::Parse,
@@ -61,67 +61,60 @@ impl LPush {
     pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
         let key = self.list_key;
 
-        match self.data {
-            LPushData::Frames {
-                mut frames,
-                start_pos,
-            } => {
-                if let Some(mut entry) = db.get_mut(&key) {
-                    match &mut entry.data {
-                        Data::Array(list) => {
-                            for frame in frames.drain(start_pos..) {
-                                list.push_front(
-                                    Data::try_from(frame).map_err(|e| WalrusError::Internal(e))?,
-                                );
-                            }
-                            conn.write_data(&Data::Integer(list.len() as i64));
-                        }
-                        _ => conn.write_error_frame(WalrusError::WrongType.get_msg()),
-                    }
-                } else {
-                    let mut list = VecDeque::with_capacity(frames.len() - start_pos);
-                    for frame in frames.drain(start_pos..) {
-                        list.push_front(
-                            Data::try_from(frame).map_err(|e| WalrusError::Internal(e))?,
-                        );
-                    }
-                    let list_len = list.len();
-                    db.set(&key, Data::Array(list), None);
-                    conn.write_data(&Data::Integer(list_len as i64));
-                    db.notify_blocked(&key);
+        let (len, pushed) = {
+            // The list is created and pushed to in one step.
+            let limits = db.listpack_limits();
+            let mut entry = db.get_or_insert_with(&key, || Value::List(List::new()));
+            let size = entry.data.size();
+            let recorded = entry.is_recorded();
+            let list = entry.data.as_list_mut()?;
+            let mut elements = Vec::new();
+            let mut push = |data: Data| {
+                if recorded {
+                    elements.push(data.clone());
                 }
-            }
-            LPushData::Data(mut new_data) => {
-                if let Some(mut entry) = db.get_mut(&key) {
-                    // Key exists.
-                    match &mut entry.data {
-                        Data::Array(list) => {
-                            for data in new_data {
-                                list.push_front(data);
-                            }
-                            conn.write_data(&Data::Integer(list.len() as i64));
-                        }
-                        // Not an array.
-                        _ => conn.write_error_frame(WalrusError::WrongType.get_msg()),
-                    }
-                } else {
-                    // Key doesn't exist, create it.
-                    new_data.make_contiguous().reverse();
-                    let list_len = new_data.len();
+                list.push_front(data, limits);
+            };
 
-                    db.set(&key, Data::Array(new_data), None);
-
-                    conn.write_data(&Data::Integer(list_len as i64));
-                    db.notify_blocked(&key);
+            // Elements pushed before a conversion error are kept, and accounted for.
+            let pushed: Result<(), WalrusError> = match self.data {
+                LPushData::Frames {
+                    mut frames,
+                    start_pos,
+                } => frames.drain(start_pos..).try_for_each(|frame| {
+                    push(Data::try_from(frame).map_err(WalrusError::Internal)?);
+                    Ok(())
+                }),
+                LPushData::Data(new_data) => {
+                    new_data.into_iter().for_each(push);
+                    Ok(())
                 }
-            }
+            };
+            let len = list.len();
+            entry.resize(size);
+            entry.record_push(elements, true);
+            (len, pushed)
+        };
+
+        if len == 0 {
+            // Nothing was pushed to a new list.
+            db.remove_empty_list(&key);
+        } else {
+            db.pushed(&key);
         }
 
+        pushed?;
+        conn.write_data(&Data::Integer(len as i64));
+
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.list_key)
+    }
+
     /// Convert `LPush` instance to `Frame` consuming self.
-    /// Will `panic` if `self.data` contains nested arrays.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
         frame.push_bulk(Bytes::from("lpush"));
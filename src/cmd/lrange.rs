@@ -1,12 +1,6 @@
 use bytes::Bytes;
 
-use crate::{
-    Connection,
-    db::{Data, Db},
-    errors::WalrusError,
-    frame::Frame,
-    parse::Parse,
-};
+use crate::{Connection, db::Db, errors::WalrusError, frame::Frame, parse::Parse};
 
 /// `LRange` Command to fetch elements of a list from some start offset
 /// to end offset (both inclusive).
@@ -57,44 +51,37 @@ impl LRange {
         let key = self.list_key;
 
         if let Some(entry) = db.get_ref(&key) {
-            match &entry.data {
-                Data::Array(list) => {
-                    let len = list.len() as i64;
-                    // Convert negative start index to positive. Say len is 5, then -1 bceomes 4
-                    // -2 becomes 3 and so on.
-                    let mut start_index = if self.start_index < 0 {
-                        len + self.start_index
-                    } else {
-                        self.start_index
-                    };
-                    // Convert negative end index to positive.
-                    let mut end_index = if self.end_index < 0 {
-                        len + self.end_index
-                    } else {
-                        self.end_index
-                    };
+            let list = entry.data.as_list()?;
+            let len = list.len() as i64;
+            // Convert negative start index to positive. Say len is 5, then -1 bceomes 4
+            // -2 becomes 3 and so on.
+            let mut start_index = if self.start_index < 0 {
+                len + self.start_index
+            } else {
+                self.start_index
+            };
+            // Convert negative end index to positive.
+            let mut end_index = if self.end_index < 0 {
+                len + self.end_index
+            } else {
+                self.end_index
+            };
 
-                    // If abs(start_index) was greater then length of the list, then it would still
-                    // be negative at this point. But the actual list starting from index 0 is
-                    // still overlapping partially with the requested list. So we bound start index
-                    // to 0.
-                    start_index = std::cmp::max(0, start_index);
-                    // If end index is greater than len of the list, then we bound it to len - 1 as
-                    // that overlaps with the requested list of greater size.
-                    end_index = std::cmp::min(len - 1, end_index);
+            // If abs(start_index) was greater then length of the list, then it would still
+            // be negative at this point. But the actual list starting from index 0 is
+            // still overlapping partially with the requested list. So we bound start index
+            // to 0.
+            start_index = std::cmp::max(0, start_index);
+            // If end index is greater than len of the list, then we bound it to len - 1 as
+            // that overlaps with the requested list of greater size.
+            end_index = std::cmp::min(len - 1, end_index);
 
-                    // The portion of the list requested is empty.
-                    if start_index > end_index || start_index >= len {
-                        conn.write_data_array(vec![].into_iter(), 0);
-                    } else {
-                        conn.write_data_array(
-                            list.range(start_index as usize..=end_index as usize),
-                            (end_index - start_index + 1) as usize,
-                        );
-                    }
-                }
-                // Data associated with the given key is not a list.
-                _ => conn.write_error_frame(WalrusError::WrongType.get_msg()),
+            // The portion of the list requested is empty.
+            if start_index > end_index || start_index >= len {
+                conn.write_data_array(vec![].into_iter(), 0);
+            } else {
+                let len = (end_index - start_index + 1) as usize;
+                conn.write_data_array_owned(list.iter().skip(start_index as usize).take(len), len);
             }
         } else {
             // No data with given key.
@@ -104,6 +91,11 @@ impl LRange {
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.list_key)
+    }
+
     /// Convert `LRange` instance to `Frame`.
     pub(crate) fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -0,0 +1,193 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    memory::{self, MemoryReport},
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// MEMORY command, reports the memory used by keys and by the server.
+///
+/// MEMORY USAGE key [SAMPLES count]
+/// MEMORY STATS
+/// MEMORY DOCTOR
+///
+/// `USAGE` replies with the approximate number of bytes used by the key, its value and the
+/// entry holding them, or null if the key doesn't exist. Redis samples the elements of large
+/// collections, `SAMPLES` is accepted for compatibility but the size of every value is tracked
+/// as it is written, so the reply is the same whatever the count.
+///
+/// `STATS` replies with a map of statistics about the keyspace and, where the allocator is
+/// known, about its allocations.
+///
+/// `DOCTOR` replies with a report of the memory issues of the server, such as fragmentation or
+/// a dataset close to `maxmemory`, and advice on each of them.
+#[derive(Debug)]
+pub enum MemoryCmd {
+    /// Memory used by a key, with the number of elements to sample, 0 for all of them.
+    Usage { key: Bytes, samples: Option<u64> },
+    /// Memory statistics of the server.
+    Stats,
+    /// Diagnosis of the memory issues of the server.
+    Doctor,
+}
+
+impl MemoryCmd {
+    /// Parse a `MemoryCmd` instance from an array frame.
+    /// The 'MEMORY' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MemoryCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"usage") {
+            let key = parse.next_bytes()?;
+            let samples = match parse.next_bytes() {
+                Ok(option) if option.eq_ignore_ascii_case(b"samples") => {
+                    let count = parse.next_int()?;
+                    if count < 0 {
+                        return Err(WalrusError::SyntaxError("ERR syntax error".into()));
+                    }
+                    Some(count as u64)
+                }
+                Ok(_) => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+                Err(ParseError::EndOfStream) => None,
+                Err(err) => return Err(err.into()),
+            };
+            Ok(MemoryCmd::Usage { key, samples })
+        } else if subcommand.eq_ignore_ascii_case(b"stats") {
+            Ok(MemoryCmd::Stats)
+        } else if subcommand.eq_ignore_ascii_case(b"doctor") {
+            Ok(MemoryCmd::Doctor)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `MemoryCmd` command.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            MemoryCmd::Usage { key, .. } => match db.memory_usage(&key) {
+                Some(size) => conn.write_data(&Data::Integer(size as i64)),
+                None => conn.write_null_frame(),
+            },
+            MemoryCmd::Stats => conn.write_frame(&stats(server)),
+            MemoryCmd::Doctor => {
+                let report = MemoryReport::sample(server).doctor(server.config.maxmemory());
+                conn.write_frame(&Frame::Bulk(Bytes::from(report)))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        match self {
+            MemoryCmd::Usage { key, .. } => std::slice::from_ref(key),
+            MemoryCmd::Stats | MemoryCmd::Doctor => &[],
+        }
+    }
+
+    /// Convert `MemoryCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("memory"));
+
+        match self {
+            MemoryCmd::Usage { key, samples } => {
+                frame.push_bulk(Bytes::from("usage"));
+                frame.push_bulk(key);
+                if let Some(samples) = samples {
+                    frame.push_bulk(Bytes::from("samples"));
+                    frame.push_int(samples as i64);
+                }
+            }
+            MemoryCmd::Stats => frame.push_bulk(Bytes::from("stats")),
+            MemoryCmd::Doctor => frame.push_bulk(Bytes::from("doctor")),
+        }
+
+        frame
+    }
+}
+
+/// Reply of `MEMORY STATS`, named like the fields of Redis. Allocator fields are left out if
+/// the allocator is unknown.
+fn stats(server: &ServerState) -> Frame {
+    let keys = server.key_count(None);
+    let report = MemoryReport::sample(server);
+    let dataset = report.dataset;
+
+    let mut fields = vec![
+        ("peak.allocated", Frame::Integer(report.peak as i64)),
+        (
+            "replication.backlog",
+            Frame::Integer(server.replication.backlog_histlen() as i64),
+        ),
+        (
+            "clients.normal",
+            Frame::Integer(server.clients.len() as i64),
+        ),
+        ("keys.count", Frame::Integer(keys as i64)),
+        (
+            "keys.bytes-per-key",
+            Frame::Integer(dataset.checked_div(keys).unwrap_or(0) as i64),
+        ),
+        ("dataset.bytes", Frame::Integer(dataset as i64)),
+    ];
+
+    if let Some(allocator) = &report.allocator {
+        fields.extend([
+            (
+                "total.allocated",
+                Frame::Integer(allocator.allocated as i64),
+            ),
+            (
+                "dataset.percentage",
+                Frame::Double(memory::percentage(dataset, allocator.allocated)),
+            ),
+            (
+                "allocator.allocated",
+                Frame::Integer(allocator.allocated as i64),
+            ),
+            ("allocator.active", Frame::Integer(allocator.active as i64)),
+            (
+                "allocator.resident",
+                Frame::Integer(allocator.resident as i64),
+            ),
+            (
+                "allocator.fragmentation.ratio",
+                Frame::Double(allocator.active as f64 / allocator.allocated.max(1) as f64),
+            ),
+            (
+                "allocator.fragmentation.bytes",
+                Frame::Integer(allocator.active as i64 - allocator.allocated as i64),
+            ),
+            (
+                "fragmentation",
+                Frame::Double(report.fragmentation_ratio().unwrap_or(0.0)),
+            ),
+            (
+                "fragmentation.bytes",
+                Frame::Integer(allocator.resident as i64 - report.used as i64),
+            ),
+        ]);
+    }
+
+    Frame::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (Frame::Bulk(Bytes::from(name)), value))
+            .collect(),
+    )
+}
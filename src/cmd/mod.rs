@@ -28,7 +28,101 @@ pub use lrange::LRange;
 mod wtype;
 pub use wtype::Type;
 
-use crate::{connection::Connection, db::Db, errors::WalrusError, frame::Frame, parse::Parse};
+mod hello;
+pub use hello::Hello;
+
+mod client;
+pub use client::ClientCmd;
+
+mod config;
+pub use config::ConfigCmd;
+
+mod command;
+pub use command::CommandCmd;
+
+mod table;
+
+mod rename;
+pub(crate) use rename::Renames;
+
+mod monitor;
+pub use monitor::Monitor;
+
+mod slowlog;
+pub use slowlog::SlowlogCmd;
+
+mod latency;
+pub use latency::LatencyCmd;
+
+#[cfg(feature = "debug-command")]
+mod debug;
+#[cfg(feature = "debug-command")]
+pub use debug::DebugCmd;
+
+mod lolwut;
+pub use lolwut::Lolwut;
+
+mod save;
+pub use save::Save;
+
+mod bgsave;
+pub use bgsave::Bgsave;
+
+mod info;
+pub use info::Info;
+
+mod sync;
+pub use sync::SyncCmd;
+
+mod replicaof;
+pub use replicaof::ReplicaOf;
+
+mod psync;
+pub use psync::Psync;
+
+mod replconf;
+pub use replconf::Replconf;
+
+mod failover;
+pub use failover::Failover;
+
+mod cluster;
+pub use cluster::{ClusterCmd, SlotState};
+
+mod asking;
+pub use asking::Asking;
+
+mod sentinel;
+pub use sentinel::SentinelCmd;
+
+mod object;
+pub use object::ObjectCmd;
+
+mod keys;
+pub use keys::Keys;
+
+mod scan;
+pub use scan::Scan;
+
+mod wmove;
+pub use wmove::Move;
+
+mod select;
+pub use select::Select;
+
+mod pttl;
+pub use pttl::PTtl;
+
+mod memory;
+pub use memory::MemoryCmd;
+
+use bytes::Bytes;
+use std::sync::Arc;
+
+use crate::{
+    connection::Connection, db::Db, errors::WalrusError, frame::Frame, parse::Parse,
+    server::ServerState,
+};
 
 pub(crate) enum Command {
     Ping(Ping),
@@ -41,19 +135,61 @@ pub(crate) enum Command {
     LLen(LLen),
     LRange(LRange),
     Type(Type),
+    Hello(Hello),
+    Client(ClientCmd),
+    Config(ConfigCmd),
+    Introspection(CommandCmd),
+    Monitor(Monitor),
+    Slowlog(SlowlogCmd),
+    Latency(LatencyCmd),
+    #[cfg(feature = "debug-command")]
+    Debug(DebugCmd),
+    Lolwut(Lolwut),
+    Save(Save),
+    Bgsave(Bgsave),
+    Info(Info),
+    Sync(SyncCmd),
+    ReplicaOf(ReplicaOf),
+    Psync(Psync),
+    Replconf(Replconf),
+    Failover(Failover),
+    Cluster(ClusterCmd),
+    Asking(Asking),
+    Sentinel(SentinelCmd),
+    Object(ObjectCmd),
+    Keys(Keys),
+    Scan(Scan),
+    Move(Move),
+    Select(Select),
+    PTtl(PTtl),
+    Memory(MemoryCmd),
     Unknown(String),
 }
 
 impl Command {
     /// Parse a command from a frame.
     /// `Frame` must be of type Frame::Array(Frame)
-    pub fn from_frame(frame: Frame) -> Result<Command, WalrusError> {
+    ///
+    /// The name of the command is looked up in `renames` first, renamed commands are only known
+    /// by their new name and disabled commands are unknown.
+    pub fn from_frame(frame: Frame, renames: &Renames) -> Result<Command, WalrusError> {
         // Convert the frame into a frame iterator using `Parse`.
         let mut parse = Parse::new(frame)?;
 
         // Command names are case insensitive, hence the given command will be compared using
         // case-insensitive comparison.
         let command_name = parse.next_bytes()?;
+        let Some(command_name) = renames.resolve(&command_name) else {
+            return Ok(Command::Unknown(
+                String::from_utf8_lossy(&command_name[..]).to_string(),
+            ));
+        };
+
+        // `DEBUG` is resolved first so it can be compiled out.
+        #[cfg(feature = "debug-command")]
+        if command_name.eq_ignore_ascii_case(b"debug") {
+            return Ok(Command::Debug(DebugCmd::parse_frames(&mut parse)?));
+        }
 
         let command = if command_name.eq_ignore_ascii_case(b"ping") {
             Command::Ping(Ping::parse_frames(&mut parse)?)
@@ -75,6 +211,58 @@ impl Command {
             Command::LRange(LRange::parse_frame(&mut parse)?)
         } else if command_name.eq_ignore_ascii_case(b"type") {
             Command::Type(Type::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"hello") {
+            Command::Hello(Hello::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"client") {
+            Command::Client(ClientCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"config") {
+            Command::Config(ConfigCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"command") {
+            Command::Introspection(CommandCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"monitor") {
+            Command::Monitor(Monitor::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"slowlog") {
+            Command::Slowlog(SlowlogCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"latency") {
+            Command::Latency(LatencyCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"lolwut") {
+            Command::Lolwut(Lolwut::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"save") {
+            Command::Save(Save::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"bgsave") {
+            Command::Bgsave(Bgsave::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"info") {
+            Command::Info(Info::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"sync") {
+            Command::Sync(SyncCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"replicaof") {
+            Command::ReplicaOf(ReplicaOf::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"psync") {
+            Command::Psync(Psync::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"replconf") {
+            Command::Replconf(Replconf::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"failover") {
+            Command::Failover(Failover::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"cluster") {
+            Command::Cluster(ClusterCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"asking") {
+            Command::Asking(Asking::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"sentinel") {
+            Command::Sentinel(SentinelCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"object") {
+            Command::Object(ObjectCmd::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"keys") {
+            Command::Keys(Keys::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"scan") {
+            Command::Scan(Scan::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"move") {
+            Command::Move(Move::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"select") {
+            Command::Select(Select::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"pttl") {
+            Command::PTtl(PTtl::parse_frames(&mut parse)?)
+        } else if command_name.eq_ignore_ascii_case(b"memory") {
+            Command::Memory(MemoryCmd::parse_frames(&mut parse)?)
         } else {
             Command::Unknown(String::from_utf8_lossy(&command_name[..]).to_string())
         };
@@ -84,9 +272,15 @@ impl Command {
 
     /// Execute the command.
     ///
-    /// The response is sent to client.
-    pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
-        match self {
+    /// The response is sent to client. A command run against a key holding the wrong kind of
+    /// value replies with a `WRONGTYPE` error, the connection stays usable.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &Arc<ServerState>,
+    ) -> Result<(), WalrusError> {
+        let res = match self {
             Command::Ping(cmd) => cmd.execute(conn).await,
             Command::Set(cmd) => cmd.execute(db, conn).await,
             Command::Get(cmd) => cmd.execute(db, conn).await,
@@ -97,10 +291,171 @@ impl Command {
             Command::LLen(cmd) => cmd.execute(db, conn).await,
             Command::LRange(cmd) => cmd.execute(db, conn).await,
             Command::Type(cmd) => cmd.execute(db, conn).await,
+            Command::Hello(cmd) => cmd.execute(conn, server).await,
+            Command::Client(cmd) => cmd.execute(db, conn, server).await,
+            Command::Config(cmd) => cmd.execute(conn, server).await,
+            Command::Introspection(cmd) => cmd.execute(conn).await,
+            Command::Monitor(cmd) => cmd.execute(conn, server).await,
+            Command::Slowlog(cmd) => cmd.execute(conn, server).await,
+            Command::Latency(cmd) => cmd.execute(conn, server).await,
+            #[cfg(feature = "debug-command")]
+            Command::Debug(cmd) => cmd.execute(db, conn, server).await,
+            Command::Lolwut(cmd) => cmd.execute(conn).await,
+            Command::Save(cmd) => cmd.execute(conn, server).await,
+            Command::Bgsave(cmd) => cmd.execute(conn, server).await,
+            Command::Info(cmd) => cmd.execute(conn, server).await,
+            Command::Sync(cmd) => cmd.execute(conn, server).await,
+            Command::ReplicaOf(cmd) => cmd.execute(conn, server).await,
+            Command::Psync(cmd) => cmd.execute(conn, server).await,
+            Command::Replconf(cmd) => cmd.execute(conn, server).await,
+            Command::Failover(cmd) => cmd.execute(conn, server).await,
+            Command::Cluster(cmd) => cmd.execute(db, conn, server).await,
+            Command::Asking(cmd) => cmd.execute(conn).await,
+            Command::Sentinel(cmd) => cmd.execute(conn, server).await,
+            Command::Object(cmd) => cmd.execute(db, conn, server).await,
+            Command::Keys(cmd) => cmd.execute(db, conn).await,
+            Command::Scan(cmd) => cmd.execute(db, conn).await,
+            Command::Move(cmd) => cmd.execute(db, conn, server).await,
+            Command::Select(cmd) => cmd.execute(conn, server).await,
+            Command::PTtl(cmd) => cmd.execute(db, conn).await,
+            Command::Memory(cmd) => cmd.execute(db, conn, server).await,
             Command::Unknown(cmd) => {
                 conn.write_error_frame(format!("unknown command {cmd}").as_str());
                 Ok(())
             }
+        };
+
+        match res {
+            Err(WalrusError::WrongType) => {
+                conn.write_error_frame(WalrusError::WrongType.get_msg());
+                Ok(())
+            }
+            res => res,
         }
     }
+
+    /// Get the name of the command.
+    pub(crate) fn get_name(&self) -> &'static str {
+        match self {
+            Command::Ping(_) => "ping",
+            Command::Set(_) => "set",
+            Command::Get(_) => "get",
+            Command::RPush(_) => "rpush",
+            Command::LPush(_) => "lpush",
+            Command::LPop(_) => "lpop",
+            Command::BLPop(_) => "blpop",
+            Command::LLen(_) => "llen",
+            Command::LRange(_) => "lrange",
+            Command::Type(_) => "type",
+            Command::Hello(_) => "hello",
+            Command::Client(_) => "client",
+            Command::Config(_) => "config",
+            Command::Introspection(_) => "command",
+            Command::Monitor(_) => "monitor",
+            Command::Slowlog(_) => "slowlog",
+            Command::Latency(_) => "latency",
+            #[cfg(feature = "debug-command")]
+            Command::Debug(_) => "debug",
+            Command::Lolwut(_) => "lolwut",
+            Command::Save(_) => "save",
+            Command::Bgsave(_) => "bgsave",
+            Command::Info(_) => "info",
+            Command::Sync(_) => "sync",
+            Command::ReplicaOf(_) => "replicaof",
+            Command::Psync(_) => "psync",
+            Command::Replconf(_) => "replconf",
+            Command::Failover(_) => "failover",
+            Command::Cluster(_) => "cluster",
+            Command::Asking(_) => "asking",
+            Command::Sentinel(_) => "sentinel",
+            Command::Object(_) => "object",
+            Command::Keys(_) => "keys",
+            Command::Scan(_) => "scan",
+            Command::Move(_) => "move",
+            Command::Select(_) => "select",
+            Command::PTtl(_) => "pttl",
+            Command::Memory(_) => "memory",
+            Command::Unknown(_) => "unknown",
+        }
+    }
+
+    /// Returns `true` if the command may modify the dataset, as flagged in the command table.
+    pub(crate) fn is_write(&self) -> bool {
+        table::lookup(self.get_name().as_bytes()).is_some_and(|spec| spec.has_flag("write"))
+    }
+
+    /// Returns `true` if the command may grow the dataset, refused once `maxmemory` is reached,
+    /// as flagged in the command table.
+    pub(crate) fn is_denyoom(&self) -> bool {
+        table::lookup(self.get_name().as_bytes()).is_some_and(|spec| spec.has_flag("denyoom"))
+    }
+
+    /// Returns `true` if the command runs in constant or logarithmic time, as flagged in the
+    /// command table.
+    pub(crate) fn is_fast(&self) -> bool {
+        table::lookup(self.get_name().as_bytes()).is_some_and(|spec| spec.has_flag("fast"))
+    }
+
+    /// Returns `true` if the command may block waiting for data, as flagged in the command table.
+    pub(crate) fn is_blocking(&self) -> bool {
+        table::lookup(self.get_name().as_bytes()).is_some_and(|spec| spec.has_flag("blocking"))
+    }
+
+    /// Returns `true` if the command is served while the dataset is loading, as flagged in the
+    /// command table.
+    pub(crate) fn is_ok_loading(&self) -> bool {
+        table::lookup(self.get_name().as_bytes()).is_some_and(|spec| spec.has_flag("loading"))
+    }
+
+    /// Keys accessed by the command, empty for commands that don't access the dataset.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        match self {
+            Command::Set(cmd) => cmd.keys(),
+            Command::Get(cmd) => cmd.keys(),
+            Command::RPush(cmd) => cmd.keys(),
+            Command::LPush(cmd) => cmd.keys(),
+            Command::LPop(cmd) => cmd.keys(),
+            Command::BLPop(cmd) => cmd.keys(),
+            Command::LLen(cmd) => cmd.keys(),
+            Command::PTtl(cmd) => cmd.keys(),
+            Command::LRange(cmd) => cmd.keys(),
+            Command::Type(cmd) => cmd.keys(),
+            Command::Move(cmd) => cmd.keys(),
+            Command::Object(cmd) => cmd.keys(),
+            Command::Memory(cmd) => cmd.keys(),
+            Command::Ping(_)
+            | Command::Hello(_)
+            | Command::Client(_)
+            | Command::Config(_)
+            | Command::Introspection(_)
+            | Command::Monitor(_)
+            | Command::Save(_)
+            | Command::Bgsave(_)
+            | Command::Info(_)
+            | Command::Slowlog(_)
+            | Command::Latency(_)
+            | Command::Lolwut(_)
+            | Command::Cluster(_)
+            | Command::Failover(_)
+            | Command::Asking(_)
+            | Command::Sentinel(_)
+            | Command::Keys(_)
+            | Command::Scan(_)
+            | Command::Select(_)
+            | Command::Psync(_)
+            | Command::Replconf(_)
+            | Command::Sync(_)
+            | Command::ReplicaOf(_)
+            | Command::Unknown(_) => &[],
+            #[cfg(feature = "debug-command")]
+            Command::Debug(_) => &[],
+        }
+    }
+}
+
+/// Returns `true` if the command `name` can be sent again when its reply was lost with the
+/// connection: it neither modifies the dataset nor administers the server, as flagged in the
+/// command table.
+pub(crate) fn is_idempotent(name: &[u8]) -> bool {
+    table::lookup(name).is_some_and(|spec| !spec.has_flag("write") && !spec.has_flag("admin"))
 }
@@ -0,0 +1,50 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse, server::ServerState,
+};
+
+/// MONITOR command, streams every command processed by the server to the connection.
+///
+/// MONITOR
+///
+/// Replies with `OK`, then each command received from other connections is sent as a simple
+/// string with the time, database, client address and arguments of the command.
+#[derive(Debug, Default)]
+pub struct Monitor;
+
+impl Monitor {
+    /// Creates a new `MONITOR` command.
+    pub fn new() -> Monitor {
+        Monitor
+    }
+
+    /// Parse a `Monitor` instance from an array frame.
+    /// The 'MONITOR' string is already consumed.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Monitor, WalrusError> {
+        Ok(Monitor)
+    }
+
+    /// Execute the `Monitor` command, switching the connection to monitor mode.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        // The connection is registered for as long as its handler runs.
+        if let Some(sender) = server.clients.push_sender(conn.id()) {
+            server.monitors.add(conn.id(), sender);
+        }
+
+        conn.write_data(&Data::String(Bytes::from("OK")));
+
+        Ok(())
+    }
+
+    /// Convert `Monitor` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("monitor"));
+        frame
+    }
+}
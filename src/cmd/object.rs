@@ -0,0 +1,110 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    parse::Parse,
+    server::ServerState,
+};
+
+/// OBJECT command, inspects the internals of the value of a key.
+///
+/// OBJECT ENCODING key
+/// OBJECT FREQ key
+///
+/// `ENCODING` replies with how the value is stored: `listpack` for small lists, `vecdeque` for
+/// lists grown beyond `list-max-listpack-entries` or `list-max-listpack-value`, `int` for
+/// integers, `embstr` for strings short enough to be stored inline and `raw` for others.
+///
+/// `FREQ` replies with the approximate access frequency of the key, counted logarithmically
+/// from 0 to 255 and decayed over time. It is only available with an LFU `maxmemory-policy`,
+/// as with Redis. Inspecting a key doesn't count as an access.
+#[derive(Debug)]
+pub enum ObjectCmd {
+    /// Encoding of the value of the key.
+    Encoding(Bytes),
+    /// Access frequency of the key.
+    Freq(Bytes),
+}
+
+impl ObjectCmd {
+    /// Parse an `ObjectCmd` instance from an array frame.
+    /// The 'OBJECT' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ObjectCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"encoding") {
+            Ok(ObjectCmd::Encoding(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"freq") {
+            Ok(ObjectCmd::Freq(parse.next_bytes()?))
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `ObjectCmd` command.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            ObjectCmd::Encoding(key) => match db.inspect(&key) {
+                Some(entry) => {
+                    conn.write_data(&Data::Bytes(Bytes::from(entry.data.encoding())));
+                }
+                None => conn.write_null_frame(),
+            },
+            ObjectCmd::Freq(key) => {
+                if !server.config.maxmemory_policy().is_lfu() {
+                    conn.write_error_frame(
+                        "ERR An LFU maxmemory policy is not selected, access frequency not tracked.",
+                    );
+                    return Ok(());
+                }
+
+                match db.inspect(&key) {
+                    Some(entry) => {
+                        let frequency = db.frequency(&entry);
+                        conn.write_data(&Data::Integer(frequency as i64));
+                    }
+                    None => conn.write_null_frame(),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        match self {
+            ObjectCmd::Encoding(key) | ObjectCmd::Freq(key) => std::slice::from_ref(key),
+        }
+    }
+
+    /// Convert `ObjectCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("object"));
+
+        match self {
+            ObjectCmd::Encoding(key) => {
+                frame.push_bulk(Bytes::from("encoding"));
+                frame.push_bulk(key);
+            }
+            ObjectCmd::Freq(key) => {
+                frame.push_bulk(Bytes::from("freq"));
+                frame.push_bulk(key);
+            }
+        }
+
+        frame
+    }
+}
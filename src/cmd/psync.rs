@@ -0,0 +1,139 @@
+use bytes::Bytes;
+use tracing::info;
+
+use crate::{
+    Connection,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    replication::SyncReply,
+    server::ServerState,
+};
+
+/// PSYNC command, used by replicas to resume the stream of writes of their master.
+///
+/// PSYNC replicationid offset [FAILOVER]
+///
+/// A replica that followed the stream `replicationid` up to `offset - 1` resumes with
+/// `+CONTINUE <replicationid>` followed by the commands it missed, if they are still in the
+/// backlog. Otherwise the reply is `+FULLRESYNC <replicationid> <offset>` followed by a
+/// snapshot of the dataset as a bulk string. Every write command executed by the server is
+/// streamed to the connection afterwards. `PSYNC ? -1` always requests a snapshot.
+///
+/// With `FAILOVER`, sent by the master of the server while failing over to it, the server
+/// stops replicating and becomes the master of the connection.
+#[derive(Debug)]
+pub struct Psync {
+    replid: String,
+    offset: i64,
+    failover: bool,
+}
+
+impl Psync {
+    /// Creates a new `PSYNC` command resuming the stream `replid` from `offset`.
+    pub fn new(replid: impl ToString, offset: i64) -> Psync {
+        Psync {
+            replid: replid.to_string(),
+            offset,
+            failover: false,
+        }
+    }
+
+    /// Ask the server to take over as the master of the connection.
+    pub fn failover(mut self) -> Psync {
+        self.failover = true;
+        self
+    }
+
+    /// Parse a `Psync` instance from an array frame.
+    /// The 'PSYNC' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Psync, WalrusError> {
+        let replid = String::from_utf8_lossy(&parse.next_bytes()?).into_owned();
+        let offset = parse.next_int()?;
+
+        let failover = match parse.next_bytes() {
+            Ok(option) if option.eq_ignore_ascii_case(b"failover") => true,
+            Ok(_) => return Err(WalrusError::SyntaxError("ERR syntax error".into())),
+            Err(ParseError::EndOfStream) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Psync {
+            replid,
+            offset,
+            failover,
+        })
+    }
+
+    /// Execute the `Psync` command, switching the connection to a replica link.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        if self.failover {
+            if !server.replication.is_replica() {
+                conn.write_error_frame("ERR PSYNC FAILOVER can't be sent to a master.");
+                return Ok(());
+            }
+            if self.replid != server.replication.replid() {
+                conn.write_error_frame("ERR PSYNC FAILOVER replid must match my replid.");
+                return Ok(());
+            }
+
+            // The master applied every write command and handed over, the stream goes on from
+            // here.
+            server.replication.stop(server);
+            info!("Failover requested by the master, promoted to master");
+        }
+
+        // The connection is registered for as long as its handler runs.
+        let Some(sender) = server.clients.push_sender(conn.id()) else {
+            return Ok(());
+        };
+        let ip = conn.peer_addr()?.ip();
+
+        let resume = match u64::try_from(self.offset) {
+            Ok(offset) if self.replid != "?" => Some((self.replid.as_str(), offset)),
+            _ => None,
+        };
+
+        match server
+            .replication
+            .add_replica(conn.id(), ip, sender, resume, server)
+            .await
+        {
+            Ok(SyncReply::Continue { replid, frames }) => {
+                conn.write_frame(&Frame::Simple(Bytes::from(format!("CONTINUE {replid}"))));
+                for frame in &frames {
+                    conn.write_frame(frame);
+                }
+            }
+            Ok(SyncReply::FullResync {
+                replid,
+                offset,
+                snapshot,
+            }) => {
+                conn.write_frame(&Frame::Simple(Bytes::from(format!(
+                    "FULLRESYNC {replid} {offset}"
+                ))));
+                conn.write_frame(&Frame::Bulk(Bytes::from(snapshot)));
+            }
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Psync` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("psync"));
+        frame.push_bulk(Bytes::from(self.replid));
+        frame.push_bulk(Bytes::from(self.offset.to_string()));
+        if self.failover {
+            frame.push_bulk(Bytes::from("failover"));
+        }
+        frame
+    }
+}
@@ -0,0 +1,58 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    parse::Parse,
+};
+
+/// `PTtl` command to get the time left before a key expires, in milliseconds.
+pub struct PTtl {
+    key: Bytes,
+}
+
+impl PTtl {
+    /// Returns a `PTtl` instance.
+    pub fn new(key: Bytes) -> PTtl {
+        PTtl { key }
+    }
+
+    /// Parse a `PTtl` instance from an array frame.
+    /// The 'PTTL' String is already consumed.
+    ///
+    /// Expects an array containing 2 entries.
+    /// PTTL key
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PTtl, WalrusError> {
+        let key = parse.next_bytes()?;
+        Ok(PTtl { key })
+    }
+
+    /// Execute the `PTtl` command, the time to live of the key is written to `conn`. Like
+    /// Redis, `-1` is written if the key never expires and `-2` if it doesn't exist.
+    pub(crate) async fn execute(&self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
+        let ttl = match db.ttl(&self.key) {
+            Some(Some(ttl)) => ttl.as_millis() as i64,
+            Some(None) => -1,
+            None => -2,
+        };
+        conn.write_data(&Data::Integer(ttl));
+
+        Ok(())
+    }
+
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.key)
+    }
+
+    /// Convert `PTtl` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("pttl"));
+        frame.push_bulk(self.key);
+
+        frame
+    }
+}
@@ -0,0 +1,61 @@
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+
+use super::table;
+
+/// Commands renamed or disabled when the server starts, such as `rename-command` in the
+/// configuration file. Renamed commands are only known by their new name, so clients unaware of
+/// it can't run dangerous commands like `DEBUG` or `CONFIG`.
+#[derive(Debug, Default)]
+pub(crate) struct Renames {
+    /// Original name of each renamed command, by lowercase new name.
+    renamed: HashMap<String, &'static str>,
+    /// Commands renamed or disabled, by lowercase original name.
+    hidden: HashSet<&'static str>,
+}
+
+impl Renames {
+    /// Rename the command `name` to `new_name`, or disable it if `new_name` is empty.
+    ///
+    /// Fails if `name` is not a command, was already renamed, or if `new_name` is the name of a
+    /// command.
+    pub(crate) fn add(&mut self, name: &str, new_name: &str) -> Result<(), String> {
+        let original = match table::lookup(name.as_bytes()) {
+            Some(spec) => spec.name,
+            None if name.eq_ignore_ascii_case("debug") => "debug",
+            None => return Err(format!("unknown command '{name}' to rename")),
+        };
+        if self.hidden.contains(original) {
+            return Err(format!("command '{name}' is renamed more than once"));
+        }
+
+        let new_name = new_name.to_ascii_lowercase();
+        if !new_name.is_empty() {
+            if new_name == "debug"
+                || table::lookup(new_name.as_bytes()).is_some()
+                || self.renamed.contains_key(&new_name)
+            {
+                return Err(format!(
+                    "command '{name}' can't be renamed to '{new_name}', the name is taken"
+                ));
+            }
+            self.renamed.insert(new_name, original);
+        }
+        self.hidden.insert(original);
+        Ok(())
+    }
+
+    /// Name of the command a client sends as `name`, `None` if the command is disabled or only
+    /// known by another name.
+    pub(crate) fn resolve(&self, name: &Bytes) -> Option<Bytes> {
+        if self.renamed.is_empty() && self.hidden.is_empty() {
+            return Some(name.clone());
+        }
+
+        let lowercase = String::from_utf8_lossy(name).to_ascii_lowercase();
+        if let Some(original) = self.renamed.get(&lowercase) {
+            return Some(Bytes::from_static(original.as_bytes()));
+        }
+        (!self.hidden.contains(lowercase.as_str())).then(|| name.clone())
+    }
+}
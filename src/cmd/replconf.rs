@@ -0,0 +1,105 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// REPLCONF command, used by replicas to configure their link with the master.
+///
+/// REPLCONF listening-port port | ACK offset
+///
+/// `listening-port` announces the port the replica accepts connections on, sent before
+/// `PSYNC`. `ACK` reports the offset of the stream the replica applied and gets no reply.
+#[derive(Debug)]
+pub struct Replconf {
+    option: ReplconfOption,
+}
+
+#[derive(Debug)]
+enum ReplconfOption {
+    ListeningPort(u16),
+    Ack(u64),
+}
+
+impl Replconf {
+    /// Creates a new `REPLCONF listening-port` command announcing `port`.
+    pub fn listening_port(port: u16) -> Replconf {
+        Replconf {
+            option: ReplconfOption::ListeningPort(port),
+        }
+    }
+
+    /// Creates a new `REPLCONF ACK` command acknowledging `offset`.
+    pub fn ack(offset: u64) -> Replconf {
+        Replconf {
+            option: ReplconfOption::Ack(offset),
+        }
+    }
+
+    /// Parse a `Replconf` instance from an array frame.
+    /// The 'REPLCONF' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Replconf, WalrusError> {
+        let option = parse.next_bytes()?;
+
+        let option = if option.eq_ignore_ascii_case(b"listening-port") {
+            let port = u16::try_from(parse.next_int()?)
+                .map_err(|_| WalrusError::SyntaxError("ERR Invalid listening port".into()))?;
+            ReplconfOption::ListeningPort(port)
+        } else if option.eq_ignore_ascii_case(b"ack") {
+            let offset = u64::try_from(parse.next_int()?)
+                .map_err(|_| WalrusError::SyntaxError("ERR Invalid offset".into()))?;
+            ReplconfOption::Ack(offset)
+        } else {
+            return Err(WalrusError::SyntaxError(format!(
+                "ERR Unrecognized REPLCONF option: {}",
+                String::from_utf8_lossy(&option)
+            )));
+        };
+
+        match parse.next_bytes() {
+            Err(ParseError::EndOfStream) => Ok(Replconf { option }),
+            Ok(_) => Err(WalrusError::SyntaxError("ERR syntax error".into())),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Execute the `Replconf` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self.option {
+            ReplconfOption::ListeningPort(port) => {
+                server.replication.announce_port(conn.id(), port);
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            // The master link only carries write commands to the replica, acks get no reply.
+            ReplconfOption::Ack(offset) => server.replication.ack(conn.id(), offset),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Replconf` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("replconf"));
+        match self.option {
+            ReplconfOption::ListeningPort(port) => {
+                frame.push_bulk(Bytes::from("listening-port"));
+                frame.push_bulk(Bytes::from(port.to_string()));
+            }
+            ReplconfOption::Ack(offset) => {
+                frame.push_bulk(Bytes::from("ack"));
+                frame.push_bulk(Bytes::from(offset.to_string()));
+            }
+        }
+        frame
+    }
+}
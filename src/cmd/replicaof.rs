@@ -0,0 +1,99 @@
+use bytes::Bytes;
+use std::sync::Arc;
+
+use crate::{
+    Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse, server::ServerState,
+};
+
+/// REPLICAOF command, makes the server a replica of another server or a master again.
+///
+/// REPLICAOF host port | NO ONE
+///
+/// A replica replaces its dataset with the one of its master, then applies every write
+/// command of the master. `REPLICAOF NO ONE` stops replicating, keeping the dataset.
+#[derive(Debug)]
+pub struct ReplicaOf {
+    /// Address of the master, `None` for `NO ONE`.
+    master: Option<(Bytes, Bytes)>,
+}
+
+impl ReplicaOf {
+    /// Creates a new `REPLICAOF` command replicating `host:port`.
+    pub fn new(host: Bytes, port: u16) -> ReplicaOf {
+        ReplicaOf {
+            master: Some((host, Bytes::from(port.to_string()))),
+        }
+    }
+
+    /// Creates a new `REPLICAOF NO ONE` command.
+    pub fn no_one() -> ReplicaOf {
+        ReplicaOf { master: None }
+    }
+
+    /// Parse a `ReplicaOf` instance from an array frame.
+    /// The 'REPLICAOF' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplicaOf, WalrusError> {
+        let host = parse.next_bytes()?;
+        let port = parse.next_bytes()?;
+
+        if host.eq_ignore_ascii_case(b"no") && port.eq_ignore_ascii_case(b"one") {
+            return Ok(ReplicaOf::no_one());
+        }
+
+        Ok(ReplicaOf {
+            master: Some((host, port)),
+        })
+    }
+
+    /// Execute the `ReplicaOf` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &Arc<ServerState>,
+    ) -> Result<(), WalrusError> {
+        // The failover changes the role of the server itself.
+        if server.replication.is_failing_over() {
+            conn.write_error_frame("ERR REPLICAOF not allowed while failing over.");
+            return Ok(());
+        }
+
+        match self.master {
+            Some((host, port)) => {
+                let Some(port) = std::str::from_utf8(&port)
+                    .ok()
+                    .and_then(|port| port.parse::<u16>().ok())
+                else {
+                    conn.write_error_frame("ERR Invalid master port");
+                    return Ok(());
+                };
+
+                let host = String::from_utf8_lossy(&host).into_owned();
+                server.replication.replicate(host, port, server);
+            }
+            None => server.replication.stop(server),
+        }
+
+        conn.write_data(&Data::String(Bytes::from("OK")));
+
+        Ok(())
+    }
+
+    /// Convert `ReplicaOf` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("replicaof"));
+
+        match self.master {
+            Some((host, port)) => {
+                frame.push_bulk(host);
+                frame.push_bulk(port);
+            }
+            None => {
+                frame.push_bulk(Bytes::from("no"));
+                frame.push_bulk(Bytes::from("one"));
+            }
+        }
+
+        frame
+    }
+}
@@ -0,0 +1,54 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse, server::ServerState,
+};
+
+/// SAVE command, writes a snapshot of the dataset to disk.
+///
+/// SAVE
+///
+/// The snapshot is written to `dbfilename` in `dir` and loaded when the server starts. The
+/// connection waits until the snapshot is on disk.
+#[derive(Debug, Default)]
+pub struct Save;
+
+impl Save {
+    /// Creates a new `SAVE` command.
+    pub fn new() -> Save {
+        Save
+    }
+
+    /// Parse a `Save` instance from an array frame.
+    /// The 'SAVE' string is already consumed.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Save, WalrusError> {
+        Ok(Save)
+    }
+
+    /// Execute the `Save` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        let config = &server.config;
+
+        match server.persistence.save(
+            &server.databases,
+            &config.snapshot_path(),
+            config.snapshot_compression(),
+        ) {
+            Ok(()) => conn.write_data(&Data::String(Bytes::from("OK"))),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Save` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("save"));
+        frame
+    }
+}
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::{Db, Kind},
+    errors::WalrusError,
+    frame::Frame,
+    glob::glob_match,
+    parse::{Parse, ParseError},
+};
+
+/// Number of keys returned by `SCAN` if no `COUNT` is given.
+const DEFAULT_COUNT: usize = 10;
+
+/// SCAN command, visits the keyspace incrementally.
+///
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+///
+/// Replies with the cursor to continue from, 0 once every key was visited, and the keys
+/// visited. Keys existing during the whole iteration are returned at least once, while other
+/// connections write keys. Keys may be returned more than once, keys deleted before a call are
+/// never returned by it. `MATCH` and `TYPE` filter the keys visited, a call may return none of
+/// them while the iteration isn't complete. `COUNT` is a hint, more keys may be returned.
+#[derive(Debug)]
+pub struct Scan {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: Option<usize>,
+    kind: Option<Kind>,
+}
+
+impl Scan {
+    /// Create a new `Scan` command continuing from `cursor`, returning keys matching
+    /// `pattern` if given, about `count` keys at a time.
+    pub fn new(cursor: u64, pattern: Option<Bytes>, count: Option<usize>) -> Scan {
+        Scan {
+            cursor,
+            pattern,
+            count,
+            kind: None,
+        }
+    }
+
+    /// Parse a `Scan` instance from an array frame.
+    /// The 'SCAN' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Scan, WalrusError> {
+        let cursor = std::str::from_utf8(&parse.next_bytes()?)
+            .ok()
+            .and_then(|cursor| cursor.parse().ok())
+            .ok_or("ERR invalid cursor")?;
+        let mut scan = Scan::new(cursor, None, None);
+
+        loop {
+            let option = match parse.next_bytes() {
+                Ok(option) => option,
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            if option.eq_ignore_ascii_case(b"match") {
+                scan.pattern = Some(parse.next_bytes()?);
+            } else if option.eq_ignore_ascii_case(b"count") {
+                let count = parse.next_int()?;
+                if count < 1 {
+                    return Err("ERR syntax error".into());
+                }
+                scan.count = Some(count as usize);
+            } else if option.eq_ignore_ascii_case(b"type") {
+                let name = parse.next_bytes()?;
+                scan.kind = Some(Kind::from_name(&name).ok_or_else(|| {
+                    format!("ERR unknown type name '{}'", String::from_utf8_lossy(&name))
+                })?);
+            } else {
+                return Err("ERR syntax error".into());
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Execute the `Scan` command, writing the next cursor and the keys visited to `conn`.
+    pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
+        let (cursor, keys) = db.scan(self.cursor, self.count.unwrap_or(DEFAULT_COUNT));
+
+        let mut matched = Vec::new();
+        for key in keys {
+            if let Some(pattern) = &self.pattern
+                && !glob_match(pattern, &key, false)
+            {
+                continue;
+            }
+
+            // Keys removed since they were visited are skipped.
+            if let Some(kind) = self.kind
+                && db
+                    .inspect(&key)
+                    .is_none_or(|entry| entry.data.kind() != kind)
+            {
+                continue;
+            }
+
+            matched.push(Frame::Bulk(key));
+        }
+
+        conn.write_frame(&Frame::Array(vec![
+            Frame::Bulk(Bytes::from(cursor.to_string())),
+            Frame::Array(matched),
+        ]));
+
+        Ok(())
+    }
+
+    /// Convert `Scan` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("scan"));
+        frame.push_bulk(Bytes::from(self.cursor.to_string()));
+
+        if let Some(pattern) = self.pattern {
+            frame.push_bulk(Bytes::from("match"));
+            frame.push_bulk(pattern);
+        }
+        if let Some(count) = self.count {
+            frame.push_bulk(Bytes::from("count"));
+            frame.push_int(count as i64);
+        }
+        if let Some(kind) = self.kind {
+            frame.push_bulk(Bytes::from("type"));
+            frame.push_bulk(Bytes::from(kind.name()));
+        }
+
+        frame
+    }
+}
@@ -0,0 +1,65 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection, db::Data, errors::WalrusError, frame::Frame, parse::Parse, server::ServerState,
+};
+
+/// SELECT command, switches the connection to another logical database.
+///
+/// SELECT index
+///
+/// Every connection starts on database 0, the number of databases is set by `databases`. In
+/// cluster mode only database 0 exists.
+#[derive(Debug)]
+pub struct Select {
+    index: i64,
+}
+
+impl Select {
+    /// Creates a new `SELECT` command for the database `index`.
+    pub fn new(index: i64) -> Select {
+        Select { index }
+    }
+
+    /// Parse a `Select` instance from an array frame.
+    /// The 'SELECT' string is already consumed.
+    ///
+    /// Expects an array containing 2 entries.
+    /// SELECT index
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Select, WalrusError> {
+        let index = parse.next_int()?;
+        Ok(Select { index })
+    }
+
+    /// Execute the `Select` command, the following commands of `conn` run against the
+    /// database selected.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        if server.cluster.is_some() && self.index != 0 {
+            conn.write_error_frame("ERR SELECT is not allowed in cluster mode");
+            return Ok(());
+        }
+
+        match usize::try_from(self.index) {
+            Ok(index) if index < server.databases.len() => {
+                conn.select(index);
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+            _ => conn.write_error_frame("ERR DB index is out of range"),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `Select` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("select"));
+        frame.push_int(self.index);
+
+        frame
+    }
+}
@@ -0,0 +1,346 @@
+use bytes::Bytes;
+use std::sync::Arc;
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    sentinel::Hello,
+    server::ServerState,
+};
+
+/// SENTINEL command, manages the masters monitored by a server in sentinel mode.
+///
+/// SENTINEL MONITOR name ip port quorum
+/// SENTINEL REMOVE name
+/// SENTINEL SET name option value [option value ...]
+/// SENTINEL MASTERS | MASTER name | REPLICAS name | SENTINELS name
+/// SENTINEL GET-MASTER-ADDR-BY-NAME name
+/// SENTINEL MYID
+///
+/// The options set are `down-after-milliseconds`, `failover-timeout` and `quorum`.
+///
+/// Sentinels also talk to each other with two subcommands. `IS-MASTER-DOWN-BY-ADDR ip port
+/// epoch runid` asks whether a master is down, and for a vote to lead its failover unless
+/// `runid` is `*`. `HELLO port runid current-epoch master-name master-ip master-port
+/// master-config-epoch` announces a sentinel and its configuration of a master.
+#[derive(Debug)]
+pub enum SentinelCmd {
+    /// Start monitoring the master at the address.
+    Monitor {
+        name: Bytes,
+        host: Bytes,
+        port: u16,
+        quorum: i64,
+    },
+    /// Stop monitoring the master.
+    Remove(Bytes),
+    /// Change options of the master.
+    Set {
+        name: Bytes,
+        options: Vec<(Bytes, Bytes)>,
+    },
+    /// Description of every master.
+    Masters,
+    /// Description of the master.
+    Master(Bytes),
+    /// Description of the replicas of the master.
+    Replicas(Bytes),
+    /// Description of the other sentinels monitoring the master.
+    Sentinels(Bytes),
+    /// Address of the master.
+    GetMasterAddrByName(Bytes),
+    /// Whether the master at the address is down, and a vote request.
+    IsMasterDownByAddr {
+        host: Bytes,
+        port: u16,
+        epoch: u64,
+        runid: Bytes,
+    },
+    /// Announce of a sentinel and its configuration of a master.
+    Hello {
+        port: u16,
+        runid: Bytes,
+        current_epoch: u64,
+        master_name: Bytes,
+        master_host: Bytes,
+        master_port: u16,
+        master_config_epoch: u64,
+    },
+    /// Run id of the sentinel.
+    MyId,
+}
+
+impl SentinelCmd {
+    /// Parse a `SentinelCmd` instance from an array frame.
+    /// The 'SENTINEL' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SentinelCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"monitor") {
+            Ok(SentinelCmd::Monitor {
+                name: parse.next_bytes()?,
+                host: parse.next_bytes()?,
+                port: parse_port(parse.next_int()?)?,
+                quorum: parse.next_int()?,
+            })
+        } else if subcommand.eq_ignore_ascii_case(b"remove") {
+            Ok(SentinelCmd::Remove(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"set") {
+            let name = parse.next_bytes()?;
+            let mut options = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+
+            loop {
+                match parse.next_bytes() {
+                    Ok(option) => options.push((option, parse.next_bytes()?)),
+                    Err(ParseError::EndOfStream) => break,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            Ok(SentinelCmd::Set { name, options })
+        } else if subcommand.eq_ignore_ascii_case(b"masters") {
+            Ok(SentinelCmd::Masters)
+        } else if subcommand.eq_ignore_ascii_case(b"master") {
+            Ok(SentinelCmd::Master(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"replicas")
+            || subcommand.eq_ignore_ascii_case(b"slaves")
+        {
+            Ok(SentinelCmd::Replicas(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"sentinels") {
+            Ok(SentinelCmd::Sentinels(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"get-master-addr-by-name") {
+            Ok(SentinelCmd::GetMasterAddrByName(parse.next_bytes()?))
+        } else if subcommand.eq_ignore_ascii_case(b"is-master-down-by-addr") {
+            Ok(SentinelCmd::IsMasterDownByAddr {
+                host: parse.next_bytes()?,
+                port: parse_port(parse.next_int()?)?,
+                epoch: parse_epoch(parse.next_int()?)?,
+                runid: parse.next_bytes()?,
+            })
+        } else if subcommand.eq_ignore_ascii_case(b"hello") {
+            Ok(SentinelCmd::Hello {
+                port: parse_port(parse.next_int()?)?,
+                runid: parse.next_bytes()?,
+                current_epoch: parse_epoch(parse.next_int()?)?,
+                master_name: parse.next_bytes()?,
+                master_host: parse.next_bytes()?,
+                master_port: parse_port(parse.next_int()?)?,
+                master_config_epoch: parse_epoch(parse.next_int()?)?,
+            })
+        } else if subcommand.eq_ignore_ascii_case(b"myid") {
+            Ok(SentinelCmd::MyId)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `SentinelCmd` command.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &Arc<ServerState>,
+    ) -> Result<(), WalrusError> {
+        let Some(sentinel) = &server.sentinel else {
+            conn.write_error_frame("ERR This instance is not running in sentinel mode");
+            return Ok(());
+        };
+
+        let result = match self {
+            SentinelCmd::Monitor {
+                name,
+                host,
+                port,
+                quorum,
+            } => sentinel.monitor(
+                String::from_utf8_lossy(&name).into_owned(),
+                String::from_utf8_lossy(&host).into_owned(),
+                port,
+                u64::try_from(quorum).unwrap_or(0),
+                server,
+            ),
+            SentinelCmd::Remove(name) => {
+                if sentinel.remove(&name) {
+                    Ok(())
+                } else {
+                    Err("No such master with that name".to_string())
+                }
+            }
+            SentinelCmd::Set { name, options } => options
+                .iter()
+                .try_for_each(|(option, value)| sentinel.set(&name, option, value)),
+            SentinelCmd::Masters => {
+                conn.write_frame(&sentinel.masters());
+                return Ok(());
+            }
+            SentinelCmd::Master(name) => {
+                write_or_no_such_master(conn, sentinel.master(&name));
+                return Ok(());
+            }
+            SentinelCmd::Replicas(name) => {
+                write_or_no_such_master(conn, sentinel.replicas(&name));
+                return Ok(());
+            }
+            SentinelCmd::Sentinels(name) => {
+                write_or_no_such_master(conn, sentinel.sentinels(&name));
+                return Ok(());
+            }
+            SentinelCmd::GetMasterAddrByName(name) => {
+                conn.write_frame(&sentinel.master_addr(&name));
+                return Ok(());
+            }
+            SentinelCmd::IsMasterDownByAddr {
+                host,
+                port,
+                epoch,
+                runid,
+            } => {
+                conn.write_frame(&sentinel.is_master_down(
+                    &String::from_utf8_lossy(&host),
+                    port,
+                    epoch,
+                    &String::from_utf8_lossy(&runid),
+                ));
+                return Ok(());
+            }
+            SentinelCmd::Hello {
+                port,
+                runid,
+                current_epoch,
+                master_name,
+                master_host,
+                master_port,
+                master_config_epoch,
+            } => {
+                // The sentinel is reached on the address it connected from.
+                let ip = conn.peer_addr()?.ip();
+
+                sentinel.hello(Hello {
+                    ip,
+                    port,
+                    runid: String::from_utf8_lossy(&runid).into_owned(),
+                    current_epoch,
+                    master_name: String::from_utf8_lossy(&master_name).into_owned(),
+                    master_host: String::from_utf8_lossy(&master_host).into_owned(),
+                    master_port,
+                    master_config_epoch,
+                });
+                Ok(())
+            }
+            SentinelCmd::MyId => {
+                conn.write_frame(&Frame::Bulk(Bytes::from(sentinel.myid().to_string())));
+                return Ok(());
+            }
+        };
+
+        match result {
+            Ok(()) => conn.write_data(&Data::String(Bytes::from("OK"))),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+
+    /// Convert `SentinelCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("sentinel"));
+
+        match self {
+            SentinelCmd::Monitor {
+                name,
+                host,
+                port,
+                quorum,
+            } => {
+                frame.push_bulk(Bytes::from("monitor"));
+                frame.push_bulk(name);
+                frame.push_bulk(host);
+                frame.push_bulk(Bytes::from(port.to_string()));
+                frame.push_bulk(Bytes::from(quorum.to_string()));
+            }
+            SentinelCmd::Remove(name) => {
+                frame.push_bulk(Bytes::from("remove"));
+                frame.push_bulk(name);
+            }
+            SentinelCmd::Set { name, options } => {
+                frame.push_bulk(Bytes::from("set"));
+                frame.push_bulk(name);
+                for (option, value) in options {
+                    frame.push_bulk(option);
+                    frame.push_bulk(value);
+                }
+            }
+            SentinelCmd::Masters => frame.push_bulk(Bytes::from("masters")),
+            SentinelCmd::Master(name) => {
+                frame.push_bulk(Bytes::from("master"));
+                frame.push_bulk(name);
+            }
+            SentinelCmd::Replicas(name) => {
+                frame.push_bulk(Bytes::from("replicas"));
+                frame.push_bulk(name);
+            }
+            SentinelCmd::Sentinels(name) => {
+                frame.push_bulk(Bytes::from("sentinels"));
+                frame.push_bulk(name);
+            }
+            SentinelCmd::GetMasterAddrByName(name) => {
+                frame.push_bulk(Bytes::from("get-master-addr-by-name"));
+                frame.push_bulk(name);
+            }
+            SentinelCmd::IsMasterDownByAddr {
+                host,
+                port,
+                epoch,
+                runid,
+            } => {
+                frame.push_bulk(Bytes::from("is-master-down-by-addr"));
+                frame.push_bulk(host);
+                frame.push_bulk(Bytes::from(port.to_string()));
+                frame.push_bulk(Bytes::from(epoch.to_string()));
+                frame.push_bulk(runid);
+            }
+            SentinelCmd::Hello {
+                port,
+                runid,
+                current_epoch,
+                master_name,
+                master_host,
+                master_port,
+                master_config_epoch,
+            } => {
+                frame.push_bulk(Bytes::from("hello"));
+                frame.push_bulk(Bytes::from(port.to_string()));
+                frame.push_bulk(runid);
+                frame.push_bulk(Bytes::from(current_epoch.to_string()));
+                frame.push_bulk(master_name);
+                frame.push_bulk(master_host);
+                frame.push_bulk(Bytes::from(master_port.to_string()));
+                frame.push_bulk(Bytes::from(master_config_epoch.to_string()));
+            }
+            SentinelCmd::MyId => frame.push_bulk(Bytes::from("myid")),
+        }
+
+        frame
+    }
+}
+
+fn write_or_no_such_master(conn: &mut Connection, reply: Option<Frame>) {
+    match reply {
+        Some(reply) => conn.write_frame(&reply),
+        None => conn.write_error_frame("ERR No such master with that name"),
+    }
+}
+
+fn parse_port(port: i64) -> Result<u16, WalrusError> {
+    u16::try_from(port).map_err(|_| WalrusError::SyntaxError("ERR Invalid port".into()))
+}
+
+fn parse_epoch(epoch: i64) -> Result<u64, WalrusError> {
+    u64::try_from(epoch).map_err(|_| WalrusError::SyntaxError("ERR Invalid epoch".into()))
+}
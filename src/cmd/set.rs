@@ -7,22 +7,42 @@ use crate::{
     frame::Frame,
     parse::{Parse, ParseError},
 };
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Set a value for a key.
 ///
-/// If key is already present it's value is overwritten.
+/// If key is already present it's value is overwritten, unless `NX` is given.
 pub struct Set {
     key: Bytes,
     value: Bytes,
     expire: Option<Duration>,
+    /// The expiration was given as a unix time, it isn't jittered. Changes replayed from the
+    /// append only file or streamed by a master are set with unix times, they expire exactly
+    /// when they expired where they were made.
+    absolute: bool,
+    /// Only set the key if it doesn't exist.
+    nx: bool,
 }
 
 impl Set {
     /// Creates a new `Set` command which sets `key` to `value`
     /// If `expire` is provided then key will expire after specified duration.
     pub fn new(key: Bytes, value: Bytes, expire: Option<Duration>) -> Set {
-        Set { key, value, expire }
+        Set {
+            key,
+            value,
+            expire,
+            absolute: false,
+            nx: false,
+        }
+    }
+
+    /// Creates a new `Set` command which sets `key` to `value` only if it doesn't exist.
+    pub fn new_nx(key: Bytes, value: Bytes, expire: Option<Duration>) -> Set {
+        Set {
+            nx: true,
+            ..Set::new(key, value, expire)
+        }
     }
 
     /// Parse a `Set` instance from a received array frame.
@@ -32,42 +52,61 @@ impl Set {
     /// Returns the `Set` value on success. Error is returned if frame is malformed.
     /// Expects an array frame containing atleast 3 entries.
     ///
-    /// SET key value [EX seconds|PX milliseconds]
+    /// SET key value [NX] [EX seconds|PX milliseconds|EXAT unix-time-seconds|PXAT unix-time-milliseconds]
     pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, WalrusError> {
         // Get key from the frame.
         let key = parse.next_bytes()?;
         // Get the value to set from the frame.
         let value = parse.next_bytes()?;
-        // Optional field.
+        // Optional fields.
         let mut expire = None;
+        let mut absolute = false;
+        let mut nx = false;
 
-        match parse.next_bytes() {
-            Ok(s) if s.eq_ignore_ascii_case(b"ex") => {
-                // Expiration in seconds, next value must be an integer.
-                let secs = parse.next_int()?;
-                expire = Some(Duration::from_secs(secs as u64));
-            }
-            Ok(s) if s.eq_ignore_ascii_case(b"px") => {
-                // Expiration in milliseconds, next value must be an integer.
-                let ms = parse.next_int()?;
-                expire = Some(Duration::from_millis(ms as u64));
+        loop {
+            match parse.next_bytes() {
+                Ok(s) if s.eq_ignore_ascii_case(b"nx") && !nx => nx = true,
+                Ok(s) if expire.is_none() => {
+                    let (duration, at) = parse_expire(&s, parse)?;
+                    expire = Some(duration);
+                    absolute = at;
+                }
+                Ok(_) => return Err("ERR syntax error".into()),
+                // No more options.
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into()),
             }
-            Ok(_) => return Err("walrus only supports expiration option for `SET`".into()),
-            // No options specified for `SET`, no expiration is set.
-            Err(ParseError::EndOfStream) => {}
-            Err(err) => return Err(err.into()),
         }
 
-        Ok(Set { key, value, expire })
+        Ok(Set {
+            key,
+            value,
+            expire,
+            absolute,
+            nx,
+        })
     }
 
     /// Execute the `Set` command, inserting the given key-value pair into `Db`.
-    /// "OK" response is written to `conn`.
+    /// "OK" response is written to `conn`. With `NX` the key is only set if it doesn't exist,
+    /// a null response is written otherwise. A relative expiration is extended by the
+    /// `expire-jitter` configuration parameter, `PTTL` returns the effective one.
     pub(crate) async fn execute(self, db: &Db, conn: &mut Connection) -> Result<(), WalrusError> {
         // optimize storage of data before inserting into db.
         let value = db::optimize_storage(self.value);
+        let expire = match self.expire {
+            Some(expire) if !self.absolute => Some(db.jitter(expire)),
+            expire => expire,
+        };
 
-        db.set(&self.key, value, self.expire);
+        if self.nx {
+            if !db.compare_and_swap(&self.key, None, value, expire) {
+                conn.write_null_frame();
+                return Ok(());
+            }
+        } else {
+            db.set(&self.key, value, expire);
+        }
 
         let response = Data::Bytes(Bytes::from("OK"));
         conn.write_data(&response);
@@ -75,6 +114,11 @@ impl Set {
         Ok(())
     }
 
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.key)
+    }
+
     /// Converts `Set` instance to `Frame`, consumes self.
     pub fn into_frame(self) -> Frame {
         let mut frame = Frame::array();
@@ -82,6 +126,10 @@ impl Set {
         frame.push_bulk(self.key);
         frame.push_bulk(self.value);
 
+        if self.nx {
+            frame.push_bulk(Bytes::from("nx"));
+        }
+
         if let Some(ms) = self.expire {
             // Expiration can be specified in two ways
             // 1. SET key value EX seconds
@@ -94,3 +142,36 @@ impl Set {
         frame
     }
 }
+
+/// Parse the expiration option `option` and its value, returns the time to live and whether
+/// it was given as a unix time.
+fn parse_expire(option: &[u8], parse: &mut Parse) -> Result<(Duration, bool), WalrusError> {
+    if option.eq_ignore_ascii_case(b"ex") {
+        // Expiration in seconds, next value must be an integer.
+        let secs = parse.next_int()?;
+        Ok((Duration::from_secs(secs as u64), false))
+    } else if option.eq_ignore_ascii_case(b"px") {
+        // Expiration in milliseconds, next value must be an integer.
+        let ms = parse.next_int()?;
+        Ok((Duration::from_millis(ms as u64), false))
+    } else if option.eq_ignore_ascii_case(b"exat") {
+        // Unix time in seconds at which the key expires.
+        let secs = parse.next_int()?;
+        Ok((until_unix_ms((secs as u64).saturating_mul(1000)), true))
+    } else if option.eq_ignore_ascii_case(b"pxat") {
+        // Unix time in milliseconds at which the key expires.
+        let ms = parse.next_int()?;
+        Ok((until_unix_ms(ms as u64), true))
+    } else {
+        Err("walrus only supports expiration and NX options for `SET`".into())
+    }
+}
+
+/// Time left until the unix time `ms` in milliseconds, zero if it's in the past.
+fn until_unix_ms(ms: u64) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Duration::from_millis(ms).saturating_sub(now)
+}
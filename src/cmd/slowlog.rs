@@ -0,0 +1,102 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::Data,
+    errors::WalrusError,
+    frame::Frame,
+    parse::{Parse, ParseError},
+    server::ServerState,
+};
+
+/// SLOWLOG command, reads and resets the log of commands exceeding
+/// `slowlog-log-slower-than`.
+///
+/// SLOWLOG GET [count]
+/// SLOWLOG LEN
+/// SLOWLOG RESET
+#[derive(Debug)]
+pub enum SlowlogCmd {
+    /// Get the `count` most recent entries, 10 by default. Negative count gets every entry.
+    Get(Option<i64>),
+    /// Number of entries.
+    Len,
+    /// Remove every entry.
+    Reset,
+}
+
+impl SlowlogCmd {
+    /// Parse a `SlowlogCmd` instance from an array frame.
+    /// The 'SLOWLOG' string is already consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SlowlogCmd, WalrusError> {
+        let subcommand = parse.next_bytes()?;
+
+        if subcommand.eq_ignore_ascii_case(b"get") {
+            let count = match parse.next_int() {
+                Ok(count) => Some(count),
+                Err(ParseError::EndOfStream) => None,
+                Err(_) => {
+                    return Err(WalrusError::SyntaxError(
+                        "ERR value is not an integer or out of range".into(),
+                    ));
+                }
+            };
+
+            Ok(SlowlogCmd::Get(count))
+        } else if subcommand.eq_ignore_ascii_case(b"len") {
+            Ok(SlowlogCmd::Len)
+        } else if subcommand.eq_ignore_ascii_case(b"reset") {
+            Ok(SlowlogCmd::Reset)
+        } else {
+            Err(WalrusError::SyntaxError(format!(
+                "ERR unknown subcommand '{}'",
+                String::from_utf8_lossy(&subcommand)
+            )))
+        }
+    }
+
+    /// Execute the `Slowlog` subcommand against the slow log of the server.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        match self {
+            SlowlogCmd::Get(count) => {
+                let count = match count {
+                    None => 10,
+                    Some(count) => usize::try_from(count).unwrap_or(usize::MAX),
+                };
+
+                conn.write_frame(&Frame::Array(server.slowlog.get(count)));
+            }
+            SlowlogCmd::Len => conn.write_data(&Data::Integer(server.slowlog.len() as i64)),
+            SlowlogCmd::Reset => {
+                server.slowlog.reset();
+                conn.write_data(&Data::String(Bytes::from("OK")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Convert `SlowlogCmd` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("slowlog"));
+
+        match self {
+            SlowlogCmd::Get(count) => {
+                frame.push_bulk(Bytes::from("get"));
+
+                if let Some(count) = count {
+                    frame.push_int(count);
+                }
+            }
+            SlowlogCmd::Len => frame.push_bulk(Bytes::from("len")),
+            SlowlogCmd::Reset => frame.push_bulk(Bytes::from("reset")),
+        }
+
+        frame
+    }
+}
@@ -0,0 +1,50 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection, errors::WalrusError, frame::Frame, parse::Parse, replication::SyncReply,
+    server::ServerState,
+};
+
+/// SYNC command, used by replicas to receive the dataset and the writes of their master.
+///
+/// SYNC
+///
+/// Replies with a snapshot of the dataset as a bulk string, then every write command executed
+/// by the server is streamed to the connection.
+#[derive(Debug)]
+pub struct SyncCmd;
+
+impl SyncCmd {
+    /// Parse a `Sync` instance from an array frame.
+    /// The 'SYNC' string is already consumed.
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<SyncCmd, WalrusError> {
+        Ok(SyncCmd)
+    }
+
+    /// Execute the `Sync` command, switching the connection to a replica link.
+    pub(crate) async fn execute(
+        self,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        // The connection is registered for as long as its handler runs.
+        let Some(sender) = server.clients.push_sender(conn.id()) else {
+            return Ok(());
+        };
+        let ip = conn.peer_addr()?.ip();
+
+        match server
+            .replication
+            .add_replica(conn.id(), ip, sender, None, server)
+            .await
+        {
+            Ok(SyncReply::FullResync { snapshot, .. }) => {
+                conn.write_frame(&Frame::Bulk(Bytes::from(snapshot)))
+            }
+            Ok(SyncReply::Continue { .. }) => unreachable!("SYNC always loads a snapshot"),
+            Err(err) => conn.write_error_frame(&format!("ERR {err}")),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+/// Static description of a command, as reported by `COMMAND`.
+#[derive(Debug)]
+pub(crate) struct CommandSpec {
+    /// Lowercase name of the command.
+    pub(crate) name: &'static str,
+    /// Number of arguments including the command name. Negative if the command takes at least
+    /// `-arity` arguments.
+    pub(crate) arity: i64,
+    /// Command flags, such as `write`, `readonly` or `blocking`.
+    pub(crate) flags: &'static [&'static str],
+    /// Position of the first key argument, 0 if the command takes no keys.
+    pub(crate) first_key: i64,
+    /// Position of the last key argument, negative positions count from the end.
+    pub(crate) last_key: i64,
+    /// Step between key positions.
+    pub(crate) step: i64,
+    /// Group of the command in the documentation.
+    pub(crate) group: &'static str,
+    /// One line description of the command.
+    pub(crate) summary: &'static str,
+    /// Time complexity of the command.
+    pub(crate) complexity: &'static str,
+}
+
+/// Every command implemented by the server, ordered by name.
+/// `DEBUG` is left out as it may be compiled out.
+pub(crate) const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "asking",
+        arity: 1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "cluster",
+        summary: "Signals that a cluster client is following an -ASK redirect.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "bgsave",
+        arity: -1,
+        flags: &["admin", "noscript"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Asynchronously saves the database to disk.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "blpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        group: "list",
+        summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise.",
+        complexity: "O(N) where N is the number of provided keys.",
+    },
+    CommandSpec {
+        name: "client",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Inspects and manages the connections of the server.",
+        complexity: "Depends on subcommand.",
+    },
+    CommandSpec {
+        name: "cluster",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "cluster",
+        summary: "A container for Redis Cluster commands.",
+        complexity: "Depends on subcommand.",
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns detailed information about commands.",
+        complexity: "O(N) where N is the total number of commands.",
+    },
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Reads and changes the configuration of the server at runtime.",
+        complexity: "O(N) where N is the number of configuration parameters.",
+    },
+    CommandSpec {
+        name: "failover",
+        arity: -1,
+        flags: &["admin", "noscript", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Starts a coordinated failover from a server to one of its replicas.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Returns the string value of a key.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Handshakes with the server, switching the protocol of the connection.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Returns information and statistics about the server.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "keys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Returns all key names that match a pattern.",
+        complexity: "O(N) with N being the number of keys in the database.",
+    },
+    CommandSpec {
+        name: "latency",
+        arity: -2,
+        flags: &["admin", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Reads and resets the latency spikes recorded by the latency monitor.",
+        complexity: "O(N) where N is the number of spikes returned or events reset.",
+    },
+    CommandSpec {
+        name: "llen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns the length of a list.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "lolwut",
+        arity: -1,
+        flags: &["readonly", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Displays computer art and the Walrus version.",
+        complexity: "O(N) where N is the size of the drawing.",
+    },
+    CommandSpec {
+        name: "lpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns the first elements in a list after removing them.",
+        complexity: "O(N) where N is the number of elements returned.",
+    },
+    CommandSpec {
+        name: "lpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Prepends one or more elements to a list.",
+        complexity: "O(N) where N is the number of elements pushed.",
+    },
+    CommandSpec {
+        name: "lrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Returns a range of elements from a list.",
+        complexity: "O(S+N) where S is the start offset and N the number of elements returned.",
+    },
+    CommandSpec {
+        name: "memory",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "A container for memory diagnostics commands.",
+        complexity: "Depends on subcommand.",
+    },
+    CommandSpec {
+        name: "monitor",
+        arity: 1,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Streams every command processed by the server to the connection.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "move",
+        arity: 3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Moves a key to another database.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "object",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        step: 1,
+        group: "generic",
+        summary: "Returns information about the internals of the value of a key.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast", "stale", "loading"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Returns the server's liveliness response.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "psync",
+        arity: 3,
+        flags: &["admin", "noscript"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "An internal command used in replication.",
+        complexity: "O(N) where N is the number of commands missed or of keys.",
+    },
+    CommandSpec {
+        name: "pttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Returns the expiration time in milliseconds of a key.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "replconf",
+        arity: -1,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "An internal command for configuring the replication stream.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "replicaof",
+        arity: 3,
+        flags: &["admin", "noscript", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Configures a server as replica of another, or promotes it to a master.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "rpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "list",
+        summary: "Appends one or more elements to a list.",
+        complexity: "O(N) where N is the number of elements pushed.",
+    },
+    CommandSpec {
+        name: "save",
+        arity: 1,
+        flags: &["admin", "noscript"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Synchronously saves the database to disk.",
+        complexity: "O(N) where N is the total number of keys in all databases.",
+    },
+    CommandSpec {
+        name: "scan",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "generic",
+        summary: "Iterates over the key names in the database.",
+        complexity: "O(1) for every call. O(N) for a complete iteration.",
+    },
+    CommandSpec {
+        name: "select",
+        arity: 2,
+        flags: &["loading", "stale", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "connection",
+        summary: "Changes the selected database.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "sentinel",
+        arity: -2,
+        flags: &["admin", "noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "sentinel",
+        summary: "A container for Redis Sentinel commands.",
+        complexity: "Depends on subcommand.",
+    },
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "string",
+        summary: "Sets the string value of a key, optionally with an expiration.",
+        complexity: "O(1)",
+    },
+    CommandSpec {
+        name: "slowlog",
+        arity: -2,
+        flags: &["admin", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "Reads and resets the log of slow commands.",
+        complexity: "O(N) where N is the number of entries returned.",
+    },
+    CommandSpec {
+        name: "sync",
+        arity: 1,
+        flags: &["admin", "noscript"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        group: "server",
+        summary: "An internal command used in replication.",
+        complexity: "O(N) where N is the total number of keys.",
+    },
+    CommandSpec {
+        name: "type",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        group: "generic",
+        summary: "Determines the type of value stored at a key.",
+        complexity: "O(1)",
+    },
+];
+
+impl CommandSpec {
+    /// Returns `true` if the command has `flag`.
+    pub(crate) fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+}
+
+/// Find the description of the command `name`, case insensitive.
+pub(crate) fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
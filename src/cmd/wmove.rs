@@ -0,0 +1,85 @@
+use bytes::Bytes;
+
+use crate::{
+    Connection,
+    db::{Data, Db},
+    errors::WalrusError,
+    frame::Frame,
+    parse::Parse,
+    server::ServerState,
+};
+
+/// `Move` command to move a key, with its time to live, to another database.
+///
+/// MOVE key db
+///
+/// Every database has its expirations of its own, the key keeps expiring at the same time in
+/// the database it's moved to. Nothing is moved if the key already exists there.
+pub struct Move {
+    key: Bytes,
+    db: i64,
+}
+
+impl Move {
+    /// Returns a `Move` instance.
+    pub fn new(key: Bytes, db: i64) -> Move {
+        Move { key, db }
+    }
+
+    /// Parse a `Move` instance from an array frame.
+    /// The 'MOVE' String is already consumed.
+    ///
+    /// Expects an array containing 3 entries.
+    /// MOVE key db
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Move, WalrusError> {
+        let key = parse.next_bytes()?;
+        let db = parse.next_int()?;
+        Ok(Move { key, db })
+    }
+
+    /// Execute the `Move` command, `1` is written to `conn` if the key was moved from the
+    /// database selected, `db`, to the target database and `0` otherwise.
+    pub(crate) async fn execute(
+        self,
+        db: &Db,
+        conn: &mut Connection,
+        server: &ServerState,
+    ) -> Result<(), WalrusError> {
+        if server.cluster.is_some() {
+            conn.write_error_frame("ERR MOVE is not allowed in cluster mode");
+            return Ok(());
+        }
+
+        let target = match usize::try_from(self.db) {
+            Ok(target) if target < server.databases.len() => target,
+            _ => {
+                conn.write_error_frame("ERR DB index is out of range");
+                return Ok(());
+            }
+        };
+        if target == conn.database() {
+            conn.write_error_frame("ERR source and destination objects are the same");
+            return Ok(());
+        }
+
+        let moved = db.move_to(&self.key, &server.databases[target]);
+        conn.write_data(&Data::Integer(moved as i64));
+
+        Ok(())
+    }
+
+    /// Keys accessed by the command.
+    pub(crate) fn keys(&self) -> &[Bytes] {
+        std::slice::from_ref(&self.key)
+    }
+
+    /// Convert `Move` instance to `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("move"));
+        frame.push_bulk(self.key);
+        frame.push_int(self.db);
+
+        frame
+    }
+}
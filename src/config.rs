@@ -0,0 +1,1488 @@
+use bytes::Bytes;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc, RwLock,
+        atomic::{
+            AtomicBool, AtomicI64, AtomicU8, AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering,
+        },
+    },
+    time::Duration,
+};
+
+pub(crate) mod file;
+
+use crate::{
+    connection::SocketOptions,
+    db::{Db, ListpackLimits},
+    frame::Limits,
+    glob::glob_match,
+    log,
+    ratelimit::RatelimitAction,
+};
+
+/// Keyspace notification classes, as used by `notify-keyspace-events`.
+pub(crate) mod notify {
+    /// `__keyspace@<db>__:<key>` events.
+    pub(crate) const KEYSPACE: u32 = 1 << 0;
+    /// `__keyevent@<db>__:<event>` events.
+    pub(crate) const KEYEVENT: u32 = 1 << 1;
+    /// Generic commands, `DEL`, `EXPIRE`, `RENAME` and so on.
+    pub(crate) const GENERIC: u32 = 1 << 2;
+    pub(crate) const STRING: u32 = 1 << 3;
+    pub(crate) const LIST: u32 = 1 << 4;
+    pub(crate) const SET: u32 = 1 << 5;
+    pub(crate) const HASH: u32 = 1 << 6;
+    pub(crate) const ZSET: u32 = 1 << 7;
+    /// Keys expired.
+    pub(crate) const EXPIRED: u32 = 1 << 8;
+    /// Keys evicted because of `maxmemory`.
+    pub(crate) const EVICTED: u32 = 1 << 9;
+    pub(crate) const STREAM: u32 = 1 << 10;
+    /// Keys missed on read.
+    pub(crate) const MISS: u32 = 1 << 11;
+    /// Keys created.
+    pub(crate) const NEW: u32 = 1 << 12;
+    /// Alias for every class except `MISS` and `NEW`.
+    pub(crate) const ALL: u32 =
+        GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM;
+
+    /// Flag character of each class, in the order they are rendered.
+    pub(super) const FLAGS: [(u8, u32); 13] = [
+        (b'g', GENERIC),
+        (b'$', STRING),
+        (b'l', LIST),
+        (b's', SET),
+        (b'h', HASH),
+        (b'z', ZSET),
+        (b'x', EXPIRED),
+        (b'e', EVICTED),
+        (b't', STREAM),
+        (b'm', MISS),
+        (b'n', NEW),
+        (b'K', KEYSPACE),
+        (b'E', KEYEVENT),
+    ];
+}
+
+/// Connections allowed to use the `DEBUG` command, only set when the server starts so clients
+/// can't enable it themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// No connection, the default.
+    No = 0,
+    /// Every connection.
+    Yes = 1,
+    /// Only connections from the loopback interface.
+    Local = 2,
+}
+
+impl FromStr for DebugCommand {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<DebugCommand, String> {
+        if value.eq_ignore_ascii_case("no") {
+            Ok(DebugCommand::No)
+        } else if value.eq_ignore_ascii_case("yes") {
+            Ok(DebugCommand::Yes)
+        } else if value.eq_ignore_ascii_case("local") {
+            Ok(DebugCommand::Local)
+        } else {
+            Err("argument must be one of the following: no, yes, local".into())
+        }
+    }
+}
+
+/// When the append only file is synced to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AppendFsync {
+    /// After every write command, before replying.
+    Always = 0,
+    /// Never explicitly, the operating system decides.
+    No = 1,
+    /// Once per second by a background task, at most a second of writes is lost on a crash.
+    Everysec = 2,
+}
+
+/// Keys evicted when the dataset exceeds `maxmemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MaxmemoryPolicy {
+    /// Nothing is evicted, commands that may grow the dataset are refused.
+    NoEviction = 0,
+    /// The least recently used keys.
+    AllkeysLru = 1,
+    /// The least recently used keys with an expiration.
+    VolatileLru = 2,
+    /// The least frequently used keys.
+    AllkeysLfu = 3,
+    /// The least frequently used keys with an expiration.
+    VolatileLfu = 4,
+}
+
+/// Deletions of values, large values are freed on a background thread for those enabled with
+/// their `lazyfree-lazy-*` or `replica-lazy-flush` parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lazyfree {
+    /// Keys evicted to stay under `maxmemory`.
+    Eviction = 0,
+    /// Expired keys.
+    Expire = 1,
+    /// Values replaced by the server, such as by `SET`.
+    ServerDel = 2,
+    /// Keys deleted by the user.
+    UserDel = 3,
+    /// The dataset of a replica replaced by the one of its master.
+    ReplicaFlush = 4,
+}
+
+/// Compression of the sections of snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SnapshotCompression {
+    No = 0,
+    /// Requires the `lz4` feature.
+    Lz4 = 1,
+    /// Requires the `zstd` feature.
+    Zstd = 2,
+}
+
+/// Classes of clients, each with its own `client-output-buffer-limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClientClass {
+    Normal = 0,
+    /// Replicas streamed the writes of this server.
+    Replica = 1,
+    /// Connections only receiving push data, such as monitors.
+    Pubsub = 2,
+}
+
+impl ClientClass {
+    const ALL: [ClientClass; 3] = [
+        ClientClass::Normal,
+        ClientClass::Replica,
+        ClientClass::Pubsub,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            ClientClass::Normal => "normal",
+            ClientClass::Replica => "replica",
+            ClientClass::Pubsub => "pubsub",
+        }
+    }
+}
+
+/// Limits on the output queued for a client, in bytes, 0 disables a limit. The client is
+/// disconnected once its output exceeds `hard`, or stays over `soft` for `soft_seconds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct OutputLimit {
+    pub(crate) hard: u64,
+    pub(crate) soft: u64,
+    pub(crate) soft_seconds: u64,
+}
+
+/// Output limits of each `ClientClass`, indexed by class. Shared with the connections, which
+/// check them as output is queued.
+pub(crate) type OutputLimits = Arc<RwLock<[OutputLimit; 3]>>;
+
+/// Runtime configuration of the server, read and written with `CONFIG GET` and `CONFIG SET`.
+///
+/// Numeric parameters are stored in atomics, so they can be read on hot paths without locking
+/// and changes are seen by all connections immediately. Paths are behind a lock, they are only
+/// read when persisting.
+pub(crate) struct Config {
+    /// Port the server listens on. Immutable.
+    port: u16,
+    /// Maximum number of connected clients, further connections are refused.
+    maxclients: AtomicUsize,
+    /// Whether clients on other hosts are refused while the server listens on an address
+    /// reachable from them, since no password can be set.
+    protected_mode: AtomicBool,
+    /// Maximum number of connections from a single address, 0 means no limit.
+    maxclients_per_ip: AtomicUsize,
+    /// Commands a single address may send per second, 0 means no limit.
+    ratelimit_per_ip: AtomicU64,
+    /// What happens to connections over `ratelimit_per_ip`, a `RatelimitAction`.
+    ratelimit_per_ip_action: AtomicU8,
+    /// Limits on the output queued for clients, by class.
+    output_limits: OutputLimits,
+    /// Seconds a client may stay idle before the connection is closed, 0 to never close idle
+    /// clients.
+    timeout: AtomicU64,
+    /// Seconds of idleness before TCP keepalives probe the peers of new connections, 0
+    /// disables keepalives.
+    tcp_keepalive: AtomicU64,
+    /// Whether `TCP_NODELAY` is set on new connections.
+    tcp_nodelay: AtomicBool,
+    /// Kernel send buffer size of new connections in bytes, 0 keeps the OS default.
+    tcp_sndbuf: AtomicU64,
+    /// Kernel receive buffer size of new connections in bytes, 0 keeps the OS default.
+    tcp_rcvbuf: AtomicU64,
+    /// Initial read buffer size of new connections in KB.
+    read_buffer_size: AtomicU16,
+    /// Initial write buffer size of new connections in KB.
+    write_buffer_size: AtomicU16,
+    /// Longest bulk string accepted in a request, in bytes.
+    proto_max_bulk_len: AtomicU64,
+    /// Most arguments accepted in a request.
+    proto_max_multibulk_len: AtomicU64,
+    /// Largest request accepted, in bytes.
+    client_query_buffer_limit: AtomicU64,
+    /// Memory limit of the dataset in bytes, 0 means no limit.
+    maxmemory: AtomicU64,
+    /// Keys evicted once `maxmemory` is reached, a `MaxmemoryPolicy`.
+    maxmemory_policy: AtomicU8,
+    /// Number of keys sampled to pick each key to evict, more samples approximate the policy
+    /// better at a higher cost.
+    maxmemory_samples: AtomicUsize,
+    /// Most elements of a list stored as a listpack.
+    list_max_listpack_entries: AtomicUsize,
+    /// Longest string element of a list stored as a listpack, in bytes.
+    list_max_listpack_value: AtomicUsize,
+    /// Enabled keyspace notification classes, a combination of `notify` flags.
+    notify_keyspace_events: AtomicU32,
+    /// Commands taking longer than this many microseconds are logged in the slow log.
+    /// Negative disables the slow log, 0 logs every command.
+    slowlog_log_slower_than: AtomicI64,
+    /// Maximum number of entries kept in the slow log.
+    slowlog_max_len: AtomicUsize,
+    /// Events taking at least this many milliseconds are recorded by the latency monitor, 0
+    /// disables it.
+    latency_monitor_threshold: AtomicU64,
+    /// Whether the execution times of each command are tracked for `INFO latencystats`.
+    latency_tracking: AtomicBool,
+    /// Percentiles of the execution times reported by `INFO latencystats`.
+    latency_tracking_info_percentiles: RwLock<Vec<f64>>,
+    /// Connections allowed to use the `DEBUG` command, a `DebugCommand`.
+    enable_debug_command: AtomicU8,
+    /// Directory snapshots are written to and loaded from.
+    dir: RwLock<PathBuf>,
+    /// File name of the snapshot within `dir`.
+    dbfilename: RwLock<String>,
+    /// Compression of new snapshots, a `SnapshotCompression`.
+    snapshot_compression: AtomicU8,
+    /// Whether a snapshot is saved when the server shuts down, if the dataset changed since the
+    /// last save.
+    save_on_shutdown: AtomicBool,
+    /// Number of logical databases, selected with `SELECT`. Immutable.
+    databases: usize,
+    /// Whether write commands are logged to the append only file. Immutable.
+    appendonly: bool,
+    /// File name of the append only file within `dir`. Immutable.
+    appendfilename: String,
+    /// When the append only file is synced to disk, an `AppendFsync`.
+    appendfsync: AtomicU8,
+    /// Size of the replication backlog in bytes, the history of writes kept for replicas to
+    /// resume from after a disconnection.
+    repl_backlog_size: AtomicU64,
+    /// Deletions freeing large values on a background thread, a combination of `Lazyfree`
+    /// flags.
+    lazyfree: AtomicU8,
+    /// Percentage of its time to live randomly added to an expiration set by a command, so keys
+    /// set together with the same time to live don't all expire at once.
+    expire_jitter: AtomicU8,
+    /// Whether an append only file ending with an incomplete command, as left by a crash while
+    /// appending, is truncated to its last complete command on startup instead of refused.
+    aof_load_truncated: AtomicBool,
+    /// Whether a replica refuses write commands from its clients, so its dataset stays the one
+    /// of its master.
+    replica_read_only: AtomicBool,
+    /// Whether write commands of clients are refused, for maintenance or to serve a frozen
+    /// dataset.
+    read_only: AtomicBool,
+}
+
+/// Validate a value and store it in the configuration, returns the error message on failure.
+type Setter = fn(&Config, &str) -> Result<(), String>;
+
+/// A configuration parameter, its name and how to read and write it.
+struct Param {
+    name: &'static str,
+    get: fn(&Config) -> String,
+    /// `None` for parameters that can't be changed at runtime.
+    set: Option<Setter>,
+}
+
+/// Smallest `proto-max-bulk-len` and `client-query-buffer-limit`, in bytes.
+const MIN_PROTO_LIMIT: u64 = 1024 * 1024;
+
+/// Smallest replication backlog, in bytes.
+const MIN_REPL_BACKLOG_SIZE: u64 = 16 * 1024;
+
+/// Parameters of the process rather than of a server, setting one changes every server the
+/// process runs.
+const PROCESS_PARAMS: &[&str] = &[
+    "loglevel",
+    "logfile",
+    "log-max-size",
+    "log-max-files",
+    "log-rotate-interval",
+];
+
+/// Table of all configuration parameters.
+const PARAMS: &[Param] = &[
+    Param {
+        name: "port",
+        get: |config| config.port().to_string(),
+        set: None,
+    },
+    Param {
+        name: "maxclients",
+        get: |config| config.maxclients().to_string(),
+        set: Some(|config, value| {
+            let maxclients = parse_number(value)?;
+            if maxclients == 0 {
+                return Err("argument must be between 1 and 18446744073709551615 inclusive".into());
+            }
+
+            config
+                .maxclients
+                .store(maxclients as usize, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "protected-mode",
+        get: |config| yes_no(config.protected_mode()),
+        set: Some(|config, value| {
+            let protected = parse_yes_no(value)?;
+            config.protected_mode.store(protected, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxclients-per-ip",
+        get: |config| config.maxclients_per_ip().to_string(),
+        set: Some(|config, value| {
+            let maxclients = parse_number(value)?;
+            config.maxclients_per_ip.store(
+                usize::try_from(maxclients).unwrap_or(usize::MAX),
+                Ordering::Relaxed,
+            );
+            Ok(())
+        }),
+    },
+    Param {
+        name: "ratelimit-per-ip",
+        get: |config| config.ratelimit_per_ip().to_string(),
+        set: Some(|config, value| {
+            let rate = parse_number(value)?;
+            config.ratelimit_per_ip.store(rate, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "ratelimit-per-ip-action",
+        get: |config| {
+            match config.ratelimit_per_ip_action() {
+                RatelimitAction::Throttle => "throttle",
+                RatelimitAction::Disconnect => "disconnect",
+            }
+            .to_string()
+        },
+        set: Some(|config, value| {
+            let action = if value.eq_ignore_ascii_case("throttle") {
+                RatelimitAction::Throttle
+            } else if value.eq_ignore_ascii_case("disconnect") {
+                RatelimitAction::Disconnect
+            } else {
+                return Err("argument must be one of the following: throttle, disconnect".into());
+            };
+
+            config
+                .ratelimit_per_ip_action
+                .store(action as u8, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "timeout",
+        get: |config| config.timeout().to_string(),
+        set: Some(|config, value| {
+            let timeout = parse_number(value)?;
+            config.timeout.store(timeout, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "tcp-keepalive",
+        get: |config| config.tcp_keepalive.load(Ordering::Relaxed).to_string(),
+        set: Some(|config, value| {
+            let seconds = parse_number(value)?;
+            config.tcp_keepalive.store(seconds, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "tcp-nodelay",
+        get: |config| yes_no(config.tcp_nodelay.load(Ordering::Relaxed)),
+        set: Some(|config, value| {
+            let nodelay = parse_yes_no(value)?;
+            config.tcp_nodelay.store(nodelay, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "tcp-sndbuf",
+        get: |config| config.tcp_sndbuf.load(Ordering::Relaxed).to_string(),
+        set: Some(|config, value| {
+            let size = parse_memory(value)?;
+            config.tcp_sndbuf.store(size, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "tcp-rcvbuf",
+        get: |config| config.tcp_rcvbuf.load(Ordering::Relaxed).to_string(),
+        set: Some(|config, value| {
+            let size = parse_memory(value)?;
+            config.tcp_rcvbuf.store(size, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "read-buffer-size",
+        get: |config| config.read_buffer_size().to_string(),
+        set: Some(|config, value| {
+            let size = parse_buffer_size(value)?;
+            config.read_buffer_size.store(size, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "write-buffer-size",
+        get: |config| config.write_buffer_size().to_string(),
+        set: Some(|config, value| {
+            let size = parse_buffer_size(value)?;
+            config.write_buffer_size.store(size, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        get: |config| config.frame_limits().max_bulk_len.to_string(),
+        set: Some(|config, value| {
+            let len = parse_memory(value)?;
+            if len < MIN_PROTO_LIMIT {
+                return Err("argument must be a memory value of at least 1mb".into());
+            }
+            config.proto_max_bulk_len.store(len, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "proto-max-multibulk-len",
+        get: |config| config.frame_limits().max_aggregate_len.to_string(),
+        set: Some(|config, value| {
+            let len = parse_number(value)?;
+            if len == 0 {
+                return Err("argument must be between 1 and 18446744073709551615 inclusive".into());
+            }
+            config.proto_max_multibulk_len.store(len, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        get: |config| config.frame_limits().max_frame_size.to_string(),
+        set: Some(|config, value| {
+            let limit = parse_memory(value)?;
+            if limit < MIN_PROTO_LIMIT {
+                return Err("argument must be a memory value of at least 1mb".into());
+            }
+            config
+                .client_query_buffer_limit
+                .store(limit, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "client-output-buffer-limit",
+        get: |config| {
+            let limits = config.output_limits.read().unwrap();
+            ClientClass::ALL
+                .iter()
+                .map(|&class| {
+                    let limit = limits[class as usize];
+                    format!(
+                        "{} {} {} {}",
+                        class.name(),
+                        limit.hard,
+                        limit.soft,
+                        limit.soft_seconds
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: Some(|config, value| {
+            let args: Vec<&str> = value.split_ascii_whitespace().collect();
+            if args.is_empty() || !args.len().is_multiple_of(4) {
+                return Err("wrong number of arguments in buffer limit configuration".into());
+            }
+
+            // Every class is validated before any limit changes.
+            let mut limits = *config.output_limits.read().unwrap();
+            for group in args.chunks(4) {
+                let class = match group[0].to_ascii_lowercase().as_str() {
+                    "normal" => ClientClass::Normal,
+                    "replica" | "slave" => ClientClass::Replica,
+                    "pubsub" => ClientClass::Pubsub,
+                    _ => {
+                        return Err(
+                            "invalid client class specified in buffer limit configuration".into(),
+                        );
+                    }
+                };
+                limits[class as usize] = OutputLimit {
+                    hard: parse_memory(group[1])?,
+                    soft: parse_memory(group[2])?,
+                    soft_seconds: parse_number(group[3])?,
+                };
+            }
+
+            *config.output_limits.write().unwrap() = limits;
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory",
+        get: |config| config.maxmemory().to_string(),
+        set: Some(|config, value| {
+            let maxmemory = parse_memory(value)?;
+            config.maxmemory.store(maxmemory, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory-policy",
+        get: |config| config.maxmemory_policy().name().to_string(),
+        set: Some(|config, value| {
+            let policy = [
+                MaxmemoryPolicy::NoEviction,
+                MaxmemoryPolicy::AllkeysLru,
+                MaxmemoryPolicy::VolatileLru,
+                MaxmemoryPolicy::AllkeysLfu,
+                MaxmemoryPolicy::VolatileLfu,
+            ]
+            .into_iter()
+            .find(|policy| value.eq_ignore_ascii_case(policy.name()))
+            .ok_or(
+                "argument must be one of the following: noeviction, allkeys-lru, volatile-lru, \
+                 allkeys-lfu, volatile-lfu",
+            )?;
+
+            config
+                .maxmemory_policy
+                .store(policy as u8, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "maxmemory-samples",
+        get: |config| config.maxmemory_samples().to_string(),
+        set: Some(|config, value| {
+            let samples = parse_number(value)?;
+            if samples == 0 || samples > 64 {
+                return Err("argument must be between 1 and 64 inclusive".into());
+            }
+
+            config
+                .maxmemory_samples
+                .store(samples as usize, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "list-max-listpack-entries",
+        get: |config| config.listpack_limits().entries.to_string(),
+        set: Some(|config, value| {
+            let entries = parse_number(value)?;
+            config
+                .list_max_listpack_entries
+                .store(entries as usize, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "list-max-listpack-value",
+        get: |config| config.listpack_limits().value.to_string(),
+        set: Some(|config, value| {
+            let size = parse_number(value)?;
+            config
+                .list_max_listpack_value
+                .store(size as usize, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "notify-keyspace-events",
+        get: |config| notify_flags_to_string(config.notify_keyspace_events()),
+        set: Some(|config, value| {
+            let flags = parse_notify_flags(value)?;
+            config
+                .notify_keyspace_events
+                .store(flags, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "loglevel",
+        // The level is the one of the process, not of this server alone.
+        get: |_| log::level().name().to_string(),
+        set: Some(|_, value| {
+            log::set_level(value.parse()?);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "logfile",
+        // Like the level, the file is the one of the process. Empty logs to stdout.
+        get: |_| {
+            log::file()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
+        },
+        set: Some(|_, value| {
+            let path = (!value.is_empty()).then(|| Path::new(value));
+            log::set_file(path).map_err(|err| format!("failed to open the log file, {err}"))
+        }),
+    },
+    Param {
+        name: "log-max-size",
+        get: |_| log::max_size().to_string(),
+        set: Some(|_, value| {
+            log::set_max_size(parse_memory(value)?);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "log-max-files",
+        get: |_| log::max_files().to_string(),
+        set: Some(|_, value| {
+            log::set_max_files(parse_number(value)? as usize);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "log-rotate-interval",
+        get: |_| log::rotate_interval().as_secs().to_string(),
+        set: Some(|_, value| {
+            log::set_rotate_interval(Duration::from_secs(parse_number(value)?));
+            Ok(())
+        }),
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        get: |config| config.slowlog_log_slower_than().to_string(),
+        set: Some(|config, value| {
+            let micros = value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer".to_string())?;
+            config
+                .slowlog_log_slower_than
+                .store(micros, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "slowlog-max-len",
+        get: |config| config.slowlog_max_len().to_string(),
+        set: Some(|config, value| {
+            let max_len = parse_number(value)?;
+            config
+                .slowlog_max_len
+                .store(max_len as usize, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        get: |config| config.latency_monitor_threshold().to_string(),
+        set: Some(|config, value| {
+            let millis = parse_number(value)?;
+            config
+                .latency_monitor_threshold
+                .store(millis, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "latency-tracking",
+        get: |config| yes_no(config.latency_tracking()),
+        set: Some(|config, value| {
+            config
+                .latency_tracking
+                .store(parse_yes_no(value)?, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "latency-tracking-info-percentiles",
+        get: |config| {
+            config
+                .latency_tracking_info_percentiles()
+                .iter()
+                .map(|percentile| percentile.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: Some(|config, value| {
+            let percentiles = value
+                .split_ascii_whitespace()
+                .map(|percentile| match percentile.parse::<f64>() {
+                    Ok(percentile) if (0.0..=100.0).contains(&percentile) => Ok(percentile),
+                    _ => Err("percentiles must be numbers between 0 and 100".to_string()),
+                })
+                .collect::<Result<_, _>>()?;
+            *config.latency_tracking_info_percentiles.write().unwrap() = percentiles;
+            Ok(())
+        }),
+    },
+    Param {
+        name: "enable-debug-command",
+        get: |config| {
+            match config.enable_debug_command() {
+                DebugCommand::No => "no",
+                DebugCommand::Yes => "yes",
+                DebugCommand::Local => "local",
+            }
+            .to_string()
+        },
+        set: None,
+    },
+    Param {
+        name: "dir",
+        get: |config| config.dir.read().unwrap().display().to_string(),
+        set: Some(|config, value| {
+            if !Path::new(value).is_dir() {
+                return Err("No such directory".into());
+            }
+
+            *config.dir.write().unwrap() = PathBuf::from(value);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "dbfilename",
+        get: |config| config.dbfilename.read().unwrap().clone(),
+        set: Some(|config, value| {
+            if value.is_empty() || value.contains(std::path::is_separator) {
+                return Err("dbfilename can't be a path, just a filename".into());
+            }
+
+            *config.dbfilename.write().unwrap() = value.to_string();
+            Ok(())
+        }),
+    },
+    Param {
+        name: "snapshot-compression",
+        get: |config| config.snapshot_compression().name().to_string(),
+        set: Some(|config, value| {
+            let compression = if value.eq_ignore_ascii_case("no") {
+                SnapshotCompression::No
+            } else if value.eq_ignore_ascii_case("lz4") {
+                SnapshotCompression::Lz4
+            } else if value.eq_ignore_ascii_case("zstd") {
+                SnapshotCompression::Zstd
+            } else {
+                return Err("argument must be one of the following: no, lz4, zstd".into());
+            };
+
+            if !compression.is_supported() {
+                return Err(format!(
+                    "walrus was built without the '{}' feature",
+                    compression.name()
+                ));
+            }
+
+            config
+                .snapshot_compression
+                .store(compression as u8, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "save-on-shutdown",
+        get: |config| yes_no(config.save_on_shutdown()),
+        set: Some(|config, value| {
+            let save = parse_yes_no(value)?;
+            config.save_on_shutdown.store(save, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "databases",
+        get: |config| config.databases.to_string(),
+        set: None,
+    },
+    Param {
+        name: "appendonly",
+        get: |config| yes_no(config.appendonly),
+        set: None,
+    },
+    Param {
+        name: "appendfilename",
+        get: |config| config.appendfilename.clone(),
+        set: None,
+    },
+    Param {
+        name: "appendfsync",
+        get: |config| {
+            match config.appendfsync() {
+                AppendFsync::Always => "always",
+                AppendFsync::Everysec => "everysec",
+                AppendFsync::No => "no",
+            }
+            .to_string()
+        },
+        set: Some(|config, value| {
+            let policy = if value.eq_ignore_ascii_case("always") {
+                AppendFsync::Always
+            } else if value.eq_ignore_ascii_case("everysec") {
+                AppendFsync::Everysec
+            } else if value.eq_ignore_ascii_case("no") {
+                AppendFsync::No
+            } else {
+                return Err("argument must be one of the following: always, everysec, no".into());
+            };
+
+            config.appendfsync.store(policy as u8, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "repl-backlog-size",
+        get: |config| config.repl_backlog_size().to_string(),
+        set: Some(|config, value| {
+            // Too small a backlog would never allow a partial resynchronization.
+            let size = parse_memory(value)?.max(MIN_REPL_BACKLOG_SIZE);
+            config.repl_backlog_size.store(size, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "aof-load-truncated",
+        get: |config| yes_no(config.aof_load_truncated()),
+        set: Some(|config, value| {
+            let truncate = parse_yes_no(value)?;
+            config.aof_load_truncated.store(truncate, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "replica-read-only",
+        get: |config| yes_no(config.replica_read_only()),
+        set: Some(|config, value| {
+            let read_only = parse_yes_no(value)?;
+            config.replica_read_only.store(read_only, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "read-only",
+        get: |config| yes_no(config.read_only()),
+        set: Some(|config, value| {
+            let read_only = parse_yes_no(value)?;
+            config.read_only.store(read_only, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "expire-jitter",
+        get: |config| config.expire_jitter().to_string(),
+        set: Some(|config, value| {
+            let percent = parse_number(value)?;
+            if percent > 100 {
+                return Err("argument must be between 0 and 100 inclusive".into());
+            }
+
+            config.expire_jitter.store(percent as u8, Ordering::Relaxed);
+            Ok(())
+        }),
+    },
+    Param {
+        name: "lazyfree-lazy-eviction",
+        get: |config| yes_no(config.is_lazyfree(Lazyfree::Eviction)),
+        set: Some(|config, value| config.set_lazyfree(Lazyfree::Eviction, value)),
+    },
+    Param {
+        name: "lazyfree-lazy-expire",
+        get: |config| yes_no(config.is_lazyfree(Lazyfree::Expire)),
+        set: Some(|config, value| config.set_lazyfree(Lazyfree::Expire, value)),
+    },
+    Param {
+        name: "lazyfree-lazy-server-del",
+        get: |config| yes_no(config.is_lazyfree(Lazyfree::ServerDel)),
+        set: Some(|config, value| config.set_lazyfree(Lazyfree::ServerDel, value)),
+    },
+    Param {
+        name: "lazyfree-lazy-user-del",
+        get: |config| yes_no(config.is_lazyfree(Lazyfree::UserDel)),
+        set: Some(|config, value| config.set_lazyfree(Lazyfree::UserDel, value)),
+    },
+    Param {
+        name: "replica-lazy-flush",
+        get: |config| yes_no(config.is_lazyfree(Lazyfree::ReplicaFlush)),
+        set: Some(|config, value| config.set_lazyfree(Lazyfree::ReplicaFlush, value)),
+    },
+];
+
+impl Config {
+    /// Create the configuration with default values, except for the values given on the command
+    /// line.
+    pub(crate) fn new(
+        port: u16,
+        read_buffer_size: Option<u16>,
+        write_buffer_size: Option<u16>,
+        dir: Option<PathBuf>,
+        databases: Option<usize>,
+        appendonly: bool,
+    ) -> Config {
+        Config {
+            port,
+            maxclients: AtomicUsize::new(10000),
+            protected_mode: AtomicBool::new(true),
+            maxclients_per_ip: AtomicUsize::new(0),
+            ratelimit_per_ip: AtomicU64::new(0),
+            ratelimit_per_ip_action: AtomicU8::new(RatelimitAction::Throttle as u8),
+            timeout: AtomicU64::new(0),
+            // The default of Redis.
+            tcp_keepalive: AtomicU64::new(300),
+            tcp_nodelay: AtomicBool::new(true),
+            tcp_sndbuf: AtomicU64::new(0),
+            tcp_rcvbuf: AtomicU64::new(0),
+            output_limits: Arc::new(RwLock::new([
+                OutputLimit::default(),
+                OutputLimit {
+                    hard: 256 * 1024 * 1024,
+                    soft: 64 * 1024 * 1024,
+                    soft_seconds: 60,
+                },
+                OutputLimit {
+                    hard: 32 * 1024 * 1024,
+                    soft: 8 * 1024 * 1024,
+                    soft_seconds: 60,
+                },
+            ])),
+            read_buffer_size: AtomicU16::new(read_buffer_size.unwrap_or(16)),
+            write_buffer_size: AtomicU16::new(write_buffer_size.unwrap_or(16)),
+            proto_max_bulk_len: AtomicU64::new(Limits::default().max_bulk_len as u64),
+            proto_max_multibulk_len: AtomicU64::new(Limits::default().max_aggregate_len as u64),
+            client_query_buffer_limit: AtomicU64::new(Limits::default().max_frame_size as u64),
+            maxmemory: AtomicU64::new(0),
+            maxmemory_policy: AtomicU8::new(MaxmemoryPolicy::NoEviction as u8),
+            maxmemory_samples: AtomicUsize::new(5),
+            list_max_listpack_entries: AtomicUsize::new(ListpackLimits::default().entries),
+            list_max_listpack_value: AtomicUsize::new(ListpackLimits::default().value),
+            notify_keyspace_events: AtomicU32::new(0),
+            slowlog_log_slower_than: AtomicI64::new(10000),
+            slowlog_max_len: AtomicUsize::new(128),
+            latency_monitor_threshold: AtomicU64::new(0),
+            latency_tracking: AtomicBool::new(true),
+            latency_tracking_info_percentiles: RwLock::new(vec![50.0, 99.0, 99.9]),
+            enable_debug_command: AtomicU8::new(DebugCommand::No as u8),
+            dir: RwLock::new(dir.unwrap_or_else(|| PathBuf::from("."))),
+            dbfilename: RwLock::new("dump.wdb".to_string()),
+            snapshot_compression: AtomicU8::new(SnapshotCompression::No as u8),
+            save_on_shutdown: AtomicBool::new(true),
+            databases: databases.unwrap_or(16),
+            appendonly,
+            appendfilename: "appendonly.aof".to_string(),
+            appendfsync: AtomicU8::new(AppendFsync::Everysec as u8),
+            repl_backlog_size: AtomicU64::new(1024 * 1024),
+            aof_load_truncated: AtomicBool::new(true),
+            lazyfree: AtomicU8::new(0),
+            expire_jitter: AtomicU8::new(0),
+            replica_read_only: AtomicBool::new(true),
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub(crate) fn maxclients(&self) -> usize {
+        self.maxclients.load(Ordering::Relaxed)
+    }
+
+    /// Set `maxclients` before the server starts, see `server::Builder::max_connections`.
+    pub(crate) fn set_maxclients(&self, maxclients: usize) {
+        self.maxclients.store(maxclients, Ordering::Relaxed);
+    }
+
+    pub(crate) fn protected_mode(&self) -> bool {
+        self.protected_mode.load(Ordering::Relaxed)
+    }
+
+    /// Set `protected-mode` before the server starts, see `server::Builder::protected_mode`.
+    pub(crate) fn set_protected_mode(&self, protected: bool) {
+        self.protected_mode.store(protected, Ordering::Relaxed);
+    }
+
+    pub(crate) fn maxclients_per_ip(&self) -> usize {
+        self.maxclients_per_ip.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn ratelimit_per_ip(&self) -> u64 {
+        self.ratelimit_per_ip.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn ratelimit_per_ip_action(&self) -> RatelimitAction {
+        match self.ratelimit_per_ip_action.load(Ordering::Relaxed) {
+            1 => RatelimitAction::Disconnect,
+            _ => RatelimitAction::Throttle,
+        }
+    }
+
+    /// Limits on the output queued for clients, shared with the connections.
+    pub(crate) fn output_limits(&self) -> OutputLimits {
+        self.output_limits.clone()
+    }
+
+    pub(crate) fn timeout(&self) -> u64 {
+        self.timeout.load(Ordering::Relaxed)
+    }
+
+    /// Set `timeout` in seconds before the server starts, see `server::Builder::timeout`.
+    pub(crate) fn set_timeout(&self, timeout: u64) {
+        self.timeout.store(timeout, Ordering::Relaxed);
+    }
+
+    /// Options of the TCP sockets of new connections.
+    pub(crate) fn socket_options(&self) -> SocketOptions {
+        // 0 keeps the OS default.
+        let size = |value: &AtomicU64| match value.load(Ordering::Relaxed) {
+            0 => None,
+            size => Some(usize::try_from(size).unwrap_or(usize::MAX)),
+        };
+
+        let keepalive = self.tcp_keepalive.load(Ordering::Relaxed);
+        SocketOptions {
+            nodelay: self.tcp_nodelay.load(Ordering::Relaxed),
+            keepalive: (keepalive > 0).then(|| Duration::from_secs(keepalive)),
+            send_buffer_size: size(&self.tcp_sndbuf),
+            recv_buffer_size: size(&self.tcp_rcvbuf),
+        }
+    }
+
+    pub(crate) fn read_buffer_size(&self) -> u16 {
+        self.read_buffer_size.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn write_buffer_size(&self) -> u16 {
+        self.write_buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// Limits on the requests of clients.
+    pub(crate) fn frame_limits(&self) -> Limits {
+        // Limits beyond the address space are no limits.
+        let limit = |value: &AtomicU64| {
+            usize::try_from(value.load(Ordering::Relaxed)).unwrap_or(usize::MAX)
+        };
+
+        Limits {
+            max_bulk_len: limit(&self.proto_max_bulk_len),
+            max_aggregate_len: limit(&self.proto_max_multibulk_len),
+            max_frame_size: limit(&self.client_query_buffer_limit),
+            max_depth: Limits::default().max_depth,
+        }
+    }
+
+    pub(crate) fn maxmemory(&self) -> u64 {
+        self.maxmemory.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn maxmemory_policy(&self) -> MaxmemoryPolicy {
+        match self.maxmemory_policy.load(Ordering::Relaxed) {
+            1 => MaxmemoryPolicy::AllkeysLru,
+            2 => MaxmemoryPolicy::VolatileLru,
+            3 => MaxmemoryPolicy::AllkeysLfu,
+            4 => MaxmemoryPolicy::VolatileLfu,
+            _ => MaxmemoryPolicy::NoEviction,
+        }
+    }
+
+    pub(crate) fn maxmemory_samples(&self) -> usize {
+        self.maxmemory_samples.load(Ordering::Relaxed)
+    }
+
+    /// Thresholds under which lists are stored as listpacks.
+    pub(crate) fn listpack_limits(&self) -> ListpackLimits {
+        ListpackLimits {
+            entries: self.list_max_listpack_entries.load(Ordering::Relaxed),
+            value: self.list_max_listpack_value.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn notify_keyspace_events(&self) -> u32 {
+        self.notify_keyspace_events.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn slowlog_log_slower_than(&self) -> i64 {
+        self.slowlog_log_slower_than.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn slowlog_max_len(&self) -> usize {
+        self.slowlog_max_len.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn latency_monitor_threshold(&self) -> u64 {
+        self.latency_monitor_threshold.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn latency_tracking(&self) -> bool {
+        self.latency_tracking.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn latency_tracking_info_percentiles(&self) -> Vec<f64> {
+        self.latency_tracking_info_percentiles
+            .read()
+            .unwrap()
+            .clone()
+    }
+
+    pub(crate) fn enable_debug_command(&self) -> DebugCommand {
+        match self.enable_debug_command.load(Ordering::Relaxed) {
+            1 => DebugCommand::Yes,
+            2 => DebugCommand::Local,
+            _ => DebugCommand::No,
+        }
+    }
+
+    /// Path of the snapshot file, `dbfilename` within `dir`.
+    pub(crate) fn snapshot_path(&self) -> PathBuf {
+        self.dir
+            .read()
+            .unwrap()
+            .join(&*self.dbfilename.read().unwrap())
+    }
+
+    pub(crate) fn save_on_shutdown(&self) -> bool {
+        self.save_on_shutdown.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot_compression(&self) -> SnapshotCompression {
+        match self.snapshot_compression.load(Ordering::Relaxed) {
+            1 => SnapshotCompression::Lz4,
+            2 => SnapshotCompression::Zstd,
+            _ => SnapshotCompression::No,
+        }
+    }
+
+    pub(crate) fn databases(&self) -> usize {
+        self.databases
+    }
+
+    pub(crate) fn appendonly(&self) -> bool {
+        self.appendonly
+    }
+
+    pub(crate) fn appendfsync(&self) -> AppendFsync {
+        match self.appendfsync.load(Ordering::Relaxed) {
+            0 => AppendFsync::Always,
+            1 => AppendFsync::No,
+            _ => AppendFsync::Everysec,
+        }
+    }
+
+    pub(crate) fn repl_backlog_size(&self) -> u64 {
+        self.repl_backlog_size.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn aof_load_truncated(&self) -> bool {
+        self.aof_load_truncated.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn replica_read_only(&self) -> bool {
+        self.replica_read_only.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Set `enable-debug-command` before the server starts, see
+    /// `server::Builder::enable_debug_command`.
+    pub(crate) fn set_enable_debug_command(&self, mode: DebugCommand) {
+        self.enable_debug_command
+            .store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Set `read-only` before the server starts, see `server::Builder::read_only`.
+    pub(crate) fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Deletions freeing large values on a background thread, a combination of `Lazyfree`
+    /// flags.
+    pub(crate) fn lazyfree(&self) -> u8 {
+        self.lazyfree.load(Ordering::Relaxed)
+    }
+
+    /// Percentage of its time to live randomly added to an expiration set by a command.
+    pub(crate) fn expire_jitter(&self) -> u8 {
+        self.expire_jitter.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if `deletion` frees large values on a background thread.
+    fn is_lazyfree(&self, deletion: Lazyfree) -> bool {
+        self.lazyfree() & deletion.flag() != 0
+    }
+
+    /// Enable or disable freeing large values on a background thread for `deletion`.
+    fn set_lazyfree(&self, deletion: Lazyfree, value: &str) -> Result<(), String> {
+        if parse_yes_no(value)? {
+            self.lazyfree.fetch_or(deletion.flag(), Ordering::Relaxed);
+        } else {
+            self.lazyfree.fetch_and(!deletion.flag(), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Path of the append only file, `appendfilename` within `dir`.
+    pub(crate) fn aof_path(&self) -> PathBuf {
+        self.dir.read().unwrap().join(&self.appendfilename)
+    }
+
+    /// Get the name and value of every parameter matching one of the glob-style `patterns`.
+    ///
+    /// Names are matched case insensitively, each parameter is returned at most once.
+    pub(crate) fn get(&self, patterns: &[Bytes]) -> Vec<(&'static str, String)> {
+        PARAMS
+            .iter()
+            .filter(|param| {
+                patterns
+                    .iter()
+                    .any(|pattern| glob_match(pattern, param.name.as_bytes(), true))
+            })
+            .map(|param| (param.name, (param.get)(self)))
+            .collect()
+    }
+
+    /// Parameters that can be changed at runtime, with their values.
+    pub(crate) fn mutable_params(&self) -> Vec<(&'static str, String)> {
+        PARAMS
+            .iter()
+            .filter(|param| param.set.is_some())
+            .map(|param| (param.name, (param.get)(self)))
+            .collect()
+    }
+
+    /// Returns `true` if `name` is a parameter of the process, such as `loglevel`, rather than
+    /// of this server.
+    pub(crate) fn is_process_param(name: &str) -> bool {
+        PROCESS_PARAMS
+            .iter()
+            .any(|param| param.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns `true` if `name` is a parameter that can be changed at runtime.
+    pub(crate) fn is_mutable(name: &str) -> bool {
+        PARAMS
+            .iter()
+            .any(|param| param.name.eq_ignore_ascii_case(name) && param.set.is_some())
+    }
+
+    /// Pass the parameters `db` reads as values are written and deleted, after they are set.
+    pub(crate) fn update_db(&self, db: &Db) {
+        db.set_listpack_limits(self.listpack_limits());
+        db.set_lazyfree(self.lazyfree());
+        db.set_expire_jitter(self.expire_jitter());
+    }
+
+    /// Set each parameter to its value.
+    ///
+    /// Either all parameters are set or none are, if a value is invalid the parameters already
+    /// set are restored. Returns the error message on failure.
+    pub(crate) fn set(&self, pairs: &[(Bytes, Bytes)]) -> Result<(), String> {
+        let mut params: Vec<(&Param, _, _)> = Vec::with_capacity(pairs.len());
+
+        for (name, value) in pairs {
+            let param = PARAMS
+                .iter()
+                .find(|param| param.name.as_bytes().eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown option or number of arguments for CONFIG SET - '{}'",
+                        String::from_utf8_lossy(name)
+                    )
+                })?;
+
+            let set = param
+                .set
+                .ok_or_else(|| format!("can't set immutable config '{}'", param.name))?;
+
+            if params
+                .iter()
+                .any(|(other, _, _)| std::ptr::eq(*other, param))
+            {
+                return Err(format!("duplicate parameter '{}'", param.name));
+            }
+
+            params.push((param, set, String::from_utf8_lossy(value)));
+        }
+
+        // Previous values of the parameters already set.
+        let mut previous: Vec<String> = Vec::with_capacity(params.len());
+
+        for (param, set, value) in &params {
+            let old = (param.get)(self);
+
+            if let Err(err) = set(self, value) {
+                // Restore the parameters already set, their previous values are known to be
+                // valid.
+                for ((_, set, _), old) in params.iter().zip(&previous) {
+                    let _ = set(self, old);
+                }
+
+                return Err(format!("Failed to set '{}' - {err}", param.name));
+            }
+
+            previous.push(old);
+        }
+
+        Ok(())
+    }
+}
+
+impl Lazyfree {
+    /// Flag of the deletion in a combination of `Lazyfree` flags.
+    pub(crate) fn flag(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl MaxmemoryPolicy {
+    /// Name of the policy, as set with `CONFIG SET maxmemory-policy`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            MaxmemoryPolicy::NoEviction => "noeviction",
+            MaxmemoryPolicy::AllkeysLru => "allkeys-lru",
+            MaxmemoryPolicy::VolatileLru => "volatile-lru",
+            MaxmemoryPolicy::AllkeysLfu => "allkeys-lfu",
+            MaxmemoryPolicy::VolatileLfu => "volatile-lfu",
+        }
+    }
+
+    /// Returns `true` if keys are evicted by access frequency, which `OBJECT FREQ` reports.
+    pub(crate) fn is_lfu(self) -> bool {
+        matches!(
+            self,
+            MaxmemoryPolicy::AllkeysLfu | MaxmemoryPolicy::VolatileLfu
+        )
+    }
+}
+
+impl SnapshotCompression {
+    /// Name of the compression, also the name of the feature providing it.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            SnapshotCompression::No => "no",
+            SnapshotCompression::Lz4 => "lz4",
+            SnapshotCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Returns `true` if the server was built with the feature providing the compression.
+    pub(crate) fn is_supported(self) -> bool {
+        match self {
+            SnapshotCompression::No => true,
+            SnapshotCompression::Lz4 => cfg!(feature = "lz4"),
+            SnapshotCompression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+}
+
+/// Parse a non negative integer.
+fn parse_number(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| "argument couldn't be parsed into an integer".to_string())
+}
+
+/// Value of a boolean parameter.
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+/// Parse the value of a boolean parameter.
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    if value.eq_ignore_ascii_case("yes") {
+        Ok(true)
+    } else if value.eq_ignore_ascii_case("no") {
+        Ok(false)
+    } else {
+        Err("argument must be one of the following: yes, no".to_string())
+    }
+}
+
+/// Parse a buffer size in KB.
+fn parse_buffer_size(value: &str) -> Result<u16, String> {
+    match parse_number(value)? {
+        size @ 1..=65535 => Ok(size as u16),
+        _ => Err("argument must be between 1 and 65535 inclusive".into()),
+    }
+}
+
+/// Parse a memory amount, either in bytes or with one of the `k`, `kb`, `m`, `mb`, `g` and `gb`
+/// units. `k` is 1000 bytes and `kb` is 1024 bytes, the same for the other units.
+fn parse_memory(value: &str) -> Result<u64, String> {
+    let lower = value.to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+
+    let multiplier = match &lower[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".into()),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(multiplier))
+        .ok_or_else(|| "argument must be a memory value".to_string())
+}
+
+/// Parse keyspace notification flags, for example `KEx`.
+fn parse_notify_flags(value: &str) -> Result<u32, String> {
+    let mut flags = 0;
+
+    for byte in value.bytes() {
+        flags |= if byte == b'A' {
+            notify::ALL
+        } else {
+            notify::FLAGS
+                .iter()
+                .find(|(flag, _)| *flag == byte)
+                .map(|(_, class)| *class)
+                .ok_or_else(|| "Invalid event class character. Use 'Ag$lshzxeKEtmn'.".to_string())?
+        };
+    }
+
+    Ok(flags)
+}
+
+/// Render keyspace notification flags, using the `A` alias when possible.
+fn notify_flags_to_string(mut flags: u32) -> String {
+    let mut out = String::new();
+
+    if flags & notify::ALL == notify::ALL {
+        out.push('A');
+        flags &= !notify::ALL;
+    }
+
+    for (flag, class) in notify::FLAGS {
+        if flags & class != 0 {
+            out.push(flag as char);
+        }
+    }
+
+    out
+}
@@ -0,0 +1,368 @@
+use bytes::Bytes;
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Weak,
+    time::{Duration, SystemTime},
+};
+use tokio::time::{self, MissedTickBehavior};
+use toml::{Table, Value};
+use toml_edit::DocumentMut;
+use tracing::{info, warn};
+
+use super::Config;
+use crate::server::ServerState;
+
+/// Interval the configuration file is checked for changes at, see `reload_task`.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configuration file of a server, in TOML. Keys are the names of the parameters of
+/// `CONFIG SET`, values are strings, numbers, booleans or arrays of them:
+///
+/// ```toml
+/// port = 6380
+/// bind = ["127.0.0.1", "::1"]
+/// maxmemory = "1gb"
+/// maxmemory-policy = "allkeys-lru"
+/// appendonly = true
+/// ```
+///
+/// Commands are renamed or disabled, with an empty name, in the `rename-command` table. Like
+/// any table it goes after the other keys:
+///
+/// ```toml
+/// [rename-command]
+/// config = "config-b840fc02"
+/// debug = ""
+/// ```
+///
+/// Options applied before the server starts, such as the addresses to bind, are taken out of
+/// the file with `take`. The remaining parameters are set like with `CONFIG SET` when the server
+/// starts, see `server::Builder::config_file`.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    params: Vec<(String, Value)>,
+}
+
+impl ConfigFile {
+    /// Read the configuration file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<ConfigFile> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("failed to read {}, {err}", path.display()),
+            )
+        })?;
+        let table: Table = toml::from_str(&contents).map_err(|err| invalid(path, err))?;
+
+        let params = table
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+        Ok(ConfigFile {
+            path: path.to_path_buf(),
+            params,
+        })
+    }
+
+    /// Path the file was read from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Take the parameter `name` out of the file, parsed as a `T`. Booleans are given as `yes`
+    /// or `no`, like `CONFIG GET` shows them.
+    pub fn take<T>(&mut self, name: &str) -> io::Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = self.take_value(name) else {
+            return Ok(None);
+        };
+        let value = to_param(&value).ok_or_else(|| unsupported(&self.path, name))?;
+        value
+            .parse()
+            .map(Some)
+            .map_err(|err| invalid(&self.path, format!("invalid '{name}', {err}")))
+    }
+
+    /// Take the boolean parameter `name` out of the file, given as `true` or `false`, or as
+    /// `yes` or `no`.
+    pub fn take_flag(&mut self, name: &str) -> io::Result<Option<bool>> {
+        match self.take_value(name) {
+            None => Ok(None),
+            Some(Value::Boolean(flag)) => Ok(Some(flag)),
+            Some(Value::String(flag)) if flag.eq_ignore_ascii_case("yes") => Ok(Some(true)),
+            Some(Value::String(flag)) if flag.eq_ignore_ascii_case("no") => Ok(Some(false)),
+            Some(_) => Err(invalid(
+                &self.path,
+                format!("invalid '{name}', expected a boolean"),
+            )),
+        }
+    }
+
+    /// Take the list `name` out of the file, an array or a single value.
+    pub fn take_list(&mut self, name: &str) -> io::Result<Option<Vec<String>>> {
+        let Some(value) = self.take_value(name) else {
+            return Ok(None);
+        };
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        values
+            .iter()
+            .map(|value| to_param(value).ok_or_else(|| unsupported(&self.path, name)))
+            .collect::<io::Result<_>>()
+            .map(Some)
+    }
+
+    /// Take the table `name` out of the file, its keys with their values.
+    pub fn take_table(&mut self, name: &str) -> io::Result<Option<Vec<(String, String)>>> {
+        let Some(value) = self.take_value(name) else {
+            return Ok(None);
+        };
+        let Value::Table(table) = value else {
+            return Err(invalid(
+                &self.path,
+                format!("invalid '{name}', expected a table"),
+            ));
+        };
+        table
+            .iter()
+            .map(|(key, value)| {
+                let value = to_param(value).ok_or_else(|| unsupported(&self.path, name))?;
+                Ok((key.clone(), value))
+            })
+            .collect::<io::Result<_>>()
+            .map(Some)
+    }
+
+    fn take_value(&mut self, name: &str) -> Option<Value> {
+        let pos = self
+            .params
+            .iter()
+            .position(|(param, _)| param.eq_ignore_ascii_case(name))?;
+        Some(self.params.remove(pos).1)
+    }
+
+    /// Parameters left in the file, as `CONFIG SET` takes them.
+    pub(crate) fn params(&self) -> io::Result<Vec<(Bytes, Bytes)>> {
+        self.params
+            .iter()
+            .map(|(name, value)| {
+                let value = to_param(value).ok_or_else(|| unsupported(&self.path, name))?;
+                Ok((Bytes::from(name.clone()), Bytes::from(value)))
+            })
+            .collect()
+    }
+
+    /// Set the parameters left in the file on `config`, all of them or none.
+    pub(crate) fn apply(&self, config: &Config) -> io::Result<()> {
+        config
+            .set(&self.params()?)
+            .map_err(|err| invalid(&self.path, err))
+    }
+
+    /// Set the parameters of the file that can be changed at runtime on `config`, all of them or
+    /// none. The others only apply when the server starts, they are ignored, even if they
+    /// aren't values `CONFIG SET` takes, such as the `rename-command` table.
+    pub(crate) fn reload(&self, config: &Config) -> io::Result<()> {
+        let params = self
+            .params
+            .iter()
+            .filter(|(name, _)| Config::is_mutable(name))
+            .map(|(name, value)| {
+                let value = to_param(value).ok_or_else(|| unsupported(&self.path, name))?;
+                Ok((Bytes::from(name.clone()), Bytes::from(value)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        config.set(&params).map_err(|err| invalid(&self.path, err))
+    }
+}
+
+/// Write the parameters of `config` that can be changed at runtime to the configuration file at
+/// `path`, for `CONFIG REWRITE`.
+///
+/// Parameters already in the file are only replaced if their value changed, keeping the
+/// comments and layout of the file. The others are added if they differ from their default.
+/// Keys that aren't parameters changed at runtime, such as `bind`, are left as they are.
+pub(crate) fn rewrite(path: &Path, config: &Config) -> io::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    let mut doc: DocumentMut = contents.parse().map_err(|err| invalid(path, err))?;
+
+    let defaults = Config::new(
+        config.port(),
+        None,
+        None,
+        None,
+        Some(config.databases()),
+        config.appendonly(),
+    );
+    let scratch = Config::new(
+        config.port(),
+        None,
+        None,
+        None,
+        Some(config.databases()),
+        config.appendonly(),
+    );
+    for ((name, value), (_, default)) in config
+        .mutable_params()
+        .into_iter()
+        .zip(defaults.mutable_params())
+    {
+        let key = doc
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(key, _)| key.to_string());
+
+        match key {
+            Some(key) => {
+                let item = &mut doc[key.as_str()];
+                if let Some(current) = item.as_value_mut() {
+                    if normalized(&scratch, name, current).is_some_and(|current| current == value) {
+                        continue;
+                    }
+                    // Comments after the value are kept.
+                    let decor = current.decor().clone();
+                    *current = typed(&value, current.is_bool());
+                    *current.decor_mut() = decor;
+                } else {
+                    *item = toml_edit::value(typed(&value, false));
+                }
+            }
+            None if value != default => doc[name] = toml_edit::value(typed(&value, false)),
+            None => {}
+        }
+    }
+
+    // Written to a temporary file renamed over the file, it's never left half written.
+    let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+    fs::write(&temp, doc.to_string())
+        .and_then(|_| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+}
+
+/// Background task applying the changes made to the configuration file at `path`. The file is
+/// checked every second, the parameters that can be changed at runtime are set again from it
+/// once it's modified. An invalid file is reported and the configuration kept as it is.
+///
+/// Holds a weak reference so the task ends when the server is dropped.
+pub(crate) async fn reload_task(path: PathBuf, server: Weak<ServerState>) {
+    let mut interval = time::interval(RELOAD_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut modified = modified_time(&path);
+
+    loop {
+        interval.tick().await;
+
+        let Some(server) = server.upgrade() else {
+            return;
+        };
+
+        let now = modified_time(&path);
+        if now == modified {
+            continue;
+        }
+        modified = now;
+
+        match ConfigFile::load(&path).and_then(|file| file.reload(&server.config)) {
+            Ok(()) => {
+                server.update_databases();
+                info!(path = %path.display(), "Reloaded the configuration");
+            }
+            Err(err) => warn!(%err, "Failed to reload the configuration, it is unchanged"),
+        }
+    }
+}
+
+/// Modification time of the file at `path`, `None` if it can't be read.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Value of a parameter as `CONFIG SET` takes it. Elements of arrays are separated by spaces,
+/// like the classes of `client-output-buffer-limit`.
+fn to_param(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Boolean(value) => Some(if *value { "yes" } else { "no" }.to_string()),
+        Value::Array(values) => values
+            .iter()
+            .map(to_param)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(" ")),
+        Value::Datetime(_) | Value::Table(_) => None,
+    }
+}
+
+/// Value of a parameter in a file being rewritten, as `CONFIG SET` takes it. See `to_param`.
+fn edit_to_param(value: &toml_edit::Value) -> Option<String> {
+    match value {
+        toml_edit::Value::String(value) => Some(value.value().clone()),
+        toml_edit::Value::Integer(value) => Some(value.value().to_string()),
+        toml_edit::Value::Float(value) => Some(value.value().to_string()),
+        toml_edit::Value::Boolean(value) => {
+            Some(if *value.value() { "yes" } else { "no" }.to_string())
+        }
+        toml_edit::Value::Array(values) => values
+            .iter()
+            .map(edit_to_param)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(" ")),
+        toml_edit::Value::Datetime(_) | toml_edit::Value::InlineTable(_) => None,
+    }
+}
+
+/// Value of the parameter `name` in a file being rewritten as `CONFIG GET` shows it, `1gb` is
+/// `1073741824`. `scratch` is the configuration it's set on. Parameters of the process, such as
+/// the log file, aren't normalized since setting them on `scratch` would change the process.
+fn normalized(scratch: &Config, name: &str, value: &toml_edit::Value) -> Option<String> {
+    if Config::is_process_param(name) {
+        return edit_to_param(value);
+    }
+    let name = Bytes::from(name.to_string());
+    scratch
+        .set(&[(name.clone(), Bytes::from(edit_to_param(value)?))])
+        .ok()?;
+    scratch.get(&[name]).pop().map(|(_, value)| value)
+}
+
+/// Value written to the file for the parameter value `value`, an integer if it is one. `yes`
+/// and `no` are written as booleans if `boolean` is set, replacing a boolean, otherwise they are
+/// kept as strings since some parameters take `no` along with other words.
+fn typed(value: &str, boolean: bool) -> toml_edit::Value {
+    match value {
+        "yes" if boolean => true.into(),
+        "no" if boolean => false.into(),
+        _ => match value.parse::<i64>() {
+            Ok(value) => value.into(),
+            Err(_) => value.into(),
+        },
+    }
+}
+
+fn unsupported(path: &Path, name: &str) -> io::Error {
+    invalid(path, format!("unsupported value of '{name}'"))
+}
+
+fn invalid(path: &Path, err: impl Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid configuration file {}, {err}", path.display()),
+    )
+}
@@ -1,12 +1,23 @@
-use std::io::{self, Cursor};
+use std::collections::VecDeque;
+use std::io::{self, Cursor, IoSlice};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use bytes::{BufMut, BytesMut};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+#[cfg(unix)]
+use tokio::net::{UnixStream, unix};
+use tracing::warn;
 
-use crate::db::Data;
+use crate::db::{Data, Value};
 use crate::errors::WalrusError;
-use crate::frame::Frame;
+use crate::frame::{self, Frame, Limits};
 
 /// Send and receive `Frame` values from a remote peer.
 ///
@@ -19,17 +30,127 @@ use crate::frame::Frame;
 /// The contents of the write buffer are then written to the socket.
 #[derive(Debug)]
 pub struct Connection {
-    stream: TcpStream,
+    /// `None` for detached connections, whose replies are discarded.
+    stream: Option<Stream>,
     // Buffer for reading frames.
     buffer: BytesMut,
+    /// Initial capacity of `buffer`, it is shrunk back to it after large frames.
+    read_capacity: usize,
     // Buffer for writing frames.
     write_buffer: BytesMut,
+    /// Output written before `write_buffer`, large bulk strings are queued here as they are
+    /// instead of being copied. Written along with `write_buffer` using vectored writes.
+    chunks: VecDeque<Bytes>,
+    /// Unique id of the connection, assigned when the connection is created.
+    id: u64,
+    /// Protocol used for encoding replies, negotiated with `HELLO`.
+    protocol: Protocol,
+    /// Name of the connection, set with `CLIENT SETNAME` or `HELLO ... SETNAME`.
+    name: Option<Bytes>,
+    /// Number of error replies written, tells whether a command failed.
+    error_replies: u64,
+    /// Index of the database the commands of the connection use, changed with `SELECT`.
+    database: usize,
+    /// Set by `ASKING`, lets the next command access a slot being imported in cluster mode.
+    asking: bool,
+    /// Set by `CLIENT TRACEPARENT`, the trace the next command is part of.
+    trace_context: Option<TraceContext>,
+    /// Limits on the frames read, unlimited unless set with `set_limits`.
+    limits: Limits,
+    /// Time allowed for the peer to send more data when a frame is read, none by default.
+    read_timeout: Option<Duration>,
+    /// Time allowed for a flush to complete, none by default.
+    write_timeout: Option<Duration>,
+    /// Number of bytes read from the peer.
+    input_bytes: u64,
+    /// Number of bytes written to the peer.
+    output_bytes: u64,
 }
 
+/// W3C trace context of a command, sent by the client with `CLIENT TRACEPARENT`. With the
+/// `otel` feature, the span of the command continues this trace.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "otel"), allow(dead_code))]
+pub(crate) struct TraceContext {
+    /// `traceparent` header, `version-traceid-parentid-flags`.
+    pub(crate) traceparent: Bytes,
+    /// `tracestate` header, vendor specific data of the trace.
+    pub(crate) tracestate: Option<Bytes>,
+}
+
+/// RESP version used to encode replies written to a `Connection`.
+///
+/// Every connection starts with `Resp2`, `HELLO 3` switches it to `Resp3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+/// Options of the TCP socket of a `Connection`, set with `Connection::set_socket_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm, sending small replies right away instead of waiting for more
+    /// data to fill a larger packet.
+    pub nodelay: bool,
+    /// Probe idle peers with TCP keepalives after this long, so dead peers are detected. The
+    /// OS default is kept if `None`.
+    pub keepalive: Option<Duration>,
+    /// Size of the kernel send buffer in bytes, the OS default is kept if `None`.
+    pub send_buffer_size: Option<usize>,
+    /// Size of the kernel receive buffer in bytes, the OS default is kept if `None`.
+    pub recv_buffer_size: Option<usize>,
+}
+
+impl Default for SocketOptions {
+    /// `TCP_NODELAY` set, OS defaults otherwise.
+    fn default() -> SocketOptions {
+        SocketOptions {
+            nodelay: true,
+            keepalive: None,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Set the options on `stream`.
+    fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        let socket = SockRef::from(stream);
+        if let Some(time) = self.keepalive {
+            let keepalive = TcpKeepalive::new().with_time(time);
+            // Like Redis, unanswered probes are repeated three times within `time`.
+            #[cfg(target_os = "linux")]
+            let keepalive = keepalive.with_interval((time / 3).max(Duration::from_secs(1)));
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Source of connection ids. Ids start from 1 and are never reused.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Replies of pipelined commands are batched up to this many bytes before being written
+/// anyway, so a long pipeline doesn't buffer all of its replies in memory.
+const MAX_BATCHED_REPLIES: usize = 64 * 1024;
+
 impl Connection {
     /// create a new `Connection` to read and write to and from `TcpStream` using read and write
     /// buffers. The default initial size for the buffers is 16KB.
-    /// There is no hard limit on how large the buffers can get.
+    /// The read buffer grows up to the `max_frame_size` set with `set_limits`, and is shrunk
+    /// back to its initial size once a larger frame is read.
+    ///
+    /// The socket is a `TcpStream`, a `UnixStream` or, with the `tls` feature, a TLS stream.
     ///
     /// example:
     ///
@@ -38,35 +159,270 @@ impl Connection {
     /// let conn = Connection::new(socket, Some(32), Some(32));
     /// // intializes a new `Connection` with 32KB initial read and write buffers.
     pub fn new(
-        socket: TcpStream,
+        socket: impl Into<Stream>,
         read_buffer_size: Option<u16>,
         write_buffer_size: Option<u16>,
     ) -> Connection {
+        // defaults to 16KB buffers.
+        let read_capacity = read_buffer_size.unwrap_or(16) as usize * 1024;
         Connection {
-            stream: socket,
-            // defaults to 16KB buffers.
-            buffer: BytesMut::with_capacity(read_buffer_size.unwrap_or(16) as usize * 1024),
+            stream: Some(socket.into()),
+            buffer: BytesMut::with_capacity(read_capacity),
+            read_capacity,
             write_buffer: BytesMut::with_capacity(write_buffer_size.unwrap_or(16) as usize * 1024),
+            chunks: VecDeque::new(),
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            error_replies: 0,
+            database: 0,
+            asking: false,
+            trace_context: None,
+            limits: Limits::UNLIMITED,
+            read_timeout: None,
+            write_timeout: None,
+            input_bytes: 0,
+            output_bytes: 0,
+        }
+    }
+
+    /// Create a `Connection` not backed by a socket, used to execute commands that don't come
+    /// from a client such as when replaying the append only file. Nothing is ever read and
+    /// replies are discarded on flush.
+    pub(crate) fn detached() -> Connection {
+        Connection {
+            stream: None,
+            buffer: BytesMut::new(),
+            read_capacity: 0,
+            write_buffer: BytesMut::new(),
+            chunks: VecDeque::new(),
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            error_replies: 0,
+            database: 0,
+            asking: false,
+            trace_context: None,
+            limits: Limits::UNLIMITED,
+            read_timeout: None,
+            write_timeout: None,
+            input_bytes: 0,
+            output_bytes: 0,
+        }
+    }
+
+    /// Unique id of this connection.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Protocol currently used to encode replies.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Switch the protocol used to encode replies.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    /// Address of the remote peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match &self.stream {
+            Some(Stream::Tcp(stream)) => stream.peer_addr(),
+            // Peers of Unix sockets have no network address.
+            #[cfg(unix)]
+            Some(Stream::Unix(_)) => Err(io::ErrorKind::Unsupported.into()),
+            #[cfg(feature = "tls")]
+            Some(Stream::Tls(stream)) => stream.get_ref().0.peer_addr(),
+            None => Err(io::ErrorKind::NotConnected.into()),
         }
     }
 
+    /// Set the options of the TCP socket of the connection, such as `TCP_NODELAY` and
+    /// keepalives. Unix sockets have no such options, they are left as they are.
+    pub fn set_socket_options(&self, options: &SocketOptions) -> io::Result<()> {
+        match &self.stream {
+            Some(Stream::Tcp(stream)) => options.apply(stream),
+            #[cfg(feature = "tls")]
+            Some(Stream::Tls(stream)) => options.apply(stream.get_ref().0),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the peer is on the same host, connected through a Unix socket or the loopback
+    /// interface.
+    pub fn is_local(&self) -> bool {
+        match &self.stream {
+            #[cfg(unix)]
+            Some(Stream::Unix(_)) => true,
+            // Clients of listeners bound to both IPv4 and IPv6 have IPv4-mapped addresses.
+            _ => self
+                .peer_addr()
+                .is_ok_and(|addr| addr.ip().to_canonical().is_loopback()),
+        }
+    }
+
+    /// Number of error replies written so far. Comparing it before and after executing a
+    /// command tells whether the command failed.
+    pub fn error_replies(&self) -> u64 {
+        self.error_replies
+    }
+
+    /// Number of bytes read from the peer so far.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes
+    }
+
+    /// Number of bytes written to the peer so far. Replies discarded by detached connections
+    /// are not counted.
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    /// Name of the connection if one was set.
+    pub fn name(&self) -> Option<&Bytes> {
+        self.name.as_ref()
+    }
+
+    /// Set the name of the connection.
+    pub fn set_name(&mut self, name: Option<Bytes>) {
+        self.name = name;
+    }
+
+    /// Index of the database the commands of the connection use, 0 until changed with
+    /// `SELECT`.
+    pub fn database(&self) -> usize {
+        self.database
+    }
+
+    /// Use the database `index` for the next commands.
+    pub fn select(&mut self, index: usize) {
+        self.database = index;
+    }
+
+    /// Let the next command access a slot being imported, set by `ASKING`.
+    pub fn set_asking(&mut self) {
+        self.asking = true;
+    }
+
+    /// Whether `ASKING` was sent before the current command. The flag only lasts for one
+    /// command, it's cleared by this call.
+    pub fn take_asking(&mut self) -> bool {
+        std::mem::take(&mut self.asking)
+    }
+
+    /// Set the trace context of the next command, see `CLIENT TRACEPARENT`.
+    pub(crate) fn set_trace_context(&mut self, context: TraceContext) {
+        self.trace_context = Some(context);
+    }
+
+    /// Trace context sent before the current command. Like `ASKING` it only lasts for one
+    /// command, it's cleared by this call.
+    pub(crate) fn take_trace_context(&mut self) -> Option<TraceContext> {
+        self.trace_context.take()
+    }
+
+    /// Split the connection into halves which can be used independently, e.g. to write
+    /// frames from one task while another is waiting for a frame. Frames already buffered and
+    /// replies not flushed yet are kept by the halves.
+    ///
+    /// Fails with `NotConnected` for detached connections.
+    pub fn split(self) -> io::Result<(ReadHalf, WriteHalf)> {
+        let Some(stream) = self.stream else {
+            return Err(io::ErrorKind::NotConnected.into());
+        };
+        let (read, write) = match stream {
+            Stream::Tcp(stream) => {
+                let (read, write) = stream.into_split();
+                (ReadStream::Tcp(read), WriteStream::Tcp(write))
+            }
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                let (read, write) = stream.into_split();
+                (ReadStream::Unix(read), WriteStream::Unix(write))
+            }
+            // TLS streams have no owned halves, the halves share the stream instead.
+            #[cfg(feature = "tls")]
+            stream => {
+                let (read, write) = tokio::io::split(stream);
+                (ReadStream::Shared(read), WriteStream::Shared(write))
+            }
+        };
+
+        let reader = ReadHalf {
+            stream: read,
+            buffer: self.buffer,
+            read_capacity: self.read_capacity,
+            limits: self.limits,
+            read_timeout: self.read_timeout,
+        };
+        let writer = WriteHalf {
+            stream: write,
+            write_buffer: self.write_buffer,
+            chunks: self.chunks,
+            protocol: self.protocol,
+            write_timeout: self.write_timeout,
+        };
+        Ok((reader, writer))
+    }
+
     /// Flush the write buffer to the TCP stream.
     /// Only performs I/O if the write buffer is non-empty.
+    ///
+    /// Large bulk strings are streamed in pieces, the write timeout applies to each of them.
     pub async fn flush(&mut self) -> io::Result<()> {
-        if !self.write_buffer.is_empty() {
-            self.stream.write_all(&self.write_buffer).await?;
-            self.write_buffer.clear();
+        let pending = self.pending_output();
+        write_out(
+            self.stream.as_mut(),
+            &mut self.write_buffer,
+            &mut self.chunks,
+            self.write_timeout,
+        )
+        .await?;
+
+        if self.stream.is_some() {
+            self.output_bytes += pending as u64;
         }
         Ok(())
     }
 
+    /// Set the limits on the frames read from now on. A frame beyond them fails the read with a
+    /// protocol error.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Set the time allowed for the peer to send more data while a frame is read, reads fail
+    /// with `TimedOut` once it elapses. `None` waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Set the time allowed for a flush to complete, flushes fail with `TimedOut` once it
+    /// elapses. `None` waits forever. Large bulk strings are streamed in pieces, each of them is
+    /// allowed the whole time.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
     /// Check if the read buffer already contains a complete frame.
     /// Used by the server to decide whether to flush the write buffer —
     /// if more pipelined commands are buffered, we skip the flush to batch
     /// responses into a single syscall.
     pub fn has_buffered_frame(&self) -> bool {
-        let mut buf = Cursor::new(&self.buffer[..]);
-        Frame::check(&mut buf).is_ok()
+        check_buffered(&self.buffer, &self.limits).is_ok()
+    }
+
+    /// Whether the replies written so far should be flushed after a command. They are held
+    /// while more pipelined commands are buffered, unless the batch grew too large.
+    pub fn should_flush(&self) -> bool {
+        self.pending_output() >= MAX_BATCHED_REPLIES || !self.has_buffered_frame()
+    }
+
+    /// Number of bytes of replies written but not flushed yet.
+    fn pending_output(&self) -> usize {
+        self.write_buffer.len() + self.chunks.iter().map(Bytes::len).sum::<usize>()
     }
 
     /// Loops until enough data is available to read a frame from the buffer.
@@ -87,15 +443,16 @@ impl Connection {
             self.flush().await?;
 
             // Wait for client to send more data
-            // If number of bytes read into buffer is 0, then the stream has ended.
-            if 0 == self.stream.read_buf(&mut self.buffer).await? {
-                // If the stream ended with no data in the buffer it is a clean shutdown.
-                // Else it ended while sending a frame.
-                if self.buffer.is_empty() {
-                    return Ok(None);
-                } else {
-                    return Err("Connection reset by peer".into());
-                }
+            let Some(stream) = &mut self.stream else {
+                return Ok(None);
+            };
+
+            let buffered = self.buffer.len();
+            let filled = fill_buffer(stream, &mut self.buffer, self.read_capacity, &self.limits);
+            let more = within(self.read_timeout, filled).await?;
+            self.input_bytes += (self.buffer.len() - buffered) as u64;
+            if !more {
+                return Ok(None);
             }
         }
     }
@@ -103,88 +460,43 @@ impl Connection {
     /// Tries to parse a frame from the buffer. Parsed data is returned and
     /// removed from buffer. Ok(None) is returned if not enough data is buffered
     /// yet. Err is returned in case of invalid frame format.
+    ///
+    /// Inline commands, lines of space separated arguments as typed in telnet, are parsed into
+    /// an array of bulk strings. Blank lines are skipped.
     pub fn parse_frame(&mut self) -> Result<Option<Frame>, WalrusError> {
-        // Wrap the cursor in buffer to track current location in the buffer.
-        // Location starts from 0 when new cursor instance is created.
-        let mut buf = Cursor::new(&self.buffer[..]);
-
-        // Parse the frame, necessary datastructures are allocated and frame
-        // is returned.
-        //
-        // If the encoded frame is invalid, an error is returned.
-        match Frame::check(&mut buf) {
-            // Full frame is available to parse.
-            // len is inclusive of \r\n
-            Ok(len) => {
-                let frame_data = self.buffer.split_to(len);
-                let mut frozen_data = frame_data.freeze();
-                let frame = Frame::parse(&mut frozen_data)?;
-                return Ok(Some(frame));
-            }
-            // Not enough data in the buffer to parse a full frame. More data must arrive
-            // from the socket.
-            //
-            // Err is not returned as `Incomplete` 'error' is expected during the application
-            // runtime.
-            Err(crate::frame::Error::Incomplete) => Ok(None),
-            // An unexpected error occured while parsing the frame. The connection will be closed.
-            Err(e) => Err(e.into()),
-        }
+        parse_buffered(&mut self.buffer, &self.limits)
     }
 
-    /// Write a single `Frame` to the stream.
+    /// Write a single `Frame` to the stream, encoded with `Frame::write_to`.
     ///
-    /// Nested array's not supported as of yet.
+    /// Large bulk strings are not copied, they are written from the frame on flush.
+    /// RESP3 types are downgraded when the connection uses RESP2.
+    /// Aggregates may be nested to any depth.
     pub fn write_frame(&mut self, frame: &Frame) {
-        match frame {
-            Frame::Array(val) => {
-                self.write_buffer.put_u8(b'*');
-                self.write_decimal(val.len() as i64);
-
-                let iter = val.iter();
-
-                for frame in iter {
-                    self.write_val(frame);
-                }
-            }
-            // frame is a literal. Encode using helper function for writing frame literals to the
-            // stream.
-            _ => self.write_val(frame),
-        }
+        frame.write_chunked(
+            &mut self.write_buffer,
+            Some(&mut self.chunks),
+            self.protocol,
+        );
     }
 
-    /// Write a frame literal (non array) to the stream.
+    /// Write a frame literal (non array) to the stream. Aggregates are written like with
+    /// `write_frame`.
     pub fn write_val(&mut self, frame: &Frame) {
-        match frame {
-            Frame::Simple(message) => {
-                self.write_buffer.put_u8(b'+');
-                self.write_buffer.put_slice(&message);
-                self.write_buffer.put_slice(b"\r\n");
-            }
-            Frame::Error(err) => {
-                self.write_buffer.put_u8(b'-');
-                self.write_buffer.put_slice(err.as_bytes());
-                self.write_buffer.put_slice(b"\r\n");
-            }
-            Frame::Integer(val) => {
-                self.write_buffer.put_u8(b':');
-                self.write_decimal(*val);
-            }
-            Frame::Double(val) => {
-                self.write_double(*val);
-            }
-            Frame::Null => {
-                self.write_buffer.put_slice(b"$-1\r\n");
-            }
-            Frame::Bulk(message) => {
-                let message_len = message.len();
+        self.write_frame(frame);
+    }
 
-                self.write_buffer.put_u8(b'$');
-                self.write_decimal(message_len as i64);
-                self.write_buffer.put_slice(message);
-                self.write_buffer.put_slice(b"\r\n");
-            }
-            Frame::Array(_) => unreachable!(),
+    /// Write a bulk string to the stream.
+    pub(crate) fn write_bulk(&mut self, val: &[u8]) {
+        frame::put_bulk(&mut self.write_buffer, val);
+    }
+
+    /// Write a bulk string to the stream, large ones are written without being copied.
+    fn write_shared_bulk(&mut self, val: &Bytes) {
+        if val.len() >= frame::SHARED_BULK_MIN {
+            frame::put_shared_bulk(&mut self.write_buffer, &mut self.chunks, val);
+        } else {
+            frame::put_bulk(&mut self.write_buffer, val);
         }
     }
 
@@ -206,17 +518,10 @@ impl Connection {
         }
     }
 
-    /// Write a `Data` item to the write_buffer.
-    /// # Panics
-    /// This functions panics if Data::Array(_) item is passed as data.
+    /// Write a `Data` item to the write_buffer, lists are written as arrays.
     pub fn write_data(&mut self, data: &Data) {
         match data {
-            Data::Bytes(val) => {
-                self.write_buffer.put_u8(b'$');
-                self.write_decimal(val.len() as i64);
-                self.write_buffer.put_slice(val);
-                self.write_buffer.put_slice(b"\r\n");
-            }
+            Data::Bytes(val) => self.write_shared_bulk(val),
             Data::String(val) => {
                 self.write_buffer.put_u8(b'+');
                 self.write_buffer.put_slice(val);
@@ -227,59 +532,547 @@ impl Connection {
                 self.write_decimal(*val);
             }
             Data::Double(val) => self.write_double(*val),
-            Data::Array(_) => {
-                unreachable!("nested arrays are not supported!");
-            }
+            Data::Array(list) => self.write_data_array(list.iter(), list.len()),
+        }
+    }
+
+    /// Write a value as stored in the `Db` like the `Data` it is returned as, without copying
+    /// it to a `Data` first.
+    pub(crate) fn write_value(&mut self, value: &Value) {
+        match value {
+            Value::Bytes(val) => self.write_shared_bulk(val),
+            Value::Inline(val) => self.write_bulk(val.as_bytes()),
+            Value::List(list) => self.write_data_array_owned(list.iter(), list.len()),
+            value => self.write_data(&value.exported()),
         }
     }
 
     pub fn write_error_frame(&mut self, error: &str) {
+        self.error_replies += 1;
         self.write_buffer.put_u8(b'-');
         self.write_buffer.put_slice(error.as_bytes());
         self.write_buffer.put_slice(b"\r\n");
     }
 
+    /// Write a null value, `$-1` in RESP2 and `_` in RESP3.
     pub fn write_null_frame(&mut self) {
-        self.write_buffer.put_slice(b"$-1\r\n");
+        frame::put_null(&mut self.write_buffer, self.protocol);
+    }
+
+    /// Write the header of a map with `len` key value pairs.
+    ///
+    /// RESP2 has no map type, the pairs are sent as a flat array of `2 * len` items instead.
+    pub fn write_map_header(&mut self, len: usize) {
+        frame::put_map_header(&mut self.write_buffer, len, self.protocol);
+    }
+
+    /// Write the header of a set with `len` items.
+    ///
+    /// RESP2 has no set type, the items are sent as an array instead.
+    pub fn write_set_header(&mut self, len: usize) {
+        frame::put_set_header(&mut self.write_buffer, len, self.protocol);
+    }
+
+    /// Write the header of a push message with `len` items.
+    ///
+    /// RESP2 has no push type, the items are sent as an array instead.
+    pub fn write_push_header(&mut self, len: usize) {
+        frame::put_push_header(&mut self.write_buffer, len, self.protocol);
     }
 
     /// Write a double value to the stream.
+    /// RESP2 has no double type, the value is sent as a bulk string instead.
     pub fn write_double(&mut self, val: f64) {
-        use ryu;
-        // RESP3 Special cases: +inf, -inf, nan
-        if val.is_infinite() {
-            if val.is_sign_positive() {
-                self.write_buffer.put_slice(b",inf\r\n");
-            } else {
-                self.write_buffer.put_slice(b"-inf\r\n");
+        frame::put_double(&mut self.write_buffer, val, self.protocol);
+    }
+
+    /// Writes a decimal frame to the stream.
+    pub fn write_decimal(&mut self, val: i64) {
+        frame::put_decimal(&mut self.write_buffer, val);
+    }
+}
+
+/// Read half of a `Connection`, created with `Connection::split`.
+///
+/// Frames are read like with `Connection::read_frame`, pending replies are not flushed while
+/// waiting for more data since they belong to the `WriteHalf`.
+#[derive(Debug)]
+pub struct ReadHalf {
+    stream: ReadStream,
+    buffer: BytesMut,
+    read_capacity: usize,
+    limits: Limits,
+    read_timeout: Option<Duration>,
+}
+
+/// Write half of a `Connection`, created with `Connection::split`.
+///
+/// Frames may be written and flushed while the `ReadHalf` is waiting for a frame, such as
+/// replies produced in another task or out of band push messages.
+#[derive(Debug)]
+pub struct WriteHalf {
+    stream: WriteStream,
+    write_buffer: BytesMut,
+    chunks: VecDeque<Bytes>,
+    protocol: Protocol,
+    write_timeout: Option<Duration>,
+}
+
+impl ReadHalf {
+    /// Read a single frame, see `Connection::read_frame`.
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>, WalrusError> {
+        loop {
+            if let Some(frame) = parse_buffered(&mut self.buffer, &self.limits)? {
+                return Ok(Some(frame));
+            }
+
+            let filled = fill_buffer(
+                &mut self.stream,
+                &mut self.buffer,
+                self.read_capacity,
+                &self.limits,
+            );
+            if !within(self.read_timeout, filled).await? {
+                return Ok(None);
             }
-            return;
-        } else if val.is_nan() {
-            self.write_buffer.put_slice(b",nan\r\n");
-            return;
         }
+    }
+
+    /// Check if the read buffer already contains a complete frame.
+    pub fn has_buffered_frame(&self) -> bool {
+        check_buffered(&self.buffer, &self.limits).is_ok()
+    }
 
-        // Identifier for double.
-        self.write_buffer.put_u8(b',');
+    /// Set the limits on the frames read from now on.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+}
 
-        // Use ryu crate for better performance than format!() or to_string() method.
-        // Uses a stack allocated buffer to avoid heap allocations.
-        let mut buffer = ryu::Buffer::new();
-        let printed: &str = buffer.format(val);
+impl WriteHalf {
+    /// Protocol used to encode the frames written.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
 
-        self.write_buffer.put_slice(printed.as_bytes());
-        self.write_buffer.put_slice(b"\r\n");
+    /// Switch the protocol used to encode the frames written.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
     }
 
-    /// Writes a decimal frame to the stream.
-    pub fn write_decimal(&mut self, val: i64) {
-        use itoa;
-        // using itoa crate for better performance than std::fmt
-        let mut buf = itoa::Buffer::new();
-        // returns a reference to string representation of the number in the buffer.
-        let printed = buf.format(val);
+    /// Write a single `Frame`, see `Connection::write_frame`.
+    pub fn write_frame(&mut self, frame: &Frame) {
+        frame.write_chunked(
+            &mut self.write_buffer,
+            Some(&mut self.chunks),
+            self.protocol,
+        );
+    }
 
-        self.write_buffer.put_slice(printed.as_bytes());
-        self.write_buffer.put_slice(b"\r\n");
+    /// Flush the frames written to the TCP stream.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        write_out(
+            Some(&mut self.stream),
+            &mut self.write_buffer,
+            &mut self.chunks,
+            self.write_timeout,
+        )
+        .await
+    }
+}
+
+/// Transport of a `Connection`, a plain TCP stream, a Unix socket or, with the `tls` feature,
+/// a TLS stream over TCP.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tokio_rustls::TlsStream<TcpStream>>),
+}
+
+impl From<TcpStream> for Stream {
+    fn from(stream: TcpStream) -> Stream {
+        Stream::Tcp(stream)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Stream {
+        Stream::Unix(stream)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<tokio_rustls::server::TlsStream<TcpStream>> for Stream {
+    fn from(stream: tokio_rustls::server::TlsStream<TcpStream>) -> Stream {
+        Stream::Tls(Box::new(stream.into()))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<tokio_rustls::client::TlsStream<TcpStream>> for Stream {
+    fn from(stream: tokio_rustls::client::TlsStream<TcpStream>) -> Stream {
+        Stream::Tls(Box::new(stream.into()))
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.is_write_vectored(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Read side of a `Stream`, owned by a `ReadHalf`.
+#[derive(Debug)]
+enum ReadStream {
+    Tcp(OwnedReadHalf),
+    #[cfg(unix)]
+    Unix(unix::OwnedReadHalf),
+    /// Streams without owned halves, the halves share the stream.
+    #[cfg(feature = "tls")]
+    Shared(tokio::io::ReadHalf<Stream>),
+}
+
+/// Write side of a `Stream`, owned by a `WriteHalf`.
+#[derive(Debug)]
+enum WriteStream {
+    Tcp(OwnedWriteHalf),
+    #[cfg(unix)]
+    Unix(unix::OwnedWriteHalf),
+    #[cfg(feature = "tls")]
+    Shared(tokio::io::WriteHalf<Stream>),
+}
+
+impl AsyncRead for ReadStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ReadStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            ReadStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            ReadStream::Shared(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for WriteStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WriteStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            WriteStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            WriteStream::Shared(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            WriteStream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            WriteStream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "tls")]
+            WriteStream::Shared(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            WriteStream::Tcp(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            WriteStream::Unix(stream) => stream.is_write_vectored(),
+            #[cfg(feature = "tls")]
+            WriteStream::Shared(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WriteStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            WriteStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            WriteStream::Shared(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            WriteStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            WriteStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            WriteStream::Shared(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Check if a complete frame is at the start of `buffer`, returns its length in bytes and
+/// whether it is an inline command.
+fn check_buffered(buffer: &BytesMut, limits: &Limits) -> Result<(usize, bool), frame::Error> {
+    match buffer.first() {
+        Some(&byte) if frame::is_inline(byte) => Ok((frame::check_inline(buffer)?, true)),
+        _ => {
+            let mut buf = Cursor::new(&buffer[..]);
+            Ok((Frame::check_with(&mut buf, limits)?, false))
+        }
+    }
+}
+
+/// Parse the frame at the start of `buffer` and remove it from the buffer, see
+/// `Connection::parse_frame`.
+fn parse_buffered(buffer: &mut BytesMut, limits: &Limits) -> Result<Option<Frame>, WalrusError> {
+    loop {
+        // Parse the frame, necessary datastructures are allocated and frame
+        // is returned.
+        //
+        // If the encoded frame is invalid, an error is returned.
+        match check_buffered(buffer, limits) {
+            // Full frame is available to parse.
+            // len is inclusive of \r\n
+            Ok((len, inline)) => {
+                let frame_data = buffer.split_to(len);
+                if !inline {
+                    let mut frozen_data = frame_data.freeze();
+                    return Ok(Some(Frame::parse(&mut frozen_data)?));
+                }
+
+                match frame::parse_inline(&frame_data)? {
+                    Frame::Array(args) if args.is_empty() => continue,
+                    Frame::Array(args) if frame::is_http_request(&args) => {
+                        warn!(
+                            "Possible security attack detected, an HTTP request was sent to \
+                             Walrus, likely by cross protocol scripting. Connection closed."
+                        );
+                        return Err("HTTP request sent as an inline command".into());
+                    }
+                    frame => return Ok(Some(frame)),
+                }
+            }
+            // Not enough data in the buffer to parse a full frame. More data must arrive
+            // from the socket.
+            //
+            // Err is not returned as `Incomplete` 'error' is expected during the
+            // application runtime.
+            Err(crate::frame::Error::Incomplete) => return Ok(None),
+            // An unexpected error occured while parsing the frame. The connection will be
+            // closed.
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Run the I/O `op`, failing with `TimedOut` if it doesn't complete within `timeout`.
+async fn within<T, E: From<io::Error>>(
+    timeout: Option<Duration>,
+    op: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, op).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+        },
+        None => op.await,
+    }
+}
+
+/// Read more bytes from `stream` into `buffer`. Returns `false` if the stream ended cleanly,
+/// with no partial frame left in the buffer.
+///
+/// A buffer grown past `capacity` by a large frame is shrunk back to it first. The buffer never
+/// grows more than a byte past the largest frame allowed by `limits`, enough for the frame
+/// check to refuse the frame.
+async fn fill_buffer(
+    stream: &mut (impl AsyncRead + Unpin),
+    buffer: &mut BytesMut,
+    capacity: usize,
+    limits: &Limits,
+) -> Result<bool, WalrusError> {
+    // The remains of the buffer still hold on to the allocation of the large frame, the
+    // pending bytes are moved to a buffer of the initial capacity.
+    if buffer.capacity() > capacity && buffer.len() <= capacity {
+        let mut shrunk = BytesMut::with_capacity(capacity);
+        shrunk.extend_from_slice(buffer);
+        *buffer = shrunk;
+    }
+
+    // Frames larger than the limit are refused once buffered, and so are inline commands
+    // larger than `INLINE_MAX_SIZE`. The buffer is never larger than either of them plus a byte.
+    let max_len = limits
+        .max_frame_size
+        .max(frame::INLINE_MAX_SIZE)
+        .saturating_add(1);
+    let mut read = buffer.limit(max_len.saturating_sub(buffer.len()));
+
+    // If number of bytes read into buffer is 0, then the stream has ended.
+    if 0 == stream.read_buf(&mut read).await? {
+        // If the stream ended with no data in the buffer it is a clean shutdown.
+        // Else it ended while sending a frame.
+        if buffer.is_empty() {
+            return Ok(false);
+        } else {
+            return Err("Connection reset by peer".into());
+        }
+    }
+    Ok(true)
+}
+
+/// Write the pending output, `chunks` followed by `write_buffer`, to `stream`. The output is
+/// discarded if there is no stream.
+///
+/// The write fails with `TimedOut` if it doesn't complete within `timeout`. When large bulk
+/// strings are queued in `chunks`, the timeout applies to each write of at most
+/// `STREAM_CHUNK_SIZE` bytes instead, so a slow peer is only timed out once it stops reading.
+async fn write_out(
+    stream: Option<&mut (impl AsyncWrite + Unpin)>,
+    write_buffer: &mut BytesMut,
+    chunks: &mut VecDeque<Bytes>,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    if chunks.is_empty() {
+        if !write_buffer.is_empty() {
+            if let Some(stream) = stream {
+                within(timeout, stream.write_all(write_buffer)).await?;
+            }
+            write_buffer.clear();
+        }
+        return Ok(());
+    }
+
+    if !write_buffer.is_empty() {
+        chunks.push_back(write_buffer.split().freeze());
+    }
+    if let Some(stream) = stream {
+        write_all_vectored(stream, chunks, timeout).await?;
+    }
+    chunks.clear();
+    Ok(())
+}
+
+/// Maximum number of chunks written with a single vectored write.
+const MAX_IOVECS: usize = 64;
+
+/// Maximum number of bytes written with a single vectored write. Multi-megabyte bulk strings
+/// are streamed to the socket in pieces of this size.
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Write all of `chunks` to `stream`, the chunks written are removed from the queue. Each write
+/// covers at most `STREAM_CHUNK_SIZE` bytes and must complete within `timeout`.
+async fn write_all_vectored(
+    stream: &mut (impl AsyncWrite + Unpin),
+    chunks: &mut VecDeque<Bytes>,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    while !chunks.is_empty() {
+        let mut budget = STREAM_CHUNK_SIZE;
+        let slices: Vec<IoSlice<'_>> = chunks
+            .iter()
+            .take(MAX_IOVECS)
+            .map_while(|chunk| {
+                let len = chunk.len().min(budget);
+                budget -= len;
+                (len > 0).then(|| IoSlice::new(&chunk[..len]))
+            })
+            .collect();
+        let mut written = within(timeout, stream.write_vectored(&slices)).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+
+        // Drop the chunks fully written, the last one may be partially written.
+        while let Some(chunk) = chunks.front_mut() {
+            if written < chunk.len() {
+                chunk.advance(written);
+                break;
+            }
+            written -= chunk.len();
+            chunks.pop_front();
+        }
     }
+    Ok(())
 }
@@ -0,0 +1,38 @@
+/// CRC-64/Jones, the checksum used by Redis for RDB files.
+///
+/// Reflected polynomial `0xad93d23594c935a9`, initial value 0 and no final xor.
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+/// Lookup table of the checksum of every byte, computed at compile time.
+const TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Continue the checksum `crc` over `bytes`, start with 0.
+pub(crate) fn crc64(mut crc: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        crc = TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+
+    crc
+}
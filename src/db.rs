@@ -2,26 +2,64 @@ use ahash;
 use bytes::Bytes;
 use dashmap::{
     DashMap,
-    mapref::one::{Ref, RefMut},
+    mapref::{
+        entry::Entry as MapEntry,
+        multiple::RefMulti,
+        one::{Ref, RefMut},
+    },
 };
 use futures::{StreamExt, stream::FuturesUnordered};
+use rand::RngExt;
 use std::{
-    collections::{BTreeSet, VecDeque},
+    collections::VecDeque,
+    mem,
+    ops::{Deref, DerefMut},
     sync::{
-        Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        mpsc,
     },
+    thread,
 };
 use tokio::{
-    sync::Notify,
+    sync::{Notify, broadcast, watch},
     time::{self, Duration, Instant},
 };
+use tracing::trace;
+
+use crate::{
+    config::{Lazyfree, MaxmemoryPolicy},
+    errors::WalrusError,
+    frame::Frame,
+    glob::glob_match,
+    parse, task,
+    tracking::Tracking,
+};
+
+mod changelog;
+mod inline;
+mod list;
+mod scan;
+mod snapshot;
+mod value;
+mod wheel;
 
-use crate::{errors::WalrusError, frame::Frame, parse};
+pub use changelog::{Change, Op};
+use changelog::{Changelog, system_time};
+pub(crate) use inline::Inline;
+pub(crate) use list::List;
+pub(crate) use list::ListpackLimits;
+use scan::ScanIndex;
+pub use snapshot::Snapshot;
+pub(crate) use value::Value;
+use wheel::Wheel;
 
 /// Data stored in an entry.
 /// Can be Bytes, Simple String or an Vec<Data>
+///
+/// With the `serde` feature, data implements `Serialize` and `Deserialize` like `Frame`.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Data {
     Bytes(Bytes),
     /// VecDeque allowing O(1) push and pop operations at both ends of the list.
@@ -31,24 +69,139 @@ pub enum Data {
     Double(f64),
 }
 
+/// Kind of the value of a key, as reported by `TYPE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Kind {
+    String,
+    List,
+}
+
+impl Kind {
+    /// Name of the kind, as reported by `TYPE`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Kind::String => "string",
+            Kind::List => "list",
+        }
+    }
+
+    /// Kind named `name`, case-insensitively.
+    pub(crate) fn from_name(name: &[u8]) -> Option<Kind> {
+        [Kind::String, Kind::List]
+            .into_iter()
+            .find(|kind| name.eq_ignore_ascii_case(kind.name().as_bytes()))
+    }
+}
+
 /// Single entry in key-value store.
 pub(crate) struct Entry {
-    pub(crate) data: Data,
+    pub(crate) data: Value,
     pub(crate) expires_at: Option<Instant>,
+    /// Approximate memory used by the entry and its key, in bytes.
+    size: usize,
+    /// Milliseconds since the creation of the `Db` at which the entry was last accessed, to
+    /// evict the least recently used keys.
+    accessed: AtomicU64,
+    /// Approximate access frequency of the entry in the low 8 bits, and the minute it was last
+    /// decayed at in the high 24 bits, to evict the least frequently used keys.
+    frequency: AtomicU32,
+    /// Version of the value, see `Db::version`.
+    version: u64,
 }
 
-/// State of the Db.
-struct State {
+/// Entry borrowed for writing with `Db::get_mut` or `Db::get_or_insert_with`. Changes to the
+/// size of its value must be reported with `resize`, to keep the memory used by its shard
+/// accurate.
+pub(crate) struct EntryMut<'a> {
+    entry: RefMut<'a, Bytes, Entry>,
+    memory: &'a AtomicUsize,
+    changelog: &'a Changelog,
+}
+
+/// Memory used by an entry besides its key and value, the key and entry stored in the map and
+/// the key and hash stored in the scan index.
+const ENTRY_OVERHEAD: usize =
+    mem::size_of::<(Bytes, Entry)>() - mem::size_of::<Data>() + mem::size_of::<(u64, Bytes)>();
+
+/// Number of shards the keyspace is split into, a power of two.
+const SHARDS: usize = 16;
+
+/// Number of shards of the map of entries of every shard, a power of two.
+const MAP_SHARDS: usize = 4;
+
+/// Buckets probed per entry sampled for eviction, see `Shard::sample`. Maps are more than
+/// a third full once grown, unless keys were removed since.
+const SAMPLE_PROBES: usize = 8;
+
+/// Values taking more than this many frees, such as lists of more elements, are freed on the
+/// lazyfree thread when their deletion is lazy. Freeing smaller values is as cheap as sending
+/// them to the thread.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+/// Change of a key, sent to the subscribers of a `Db`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyEvent {
+    /// The key was set to a new value.
+    Set(Bytes),
+    /// Elements were pushed to the list of the key, created if it didn't exist.
+    Push(Bytes),
+    /// Elements were popped from the list of the key, removed once empty.
+    Pop(Bytes),
+    /// The key was removed.
+    Del(Bytes),
+    /// The expiration of the key was set or removed.
+    Expire(Bytes),
+    /// The key expired and was removed.
+    Expired(Bytes),
+    /// The key was evicted to stay under `maxmemory`.
+    Evicted(Bytes),
+}
+
+/// Access frequency of new keys, so they aren't evicted before being accessed again.
+const LFU_INIT: u32 = 5;
+
+/// How slowly access frequencies grow, the `lfu-log-factor` default of Redis. A frequency is
+/// incremented with a probability of `1 / ((frequency - LFU_INIT) * LFU_LOG_FACTOR + 1)`, so
+/// the 255 accesses it can count represent about a million accesses.
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// Minutes after which the access frequency of a key not accessed is decremented, the
+/// `lfu-decay-time` default of Redis.
+const LFU_DECAY_MINUTES: u32 = 1;
+
+/// Maximum number of expired keys purged from a shard in one cycle of the background task.
+/// A burst of expirations is purged over several cycles, letting connections use the shard
+/// in between.
+const PURGE_BATCH: usize = 64;
+
+/// A part of the keyspace, keys are assigned to shards by their hash. Shards are locked
+/// independently, so commands on keys of different shards don't wait on each other.
+struct Shard {
     /// Dashmap using ahash hashing algorithm providing better performance compared to SipHash.
     entries: DashMap<Bytes, Entry, ahash::RandomState>,
 
+    /// Approximate memory used by the entries, see `Entry::size`.
+    memory: AtomicUsize,
+
     /// Tracks key's Time To Live.
-    /// Binary Tree Set is used to the value expiring next.
-    /// It is possible to have two values expire at same instant.
-    /// A unique key is used to break these ties.
-    /// std::sync::Mutex is used here as its cheaper to just wait for BTreeSet operation than wait
+    /// Replaced expirations are left in the wheel, an expiration is only acted upon if it is
+    /// still the one of the entry.
+    /// std::sync::Mutex is used here as its cheaper to just wait for the wheel than wait
     /// for context switiching if using tokio::sync::Mutex
-    expirations: Mutex<BTreeSet<(Instant, Bytes)>>,
+    expirations: Mutex<Wheel>,
+
+    /// Keys of the shard by hash, visited by `Db::scan`. Updated while the entry of the key
+    /// is locked, and never locked while reading the map, to avoid deadlock.
+    scan: Mutex<ScanIndex>,
+}
+
+/// State of the Db.
+struct State {
+    /// Shards of the keyspace, see `State::shard`.
+    shards: Box<[Shard]>,
+
+    /// Hashes keys to pick their shard.
+    hasher: ahash::RandomState,
 
     /// Indicates if Db instance is shutting down. Background tasks are signaled to exit
     /// when this is true.
@@ -56,6 +209,85 @@ struct State {
 
     /// Map of keys to Notification triggers.
     blocking_keys: DashMap<Bytes, Arc<Notify>>,
+
+    /// Keys read by connections using client side caching, shared by the databases of a
+    /// server as keys are tracked by name whatever their database.
+    tracking: Arc<Tracking>,
+
+    /// Number of keys of every kind, see `Counts`.
+    counts: Counts,
+
+    /// Instant access times of entries are relative to.
+    epoch: Instant,
+
+    /// Number of keys evicted to stay under `maxmemory`.
+    evicted: AtomicU64,
+
+    /// Number of expired keys removed, by the background task or when accessed.
+    expired: AtomicU64,
+
+    /// Number of reads of existing keys.
+    hits: AtomicU64,
+
+    /// Number of reads of missing keys.
+    misses: AtomicU64,
+
+    /// Statistics of the commands executed, by command name.
+    commands: DashMap<&'static str, CommandCounters>,
+
+    /// Changes of keys, see `Db::on_change` and `Db::subscribe`.
+    changelog: Changelog,
+
+    /// Values of watched keys, see `Db::watch`.
+    watchers: DashMap<Bytes, watch::Sender<Option<Data>>>,
+
+    /// Last version given to a written entry.
+    versions: AtomicU64,
+
+    /// Thresholds under which lists are stored as listpacks, see `ListpackLimits`.
+    listpack_entries: AtomicUsize,
+    listpack_value: AtomicUsize,
+
+    /// Indicates if the background task purges expired keys, toggled with
+    /// `DEBUG SET-ACTIVE-EXPIRE`.
+    active_expire: AtomicBool,
+
+    /// Deletions freeing large values on the lazyfree thread, a combination of `Lazyfree`
+    /// flags.
+    lazyfree: AtomicU8,
+
+    /// Percentage of its time to live randomly added to an expiration set by a command, see
+    /// `Db::jitter`.
+    expire_jitter: AtomicU8,
+
+    /// Called with the duration of each cycle of the background task purging expired keys,
+    /// see `Db::on_expire_cycle`.
+    expire_cycle_hook: OnceLock<Box<dyn Fn(Duration) + Send + Sync>>,
+
+    /// Sends values to free to the lazyfree thread, which stops once it is dropped.
+    free: mpsc::Sender<Value>,
+
+    /// Values sent to the lazyfree thread.
+    freeing: Arc<Freeing>,
+}
+
+/// Values freed by the lazyfree thread.
+#[derive(Default)]
+struct Freeing {
+    /// Values waiting to be freed.
+    pending: AtomicUsize,
+    /// Values freed.
+    freed: AtomicU64,
+}
+
+/// Number of keys of every kind and of keys with an expiration, updated as entries are added
+/// and removed so statistics don't walk every entry. Expired keys are counted until they are
+/// purged.
+#[derive(Default)]
+struct Counts {
+    strings: AtomicUsize,
+    lists: AtomicUsize,
+    expires: AtomicUsize,
 }
 
 /// Shared state.
@@ -69,51 +301,248 @@ struct Shared {
 
 /// Shared across all connections.
 /// When `Db` instance is created a background task is created to expire values after the
-/// requested duration has elapsed. This task terminates when `Db` instance is dropped.
+/// requested duration has elapsed. This task terminates when the `DbDropGuard` owning the
+/// `Db` is dropped.
+///
+/// The key-value store can be embedded in an application without running a server, through
+/// `DbDropGuard`:
+///
+/// ```
+/// use bytes::Bytes;
+/// use walrus::db::{Data, DbDropGuard};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let guard = DbDropGuard::new();
+/// let db = guard.get_db();
+///
+/// let key = Bytes::from("key");
+/// db.set(&key, Data::Bytes(Bytes::from("value")), None);
+/// assert_eq!(db.get(&key), Some(Data::Bytes(Bytes::from("value"))));
+/// # }
+/// ```
+///
+/// `Db` is cheap to clone, clones share the same keys and can be used from any thread or task.
+/// Every operation on a key is atomic: operations on the same key are serialized and always see
+/// each other's writes in full, operations on different keys may run in parallel. There is no
+/// ordering or atomicity across keys.
 #[derive(Clone)]
-pub(crate) struct Db {
+pub struct Db {
     shared: Arc<Shared>,
 }
 
+/// Statistics of a `Db`, see `Db::stats`. Counters are updated without synchronizing with
+/// each other, they may be slightly out of step.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// Number of reads of existing keys.
+    pub keyspace_hits: u64,
+    /// Number of reads of missing keys.
+    pub keyspace_misses: u64,
+    /// Number of expired keys removed.
+    pub expired_keys: u64,
+    /// Number of keys evicted to stay under `maxmemory`.
+    pub evicted_keys: u64,
+    /// Number of large values freed on the lazyfree thread.
+    pub lazyfreed_objects: u64,
+    /// Number of large values waiting to be freed on the lazyfree thread.
+    pub lazyfree_pending_objects: usize,
+    /// Statistics of the commands executed by a server using the `Db`, by command name in
+    /// alphabetical order.
+    pub commands: Vec<(&'static str, CommandStats)>,
+}
+
+impl Stats {
+    /// Total number of commands executed.
+    pub fn total_commands(&self) -> u64 {
+        self.commands.iter().map(|(_, stats)| stats.calls).sum()
+    }
+}
+
+/// Statistics of the executions of a command, see `Stats::commands`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CommandStats {
+    /// Number of executions.
+    pub calls: u64,
+    /// Total execution time in microseconds.
+    pub usec: u64,
+    /// Longest execution time in microseconds.
+    pub max_usec: u64,
+    /// Number of executions replying with an error.
+    pub failed_calls: u64,
+}
+
+/// Counters of a `CommandStats`, updated as commands are executed.
+#[derive(Default)]
+struct CommandCounters {
+    calls: AtomicU64,
+    usec: AtomicU64,
+    max_usec: AtomicU64,
+    failed_calls: AtomicU64,
+}
+
 /// Wrapper around `Db` instance, allows for cleanup of the `Db` by signalling the background
 /// purge task to shutdown when this struct is dropped.
-pub(crate) struct DbDropGuard {
+pub struct DbDropGuard {
     db: Db,
 }
 
 impl Data {
+    /// Approximate memory used by the value, in bytes. Arrays count the size of their
+    /// elements.
+    pub(crate) fn size(&self) -> usize {
+        let heap = match self {
+            Data::Bytes(bytes) | Data::String(bytes) => bytes.len(),
+            Data::Array(list) => list.iter().map(Data::size).sum(),
+            Data::Integer(_) | Data::Double(_) => 0,
+        };
+
+        mem::size_of::<Data>() + heap
+    }
+
     /// Try to convert `Frame` to `Vec<Data>`.
     pub(crate) fn frame_to_data_vec(frame: Frame) -> Result<Vec<Data>, WalrusError> {
         match frame {
-            Frame::Array(arr) => arr
+            Frame::Array(arr) | Frame::Set(arr) | Frame::Push(arr) => arr
+                .into_iter()
+                .map(Data::try_from)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Into::into),
+            // Maps are flattened into alternating keys and values.
+            Frame::Map(pairs) => pairs
                 .into_iter()
+                .flat_map(|(key, value)| [key, value])
                 .map(Data::try_from)
                 .collect::<Result<Vec<_>, _>>()
                 .map_err(Into::into),
+            Frame::Attribute { data, .. } => Data::frame_to_data_vec(*data),
             other => Ok(vec![Data::try_from(other)?]),
         }
     }
 }
 
+impl Entry {
+    /// Create an entry for `key` at `version`, accessed at `clock`.
+    fn new(
+        key: &[u8],
+        data: Value,
+        expires_at: Option<Instant>,
+        clock: u64,
+        version: u64,
+    ) -> Entry {
+        Entry {
+            version,
+            size: ENTRY_OVERHEAD + key.len() + data.size(),
+            data,
+            expires_at,
+            accessed: AtomicU64::new(clock),
+            frequency: AtomicU32::new(minutes(clock) << 8 | LFU_INIT),
+        }
+    }
+
+    /// Change recording the entry being set.
+    fn set_op(&self) -> Op {
+        Op::Set {
+            value: self.data.exported(),
+            expires_at: self.expires_at.map(system_time),
+        }
+    }
+
+    /// Record an access at `clock`. Only atomics are written, the entry may be read locked.
+    fn touch(&self, clock: u64) {
+        self.accessed.store(clock, Ordering::Relaxed);
+
+        let mut frequency = self.frequency(clock);
+        if frequency < 255 {
+            let base = frequency.saturating_sub(LFU_INIT) as f64;
+            if rand::rng().random::<f64>() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                frequency += 1;
+            }
+        }
+
+        // Concurrent accesses may overwrite each other, the frequency is approximate anyway.
+        self.frequency
+            .store(minutes(clock) << 8 | frequency, Ordering::Relaxed);
+    }
+
+    /// Access frequency at `clock`, decremented once for every `LFU_DECAY_MINUTES` since it was
+    /// last decayed.
+    fn frequency(&self, clock: u64) -> u32 {
+        let packed = self.frequency.load(Ordering::Relaxed);
+        let elapsed = minutes(clock).wrapping_sub(packed >> 8) & 0xff_ffff;
+        (packed & 0xff).saturating_sub(elapsed / LFU_DECAY_MINUTES)
+    }
+
+    /// Returns `true` if the entry expired at `now`.
+    pub(crate) fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|when| when <= now)
+    }
+}
+
 impl Db {
-    /// Create a new empty `Db` instance.
-    pub(crate) fn new() -> Db {
-        let shared = Arc::new(Shared {
-            state: State {
+    /// Create a new empty `Db` instance, tracking the keys read by connections in `tracking`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime, the background task purging expired keys
+    /// is spawned on it.
+    fn new(tracking: Arc<Tracking>) -> Db {
+        let shards = (0..SHARDS)
+            .map(|_| Shard {
                 entries: DashMap::with_capacity_and_hasher_and_shard_amount(
-                    512,
+                    512 / SHARDS,
                     ahash::RandomState::new(),
-                    64,
+                    MAP_SHARDS,
                 ),
-                expirations: Mutex::new(BTreeSet::new()),
+                memory: AtomicUsize::new(0),
+                expirations: Mutex::new(Wheel::new()),
+                scan: Mutex::new(ScanIndex::new()),
+            })
+            .collect();
+
+        // Large values are freed on a thread of their own rather than a task, freeing them
+        // blocks until it is done.
+        let (free, values) = mpsc::channel();
+        let freeing = Arc::new(Freeing::default());
+        {
+            let freeing = freeing.clone();
+            thread::Builder::new()
+                .name("lazyfree".to_string())
+                .spawn(move || free_values(values, &freeing))
+                .expect("failed to spawn the lazyfree thread");
+        }
+
+        let shared = Arc::new(Shared {
+            state: State {
+                shards,
+                hasher: ahash::RandomState::new(),
                 shutdown: AtomicBool::new(false),
                 blocking_keys: DashMap::new(),
+                tracking,
+                counts: Counts::default(),
+                epoch: Instant::now(),
+                versions: AtomicU64::new(0),
+                listpack_entries: AtomicUsize::new(ListpackLimits::default().entries),
+                listpack_value: AtomicUsize::new(ListpackLimits::default().value),
+                evicted: AtomicU64::new(0),
+                expired: AtomicU64::new(0),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                commands: DashMap::new(),
+                changelog: Changelog::new(),
+                watchers: DashMap::new(),
+                active_expire: AtomicBool::new(true),
+                lazyfree: AtomicU8::new(0),
+                expire_jitter: AtomicU8::new(0),
+                expire_cycle_hook: OnceLock::new(),
+                free,
+                freeing,
             },
             background_task: Notify::new(),
         });
 
         // Start the background task for purging expired keys passing shared Db state.
-        tokio::spawn(purge_expired_tasks(shared.clone()));
+        task::spawn("purge-expired", purge_expired_tasks(shared.clone()));
 
         Db { shared }
     }
@@ -121,150 +550,1005 @@ impl Db {
     /// Get the value associated with a key.
     ///
     /// Returns `None` if no value is associated with the key.
-    pub(crate) fn get(&self, key: &Bytes) -> Option<Data> {
-        // clone here is shallow as data is stored using `Bytes`.
+    pub fn get(&self, key: &Bytes) -> Option<Data> {
+        // clone here is shallow as data is stored using `Bytes`, lists are decoded.
+        self.get_ref(key).map(|entry| entry.data.exported())
+    }
+
+    /// Get the entry of a key for writing. Expired entries the background task hasn't purged
+    /// yet are removed, and `None` returned.
+    pub(crate) fn get_mut(&self, key: &Bytes) -> Option<EntryMut<'_>> {
+        let state = &self.shared.state;
+        let shard = state.shard(key);
+        let mut entry = shard.entries.get_mut(key)?;
+        let now = Instant::now();
+        if !entry.is_expired(now) {
+            entry.touch(state.clock(now));
+            // The entry is written, or may be.
+            entry.version = state.next_version();
+            return Some(EntryMut {
+                entry,
+                memory: &shard.memory,
+                changelog: &state.changelog,
+            });
+        }
+
+        // Drop the lock of the entry before removing it to avoid deadlock.
+        drop(entry);
+        self.remove_expired(shard, key);
+        None
+    }
+
+    /// Get the entry of a key for writing like `get_mut`, inserting a value created with
+    /// `default` if the key doesn't exist or expired. The entry stays locked from its creation
+    /// until it is dropped, so commands creating a key and modifying it do so in one step: a
+    /// key created by another connection in the meantime is modified rather than replaced.
+    pub(crate) fn get_or_insert_with(
+        &self,
+        key: &Bytes,
+        default: impl FnOnce() -> Value,
+    ) -> EntryMut<'_> {
+        if let Some(entry) = self.get_mut(key) {
+            return entry;
+        }
+
+        let state = &self.shared.state;
+        let shard = state.shard(key);
+        let now = Instant::now();
+        let clock = state.clock(now);
+
+        // The `key` still refers to the Bytes from the BytesMut buffer, to avoid memory mapping
+        // copy it before storing.
+        let entry = match shard.entries.entry(Bytes::copy_from_slice(key)) {
+            MapEntry::Occupied(occupied) if !occupied.get().is_expired(now) => {
+                // Created by another connection since it was looked up, written like an entry
+                // returned by `get_mut`.
+                let mut entry = occupied.into_ref();
+                entry.touch(clock);
+                entry.version = state.next_version();
+                entry
+            }
+            map_entry => {
+                let entry = Entry::new(key, default(), None, clock, state.next_version());
+                shard.track(&entry);
+                state.counts.add(&entry);
+
+                match map_entry {
+                    MapEntry::Occupied(mut occupied) => {
+                        // Expired since it was looked up, purged like the background task
+                        // does before the new entry is written. Connections caching it must
+                        // drop it.
+                        state.changelog.record(key, || Op::Expired);
+                        let prev = occupied.insert(entry);
+                        shard.untrack(&prev);
+                        state.counts.remove(&prev);
+                        state.expired.fetch_add(1, Ordering::Relaxed);
+                        state.tracking.invalidate(key, None);
+                        self.shared.free(Lazyfree::Expire, prev.data);
+                        occupied.into_ref()
+                    }
+                    MapEntry::Vacant(vacant) => {
+                        state.index(shard, vacant.key());
+                        vacant.insert(entry)
+                    }
+                }
+            }
+        };
+
+        EntryMut {
+            entry,
+            memory: &shard.memory,
+            changelog: &state.changelog,
+        }
+    }
+
+    /// Get the entry of a key for reading. Expired entries the background task hasn't purged
+    /// yet are removed, and `None` returned.
+    pub(crate) fn get_ref(&self, key: &Bytes) -> Option<Ref<'_, Bytes, Entry>> {
+        let state = &self.shared.state;
+        let Some(entry) = self.inspect(key) else {
+            state.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        state.hits.fetch_add(1, Ordering::Relaxed);
+        entry.touch(state.clock(Instant::now()));
+        Some(entry)
+    }
+
+    /// Get the entry of a key for reading like `get_ref`, without counting it as an access.
+    pub(crate) fn inspect(&self, key: &Bytes) -> Option<Ref<'_, Bytes, Entry>> {
+        let shard = self.shared.state.shard(key);
+        let entry = shard.entries.get(key)?;
+        if !entry.is_expired(Instant::now()) {
+            return Some(entry);
+        }
+
+        drop(entry);
+        self.remove_expired(shard, key);
+        None
+    }
+
+    /// Get the entry of a key, including an expired entry not purged yet.
+    #[cfg_attr(not(feature = "debug-command"), allow(dead_code))]
+    pub(crate) fn peek(&self, key: &Bytes) -> Option<Ref<'_, Bytes, Entry>> {
+        self.shared.state.shard(key).entries.get(key)
+    }
+
+    /// Remove the entry of `key` if it is expired, it may have been replaced since it was
+    /// found expired.
+    fn remove_expired(&self, shard: &Shard, key: &Bytes) {
+        let state = &self.shared.state;
+        let now = Instant::now();
+        if let Some((key, entry)) = shard.entries.remove_if(key, |key, entry| {
+            let expired = entry.is_expired(now);
+            if expired {
+                state.changelog.record(key, || Op::Expired);
+                state.unindex(shard, key);
+            }
+            expired
+        }) {
+            shard.untrack(&entry);
+            state.counts.remove(&entry);
+            state.expired.fetch_add(1, Ordering::Relaxed);
+
+            // Connections caching the key must drop it.
+            state.tracking.invalidate(&key, None);
+            self.shared.changed(&key);
+            self.shared.free(Lazyfree::Expire, entry.data);
+        }
+    }
+
+    /// Iterate over every entry, including expired entries not purged yet.
+    /// Each shard of the map is read locked while its entries are visited.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = RefMulti<'_, Bytes, Entry>> {
         self.shared
             .state
-            .entries
-            .get(key)
-            .map(|entry| entry.data.clone())
+            .shards
+            .iter()
+            .flat_map(|shard| shard.entries.iter())
     }
 
-    pub(crate) fn get_mut(&self, key: &Bytes) -> Option<RefMut<'_, Bytes, Entry>> {
-        self.shared.state.entries.get_mut(key)
+    /// Iterate over every key and its value. Values are cheap to clone, strings share their
+    /// buffers.
+    ///
+    /// The keys and values of a shard are copied before they are visited, shards are never
+    /// locked while the iterator is held: keys can be written meanwhile, even from the same
+    /// task. Keys written during the iteration may or may not be visited.
+    pub fn iter(&self) -> impl Iterator<Item = (Bytes, Data)> + '_ {
+        self.shared.state.shards.iter().flat_map(|shard| {
+            let now = Instant::now();
+            shard
+                .entries
+                .iter()
+                .filter(|entry| !entry.is_expired(now))
+                .map(|entry| (entry.key().clone(), entry.data.exported()))
+                .collect::<Vec<_>>()
+        })
     }
 
-    pub(crate) fn get_ref(&self, key: &Bytes) -> Option<Ref<'_, Bytes, Entry>> {
-        self.shared.state.entries.get(key)
+    /// Read-only view of every key and its value, which can be held and iterated without
+    /// blocking writers, as when persisting the dataset.
+    ///
+    /// The keys are copied shard by shard, each of the maps of a shard is read locked while
+    /// its entries are cloned, which is cheap as values are shared until they are written. The
+    /// keys of a shard are captured at a single point in time, writes made while the snapshot
+    /// is taken may or may not be seen in the shards visited later.
+    pub fn snapshot(&self) -> Snapshot {
+        let now = Instant::now();
+        Snapshot::new(
+            self.entries()
+                .filter(|entry| !entry.is_expired(now))
+                .map(|entry| (entry.key().clone(), entry.data.clone(), entry.expires_at))
+                .collect(),
+        )
+    }
+
+    /// Keys matching the glob-style `pattern`, visiting every key at once.
+    pub fn keys(&self, pattern: &[u8]) -> Vec<Bytes> {
+        let now = Instant::now();
+        self.entries()
+            .filter(|entry| !entry.is_expired(now) && glob_match(pattern, entry.key(), false))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Keys from `cursor`, 0 to start an iteration, and the cursor to continue the iteration
+    /// from, 0 once it is complete. Keys existing during the whole iteration are returned at
+    /// least once, keys written meanwhile may or may not be. A key removed, or expired, before
+    /// a call is never returned by it: keys are read from the index when their bucket is
+    /// visited, the cursor holds no keys.
+    ///
+    /// Shards are iterated one after the other, the low bits of the cursor are the shard and
+    /// the others a bucket of the scan index of the shard, see `ScanIndex`. Buckets are visited
+    /// until `count` keys are found, buckets hold a couple of keys so a few more keys than
+    /// `count` may be returned.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let state = &self.shared.state;
+        let shard_bits = SHARDS.trailing_zeros();
+        let mut shard = cursor as usize & (SHARDS - 1);
+        let mut bucket = cursor >> shard_bits;
+
+        let now = Instant::now();
+        let mut keys = Vec::new();
+        let mut visited = Vec::new();
+        loop {
+            // The index is unlocked before the map is read, to avoid deadlock.
+            let entries = &state.shards[shard].entries;
+            bucket = state.shards[shard]
+                .scan
+                .lock()
+                .unwrap()
+                .visit(bucket, &mut visited);
+            keys.extend(
+                visited
+                    .drain(..)
+                    .filter(|key| entries.get(key).is_some_and(|entry| !entry.is_expired(now))),
+            );
+
+            if bucket == 0 {
+                shard += 1;
+                if shard == SHARDS {
+                    return (0, keys);
+                }
+            }
+            if keys.len() >= count {
+                return ((bucket << shard_bits) | shard as u64, keys);
+            }
+        }
     }
 
     /// Insert key value pair into db.
     /// Optional expires_at determines the instant when key will expire.
     /// If key already exists, its old value is replaced.
-    pub(crate) fn set(&self, key: &Bytes, value: Data, expire: Option<Duration>) {
-        let mut notify = false;
-        // The `key` still refers to the Bytes from the BytesMut buffer, to avoid memory mapping copy
-        // it before storing. `value` maybe owned already if its not bytes.
-        let stored_key = Bytes::copy_from_slice(&key);
-        let stored_value = value.to_owned();
-
-        let expires_at = expire.map(|duration| {
-            // Calculate the instant at which key will expire.
-            let when = Instant::now() + duration;
-
-            // Set notify to true if new key will expire earlier than current scheduled next
-            // expiration.
-            notify = self
-                .shared
-                .state
-                .next_expiration()
-                .map(|expiration| when < expiration)
-                .unwrap_or(true);
+    pub fn set(&self, key: &Bytes, value: Data, expire: Option<Duration>) {
+        self.set_if(key, value, expire, |_| true);
+    }
 
-            when
-        });
+    /// Remove a key. Returns `true` if the key existed.
+    pub fn del(&self, key: &Bytes) -> bool {
+        let state = &self.shared.state;
+        let shard = state.shard(key);
+        let now = Instant::now();
+        let Some((key, entry)) = shard.entries.remove_if(key, |key, entry| {
+            if entry.is_expired(now) {
+                state.changelog.record(key, || Op::Expired);
+            } else {
+                state.changelog.record(key, || Op::Del);
+            }
+            state.unindex(shard, key);
+            true
+        }) else {
+            return false;
+        };
+        shard.untrack(&entry);
+        state.counts.remove(&entry);
 
-        // Insert pair into dashmap, returns previous entry if key already present.
-        let prev = self.shared.state.entries.insert(
-            key.clone(),
-            Entry {
-                data: stored_value,
-                expires_at,
-            },
+        // Connections caching the key must drop it.
+        state.tracking.invalidate(&key, None);
+        self.shared.changed(&key);
+
+        if entry.is_expired(now) {
+            state.expired.fetch_add(1, Ordering::Relaxed);
+            self.shared.free(Lazyfree::Expire, entry.data);
+            false
+        } else {
+            self.shared.free(Lazyfree::UserDel, entry.data);
+            true
+        }
+    }
+
+    /// Move `key`, with its time to live, to the database `target`. Returns `false` if the key
+    /// doesn't exist, or already exists in `target` in which case it's left where it is.
+    ///
+    /// The key is removed and inserted in one step: its entries in both databases are locked
+    /// meanwhile, so other connections never see the key in both databases or in neither. The
+    /// expiration of the key moves to the shards of `target`, the one left in this database
+    /// becomes a tombstone.
+    pub(crate) fn move_to(&self, key: &Bytes, target: &Db) -> bool {
+        let state = &self.shared.state;
+        let target_state = &target.shared.state;
+        let shard = state.shard(key);
+        let target_shard = target_state.shard(key);
+        let now = Instant::now();
+
+        // The entries of two databases are always locked in the same order, so keys moved in
+        // opposite directions at the same time don't deadlock.
+        let stored_key = Bytes::copy_from_slice(key);
+        let (map_entry, target_entry) = if Arc::as_ptr(&self.shared) < Arc::as_ptr(&target.shared) {
+            let map_entry = shard.entries.entry(key.clone());
+            (map_entry, target_shard.entries.entry(stored_key.clone()))
+        } else {
+            let target_entry = target_shard.entries.entry(stored_key.clone());
+            (shard.entries.entry(key.clone()), target_entry)
+        };
+
+        let MapEntry::Occupied(occupied) = map_entry else {
+            return false;
+        };
+        if occupied.get().is_expired(now) {
+            drop((occupied, target_entry));
+            self.remove_expired(shard, key);
+            return false;
+        }
+        if let MapEntry::Occupied(existing) = &target_entry
+            && !existing.get().is_expired(now)
+        {
+            return false;
+        }
+
+        let (_, entry) = occupied.remove_entry();
+        state.changelog.record(key, || Op::Del);
+        state.unindex(shard, key);
+        shard.untrack(&entry);
+        state.counts.remove(&entry);
+
+        // The moved entry is a new entry of `target`, accessed and versioned as such.
+        let expires_at = entry.expires_at;
+        let moved = Entry::new(
+            key,
+            entry.data,
+            expires_at,
+            target_state.clock(now),
+            target_state.next_version(),
         );
+        let is_list = matches!(moved.data, Value::List(_));
+        target_shard.track(&moved);
+        target_state.counts.add(&moved);
 
-        // If prev entry was present then remove its expiration to avoid data leak.
-        if let Some(prev) = prev {
-            if let Some(when) = prev.expires_at {
-                self.shared
-                    .state
-                    .expirations
-                    .lock()
-                    .unwrap()
-                    .remove(&(when, stored_key.clone()));
+        let prev = match target_entry {
+            MapEntry::Occupied(mut occupied) => {
+                // Expired but not purged yet, purged like the background task does.
+                target_state.changelog.record(key, || Op::Expired);
+                let prev = occupied.insert(moved);
+                target_shard.untrack(&prev);
+                target_state.counts.remove(&prev);
+                target_state.expired.fetch_add(1, Ordering::Relaxed);
+                target.record_moved(key, occupied.get());
+                Some(prev)
+            }
+            MapEntry::Vacant(vacant) => {
+                target_state.index(target_shard, &stored_key);
+                let entry = vacant.insert(moved);
+                target.record_moved(key, &entry);
+                None
             }
+        };
+
+        // Connections caching the key must drop it, whatever its database.
+        state.tracking.invalidate(key, None);
+        self.shared.changed(key);
+        target.shared.changed(key);
+        if is_list {
+            target.notify_blocked(key);
+        }
+        if let Some(prev) = prev {
+            target.shared.free(Lazyfree::Expire, prev.data);
         }
 
-        // Track the expiration of new entry.
         if let Some(when) = expires_at {
-            self.shared
-                .state
+            let notify = target_shard
+                .next_expiration()
+                .is_none_or(|expiration| when < expiration);
+            target_shard
                 .expirations
                 .lock()
                 .unwrap()
-                .insert((when, stored_key));
+                .insert(when, stored_key);
+
+            if notify {
+                target.shared.background_task.notify_one();
+            }
         }
 
-        // Notify the background task if it needs to update its state to reflect new expiration.
-        if notify {
-            self.shared.background_task.notify_one();
+        true
+    }
+
+    /// Record `entry` of `key` moved to the `Db`. Lists are recorded as pushed to a new list,
+    /// the only way commands create them, then given their expiration.
+    fn record_moved(&self, key: &Bytes, entry: &Entry) {
+        let changelog = &self.shared.state.changelog;
+        let Value::List(list) = &entry.data else {
+            changelog.record(key, || entry.set_op());
+            return;
+        };
+
+        changelog.record(key, || Op::Push {
+            elements: list.iter().collect(),
+            front: false,
+        });
+        if let Some(when) = entry.expires_at {
+            changelog.record(key, || Op::Expire {
+                expires_at: Some(system_time(when)),
+            });
         }
     }
 
-    /// Pop the first element of an array.
-    /// Returns `None` if the array is empty or key does not exist.
-    /// Returns `Err` if key holds a non-array value.
-    pub(crate) fn pop_front(&self, key: &Bytes) -> Result<Option<Data>, WalrusError> {
-        let mut remove = false;
-        let data = {
-            let maybe_entry = self.shared.state.entries.get_mut(key);
-            if let Some(mut entry) = maybe_entry {
-                match entry.data {
-                    Data::Array(ref mut arr) => {
-                        let data = arr.pop_front();
-                        if arr.is_empty() {
-                            remove = true;
-                        }
-                        Ok(data)
+    /// Time left before the key expires, `Some(None)` if it never expires and `None` if the key
+    /// doesn't exist.
+    pub fn ttl(&self, key: &Bytes) -> Option<Option<Duration>> {
+        let entry = self.inspect(key)?;
+        let now = Instant::now();
+        Some(
+            entry
+                .expires_at
+                .map(|when| when.saturating_duration_since(now)),
+        )
+    }
+
+    /// Set the key to expire after `expire`, or to never expire if `None`. Returns `false` if
+    /// the key doesn't exist.
+    pub fn expire(&self, key: &Bytes, expire: Option<Duration>) -> bool {
+        let state = &self.shared.state;
+        let shard = state.shard(key);
+        let Some(mut entry) = self.get_mut(key) else {
+            return false;
+        };
+
+        let expires_at = expire.map(|duration| Instant::now() + duration);
+        state.counts.remove(&entry);
+        entry.expires_at = expires_at;
+        state.counts.add(&entry);
+        let stored_key = entry.entry.key().clone();
+        entry.record(|| Op::Expire {
+            expires_at: expires_at.map(system_time),
+        });
+        // The value of the key is unchanged, its watchers aren't notified.
+        drop(entry);
+
+        // The previous expiration becomes a tombstone.
+        if let Some(when) = expires_at {
+            let notify = shard
+                .next_expiration()
+                .is_none_or(|expiration| when < expiration);
+            shard.expirations.lock().unwrap().insert(when, stored_key);
+
+            if notify {
+                self.shared.background_task.notify_one();
+            }
+        }
+
+        true
+    }
+
+    /// Append `values` to the end of the list of `key`, created if it doesn't exist. Returns
+    /// the length of the list, or `WrongType` error if the key doesn't hold a list.
+    pub fn push(
+        &self,
+        key: &Bytes,
+        values: impl IntoIterator<Item = Data>,
+    ) -> Result<usize, WalrusError> {
+        let limits = self.listpack_limits();
+        let len = {
+            let mut entry = self.get_or_insert_with(key, || Value::List(List::new()));
+            let size = entry.data.size();
+            let recorded = entry.is_recorded();
+            let list = entry.data.as_list_mut()?;
+            let mut elements = Vec::new();
+            for data in values {
+                if recorded {
+                    elements.push(data.clone());
+                }
+                list.push_back(data, limits);
+            }
+            let len = list.len();
+            entry.resize(size);
+            entry.record_push(elements, false);
+            len
+        };
+
+        if len == 0 {
+            // Nothing was pushed to a new list.
+            self.remove_empty_list(key);
+        } else {
+            self.pushed(key);
+        }
+
+        Ok(len)
+    }
+
+    /// Version of the value of a key, `None` if the key doesn't exist. Every write to the key,
+    /// including replacing or removing and creating it again, gives it a new version.
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use walrus::db::{Data, DbDropGuard};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let guard = DbDropGuard::new();
+    /// let db = guard.get_db();
+    ///
+    /// let key = Bytes::from("key");
+    /// assert_eq!(db.version(&key), None);
+    /// db.set(&key, Data::Integer(1), None);
+    /// let version = db.version(&key);
+    /// db.set(&key, Data::Integer(1), None);
+    /// assert_ne!(db.version(&key), version);
+    /// # }
+    /// ```
+    pub fn version(&self, key: &Bytes) -> Option<u64> {
+        self.inspect(key).map(|entry| entry.version)
+    }
+
+    /// Set `key` to `value` like `set`, only if the version of its value is still `expected`,
+    /// as returned by `version`. `None` expects the key not to exist. The comparison and the
+    /// write are a single step, returns `true` if the key was set.
+    ///
+    /// A value read along with its version is only replaced if no other write happened since,
+    /// retrying otherwise:
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use walrus::db::{Data, DbDropGuard};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let guard = DbDropGuard::new();
+    /// let db = guard.get_db();
+    ///
+    /// let key = Bytes::from("counter");
+    /// loop {
+    ///     let version = db.version(&key);
+    ///     let count = match db.get(&key) {
+    ///         Some(Data::Integer(count)) => count,
+    ///         _ => 0,
+    ///     };
+    ///     if db.compare_and_swap(&key, version, Data::Integer(count + 1), None) {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(db.get(&key), Some(Data::Integer(1)));
+    /// # }
+    /// ```
+    pub fn compare_and_swap(
+        &self,
+        key: &Bytes,
+        expected: Option<u64>,
+        value: Data,
+        expire: Option<Duration>,
+    ) -> bool {
+        self.set_if(key, value, expire, |current| {
+            current.map(|entry| entry.version) == expected
+        })
+    }
+
+    /// Set `key` to `value` if `condition` holds for its current entry, `None` if it doesn't
+    /// exist or expired. The entry is locked while the condition is checked, returns `true` if
+    /// the key was set.
+    fn set_if(
+        &self,
+        key: &Bytes,
+        value: Data,
+        expire: Option<Duration>,
+        condition: impl FnOnce(Option<&Entry>) -> bool,
+    ) -> bool {
+        let state = &self.shared.state;
+        let stored_value = Value::new(value, state.listpack_limits());
+        let shard = state.shard(key);
+        let now = Instant::now();
+        let clock = state.clock(now);
+
+        // Calculate the instant at which key will expire.
+        let expires_at = expire.map(|duration| now + duration);
+
+        // Keys are allocated once, the entry and its expirations share the stored key. The
+        // expiration of a previous entry becomes a tombstone.
+        let stored_key = match shard.entries.get_mut(key) {
+            Some(mut entry) => {
+                if !condition((!entry.is_expired(now)).then_some(&*entry)) {
+                    return false;
+                }
+
+                shard.untrack(&entry);
+                state.counts.remove(&entry);
+                let prev = mem::replace(
+                    &mut *entry,
+                    Entry::new(key, stored_value, expires_at, clock, state.next_version()),
+                );
+                shard.track(&entry);
+                state.counts.add(&entry);
+                let stored_key = entry.key().clone();
+                state.changelog.record(key, || entry.set_op());
+
+                // The previous value is freed once the entry is unlocked.
+                drop(entry);
+                self.shared.free(Lazyfree::ServerDel, prev.data);
+                stored_key
+            }
+            None => {
+                // The `key` still refers to the Bytes from the BytesMut buffer, to avoid memory
+                // mapping copy it before storing.
+                let stored_key = Bytes::copy_from_slice(key);
+
+                // The key may have been set by another connection in the meantime.
+                let map_entry = shard.entries.entry(stored_key.clone());
+                let current = match &map_entry {
+                    MapEntry::Occupied(occupied) if !occupied.get().is_expired(now) => {
+                        Some(occupied.get())
                     }
-                    _ => Err(WalrusError::WrongType),
+                    _ => None,
+                };
+                if !condition(current) {
+                    return false;
                 }
-            } else {
-                return Ok(None);
+
+                let entry = Entry::new(key, stored_value, expires_at, clock, state.next_version());
+                shard.track(&entry);
+                state.counts.add(&entry);
+                match map_entry {
+                    MapEntry::Occupied(mut occupied) => {
+                        let prev = occupied.insert(entry);
+                        shard.untrack(&prev);
+                        state.counts.remove(&prev);
+                        state.changelog.record(key, || occupied.get().set_op());
+                        drop(occupied);
+                        self.shared.free(Lazyfree::ServerDel, prev.data);
+                    }
+                    MapEntry::Vacant(vacant) => {
+                        state.index(shard, &stored_key);
+                        let entry = vacant.insert(entry);
+                        state.changelog.record(key, || entry.set_op());
+                    }
+                }
+                stored_key
             }
         };
+        self.shared.changed(&stored_key);
 
-        if remove {
-            self.shared.state.entries.remove(key);
+        // Track the expiration of new entry. Notify the background task if the new key expires
+        // earlier than the next expiration of its shard. The background task may be scheduled
+        // earlier for another shard, waking it up is harmless then.
+        if let Some(when) = expires_at {
+            let notify = shard
+                .next_expiration()
+                .is_none_or(|expiration| when < expiration);
+            shard.expirations.lock().unwrap().insert(when, stored_key);
+
+            if notify {
+                self.shared.background_task.notify_one();
+            }
+        }
+
+        true
+    }
+
+    /// Remove every key, as when a replica replaces its dataset with the one of its master.
+    pub(crate) fn clear(&self) {
+        let state = &self.shared.state;
+
+        let tracking = state.tracking.is_active();
+
+        for shard in &state.shards {
+            shard.entries.retain(|key, entry| {
+                shard.untrack(entry);
+                state.counts.remove(entry);
+                if tracking {
+                    state.tracking.invalidate(key, None);
+                }
+                // The entry is dropped by the map, its value is taken to be freed lazily.
+                let data = mem::replace(&mut entry.data, Value::Integer(0));
+                self.shared.free(Lazyfree::ReplicaFlush, data);
+                false
+            });
+            shard.expirations.lock().unwrap().clear();
+            shard.scan.lock().unwrap().clear();
+        }
+
+        for watcher in state.watchers.iter() {
+            watcher.send_replace(None);
         }
+    }
 
-        data
+    /// Pop the first element of an array.
+    /// Returns `None` if the array is empty or key does not exist.
+    /// Returns `Err` if key holds a non-array value.
+    pub(crate) fn pop_front(&self, key: &Bytes) -> Result<Option<Data>, WalrusError> {
+        self.pop(key, true)
     }
 
     /// Pop the last element of an array.
     /// Returns `None` if the array is empty or key does not exist.
     /// Returns `Err` if key holds a non-array value.
+    #[allow(dead_code)]
     pub(crate) fn pop_back(&self, key: &Bytes) -> Result<Option<Data>, WalrusError> {
-        let mut remove = false;
-        let data = {
-            let maybe_entry = self.shared.state.entries.get_mut(key);
-            if let Some(mut entry) = maybe_entry {
-                match entry.data {
-                    Data::Array(ref mut arr) => {
-                        let data = arr.pop_back();
-                        if arr.is_empty() {
-                            remove = true;
-                        }
-                        Ok(data)
-                    }
-                    _ => Err(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                    ),
-                }
-            } else {
+        self.pop(key, false)
+    }
+
+    /// Pop an element from the front of an array, or from its back, removing the array once it
+    /// is empty.
+    fn pop(&self, key: &Bytes, front: bool) -> Result<Option<Data>, WalrusError> {
+        let (data, remove) = {
+            let Some(mut entry) = self.get_mut(key) else {
                 return Ok(None);
+            };
+            let size = entry.data.size();
+            let list = entry.data.as_list_mut()?;
+            let data = if front {
+                list.pop_front()
+            } else {
+                list.pop_back()
+            };
+            let remove = list.is_empty();
+            entry.resize(size);
+            if data.is_some() {
+                entry.record(|| Op::Pop { count: 1, front });
             }
+            (data, remove)
         };
 
         if remove {
-            self.shared.state.entries.remove(key);
+            self.remove_empty_list(key);
+        }
+        if data.is_some() {
+            self.popped(key);
+        }
+
+        Ok(data)
+    }
+
+    /// Remove the list of `key` if it is still empty, elements may have been pushed since it
+    /// was emptied.
+    pub(crate) fn remove_empty_list(&self, key: &Bytes) {
+        let state = &self.shared.state;
+        let shard = state.shard(key);
+        if let Some((_, entry)) = shard.entries.remove_if(key, |key, entry| {
+            let empty = matches!(&entry.data, Value::List(list) if list.is_empty());
+            if empty {
+                state.unindex(shard, key);
+            }
+            empty
+        }) {
+            shard.untrack(&entry);
+            state.counts.remove(&entry);
+        }
+    }
+
+    /// Number of keys, of `kind` if given. Expired keys not purged yet are included.
+    pub(crate) fn key_count(&self, kind: Option<Kind>) -> usize {
+        let counts = &self.shared.state.counts;
+        match kind {
+            Some(kind) => counts.of(kind).load(Ordering::Relaxed),
+            None => [Kind::String, Kind::List]
+                .into_iter()
+                .map(|kind| counts.of(kind).load(Ordering::Relaxed))
+                .sum(),
+        }
+    }
+
+    /// Approximate memory used by the keys and values, in bytes.
+    pub(crate) fn used_memory(&self) -> usize {
+        self.shared
+            .state
+            .shards
+            .iter()
+            .map(|shard| shard.memory.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Approximate memory used by `key` and its value, in bytes, `None` if the key doesn't
+    /// exist. The size of every value is tracked as it is written, whatever its length.
+    pub(crate) fn memory_usage(&self, key: &Bytes) -> Option<usize> {
+        self.inspect(key).map(|entry| entry.size)
+    }
+
+    /// Evict keys following `policy` until the dataset uses at most `maxmemory` bytes.
+    /// Returns `false` if the dataset is still larger, no key can be evicted with the policy.
+    ///
+    /// Like Redis the least recently used key is approximated, each key evicted is the least
+    /// recently used of `samples` keys sampled from a random shard.
+    pub(crate) fn evict(&self, maxmemory: usize, policy: MaxmemoryPolicy, samples: usize) -> bool {
+        let state = &self.shared.state;
+
+        while self.used_memory() > maxmemory {
+            let (volatile, lfu) = match policy {
+                MaxmemoryPolicy::NoEviction => return false,
+                MaxmemoryPolicy::AllkeysLru => (false, false),
+                MaxmemoryPolicy::VolatileLru => (true, false),
+                MaxmemoryPolicy::AllkeysLfu => (false, true),
+                MaxmemoryPolicy::VolatileLfu => (true, true),
+            };
+
+            let Some((shard, key)) = self.eviction_candidate(volatile, lfu, samples) else {
+                return false;
+            };
+
+            if let Some((key, entry)) = shard.entries.remove_if(&key, |key, _| {
+                state.changelog.record(key, || Op::Evicted);
+                state.unindex(shard, key);
+                true
+            }) {
+                shard.untrack(&entry);
+                state.counts.remove(&entry);
+                state.evicted.fetch_add(1, Ordering::Relaxed);
+
+                // Connections caching the key must drop it.
+                state.tracking.invalidate(&key, None);
+                self.shared.changed(&key);
+                self.shared.free(Lazyfree::Eviction, entry.data);
+            }
         }
 
-        data
+        true
+    }
+
+    /// Least recently used of `samples` keys picked at random in a random shard, or least
+    /// frequently used if `lfu`, only keys with an expiration if `volatile`. Shards without
+    /// such keys are skipped.
+    fn eviction_candidate(
+        &self,
+        volatile: bool,
+        lfu: bool,
+        samples: usize,
+    ) -> Option<(&Shard, Bytes)> {
+        let state = &self.shared.state;
+        if volatile && self.expires_count() == 0 {
+            return None;
+        }
+
+        // Keys accessed as often are evicted least recently used first.
+        let clock = state.clock(Instant::now());
+        let score = |entry: &Entry| {
+            let frequency = if lfu { entry.frequency(clock) } else { 0 };
+            (frequency, entry.accessed.load(Ordering::Relaxed))
+        };
+
+        let first = rand::rng().random_range(0..SHARDS);
+
+        (0..SHARDS).find_map(|i| {
+            let shard = &state.shards[(first + i) & (SHARDS - 1)];
+            let sampled = if volatile {
+                shard.sample_volatile(samples, score)
+            } else {
+                shard.sample(samples, score)
+            };
+
+            sampled
+                .into_iter()
+                .min_by_key(|(score, _)| *score)
+                .map(|(_, key)| (shard, key))
+        })
+    }
+
+    /// Approximate access frequency of `entry`, see `Entry::frequency`.
+    pub(crate) fn frequency(&self, entry: &Entry) -> u32 {
+        entry.frequency(self.shared.state.clock(Instant::now()))
+    }
+
+    /// Count a command named `name` executed in `elapsed`, which replied with an error if
+    /// `failed`.
+    pub(crate) fn count_command(&self, name: &'static str, elapsed: Duration, failed: bool) {
+        let usec = elapsed.as_micros() as u64;
+        let count = |counters: &CommandCounters| {
+            counters.calls.fetch_add(1, Ordering::Relaxed);
+            counters.usec.fetch_add(usec, Ordering::Relaxed);
+            counters.max_usec.fetch_max(usec, Ordering::Relaxed);
+            if failed {
+                counters.failed_calls.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+
+        let commands = &self.shared.state.commands;
+        match commands.get(name) {
+            Some(counters) => count(&counters),
+            None => count(&commands.entry(name).or_default()),
+        }
+    }
+
+    /// Reset the statistics returned by `stats` to zero, except for the values waiting to be
+    /// freed.
+    pub fn reset_stats(&self) {
+        let state = &self.shared.state;
+        state.hits.store(0, Ordering::Relaxed);
+        state.misses.store(0, Ordering::Relaxed);
+        state.expired.store(0, Ordering::Relaxed);
+        state.evicted.store(0, Ordering::Relaxed);
+        state.freeing.freed.store(0, Ordering::Relaxed);
+        state.commands.clear();
+    }
+
+    /// Statistics of the keyspace since the `Db` was created.
+    pub fn stats(&self) -> Stats {
+        let state = &self.shared.state;
+        let mut commands: Vec<_> = state
+            .commands
+            .iter()
+            .map(|counters| {
+                let stats = CommandStats {
+                    calls: counters.calls.load(Ordering::Relaxed),
+                    usec: counters.usec.load(Ordering::Relaxed),
+                    max_usec: counters.max_usec.load(Ordering::Relaxed),
+                    failed_calls: counters.failed_calls.load(Ordering::Relaxed),
+                };
+                (*counters.key(), stats)
+            })
+            .collect();
+        commands.sort_unstable_by_key(|(name, _)| *name);
+
+        Stats {
+            keyspace_hits: state.hits.load(Ordering::Relaxed),
+            keyspace_misses: state.misses.load(Ordering::Relaxed),
+            expired_keys: state.expired.load(Ordering::Relaxed),
+            evicted_keys: state.evicted.load(Ordering::Relaxed),
+            lazyfreed_objects: state.freeing.freed.load(Ordering::Relaxed),
+            lazyfree_pending_objects: state.freeing.pending.load(Ordering::Relaxed),
+            commands,
+        }
+    }
+
+    /// Time since `entry` was last accessed.
+    #[cfg_attr(not(feature = "debug-command"), allow(dead_code))]
+    pub(crate) fn idle_time(&self, entry: &Entry) -> Duration {
+        let state = &self.shared.state;
+        let accessed = entry.accessed.load(Ordering::Relaxed);
+        Duration::from_millis(state.clock(Instant::now()).saturating_sub(accessed))
+    }
+
+    /// Number of keys with an expiration.
+    pub(crate) fn expires_count(&self) -> usize {
+        self.shared.state.counts.expires.load(Ordering::Relaxed)
+    }
+
+    /// Subscribe to the changes of keys. Events are sent as keys are written, expired and
+    /// evicted, the values aren't included. Keys removed by clearing the `Db`, as when a replica
+    /// loads the dataset of its master, have no events.
+    ///
+    /// Events are only kept for subscribers until `EVENTS_CAPACITY` newer events are sent, a
+    /// subscriber falling further behind misses the oldest events and its next receive fails
+    /// with `RecvError::Lagged`.
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyEvent> {
+        self.shared.state.changelog.subscribe()
+    }
+
+    /// Pass every change recorded from now on to `consumer`, such as the append only file and
+    /// the replicas of a server logging the writes of its `Db`.
+    ///
+    /// Changes are passed in the order of their sequence number, which is the order they were
+    /// made in for the changes of a key, and include what was written, unlike the `KeyEvent`s
+    /// of `subscribe`. The key is still locked when its change is passed, `consumer` must be
+    /// quick and must not use the `Db`. Keys removed by clearing the `Db` have no changes.
+    pub fn on_change(&self, consumer: impl Fn(&Change) + Send + Sync + 'static) {
+        self.shared.state.changelog.add_consumer(Box::new(consumer));
+    }
+
+    /// Pass the duration of every cycle of the background task purging expired keys to
+    /// `consumer`, such as the latency monitor of a server. Only the first consumer is kept.
+    pub fn on_expire_cycle(&self, consumer: impl Fn(Duration) + Send + Sync + 'static) {
+        let _ = self.shared.state.expire_cycle_hook.set(Box::new(consumer));
+    }
+
+    /// Watch the value of `key`, `None` while it doesn't exist. The receiver is marked changed
+    /// as the key is written, expired or evicted, possibly more than once for one change.
+    /// Lists are cloned for every change while watched.
+    pub fn watch(&self, key: &Bytes) -> watch::Receiver<Option<Data>> {
+        let state = &self.shared.state;
+
+        // The watcher is locked while the value is read, changes made meanwhile are sent once
+        // it is added.
+        match state.watchers.entry(Bytes::copy_from_slice(key)) {
+            MapEntry::Occupied(watcher) => watcher.get().subscribe(),
+            MapEntry::Vacant(vacant) => {
+                let (watcher, receiver) = watch::channel(self.shared.value(key));
+                vacant.insert(watcher);
+                receiver
+            }
+        }
+    }
+
+    /// Report elements pushed to the list of `key`, waking up a connection waiting on it. The
+    /// push is recorded with `EntryMut::record_push`.
+    pub(crate) fn pushed(&self, key: &Bytes) {
+        self.notify_blocked(key);
+        self.shared.changed(key);
+    }
+
+    /// Report elements popped from the list of `key`. The pop is recorded with
+    /// `EntryMut::record`.
+    pub(crate) fn popped(&self, key: &Bytes) {
+        self.shared.changed(key);
     }
 
     /// Notify a connection waiting on a key.
-    pub(crate) fn notify_blocked(&self, key: &Bytes) {
+    fn notify_blocked(&self, key: &Bytes) {
         if let Some(notify) = self.shared.state.blocking_keys.get(key) {
             notify.notify_one();
         }
@@ -272,14 +1556,85 @@ impl Db {
 
     /// Get or create a notifier for a key.
     pub(crate) fn get_or_create_notifier(&self, key: &Bytes) -> Arc<Notify> {
-        self.shared
-            .state
-            .blocking_keys
-            .entry(key.clone())
+        let blocking_keys = &self.shared.state.blocking_keys;
+        if let Some(notify) = blocking_keys.get(key) {
+            return notify.clone();
+        }
+
+        // Copy the key, like keys of entries, so it doesn't keep the read buffer alive.
+        blocking_keys
+            .entry(Bytes::copy_from_slice(key))
             .or_insert_with(|| Arc::new(Notify::new()))
             .clone()
     }
 
+    /// Client side caching state, used to invalidate keys cached by connections.
+    pub(crate) fn tracking(&self) -> &Tracking {
+        &self.shared.state.tracking
+    }
+
+    /// Enable or disable purging of expired keys by the background task.
+    /// Expired keys are kept until it is enabled again.
+    #[cfg_attr(not(feature = "debug-command"), allow(dead_code))]
+    pub(crate) fn set_active_expire(&self, enabled: bool) {
+        self.shared
+            .state
+            .active_expire
+            .store(enabled, Ordering::Relaxed);
+
+        // Wake up the background task to purge the keys that expired in the meantime.
+        if enabled {
+            self.shared.background_task.notify_one();
+        }
+    }
+
+    /// Thresholds under which lists are stored as listpacks.
+    pub(crate) fn listpack_limits(&self) -> ListpackLimits {
+        self.shared.state.listpack_limits()
+    }
+
+    /// Set the thresholds under which lists are stored as listpacks, as with `CONFIG SET`.
+    /// Lists already upgraded stay upgraded.
+    pub(crate) fn set_listpack_limits(&self, limits: ListpackLimits) {
+        let state = &self.shared.state;
+        state
+            .listpack_entries
+            .store(limits.entries, Ordering::Relaxed);
+        state.listpack_value.store(limits.value, Ordering::Relaxed);
+    }
+
+    /// Set the deletions freeing large values on the lazyfree thread, a combination of
+    /// `Lazyfree` flags as with `CONFIG SET lazyfree-lazy-*`.
+    pub(crate) fn set_lazyfree(&self, lazyfree: u8) {
+        self.shared
+            .state
+            .lazyfree
+            .store(lazyfree, Ordering::Relaxed);
+    }
+
+    /// Set the percentage of its time to live randomly added to an expiration set by a
+    /// command, as with `CONFIG SET expire-jitter`.
+    pub(crate) fn set_expire_jitter(&self, percent: u8) {
+        self.shared
+            .state
+            .expire_jitter
+            .store(percent, Ordering::Relaxed);
+    }
+
+    /// Time to live `expire` extended by a random part of the `expire-jitter` percentage of it.
+    /// Keys set together with the same time to live then expire spread over a period instead of
+    /// all at once. Applied by commands only: a key loaded or set through the `Db` API expires
+    /// exactly when asked.
+    pub(crate) fn jitter(&self, expire: Duration) -> Duration {
+        let percent = self.shared.state.expire_jitter.load(Ordering::Relaxed);
+        if percent == 0 {
+            return expire;
+        }
+
+        let max = expire.mul_f64(percent as f64 / 100.0);
+        expire + max.mul_f64(rand::rng().random_range(0.0..=1.0))
+    }
+
     /// Signals the background task to shutdown.
     fn shutdown_purge_task(&self) {
         // Set state.shutdown to `true` signaling the background task to shutdown.
@@ -294,17 +1649,45 @@ impl Db {
 impl DbDropGuard {
     /// Create a new `DbDropGuard` instance, this wraps a `Db` instance.
     /// Dropping DbDropGuard will shutdown the `Db`'s background purge task.
-    pub(crate) fn new() -> DbDropGuard {
-        DbDropGuard { db: Db::new() }
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub fn new() -> DbDropGuard {
+        DbDropGuard {
+            db: Db::new(Arc::new(Tracking::new())),
+        }
+    }
+
+    /// Create the `count` logical databases of a server, each with its own keys and its own
+    /// background task purging them as they expire. Connections tracking keys are notified
+    /// of writes to any of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime.
+    pub(crate) fn databases(count: usize) -> Vec<DbDropGuard> {
+        let tracking = Arc::new(Tracking::new());
+        (0..count)
+            .map(|_| DbDropGuard {
+                db: Db::new(tracking.clone()),
+            })
+            .collect()
     }
 
     /// Get the shared `Db`. Since Db has Arc internally -- cloning it is same as cloning
     /// Arc so it only increments the ref count.
-    pub(crate) fn get_db(&self) -> Db {
+    pub fn get_db(&self) -> Db {
         self.db.clone()
     }
 }
 
+impl Default for DbDropGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Drop for DbDropGuard {
     fn drop(&mut self) {
         // Signal the `Db` instance to shutdown the background task that purges expired keys.
@@ -313,53 +1696,333 @@ impl Drop for DbDropGuard {
 }
 
 impl State {
+    /// Version of an entry written now.
+    fn next_version(&self) -> u64 {
+        self.versions.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Thresholds under which lists are stored as listpacks.
+    fn listpack_limits(&self) -> ListpackLimits {
+        ListpackLimits {
+            entries: self.listpack_entries.load(Ordering::Relaxed),
+            value: self.listpack_value.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Milliseconds elapsed at `now` since the creation of the `Db`.
+    fn clock(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.epoch).as_millis() as u64
+    }
+
+    /// Shard of `key`.
+    fn shard(&self, key: &[u8]) -> &Shard {
+        &self.shards[self.hash(key) as usize & (SHARDS - 1)]
+    }
+
+    /// Hash of `key`, its lowest bits pick its shard.
+    fn hash(&self, key: &[u8]) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    /// Hash of `key` in the scan index of its shard, the bits of its hash above the ones
+    /// picking the shard.
+    fn scan_hash(&self, key: &[u8]) -> u64 {
+        self.hash(key) >> SHARDS.trailing_zeros()
+    }
+
+    /// Add `key`, inserted in `shard`, to the scan index of the shard.
+    fn index(&self, shard: &Shard, key: &Bytes) {
+        let hash = self.scan_hash(key);
+        shard.scan.lock().unwrap().insert(hash, key.clone());
+    }
+
+    /// Remove `key`, removed from `shard`, from the scan index of the shard.
+    fn unindex(&self, shard: &Shard, key: &[u8]) {
+        let hash = self.scan_hash(key);
+        shard.scan.lock().unwrap().remove(hash, key);
+    }
+}
+
+impl Counts {
+    /// Count a new entry.
+    fn add(&self, entry: &Entry) {
+        self.of(entry.data.kind()).fetch_add(1, Ordering::Relaxed);
+        if entry.expires_at.is_some() {
+            self.expires.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stop counting a removed entry, or an entry about to be replaced.
+    fn remove(&self, entry: &Entry) {
+        self.of(entry.data.kind()).fetch_sub(1, Ordering::Relaxed);
+        if entry.expires_at.is_some() {
+            self.expires.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counter of the keys of `kind`.
+    fn of(&self, kind: Kind) -> &AtomicUsize {
+        match kind {
+            Kind::String => &self.strings,
+            Kind::List => &self.lists,
+        }
+    }
+}
+
+impl EntryMut<'_> {
+    /// Returns `true` if changes are recorded, the elements of a push only need to be copied
+    /// for `record_push` then.
+    pub(crate) fn is_recorded(&self) -> bool {
+        self.changelog.is_active()
+    }
+
+    /// Record the change `op` of the entry in the changelog of the `Db`.
+    pub(crate) fn record(&self, op: impl FnOnce() -> Op) {
+        self.changelog.record(self.entry.key(), op);
+    }
+
+    /// Record `elements` pushed to the front of the list of the entry, or to its back. Nothing
+    /// is recorded if no elements were pushed.
+    pub(crate) fn record_push(&self, elements: Vec<Data>, front: bool) {
+        if !elements.is_empty() {
+            self.record(|| Op::Push { elements, front });
+        }
+    }
+
+    /// Report a change of the value, such as pushed or popped elements, from `previous` bytes
+    /// as returned by `Data::size` before the change.
+    pub(crate) fn resize(&mut self, previous: usize) {
+        let current = self.entry.data.size();
+        self.entry.size = self.entry.size + current - previous;
+        if current > previous {
+            self.memory.fetch_add(current - previous, Ordering::Relaxed);
+        } else {
+            self.memory.fetch_sub(previous - current, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Deref for EntryMut<'_> {
+    type Target = Entry;
+
+    fn deref(&self) -> &Entry {
+        &self.entry
+    }
+}
+
+impl DerefMut for EntryMut<'_> {
+    fn deref_mut(&mut self) -> &mut Entry {
+        &mut self.entry
+    }
+}
+
+impl Shard {
+    /// Count the memory of an entry added to the shard.
+    fn track(&self, entry: &Entry) {
+        self.memory.fetch_add(entry.size, Ordering::Relaxed);
+    }
+
+    /// Stop counting the memory of an entry removed from the shard, or about to be replaced.
+    fn untrack(&self, entry: &Entry) {
+        self.memory.fetch_sub(entry.size, Ordering::Relaxed);
+    }
+
+    /// `count` entries picked at random, a key may be picked twice, with their `score`.
+    ///
+    /// Buckets of a random map shard are probed at random rather than iterated, so sampling
+    /// takes O(count) probes however many keys the shard holds. A map shard left sparse by
+    /// removals may not be hit, its first entries are then taken instead.
+    fn sample<S>(&self, count: usize, score: impl Fn(&Entry) -> S) -> Vec<(S, Bytes)> {
+        let mut rng = rand::rng();
+        let maps = self.entries.shards();
+        let first = rng.random_range(0..maps.len());
+        let mut sampled = Vec::with_capacity(count);
+
+        for i in 0..maps.len() {
+            let map = maps[(first + i) & (maps.len() - 1)].read();
+            if map.is_empty() {
+                continue;
+            }
+
+            let buckets = map.buckets();
+            for _ in 0..count.saturating_mul(SAMPLE_PROBES) {
+                let index = rng.random_range(0..buckets);
+                // SAFETY: `index` is a bucket of the map, which is read locked: full buckets
+                // hold entries that are neither moved nor dropped until the guard is dropped,
+                // and the entry is only borrowed while the guard is held.
+                unsafe {
+                    if map.is_bucket_full(index) {
+                        let (key, entry) = map.bucket(index).as_ref();
+                        sampled.push((score(entry.get()), key.clone()));
+                    }
+                }
+                if sampled.len() == count {
+                    break;
+                }
+            }
+
+            if sampled.is_empty() {
+                // The map shard is sparse, it is read locked once at a time.
+                drop(map);
+                sampled.extend(
+                    self.entries
+                        .iter()
+                        .take(count)
+                        .map(|entry| (score(entry.value()), entry.key().clone())),
+                );
+            }
+            return sampled;
+        }
+
+        sampled
+    }
+
+    /// `count` entries with an expiration picked at random from the expirations of the shard,
+    /// a key may be picked twice, with their `score`. Picked tombstones are skipped, fewer
+    /// entries are returned.
+    fn sample_volatile<S>(&self, count: usize, score: impl Fn(&Entry) -> S) -> Vec<(S, Bytes)> {
+        let mut timers = Vec::with_capacity(count);
+        self.expirations.lock().unwrap().sample(count, &mut timers);
+
+        // The lock is dropped before reading DashMap entries to avoid deadlock.
+        timers
+            .into_iter()
+            .filter_map(|(when, key)| {
+                let entry = self.entries.get(&key)?;
+                // Skip tombstones, the key was removed or given another expiration since.
+                (entry.expires_at == Some(when)).then(|| (score(&entry), key))
+            })
+            .collect()
+    }
+
     /// Get the `Instant` of next expiration if any.
+    /// Keys of the next expiration may not be due yet, the wheel is only advanced then.
     fn next_expiration(&self) -> Option<Instant> {
-        self.expirations
-            .lock()
-            .unwrap()
-            .iter()
-            .next()
-            .map(|expiration| expiration.0)
+        self.expirations.lock().unwrap().next_expiration()
     }
 }
 
 impl Shared {
-    /// Purge all expired keys and return the `Instant` at which the next key will expire.
-    /// Background task will sleep until this instant.
-    fn purge_expired_keys(&self) -> Option<Instant> {
+    /// Purge expired keys, at most `PURGE_BATCH` per shard, and return the `Instant` at which
+    /// the next key will expire with the number of keys purged. Background task will sleep
+    /// until this instant, it is already past if expired keys are left.
+    fn purge_expired_keys(&self) -> (Option<Instant>, usize) {
         if self.state.shutdown.load(Ordering::Relaxed) {
             // The database is shutting down. The background task should exit.
-            return None;
+            return (None, 0);
+        }
+
+        if !self.state.active_expire.load(Ordering::Relaxed) {
+            // Purging is disabled, wait to be notified when it is enabled again.
+            return (None, 0);
         }
 
-        // Find all keys scheduled to expire before `now`.
+        // Find all keys scheduled to expire before `now`, one shard at a time. The background
+        // task waits until the earliest next expiration of all shards.
         let now = Instant::now();
+        let mut purged = 0;
 
-        loop {
-            let mut expirations = self.state.expirations.lock().unwrap();
-            if let Some(&(when, ref key)) = expirations.iter().next() {
-                if when > now {
-                    // Done purging, `when` is the instant at which the next key will expire.
-                    // The worker task will wait until this instant.
-                    return Some(when);
-                }
+        let next = self
+            .state
+            .shards
+            .iter()
+            .filter_map(|shard| self.purge_shard(shard, now, &mut purged))
+            .min();
+        (next, purged)
+    }
 
-                let key_clone = key.clone();
-                let when_clone = when;
+    /// Purge up to `PURGE_BATCH` keys of `shard` expired at `now`, counted in `purged`, and
+    /// return the `Instant` at which the expirations of the shard have to be checked next.
+    fn purge_shard(&self, shard: &Shard, now: Instant, purged: &mut usize) -> Option<Instant> {
+        let mut expired = Vec::new();
+        let next = {
+            let mut expirations = shard.expirations.lock().unwrap();
+            expirations.poll(now, PURGE_BATCH, &mut expired);
 
-                // Remove from expirations set first.
-                expirations.remove(&(when_clone, key_clone.clone()));
+            if expired.len() >= PURGE_BATCH {
+                // Keys are left to purge, check the shard again in the next cycle.
+                Some(now)
+            } else {
+                expirations.next_expiration()
+            }
+        };
 
-                // Drop the lock before operating on DashMap entries to avoid deadlock.
-                drop(expirations);
+        // The lock is dropped before operating on DashMap entries to avoid deadlock.
+        for (when, key) in expired {
+            // Skip tombstones, the key was removed or given another expiration since.
+            let removed = shard.entries.remove_if(&key, |key, entry| {
+                let expired = entry.expires_at == Some(when);
+                if expired {
+                    self.state.changelog.record(key, || Op::Expired);
+                    self.state.unindex(shard, key);
+                }
+                expired
+            });
 
-                // Remove the expired entry from DashMap.
-                self.state.entries.remove(&key_clone);
-            } else {
-                return None;
+            if let Some((key, entry)) = removed {
+                shard.untrack(&entry);
+                self.state.counts.remove(&entry);
+                self.state.expired.fetch_add(1, Ordering::Relaxed);
+                *purged += 1;
+
+                // Connections caching the key must drop it.
+                self.state.tracking.invalidate(&key, None);
+                self.changed(&key);
+                self.free(Lazyfree::Expire, entry.data);
             }
         }
+
+        next
+    }
+
+    /// Free `data` removed from the map by `deletion`. Large values are sent to the lazyfree
+    /// thread if the deletion is lazy, the value is freed right away otherwise.
+    fn free(&self, deletion: Lazyfree, data: Value) {
+        let state = &self.state;
+        if state.lazyfree.load(Ordering::Relaxed) & deletion.flag() == 0
+            || data.free_effort() <= LAZYFREE_THRESHOLD
+        {
+            return;
+        }
+
+        state.freeing.pending.fetch_add(1, Ordering::Relaxed);
+        if state.free.send(data).is_err() {
+            // The thread stopped, the value was freed with the error.
+            state.freeing.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Report a change of the value of `key`, recorded in the changelog, sending the new value
+    /// to its watchers. Must be called without holding the entry of the key.
+    fn changed(&self, key: &Bytes) {
+        if !self.state.watchers.is_empty() {
+            self.update_watchers(key);
+        }
+    }
+
+    /// Send the current value of `key` to its watchers, and stop watching it once all of them
+    /// are dropped.
+    fn update_watchers(&self, key: &Bytes) {
+        let state = &self.state;
+        let Some(watcher) = state.watchers.get(key) else {
+            return;
+        };
+
+        if watcher.receiver_count() > 0 {
+            watcher.send_replace(self.value(key));
+            return;
+        }
+
+        drop(watcher);
+        state
+            .watchers
+            .remove_if(key, |_, watcher| watcher.receiver_count() == 0);
+    }
+
+    /// Value of `key`, `None` if it doesn't exist or expired.
+    fn value(&self, key: &Bytes) -> Option<Data> {
+        let entry = self.state.shard(key).entries.get(key)?;
+        (!entry.is_expired(Instant::now())).then(|| entry.data.exported())
     }
 
     /// Returns `true` if database is shutting down.
@@ -368,16 +2031,45 @@ impl Shared {
     }
 }
 
+/// Free the values received, until every sender is dropped with the `Db`.
+fn free_values(values: mpsc::Receiver<Value>, freeing: &Freeing) {
+    for data in values {
+        drop(data);
+        freeing.pending.fetch_sub(1, Ordering::Relaxed);
+        freeing.freed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Minutes elapsed at `clock`, wrapping around after 24 bits.
+fn minutes(clock: u64) -> u32 {
+    (clock / 60_000) as u32 & 0xff_ffff
+}
+
 /// Executed by background tasks.
 ///
 /// Wait to be notified. On notification purge any expired keys from the
 /// shared state. If `shutdown` is set, terminate the task.
 async fn purge_expired_tasks(shared: Arc<Shared>) {
     while !shared.is_shutdown() {
-        // Purges all expired keys, the function returns the instant at which next
+        // Purges expired keys, the function returns the instant at which next
         // key will expire. The worker must wait until the instant has passed or is
         // notified.
-        if let Some(when) = shared.purge_expired_keys() {
+        let start = Instant::now();
+        let (next, purged) = shared.purge_expired_keys();
+        if let Some(hook) = shared.state.expire_cycle_hook.get() {
+            hook(start.elapsed());
+        }
+        if purged > 0 {
+            trace!(keys = purged, "Purged expired keys");
+        }
+
+        if let Some(when) = next {
+            if when <= Instant::now() {
+                // Expired keys are left, let other tasks run before the next cycle.
+                tokio::task::yield_now().await;
+                continue;
+            }
+
             tokio::select! {
                 _ = time::sleep_until(when) => {},
                 _ = shared.background_task.notified() => {},
@@ -388,7 +2080,7 @@ async fn purge_expired_tasks(shared: Arc<Shared>) {
         }
     }
 
-    println!("Purge background task shutdown")
+    trace!("Purge background task shutdown")
 }
 
 /// Wait on any of the notifiers to be notified.
@@ -408,10 +2100,15 @@ pub(crate) async fn wait_on_any(notifiers: &[Arc<Notify>]) {
 /// # example
 /// b'123' will be represented as `Data::Integer(123)`.
 /// b'123.456' will be represented as `Data::Double(123.456)`.
+///
+/// Numbers are only stored as such if they are written back the same, b'-9223372036854775809'
+/// is out of the range of `i64` and would be rounded as a double, it stays `Data::Bytes`.
 pub(crate) fn optimize_storage(bytes: Bytes) -> Data {
     if let Some(i) = parse::extract_i64_strict(&bytes) {
         Data::Integer(i)
-    } else if let Some(f) = parse::extract_f64(&bytes) {
+    } else if let Some(f) = parse::extract_f64(&bytes)
+        && double_to_bytes(f) == bytes
+    {
         Data::Double(f)
     } else {
         Data::Bytes(bytes)
@@ -0,0 +1,146 @@
+use bytes::Bytes;
+use std::{
+    sync::{
+        Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
+};
+use tokio::{sync::broadcast, time::Instant};
+
+use super::{Data, KeyEvent};
+
+/// Number of events kept for subscribers falling behind, see `Db::subscribe`.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Write to a key, as recorded in the changelog of a `Db`, see `Db::on_change`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// Position of the change in the changelog, increasing with every change recorded.
+    pub seq: u64,
+    /// Key written.
+    pub key: Bytes,
+    /// What was written.
+    pub op: Op,
+}
+
+/// Operation of a `Change`, with what it wrote.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// The key was set to `value`, replacing any previous value, to expire at `expires_at`.
+    Set {
+        value: Data,
+        expires_at: Option<SystemTime>,
+    },
+    /// `elements` were pushed one after the other to the front of the list of the key, or to
+    /// its back, the list was created if it didn't exist.
+    Push { elements: Vec<Data>, front: bool },
+    /// `count` elements were popped from the front of the list of the key, or from its back,
+    /// the list was removed once empty.
+    Pop { count: usize, front: bool },
+    /// The key was removed.
+    Del,
+    /// The key was set to expire at `expires_at`, or to never expire if `None`.
+    Expire { expires_at: Option<SystemTime> },
+    /// The key expired and was removed.
+    Expired,
+    /// The key was evicted to stay under `maxmemory`.
+    Evicted,
+}
+
+/// Function a `Change` is passed to, see `Db::on_change`.
+type Consumer = Box<dyn Fn(&Change) + Send + Sync>;
+
+/// Ordered stream of the changes of a `Db`, passed to its consumers and sent to its
+/// subscribers as `KeyEvent`s.
+pub(crate) struct Changelog {
+    /// Functions every change is passed to, in the order they were added.
+    consumers: RwLock<Vec<Consumer>>,
+    /// `true` once a consumer is added, checked without locking on every write.
+    consumed: AtomicBool,
+    /// Sequence number of the last change. Held while a change is passed to the consumers and
+    /// sent to the subscribers, so they see changes in the order of their numbers.
+    seq: Mutex<u64>,
+    /// Subscribers of `KeyEvent`s, see `Db::subscribe`.
+    events: broadcast::Sender<KeyEvent>,
+}
+
+impl Changelog {
+    pub(crate) fn new() -> Changelog {
+        Changelog {
+            consumers: RwLock::new(Vec::new()),
+            consumed: AtomicBool::new(false),
+            seq: Mutex::new(0),
+            events: broadcast::Sender::new(EVENTS_CAPACITY),
+        }
+    }
+
+    /// Add `consumer`, changes recorded from now on are passed to it.
+    pub(crate) fn add_consumer(&self, consumer: Consumer) {
+        self.consumers.write().unwrap().push(consumer);
+        self.consumed.store(true, Ordering::Relaxed);
+    }
+
+    /// Subscribe to the `KeyEvent`s of the changes recorded from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<KeyEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns `true` if changes are consumed or subscribed to. `op` is only called to record
+    /// a change then, payloads aren't copied otherwise.
+    pub(crate) fn is_active(&self) -> bool {
+        self.consumed.load(Ordering::Relaxed) || self.events.receiver_count() > 0
+    }
+
+    /// Record the change `op` of `key`. Must be called while the key is locked, so the changes
+    /// of a key are recorded in the order they were made.
+    pub(crate) fn record(&self, key: &Bytes, op: impl FnOnce() -> Op) {
+        if !self.is_active() {
+            return;
+        }
+
+        let mut seq = self.seq.lock().unwrap();
+        *seq += 1;
+        // `key` may still refer to the Bytes from the BytesMut buffer, copy it rather than
+        // holding on to the buffer.
+        let change = Change {
+            seq: *seq,
+            key: Bytes::copy_from_slice(key),
+            op: op(),
+        };
+
+        for consumer in self.consumers.read().unwrap().iter() {
+            consumer(&change);
+        }
+
+        if self.events.receiver_count() > 0 {
+            // Subscribers may drop their receiver meanwhile.
+            let _ = self.events.send(KeyEvent::from(change));
+        }
+    }
+}
+
+impl From<Change> for KeyEvent {
+    fn from(change: Change) -> KeyEvent {
+        let event = match change.op {
+            Op::Set { .. } => KeyEvent::Set,
+            Op::Push { .. } => KeyEvent::Push,
+            Op::Pop { .. } => KeyEvent::Pop,
+            Op::Del => KeyEvent::Del,
+            Op::Expire { .. } => KeyEvent::Expire,
+            Op::Expired => KeyEvent::Expired,
+            Op::Evicted => KeyEvent::Evicted,
+        };
+        event(change.key)
+    }
+}
+
+/// Wall clock time of `when`, as recorded in changes consumed by other processes.
+pub(crate) fn system_time(when: Instant) -> SystemTime {
+    let now = Instant::now();
+    if when >= now {
+        SystemTime::now() + (when - now)
+    } else {
+        SystemTime::now() - (now - when)
+    }
+}
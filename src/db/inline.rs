@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// Longest string stored inline, the `Value` holding it is no larger than with a `Bytes`.
+pub(crate) const INLINE_CAPACITY: usize = 38;
+
+/// Short string stored within its `Value`, as Redis embeds short strings in their object.
+///
+/// Strings written by clients are slices of the frame they were read from, storing them as
+/// `Bytes` keeps that buffer alive and allocates its shared header. Short strings are copied
+/// instead, taking no allocation of their own.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Inline {
+    len: u8,
+    buf: [u8; INLINE_CAPACITY],
+}
+
+impl Inline {
+    /// Copy `bytes` inline, `None` if they are longer than `INLINE_CAPACITY`.
+    pub(crate) fn new(bytes: &[u8]) -> Option<Inline> {
+        if bytes.len() > INLINE_CAPACITY {
+            return None;
+        }
+
+        // Unused bytes stay zeroed so equal strings compare equal.
+        let mut buf = [0; INLINE_CAPACITY];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        Some(Inline {
+            len: bytes.len() as u8,
+            buf,
+        })
+    }
+
+    /// Content of the string.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl fmt::Debug for Inline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b\"{}\"", self.as_bytes().escape_ascii())
+    }
+}
@@ -0,0 +1,396 @@
+use bytes::Bytes;
+use std::{
+    collections::{VecDeque, vec_deque},
+    mem,
+    sync::Arc,
+};
+
+use super::Data;
+
+/// Thresholds under which a list is stored as a listpack, set with the
+/// `list-max-listpack-entries` and `list-max-listpack-value` configuration parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ListpackLimits {
+    /// Most elements of a listpack.
+    pub(crate) entries: usize,
+    /// Longest string element of a listpack, in bytes.
+    pub(crate) value: usize,
+}
+
+impl Default for ListpackLimits {
+    fn default() -> ListpackLimits {
+        ListpackLimits {
+            entries: 128,
+            value: 64,
+        }
+    }
+}
+
+/// List stored in the `Db`.
+///
+/// Like Redis, small lists are stored as a listpack: their elements are encoded one after the
+/// other in a single buffer, instead of every element taking a `Data` and a buffer of its own.
+/// Pushing and popping a listpack copies it, and elements are found by walking it from the
+/// start, so a list is upgraded to a `VecDeque` once it grows beyond the `ListpackLimits`. A
+/// list is never converted back to a listpack.
+///
+/// Cloning a list is cheap: a listpack is small, and the elements of a `VecDeque` are shared
+/// until one of the clones is written.
+#[derive(Clone, Debug, Default)]
+pub struct List {
+    encoding: Encoding,
+}
+
+#[derive(Clone, Debug)]
+enum Encoding {
+    /// Encoded elements and their number.
+    Listpack { buf: Vec<u8>, len: usize },
+    /// Elements and the sum of their sizes. The elements are shared by the clones of the list,
+    /// such as in a `Snapshot`, and copied when a shared list is written.
+    Deque {
+        list: Arc<VecDeque<Data>>,
+        size: usize,
+    },
+}
+
+/// Iterator over the elements of a `List`, decoded or cloned.
+pub struct Iter<'a> {
+    inner: IterInner<'a>,
+}
+
+enum IterInner<'a> {
+    Listpack { buf: &'a [u8], remaining: usize },
+    Deque(vec_deque::Iter<'a, Data>),
+}
+
+/// Tags of the elements of a listpack, written before their encoding.
+const TAG_BYTES: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_INTEGER: u8 = 2;
+const TAG_DOUBLE: u8 = 3;
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Listpack {
+            buf: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl List {
+    /// Create an empty list.
+    pub fn new() -> List {
+        List::default()
+    }
+
+    /// Create a list of `elements`, stored as a listpack if they are within `limits`.
+    pub(crate) fn from_elements(elements: VecDeque<Data>, limits: ListpackLimits) -> List {
+        if elements.len() <= limits.entries && elements.iter().all(|data| packable(data, limits)) {
+            let mut buf = Vec::new();
+            elements.iter().for_each(|data| encode(data, &mut buf));
+            List {
+                encoding: Encoding::Listpack {
+                    buf,
+                    len: elements.len(),
+                },
+            }
+        } else {
+            let size = elements.iter().map(Data::size).sum();
+            List {
+                encoding: Encoding::Deque {
+                    list: Arc::new(elements),
+                    size,
+                },
+            }
+        }
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack { len, .. } => *len,
+            Encoding::Deque { list, .. } => list.len(),
+        }
+    }
+
+    /// Returns `true` if the list has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the elements, from the first.
+    pub fn iter(&self) -> Iter<'_> {
+        let inner = match &self.encoding {
+            Encoding::Listpack { buf, len } => IterInner::Listpack {
+                buf,
+                remaining: *len,
+            },
+            Encoding::Deque { list, .. } => IterInner::Deque(list.iter()),
+        };
+        Iter { inner }
+    }
+
+    /// Append `data`, upgrading the list if it no longer fits the `limits` of a listpack.
+    pub(crate) fn push_back(&mut self, data: Data, limits: ListpackLimits) {
+        self.fit(&data, limits);
+        match &mut self.encoding {
+            Encoding::Listpack { buf, len } => {
+                encode(&data, buf);
+                *len += 1;
+            }
+            Encoding::Deque { list, size } => {
+                *size += data.size();
+                Arc::make_mut(list).push_back(data);
+            }
+        }
+    }
+
+    /// Prepend `data`, upgrading the list if it no longer fits the `limits` of a listpack.
+    pub(crate) fn push_front(&mut self, data: Data, limits: ListpackLimits) {
+        self.fit(&data, limits);
+        match &mut self.encoding {
+            Encoding::Listpack { buf, len } => {
+                let mut encoded = Vec::new();
+                encode(&data, &mut encoded);
+                buf.splice(0..0, encoded);
+                *len += 1;
+            }
+            Encoding::Deque { list, size } => {
+                *size += data.size();
+                Arc::make_mut(list).push_front(data);
+            }
+        }
+    }
+
+    /// Remove the first element.
+    pub(crate) fn pop_front(&mut self) -> Option<Data> {
+        match &mut self.encoding {
+            Encoding::Listpack { buf, len } => {
+                if *len == 0 {
+                    return None;
+                }
+                let (data, end) = decode(buf, 0);
+                buf.drain(..end);
+                *len -= 1;
+                Some(data)
+            }
+            Encoding::Deque { list, size } => {
+                let data = Arc::make_mut(list).pop_front()?;
+                *size -= data.size();
+                Some(data)
+            }
+        }
+    }
+
+    /// Remove the last element.
+    pub(crate) fn pop_back(&mut self) -> Option<Data> {
+        match &mut self.encoding {
+            Encoding::Listpack { buf, len } => {
+                if *len == 0 {
+                    return None;
+                }
+                // Elements are only walked forward, the last one starts where the others end.
+                let mut start = 0;
+                for _ in 1..*len {
+                    start = skip(buf, start);
+                }
+                let (data, _) = decode(buf, start);
+                buf.truncate(start);
+                *len -= 1;
+                Some(data)
+            }
+            Encoding::Deque { list, size } => {
+                let data = Arc::make_mut(list).pop_back()?;
+                *size -= data.size();
+                Some(data)
+            }
+        }
+    }
+
+    /// Approximate memory used by the elements, in bytes.
+    pub(crate) fn size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack { buf, .. } => buf.len(),
+            Encoding::Deque { size, .. } => *size,
+        }
+    }
+
+    /// Number of allocations freed when the list is dropped, approximately.
+    pub(crate) fn free_effort(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack { .. } => 1,
+            Encoding::Deque { list, .. } => list.len(),
+        }
+    }
+
+    /// Name of the encoding of the list, as reported by `OBJECT ENCODING`.
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.encoding {
+            Encoding::Listpack { .. } => "listpack",
+            Encoding::Deque { .. } => "vecdeque",
+        }
+    }
+
+    /// Upgrade a listpack to a `VecDeque` if adding `data` exceeds its `limits`.
+    fn fit(&mut self, data: &Data, limits: ListpackLimits) {
+        if let Encoding::Listpack { len, .. } = &self.encoding
+            && (*len >= limits.entries || !packable(data, limits))
+        {
+            let list: VecDeque<Data> = self.iter().collect();
+            let size = list.iter().map(Data::size).sum();
+            self.encoding = Encoding::Deque {
+                list: Arc::new(list),
+                size,
+            };
+        }
+    }
+}
+
+impl PartialEq for List {
+    /// Lists are equal if they have the same elements, whatever their encoding.
+    fn eq(&self, other: &List) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl From<List> for VecDeque<Data> {
+    fn from(list: List) -> VecDeque<Data> {
+        match list.encoding {
+            Encoding::Listpack { .. } => list.iter().collect(),
+            Encoding::Deque { list, .. } => Arc::unwrap_or_clone(list),
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Data> {
+        match &mut self.inner {
+            IterInner::Listpack { buf, remaining } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                let (data, end) = decode(buf, 0);
+                *buf = &buf[end..];
+                *remaining -= 1;
+                Some(data)
+            }
+            IterInner::Deque(iter) => iter.next().cloned(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match &self.inner {
+            IterInner::Listpack { remaining, .. } => *remaining,
+            IterInner::Deque(iter) => iter.len(),
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Returns `true` if `data` can be an element of a listpack within `limits`.
+fn packable(data: &Data, limits: ListpackLimits) -> bool {
+    match data {
+        Data::Bytes(bytes) | Data::String(bytes) => bytes.len() <= limits.value,
+        Data::Integer(_) | Data::Double(_) => true,
+        Data::Array(_) => false,
+    }
+}
+
+/// Append the encoding of `data` to `buf`: its tag, then the length and content of strings,
+/// integers as zigzag varints, and doubles as 8 bytes little endian.
+fn encode(data: &Data, buf: &mut Vec<u8>) {
+    match data {
+        Data::Bytes(bytes) | Data::String(bytes) => {
+            let tag = if let Data::Bytes(_) = data {
+                TAG_BYTES
+            } else {
+                TAG_STRING
+            };
+            buf.push(tag);
+            write_varint(buf, bytes.len() as u64);
+            buf.extend_from_slice(bytes);
+        }
+        Data::Integer(int) => {
+            buf.push(TAG_INTEGER);
+            write_varint(buf, ((int << 1) ^ (int >> 63)) as u64);
+        }
+        Data::Double(double) => {
+            buf.push(TAG_DOUBLE);
+            buf.extend_from_slice(&double.to_le_bytes());
+        }
+        Data::Array(_) => unreachable!("element never packed"),
+    }
+}
+
+/// Decode the element at `pos` of `buf`, returns it and the position of the next element.
+fn decode(buf: &[u8], pos: usize) -> (Data, usize) {
+    let tag = buf[pos];
+    let pos = pos + 1;
+    match tag {
+        TAG_BYTES | TAG_STRING => {
+            let (len, pos) = read_varint(buf, pos);
+            let end = pos + len as usize;
+            let bytes = Bytes::copy_from_slice(&buf[pos..end]);
+            let data = if tag == TAG_BYTES {
+                Data::Bytes(bytes)
+            } else {
+                Data::String(bytes)
+            };
+            (data, end)
+        }
+        TAG_INTEGER => {
+            let (zigzag, pos) = read_varint(buf, pos);
+            (
+                Data::Integer((zigzag >> 1) as i64 ^ -((zigzag & 1) as i64)),
+                pos,
+            )
+        }
+        TAG_DOUBLE => {
+            let end = pos + mem::size_of::<f64>();
+            let double = f64::from_le_bytes(buf[pos..end].try_into().unwrap());
+            (Data::Double(double), end)
+        }
+        _ => unreachable!("unknown listpack tag {tag}"),
+    }
+}
+
+/// Position of the element after the one at `pos` of `buf`.
+fn skip(buf: &[u8], pos: usize) -> usize {
+    match buf[pos] {
+        TAG_BYTES | TAG_STRING => {
+            let (len, pos) = read_varint(buf, pos + 1);
+            pos + len as usize
+        }
+        TAG_INTEGER => read_varint(buf, pos + 1).1,
+        _ => pos + 1 + mem::size_of::<f64>(),
+    }
+}
+
+/// Append `value` to `buf` 7 bits at a time, the high bit set on all bytes but the last.
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Read the varint at `pos` of `buf`, returns it and the position after it.
+fn read_varint(buf: &[u8], mut pos: usize) -> (u64, usize) {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buf[pos];
+        pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return (value, pos);
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,95 @@
+use bytes::Bytes;
+
+/// Buckets of a new index, a power of two.
+const INITIAL_BUCKETS: usize = 16;
+
+/// Average number of keys per bucket over which the index doubles its buckets.
+const MAX_LOAD: usize = 2;
+
+/// Keys of a shard bucketed by the low bits of their hash, so `Db::scan` visits a bucket
+/// without walking the shard.
+///
+/// As in Redis the cursor is a bucket incremented from its highest bit. The index only grows,
+/// doubling its buckets: keys of bucket `n` are split between `n` and `n + len`, the bucket
+/// visited right after `n` by the cursor, so buckets already visited stay visited.
+pub(super) struct ScanIndex {
+    /// Keys of every bucket, with their hash.
+    buckets: Vec<Vec<(u64, Bytes)>>,
+
+    /// Number of keys.
+    len: usize,
+}
+
+impl ScanIndex {
+    pub(super) fn new() -> ScanIndex {
+        ScanIndex {
+            buckets: (0..INITIAL_BUCKETS).map(|_| Vec::new()).collect(),
+            len: 0,
+        }
+    }
+
+    /// Add `key` of hash `hash`, which isn't indexed yet.
+    pub(super) fn insert(&mut self, hash: u64, key: Bytes) {
+        if self.len >= self.buckets.len() * MAX_LOAD {
+            self.grow();
+        }
+
+        let bucket = self.bucket(hash);
+        self.buckets[bucket].push((hash, key));
+        self.len += 1;
+    }
+
+    /// Remove `key` of hash `hash`, if indexed.
+    pub(super) fn remove(&mut self, hash: u64, key: &[u8]) {
+        let bucket = self.bucket(hash);
+        let keys = &mut self.buckets[bucket];
+        if let Some(index) = keys.iter().position(|(_, indexed)| indexed == key) {
+            keys.swap_remove(index);
+            self.len -= 1;
+        }
+    }
+
+    /// Remove every key, the buckets are kept.
+    pub(super) fn clear(&mut self) {
+        for keys in &mut self.buckets {
+            keys.clear();
+        }
+        self.len = 0;
+    }
+
+    /// Push the keys of the bucket of `cursor` to `keys`, and return the cursor of the next
+    /// bucket, 0 once every bucket is visited.
+    pub(super) fn visit(&self, cursor: u64, keys: &mut Vec<Bytes>) -> u64 {
+        let mask = self.buckets.len() as u64 - 1;
+        keys.extend(
+            self.buckets[(cursor & mask) as usize]
+                .iter()
+                .map(|(_, key)| key.clone()),
+        );
+
+        // Increment the bits of the bucket in reverse, the bits above are set so the
+        // increment carries over them.
+        (cursor | !mask)
+            .reverse_bits()
+            .wrapping_add(1)
+            .reverse_bits()
+    }
+
+    /// Double the buckets, splitting the keys of every bucket between itself and its new
+    /// sibling.
+    fn grow(&mut self) {
+        let len = self.buckets.len();
+        self.buckets.resize_with(len * 2, Vec::new);
+        for bucket in 0..len {
+            let keys = std::mem::take(&mut self.buckets[bucket]);
+            for (hash, key) in keys {
+                let split = self.bucket(hash);
+                self.buckets[split].push((hash, key));
+            }
+        }
+    }
+
+    fn bucket(&self, hash: u64) -> usize {
+        hash as usize & (self.buckets.len() - 1)
+    }
+}
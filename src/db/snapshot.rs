@@ -0,0 +1,46 @@
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::time::Instant;
+
+use super::{Data, Value};
+
+/// Read-only view of the keys of a `Db`, as returned by `Db::snapshot`.
+///
+/// The view is frozen, writes to the `Db` after it was taken are not seen, and cloning it is
+/// cheap: clones share the same keys. Values are shared with the `Db` until they are written,
+/// strings share their buffers and large lists their elements.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    entries: Arc<[(Bytes, Value, Option<Instant>)]>,
+}
+
+impl Snapshot {
+    /// Create a snapshot of `entries`, keys with their stored value and expiration.
+    pub(crate) fn new(entries: Vec<(Bytes, Value, Option<Instant>)>) -> Snapshot {
+        Snapshot {
+            entries: entries.into(),
+        }
+    }
+
+    /// Number of keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over every key and its value, as returned by `Db::iter`.
+    pub fn iter(&self) -> impl Iterator<Item = (Bytes, Data)> + '_ {
+        self.entries
+            .iter()
+            .map(|(key, data, _)| (key.clone(), data.exported()))
+    }
+
+    /// Keys with their value as stored in the `Db`, and the instant they expire at.
+    pub(crate) fn entries(&self) -> &[(Bytes, Value, Option<Instant>)] {
+        &self.entries
+    }
+}
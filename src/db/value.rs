@@ -0,0 +1,110 @@
+use bytes::Bytes;
+use std::mem;
+
+use super::{Data, Inline, Kind, List, ListpackLimits};
+use crate::errors::WalrusError;
+
+/// Value as stored in an entry of the `Db`, the representation of a `Data` kept private.
+///
+/// Lists are stored as a `List`, compact while it is small, whether they are set as an `Array`
+/// or pushed to. Short `Bytes` are stored `Inline`. Both are returned as the `Data` they were
+/// set as, see `exported`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Bytes(Bytes),
+    /// Short string stored inline, see `Inline`.
+    Inline(Inline),
+    String(Bytes),
+    Integer(i64),
+    Double(f64),
+    List(List),
+}
+
+impl Value {
+    /// Value storing `data`, an `Array` is stored as a `List` within `limits` and short
+    /// `Bytes` inline.
+    pub(crate) fn new(data: Data, limits: ListpackLimits) -> Value {
+        match data {
+            Data::Bytes(bytes) => match Inline::new(&bytes) {
+                Some(inline) => Value::Inline(inline),
+                None => Value::Bytes(bytes),
+            },
+            Data::String(bytes) => Value::String(bytes),
+            Data::Integer(int) => Value::Integer(int),
+            Data::Double(double) => Value::Double(double),
+            Data::Array(list) => Value::List(List::from_elements(list, limits)),
+        }
+    }
+
+    /// Copy of the value as returned by the `Db`, lists are returned as an `Array` and inline
+    /// strings as `Bytes`.
+    pub(crate) fn exported(&self) -> Data {
+        match self {
+            Value::Bytes(bytes) => Data::Bytes(bytes.clone()),
+            Value::Inline(inline) => Data::Bytes(Bytes::copy_from_slice(inline.as_bytes())),
+            Value::String(bytes) => Data::String(bytes.clone()),
+            Value::Integer(int) => Data::Integer(*int),
+            Value::Double(double) => Data::Double(*double),
+            Value::List(list) => Data::Array(list.iter().collect()),
+        }
+    }
+
+    /// Approximate memory used by the value, in bytes. Lists count the size of their elements.
+    pub(crate) fn size(&self) -> usize {
+        let heap = match self {
+            Value::Bytes(bytes) | Value::String(bytes) => bytes.len(),
+            Value::List(list) => list.size(),
+            Value::Inline(_) | Value::Integer(_) | Value::Double(_) => 0,
+        };
+
+        mem::size_of::<Value>() + heap
+    }
+
+    /// Kind of the value.
+    pub(crate) fn kind(&self) -> Kind {
+        match self {
+            Value::List(_) => Kind::List,
+            Value::Bytes(_)
+            | Value::Inline(_)
+            | Value::String(_)
+            | Value::Integer(_)
+            | Value::Double(_) => Kind::String,
+        }
+    }
+
+    /// Number of allocations freed when the value is dropped, approximately.
+    pub(crate) fn free_effort(&self) -> usize {
+        match self {
+            Value::List(list) => list.free_effort(),
+            _ => 1,
+        }
+    }
+
+    /// Name of the encoding of the value, as reported by `OBJECT ENCODING`.
+    pub(crate) fn encoding(&self) -> &'static str {
+        match self {
+            Value::Bytes(_) => "raw",
+            Value::Inline(_) => "embstr",
+            Value::String(_) => "simple",
+            Value::Integer(_) => "int",
+            Value::Double(_) => "double",
+            Value::List(list) => list.encoding(),
+        }
+    }
+
+    /// Elements of the list, `WrongType` error if the value isn't a list.
+    pub(crate) fn as_list(&self) -> Result<&List, WalrusError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WalrusError::WrongType),
+        }
+    }
+
+    /// Mutable elements of the list, `WrongType` error if the value isn't a list.
+    pub(crate) fn as_list_mut(&mut self) -> Result<&mut List, WalrusError> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WalrusError::WrongType),
+        }
+    }
+}
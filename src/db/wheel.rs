@@ -0,0 +1,181 @@
+use bytes::Bytes;
+use rand::RngExt;
+use tokio::time::{Duration, Instant};
+
+/// Number of slots of every level, a power of two.
+const SLOTS: usize = 64;
+
+/// Bits of a tick selecting the slot of a level.
+const SLOT_BITS: u32 = SLOTS.trailing_zeros();
+
+/// Number of levels, timers up to `SLOTS.pow(LEVELS)` milliseconds (about two years) away
+/// are placed directly. Later timers wait in the last level and are placed again when it
+/// comes around.
+const LEVELS: usize = 6;
+
+/// Ticks covered by all levels.
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+
+/// Hierarchical timer wheel tracking the expirations of keys, with millisecond ticks.
+///
+/// Level `n` has slots spanning `SLOTS.pow(n)` ticks, a timer is placed in the lowest level
+/// whose slots don't hold timers of its current span of ticks. When the wheel reaches a slot
+/// of a higher level its timers are placed again in the lower levels, until they reach the
+/// first level and are due. Adding a timer is O(1), and timers are never removed: expirations
+/// replaced or of removed keys are left as tombstones, skipped when they are due by comparing
+/// them with the expiration of the entry.
+pub(super) struct Wheel {
+    /// Instant of the first tick.
+    start: Instant,
+
+    /// Ticks the wheel has advanced to, timers due at or before it have been returned.
+    elapsed: u64,
+
+    levels: [Level; LEVELS],
+}
+
+/// Slots of a level of the wheel.
+struct Level {
+    /// Bit `n` is set if slot `n` holds timers.
+    occupied: u64,
+
+    /// Timers of every slot, with the key expiring.
+    slots: [Vec<(Instant, Bytes)>; SLOTS],
+}
+
+impl Wheel {
+    pub(super) fn new() -> Wheel {
+        Wheel {
+            start: Instant::now(),
+            elapsed: 0,
+            levels: std::array::from_fn(|_| Level {
+                occupied: 0,
+                slots: std::array::from_fn(|_| Vec::new()),
+            }),
+        }
+    }
+
+    /// Add a timer expiring `key` at `when`.
+    pub(super) fn insert(&mut self, when: Instant, key: Bytes) {
+        // The tick is always ahead of the wheel.
+        let tick = self
+            .tick(when)
+            .clamp(self.elapsed + 1, self.elapsed + MAX_TICKS - 1);
+
+        // The highest bit differing from the current tick picks the level.
+        let masked = (self.elapsed ^ tick) | (SLOTS as u64 - 1);
+        let level = ((63 - masked.leading_zeros()) / SLOT_BITS).min(LEVELS as u32 - 1);
+        let slot = (tick >> (level * SLOT_BITS)) as usize & (SLOTS - 1);
+
+        let level = &mut self.levels[level as usize];
+        level.occupied |= 1 << slot;
+        level.slots[slot].push((when, key));
+    }
+
+    /// Advance the wheel to `now`, pushing the timers due to `expired`. Stops once `limit`
+    /// timers are pushed, the wheel is then left behind `now` and the timers still due are
+    /// returned by the next poll.
+    pub(super) fn poll(&mut self, now: Instant, limit: usize, expired: &mut Vec<(Instant, Bytes)>) {
+        // Slots of the tick `now` falls in are also visited, timers of the tick not due yet
+        // are placed in the next one.
+        let now_tick = self.tick(now);
+
+        while let Some((level, slot, deadline)) = self.next_slot()
+            && deadline <= now_tick
+        {
+            self.elapsed = deadline;
+
+            let level = &mut self.levels[level];
+            level.occupied &= !(1 << slot);
+            let timers = std::mem::take(&mut level.slots[slot]);
+
+            for (when, key) in timers {
+                if when <= now && expired.len() < limit {
+                    expired.push((when, key));
+                } else {
+                    self.insert(when, key);
+                }
+            }
+
+            if expired.len() >= limit {
+                return;
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now_tick);
+    }
+
+    /// Instant at which the wheel has to be polled next, if it holds any timer. Timers of
+    /// higher levels may not be due then, they are only placed in lower levels.
+    pub(super) fn next_expiration(&self) -> Option<Instant> {
+        self.next_slot()
+            .map(|(_, _, deadline)| self.start + Duration::from_millis(deadline))
+    }
+
+    /// Push `count` timers picked at random to `sampled`, a timer may be picked twice. Timers
+    /// are picked from random slots holding timers, so picking is O(count) however many timers
+    /// the wheel holds. Tombstones are picked like the other timers.
+    pub(super) fn sample(&self, count: usize, sampled: &mut Vec<(Instant, Bytes)>) {
+        let slots: Vec<&Vec<(Instant, Bytes)>> = self
+            .levels
+            .iter()
+            .flat_map(|level| &level.slots)
+            .filter(|slot| !slot.is_empty())
+            .collect();
+        if slots.is_empty() {
+            return;
+        }
+
+        let mut rng = rand::rng();
+        for _ in 0..count {
+            let slot = slots[rng.random_range(0..slots.len())];
+            sampled.push(slot[rng.random_range(0..slot.len())].clone());
+        }
+    }
+
+    /// Remove every timer.
+    pub(super) fn clear(&mut self) {
+        for level in &mut self.levels {
+            for slot in &mut level.slots {
+                slot.clear();
+            }
+            level.occupied = 0;
+        }
+    }
+
+    /// Tick of `instant`, rounded up.
+    fn tick(&self, instant: Instant) -> u64 {
+        instant
+            .saturating_duration_since(self.start)
+            .as_nanos()
+            .div_ceil(1_000_000)
+            .min(u64::MAX as u128) as u64
+    }
+
+    /// Level and slot reached next by the wheel, with the tick at which it is reached.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        self.levels.iter().enumerate().find_map(|(n, level)| {
+            if level.occupied == 0 {
+                return None;
+            }
+
+            let slot_ticks = 1u64 << (n as u32 * SLOT_BITS);
+            let level_ticks = slot_ticks << SLOT_BITS;
+            let current = (self.elapsed / slot_ticks) as usize & (SLOTS - 1);
+
+            // First occupied slot starting from the current one, wrapping around.
+            let slot = (level.occupied.rotate_right(current as u32).trailing_zeros() as usize
+                + current)
+                & (SLOTS - 1);
+
+            // Every timer is ahead of the wheel, a slot starting at or before the current tick
+            // belongs to the next round of the level.
+            let mut deadline = (self.elapsed & !(level_ticks - 1)) + slot as u64 * slot_ticks;
+            if deadline <= self.elapsed {
+                deadline += level_ticks;
+            }
+
+            Some((n, slot, deadline))
+        })
+    }
+}
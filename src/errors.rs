@@ -67,9 +67,9 @@ impl From<String> for WalrusError {
     }
 }
 
-impl Into<String> for WalrusError {
-    fn into(self) -> String {
-        match self {
+impl From<WalrusError> for String {
+    fn from(val: WalrusError) -> Self {
+        match val {
             WalrusError::WrongType => WRONGTYPE_ERR.into(),
             WalrusError::EndOfStream => END_OF_STREAM_ERR.into(),
             WalrusError::Internal(msg) | WalrusError::SyntaxError(msg) => msg,
@@ -1,12 +1,13 @@
 //! Provides a type represting a RESP frame as well as utilities for
 //! parsing frames from a byte array.
 
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use core::fmt;
 use std::collections::VecDeque;
 use std::string::FromUtf8Error;
 use std::{io::Cursor, num::TryFromIntError};
 
+use crate::connection::Protocol;
 use crate::db::{Data, optimize_storage};
 use crate::errors::WalrusError;
 use crate::parse;
@@ -15,7 +16,8 @@ use crate::parse;
 ///
 /// Size of each frame instance:
 ///
-/// Integer(u64): Requires 8 bytes.
+/// Integer(i64): Requires 8 bytes.
+/// Double(f64): Requires 8 bytes.
 /// Simple(String): Requires 24 bytes (pointer + len + cap).
 /// Bulk(Bytes): Bytes is usually 32 bytes.
 /// Array(Vec<Frame>): A Vec Requires 24 bytes (pointer + len + cap).
@@ -23,15 +25,42 @@ use crate::parse;
 /// The largest variant is Bulk(Bytes) at ~32 bytes. It adds 1 byte for the enum tag. padding to align memory correctly.
 ///
 /// So, every single Frame instance will take up roughly 40 bytes in memory (32 + 1 + padding).
+///
+/// `Map`, `Set`, `Boolean`, `BigNumber`, `Verbatim`, `Push` and `Attribute` are RESP3 types.
+/// When written to a RESP2 connection they are downgraded to the closest RESP2 type.
+///
+/// With the `serde` feature, frames implement `Serialize` and `Deserialize`, variants as
+/// externally tagged enums and `Bytes` as byte strings.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Frame {
     Simple(Bytes),
     Error(String),
     Integer(i64),
     Double(f64),
     Bulk(Bytes),
+    /// `$-1` and `*-1` in RESP2, `_` in RESP3.
     Null,
     Array(Vec<Frame>),
+    /// Ordered key value pairs.
+    Map(Vec<(Frame, Frame)>),
+    /// Unordered collection of unique frames.
+    Set(Vec<Frame>),
+    Boolean(bool),
+    /// Integer outside the range of i64, kept as its decimal representation.
+    BigNumber(Bytes),
+    /// Text along with its three character format, e.g. `txt` or `mkd`.
+    Verbatim {
+        format: Bytes,
+        text: Bytes,
+    },
+    /// Out of band data sent by the server, not a reply to any request.
+    Push(Vec<Frame>),
+    /// Reply `data` along with auxiliary `attributes` describing it.
+    Attribute {
+        attributes: Vec<(Frame, Frame)>,
+        data: Box<Frame>,
+    },
 }
 
 /// Error::Incomplete; Not enough data is available to parse a message
@@ -42,45 +71,59 @@ pub enum Error {
     Other(WalrusError),
 }
 
-impl Frame {
-    /// Push Frame into an array frame.
-    /// self needs to be an array frame.
-    /// Takes Simple, Bulk, Integer and Double frames.
-    pub(crate) fn push(&mut self, frame: Frame) {
-        match frame {
-            Frame::Simple(string) => self.push_string(string),
-            Frame::Bulk(bytes) => self.push_bulk(bytes),
-            Frame::Integer(val) => self.push_int(val),
-            Frame::Double(val) => self.push_double(val),
-            // Nested array's are not supported.
-            _ => unreachable!(),
+/// Limits on the frames accepted by `Frame::check_with`, so a peer can't make the reader
+/// buffer or allocate without bound by announcing huge lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest bulk or verbatim string, in bytes.
+    pub max_bulk_len: usize,
+    /// Most elements of an aggregate, pairs for maps and attributes.
+    pub max_aggregate_len: usize,
+    /// Largest frame including its nested frames, in bytes.
+    pub max_frame_size: usize,
+    /// Most levels of aggregates nested in one another, an array of arrays is 2 levels deep.
+    pub max_depth: usize,
+}
+
+impl Limits {
+    /// No limits, frames are only bounded by memory.
+    pub const UNLIMITED: Limits = Limits {
+        max_bulk_len: usize::MAX,
+        max_aggregate_len: usize::MAX,
+        max_frame_size: usize::MAX,
+        max_depth: usize::MAX,
+    };
+}
+
+impl Default for Limits {
+    /// The defaults of Redis: 512MB bulk strings, `i32::MAX` elements and 1GB requests.
+    /// Requests are flat arrays, replies are nested a few levels deep at most, 128 levels leave
+    /// plenty of room.
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_aggregate_len: i32::MAX as usize,
+            max_frame_size: 1024 * 1024 * 1024,
+            max_depth: 128,
         }
     }
+}
 
-    /// Push Frame::Simple(String) into an array frame.
+impl Frame {
+    /// Push Frame into an array frame, including nested arrays.
     /// Will `panic` if called by non array frame.
-    pub(crate) fn push_string(&mut self, string: Bytes) {
+    pub(crate) fn push(&mut self, frame: Frame) {
         match self {
-            Frame::Array(frame) => frame.push(Frame::Simple(string)),
+            Frame::Array(frames) => frames.push(frame),
             _ => panic!("not an array frame"),
         }
     }
 
-    /// Push `Data` into an array frame.
-    /// uses recursion to push nested arrays of `Data`.
-    /// Will `panic` if `data_vec` contains nested arrays.
+    /// Push `Data` into an array frame, nested arrays of `Data` are pushed as nested array
+    /// frames.
     pub(crate) fn push_data(&mut self, data_vec: VecDeque<Data>) {
         for data in data_vec {
-            match data {
-                Data::String(data) => self.push_string(data),
-                Data::Bytes(data) => self.push_bulk(data),
-                Data::Integer(data) => self.push_int(data),
-                Data::Double(data) => self.push_double(data),
-                // Nested arrays are not supported.
-                Data::Array(_) => {
-                    panic!("Nested arrays are not supported.");
-                }
-            }
+            self.push(Frame::from(data));
         }
     }
 
@@ -100,7 +143,7 @@ impl Frame {
         }
     }
 
-    /// Push Frame::Integer(u64) into an array frame.
+    /// Push Frame::Integer(i64) into an array frame.
     /// Will `panic` if called by non array frame.
     pub(crate) fn push_int(&mut self, val: i64) {
         match self {
@@ -112,161 +155,622 @@ impl Frame {
     /// Check if a complete frame exists in the buffer without consuming it.
     /// If a frame can be parsed then the length of the complete frame is returned in bytes.
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<usize, Error> {
+        Frame::check_with(src, &Limits::UNLIMITED)
+    }
+
+    /// Check if a complete frame within `limits` exists in the buffer without consuming it.
+    ///
+    /// Lengths are checked as soon as their header is read, a frame announcing a bulk string
+    /// or an aggregate beyond the limits is refused before it is buffered, and so is a frame
+    /// whose buffered part is already larger than `max_frame_size`.
+    pub fn check_with(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<usize, Error> {
         let start = src.position() as usize;
-        match get_u8(src)? {
-            b'+' | b'-' => {
-                get_line(src)?;
-                Ok(src.position() as usize - start)
+        match check_frame(src, limits, start) {
+            Err(Error::Incomplete) if src.get_ref().len() - start > limits.max_frame_size => {
+                Err(frame_too_large())
             }
-            b':' => {
-                get_decimal(src)?;
-                Ok(src.position() as usize - start)
-            }
-            b',' => {
-                get_double(src)?;
-                Ok(src.position() as usize - start)
-            }
-            b'$' => {
-                // $-1\r\n is Null
-                if b'-' == peek_u8(src)? {
-                    let line = get_line(src)?;
-
-                    if line != b"-1" {
-                        return Err("protocol error; invalid frame format".into());
-                    }
-
-                    Ok(src.position() as usize - start)
-                } else {
-                    // Read the bulk string
-                    // `try_into` fails if the number doesn't fit in usize, for example on 32 bit
-                    // computer u64 may not fit in usize (32 bit)
-                    let len: usize = get_decimal(src)?.try_into()?;
-                    let len_inclusive_crlf = len + 2;
+            result => result,
+        }
+    }
 
-                    if src.remaining() < len_inclusive_crlf {
-                        return Err(Error::Incomplete);
-                    }
+    /// Parse message from `src`.
+    /// The frame contains just enough data to parse a frame, doesn't include the \r\n at the end of
+    /// the frame.
+    ///
+    /// Aggregates are built with a stack of the aggregates being parsed rather than
+    /// recursively, so a deeply nested frame can't overflow the call stack.
+    pub fn parse(src: &mut Bytes) -> Result<Frame, Error> {
+        let mut pending: Vec<Partial> = Vec::new();
+
+        loop {
+            let mut frame = match parse_header(src)? {
+                Parsed::Frame(frame) => frame,
+                Parsed::Aggregate(partial) if partial.len == 0 => partial.build(),
+                Parsed::Aggregate(partial) => {
+                    pending.push(partial);
+                    continue;
+                }
+            };
 
-                    // skip `len_inclusive_crlf` number of bytes
-                    skip(src, len_inclusive_crlf)?;
+            // The frame is complete, and so are the aggregates it completes.
+            loop {
+                let Some(parent) = pending.last_mut() else {
+                    return Ok(frame);
+                };
 
-                    Ok(src.position() as usize - start)
+                parent.items.push(frame);
+                if parent.items.len() < parent.len {
+                    break;
                 }
+                frame = pending.pop().unwrap().build();
             }
-            b'*' => {
-                if b'-' == peek_u8(src)? {
-                    let line = get_line(src)?;
+        }
+    }
 
-                    if line != b"-1" {
-                        return Err("protocol error; invalid frame format".into());
-                    }
+    /// Encode the frame in the RESP3 format, RESP3 types are kept as they are.
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        self.write_to(&mut buf, Protocol::Resp3);
+        buf.freeze()
+    }
 
-                    Ok(src.position() as usize - start)
-                } else {
-                    let len: usize = get_decimal(src)?.try_into()?;
+    /// Append the encoding of the frame to `buf`, as written to a `Connection` using
+    /// `protocol`. RESP3 types are downgraded to the closest RESP2 type with `Resp2`.
+    ///
+    /// Aggregates may be nested to any depth. They are walked with a stack of the frames left
+    /// to write rather than recursively, so deeply nested frames can't overflow the call stack.
+    pub fn write_to(&self, buf: &mut BytesMut, protocol: Protocol) {
+        self.write_chunked(buf, None, protocol);
+    }
 
-                    for _ in 0..len {
-                        Frame::check(src)?;
+    /// Like `write_to`, but bulk strings of at least `SHARED_BULK_MIN` bytes are not copied
+    /// into `buf`. The bytes written so far are moved to `chunks` followed by the bulk itself,
+    /// see `put_shared_bulk`.
+    pub(crate) fn write_chunked(
+        &self,
+        buf: &mut BytesMut,
+        mut chunks: Option<&mut VecDeque<Bytes>>,
+        protocol: Protocol,
+    ) {
+        let mut pending = vec![self];
+
+        while let Some(frame) = pending.pop() {
+            // Items are pushed in reverse, the first one is written next.
+            match frame {
+                Frame::Simple(message) => {
+                    buf.put_u8(b'+');
+                    buf.put_slice(message);
+                    buf.put_slice(b"\r\n");
+                }
+                Frame::Error(err) => {
+                    buf.put_u8(b'-');
+                    buf.put_slice(err.as_bytes());
+                    buf.put_slice(b"\r\n");
+                }
+                Frame::Integer(val) => {
+                    buf.put_u8(b':');
+                    put_decimal(buf, *val);
+                }
+                Frame::Double(val) => put_double(buf, *val, protocol),
+                Frame::Null => put_null(buf, protocol),
+                Frame::Bulk(message) => match chunks.as_deref_mut() {
+                    Some(chunks) if message.len() >= SHARED_BULK_MIN => {
+                        put_shared_bulk(buf, chunks, message)
+                    }
+                    _ => put_bulk(buf, message),
+                },
+                Frame::Boolean(val) => match protocol {
+                    // RESP2 has no boolean type, integer 1 or 0 is sent instead.
+                    Protocol::Resp2 => {
+                        buf.put_u8(b':');
+                        put_decimal(buf, *val as i64);
+                    }
+                    Protocol::Resp3 => buf.put_slice(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+                },
+                Frame::BigNumber(num) => match protocol {
+                    // RESP2 has no big number type, the decimal representation is sent instead.
+                    Protocol::Resp2 => put_bulk(buf, num),
+                    Protocol::Resp3 => {
+                        buf.put_u8(b'(');
+                        buf.put_slice(num);
+                        buf.put_slice(b"\r\n");
+                    }
+                },
+                Frame::Verbatim { format, text } => match protocol {
+                    // RESP2 has no verbatim string type, the text is sent as a bulk string.
+                    Protocol::Resp2 => put_bulk(buf, text),
+                    Protocol::Resp3 => {
+                        buf.put_u8(b'=');
+                        put_decimal(buf, (format.len() + 1 + text.len()) as i64);
+                        buf.put_slice(format);
+                        buf.put_u8(b':');
+                        buf.put_slice(text);
+                        buf.put_slice(b"\r\n");
+                    }
+                },
+                Frame::Array(val) => {
+                    buf.put_u8(b'*');
+                    put_decimal(buf, val.len() as i64);
+                    pending.extend(val.iter().rev());
+                }
+                Frame::Set(val) => {
+                    put_set_header(buf, val.len(), protocol);
+                    pending.extend(val.iter().rev());
+                }
+                Frame::Map(pairs) => {
+                    put_map_header(buf, pairs.len(), protocol);
+                    pending.extend(pairs.iter().rev().flat_map(|(key, value)| [value, key]));
+                }
+                Frame::Push(val) => {
+                    put_push_header(buf, val.len(), protocol);
+                    pending.extend(val.iter().rev());
+                }
+                Frame::Attribute { attributes, data } => {
+                    pending.push(data);
+
+                    // RESP2 has no attribute type, only the data is sent.
+                    if protocol == Protocol::Resp3 {
+                        buf.put_u8(b'|');
+                        put_decimal(buf, attributes.len() as i64);
+                        pending.extend(
+                            attributes
+                                .iter()
+                                .rev()
+                                .flat_map(|(key, value)| [value, key]),
+                        );
                     }
-
-                    Ok(src.position() as usize - start)
                 }
             }
-            b => {
-                return Err(format!(
-                    "protocol error; invalid frame format. Unexpected byte: {}",
-                    b
-                )
-                .into());
+        }
+    }
+
+    /// Returns an empty array
+    pub(crate) fn array() -> Frame {
+        Frame::Array(vec![])
+    }
+}
+
+/// Check the frame at the position of `src` within `limits`, `frame_start` is the position of the
+/// outermost frame. Returns the length of the frame in bytes.
+///
+/// Aggregates are walked with a stack of the number of frames left in each aggregate being
+/// checked, rather than recursively, so a deeply nested frame can't overflow the call stack.
+fn check_frame(
+    src: &mut Cursor<&[u8]>,
+    limits: &Limits,
+    frame_start: usize,
+) -> Result<usize, Error> {
+    let start = src.position() as usize;
+    let mut pending: Vec<usize> = Vec::new();
+
+    loop {
+        let nested = check_header(src, limits, frame_start)?;
+        if nested > 0 {
+            if pending.len() >= limits.max_depth {
+                return Err("protocol error; nesting too deep".into());
             }
+            pending.push(nested);
+            continue;
+        }
+
+        // The frame is complete, and so are the aggregates it completes.
+        loop {
+            let Some(left) = pending.last_mut() else {
+                return Ok(src.position() as usize - start);
+            };
+
+            *left -= 1;
+            if *left > 0 {
+                break;
+            }
+            pending.pop();
         }
     }
+}
 
-    /// Parse message from `src`.
-    /// The frame contains just enough data to parse a frame, doesn't include the \r\n at the end of
-    /// the frame.
-    pub fn parse(src: &mut Bytes) -> Result<Frame, Error> {
-        // get_u8 panics if no data is avaiable in the buffer, but its safe here as check phase
-        // would have confirmed that enough data is available for a frame here.
-        match src.get_u8() {
-            b'+' => {
-                let line = get_line_from_bytes(src)?;
+/// Check the frame at the position of `src` up to its nested frames, returns their number.
+fn check_header(
+    src: &mut Cursor<&[u8]>,
+    limits: &Limits,
+    frame_start: usize,
+) -> Result<usize, Error> {
+    match get_u8(src)? {
+        b'+' | b'-' => {
+            get_line(src)?;
+        }
+        b':' => {
+            get_decimal(src)?;
+        }
+        b',' => {
+            get_double(src)?;
+        }
+        // $-1\r\n and *-1\r\n are Null
+        b'$' | b'*' if b'-' == peek_u8(src)? => {
+            if get_line(src)? != b"-1" {
+                return Err("protocol error; invalid frame format".into());
+            }
+        }
+        b'$' => {
+            // Read the bulk string, its length is checked against the limits before it is
+            // buffered.
+            let len = get_bulk_len(src, limits, frame_start)?;
 
-                Ok(Frame::Simple(line))
+            // skip the string and its \r\n
+            skip(src, len.saturating_add(2))?;
+        }
+        b'*' | b'~' | b'>' => return get_aggregate_len(src, limits),
+        b'%' => {
+            // Each entry is a key frame followed by a value frame.
+            return Ok(get_aggregate_len(src, limits)?.saturating_mul(2));
+        }
+        b'|' => {
+            // Attributes are always followed by the frame they describe, both are treated
+            // as a single frame so the reply is never separated from its attributes.
+            return Ok(get_aggregate_len(src, limits)?
+                .saturating_mul(2)
+                .saturating_add(1));
+        }
+        b'_' => {
+            if !get_line(src)?.is_empty() {
+                return Err("protocol error; invalid frame format".into());
             }
-            b'-' => {
-                let line = get_line_from_bytes(src)?;
-                let err = String::from_utf8(line.to_vec())?;
-                Ok(Frame::Error(err))
+        }
+        b'#' => {
+            get_boolean(get_line(src)?)?;
+        }
+        b'(' => {
+            if !is_big_number(get_line(src)?) {
+                return Err("protocol error; invalid frame format".into());
             }
-            b':' => {
-                let number = get_decimal_from_bytes(src)?;
+        }
+        b'=' => {
+            let len = get_bulk_len(src, limits, frame_start)?;
 
-                Ok(Frame::Integer(number))
+            // The payload starts with a three bytes format and a `:` separator.
+            if len < 4 {
+                return Err("protocol error; invalid frame format".into());
             }
-            b',' => {
-                let number = get_double_from_bytes(src)?;
-                Ok(Frame::Double(number))
+
+            let payload = src.position() as usize;
+            skip(src, len.saturating_add(2))?;
+            if src.get_ref()[payload + 3] != b':' {
+                return Err("protocol error; invalid frame format".into());
             }
-            b'$' => {
-                // $-1\r\n is Null
-                if b'-' == peek_u8(src)? {
-                    let line = get_line_from_bytes(src)?;
-                    if *line != *b"-1" {
-                        return Err("protocol error; invalid frame format".into());
-                    }
+        }
+        b => {
+            return Err(format!(
+                "protocol error; invalid frame format. Unexpected byte: {}",
+                b
+            )
+            .into());
+        }
+    }
 
-                    Ok(Frame::Null)
-                } else {
-                    // Read the bulk string
-                    // `try_into` fails if the number doesn't fit in usize, for example on 32 bit
-                    // computer u64 may not fit in usize (32 bit)
-                    let len: usize = get_decimal_from_bytes(src)?.try_into()?;
-                    // len + 2 to include the \r\n.
-                    if src.remaining() < len + 2 {
-                        return Err(Error::Incomplete);
-                    }
+    Ok(0)
+}
+
+/// Longest inline command, in bytes, as with Redis.
+pub(crate) const INLINE_MAX_SIZE: usize = 64 * 1024;
+
+/// Returns `true` if a frame starting with `byte` is an inline command rather than a RESP
+/// frame: a line of space separated arguments, as typed in telnet.
+pub(crate) fn is_inline(byte: u8) -> bool {
+    !matches!(
+        byte,
+        b'+' | b'-'
+            | b':'
+            | b','
+            | b'$'
+            | b'*'
+            | b'~'
+            | b'%'
+            | b'_'
+            | b'#'
+            | b'('
+            | b'='
+            | b'>'
+            | b'|'
+    )
+}
 
-                    let data = src.split_to(len);
-                    // skip the \r\n
-                    src.advance(2);
+/// Returns `true` if the inline command `args` starts an HTTP request, with a `POST` request
+/// line or a `Host:` header. Browsers can be made to send such requests to any port, a web
+/// page could otherwise have commands in their body run, as with cross protocol scripting.
+pub(crate) fn is_http_request(args: &[Frame]) -> bool {
+    let Some(Frame::Bulk(name)) = args.first() else {
+        return false;
+    };
+    name.eq_ignore_ascii_case(b"post") || name.eq_ignore_ascii_case(b"host:")
+}
 
-                    Ok(Frame::Bulk(data))
+/// Check if a complete inline command is at the start of `src`, returns its length including
+/// the line ending. Lines may end with LF alone, as sent by netcat.
+pub(crate) fn check_inline(src: &[u8]) -> Result<usize, Error> {
+    match memchr::memchr(b'\n', src) {
+        Some(end) if end < INLINE_MAX_SIZE => Ok(end + 1),
+        None if src.len() < INLINE_MAX_SIZE => Err(Error::Incomplete),
+        _ => Err("protocol error; too big inline request".into()),
+    }
+}
+
+/// Parse the inline command `line`, checked with `check_inline`, into an array of bulk
+/// strings. Arguments are split on whitespace, and may be quoted like in `redis-cli`: double
+/// quoted arguments support escapes such as `\n` and `\x41`, single quoted ones only `\'`.
+/// The array is empty for a blank line.
+pub(crate) fn parse_inline(line: &[u8]) -> Result<Frame, Error> {
+    let mut args = Vec::new();
+    let mut rest = line.trim_ascii();
+
+    while !rest.is_empty() {
+        let mut arg = Vec::new();
+
+        match rest[0] {
+            quote @ (b'"' | b'\'') => {
+                let mut i = 1;
+                loop {
+                    match (rest.get(i), rest.get(i + 1)) {
+                        (None, _) => return Err(unbalanced_quotes()),
+                        (Some(b'\\'), Some(&escaped)) if quote == b'"' => {
+                            let hex = rest.get(i + 2..i + 4).and_then(|hex| {
+                                u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+                            });
+                            match (escaped, hex) {
+                                (b'x', Some(byte)) => {
+                                    arg.push(byte);
+                                    i += 4;
+                                    continue;
+                                }
+                                (b'n', _) => arg.push(b'\n'),
+                                (b'r', _) => arg.push(b'\r'),
+                                (b't', _) => arg.push(b'\t'),
+                                (b'b', _) => arg.push(0x08),
+                                (b'a', _) => arg.push(0x07),
+                                (other, _) => arg.push(other),
+                            }
+                            i += 2;
+                        }
+                        (Some(b'\\'), Some(b'\'')) => {
+                            arg.push(b'\'');
+                            i += 2;
+                        }
+                        (Some(&byte), _) if byte == quote => {
+                            // The closing quote must end the argument.
+                            if rest
+                                .get(i + 1)
+                                .is_some_and(|next| !next.is_ascii_whitespace())
+                            {
+                                return Err(unbalanced_quotes());
+                            }
+                            rest = &rest[i + 1..];
+                            break;
+                        }
+                        (Some(&byte), _) => {
+                            arg.push(byte);
+                            i += 1;
+                        }
+                    }
                 }
             }
-            b'*' => {
-                if b'-' == peek_u8(src)? {
-                    let line = get_line_from_bytes(src)?;
-                    if *line != *b"-1" {
-                        return Err("protocol error; invalid frame format".into());
-                    }
+            _ => {
+                let end = rest
+                    .iter()
+                    .position(|byte| byte.is_ascii_whitespace())
+                    .unwrap_or(rest.len());
+                arg.extend_from_slice(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
 
-                    Ok(Frame::Null)
-                } else {
-                    let len: usize = get_decimal_from_bytes(src)?.try_into()?;
-                    let mut out_vec = Vec::with_capacity(len);
+        args.push(Frame::Bulk(Bytes::from(arg)));
+        rest = rest.trim_ascii_start();
+    }
 
-                    for _ in 0..len {
-                        out_vec.push(Frame::parse(src)?);
-                    }
+    Ok(Frame::Array(args))
+}
+
+fn unbalanced_quotes() -> Error {
+    "protocol error; unbalanced quotes in request".into()
+}
 
-                    Ok(Frame::Array(out_vec))
+/// Frame of `parse_header`, complete or an aggregate whose nested frames follow.
+enum Parsed {
+    Frame(Frame),
+    Aggregate(Partial),
+}
+
+/// Aggregate being parsed, with the nested frames parsed so far.
+struct Partial {
+    /// Type byte of the aggregate.
+    tag: u8,
+    /// Number of nested frames, two per pair of maps and attributes, plus the data of
+    /// attributes.
+    len: usize,
+    items: Vec<Frame>,
+}
+
+impl Partial {
+    fn new(tag: u8, len: usize) -> Partial {
+        Partial {
+            tag,
+            len,
+            // The frame was checked, its nested frames are all buffered.
+            items: Vec::with_capacity(len),
+        }
+    }
+
+    /// Build the aggregate from its nested frames.
+    fn build(self) -> Frame {
+        let mut items = self.items.into_iter();
+        match self.tag {
+            b'*' => Frame::Array(items.collect()),
+            b'~' => Frame::Set(items.collect()),
+            b'>' => Frame::Push(items.collect()),
+            b'%' => Frame::Map(pairs(&mut items)),
+            b'|' => {
+                let data = items.next_back().unwrap();
+                Frame::Attribute {
+                    attributes: pairs(&mut items),
+                    data: Box::new(data),
                 }
             }
-            b => {
-                return Err(format!(
-                    "protocol error; invalid frame format. Unexpected byte: {}",
-                    b
-                )
-                .into());
+            tag => unreachable!("not an aggregate type {tag}"),
+        }
+    }
+}
+
+/// Pair up the frames of `items`, keys followed by their value.
+fn pairs(items: &mut impl Iterator<Item = Frame>) -> Vec<(Frame, Frame)> {
+    let mut pairs = Vec::new();
+    while let (Some(key), Some(value)) = (items.next(), items.next()) {
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// Parse the frame at the start of `src` up to its nested frames.
+fn parse_header(src: &mut Bytes) -> Result<Parsed, Error> {
+    // get_u8 panics if no data is avaiable in the buffer, but its safe here as check phase
+    // would have confirmed that enough data is available for a frame here.
+    let frame = match src.get_u8() {
+        b'+' => {
+            let line = get_line_from_bytes(src)?;
+
+            Frame::Simple(line)
+        }
+        b'-' => {
+            let line = get_line_from_bytes(src)?;
+            let err = String::from_utf8(line.to_vec())?;
+            Frame::Error(err)
+        }
+        b':' => {
+            let number = get_decimal_from_bytes(src)?;
+
+            Frame::Integer(number)
+        }
+        b',' => {
+            let number = get_double_from_bytes(src)?;
+            Frame::Double(number)
+        }
+        // $-1\r\n and *-1\r\n are Null
+        b'$' | b'*' if b'-' == peek_u8(src)? => {
+            let line = get_line_from_bytes(src)?;
+            if *line != *b"-1" {
+                return Err("protocol error; invalid frame format".into());
+            }
+
+            Frame::Null
+        }
+        b'$' => {
+            // Read the bulk string
+            // `try_into` fails if the number doesn't fit in usize, for example on 32 bit
+            // computer u64 may not fit in usize (32 bit)
+            let len: usize = get_decimal_from_bytes(src)?.try_into()?;
+            // len + 2 to include the \r\n.
+            if src.remaining() < len.saturating_add(2) {
+                return Err(Error::Incomplete);
             }
+
+            let data = src.split_to(len);
+            // skip the \r\n
+            src.advance(2);
+
+            Frame::Bulk(data)
+        }
+        tag @ (b'*' | b'~' | b'>') => {
+            let len: usize = get_decimal_from_bytes(src)?.try_into()?;
+            return Ok(Parsed::Aggregate(Partial::new(tag, len)));
+        }
+        tag @ b'%' => {
+            let len: usize = get_decimal_from_bytes(src)?.try_into()?;
+            return Ok(Parsed::Aggregate(Partial::new(tag, len.saturating_mul(2))));
+        }
+        tag @ b'|' => {
+            let len: usize = get_decimal_from_bytes(src)?.try_into()?;
+            let len = len.saturating_mul(2).saturating_add(1);
+            return Ok(Parsed::Aggregate(Partial::new(tag, len)));
+        }
+        b'_' => {
+            get_line_from_bytes(src)?;
+            Frame::Null
         }
+        b'#' => {
+            let line = get_line_from_bytes(src)?;
+            Frame::Boolean(get_boolean(&line)?)
+        }
+        b'(' => {
+            let line = get_line_from_bytes(src)?;
+            Frame::BigNumber(line)
+        }
+        b'=' => {
+            let len: usize = get_decimal_from_bytes(src)?.try_into()?;
+            // len + 2 to include the \r\n.
+            if src.remaining() < len.saturating_add(2) {
+                return Err(Error::Incomplete);
+            }
+
+            let mut text = src.split_to(len);
+            // skip the \r\n
+            src.advance(2);
+
+            // `fmt:` prefix.
+            if text.len() < 4 || text[3] != b':' {
+                return Err("protocol error; invalid frame format".into());
+            }
+            let format = text.split_to(3);
+            text.advance(1);
+
+            Frame::Verbatim { format, text }
+        }
+        b => {
+            return Err(format!(
+                "protocol error; invalid frame format. Unexpected byte: {}",
+                b
+            )
+            .into());
+        }
+    };
+
+    Ok(Parsed::Frame(frame))
+}
+
+/// Read the length of a bulk string, refused if it exceeds `max_bulk_len` or if the frame
+/// starting at `frame_start` would exceed `max_frame_size` with it.
+fn get_bulk_len(
+    src: &mut Cursor<&[u8]>,
+    limits: &Limits,
+    frame_start: usize,
+) -> Result<usize, Error> {
+    let len: usize = get_decimal(src)?
+        .try_into()
+        .map_err(|_| Error::from("protocol error; invalid bulk length"))?;
+    if len > limits.max_bulk_len {
+        return Err("protocol error; invalid bulk length".into());
     }
 
-    /// Returns an empty array
-    pub(crate) fn array() -> Frame {
-        Frame::Array(vec![])
+    let frame_len = (src.position() as usize - frame_start)
+        .saturating_add(len)
+        .saturating_add(2);
+    if frame_len > limits.max_frame_size {
+        return Err(frame_too_large());
     }
+
+    Ok(len)
+}
+
+/// Read the number of elements of an aggregate, refused if it exceeds `max_aggregate_len`.
+fn get_aggregate_len(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<usize, Error> {
+    let len: usize = get_decimal(src)?
+        .try_into()
+        .map_err(|_| Error::from("protocol error; invalid multibulk length"))?;
+    if len > limits.max_aggregate_len {
+        return Err("protocol error; invalid multibulk length".into());
+    }
+
+    Ok(len)
+}
+
+fn frame_too_large() -> Error {
+    "protocol error; frame too large".into()
 }
 
 /// Get byte at current cursor position without advancing the cursor.
@@ -324,6 +828,25 @@ fn get_double_from_bytes(src: &mut Bytes) -> Result<f64, Error> {
     parse::extract_f64(&line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+/// Parse the payload of a RESP3 boolean, `t` or `f`.
+fn get_boolean(line: &[u8]) -> Result<bool, Error> {
+    match line {
+        b"t" => Ok(true),
+        b"f" => Ok(false),
+        _ => Err("protocol error; invalid frame format".into()),
+    }
+}
+
+/// Check if the payload of a RESP3 big number is an optionally signed sequence of digits.
+fn is_big_number(line: &[u8]) -> bool {
+    let digits = match line.first() {
+        Some(b'-') | Some(b'+') => &line[1..],
+        _ => line,
+    };
+
+    !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+}
+
 /// Get all bytes until next CRLF.
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     let start = src.position() as usize;
@@ -356,13 +879,118 @@ fn get_line_from_bytes(src: &mut Bytes) -> Result<Bytes, Error> {
     Ok(line)
 }
 
+/// Append the decimal representation of `val` followed by CRLF.
+pub(crate) fn put_decimal(buf: &mut BytesMut, val: i64) {
+    // using itoa crate for better performance than std::fmt
+    let mut printed = itoa::Buffer::new();
+    buf.put_slice(printed.format(val).as_bytes());
+    buf.put_slice(b"\r\n");
+}
+
+/// Append a bulk string.
+pub(crate) fn put_bulk(buf: &mut BytesMut, val: &[u8]) {
+    buf.put_u8(b'$');
+    put_decimal(buf, val.len() as i64);
+    buf.put_slice(val);
+    buf.put_slice(b"\r\n");
+}
+
+/// Bulk strings at least this large are shared with the reply rather than copied into the
+/// write buffer, when the writer supports it.
+pub(crate) const SHARED_BULK_MIN: usize = 16 * 1024;
+
+/// Append a bulk string without copying it. The bytes of `buf` up to the payload are moved to
+/// `chunks`, followed by a reference to `val`, the trailing CRLF stays in `buf`.
+pub(crate) fn put_shared_bulk(buf: &mut BytesMut, chunks: &mut VecDeque<Bytes>, val: &Bytes) {
+    buf.put_u8(b'$');
+    put_decimal(buf, val.len() as i64);
+    chunks.push_back(buf.split().freeze());
+    chunks.push_back(val.clone());
+    buf.put_slice(b"\r\n");
+}
+
+/// Append a null value, `$-1` in RESP2 and `_` in RESP3.
+pub(crate) fn put_null(buf: &mut BytesMut, protocol: Protocol) {
+    match protocol {
+        Protocol::Resp2 => buf.put_slice(b"$-1\r\n"),
+        Protocol::Resp3 => buf.put_slice(b"_\r\n"),
+    }
+}
+
+/// Append a double value.
+/// RESP2 has no double type, the value is sent as a bulk string instead.
+pub(crate) fn put_double(buf: &mut BytesMut, val: f64, protocol: Protocol) {
+    if protocol == Protocol::Resp2 {
+        put_bulk(buf, &crate::db::double_to_bytes(val));
+        return;
+    }
+
+    // RESP3 Special cases: +inf, -inf, nan
+    if val.is_infinite() {
+        if val.is_sign_positive() {
+            buf.put_slice(b",inf\r\n");
+        } else {
+            buf.put_slice(b",-inf\r\n");
+        }
+        return;
+    } else if val.is_nan() {
+        buf.put_slice(b",nan\r\n");
+        return;
+    }
+
+    // Use ryu crate for better performance than format!() or to_string() method.
+    // Uses a stack allocated buffer to avoid heap allocations.
+    let mut printed = ryu::Buffer::new();
+    buf.put_u8(b',');
+    buf.put_slice(printed.format(val).as_bytes());
+    buf.put_slice(b"\r\n");
+}
+
+/// Append the header of a map with `len` key value pairs.
+///
+/// RESP2 has no map type, the pairs are sent as a flat array of `2 * len` items instead.
+pub(crate) fn put_map_header(buf: &mut BytesMut, len: usize, protocol: Protocol) {
+    match protocol {
+        Protocol::Resp2 => {
+            buf.put_u8(b'*');
+            put_decimal(buf, 2 * len as i64);
+        }
+        Protocol::Resp3 => {
+            buf.put_u8(b'%');
+            put_decimal(buf, len as i64);
+        }
+    }
+}
+
+/// Append the header of a set with `len` items.
+///
+/// RESP2 has no set type, the items are sent as an array instead.
+pub(crate) fn put_set_header(buf: &mut BytesMut, len: usize, protocol: Protocol) {
+    match protocol {
+        Protocol::Resp2 => buf.put_u8(b'*'),
+        Protocol::Resp3 => buf.put_u8(b'~'),
+    }
+    put_decimal(buf, len as i64);
+}
+
+/// Append the header of a push message with `len` items.
+///
+/// RESP2 has no push type, the items are sent as an array instead.
+pub(crate) fn put_push_header(buf: &mut BytesMut, len: usize, protocol: Protocol) {
+    match protocol {
+        Protocol::Resp2 => buf.put_u8(b'*'),
+        Protocol::Resp3 => buf.put_u8(b'>'),
+    }
+    put_decimal(buf, len as i64);
+}
+
 impl fmt::Display for Frame {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Frame::Error(err) => write!(fmt, "error: {err}"),
             Frame::Integer(num) => num.fmt(fmt),
             Frame::Double(num) => num.fmt(fmt),
-            Frame::Bulk(msg) | Frame::Simple(msg) => match str::from_utf8(&msg) {
+            Frame::Bulk(msg) | Frame::Simple(msg) => match str::from_utf8(msg) {
                 // valid text
                 Ok(string) => string.fmt(fmt),
                 // print raw bytes
@@ -392,6 +1020,56 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Set(frame_vec) => {
+                write!(fmt, "{{")?;
+
+                let mut iter = frame_vec.iter();
+
+                if let Some(first) = iter.next() {
+                    first.fmt(fmt)?;
+                }
+
+                for part in iter {
+                    write!(fmt, " {}", part)?;
+                }
+
+                write!(fmt, "}}")
+            }
+            Frame::Map(pairs) => {
+                write!(fmt, "{{")?;
+
+                let mut iter = pairs.iter();
+
+                if let Some((key, value)) = iter.next() {
+                    write!(fmt, "{key}: {value}")?;
+                }
+
+                for (key, value) in iter {
+                    write!(fmt, ", {key}: {value}")?;
+                }
+
+                write!(fmt, "}}")
+            }
+            Frame::Push(frame_vec) => {
+                write!(fmt, ">[")?;
+
+                let mut iter = frame_vec.iter();
+
+                if let Some(first) = iter.next() {
+                    first.fmt(fmt)?;
+                }
+
+                for part in iter {
+                    write!(fmt, " {}", part)?;
+                }
+
+                write!(fmt, "]")
+            }
+            // Attributes are auxiliary, only the data is printed.
+            Frame::Attribute { data, .. } => data.fmt(fmt),
+            Frame::Boolean(val) => val.fmt(fmt),
+            Frame::BigNumber(num) => String::from_utf8_lossy(num).fmt(fmt),
+            Frame::Verbatim { text, .. } => String::from_utf8_lossy(text).fmt(fmt),
         }
     }
 }
@@ -403,14 +1081,8 @@ impl From<Data> for Frame {
             Data::Bytes(val) => Frame::Bulk(val),
             Data::String(val) => Frame::Simple(val),
             Data::Double(val) => Frame::Double(val),
-            // NOTE: This will flatten nested array's.
-            Data::Array(arr) => {
-                let mut frame = Frame::array();
-                for item in arr.iter() {
-                    frame.push(Frame::from(item.clone()));
-                }
-                frame
-            }
+            // Nested arrays stay nested.
+            Data::Array(arr) => Frame::Array(arr.into_iter().map(Frame::from).collect()),
         }
     }
 }
@@ -429,7 +1101,7 @@ impl TryFrom<Frame> for Data {
             Frame::Bulk(bytes) => Ok(optimize_storage(bytes)),
             Frame::Integer(val) => Ok(Data::Integer(val)),
             Frame::Double(val) => Ok(Data::Double(val)),
-            // NOTE: This will flatten nested arrays.
+            // Nested arrays stay nested.
             Frame::Array(arr) => {
                 let mut data_vec = VecDeque::with_capacity(arr.len());
                 for frame in arr.into_iter() {
@@ -437,7 +1109,27 @@ impl TryFrom<Frame> for Data {
                 }
                 Ok(Data::Array(data_vec))
             }
-            Frame::Error(err) => Err(err.into()),
+            // NOTE: Maps are flattened into alternating keys and values.
+            Frame::Map(pairs) => {
+                let mut data_vec = VecDeque::with_capacity(pairs.len() * 2);
+                for (key, value) in pairs.into_iter() {
+                    data_vec.push_back(Data::try_from(key)?);
+                    data_vec.push_back(Data::try_from(value)?);
+                }
+                Ok(Data::Array(data_vec))
+            }
+            Frame::Set(set) | Frame::Push(set) => {
+                let mut data_vec = VecDeque::with_capacity(set.len());
+                for frame in set.into_iter() {
+                    data_vec.push_back(Data::try_from(frame)?);
+                }
+                Ok(Data::Array(data_vec))
+            }
+            Frame::Boolean(val) => Ok(Data::Integer(val as i64)),
+            Frame::BigNumber(num) => Ok(Data::Bytes(num)),
+            Frame::Verbatim { text, .. } => Ok(Data::Bytes(text)),
+            Frame::Attribute { data, .. } => Data::try_from(*data),
+            Frame::Error(err) => Err(err),
             Frame::Null => Err("Null not allowed for DB value.".into()),
         }
     }
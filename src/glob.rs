@@ -0,0 +1,126 @@
+/// Match `string` against the glob-style `pattern`.
+///
+/// Supported patterns:
+/// * `*` matches any sequence of bytes, including an empty one.
+/// * `?` matches any single byte.
+/// * `[abc]`, `[^abc]` and `[a-z]` match a single byte in, or not in, the set.
+/// * `\x` matches `x` literally.
+///
+/// Matching is case sensitive unless `nocase` is set.
+pub(crate) fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    let mut p = 0;
+    let mut s = 0;
+    // Position in the pattern after the last `*` and the position in the string it was tried
+    // at, used to backtrack when the rest of the pattern fails to match.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        let matched = match pattern.get(p) {
+            Some(b'*') => {
+                // Collapse consecutive stars.
+                while pattern.get(p) == Some(&b'*') {
+                    p += 1;
+                }
+
+                if p == pattern.len() {
+                    return true;
+                }
+
+                backtrack = Some((p, s));
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                true
+            }
+            Some(b'[') => match match_class(pattern, p + 1, string[s], nocase) {
+                Some((matched, next)) => {
+                    p = next;
+                    matched
+                }
+                // Unterminated class, `[` is matched literally.
+                None => {
+                    p += 1;
+                    string[s] == b'['
+                }
+            },
+            Some(b'\\') if p + 1 < pattern.len() => {
+                p += 2;
+                eq(pattern[p - 1], string[s])
+            }
+            Some(&byte) => {
+                p += 1;
+                eq(byte, string[s])
+            }
+            None => false,
+        };
+
+        if matched {
+            s += 1;
+            continue;
+        }
+
+        // Let the last `*` consume one more byte and retry.
+        match backtrack {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                backtrack = Some((star_p, star_s + 1));
+            }
+            None => return false,
+        }
+    }
+
+    // String is consumed, only stars may remain in the pattern.
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+/// Match `byte` against the class starting at `start`, just after the opening `[`.
+///
+/// Returns whether the byte matched and the position after the closing `]`, or `None` if the
+/// class is not terminated.
+fn match_class(pattern: &[u8], start: usize, byte: u8, nocase: bool) -> Option<(bool, usize)> {
+    let fold = |b: u8| if nocase { b.to_ascii_lowercase() } else { b };
+    let byte = fold(byte);
+
+    let mut p = start;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+
+    loop {
+        match *pattern.get(p)? {
+            b']' => return Some((matched != negate, p + 1)),
+            b'\\' if p + 1 < pattern.len() => {
+                matched |= fold(pattern[p + 1]) == byte;
+                p += 2;
+            }
+            low if pattern.get(p + 1) == Some(&b'-')
+                && pattern.get(p + 2).is_some_and(|&high| high != b']') =>
+            {
+                let (mut low, mut high) = (fold(low), fold(pattern[p + 2]));
+                if low > high {
+                    std::mem::swap(&mut low, &mut high);
+                }
+
+                matched |= (low..=high).contains(&byte);
+                p += 3;
+            }
+            other => {
+                matched |= fold(other) == byte;
+                p += 1;
+            }
+        }
+    }
+}
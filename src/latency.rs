@@ -0,0 +1,246 @@
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::frame::Frame;
+
+/// Number of samples kept for each event, the most recent ones.
+const HISTORY_LEN: usize = 160;
+
+/// Latencies below this many microseconds have a bucket of their own in a `Histogram`, larger
+/// ones share a bucket with the latencies within about 6% of them.
+const SUB_BUCKETS: u64 = 16;
+/// Number of bits of `SUB_BUCKETS`.
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+/// Largest latency told apart by a `Histogram`, about 38 hours. Longer ones are counted as it.
+const MAX_LATENCY_US: u64 = (1 << 37) - 1;
+/// Number of buckets of a `Histogram`.
+const BUCKETS: usize = bucket(MAX_LATENCY_US) + 1;
+
+/// Latency spikes of an event, above `latency-monitor-threshold`.
+#[derive(Default)]
+struct EventHistory {
+    /// Most recent spikes, at most one per second holding the worst latency of that second.
+    samples: VecDeque<Sample>,
+    /// Worst latency of the event since it was last reset, in milliseconds.
+    max: u64,
+}
+
+/// Worst latency of an event during a second.
+#[derive(Clone, Copy)]
+struct Sample {
+    /// Unix time of the second, in seconds.
+    time: u64,
+    /// Latency in milliseconds.
+    latency: u64,
+}
+
+/// Execution times of a command, in buckets of about 6% of their latency.
+struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+}
+
+/// Latency spikes of the server's events, such as commands or syncs of the append only file,
+/// reported by `LATENCY`, and the distribution of the execution times of each command reported
+/// by `INFO latencystats`.
+pub(crate) struct LatencyMonitor {
+    events: Mutex<HashMap<&'static str, EventHistory>>,
+    commands: DashMap<&'static str, Histogram>,
+}
+
+impl LatencyMonitor {
+    pub(crate) fn new() -> LatencyMonitor {
+        LatencyMonitor {
+            events: Mutex::new(HashMap::new()),
+            commands: DashMap::new(),
+        }
+    }
+
+    /// Record that `event` took `elapsed`, if it's at least `threshold` milliseconds. A
+    /// threshold of 0 disables the monitor.
+    pub(crate) fn add_sample(&self, event: &'static str, elapsed: Duration, threshold: u64) {
+        let latency = elapsed.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut events = self.events.lock().unwrap();
+        let history = events.entry(event).or_default();
+        history.max = history.max.max(latency);
+        match history.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                if history.samples.len() == HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+                history.samples.push_back(Sample { time, latency });
+            }
+        }
+    }
+
+    /// Latest spike of every event, as in the reply of `LATENCY LATEST`: event, Unix time,
+    /// latency and worst latency of the event.
+    pub(crate) fn latest(&self) -> Vec<Frame> {
+        let events = self.events.lock().unwrap();
+        let mut latest: Vec<_> = events
+            .iter()
+            .filter_map(|(event, history)| Some((*event, *history.samples.back()?, history.max)))
+            .collect();
+        latest.sort_unstable_by_key(|(event, _, _)| *event);
+
+        latest
+            .into_iter()
+            .map(|(event, sample, max)| {
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(event.as_bytes())),
+                    Frame::Integer(sample.time as i64),
+                    Frame::Integer(sample.latency as i64),
+                    Frame::Integer(max as i64),
+                ])
+            })
+            .collect()
+    }
+
+    /// Spikes of `event`, oldest first, as in the reply of `LATENCY HISTORY`: Unix time and
+    /// latency.
+    pub(crate) fn history(&self, event: &[u8]) -> Vec<Frame> {
+        let events = self.events.lock().unwrap();
+        let Some(history) = events
+            .iter()
+            .find(|(name, _)| name.as_bytes().eq_ignore_ascii_case(event))
+            .map(|(_, history)| history)
+        else {
+            return Vec::new();
+        };
+
+        history
+            .samples
+            .iter()
+            .map(|sample| {
+                Frame::Array(vec![
+                    Frame::Integer(sample.time as i64),
+                    Frame::Integer(sample.latency as i64),
+                ])
+            })
+            .collect()
+    }
+
+    /// Remove the spikes of `events`, of every event if empty. Returns the number of events
+    /// removed.
+    pub(crate) fn reset(&self, events: &[Bytes]) -> usize {
+        let mut history = self.events.lock().unwrap();
+        if events.is_empty() {
+            let removed = history.len();
+            history.clear();
+            return removed;
+        }
+
+        let before = history.len();
+        history.retain(|name, _| {
+            !events
+                .iter()
+                .any(|event| name.as_bytes().eq_ignore_ascii_case(event))
+        });
+        before - history.len()
+    }
+
+    /// Record that the command `name` took `elapsed` to execute.
+    pub(crate) fn record_command(&self, name: &'static str, elapsed: Duration) {
+        let latency = (elapsed.as_micros() as u64).min(MAX_LATENCY_US);
+        let record = |histogram: &Histogram| {
+            histogram.buckets[bucket(latency)].fetch_add(1, Ordering::Relaxed);
+            histogram.count.fetch_add(1, Ordering::Relaxed);
+        };
+
+        match self.commands.get(name) {
+            Some(histogram) => record(&histogram),
+            None => record(&self.commands.entry(name).or_insert_with(Histogram::new)),
+        }
+    }
+
+    /// Forget the execution times of every command, with `CONFIG RESETSTAT`.
+    pub(crate) fn reset_commands(&self) {
+        self.commands.clear();
+    }
+
+    /// Execution time of each command at `percentiles`, in microseconds, by command name in
+    /// alphabetical order. A percentile is the highest latency of its bucket.
+    pub(crate) fn command_percentiles(
+        &self,
+        percentiles: &[f64],
+    ) -> Vec<(&'static str, Vec<(f64, u64)>)> {
+        let mut commands: Vec<_> = self
+            .commands
+            .iter()
+            .filter(|histogram| histogram.count.load(Ordering::Relaxed) > 0)
+            .map(|histogram| {
+                let values = percentiles
+                    .iter()
+                    .map(|&percentile| (percentile, histogram.percentile(percentile)))
+                    .collect();
+                (*histogram.key(), values)
+            })
+            .collect();
+        commands.sort_unstable_by_key(|(name, _)| *name);
+        commands
+    }
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// Latency under which `percentile` percent of the executions are, in microseconds.
+    fn percentile(&self, percentile: f64) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        let rank = ((percentile / 100.0 * count as f64).ceil() as u64).clamp(1, count.max(1));
+
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= rank {
+                return bucket_max(i);
+            }
+        }
+        MAX_LATENCY_US
+    }
+}
+
+/// Bucket of a latency of `us` microseconds. The buckets of each power of two are split in
+/// `SUB_BUCKETS`.
+const fn bucket(us: u64) -> usize {
+    if us < SUB_BUCKETS {
+        return us as usize;
+    }
+    let shift = 63 - us.leading_zeros() - SUB_BUCKET_BITS;
+    let sub = (us >> shift) - SUB_BUCKETS;
+    ((shift as u64 + 1) * SUB_BUCKETS + sub) as usize
+}
+
+/// Highest latency of the bucket `i`, in microseconds.
+fn bucket_max(i: usize) -> u64 {
+    let i = i as u64;
+    if i < SUB_BUCKETS {
+        return i;
+    }
+    let shift = i / SUB_BUCKETS - 1;
+    let sub = i % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub + 1) << shift) - 1
+}
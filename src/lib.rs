@@ -1,3 +1,5 @@
+pub mod log;
+
 pub mod connection;
 
 pub use connection::Connection;
@@ -5,13 +7,62 @@ pub use connection::Connection;
 pub(crate) mod cmd;
 pub(crate) use cmd::Command;
 
-pub(crate) mod frame;
+pub mod frame;
+pub use frame::Frame;
+
 pub(crate) mod parse;
 
 pub mod server;
 
+pub(crate) mod registry;
+
+pub(crate) mod pause;
+
+pub(crate) mod ratelimit;
+
+pub(crate) mod tracking;
+
+pub(crate) mod config;
+
+pub(crate) mod glob;
+
+pub(crate) mod monitor;
+
+pub(crate) mod slowlog;
+
+pub(crate) mod metrics;
+
+pub(crate) mod latency;
+
+pub(crate) mod memory;
+
+pub mod audit;
+
+pub(crate) mod task;
+
+#[cfg(unix)]
+pub mod systemd;
+
+pub(crate) mod persistence;
+
+pub(crate) mod crc64;
+
+pub(crate) mod replication;
+
+pub mod slot;
+
+pub(crate) mod cluster;
+
+pub(crate) mod sentinel;
+
 pub mod client;
 
 pub mod db;
 
 pub mod errors;
+
+#[cfg(feature = "tls")]
+pub mod tls;
+
+#[cfg(feature = "otel")]
+pub mod otel;
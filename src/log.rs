@@ -0,0 +1,261 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    time::Duration,
+};
+use tracing::Level;
+
+use rotate::RotatingFile;
+
+pub(crate) mod rotate;
+
+/// Number of rotated log files kept by default.
+pub const DEFAULT_MAX_FILES: usize = 4;
+
+/// Severity of a message logged by the server. Messages less severe than the level set with
+/// `set_level` or `CONFIG SET loglevel` are not logged. The levels are the ones of Redis.
+///
+/// The server emits `tracing` events, each level matches a level of `tracing`: `debug` is
+/// `TRACE`, `verbose` is `DEBUG`, `notice` is `INFO` and `warning` is `WARN` and `ERROR`.
+/// Subscribers honour the level by filtering events with `enabled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Details useful while developing, such as tasks ending.
+    Debug,
+    /// Events of single connections, such as their errors.
+    Verbose,
+    /// Events of the server, such as the dataset being loaded. The default level.
+    Notice,
+    /// Failures needing attention.
+    Warning,
+    /// Only used as the level, nothing is logged.
+    Nothing,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Verbose => "verbose",
+            LogLevel::Notice => "notice",
+            LogLevel::Warning => "warning",
+            LogLevel::Nothing => "nothing",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<LogLevel, String> {
+        [
+            LogLevel::Debug,
+            LogLevel::Verbose,
+            LogLevel::Notice,
+            LogLevel::Warning,
+            LogLevel::Nothing,
+        ]
+        .into_iter()
+        .find(|level| level.name().eq_ignore_ascii_case(value))
+        .ok_or_else(|| {
+            "argument must be one of the following: debug, verbose, notice, warning, nothing".into()
+        })
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Level of the process, shared by every server it runs.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Notice as u8);
+
+/// Only log messages at least as severe as `level`.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Least severe level of the messages logged.
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Debug,
+        1 => LogLevel::Verbose,
+        2 => LogLevel::Notice,
+        3 => LogLevel::Warning,
+        _ => LogLevel::Nothing,
+    }
+}
+
+/// Returns `true` if `tracing` events of `level` are logged at the current level.
+///
+/// ```no_run
+/// use tracing_subscriber::{filter, prelude::*};
+///
+/// tracing_subscriber::registry()
+///     .with(
+///         tracing_subscriber::fmt::layer()
+///             .with_filter(filter::filter_fn(|meta| walrus::log::enabled(*meta.level()))),
+///     )
+///     .init();
+/// ```
+pub fn enabled(level: Level) -> bool {
+    let level = match level {
+        Level::TRACE => LogLevel::Debug,
+        Level::DEBUG => LogLevel::Verbose,
+        Level::INFO => LogLevel::Notice,
+        _ => LogLevel::Warning,
+    };
+    level >= self::level()
+}
+
+/// File messages are logged to, with its rotation settings.
+struct Output {
+    /// Path of the file, `None` to log to stdout.
+    path: Option<PathBuf>,
+    file: Option<RotatingFile>,
+    /// Size in bytes the file is rotated at, 0 never rotates.
+    max_size: u64,
+    /// Number of rotated files kept.
+    max_files: usize,
+    /// Age the file is rotated at, zero never rotates.
+    max_age: Duration,
+}
+
+/// File of the process, shared by every server it runs.
+static OUTPUT: Mutex<Output> = Mutex::new(Output {
+    path: None,
+    file: None,
+    max_size: 0,
+    max_files: DEFAULT_MAX_FILES,
+    max_age: Duration::ZERO,
+});
+
+/// Whether a file is set, read by the filters of every message.
+static TO_FILE: AtomicBool = AtomicBool::new(false);
+
+/// Log messages to the file at `path`, appending to it, or to stdout if `None`. The previous
+/// file is kept if the new one can't be opened.
+pub fn set_file(path: Option<&Path>) -> io::Result<()> {
+    let mut output = OUTPUT.lock().unwrap();
+
+    let file = match path {
+        Some(path) => {
+            let mut file = RotatingFile::open(path, output.max_size, output.max_files)?;
+            file.set_max_age(output.max_age);
+            Some(file)
+        }
+        None => None,
+    };
+    if let Some(previous) = output.file.as_mut() {
+        let _ = previous.flush();
+    }
+
+    output.path = path.map(Path::to_path_buf);
+    output.file = file;
+    TO_FILE.store(output.file.is_some(), Ordering::Relaxed);
+    Ok(())
+}
+
+/// File messages are logged to, `None` if they are logged to stdout.
+pub fn file() -> Option<PathBuf> {
+    OUTPUT.lock().unwrap().path.clone()
+}
+
+/// Returns `true` if messages are logged to a file rather than to stdout.
+pub fn to_file() -> bool {
+    TO_FILE.load(Ordering::Relaxed)
+}
+
+/// Rotate the log file once it reaches `max_size` bytes, 0 never rotates it.
+pub fn set_max_size(max_size: u64) {
+    let mut output = OUTPUT.lock().unwrap();
+    output.max_size = max_size;
+    if let Some(file) = output.file.as_mut() {
+        file.set_max_size(max_size);
+    }
+}
+
+/// Size in bytes the log file is rotated at, 0 if never.
+pub fn max_size() -> u64 {
+    OUTPUT.lock().unwrap().max_size
+}
+
+/// Keep `max_files` rotated log files, 0 truncates the file instead of rotating it.
+pub fn set_max_files(max_files: usize) {
+    let mut output = OUTPUT.lock().unwrap();
+    output.max_files = max_files;
+    if let Some(file) = output.file.as_mut() {
+        file.set_max_files(max_files);
+    }
+}
+
+/// Number of rotated log files kept.
+pub fn max_files() -> usize {
+    OUTPUT.lock().unwrap().max_files
+}
+
+/// Rotate the log file once it was written to for `interval`, such as a day, zero never
+/// rotates it.
+pub fn set_rotate_interval(interval: Duration) {
+    let mut output = OUTPUT.lock().unwrap();
+    output.max_age = interval;
+    if let Some(file) = output.file.as_mut() {
+        file.set_max_age(interval);
+    }
+}
+
+/// Interval the log file is rotated at, zero if never.
+pub fn rotate_interval() -> Duration {
+    OUTPUT.lock().unwrap().max_age
+}
+
+/// Writer of the messages to the log file set with `set_file`. Each write is flushed, so a
+/// message is written whole. Messages are dropped if no file is set.
+///
+/// ```no_run
+/// use tracing_subscriber::{filter, prelude::*};
+///
+/// walrus::log::set_file(Some("walrus.log".as_ref())).unwrap();
+/// tracing_subscriber::registry()
+///     .with(
+///         tracing_subscriber::fmt::layer()
+///             .with_ansi(false)
+///             .with_writer(walrus::log::file_writer)
+///             .with_filter(filter::filter_fn(|meta| {
+///                 walrus::log::to_file() && walrus::log::enabled(*meta.level())
+///             })),
+///     )
+///     .init();
+/// ```
+pub fn file_writer() -> FileWriter {
+    FileWriter
+}
+
+/// Writer returned by `file_writer`.
+pub struct FileWriter;
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = OUTPUT.lock().unwrap();
+        if let Some(file) = output.file.as_mut() {
+            file.write(buf)?;
+            file.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match OUTPUT.lock().unwrap().file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// File appended to, moved aside once it reaches a maximum size or age. The file at `path` is
+/// renamed `path.1`, the previous `path.1` is renamed `path.2` and so on, the oldest file beyond
+/// `max_files` is removed.
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    /// Size in bytes the file is rotated at, 0 never rotates.
+    max_size: u64,
+    /// Number of rotated files kept, 0 truncates the file instead of rotating it.
+    max_files: usize,
+    /// Age the file is rotated at, counted from when it was opened or last rotated. Zero never
+    /// rotates.
+    max_age: Duration,
+    /// Instant the file was opened or last rotated.
+    started: Instant,
+    writer: BufWriter<File>,
+    /// Current size of the file, including what is still buffered.
+    size: u64,
+}
+
+impl RotatingFile {
+    /// Open the file at `path` for appending, creating it if it doesn't exist.
+    pub(crate) fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_size,
+            max_files,
+            max_age: Duration::ZERO,
+            started: Instant::now(),
+            writer: BufWriter::new(file),
+            size,
+        })
+    }
+
+    /// Set the size in bytes the file is rotated at, 0 never rotates.
+    pub(crate) fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+    }
+
+    /// Set the number of rotated files kept.
+    pub(crate) fn set_max_files(&mut self, max_files: usize) {
+        self.max_files = max_files;
+    }
+
+    /// Set the age the file is rotated at, zero never rotates.
+    pub(crate) fn set_max_age(&mut self, max_age: Duration) {
+        self.max_age = max_age;
+    }
+
+    /// Append `bytes`, rotating the file first if they would take it past its maximum size or
+    /// if it reached its maximum age. A file is never left empty by rotating, larger writes go
+    /// to a file of their own.
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let full = self.max_size > 0 && self.size + bytes.len() as u64 > self.max_size;
+        let old = !self.max_age.is_zero() && self.started.elapsed() >= self.max_age;
+        if self.size > 0 && (full || old) {
+            self.rotate()?;
+        } else if old {
+            // An empty file starts its age with its first write.
+            self.started = Instant::now();
+        }
+
+        self.writer.write_all(bytes)?;
+        self.size += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Write what is buffered to the file.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Move the file aside and start a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        if self.max_files > 0 {
+            match fs::remove_file(self.rotated(self.max_files)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+            for i in (1..self.max_files).rev() {
+                match fs::rename(self.rotated(i), self.rotated(i + 1)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    /// Path of the `n`th most recent rotated file.
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        PathBuf::from(path)
+    }
+}
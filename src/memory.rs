@@ -0,0 +1,362 @@
+//! Memory used by the server, as seen by its allocator and by the keyspace, reported by
+//! `INFO memory`, `MEMORY STATS` and `MEMORY DOCTOR`.
+//!
+//! Allocator statistics are read from jemalloc with the `jemalloc` feature, enabled by default
+//! but unavailable on MSVC, or from mimalloc with the `mimalloc` feature, which takes precedence
+//! when both are enabled. They describe the allocator the server binary is built with, an
+//! application embedding the server with another global allocator gets meaningless values.
+//! Without them only the size of the dataset is known.
+
+use std::{
+    fmt::Write,
+    sync::{
+        Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::server::ServerState;
+
+/// Interval the memory used is sampled at to track its peak.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Memory used below which `MEMORY DOCTOR` has nothing to diagnose, 5MB.
+const DOCTOR_MIN_MEMORY: usize = 5 * 1024 * 1024;
+
+/// Bytes wasted below which a fragmentation ratio isn't worth reporting, 10MB.
+const DOCTOR_MIN_WASTE: usize = 10 * 1024 * 1024;
+
+/// Name of the allocator, reported as `mem_allocator`.
+#[cfg(feature = "mimalloc")]
+pub(crate) const ALLOCATOR: &str = "mimalloc";
+#[cfg(all(
+    feature = "jemalloc",
+    not(feature = "mimalloc"),
+    not(target_env = "msvc")
+))]
+pub(crate) const ALLOCATOR: &str = "jemalloc";
+#[cfg(not(any(
+    feature = "mimalloc",
+    all(feature = "jemalloc", not(target_env = "msvc"))
+)))]
+pub(crate) const ALLOCATOR: &str = "libc";
+
+/// Statistics of the allocator, in bytes.
+pub(crate) struct AllocatorStats {
+    /// Bytes allocated by the application.
+    pub(crate) allocated: usize,
+    /// Bytes of the pages holding allocations.
+    pub(crate) active: usize,
+    /// Bytes of the pages mapped by the allocator and resident in memory.
+    pub(crate) resident: usize,
+}
+
+/// Statistics read with `mallctl` from jemalloc, `None` if they can't be read.
+#[cfg(all(
+    feature = "jemalloc",
+    not(feature = "mimalloc"),
+    not(target_env = "msvc")
+))]
+pub(crate) fn allocator_stats() -> Option<AllocatorStats> {
+    use std::{ffi::c_void, mem, ptr};
+
+    /// Read the `usize` statistic `name`, a nul terminated string.
+    fn read(name: &[u8]) -> Option<usize> {
+        let mut value: usize = 0;
+        let mut len = mem::size_of::<usize>();
+        // SAFETY: `name` is nul terminated and `value` is a writable `usize` of `len` bytes.
+        let ret = unsafe {
+            jemalloc_sys::mallctl(
+                name.as_ptr().cast(),
+                &mut value as *mut usize as *mut c_void,
+                &mut len,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        (ret == 0).then_some(value)
+    }
+
+    // Statistics are cached by jemalloc until the epoch is advanced.
+    let mut epoch: u64 = 1;
+    let mut len = mem::size_of::<u64>();
+    // SAFETY: `epoch` is a readable and writable `u64` of `len` bytes.
+    unsafe {
+        jemalloc_sys::mallctl(
+            c"epoch".as_ptr(),
+            &mut epoch as *mut u64 as *mut c_void,
+            &mut len,
+            &mut epoch as *mut u64 as *mut c_void,
+            len,
+        );
+    }
+
+    Some(AllocatorStats {
+        allocated: read(b"stats.allocated\0")?,
+        active: read(b"stats.active\0")?,
+        resident: read(b"stats.resident\0")?,
+    })
+}
+
+/// Statistics read with `mi_process_info` from mimalloc. It doesn't count the bytes allocated,
+/// the memory committed stands for both the allocated and the active bytes.
+#[cfg(feature = "mimalloc")]
+pub(crate) fn allocator_stats() -> Option<AllocatorStats> {
+    let mut elapsed = 0;
+    let mut user = 0;
+    let mut system = 0;
+    let mut rss = 0;
+    let mut peak_rss = 0;
+    let mut commit = 0;
+    let mut peak_commit = 0;
+    let mut page_faults = 0;
+    // SAFETY: every pointer is to a writable `usize`.
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            &mut elapsed,
+            &mut user,
+            &mut system,
+            &mut rss,
+            &mut peak_rss,
+            &mut commit,
+            &mut peak_commit,
+            &mut page_faults,
+        );
+    }
+
+    Some(AllocatorStats {
+        allocated: commit,
+        active: commit,
+        resident: rss,
+    })
+}
+
+#[cfg(not(any(
+    feature = "mimalloc",
+    all(feature = "jemalloc", not(target_env = "msvc"))
+)))]
+pub(crate) fn allocator_stats() -> Option<AllocatorStats> {
+    None
+}
+
+/// Highest memory used by the server since it started, sampled every `SAMPLE_INTERVAL` and
+/// whenever the memory used is reported.
+pub(crate) struct MemoryPeak(AtomicUsize);
+
+impl MemoryPeak {
+    pub(crate) fn new() -> MemoryPeak {
+        MemoryPeak(AtomicUsize::new(0))
+    }
+
+    /// Record `used` bytes, returns the peak including them.
+    fn update(&self, used: usize) -> usize {
+        self.0.fetch_max(used, Ordering::Relaxed).max(used)
+    }
+}
+
+/// Memory used by the server at one point.
+pub(crate) struct MemoryReport {
+    /// Bytes allocated, the dataset size if the allocator is unknown.
+    pub(crate) used: usize,
+    /// Highest `used` since the server started.
+    pub(crate) peak: usize,
+    /// Approximate bytes used by the keys and values.
+    pub(crate) dataset: usize,
+    /// Statistics of the allocator, if available.
+    pub(crate) allocator: Option<AllocatorStats>,
+}
+
+impl MemoryReport {
+    /// Sample the memory used now, updating the peak of `server`.
+    pub(crate) fn sample(server: &ServerState) -> MemoryReport {
+        let dataset = server.used_memory();
+        let allocator = allocator_stats();
+        let used = allocator
+            .as_ref()
+            .map_or(dataset, |allocator| allocator.allocated);
+
+        MemoryReport {
+            used,
+            peak: server.memory_peak.update(used),
+            dataset,
+            allocator,
+        }
+    }
+
+    /// Ratio of the memory resident to the memory used, `None` if the allocator is unknown.
+    pub(crate) fn fragmentation_ratio(&self) -> Option<f64> {
+        self.allocator
+            .as_ref()
+            .map(|allocator| allocator.resident as f64 / self.used.max(1) as f64)
+    }
+
+    /// Fields of the `INFO memory` section, named like the fields of Redis. Allocator fields
+    /// are left out if the allocator is unknown.
+    pub(crate) fn info(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("used_memory", self.used.to_string()),
+            ("used_memory_human", human(self.used)),
+            ("used_memory_peak", self.peak.to_string()),
+            ("used_memory_peak_human", human(self.peak)),
+            (
+                "used_memory_peak_perc",
+                format!("{:.2}%", percentage(self.used, self.peak)),
+            ),
+            ("used_memory_dataset", self.dataset.to_string()),
+        ];
+
+        if let Some(allocator) = &self.allocator {
+            fields.extend([
+                ("used_memory_rss", allocator.resident.to_string()),
+                ("used_memory_rss_human", human(allocator.resident)),
+                ("allocator_allocated", allocator.allocated.to_string()),
+                ("allocator_active", allocator.active.to_string()),
+                ("allocator_resident", allocator.resident.to_string()),
+                (
+                    "allocator_frag_ratio",
+                    format!(
+                        "{:.2}",
+                        allocator.active as f64 / allocator.allocated.max(1) as f64
+                    ),
+                ),
+                (
+                    "allocator_frag_bytes",
+                    (allocator.active as i64 - allocator.allocated as i64).to_string(),
+                ),
+                (
+                    "mem_fragmentation_ratio",
+                    format!("{:.2}", self.fragmentation_ratio().unwrap_or(0.0)),
+                ),
+                (
+                    "mem_fragmentation_bytes",
+                    (allocator.resident as i64 - self.used as i64).to_string(),
+                ),
+            ]);
+        }
+
+        fields.push(("mem_allocator", ALLOCATOR.to_string()));
+        fields
+    }
+
+    /// Report of `MEMORY DOCTOR`, the issues found in the memory used with advice on each of
+    /// them. `maxmemory` is the limit of the dataset, 0 if unlimited.
+    pub(crate) fn doctor(&self, maxmemory: u64) -> String {
+        if self.allocator.is_some() && self.used < DOCTOR_MIN_MEMORY {
+            return "The server uses very little memory, there is nothing to diagnose yet. \
+                Come back once it holds some data.\n"
+                .to_string();
+        }
+
+        let mut issues = Vec::new();
+
+        if self.allocator.is_none() {
+            issues.push(format!(
+                "Allocator statistics unavailable: the server is built without jemalloc or \
+                mimalloc, only the size of the dataset, {}, is known. Build it with the \
+                `jemalloc` or `mimalloc` feature to diagnose fragmentation.",
+                human(self.dataset)
+            ));
+        }
+
+        if self.peak > self.used + self.used / 2 {
+            issues.push(format!(
+                "Peak memory: the server used {} at its peak, more than 150% of the {} it uses \
+                now. Allocators rarely return memory to the system after a peak, so the \
+                fragmentation ratio may look high while the memory is only waiting to be \
+                reused by new keys.",
+                human(self.peak),
+                human(self.used)
+            ));
+        }
+
+        if let Some(allocator) = &self.allocator {
+            let frag_bytes = allocator.active.saturating_sub(allocator.allocated);
+            if allocator.active as f64 > allocator.allocated as f64 * 1.1
+                && frag_bytes > DOCTOR_MIN_WASTE
+            {
+                issues.push(format!(
+                    "High allocator fragmentation: the pages of the allocator hold {} more than \
+                    the {} allocated. Deleting many small keys leaves pages partially used, \
+                    they are reused as keys are written again.",
+                    human(frag_bytes),
+                    human(allocator.allocated)
+                ));
+            }
+
+            let rss_bytes = allocator.resident.saturating_sub(allocator.active);
+            if allocator.resident as f64 > allocator.active as f64 * 1.1
+                && rss_bytes > DOCTOR_MIN_WASTE
+            {
+                issues.push(format!(
+                    "High allocator RSS overhead: {} of memory is resident but no longer holds \
+                    allocations. The allocator returns it to the system over time.",
+                    human(rss_bytes)
+                ));
+            }
+        }
+
+        if maxmemory > 0 && self.dataset as f64 > maxmemory as f64 * 0.9 {
+            issues.push(format!(
+                "Close to maxmemory: the dataset uses {} of the {} allowed by `maxmemory`. Once \
+                the limit is reached keys are evicted following `maxmemory-policy`, or writes \
+                are refused with `noeviction`.",
+                human(self.dataset),
+                human(maxmemory as usize)
+            ));
+        }
+
+        if issues.is_empty() {
+            return "No memory issues were found in the server.\n".to_string();
+        }
+
+        let mut report = String::from("The following memory issues were found:\n\n");
+        for issue in issues {
+            let _ = writeln!(report, " * {issue}\n");
+        }
+        report
+    }
+}
+
+/// Sample the memory used by the server every `SAMPLE_INTERVAL` to track its peak, until the
+/// server is dropped.
+pub(crate) async fn sample_task(server: Weak<ServerState>) {
+    let mut interval = time::interval(SAMPLE_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let Some(server) = server.upgrade() else {
+            return;
+        };
+        MemoryReport::sample(&server);
+    }
+}
+
+/// `bytes` with a binary unit, such as `1.50M`.
+fn human(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{value:.2}{}", UNITS[unit])
+    }
+}
+
+/// `part` as a percentage of `total`, 0 if `total` is 0.
+pub(crate) fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / total as f64
+}
@@ -0,0 +1,335 @@
+use std::{
+    fmt::Write as _,
+    sync::{
+        Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tracing::{debug, info, warn};
+
+use crate::{server::ServerState, task};
+
+/// Upper bounds of the buckets of the command latency histogram, in microseconds. Commands
+/// slower than the last bound are only counted in the `+Inf` bucket.
+const LATENCY_BUCKETS: [u64; 13] = [
+    10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000,
+];
+
+/// Maximum size of the head of a request to the metrics endpoint, larger requests are refused.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time a scraper has to send its request once connected.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics of the server not kept elsewhere, exported along with the statistics of the
+/// dataset and of the connections on the metrics endpoint, see `serve`.
+pub(crate) struct Metrics {
+    /// Number of bytes read from clients.
+    net_input_bytes: AtomicU64,
+    /// Number of bytes written to clients.
+    net_output_bytes: AtomicU64,
+    /// Number of commands in each bucket of `LATENCY_BUCKETS`, the last one is `+Inf`. Not
+    /// cumulative, unlike the buckets exported.
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    /// Total execution time of the commands in the histogram, in microseconds.
+    latency_sum: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            net_input_bytes: AtomicU64::new(0),
+            net_output_bytes: AtomicU64::new(0),
+            latency_buckets: Default::default(),
+            latency_sum: AtomicU64::new(0),
+        }
+    }
+
+    /// Count `input` bytes read from and `output` bytes written to a client.
+    pub(crate) fn add_traffic(&self, input: u64, output: u64) {
+        self.net_input_bytes.fetch_add(input, Ordering::Relaxed);
+        self.net_output_bytes.fetch_add(output, Ordering::Relaxed);
+    }
+
+    /// Number of bytes read from clients.
+    pub(crate) fn net_input_bytes(&self) -> u64 {
+        self.net_input_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes written to clients.
+    pub(crate) fn net_output_bytes(&self) -> u64 {
+        self.net_output_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Record a command that took `elapsed` to execute in the latency histogram.
+    pub(crate) fn observe_latency(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        let bucket = LATENCY_BUCKETS.partition_point(|&bound| bound < us);
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_sum.fetch_add(us, Ordering::Relaxed);
+    }
+
+    /// Reset every metric to zero, with `CONFIG RESETSTAT`.
+    pub(crate) fn reset(&self) {
+        self.net_input_bytes.store(0, Ordering::Relaxed);
+        self.net_output_bytes.store(0, Ordering::Relaxed);
+        for bucket in &self.latency_buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.latency_sum.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Serve the metrics of the server on `listener` until the server shuts down. `GET /metrics`
+/// is answered with the metrics in the Prometheus text format, any other path with `404`.
+///
+/// Each request is answered on its own connection, which is closed after the response.
+pub(crate) async fn serve(listener: TcpListener, server: Weak<ServerState>) {
+    if let Ok(addr) = listener.local_addr() {
+        info!(%addr, "Serving metrics");
+    }
+
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                warn!(%err, "Failed to accept a metrics connection");
+                continue;
+            }
+        };
+
+        if server.strong_count() == 0 {
+            return;
+        }
+
+        let server = server.clone();
+        task::spawn("metrics-request", async move {
+            if let Err(err) = respond(socket, server).await {
+                debug!(%err, "Failed to answer a metrics request");
+            }
+        });
+    }
+}
+
+/// Read the request of a scraper on `socket` and write the response.
+async fn respond(mut socket: TcpStream, server: Weak<ServerState>) -> std::io::Result<()> {
+    let mut request = Vec::with_capacity(1024);
+    let head = time::timeout(REQUEST_TIMEOUT, async {
+        loop {
+            if request.windows(4).any(|window| window == b"\r\n\r\n") {
+                return Ok::<_, std::io::Error>(true);
+            }
+            if request.len() >= MAX_REQUEST_SIZE {
+                return Ok(false);
+            }
+            if socket.read_buf(&mut request).await? == 0 {
+                return Ok(false);
+            }
+        }
+    });
+    let complete = match head.await {
+        Ok(res) => res?,
+        Err(_) => return Err(std::io::ErrorKind::TimedOut.into()),
+    };
+    if !complete {
+        return write_response(&mut socket, "400 Bad Request", "", false).await;
+    }
+
+    // Only the request line matters, `GET /metrics HTTP/1.1`. Query strings are ignored.
+    let line = request
+        .split(|&byte| byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut parts = line.split(|&byte| byte == b' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .unwrap_or_default()
+        .split(|&byte| byte == b'?')
+        .next()
+        .unwrap_or_default();
+
+    let head_only = method == b"HEAD";
+    if method != b"GET" && !head_only {
+        return write_response(&mut socket, "405 Method Not Allowed", "", false).await;
+    }
+    if path != b"/metrics" {
+        return write_response(&mut socket, "404 Not Found", "", head_only).await;
+    }
+
+    let Some(server) = server.upgrade() else {
+        return write_response(&mut socket, "503 Service Unavailable", "", head_only).await;
+    };
+    let body = render(&server);
+    drop(server);
+    write_response(&mut socket, "200 OK", &body, head_only).await
+}
+
+/// Write a response with `status` and `body`, then close the connection. The body is left out
+/// for `HEAD` requests, its length is still given.
+async fn write_response(
+    socket: &mut TcpStream,
+    status: &str,
+    body: &str,
+    head_only: bool,
+) -> std::io::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    );
+    if !head_only {
+        response.push_str(body);
+    }
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+/// Metrics of `server` and its dataset, in the Prometheus text format.
+pub(crate) fn render(server: &ServerState) -> String {
+    let stats = server.stats();
+    let metrics = &server.metrics;
+    let mut out = String::new();
+
+    metric(
+        &mut out,
+        "walrus_uptime_seconds",
+        "gauge",
+        "Time since the server started.",
+        server.started.elapsed().as_secs(),
+    );
+    metric(
+        &mut out,
+        "walrus_connected_clients",
+        "gauge",
+        "Number of client connections.",
+        server.clients.len(),
+    );
+    metric(
+        &mut out,
+        "walrus_rejected_connections_total",
+        "counter",
+        "Number of connections refused because of maxclients or maxclients-per-ip.",
+        server.clients.rejected(),
+    );
+    metric(
+        &mut out,
+        "walrus_keys",
+        "gauge",
+        "Number of keys in the dataset.",
+        server.key_count(None),
+    );
+    metric(
+        &mut out,
+        "walrus_keys_with_expiration",
+        "gauge",
+        "Number of keys with an expiration.",
+        server.expires_count(),
+    );
+    metric(
+        &mut out,
+        "walrus_used_memory_dataset_bytes",
+        "gauge",
+        "Approximate memory used by the keys and values.",
+        server.used_memory(),
+    );
+    metric(
+        &mut out,
+        "walrus_expired_keys_total",
+        "counter",
+        "Number of expired keys removed.",
+        stats.expired_keys,
+    );
+    metric(
+        &mut out,
+        "walrus_evicted_keys_total",
+        "counter",
+        "Number of keys evicted to stay under maxmemory.",
+        stats.evicted_keys,
+    );
+    metric(
+        &mut out,
+        "walrus_keyspace_hits_total",
+        "counter",
+        "Number of reads of existing keys.",
+        stats.keyspace_hits,
+    );
+    metric(
+        &mut out,
+        "walrus_keyspace_misses_total",
+        "counter",
+        "Number of reads of missing keys.",
+        stats.keyspace_misses,
+    );
+    metric(
+        &mut out,
+        "walrus_net_input_bytes_total",
+        "counter",
+        "Number of bytes read from clients.",
+        metrics.net_input_bytes(),
+    );
+    metric(
+        &mut out,
+        "walrus_net_output_bytes_total",
+        "counter",
+        "Number of bytes written to clients.",
+        metrics.net_output_bytes(),
+    );
+
+    // Writing to a `String` never fails.
+    let _ = writeln!(
+        out,
+        "# HELP walrus_commands_total Number of commands executed, by command.\n\
+         # TYPE walrus_commands_total counter"
+    );
+    for (name, command) in &stats.commands {
+        let calls = command.calls;
+        let _ = writeln!(out, "walrus_commands_total{{cmd=\"{name}\"}} {calls}");
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP walrus_command_duration_seconds Execution time of commands, blocking commands \
+         excluded.\n\
+         # TYPE walrus_command_duration_seconds histogram"
+    );
+    let mut count = 0;
+    for (i, bucket) in metrics.latency_buckets.iter().enumerate() {
+        count += bucket.load(Ordering::Relaxed);
+        match LATENCY_BUCKETS.get(i) {
+            Some(&bound) => {
+                let le = bound as f64 / 1_000_000.0;
+                let _ = writeln!(
+                    out,
+                    "walrus_command_duration_seconds_bucket{{le=\"{le}\"}} {count}"
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    out,
+                    "walrus_command_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+                );
+            }
+        }
+    }
+    let sum = metrics.latency_sum.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "walrus_command_duration_seconds_sum {sum}");
+    let _ = writeln!(out, "walrus_command_duration_seconds_count {count}");
+
+    out
+}
+
+/// Write the metric `name` of type `kind` with a single `value`, after its description.
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
@@ -0,0 +1,121 @@
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::config::ClientClass;
+use crate::frame::Frame;
+use crate::registry::PushSender;
+
+/// Connections that issued `MONITOR`, every command processed by the server is streamed to them.
+pub(crate) struct Monitors {
+    /// Map of connection id to the channel delivering lines to the connection.
+    clients: DashMap<u64, PushSender>,
+    /// Number of monitoring connections, skips formatting commands when zero.
+    count: AtomicUsize,
+}
+
+impl Monitors {
+    /// Create an empty set of monitors.
+    pub(crate) fn new() -> Monitors {
+        Monitors {
+            clients: DashMap::new(),
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Stream commands to connection `id` through `sender`.
+    pub(crate) fn add(&self, id: u64, sender: PushSender) {
+        // Monitors only receive pushed commands, their output is limited as such.
+        sender.set_class(ClientClass::Pubsub);
+        if self.clients.insert(id, sender).is_none() {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stop streaming commands to connection `id`.
+    pub(crate) fn remove(&self, id: u64) {
+        if self.clients.remove(&id).is_some() {
+            self.count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `true` if connection `id` is monitoring.
+    pub(crate) fn contains(&self, id: u64) -> bool {
+        self.clients.contains_key(&id)
+    }
+
+    /// Returns `true` if at least one connection is monitoring.
+    pub(crate) fn is_active(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    /// Send the command `frame` received from `addr` to every monitoring connection, except the
+    /// connection `origin` that sent it.
+    ///
+    /// Each command is sent as a simple string such as
+    /// `1700000000.123456 [0 127.0.0.1:50000] "set" "key" "value"`.
+    pub(crate) fn feed(&self, origin: u64, db: usize, addr: Option<SocketAddr>, frame: &Frame) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut line = String::new();
+        // Writing to a `String` never fails.
+        let _ = write!(line, "{}.{:06} [{db} ", now.as_secs(), now.subsec_micros());
+        match addr {
+            Some(addr) => {
+                let _ = write!(line, "{addr}]");
+            }
+            None => line.push_str("unknown]"),
+        }
+
+        if let Frame::Array(args) = frame {
+            for arg in args {
+                line.push(' ');
+                match arg {
+                    Frame::Bulk(bytes) | Frame::Simple(bytes) => quote(&mut line, bytes),
+                    other => quote(&mut line, other.to_string().as_bytes()),
+                }
+            }
+        }
+
+        let line = Bytes::from(line);
+
+        for client in self.clients.iter() {
+            if *client.key() == origin {
+                continue;
+            }
+
+            client.send(Frame::Simple(line.clone()));
+        }
+    }
+}
+
+/// Append `bytes` to `out` as a double quoted string, escaping quotes, backslashes and non
+/// printable bytes so the line never contains a newline.
+pub(crate) fn quote(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+
+    for &byte in bytes {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\x{byte:02x}");
+            }
+        }
+    }
+
+    out.push('"');
+}
@@ -0,0 +1,106 @@
+//! Export of the commands executed by the server as OpenTelemetry spans, with the `otel`
+//! feature.
+//!
+//! Each command runs in a `command` span with its name, its first key, its latency and whether
+//! it failed. Clients continue their own traces by sending the W3C trace context of their next
+//! command with `CLIENT TRACEPARENT`. The spans are exported with a tracing layer:
+//!
+//! ```no_run
+//! # async fn init() -> Result<(), Box<dyn std::error::Error>> {
+//! use tracing_subscriber::prelude::*;
+//!
+//! let provider = walrus::otel::tracer_provider("http://localhost:4317")?;
+//! tracing_subscriber::registry()
+//!     .with(walrus::otel::layer(&provider))
+//!     .init();
+//! // ... run the server, then flush the pending spans.
+//! provider.shutdown()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use std::{collections::HashMap, time::Duration};
+use tracing::{Span, Subscriber, field, info_span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{Layer, filter, registry::LookupSpan};
+
+use crate::connection::TraceContext;
+
+/// Target of the spans of commands, the only spans exported by `layer`.
+const TARGET: &str = "walrus::otel";
+
+/// Tracer provider exporting spans in batches over OTLP with gRPC to the collector at
+/// `endpoint`, such as `http://localhost:4317`. Must be called within a Tokio runtime.
+///
+/// Spans still pending are lost unless `SdkTracerProvider::shutdown` is called before exiting.
+pub fn tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("walrus").build())
+        .build())
+}
+
+/// Tracing layer exporting the spans of commands with a tracer of `provider`. Other spans and
+/// events, such as those of connections, are left to the other layers.
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("walrus"))
+        .with_filter(filter::filter_fn(|meta| meta.target() == TARGET))
+}
+
+/// Span of the execution of the command `name` on `keys`. The span continues the trace of
+/// `context` if the client sent one, otherwise it starts a new trace.
+pub(crate) fn command_span(name: &str, keys: &[Bytes], context: Option<TraceContext>) -> Span {
+    let span = info_span!(
+        target: TARGET,
+        "command",
+        otel.name = name,
+        otel.kind = "server",
+        otel.status_code = field::Empty,
+        db.system.name = "walrus",
+        db.namespace = "0",
+        db.operation.name = name,
+        db.key = field::Empty,
+        elapsed_us = field::Empty,
+    );
+    if let Some(key) = keys.first() {
+        span.record("db.key", String::from_utf8_lossy(key).as_ref());
+    }
+
+    if let Some(context) = context {
+        let mut carrier = HashMap::new();
+        carrier.insert(
+            "traceparent".to_string(),
+            String::from_utf8_lossy(&context.traceparent).into_owned(),
+        );
+        if let Some(tracestate) = &context.tracestate {
+            carrier.insert(
+                "tracestate".to_string(),
+                String::from_utf8_lossy(tracestate).into_owned(),
+            );
+        }
+        // Fails only if no layer exports the span.
+        let _ = span.set_parent(TraceContextPropagator::new().extract(&carrier));
+    }
+
+    span
+}
+
+/// Record the outcome of the command of `span`, executed in `elapsed`. Commands replying with
+/// an error have the `ERROR` status.
+pub(crate) fn record_outcome(span: &Span, elapsed: Duration, failed: bool) {
+    span.record("elapsed_us", elapsed.as_micros() as u64);
+    span.record("otel.status_code", if failed { "ERROR" } else { "OK" });
+}
@@ -81,14 +81,14 @@ impl Parse {
                     // If this is the last element of the blpop command then it must be the timeout.
                     if self.peek().is_err() {
                         // parse int or float from bytes.
-                        let timeout = optimize_storage(Bytes::from(data));
+                        let timeout = optimize_storage(data);
                         match timeout {
                             Data::Double(t) => return Ok((result, t)),
                             Data::Integer(t) => return Ok((result, t as f64)),
                             _ => return Err("protocol error; last item must be the timeout".into()),
                         }
                     }
-                    result.push(Bytes::from(data));
+                    result.push(data);
                 }
                 Frame::Integer(data) => {
                     if result.is_empty() {
@@ -137,9 +137,15 @@ impl Parse {
                 Frame::Null => {
                     return Err("protocol error; null not allowed in BLPOP".into());
                 }
-                Frame::Array(_) => {
+                Frame::Array(_) | Frame::Map(_) | Frame::Set(_) | Frame::Push(_) => {
                     return Err("protocol error; array not allowed in BLPOP".into());
                 }
+                Frame::Boolean(_)
+                | Frame::BigNumber(_)
+                | Frame::Verbatim { .. }
+                | Frame::Attribute { .. } => {
+                    return Err("protocol error; unexpected frame type in BLPOP".into());
+                }
             }
         }
 
@@ -184,7 +190,7 @@ impl Parse {
 pub(crate) fn extract_f64(bytes: &[u8]) -> Option<f64> {
     use fast_float;
     if !check_float_trailing_zeros(bytes) {
-        return fast_float::parse::<f64, _>(bytes.as_ref()).ok();
+        return fast_float::parse::<f64, _>(bytes).ok();
     }
     None
 }
@@ -194,9 +200,9 @@ pub(crate) fn extract_f64(bytes: &[u8]) -> Option<f64> {
 /// them doesn't change the value and hence false is returned.
 fn check_float_trailing_zeros(bytes: &[u8]) -> bool {
     let mut number_of_trailing_zeros = 0;
-    let mut rev_iter = bytes.iter().rev();
+    let rev_iter = bytes.iter().rev();
 
-    while let Some(&byte) = rev_iter.next() {
+    for &byte in rev_iter {
         if byte == b'0' {
             number_of_trailing_zeros += 1;
         } else if byte == b'.' {
@@ -243,7 +249,7 @@ pub(crate) fn extract_i64_strict(bytes: &[u8]) -> Option<i64> {
 
     for &byte in &bytes[start_idx..] {
         // Ensure that the byte is an ASCII digit.
-        if byte >= b'0' && byte <= b'9' {
+        if byte.is_ascii_digit() {
             let digit = (byte - b'0') as i64;
 
             // If leading zeroes are present then turning into integer will change the actual value.
@@ -252,20 +258,22 @@ pub(crate) fn extract_i64_strict(bytes: &[u8]) -> Option<i64> {
                 return None;
             }
 
-            // Multiply the current result by 10 and add the new digit.
-            // Using checked_mul and checked_add to avoid overflow.
-            result = result.checked_mul(10)?.checked_add(digit)?;
+            // Multiply the current result by 10 and add the new digit, subtract it for negative
+            // numbers so `i64::MIN` can be represented.
+            // Using checked operations to avoid overflow.
+            result = result.checked_mul(10)?;
+            result = if is_negative {
+                result.checked_sub(digit)?
+            } else {
+                result.checked_add(digit)?
+            };
         } else {
             // If the byte is not a digit, return None.
             return None;
         }
     }
 
-    if is_negative {
-        Some(-result)
-    } else {
-        Some(result)
-    }
+    Some(result)
 }
 
 /// Extracts i64 from bytes.
@@ -294,23 +302,25 @@ pub(crate) fn extract_i64(bytes: &[u8]) -> Option<i64> {
 
     for &byte in &bytes[start_idx..] {
         // Ensure that the byte is an ASCII digit.
-        if byte >= b'0' && byte <= b'9' {
+        if byte.is_ascii_digit() {
             let digit = (byte - b'0') as i64;
 
-            // Multiply the current result by 10 and add the new digit.
-            // Using checked_mul and checked_add to avoid overflow.
-            result = result.checked_mul(10)?.checked_add(digit)?;
+            // Multiply the current result by 10 and add the new digit, subtract it for negative
+            // numbers so `i64::MIN` can be represented.
+            // Using checked operations to avoid overflow.
+            result = result.checked_mul(10)?;
+            result = if is_negative {
+                result.checked_sub(digit)?
+            } else {
+                result.checked_add(digit)?
+            };
         } else {
             // If the byte is not a digit, return None.
             return None;
         }
     }
 
-    if is_negative {
-        Some(-result)
-    } else {
-        Some(result)
-    }
+    Some(result)
 }
 
 impl From<String> for ParseError {
@@ -0,0 +1,98 @@
+use tokio::{
+    sync::watch,
+    time::{self, Instant},
+};
+
+/// Commands suspended by `CLIENT PAUSE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PauseMode {
+    /// Only commands that modify the dataset are suspended.
+    Write,
+    /// Every command is suspended.
+    All,
+}
+
+/// A pause in effect until `until`.
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+    mode: PauseMode,
+}
+
+/// Server wide gate suspending the processing of commands while a pause is in effect.
+///
+/// Connection handlers wait on the gate before executing each command. The gate opens when the
+/// pause expires or when it is lifted with `CLIENT UNPAUSE`.
+pub(crate) struct PauseGate {
+    /// Current pause if any. `watch` is used so waiting handlers are woken up when the pause is
+    /// changed or lifted.
+    state: watch::Sender<Option<Pause>>,
+}
+
+impl PauseGate {
+    /// Create an open gate.
+    pub(crate) fn new() -> PauseGate {
+        PauseGate {
+            state: watch::Sender::new(None),
+        }
+    }
+
+    /// Pause commands matching `mode` until `until`.
+    ///
+    /// If a pause is already in effect the longer of the two durations and the more
+    /// restrictive of the two modes is kept.
+    pub(crate) fn pause(&self, until: Instant, mode: PauseMode) {
+        self.state.send_modify(|state| {
+            let pause = match state.take() {
+                Some(current) if current.until > Instant::now() => Pause {
+                    until: current.until.max(until),
+                    mode: if current.mode == PauseMode::All {
+                        PauseMode::All
+                    } else {
+                        mode
+                    },
+                },
+                _ => Pause { until, mode },
+            };
+
+            *state = Some(pause);
+        });
+    }
+
+    /// Lift the current pause, waking up all waiting handlers.
+    pub(crate) fn unpause(&self) {
+        self.state.send_replace(None);
+    }
+
+    /// Wait until a command is allowed to execute.
+    ///
+    /// `is_write` tells if the command modifies the dataset. Returns immediately if no pause is
+    /// in effect for the command.
+    pub(crate) async fn wait(&self, is_write: bool) {
+        // Fast path, no pause in effect.
+        if self.state.borrow().is_none() {
+            return;
+        }
+
+        let mut rx = self.state.subscribe();
+
+        loop {
+            let pause = *rx.borrow_and_update();
+
+            let until = match pause {
+                Some(pause) if pause.mode == PauseMode::All || is_write => pause.until,
+                _ => return,
+            };
+
+            if until <= Instant::now() {
+                return;
+            }
+
+            tokio::select! {
+                _ = time::sleep_until(until) => return,
+                // Pause was changed or lifted, check again.
+                _ = rx.changed() => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,766 @@
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::{
+    config::SnapshotCompression,
+    crc64::crc64,
+    db::{Data, Db, Snapshot, Value},
+    errors::WalrusError,
+    task,
+};
+
+pub(crate) mod aof;
+pub(crate) mod loading;
+pub(crate) mod rdb;
+
+use loading::Loading;
+
+/// First bytes of every snapshot file.
+const MAGIC: &[u8; 6] = b"WALRUS";
+/// Version of the snapshot format, bumped on incompatible changes. Snapshots of version 2,
+/// which only hold database 0, are still loaded.
+const VERSION: u8 = 3;
+
+/// Opcode preceding the entries of a database other than 0, followed by its index.
+const OPCODE_SELECT_DB: u8 = 0xFD;
+/// Opcode preceding an entry with a time to live, followed by the unix time in milliseconds at
+/// which the entry expires.
+const OPCODE_EXPIRE_MS: u8 = 0xFC;
+/// Opcode starting a section of entries.
+const OPCODE_SECTION: u8 = 0xFE;
+/// Opcode marking the end of the sections, followed by the number of keys and a checksum.
+const OPCODE_EOF: u8 = 0xFF;
+
+/// Size of the entries of a section before compression. Entries never span sections, so a
+/// section holding a large value is larger.
+const SECTION_SIZE: usize = 1024 * 1024;
+/// Size of the header of a section: opcode, raw length, stored length and checksum.
+const SECTION_HEADER_SIZE: usize = 1 + 4 + 4 + 8;
+
+/// Type tags of values.
+const TYPE_BYTES: u8 = 0;
+const TYPE_STRING: u8 = 1;
+const TYPE_INTEGER: u8 = 2;
+const TYPE_DOUBLE: u8 = 3;
+const TYPE_LIST: u8 = 4;
+
+/// Snapshot state of the server: whether a background save is running, its progress and the
+/// outcome of the last save, as reported by `INFO persistence`.
+pub(crate) struct Persistence {
+    status: Arc<Status>,
+}
+
+struct Status {
+    /// `true` while a save, foreground or background, is running.
+    in_progress: AtomicBool,
+    /// Number of keys in the snapshot being written, and number written so far.
+    keys_total: AtomicUsize,
+    keys_saved: AtomicUsize,
+    /// Unix time at which the running background save started, in seconds.
+    bgsave_started: AtomicU64,
+    /// Unix time of the last successful save, in seconds.
+    last_save: AtomicU64,
+    /// Outcome of the last background save.
+    last_bgsave_ok: AtomicBool,
+    /// Duration of the last background save in seconds, -1 if none ran yet.
+    last_bgsave_secs: AtomicI64,
+    /// Write commands executed since the last successful save.
+    changes: AtomicU64,
+}
+
+impl Persistence {
+    pub(crate) fn new() -> Persistence {
+        Persistence {
+            status: Arc::new(Status {
+                in_progress: AtomicBool::new(false),
+                keys_total: AtomicUsize::new(0),
+                keys_saved: AtomicUsize::new(0),
+                bgsave_started: AtomicU64::new(0),
+                // Like Redis, the dataset is considered saved when the server starts.
+                last_save: AtomicU64::new(unix_ms() / 1000),
+                last_bgsave_ok: AtomicBool::new(true),
+                last_bgsave_secs: AtomicI64::new(-1),
+                changes: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Record a write command, counted until the next successful save.
+    pub(crate) fn changed(&self) {
+        self.status.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of write commands executed since the last successful save.
+    pub(crate) fn changes(&self) -> u64 {
+        self.status.changes.load(Ordering::Relaxed)
+    }
+
+    /// Write a snapshot of `databases` to `path`, blocking until it is on disk.
+    pub(crate) fn save(
+        &self,
+        databases: &[Db],
+        path: &Path,
+        compression: SnapshotCompression,
+    ) -> Result<(), WalrusError> {
+        self.status.start()?;
+
+        let result = self.status.save(databases, path, compression);
+        self.status.in_progress.store(false, Ordering::Release);
+
+        result
+    }
+
+    /// Write a snapshot of `databases` to `path` on a blocking thread, returns as soon as the
+    /// save started. The outcome is reported by `INFO persistence`.
+    pub(crate) fn bgsave(
+        &self,
+        databases: &[Db],
+        path: PathBuf,
+        compression: SnapshotCompression,
+    ) -> Result<(), WalrusError> {
+        self.status.start()?;
+
+        let status = self.status.clone();
+        let databases = databases.to_vec();
+        status
+            .bgsave_started
+            .store(unix_ms() / 1000, Ordering::Relaxed);
+
+        task::spawn_blocking("bgsave", move || {
+            let start = Instant::now();
+            let result = status.save(&databases, &path, compression);
+
+            if let Err(err) = &result {
+                warn!(%err, "Background save failed");
+            }
+
+            status
+                .last_bgsave_ok
+                .store(result.is_ok(), Ordering::Relaxed);
+            status
+                .last_bgsave_secs
+                .store(start.elapsed().as_secs() as i64, Ordering::Relaxed);
+            status.in_progress.store(false, Ordering::Release);
+        });
+
+        Ok(())
+    }
+
+    /// Fields of the `persistence` section of `INFO`.
+    pub(crate) fn info(&self) -> Vec<(&'static str, String)> {
+        let status = &self.status;
+        let in_progress = status.in_progress.load(Ordering::Acquire);
+        let current_secs = if in_progress {
+            (unix_ms() / 1000).saturating_sub(status.bgsave_started.load(Ordering::Relaxed)) as i64
+        } else {
+            -1
+        };
+        let last_bgsave_status = if status.last_bgsave_ok.load(Ordering::Relaxed) {
+            "ok"
+        } else {
+            "err"
+        };
+
+        vec![
+            (
+                "rdb_changes_since_last_save",
+                status.changes.load(Ordering::Relaxed).to_string(),
+            ),
+            ("rdb_bgsave_in_progress", (in_progress as u8).to_string()),
+            (
+                "rdb_last_save_time",
+                status.last_save.load(Ordering::Relaxed).to_string(),
+            ),
+            ("rdb_last_bgsave_status", last_bgsave_status.to_string()),
+            (
+                "rdb_last_bgsave_time_sec",
+                status.last_bgsave_secs.load(Ordering::Relaxed).to_string(),
+            ),
+            ("rdb_current_bgsave_time_sec", current_secs.to_string()),
+            (
+                "rdb_saving_keys_total",
+                status.keys_total.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "rdb_saving_keys_saved",
+                status.keys_saved.load(Ordering::Relaxed).to_string(),
+            ),
+        ]
+    }
+}
+
+impl Status {
+    /// Mark a save as running, fails if one already is.
+    fn start(&self) -> Result<(), WalrusError> {
+        if self
+            .in_progress
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err("Background save already in progress".into());
+        }
+
+        self.keys_total.store(0, Ordering::Relaxed);
+        self.keys_saved.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Capture the dataset and write it to `path`, updating the progress as keys are written.
+    fn save(
+        &self,
+        databases: &[Db],
+        path: &Path,
+        compression: SnapshotCompression,
+    ) -> Result<(), WalrusError> {
+        // Writes from now on are not part of the snapshot.
+        let changes = self.changes.load(Ordering::Relaxed);
+
+        // Writes are not blocked while the snapshot is serialized.
+        let snapshots: Vec<_> = databases.iter().map(Db::snapshot).collect();
+        self.keys_total
+            .store(snapshots.iter().map(Snapshot::len).sum(), Ordering::Relaxed);
+
+        save(&snapshots, path, compression, &self.keys_saved)
+            .map_err(|err| format!("failed to save snapshot, {err}"))?;
+
+        self.changes.fetch_sub(changes, Ordering::Relaxed);
+        self.last_save.store(unix_ms() / 1000, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Write the `snapshots` of the databases to `path`, counting the keys written in `saved`.
+///
+/// The snapshot is written to a temporary file in the same directory, which is then renamed
+/// over `path` so a crash while saving never leaves a truncated snapshot behind.
+///
+/// Format, integers are little endian:
+///
+/// ```text
+/// "WALRUS" version:u8 compression:u8 databases:u32
+/// ( 0xFE raw_len:u32 stored_len:u32 crc64:u64 payload )*
+/// 0xFF keys:u64 crc64:u64
+/// ```
+///
+/// Entries are split in sections of about `SECTION_SIZE` bytes, each optionally compressed and
+/// checksummed on its own, so a damaged section is reported precisely. The payload of a section
+/// decompresses to `raw_len` bytes of entries:
+///
+/// ```text
+/// ( [0xFD db:u32] [0xFC expires_at_ms:u64] type:u8 key value )*
+/// ```
+///
+/// Entries are in the order of their databases, the entries of a database other than 0 are
+/// preceded by its index and may span several sections. The header gives the number of
+/// databases up to the last one holding keys, so a server with fewer databases refuses the
+/// snapshot before loading any key.
+/// Keys and byte values are a `u32` length followed by the bytes, integers and doubles are 8
+/// bytes, lists are a `u32` length followed by each element as a type and value. The checksum
+/// of a section covers its stored payload, the final checksum covers the header and the number
+/// of keys. Checksums are CRC-64/Jones.
+fn save(
+    snapshots: &[Snapshot],
+    path: &Path,
+    compression: SnapshotCompression,
+    saved: &AtomicUsize,
+) -> Result<(), WalrusError> {
+    let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+
+    let result = write_snapshot(snapshots, &temp, compression, saved)
+        .and_then(|_| Ok(fs::rename(&temp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+/// Load the snapshot at `path` into `databases`, returns the number of keys loaded.
+///
+/// A missing file is not an error, the server simply starts empty. Entries that expired while
+/// the server was down are skipped. Every checksum is verified before `databases` are touched,
+/// so a damaged file is never partially loaded. Progress is reported to `loading`.
+pub(crate) fn load(databases: &[Db], path: &Path, loading: &Loading) -> Result<usize, WalrusError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+
+    load_bytes(databases, &bytes, loading)
+}
+
+/// Load the snapshot `bytes` into `databases`, returns the number of keys loaded. Fails if
+/// it holds keys of databases out of range, before any key is loaded.
+pub(crate) fn load_bytes(
+    databases: &[Db],
+    bytes: &[u8],
+    loading: &Loading,
+) -> Result<usize, WalrusError> {
+    loading.start(bytes.len() as u64);
+
+    let mut reader = Reader::new(bytes);
+
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err("not a walrus snapshot".into());
+    }
+
+    let version = reader.u8()?;
+    if !(2..=VERSION).contains(&version) {
+        return Err(format!("unsupported snapshot version {version}").into());
+    }
+
+    let compression = match reader.u8()? {
+        0 => SnapshotCompression::No,
+        1 => SnapshotCompression::Lz4,
+        2 => SnapshotCompression::Zstd,
+        tag => return Err(format!("unknown snapshot compression {tag}").into()),
+    };
+    if !compression.is_supported() {
+        return Err(format!(
+            "the snapshot is compressed with {0}, walrus was built without the '{0}' feature",
+            compression.name()
+        )
+        .into());
+    }
+
+    // Checked before any key is loaded, the keys of a database out of range would be lost.
+    let used = if version >= 3 {
+        reader.u32()? as usize
+    } else {
+        1
+    };
+    if used > databases.len() {
+        return Err(format!(
+            "the snapshot holds keys of database {}, only {} databases are configured",
+            used - 1,
+            databases.len()
+        )
+        .into());
+    }
+
+    let header_len = reader.pos;
+    let mut sections = Vec::new();
+
+    loop {
+        let offset = reader.pos;
+
+        match reader.u8()? {
+            OPCODE_SECTION => {}
+            OPCODE_EOF => break,
+            opcode => {
+                return Err(
+                    format!("unknown opcode {opcode} at offset {offset} of snapshot").into(),
+                );
+            }
+        }
+
+        let raw_len = reader.u32()? as usize;
+        let stored_len = reader.u32()? as usize;
+        let checksum = reader.u64()?;
+        let payload = reader.take(stored_len)?;
+
+        if crc64(0, payload) != checksum {
+            return Err(format!(
+                "snapshot section {} at offset {offset} is corrupted, checksum mismatch",
+                sections.len()
+            )
+            .into());
+        }
+
+        sections.push((offset, raw_len, payload));
+    }
+
+    let trailer = reader.pos;
+    let keys = reader.u64()?;
+    let checksum = reader.u64()?;
+    let expected = crc64(
+        crc64(0, &bytes[..header_len]),
+        &bytes[trailer - 1..trailer + 8],
+    );
+    if checksum != expected {
+        return Err("snapshot header is corrupted, checksum mismatch".into());
+    }
+
+    if reader.pos != bytes.len() {
+        return Err("unexpected data after the end of the snapshot".into());
+    }
+
+    let now = unix_ms();
+    let mut read = 0;
+    let mut loaded = 0;
+    let mut db = &databases[0];
+
+    for (i, (offset, raw_len, payload)) in sections.into_iter().enumerate() {
+        let raw = decompress(compression, payload, raw_len).map_err(|err| {
+            format!("snapshot section {i} at offset {offset} can't be decompressed, {err}")
+        })?;
+        let mut reader = Reader::new(&raw);
+
+        while reader.pos < raw.len() {
+            let mut expires_at = None;
+            let mut tag = reader.u8()?;
+
+            if tag == OPCODE_SELECT_DB {
+                let index = reader.u32()? as usize;
+                db = databases.get(index).ok_or_else(|| {
+                    format!("snapshot section {i} at offset {offset} selects database {index}")
+                })?;
+                tag = reader.u8()?;
+            }
+
+            if tag == OPCODE_EXPIRE_MS {
+                expires_at = Some(reader.u64()?);
+                tag = reader.u8()?;
+            }
+
+            let key = Bytes::copy_from_slice(reader.bytes()?);
+            let value = reader.value(tag)?;
+            read += 1;
+
+            // Bytes of the file loaded, assuming the section compresses evenly.
+            let section_read = payload.len() * reader.pos / raw.len();
+            loading.progress((offset + SECTION_HEADER_SIZE + section_read) as u64, read);
+
+            match expires_at {
+                Some(when) if when <= now => {}
+                Some(when) => {
+                    db.set(&key, value, Some(Duration::from_millis(when - now)));
+                    loaded += 1;
+                }
+                None => {
+                    db.set(&key, value, None);
+                    loaded += 1;
+                }
+            }
+        }
+    }
+
+    if read != keys {
+        return Err(format!("snapshot holds {read} keys, {keys} expected").into());
+    }
+
+    Ok(loaded)
+}
+
+/// Serialize `snapshot` to a new file at `path`, synced to disk before returning.
+fn write_snapshot(
+    snapshots: &[Snapshot],
+    path: &Path,
+    compression: SnapshotCompression,
+    saved: &AtomicUsize,
+) -> Result<(), WalrusError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_entries(&mut out, snapshots, compression, saved)?;
+
+    let file = out.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Serialize the `snapshots` of the databases in memory, as sent to replicas.
+pub(crate) fn dump(
+    snapshots: &[Snapshot],
+    compression: SnapshotCompression,
+) -> Result<Vec<u8>, WalrusError> {
+    let mut out = Vec::new();
+    write_entries(&mut out, snapshots, compression, &AtomicUsize::new(0))?;
+    Ok(out)
+}
+
+/// Serialize the `snapshots` of the databases to `out`, counting the keys written in `saved`.
+fn write_entries(
+    out: &mut impl Write,
+    snapshots: &[Snapshot],
+    compression: SnapshotCompression,
+    saved: &AtomicUsize,
+) -> Result<(), WalrusError> {
+    let used = snapshots
+        .iter()
+        .rposition(|snapshot| !snapshot.is_empty())
+        .map_or(1, |index| index + 1) as u32;
+    let header = [
+        &MAGIC[..],
+        &[VERSION, compression as u8],
+        &used.to_le_bytes(),
+    ]
+    .concat();
+    out.write_all(&header)?;
+
+    let now = Instant::now();
+    let now_ms = unix_ms();
+    let mut section = Section::default();
+
+    let entries = snapshots.iter().enumerate().flat_map(|(index, snapshot)| {
+        snapshot
+            .entries()
+            .iter()
+            .enumerate()
+            .map(move |(i, entry)| (index, i == 0, entry))
+    });
+    for (index, first, (key, value, expires_at)) in entries {
+        if first && index > 0 {
+            section.write(&[OPCODE_SELECT_DB]);
+            section.write(&(index as u32).to_le_bytes());
+        }
+        if let Some(when) = expires_at {
+            // `Instant` is meaningless across restarts, store the wall clock time instead.
+            // Keys expiring while the snapshot is written are stored already expired and
+            // skipped on load.
+            let ttl = when.saturating_duration_since(now).as_millis() as u64;
+            section.write(&[OPCODE_EXPIRE_MS]);
+            section.write(&(now_ms + ttl).to_le_bytes());
+        }
+
+        section.write(&[stored_type_of(value)]);
+        section.write_bytes(key)?;
+        section.write_value(value)?;
+        saved.fetch_add(1, Ordering::Relaxed);
+
+        if section.buf.len() >= SECTION_SIZE {
+            section.flush(out, compression)?;
+        }
+    }
+
+    if !section.buf.is_empty() {
+        section.flush(out, compression)?;
+    }
+
+    let mut trailer = vec![OPCODE_EOF];
+    let keys: usize = snapshots.iter().map(Snapshot::len).sum();
+    trailer.extend_from_slice(&(keys as u64).to_le_bytes());
+    let checksum = crc64(crc64(0, &header), &trailer);
+    trailer.extend_from_slice(&checksum.to_le_bytes());
+    out.write_all(&trailer)?;
+
+    Ok(())
+}
+
+/// Compress the entries of a section.
+fn compress(compression: SnapshotCompression, raw: &[u8]) -> Result<Vec<u8>, WalrusError> {
+    match compression {
+        SnapshotCompression::No => Ok(raw.to_vec()),
+        #[cfg(feature = "lz4")]
+        SnapshotCompression::Lz4 => Ok(lz4_flex::block::compress(raw)),
+        #[cfg(feature = "zstd")]
+        SnapshotCompression::Zstd => Ok(zstd::bulk::compress(raw, 0)?),
+        #[allow(unreachable_patterns)]
+        _ => Err("compression not supported by this build".into()),
+    }
+}
+
+/// Decompress the `raw_len` bytes of entries of a section.
+fn decompress(
+    compression: SnapshotCompression,
+    payload: &[u8],
+    raw_len: usize,
+) -> Result<Vec<u8>, WalrusError> {
+    let raw = match compression {
+        SnapshotCompression::No => payload.to_vec(),
+        #[cfg(feature = "lz4")]
+        SnapshotCompression::Lz4 => lz4_flex::block::decompress(payload, raw_len)
+            .map_err(|err| WalrusError::Internal(err.to_string()))?,
+        #[cfg(feature = "zstd")]
+        SnapshotCompression::Zstd => zstd::bulk::decompress(payload, raw_len)?,
+        #[allow(unreachable_patterns)]
+        _ => return Err("compression not supported by this build".into()),
+    };
+
+    if raw.len() != raw_len {
+        return Err(format!("{} bytes decompressed, {raw_len} expected", raw.len()).into());
+    }
+
+    Ok(raw)
+}
+
+/// Type tag of `data`.
+fn type_of(data: &Data) -> u8 {
+    match data {
+        Data::Bytes(_) => TYPE_BYTES,
+        Data::String(_) => TYPE_STRING,
+        Data::Integer(_) => TYPE_INTEGER,
+        Data::Double(_) => TYPE_DOUBLE,
+        Data::Array(_) => TYPE_LIST,
+    }
+}
+
+/// Type tag of the stored `value`, the one of the `Data` it is returned as.
+fn stored_type_of(value: &Value) -> u8 {
+    match value {
+        Value::Bytes(_) | Value::Inline(_) => TYPE_BYTES,
+        Value::String(_) => TYPE_STRING,
+        Value::Integer(_) => TYPE_INTEGER,
+        Value::Double(_) => TYPE_DOUBLE,
+        Value::List(_) => TYPE_LIST,
+    }
+}
+
+/// Current unix time in milliseconds.
+pub(crate) fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Entries of the section being written.
+#[derive(Default)]
+struct Section {
+    buf: Vec<u8>,
+}
+
+impl Section {
+    fn write(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Write `bytes` prefixed with their length.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), WalrusError> {
+        let len = u32::try_from(bytes.len()).map_err(|_| "value too large for a snapshot")?;
+        self.write(&len.to_le_bytes());
+        self.write(bytes);
+        Ok(())
+    }
+
+    /// Write the stored `value` like the `Data` it is returned as, its type tag is written by
+    /// the caller.
+    fn write_value(&mut self, value: &Value) -> Result<(), WalrusError> {
+        match value {
+            Value::Inline(inline) => self.write_bytes(inline.as_bytes()),
+            Value::List(list) => {
+                let len = u32::try_from(list.len()).map_err(|_| "list too large for a snapshot")?;
+                self.write(&len.to_le_bytes());
+
+                for element in list.iter() {
+                    self.write(&[type_of(&element)]);
+                    self.write_data(&element)?;
+                }
+                Ok(())
+            }
+            value => self.write_data(&value.exported()),
+        }
+    }
+
+    /// Write `data`, its type tag is written by the caller.
+    fn write_data(&mut self, data: &Data) -> Result<(), WalrusError> {
+        match data {
+            Data::Bytes(bytes) | Data::String(bytes) => self.write_bytes(bytes)?,
+            Data::Integer(int) => self.write(&int.to_le_bytes()),
+            Data::Double(double) => self.write(&double.to_le_bytes()),
+            Data::Array(list) => {
+                let len = u32::try_from(list.len()).map_err(|_| "list too large for a snapshot")?;
+                self.write(&len.to_le_bytes());
+
+                for element in list {
+                    self.write(&[type_of(element)]);
+                    self.write_data(element)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compress and write the section to `out`, then start a new one.
+    fn flush(
+        &mut self,
+        out: &mut impl Write,
+        compression: SnapshotCompression,
+    ) -> Result<(), WalrusError> {
+        let payload = compress(compression, &self.buf)?;
+        let raw_len = u32::try_from(self.buf.len()).map_err(|_| "section too large")?;
+        let stored_len = u32::try_from(payload.len()).map_err(|_| "section too large")?;
+
+        let mut header = [0; SECTION_HEADER_SIZE];
+        header[0] = OPCODE_SECTION;
+        header[1..5].copy_from_slice(&raw_len.to_le_bytes());
+        header[5..9].copy_from_slice(&stored_len.to_le_bytes());
+        header[9..].copy_from_slice(&crc64(0, &payload).to_le_bytes());
+
+        out.write_all(&header)?;
+        out.write_all(&payload)?;
+        self.buf.clear();
+
+        Ok(())
+    }
+}
+
+/// Cursor over the bytes of a snapshot file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    /// Take the next `len` bytes, fails if the input ends before.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WalrusError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("unexpected end of snapshot, the file is truncated")?;
+
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, WalrusError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, WalrusError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, WalrusError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Bytes prefixed with their length.
+    fn bytes(&mut self) -> Result<&'a [u8], WalrusError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Read a value of type `tag`. Values are copied so they don't keep the file in memory.
+    fn value(&mut self, tag: u8) -> Result<Data, WalrusError> {
+        match tag {
+            TYPE_BYTES => Ok(Data::Bytes(Bytes::copy_from_slice(self.bytes()?))),
+            TYPE_STRING => Ok(Data::String(Bytes::copy_from_slice(self.bytes()?))),
+            TYPE_INTEGER => Ok(Data::Integer(self.u64()? as i64)),
+            TYPE_DOUBLE => Ok(Data::Double(f64::from_bits(self.u64()?))),
+            TYPE_LIST => {
+                let len = self.u32()? as usize;
+                // Don't trust the length for the allocation, a corrupted file could claim
+                // billions of elements.
+                let mut list = VecDeque::with_capacity(len.min(1024));
+
+                for _ in 0..len {
+                    let tag = self.u8()?;
+                    list.push_back(self.value(tag)?);
+                }
+
+                Ok(Data::Array(list))
+            }
+            _ => Err(format!("unknown value type {tag} in snapshot").into()),
+        }
+    }
+}
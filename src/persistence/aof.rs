@@ -0,0 +1,668 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Cursor, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::{self, Instant, MissedTickBehavior},
+};
+use tracing::warn;
+
+use crate::{
+    Command, Connection,
+    cmd::{Renames, Select},
+    config::AppendFsync,
+    connection::Protocol,
+    db::{self, Change, Data, Db, Op, Value},
+    errors::WalrusError,
+    frame::{self, Frame},
+    parse::{Parse, extract_i64},
+    server::ServerState,
+    task,
+};
+
+use super::unix_ms;
+
+/// Interval between syncs with the `everysec` policy.
+const FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Append only file, the changes made by every write are appended to it as commands in the
+/// RESP format, see `change_frame`, and replayed on startup to reconstruct the dataset.
+///
+/// Commands are appended to a queue, written to the file by the task of `write_task` so writes
+/// never wait on the disk while their key is locked.
+pub(crate) struct Aof {
+    /// The file commands are appended to, `None` until opened. The writer and the background
+    /// sync only clone the handle, reopening the file never waits for them.
+    file: Mutex<Option<Arc<File>>>,
+    /// `true` once the file is opened, checked without locking on every command.
+    enabled: AtomicBool,
+    /// Commands appended and not written yet, with their sequence number.
+    queue: mpsc::UnboundedSender<(u64, Bytes)>,
+    /// Receiving end of `queue`, taken by the writer task once it starts.
+    queued: Mutex<Option<mpsc::UnboundedReceiver<(u64, Bytes)>>>,
+    /// Sequence number of the last command appended.
+    appended: AtomicU64,
+    /// Sequence number of the last command appended when the file was opened, commands up to
+    /// it describe a dataset the file was rewritten without and are dropped.
+    opened_at: AtomicU64,
+    /// Sequence number of the last command written, synced too with the `always` policy.
+    written: watch::Sender<u64>,
+    /// `false` if the last write failed.
+    last_write_ok: AtomicBool,
+    /// Bytes written but not synced yet by the background task.
+    pending_bytes: AtomicU64,
+    /// Unix time in milliseconds of the oldest write not synced yet, 0 if none.
+    oldest_pending: AtomicU64,
+    /// Duration of the last sync by the background task, in microseconds.
+    last_fsync_us: AtomicU64,
+    /// Number of background syncs that took longer than their interval, delaying the next.
+    delayed_fsyncs: AtomicU64,
+}
+
+impl Aof {
+    /// Create a closed append only file, commands aren't logged until `open` is called.
+    pub(crate) fn new() -> Aof {
+        let (queue, queued) = mpsc::unbounded_channel();
+        Aof {
+            file: Mutex::new(None),
+            enabled: AtomicBool::new(false),
+            queue,
+            queued: Mutex::new(Some(queued)),
+            appended: AtomicU64::new(0),
+            opened_at: AtomicU64::new(0),
+            written: watch::Sender::new(0),
+            last_write_ok: AtomicBool::new(true),
+            pending_bytes: AtomicU64::new(0),
+            oldest_pending: AtomicU64::new(0),
+            last_fsync_us: AtomicU64::new(0),
+            delayed_fsyncs: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` if write commands are logged.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Start appending commands to the file at `path`, creating it if needed. Commands appended
+    /// before and not written yet are dropped, the file describes the dataset as it is now.
+    pub(crate) fn open(&self, path: &Path) -> Result<(), WalrusError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        let mut current = self.file.lock().unwrap();
+        *current = Some(Arc::new(file));
+        let appended = self.appended.load(Ordering::Relaxed);
+        self.opened_at.store(appended, Ordering::Relaxed);
+        self.written
+            .send_modify(|written| *written = appended.max(*written));
+        drop(current);
+
+        self.enabled.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Queue the write commands `frames`, they are written together by the writer task.
+    ///
+    /// Relative expirations are logged as absolute unix times, so keys don't live longer when
+    /// the file is replayed later.
+    pub(crate) fn append(&self, frames: &[Frame]) {
+        let mut buf = BytesMut::new();
+        for frame in frames {
+            match frame {
+                Frame::Array(args) => encode_command(&mut buf, args),
+                other => other.write_to(&mut buf, Protocol::Resp3),
+            }
+        }
+
+        // Sequence numbers are taken and queued in the same order, the changes of a key are
+        // logged while it is locked.
+        let seq = self.appended.fetch_add(1, Ordering::Relaxed) + 1;
+        // Only fails once the writer task is gone, when the server is dropped.
+        let _ = self.queue.send((seq, buf.freeze()));
+    }
+
+    /// Wait until the commands appended so far are written, and synced with the `always`
+    /// policy.
+    pub(crate) async fn wait_written(&self) {
+        let appended = self.appended.load(Ordering::Relaxed);
+        let mut written = self.written.subscribe();
+        // The sender is owned by `self`, it can't be dropped meanwhile.
+        let _ = written.wait_for(|written| *written >= appended).await;
+    }
+
+    /// Write the queued `commands` to the file, syncing it with the `always` policy or
+    /// recording them as pending for the background sync with `everysec`.
+    async fn write_queued(
+        &self,
+        commands: Vec<(u64, Bytes)>,
+        fsync: AppendFsync,
+    ) -> Result<(), WalrusError> {
+        let Some(&(last, _)) = commands.last() else {
+            return Ok(());
+        };
+
+        // The file and the commands it was opened after are read together, so a command is
+        // never written to a file rewritten without it.
+        let (file, opened_at) = {
+            let file = self.file.lock().unwrap();
+            (file.clone(), self.opened_at.load(Ordering::Relaxed))
+        };
+        let mut buf = Vec::new();
+        for (seq, command) in commands {
+            if seq > opened_at {
+                buf.extend_from_slice(&command);
+            }
+        }
+
+        let result = match file {
+            Some(file) if !buf.is_empty() => {
+                let len = buf.len();
+                let written = task::spawn_blocking("aof-write", move || {
+                    (&*file).write_all(&buf)?;
+                    if fsync == AppendFsync::Always {
+                        file.sync_data()?;
+                    }
+                    Ok::<_, std::io::Error>(())
+                })
+                .await
+                .map_err(|err| WalrusError::Internal(err.to_string()))?;
+
+                if written.is_ok() && fsync == AppendFsync::Everysec {
+                    self.pending_bytes.fetch_add(len as u64, Ordering::Relaxed);
+                    let _ = self.oldest_pending.compare_exchange(
+                        0,
+                        unix_ms(),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
+                }
+                written.map_err(WalrusError::from)
+            }
+            _ => Ok(()),
+        };
+
+        // Commands failing to be written are not retried, waiting for them must not hang.
+        self.last_write_ok.store(result.is_ok(), Ordering::Relaxed);
+        self.written
+            .send_modify(|written| *written = last.max(*written));
+        result
+    }
+
+    /// Sync the appends not synced yet, if any.
+    pub(crate) async fn fsync_pending(&self) -> Result<(), WalrusError> {
+        let Some(file) = self.file.lock().unwrap().clone() else {
+            return Ok(());
+        };
+
+        // Appends from now on may not be covered by this sync, they're left for the next one.
+        let oldest = self.oldest_pending.swap(0, Ordering::Relaxed);
+        let pending = self.pending_bytes.swap(0, Ordering::Relaxed);
+        if oldest == 0 {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let result = task::spawn_blocking("aof-fsync", move || file.sync_data())
+            .await
+            .map_err(|err| WalrusError::Internal(err.to_string()))?;
+        let elapsed = start.elapsed();
+
+        self.last_fsync_us
+            .store(elapsed.as_micros() as u64, Ordering::Relaxed);
+        if elapsed > FSYNC_INTERVAL {
+            self.delayed_fsyncs.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Err(err) = result {
+            // Still pending, retried on the next tick.
+            self.pending_bytes.fetch_add(pending, Ordering::Relaxed);
+            let _ = self.oldest_pending.compare_exchange(
+                0,
+                oldest,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    /// Forget the appends not synced yet, the operating system syncs them with the `no` policy.
+    fn clear_pending(&self) {
+        self.oldest_pending.store(0, Ordering::Relaxed);
+        self.pending_bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// Fields of the `persistence` section of `INFO`.
+    pub(crate) fn info(&self) -> Vec<(&'static str, String)> {
+        let oldest = self.oldest_pending.load(Ordering::Relaxed);
+        let lag = if oldest == 0 {
+            0
+        } else {
+            unix_ms().saturating_sub(oldest)
+        };
+        let last_write_status = if self.last_write_ok.load(Ordering::Relaxed) {
+            "ok"
+        } else {
+            "err"
+        };
+
+        vec![
+            ("aof_enabled", (self.is_enabled() as u8).to_string()),
+            ("aof_last_write_status", last_write_status.to_string()),
+            (
+                "aof_pending_fsync_bytes",
+                self.pending_bytes.load(Ordering::Relaxed).to_string(),
+            ),
+            ("aof_fsync_lag_ms", lag.to_string()),
+            (
+                "aof_last_fsync_duration_us",
+                self.last_fsync_us.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "aof_delayed_fsync",
+                self.delayed_fsyncs.load(Ordering::Relaxed).to_string(),
+            ),
+        ]
+    }
+}
+
+/// Background task syncing the append only file every second while the policy is `everysec`.
+///
+/// Holds a weak reference so the task ends when the server is dropped.
+pub(crate) async fn fsync_task(server: Weak<ServerState>) {
+    let mut interval = time::interval(FSYNC_INTERVAL);
+    // A slow sync delays the next one instead of triggering a burst of syncs.
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let Some(server) = server.upgrade() else {
+            return;
+        };
+
+        match server.config.appendfsync() {
+            AppendFsync::Everysec => {
+                let start = Instant::now();
+                if let Err(err) = server.aof.fsync_pending().await {
+                    warn!(%err, "Failed to sync the append only file");
+                }
+                server.latency.add_sample(
+                    "aof-fsync",
+                    start.elapsed(),
+                    server.config.latency_monitor_threshold(),
+                );
+            }
+            _ => server.aof.clear_pending(),
+        }
+    }
+}
+
+/// Task writing the commands appended to the append only file, see `Aof::append`. The
+/// commands queued meanwhile are written together once a write completes.
+///
+/// Holds a weak reference so the task ends when the server is dropped.
+pub(crate) async fn write_task(server: Weak<ServerState>) {
+    let Some(mut queued) = server
+        .upgrade()
+        .and_then(|server| server.aof.queued.lock().unwrap().take())
+    else {
+        return;
+    };
+
+    while let Some(command) = queued.recv().await {
+        let mut commands = vec![command];
+        while let Ok(command) = queued.try_recv() {
+            commands.push(command);
+        }
+
+        let Some(server) = server.upgrade() else {
+            return;
+        };
+
+        // The changes were made, failing to log them must not fail the commands.
+        let start = Instant::now();
+        if let Err(err) = server
+            .aof
+            .write_queued(commands, server.config.appendfsync())
+            .await
+        {
+            warn!(%err, "Failed to write to the append only file");
+        }
+        server.latency.add_sample(
+            "aof-write",
+            start.elapsed(),
+            server.config.latency_monitor_threshold(),
+        );
+    }
+}
+
+/// Replay the append only file at `path` into the databases of `server`, returns the number of
+/// commands replayed or `None` if there is no file.
+///
+/// Commands go through the normal execution path against a detached connection, see `replay`.
+///
+/// A file ending with an incomplete command, left by a crash while appending, is truncated
+/// to its last complete command if `aof-load-truncated` is enabled, refused otherwise.
+/// Progress is reported to the loading state of `server`.
+pub(crate) async fn load(
+    server: &Arc<ServerState>,
+    path: &Path,
+) -> Result<Option<usize>, WalrusError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    server.loading.start(bytes.len() as u64);
+
+    let mut conn = Connection::detached();
+    let mut pos = 0;
+    let mut replayed = 0;
+
+    while pos < bytes.len() {
+        let len = match Frame::check(&mut Cursor::new(&bytes[pos..])) {
+            Ok(len) => len,
+            Err(frame::Error::Incomplete) if server.config.aof_load_truncated() => {
+                warn!(
+                    from = bytes.len(),
+                    to = pos,
+                    "The append only file ends with an incomplete command, truncating it"
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(pos as u64)?;
+                break;
+            }
+            Err(frame::Error::Incomplete) => {
+                return Err("unexpected end of the append only file, the file is truncated".into());
+            }
+            Err(err) => {
+                return Err(format!("invalid command in the append only file, {err}").into());
+            }
+        };
+
+        // Copy each command so the values stored don't keep the whole file in memory.
+        let mut data = Bytes::copy_from_slice(&bytes[pos..pos + len]);
+        pos += len;
+
+        replay(server, &mut conn, Frame::parse(&mut data)?).await?;
+        replayed += 1;
+        server.loading.progress(pos as u64, replayed as u64);
+
+        // Replaying never waits, give connections a chance to be served meanwhile.
+        if replayed % 1024 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    Ok(Some(replayed))
+}
+
+/// Apply the write command `frame` to the database selected by `conn`, as logged in the
+/// append only file or streamed to a replica, replies are written to `conn` and discarded.
+/// `SELECT` changes the database the next commands apply to.
+///
+/// Blocking commands never block: only the ones that were served were logged, before their
+/// pops were logged as `LPOP`, so the element they popped is always there.
+///
+/// Keys removed or evicted are logged as `DEL`, and expirations as `PEXPIREAT` or `PERSIST`.
+/// Clients can't send these, they are applied to the database directly.
+pub(crate) async fn replay(
+    server: &Arc<ServerState>,
+    conn: &mut Connection,
+    frame: Frame,
+) -> Result<(), WalrusError> {
+    let db = &server.databases[conn.database()];
+    if is_named(&frame, b"del") {
+        let mut parse = Parse::new(frame)?;
+        parse.next_bytes()?;
+        db.del(&parse.next_bytes()?);
+        return Ok(());
+    }
+    if is_named(&frame, b"pexpireat") {
+        let mut parse = Parse::new(frame)?;
+        parse.next_bytes()?;
+        let key = parse.next_bytes()?;
+        // Expirations already passed expire the key right away.
+        let when = u64::try_from(parse.next_int()?).unwrap_or(0);
+        db.expire(
+            &key,
+            Some(Duration::from_millis(when.saturating_sub(unix_ms()))),
+        );
+        return Ok(());
+    }
+    if is_named(&frame, b"persist") {
+        let mut parse = Parse::new(frame)?;
+        parse.next_bytes()?;
+        db.expire(&parse.next_bytes()?, None);
+        return Ok(());
+    }
+
+    // Commands are logged by their original name, whatever their name for clients.
+    match Command::from_frame(frame, &Renames::default())? {
+        Command::BLPop(cmd) => {
+            for key in cmd.keys() {
+                if db.pop_front(key)?.is_some() {
+                    break;
+                }
+            }
+        }
+        cmd => cmd.execute(db, conn, server).await?,
+    }
+
+    conn.flush().await?;
+    Ok(())
+}
+
+/// Write the commands recreating the current dataset of `databases` to a new append only file
+/// at `path`, replacing any existing file. Used to start the file from a dataset loaded from a
+/// snapshot.
+///
+/// The keys of a database other than 0 follow a `SELECT` of their database, and the file ends
+/// with `SELECT 0` if another database was selected, as the commands appended next apply to
+/// database 0.
+pub(crate) fn rewrite(databases: &[Db], path: &Path) -> Result<(), WalrusError> {
+    let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+
+    let result = write_dataset(databases, &temp).and_then(|_| Ok(fs::rename(&temp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result
+}
+
+fn write_dataset(databases: &[Db], path: &Path) -> Result<(), WalrusError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut buf = BytesMut::new();
+    let now = Instant::now();
+    let now_ms = unix_ms();
+
+    let mut selected = 0;
+    for (index, db) in databases.iter().enumerate() {
+        let snapshot = db.snapshot();
+        if snapshot.is_empty() {
+            continue;
+        }
+        if index != selected {
+            write_frame(&mut out, &mut buf, &Select::new(index as i64).into_frame())?;
+            selected = index;
+        }
+
+        for (key, data, expires_at) in snapshot.entries().iter().cloned() {
+            let mut frame = Frame::array();
+            let value = match data {
+                // Lists can't have a time to live.
+                Value::List(list) => {
+                    frame.push_bulk(Bytes::from("rpush"));
+                    frame.push_bulk(key.clone());
+                    list.iter()
+                        .for_each(|element| frame.push(Frame::from(element)));
+                    None
+                }
+                data => string_value(data.exported()),
+            };
+
+            if let Some(value) = value {
+                frame.push_bulk(Bytes::from("set"));
+                frame.push_bulk(key);
+                frame.push_bulk(value);
+
+                if let Some(when) = expires_at {
+                    let ttl = when.saturating_duration_since(now).as_millis() as u64;
+                    frame.push_bulk(Bytes::from("pxat"));
+                    frame.push_int((now_ms + ttl) as i64);
+                }
+            }
+
+            write_frame(&mut out, &mut buf, &frame)?;
+        }
+    }
+    if selected != 0 {
+        write_frame(&mut out, &mut buf, &Select::new(0).into_frame())?;
+    }
+
+    let file = out.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+/// Write `frame` to `out`, encoded in `buf`.
+fn write_frame(out: &mut impl Write, buf: &mut BytesMut, frame: &Frame) -> std::io::Result<()> {
+    buf.clear();
+    frame.write_to(buf, Protocol::Resp3);
+    out.write_all(buf)
+}
+
+/// Command making `change` when applied, as logged in the append only file and streamed to
+/// replicas. Keys removed or evicted are deleted with `DEL`, as replicas don't evict keys, and
+/// expirations are set with `PEXPIREAT` at their unix time or removed with `PERSIST`. `None`
+/// for changes no command makes: keys expire again where the change is applied, as they are
+/// set with their expiration time, and the other changes are only made through the `Db` API.
+pub(crate) fn change_frame(change: &Change) -> Option<Frame> {
+    let mut frame = Frame::array();
+    match &change.op {
+        Op::Set { value, expires_at } => {
+            // Lists are only pushed to by commands.
+            let value = string_value(value.clone())?;
+            frame.push_bulk(Bytes::from("set"));
+            frame.push_bulk(change.key.clone());
+            frame.push_bulk(value);
+
+            if let Some(when) = expires_at {
+                let ms = when.duration_since(UNIX_EPOCH).unwrap_or_default();
+                frame.push_bulk(Bytes::from("pxat"));
+                frame.push_int(ms.as_millis() as i64);
+            }
+        }
+        Op::Push { elements, front } => {
+            frame.push_bulk(Bytes::from(if *front { "lpush" } else { "rpush" }));
+            frame.push_bulk(change.key.clone());
+            for element in elements {
+                frame.push(Frame::from(element.clone()));
+            }
+        }
+        Op::Pop { count, front: true } => {
+            frame.push_bulk(Bytes::from("lpop"));
+            frame.push_bulk(change.key.clone());
+            frame.push_int(*count as i64);
+        }
+        Op::Del | Op::Evicted => {
+            frame.push_bulk(Bytes::from("del"));
+            frame.push_bulk(change.key.clone());
+        }
+        Op::Expire {
+            expires_at: Some(when),
+        } => {
+            let ms = when.duration_since(UNIX_EPOCH).unwrap_or_default();
+            frame.push_bulk(Bytes::from("pexpireat"));
+            frame.push_bulk(change.key.clone());
+            frame.push_int(ms.as_millis() as i64);
+        }
+        Op::Expire { expires_at: None } => {
+            frame.push_bulk(Bytes::from("persist"));
+            frame.push_bulk(change.key.clone());
+        }
+        Op::Pop { front: false, .. } | Op::Expired => return None,
+    }
+
+    Some(frame)
+}
+
+/// Value of a string as written in a command, `None` for lists.
+fn string_value(data: Data) -> Option<Bytes> {
+    match data {
+        Data::Bytes(bytes) | Data::String(bytes) => Some(bytes),
+        Data::Integer(int) => Some(db::int_to_bytes(int)),
+        Data::Double(double) => Some(db::double_to_bytes(double)),
+        Data::Array(_) => None,
+    }
+}
+
+/// Encode the arguments of a command, rewriting relative expirations to absolute ones.
+fn encode_command(buf: &mut BytesMut, args: &[Frame]) {
+    let rewritten = absolute_expire(args);
+    let args = rewritten.as_deref().unwrap_or(args);
+
+    buf.put_u8(b'*');
+    frame::put_decimal(buf, args.len() as i64);
+    for arg in args {
+        arg.write_to(buf, Protocol::Resp3);
+    }
+}
+
+/// Returns `true` if `frame` is the command `name`, case-insensitively.
+fn is_named(frame: &Frame, name: &[u8]) -> bool {
+    let Frame::Array(args) = frame else {
+        return false;
+    };
+    matches!(args.first(), Some(Frame::Bulk(arg)) if arg.eq_ignore_ascii_case(name))
+}
+
+/// Rewrite the arguments of `SET key value EX|PX ttl` to use `PXAT`, so the key expires at the
+/// same time when the command is applied later. `None` if the command has no relative
+/// expiration.
+pub(crate) fn absolute_expire(args: &[Frame]) -> Option<Vec<Frame>> {
+    let is_set =
+        matches!(args.first(), Some(Frame::Bulk(name)) if name.eq_ignore_ascii_case(b"set"));
+
+    let expire_ms = match args.get(3..5) {
+        Some([Frame::Bulk(unit), ttl]) if is_set => {
+            let ttl = match ttl {
+                Frame::Integer(ttl) => Some(*ttl),
+                Frame::Bulk(ttl) => extract_i64(ttl),
+                _ => None,
+            };
+
+            if unit.eq_ignore_ascii_case(b"ex") {
+                ttl.map(|secs| secs.saturating_mul(1000))
+            } else if unit.eq_ignore_ascii_case(b"px") {
+                ttl
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    let ms = expire_ms?;
+    let mut rewritten = args[..3].to_vec();
+    rewritten.push(Frame::Bulk(Bytes::from("pxat")));
+    rewritten.push(Frame::Integer(unix_ms() as i64 + ms));
+    rewritten.extend_from_slice(&args[5..]);
+    Some(rewritten)
+}
@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tracing::info;
+
+use super::unix_ms;
+
+/// Interval between progress reports in the log while loading, in milliseconds.
+const REPORT_INTERVAL_MS: u64 = 1000;
+
+/// Progress of the dataset being loaded, on startup or from a master.
+///
+/// Connections are accepted while loading, commands without the `loading` flag are refused
+/// until `finish` is called.
+pub(crate) struct Loading {
+    /// `true` until the dataset is loaded.
+    active: AtomicBool,
+    /// Unix time in milliseconds loading started at.
+    started: AtomicU64,
+    /// Size of the file being loaded.
+    total_bytes: AtomicU64,
+    /// Bytes of the file loaded so far.
+    loaded_bytes: AtomicU64,
+    /// Keys or commands loaded so far.
+    loaded_keys: AtomicU64,
+    /// Unix time in milliseconds progress was last reported in the log.
+    last_report: AtomicU64,
+}
+
+impl Loading {
+    /// Create the state of a server loading its dataset, loading ends with `finish`.
+    pub(crate) fn new() -> Loading {
+        let now = unix_ms();
+
+        Loading {
+            active: AtomicBool::new(true),
+            started: AtomicU64::new(now),
+            total_bytes: AtomicU64::new(0),
+            loaded_bytes: AtomicU64::new(0),
+            loaded_keys: AtomicU64::new(0),
+            last_report: AtomicU64::new(now),
+        }
+    }
+
+    /// Returns `true` until the dataset is loaded.
+    pub(crate) fn is_loading(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Start loading a file of `total_bytes` bytes, commands are refused until `finish` is
+    /// called.
+    pub(crate) fn start(&self, total_bytes: u64) {
+        let now = unix_ms();
+
+        self.active.store(true, Ordering::Release);
+        self.started.store(now, Ordering::Relaxed);
+        self.last_report.store(now, Ordering::Relaxed);
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.loaded_bytes.store(0, Ordering::Relaxed);
+        self.loaded_keys.store(0, Ordering::Relaxed);
+    }
+
+    /// Record that `bytes` of the file and `keys` keys are loaded, reported in the log at most
+    /// every `REPORT_INTERVAL_MS`.
+    pub(crate) fn progress(&self, bytes: u64, keys: u64) {
+        self.loaded_bytes.store(bytes, Ordering::Relaxed);
+        self.loaded_keys.store(keys, Ordering::Relaxed);
+
+        let now = unix_ms();
+        let last = self.last_report.load(Ordering::Relaxed);
+
+        if now.saturating_sub(last) >= REPORT_INTERVAL_MS {
+            self.last_report.store(now, Ordering::Relaxed);
+
+            info!(
+                keys,
+                bytes,
+                total_bytes = self.total_bytes.load(Ordering::Relaxed),
+                percent = format_args!("{:.2}", self.percent()),
+                eta_seconds = self.eta_seconds(),
+                "Loading the dataset"
+            );
+        }
+    }
+
+    /// End loading, commands are accepted from now on.
+    pub(crate) fn finish(&self) {
+        self.active.store(false, Ordering::Release);
+    }
+
+    /// Percentage of the file loaded.
+    fn percent(&self) -> f64 {
+        let total = self.total_bytes.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.loaded_bytes.load(Ordering::Relaxed) as f64 * 100.0 / total as f64
+    }
+
+    /// Estimated seconds left, assuming the rest of the file loads at the average rate so far.
+    /// `None` until some bytes are loaded.
+    fn eta_seconds(&self) -> Option<u64> {
+        let total = self.total_bytes.load(Ordering::Relaxed);
+        let loaded = self.loaded_bytes.load(Ordering::Relaxed);
+        if loaded == 0 {
+            return None;
+        }
+
+        let elapsed_ms = unix_ms().saturating_sub(self.started.load(Ordering::Relaxed));
+        let left_ms = elapsed_ms as u128 * total.saturating_sub(loaded) as u128 / loaded as u128;
+        Some((left_ms / 1000) as u64)
+    }
+
+    /// Fields of the `persistence` section of `INFO`, the progress is only reported while
+    /// loading.
+    pub(crate) fn info(&self) -> Vec<(&'static str, String)> {
+        if !self.is_loading() {
+            return vec![("loading", "0".to_string())];
+        }
+
+        let eta = self.eta_seconds().map_or(-1, |eta| eta as i64);
+
+        vec![
+            ("loading", "1".to_string()),
+            (
+                "loading_start_time",
+                (self.started.load(Ordering::Relaxed) / 1000).to_string(),
+            ),
+            (
+                "loading_total_bytes",
+                self.total_bytes.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "loading_loaded_bytes",
+                self.loaded_bytes.load(Ordering::Relaxed).to_string(),
+            ),
+            ("loading_loaded_perc", format!("{:.2}", self.percent())),
+            (
+                "loading_loaded_keys",
+                self.loaded_keys.load(Ordering::Relaxed).to_string(),
+            ),
+            ("loading_eta_seconds", eta.to_string()),
+        ]
+    }
+}
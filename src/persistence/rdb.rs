@@ -0,0 +1,498 @@
+use bytes::Bytes;
+use std::{collections::VecDeque, fs, path::Path, time::Duration};
+
+use crate::{
+    crc64::crc64,
+    db::{self, Data, Db},
+    errors::WalrusError,
+};
+
+use super::{Reader, loading::Loading, unix_ms};
+
+/// First bytes of every Redis dump, followed by the version as 4 ascii digits.
+const MAGIC: &[u8; 5] = b"REDIS";
+/// Newest version of the format understood.
+const MAX_VERSION: u32 = 12;
+/// First version ending with a checksum.
+const CHECKSUM_VERSION: u32 = 5;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xF6;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+/// Special encodings of strings, in place of their length.
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+/// Container of a quicklist node holding a single element as is.
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+/// Outcome of an import.
+pub(crate) struct Imported {
+    /// Keys stored in the dataset.
+    pub(crate) keys: usize,
+    /// Keys left out, because walrus doesn't support their type or their database is out of
+    /// range.
+    pub(crate) skipped: usize,
+}
+
+/// Import the Redis dump at `path` into `databases`, reporting progress to `loading`.
+///
+/// Strings and lists are imported in their database with their expiration, keys that already
+/// expired are dropped. Hashes, sets and sorted sets are read but skipped as walrus doesn't
+/// support them, as are keys of databases out of range. Streams and module types can't be
+/// read and fail the import.
+///
+/// The checksum of the file is verified before anything is imported.
+pub(crate) fn load(
+    databases: &[Db],
+    path: &Path,
+    loading: &Loading,
+) -> Result<Imported, WalrusError> {
+    let bytes = fs::read(path)?;
+    loading.start(bytes.len() as u64);
+
+    let mut reader = Reader::new(&bytes);
+
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err("not a Redis dump".into());
+    }
+
+    let version = std::str::from_utf8(reader.take(4)?)
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or("invalid Redis dump version")?;
+    if version > MAX_VERSION {
+        return Err(format!("unsupported Redis dump version {version}").into());
+    }
+
+    // Dumps end with the checksum of everything before it, 0 if checksums were disabled.
+    let mut end = bytes.len();
+    if version >= CHECKSUM_VERSION {
+        end = end
+            .checked_sub(8)
+            .ok_or("unexpected end of Redis dump, the file is truncated")?;
+        let checksum = u64::from_le_bytes(bytes[end..].try_into().unwrap());
+
+        if checksum != 0 && checksum != crc64(0, &bytes[..end]) {
+            return Err("Redis dump is corrupted, checksum mismatch".into());
+        }
+    }
+
+    let now = unix_ms();
+    let mut imported = Imported {
+        keys: 0,
+        skipped: 0,
+    };
+    let mut selected_db = 0;
+    let mut expires_at = None;
+
+    loop {
+        let opcode = reader.u8()?;
+
+        match opcode {
+            OPCODE_EOF => break,
+            OPCODE_AUX => {
+                string(&mut reader)?;
+                string(&mut reader)?;
+            }
+            OPCODE_RESIZEDB => {
+                length(&mut reader)?;
+                length(&mut reader)?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    length(&mut reader)?;
+                }
+            }
+            OPCODE_SELECTDB => selected_db = length(&mut reader)?,
+            OPCODE_EXPIRETIME_MS => expires_at = Some(reader.u64()?),
+            OPCODE_EXPIRETIME => expires_at = Some(reader.u32()? as u64 * 1000),
+            OPCODE_IDLE => {
+                length(&mut reader)?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            // Function libraries, walrus has no functions.
+            OPCODE_FUNCTION2 => {
+                string(&mut reader)?;
+            }
+            OPCODE_FUNCTION_PRE_GA | OPCODE_MODULE_AUX => {
+                return Err(format!("unsupported opcode {opcode} in Redis dump").into());
+            }
+            tag => {
+                let key = Bytes::from(string(&mut reader)?);
+                let value = value(&mut reader, tag)?;
+
+                let db = databases.get(selected_db as usize);
+                match (value, expires_at.take(), db) {
+                    (None, _, _) | (_, _, None) => imported.skipped += 1,
+                    (Some(_), Some(when), _) if when <= now => {}
+                    (Some(value), Some(when), Some(db)) => {
+                        db.set(&key, value, Some(Duration::from_millis(when - now)));
+                        imported.keys += 1;
+                    }
+                    (Some(value), None, Some(db)) => {
+                        db.set(&key, value, None);
+                        imported.keys += 1;
+                    }
+                }
+
+                loading.progress(reader.pos as u64, (imported.keys + imported.skipped) as u64);
+            }
+        }
+    }
+
+    if reader.pos != end {
+        return Err("unexpected data after the end of the Redis dump".into());
+    }
+
+    Ok(imported)
+}
+
+/// Read a value of type `tag`, `None` if walrus doesn't support its type.
+fn value(reader: &mut Reader, tag: u8) -> Result<Option<Data>, WalrusError> {
+    let value = match tag {
+        TYPE_STRING => Some(db::optimize_storage(Bytes::from(string(reader)?))),
+        TYPE_LIST => {
+            let len = length(reader)?;
+            let mut list = VecDeque::new();
+
+            for _ in 0..len {
+                list.push_back(element(string(reader)?));
+            }
+
+            Some(Data::Array(list))
+        }
+        TYPE_LIST_ZIPLIST => Some(Data::Array(
+            ziplist(&string(reader)?)?
+                .into_iter()
+                .map(element)
+                .collect(),
+        )),
+        TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+            let nodes = length(reader)?;
+            let mut list = VecDeque::new();
+
+            for _ in 0..nodes {
+                let container = if tag == TYPE_LIST_QUICKLIST_2 {
+                    length(reader)?
+                } else {
+                    0
+                };
+                let node = string(reader)?;
+
+                if container == QUICKLIST_NODE_PLAIN {
+                    list.push_back(element(node));
+                } else if tag == TYPE_LIST_QUICKLIST_2 {
+                    list.extend(listpack(&node)?.into_iter().map(element));
+                } else {
+                    list.extend(ziplist(&node)?.into_iter().map(element));
+                }
+            }
+
+            Some(Data::Array(list))
+        }
+        TYPE_SET => {
+            for _ in 0..length(reader)? {
+                string(reader)?;
+            }
+            None
+        }
+        TYPE_HASH => {
+            for _ in 0..length(reader)? {
+                string(reader)?;
+                string(reader)?;
+            }
+            None
+        }
+        TYPE_ZSET => {
+            for _ in 0..length(reader)? {
+                string(reader)?;
+                // Scores are stored as strings prefixed with their length, except for the
+                // special lengths of NaN and infinities.
+                let len = reader.u8()?;
+                if len < 253 {
+                    reader.take(len as usize)?;
+                }
+            }
+            None
+        }
+        TYPE_ZSET_2 => {
+            for _ in 0..length(reader)? {
+                string(reader)?;
+                reader.u64()?;
+            }
+            None
+        }
+        // Compact encodings stored as a single string.
+        TYPE_HASH_ZIPMAP | TYPE_SET_INTSET | TYPE_ZSET_ZIPLIST | TYPE_HASH_ZIPLIST
+        | TYPE_HASH_LISTPACK | TYPE_ZSET_LISTPACK | TYPE_SET_LISTPACK => {
+            string(reader)?;
+            None
+        }
+        TYPE_HASH_LISTPACK_EX => {
+            // Minimum expiration of the fields.
+            reader.u64()?;
+            string(reader)?;
+            None
+        }
+        _ => return Err(format!("unsupported value type {tag} in Redis dump").into()),
+    };
+
+    Ok(value)
+}
+
+/// Element of a list.
+fn element(bytes: Vec<u8>) -> Data {
+    db::optimize_storage(Bytes::from(bytes))
+}
+
+/// Length of the next string, or its special encoding.
+enum Length {
+    Len(u64),
+    Encoded(u8),
+}
+
+/// Read a length, the 2 high bits of the first byte tell how it's encoded.
+fn length_or_encoding(reader: &mut Reader) -> Result<Length, WalrusError> {
+    let first = reader.u8()?;
+
+    let len = match first >> 6 {
+        0 => (first & 0x3F) as u64,
+        1 => ((first & 0x3F) as u64) << 8 | reader.u8()? as u64,
+        2 => match first {
+            0x80 => u32::from_be_bytes(reader.take(4)?.try_into().unwrap()) as u64,
+            0x81 => u64::from_be_bytes(reader.take(8)?.try_into().unwrap()),
+            _ => return Err(format!("invalid length encoding {first} in Redis dump").into()),
+        },
+        _ => return Ok(Length::Encoded(first & 0x3F)),
+    };
+
+    Ok(Length::Len(len))
+}
+
+/// Read a length which can't be a special encoding.
+fn length(reader: &mut Reader) -> Result<u64, WalrusError> {
+    match length_or_encoding(reader)? {
+        Length::Len(len) => Ok(len),
+        Length::Encoded(_) => Err("unexpected encoded length in Redis dump".into()),
+    }
+}
+
+/// Read a string, integers are converted to their decimal representation.
+fn string(reader: &mut Reader) -> Result<Vec<u8>, WalrusError> {
+    let string = match length_or_encoding(reader)? {
+        Length::Len(len) => reader.take(to_usize(len)?)?.to_vec(),
+        Length::Encoded(ENC_INT8) => (reader.u8()? as i8).to_string().into_bytes(),
+        Length::Encoded(ENC_INT16) => i16::from_le_bytes(reader.take(2)?.try_into().unwrap())
+            .to_string()
+            .into_bytes(),
+        Length::Encoded(ENC_INT32) => (reader.u32()? as i32).to_string().into_bytes(),
+        Length::Encoded(ENC_LZF) => {
+            let compressed_len = to_usize(length(reader)?)?;
+            let len = to_usize(length(reader)?)?;
+            lzf_decompress(reader.take(compressed_len)?, len)?
+        }
+        Length::Encoded(enc) => {
+            return Err(format!("invalid string encoding {enc} in Redis dump").into());
+        }
+    };
+
+    Ok(string)
+}
+
+fn to_usize(len: u64) -> Result<usize, WalrusError> {
+    usize::try_from(len).map_err(|_| "length too large in Redis dump".into())
+}
+
+/// Decompress `input` compressed with LZF to `len` bytes.
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, WalrusError> {
+    const CORRUPTED: &str = "corrupted LZF string in Redis dump";
+
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+
+        if ctrl < 32 {
+            // Literal run of `ctrl + 1` bytes.
+            let literal = input.get(i..i + ctrl + 1).ok_or(CORRUPTED)?;
+            out.extend_from_slice(literal);
+            i += ctrl + 1;
+        } else {
+            // Back reference, copied byte by byte as it may overlap the bytes it produces.
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or(CORRUPTED)? as usize;
+                i += 1;
+            }
+
+            let distance = ((ctrl & 0x1F) << 8) + *input.get(i).ok_or(CORRUPTED)? as usize + 1;
+            i += 1;
+
+            let start = out.len().checked_sub(distance).ok_or(CORRUPTED)?;
+            for j in start..start + run + 2 {
+                out.push(out[j]);
+            }
+        }
+
+        if out.len() > len {
+            return Err(CORRUPTED.into());
+        }
+    }
+
+    if out.len() != len {
+        return Err(CORRUPTED.into());
+    }
+
+    Ok(out)
+}
+
+/// Elements of a ziplist, the list encoding of Redis before 7.0.
+fn ziplist(bytes: &[u8]) -> Result<Vec<Vec<u8>>, WalrusError> {
+    let mut reader = Reader::new(bytes);
+    let mut elements = Vec::new();
+
+    // Total size, offset of the last element and number of elements.
+    reader.take(10)?;
+
+    loop {
+        // Length of the previous element, 1 byte or 0xFE followed by 4 bytes. The end of the
+        // list takes its place.
+        match reader.u8()? {
+            0xFF => break,
+            0xFE => {
+                reader.take(4)?;
+            }
+            _ => {}
+        }
+
+        let enc = reader.u8()?;
+        let element = match enc >> 6 {
+            0 => reader.take((enc & 0x3F) as usize)?.to_vec(),
+            1 => {
+                let len = ((enc & 0x3F) as usize) << 8 | reader.u8()? as usize;
+                reader.take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+                reader.take(len as usize)?.to_vec()
+            }
+            _ => {
+                let int = match enc {
+                    0xC0 => i16::from_le_bytes(reader.take(2)?.try_into().unwrap()) as i64,
+                    0xD0 => reader.u32()? as i32 as i64,
+                    0xE0 => reader.u64()? as i64,
+                    0xF0 => int24(reader.take(3)?),
+                    0xFE => reader.u8()? as i8 as i64,
+                    // Immediate 4 bit integer, from 1 for 0 to 13 for 12.
+                    0xF1..=0xFD => (enc & 0x0F) as i64 - 1,
+                    _ => return Err(format!("invalid ziplist encoding {enc}").into()),
+                };
+                int.to_string().into_bytes()
+            }
+        };
+
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+/// Elements of a listpack, the compact encoding of Redis since 7.0.
+fn listpack(bytes: &[u8]) -> Result<Vec<Vec<u8>>, WalrusError> {
+    let mut reader = Reader::new(bytes);
+    let mut elements = Vec::new();
+
+    // Total size and number of elements.
+    reader.take(6)?;
+
+    loop {
+        let start = reader.pos;
+        let enc = reader.u8()?;
+
+        let element = if enc == 0xFF {
+            break;
+        } else if enc & 0x80 == 0 {
+            // 7 bit unsigned integer.
+            enc.to_string().into_bytes()
+        } else if enc & 0xC0 == 0x80 {
+            reader.take((enc & 0x3F) as usize)?.to_vec()
+        } else if enc & 0xE0 == 0xC0 {
+            // 13 bit signed integer.
+            let int = ((enc & 0x1F) as i64) << 8 | reader.u8()? as i64;
+            let int = if int >= 1 << 12 { int - (1 << 13) } else { int };
+            int.to_string().into_bytes()
+        } else if enc & 0xF0 == 0xE0 {
+            let len = ((enc & 0x0F) as usize) << 8 | reader.u8()? as usize;
+            reader.take(len)?.to_vec()
+        } else {
+            match enc {
+                0xF0 => {
+                    let len = reader.u32()? as usize;
+                    reader.take(len)?.to_vec()
+                }
+                0xF1 => i16::from_le_bytes(reader.take(2)?.try_into().unwrap())
+                    .to_string()
+                    .into_bytes(),
+                0xF2 => int24(reader.take(3)?).to_string().into_bytes(),
+                0xF3 => (reader.u32()? as i32).to_string().into_bytes(),
+                0xF4 => (reader.u64()? as i64).to_string().into_bytes(),
+                _ => return Err(format!("invalid listpack encoding {enc}").into()),
+            }
+        };
+
+        // Every element is followed by its own length, for backward traversal.
+        let len = reader.pos - start;
+        let backlen = match len {
+            0..=127 => 1,
+            128..16383 => 2,
+            16383..2097151 => 3,
+            2097151..268435455 => 4,
+            _ => 5,
+        };
+        reader.take(backlen)?;
+
+        elements.push(element);
+    }
+
+    Ok(elements)
+}
+
+/// Sign extend a 24 bit little endian integer.
+fn int24(bytes: &[u8]) -> i64 {
+    (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as i64
+}
@@ -0,0 +1,141 @@
+use dashmap::DashMap;
+use std::{
+    net::IpAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Time it takes the commands allowance of an address to refill entirely. The allowance is
+/// `ratelimit-per-ip` commands, so bursts of up to a second of commands are allowed.
+const REFILL_PERIOD: Duration = Duration::from_secs(1);
+
+/// What happens to a connection sending commands over `ratelimit-per-ip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RatelimitAction {
+    /// Commands over the limit are refused with a `THROTTLED` error.
+    Throttle = 0,
+    /// The connection is closed after the `THROTTLED` error.
+    Disconnect = 1,
+}
+
+/// Connections and commands allowance of a peer address.
+struct Peer {
+    /// Number of connections open from the address.
+    connections: usize,
+    /// Commands the address may still send right away, refilled over `REFILL_PERIOD`.
+    allowance: f64,
+    /// Last time `allowance` was refilled.
+    refilled: Instant,
+}
+
+/// Limits of the connections and commands of each peer address, so a single host can't take
+/// every connection of the server or slow down the other clients sharing the dataset.
+///
+/// Connections over unix sockets have no address, they are never limited.
+pub(crate) struct PeerLimits {
+    peers: Arc<DashMap<IpAddr, Peer>>,
+    /// Number of commands refused because of `ratelimit-per-ip`.
+    throttled: AtomicU64,
+    /// Last time addresses without connections and with a full allowance were removed.
+    purged: Mutex<Instant>,
+}
+
+/// Connection counted against the limit of its address, until dropped.
+pub(crate) struct PeerGuard {
+    peers: Arc<DashMap<IpAddr, Peer>>,
+    ip: IpAddr,
+}
+
+impl PeerLimits {
+    pub(crate) fn new() -> PeerLimits {
+        PeerLimits {
+            peers: Arc::new(DashMap::new()),
+            throttled: AtomicU64::new(0),
+            purged: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Count a new connection from `ip`. Returns `None` if `ip` already has `max` connections,
+    /// 0 is no limit.
+    pub(crate) fn connect(&self, ip: IpAddr, max: usize) -> Option<PeerGuard> {
+        self.purge();
+
+        let mut peer = self.peers.entry(ip).or_insert_with(|| Peer {
+            connections: 0,
+            allowance: f64::INFINITY,
+            refilled: Instant::now(),
+        });
+        if max > 0 && peer.connections >= max {
+            return None;
+        }
+        peer.connections += 1;
+
+        Some(PeerGuard {
+            peers: self.peers.clone(),
+            ip,
+        })
+    }
+
+    /// Returns `true` if `ip` may execute one more command with a limit of `rate` commands per
+    /// second, 0 is no limit. Refused commands are counted as throttled.
+    pub(crate) fn allow(&self, ip: IpAddr, rate: u64) -> bool {
+        if rate == 0 {
+            return true;
+        }
+
+        let Some(mut peer) = self.peers.get_mut(&ip) else {
+            return true;
+        };
+
+        // The allowance refills continuously, up to a second of commands.
+        let now = Instant::now();
+        let elapsed = now.duration_since(peer.refilled).as_secs_f64();
+        let rate = rate as f64;
+        peer.allowance = (peer.allowance + elapsed * rate / REFILL_PERIOD.as_secs_f64()).min(rate);
+        peer.refilled = now;
+
+        if peer.allowance < 1.0 {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        peer.allowance -= 1.0;
+        true
+    }
+
+    /// Number of commands refused because of `ratelimit-per-ip`.
+    pub(crate) fn throttled(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// Reset the number of commands refused, with `CONFIG RESETSTAT`.
+    pub(crate) fn reset_throttled(&self) {
+        self.throttled.store(0, Ordering::Relaxed);
+    }
+
+    /// Remove the addresses without connections whose allowance refilled, at most once per
+    /// `REFILL_PERIOD`. Addresses are kept a while after their last connection closes, so a
+    /// peer can't reset its allowance by reconnecting.
+    fn purge(&self) {
+        {
+            let mut purged = self.purged.lock().unwrap();
+            if purged.elapsed() < REFILL_PERIOD {
+                return;
+            }
+            *purged = Instant::now();
+        }
+
+        self.peers
+            .retain(|_, peer| peer.connections > 0 || peer.refilled.elapsed() < REFILL_PERIOD);
+    }
+}
+
+impl Drop for PeerGuard {
+    fn drop(&mut self) {
+        if let Some(mut peer) = self.peers.get_mut(&self.ip) {
+            peer.connections -= 1;
+        }
+    }
+}
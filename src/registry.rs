@@ -0,0 +1,356 @@
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{
+        Notify,
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+    },
+    time::Instant,
+};
+use tracing::warn;
+
+use crate::config::{ClientClass, OutputLimits};
+use crate::frame::Frame;
+
+/// Information about a single active connection.
+struct ClientInfo {
+    /// Address of the peer.
+    addr: Option<SocketAddr>,
+    /// Name of the connection, set with `CLIENT SETNAME` or `HELLO ... SETNAME`.
+    name: Option<Bytes>,
+    /// Instant at which the connection was accepted.
+    created: Instant,
+    /// Instant at which the last command was received, and the name of that command.
+    /// Locked on its own rather than with the map entry, so recording every command only
+    /// read locks the registry and doesn't hold back other connections.
+    activity: Mutex<(Instant, &'static str)>,
+    /// Notified to terminate the handler task of the connection.
+    kill: Arc<Notify>,
+    /// Channel to the handler task of the connection, frames sent are written to the
+    /// connection as soon as no command is executing.
+    push: PushSender,
+}
+
+/// Output queued for a connection through its `PushSender` and not taken by its handler yet.
+struct Output {
+    /// Size of the frames queued, in bytes.
+    queued: AtomicU64,
+    /// Class of the client, selects the limits of its output.
+    class: AtomicU8,
+    /// Instant the output went over the soft limit, `None` while under it.
+    soft_since: Mutex<Option<Instant>>,
+    /// Set once a limit is exceeded, further frames are dropped.
+    closed: AtomicBool,
+    /// Notified to terminate the handler task of the connection.
+    kill: Arc<Notify>,
+    limits: OutputLimits,
+}
+
+impl Output {
+    /// Returns `true` if `queued` bytes exceed the limits of the client.
+    fn exceeds_limits(&self, queued: u64) -> bool {
+        let class = self.class.load(Ordering::Relaxed) as usize;
+        let limit = self.limits.read().unwrap()[class];
+
+        if limit.hard > 0 && queued > limit.hard {
+            return true;
+        }
+
+        let mut soft_since = self.soft_since.lock().unwrap();
+        if limit.soft > 0 && queued > limit.soft {
+            let since = soft_since.get_or_insert_with(Instant::now);
+            since.elapsed() >= Duration::from_secs(limit.soft_seconds)
+        } else {
+            *soft_since = None;
+            false
+        }
+    }
+}
+
+/// Sending side of the channel delivering out of band frames to a connection, such as the
+/// commands streamed to replicas and monitors.
+///
+/// The frames queued are accounted for, a connection not reading them fast enough is closed
+/// once they exceed the `client-output-buffer-limit` of its class.
+#[derive(Clone)]
+pub(crate) struct PushSender {
+    sender: UnboundedSender<(Frame, u64)>,
+    output: Arc<Output>,
+}
+
+/// Receiving side of the channel delivering out of band frames to a connection.
+pub(crate) struct PushReceiver {
+    receiver: UnboundedReceiver<(Frame, u64)>,
+    output: Arc<Output>,
+}
+
+impl PushSender {
+    /// Queue `frame` to the connection. Nothing is queued if the connection is closing.
+    pub(crate) fn send(&self, frame: Frame) {
+        let output = &self.output;
+        if output.closed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let size = output_size(&frame);
+        let queued = output.queued.fetch_add(size, Ordering::Relaxed) + size;
+        if output.exceeds_limits(queued) {
+            // Only the first frame over the limit closes the connection.
+            if !output.closed.swap(true, Ordering::Relaxed) {
+                warn!(
+                    queued,
+                    "Client closed for overcoming of output buffer limits"
+                );
+                output.kill.notify_one();
+            }
+            return;
+        }
+
+        let _ = self.sender.send((frame, size));
+    }
+
+    /// Set the class of the client, its output is limited by the limits of the class.
+    pub(crate) fn set_class(&self, class: ClientClass) {
+        self.output.class.store(class as u8, Ordering::Relaxed);
+    }
+}
+
+impl PushReceiver {
+    /// Receive the next frame queued to the connection.
+    pub(crate) async fn recv(&mut self) -> Option<Frame> {
+        let (frame, size) = self.receiver.recv().await?;
+        self.output.queued.fetch_sub(size, Ordering::Relaxed);
+        Some(frame)
+    }
+}
+
+/// Approximate size of `frame` once encoded, in bytes. Each frame is counted with a small
+/// fixed overhead for its type and length.
+fn output_size(frame: &Frame) -> u64 {
+    const OVERHEAD: u64 = 16;
+
+    let mut size = 0;
+    let mut pending = vec![frame];
+    while let Some(frame) = pending.pop() {
+        size += OVERHEAD;
+        match frame {
+            Frame::Simple(val) | Frame::Bulk(val) | Frame::BigNumber(val) => {
+                size += val.len() as u64
+            }
+            Frame::Error(err) => size += err.len() as u64,
+            Frame::Verbatim { format, text } => size += (format.len() + text.len()) as u64,
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => pending.extend(items),
+            Frame::Map(pairs) => pending.extend(pairs.iter().flat_map(|(key, value)| [key, value])),
+            Frame::Attribute { attributes, data } => {
+                pending.push(data);
+                pending.extend(attributes.iter().flat_map(|(key, value)| [key, value]));
+            }
+            Frame::Integer(_) | Frame::Double(_) | Frame::Null | Frame::Boolean(_) => {}
+        }
+    }
+    size
+}
+
+/// Filter selecting the connections to kill with `CLIENT KILL`.
+#[derive(Debug, Default)]
+pub(crate) struct KillFilter {
+    pub(crate) id: Option<u64>,
+    pub(crate) addr: Option<Bytes>,
+    /// Id of the connection to skip, usually the connection issuing the `CLIENT KILL`.
+    pub(crate) skip: Option<u64>,
+}
+
+/// Registry of all active connections of the server.
+///
+/// Entries are added by the connection handler when it starts and removed when it terminates.
+pub(crate) struct ClientRegistry {
+    /// Map of connection id to connection info.
+    clients: DashMap<u64, ClientInfo>,
+    /// Number of connections refused because `maxclients` was reached.
+    rejected: AtomicU64,
+}
+
+impl ClientRegistry {
+    /// Create an empty registry.
+    pub(crate) fn new() -> ClientRegistry {
+        ClientRegistry {
+            clients: DashMap::new(),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Add a connection to the registry, its queued output is bounded by `limits`.
+    ///
+    /// Returns the `Notify` used to signal the handler task of the connection to terminate,
+    /// and the channel delivering out of band frames to the connection.
+    pub(crate) fn register(
+        &self,
+        id: u64,
+        addr: Option<SocketAddr>,
+        limits: OutputLimits,
+    ) -> (Arc<Notify>, PushReceiver) {
+        let now = Instant::now();
+        let kill = Arc::new(Notify::new());
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let output = Arc::new(Output {
+            queued: AtomicU64::new(0),
+            class: AtomicU8::new(ClientClass::Normal as u8),
+            soft_since: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            kill: kill.clone(),
+            limits,
+        });
+        let push = PushSender {
+            sender,
+            output: output.clone(),
+        };
+
+        self.clients.insert(
+            id,
+            ClientInfo {
+                addr,
+                name: None,
+                created: now,
+                activity: Mutex::new((now, "NULL")),
+                kill: kill.clone(),
+                push,
+            },
+        );
+
+        (kill, PushReceiver { receiver, output })
+    }
+
+    /// Number of active connections.
+    pub(crate) fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Record a connection refused because `maxclients` was reached.
+    pub(crate) fn reject(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of connections refused because `maxclients` was reached.
+    pub(crate) fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Reset the number of connections refused, with `CONFIG RESETSTAT`.
+    pub(crate) fn reset_rejected(&self) {
+        self.rejected.store(0, Ordering::Relaxed);
+    }
+
+    /// Remove a connection from the registry.
+    pub(crate) fn unregister(&self, id: u64) {
+        self.clients.remove(&id);
+    }
+
+    /// Record that the connection with `id` is executing `cmd`.
+    pub(crate) fn touch(&self, id: u64, cmd: &'static str) {
+        if let Some(info) = self.clients.get(&id) {
+            *info.activity.lock().unwrap() = (Instant::now(), cmd);
+        }
+    }
+
+    /// Channel delivering out of band frames to the connection with `id`.
+    pub(crate) fn push_sender(&self, id: u64) -> Option<PushSender> {
+        self.clients.get(&id).map(|info| info.push.clone())
+    }
+
+    /// Update the name of the connection with `id`.
+    pub(crate) fn set_name(&self, id: u64, name: Option<Bytes>) {
+        if let Some(mut info) = self.clients.get_mut(&id) {
+            info.name = name;
+        }
+    }
+
+    /// Describe every active connection, one connection per line, ordered by id.
+    ///
+    /// Each line contains space separated `field=value` pairs, as in `CLIENT LIST`.
+    pub(crate) fn list(&self) -> String {
+        let now = Instant::now();
+        let mut clients: Vec<_> = self
+            .clients
+            .iter()
+            .map(|entry| {
+                let info = entry.value();
+                let addr = info.addr.map(|addr| addr.to_string()).unwrap_or_default();
+                let (last_interaction, last_cmd) = *info.activity.lock().unwrap();
+                let name = info
+                    .name
+                    .as_ref()
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .unwrap_or_default();
+
+                (
+                    *entry.key(),
+                    addr,
+                    name,
+                    now.duration_since(info.created).as_secs(),
+                    now.duration_since(last_interaction).as_secs(),
+                    info.push.output.queued.load(Ordering::Relaxed),
+                    last_cmd,
+                )
+            })
+            .collect();
+
+        clients.sort_unstable_by_key(|client| client.0);
+
+        let mut out = String::new();
+        for (id, addr, name, age, idle, omem, cmd) in clients {
+            // Writing to a `String` never fails.
+            let _ = writeln!(
+                out,
+                "id={id} addr={addr} name={name} age={age} idle={idle} omem={omem} cmd={cmd}"
+            );
+        }
+
+        out
+    }
+
+    /// Signal every connection matching `filter` to terminate.
+    ///
+    /// Returns the number of connections signaled.
+    pub(crate) fn kill(&self, filter: &KillFilter) -> usize {
+        let mut killed = 0;
+
+        for entry in self.clients.iter() {
+            let id = *entry.key();
+
+            if filter.skip == Some(id) {
+                continue;
+            }
+
+            if filter.id.is_some_and(|filter_id| filter_id != id) {
+                continue;
+            }
+
+            if let Some(addr) = &filter.addr {
+                let matches = entry
+                    .addr
+                    .is_some_and(|peer| peer.to_string().as_bytes() == addr.as_ref());
+
+                if !matches {
+                    continue;
+                }
+            }
+
+            // `notify_one` stores a permit if the handler isn't waiting yet, so the signal is
+            // never lost.
+            entry.kill.notify_one();
+            killed += 1;
+        }
+
+        killed
+    }
+}
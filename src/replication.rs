@@ -0,0 +1,723 @@
+mod backlog;
+mod failover;
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use rand::RngExt;
+use std::{
+    borrow::Cow,
+    io::Cursor,
+    net::IpAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{RwLock, RwLockReadGuard, oneshot},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+use crate::{
+    Connection,
+    cmd::{Psync, Replconf},
+    config::ClientClass,
+    db::Db,
+    errors::WalrusError,
+    frame::Frame,
+    persistence::{self, aof},
+    registry::{KillFilter, PushSender},
+    server::ServerState,
+    task,
+};
+
+use backlog::Backlog;
+use failover::FailoverState;
+pub(crate) use failover::FailoverTarget;
+
+/// Delay before reconnecting to the master after the link is lost.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Replication id of a server without a previous history.
+const NO_REPLID: &str = "0000000000000000000000000000000000000000";
+
+/// Replication state of the server.
+///
+/// As a master, write commands are streamed to every connection that issued `SYNC` or `PSYNC`
+/// and recorded in the backlog, so replicas losing the link can resume where they stopped. As
+/// a replica, set with `REPLICAOF`, a background task keeps a link to the master, loads its
+/// dataset and applies the commands it streams.
+pub(crate) struct Replication {
+    /// Orders write commands with the snapshot sent to a new replica. Write commands hold it
+    /// for reading from their execution until they are streamed, `SYNC` holds it for writing
+    /// while capturing the dataset, so every write is either in the snapshot or streamed
+    /// after it.
+    writes: RwLock<()>,
+    /// Map of connection id to the replicas connected.
+    replicas: DashMap<u64, Replica>,
+    /// Map of connection id to the port announced with `REPLCONF listening-port`, before the
+    /// connection becomes a replica.
+    announced_ports: DashMap<u64, u16>,
+    /// `true` once the backlog is created, write commands are recorded from then on.
+    active: AtomicBool,
+    /// Position of the server in the stream of write commands.
+    history: Mutex<History>,
+    /// Master of the server, `None` if it's a master itself.
+    master: Mutex<Option<Master>>,
+    /// Number of full resynchronizations served.
+    sync_full: AtomicU64,
+    /// Number of partial resynchronizations accepted.
+    sync_partial_ok: AtomicU64,
+    /// Number of partial resynchronizations refused.
+    sync_partial_err: AtomicU64,
+    /// Step of the failover in progress, a `FailoverState`.
+    failover_state: AtomicU8,
+    /// Task running the failover in progress.
+    failover: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// A replica connected to this server.
+struct Replica {
+    /// Channel streaming commands to the replica.
+    sender: PushSender,
+    /// Address the replica connected from.
+    ip: IpAddr,
+    /// Port the replica accepts connections on, if announced.
+    port: Option<u16>,
+    /// Offset of the stream the replica applied, `None` until it acknowledges one.
+    ack: Option<u64>,
+}
+
+/// Identity and position of the stream of write commands of the server.
+struct History {
+    /// Id of the stream, replaced when the dataset stops following the one of the master.
+    replid: String,
+    /// Id of the stream followed before the last change of master.
+    replid2: String,
+    /// Replicas of the previous master may resume with `replid2` up to this offset, -1 if
+    /// there is no previous stream.
+    second_offset: i64,
+    /// Most recent bytes of the stream, created when the first replica connects.
+    backlog: Option<Backlog>,
+}
+
+impl History {
+    /// Offset of the last byte of the stream.
+    fn offset(&self) -> u64 {
+        self.backlog.as_ref().map_or(0, Backlog::offset)
+    }
+
+    /// Start a new stream, keeping the current one resumable up to its current offset.
+    fn shift_replid(&mut self, replid: String) {
+        self.replid2 = std::mem::replace(&mut self.replid, replid);
+        self.second_offset = self.offset() as i64 + 1;
+    }
+}
+
+/// Outcome of a synchronization request from a replica.
+pub(crate) enum SyncReply {
+    /// The replica resumes the stream `replid` with `frames`, the commands it missed.
+    Continue { replid: String, frames: Vec<Frame> },
+    /// The replica loads `snapshot`, then follows the stream `replid` from `offset`.
+    FullResync {
+        replid: String,
+        offset: u64,
+        snapshot: Vec<u8>,
+    },
+}
+
+/// The master replicated by this server.
+struct Master {
+    host: String,
+    port: u16,
+    /// `true` while connected to the master and in sync with it.
+    link_up: Arc<AtomicBool>,
+    /// Task maintaining the link, aborted when replication stops.
+    task: JoinHandle<()>,
+}
+
+impl Replication {
+    /// Create the state of a master without replicas.
+    pub(crate) fn new() -> Replication {
+        Replication {
+            writes: RwLock::new(()),
+            replicas: DashMap::new(),
+            announced_ports: DashMap::new(),
+            active: AtomicBool::new(false),
+            history: Mutex::new(History {
+                replid: new_replid(),
+                replid2: NO_REPLID.to_string(),
+                second_offset: -1,
+                backlog: None,
+            }),
+            master: Mutex::new(None),
+            sync_full: AtomicU64::new(0),
+            sync_partial_ok: AtomicU64::new(0),
+            sync_partial_err: AtomicU64::new(0),
+            failover_state: AtomicU8::new(FailoverState::None as u8),
+            failover: Mutex::new(None),
+        }
+    }
+
+    /// Wait until a write command can execute, the command must be streamed to replicas
+    /// before the returned guard is dropped.
+    pub(crate) async fn write_guard(&self) -> RwLockReadGuard<'_, ()> {
+        self.writes.read().await
+    }
+
+    /// Register connection `id` as a replica, commands are streamed to it through `sender`
+    /// from then on.
+    ///
+    /// A replica that followed the stream `replid` up to offset `from - 1` resumes from the
+    /// backlog if the commands it missed are still there, otherwise it receives a snapshot of
+    /// the databases.
+    pub(crate) async fn add_replica(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        sender: PushSender,
+        resume: Option<(&str, u64)>,
+        server: &ServerState,
+    ) -> Result<SyncReply, WalrusError> {
+        // Wait for the writes in progress, new ones wait for the dataset to be captured.
+        let writes = self.writes.write().await;
+
+        {
+            let mut history = self.history.lock().unwrap();
+            let history = &mut *history;
+            let backlog = history.backlog.get_or_insert_with(|| Backlog::new(0));
+            self.active.store(true, Ordering::Relaxed);
+
+            let missed = resume.and_then(|(replid, from)| {
+                let known = replid == history.replid
+                    || (replid == history.replid2 && from as i64 <= history.second_offset);
+                if known { backlog.since(from) } else { None }
+            });
+
+            if let Some(missed) = missed {
+                let frames = parse_stream(missed)?;
+                self.sync_partial_ok.fetch_add(1, Ordering::Relaxed);
+                self.register(id, ip, sender);
+                return Ok(SyncReply::Continue {
+                    replid: history.replid.clone(),
+                    frames,
+                });
+            }
+        }
+
+        if resume.is_some() {
+            self.sync_partial_err.fetch_add(1, Ordering::Relaxed);
+        }
+        self.sync_full.fetch_add(1, Ordering::Relaxed);
+
+        // Writes not waiting, such as the pops of blocking commands and evictions, stream their
+        // changes while the key is locked, so the history can't be locked while capturing the
+        // dataset. If the offset didn't move meanwhile, the changes streamed up to it are the
+        // ones in the dataset. The replica is registered with the history locked, changes
+        // after the offset are streamed to it.
+        let (snapshot, replid, offset) = loop {
+            let before = self.offset();
+            let snapshot: Vec<_> = server.databases.iter().map(Db::snapshot).collect();
+
+            let history = self.history.lock().unwrap();
+            if history.offset() == before {
+                self.register(id, ip, sender);
+                break (snapshot, history.replid.clone(), before);
+            }
+        };
+        drop(writes);
+
+        let compression = server.config.snapshot_compression();
+        let dumped = task::spawn_blocking("replication-dump", move || {
+            persistence::dump(&snapshot, compression)
+        })
+        .await
+        .map_err(|err| WalrusError::Internal(err.to_string()))
+        .and_then(|dumped| dumped);
+        let snapshot = match dumped {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                // The replica is sent the error rather than the dataset, nothing is streamed
+                // to it.
+                self.remove_replica(id);
+                return Err(err);
+            }
+        };
+
+        Ok(SyncReply::FullResync {
+            replid,
+            offset,
+            snapshot,
+        })
+    }
+
+    /// Stream commands to connection `id` from now on, must be called with the history locked
+    /// so no command is missed or streamed twice.
+    fn register(&self, id: u64, ip: IpAddr, sender: PushSender) {
+        sender.set_class(ClientClass::Replica);
+        self.replicas.insert(
+            id,
+            Replica {
+                sender,
+                ip,
+                port: self.announced_ports.remove(&id).map(|(_, port)| port),
+                ack: None,
+            },
+        );
+    }
+
+    /// Returns `true` if the server replicates a master.
+    pub(crate) fn is_replica(&self) -> bool {
+        self.master.lock().unwrap().is_some()
+    }
+
+    /// Returns `true` once write commands are recorded for replicas.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if connection `id` is a replica of this server.
+    pub(crate) fn has_replica(&self, id: u64) -> bool {
+        self.replicas.contains_key(&id)
+    }
+
+    /// Stop streaming commands to connection `id`.
+    pub(crate) fn remove_replica(&self, id: u64) {
+        self.replicas.remove(&id);
+        self.announced_ports.remove(&id);
+    }
+
+    /// Record `port` as the port connection `id` accepts connections on, once it becomes a
+    /// replica.
+    pub(crate) fn announce_port(&self, id: u64, port: u16) {
+        self.announced_ports.insert(id, port);
+    }
+
+    /// Record that the replica on connection `id` applied the stream up to `offset`.
+    pub(crate) fn ack(&self, id: u64, offset: u64) {
+        if let Some(mut replica) = self.replicas.get_mut(&id) {
+            replica.ack = Some(offset);
+        }
+    }
+
+    /// Offset of the last byte of the stream of write commands.
+    fn offset(&self) -> u64 {
+        self.history.lock().unwrap().offset()
+    }
+
+    /// Stream the write commands `frames` to every replica and record them in the backlog,
+    /// keeping at most `backlog_size` bytes. No command is streamed between them.
+    ///
+    /// Relative expirations are sent as absolute unix times, so keys expire at the same time
+    /// on the replicas.
+    pub(crate) fn propagate(&self, frames: &[Frame], backlog_size: u64) {
+        if !self.is_active() {
+            return;
+        }
+
+        let frames: Vec<Frame> = frames
+            .iter()
+            .map(|frame| match frame {
+                Frame::Array(args) => match aof::absolute_expire(args) {
+                    Some(args) => Frame::Array(args),
+                    None => frame.clone(),
+                },
+                other => other.clone(),
+            })
+            .collect();
+
+        // Held while streaming, so replicas receive commands in the order of the backlog.
+        let mut history = self.history.lock().unwrap();
+        if let Some(backlog) = history.backlog.as_mut() {
+            for frame in &frames {
+                backlog.push(&frame.encode(), backlog_size as usize);
+            }
+        }
+
+        for replica in self.replicas.iter() {
+            for frame in &frames {
+                replica.sender.send(frame.clone());
+            }
+        }
+    }
+
+    /// Disconnect every replica, they reconnect and load the new dataset.
+    fn disconnect_replicas(&self, server: &ServerState) {
+        let ids: Vec<u64> = self.replicas.iter().map(|replica| *replica.key()).collect();
+
+        for id in ids {
+            self.replicas.remove(&id);
+            server.clients.kill(&KillFilter {
+                id: Some(id),
+                addr: None,
+                skip: None,
+            });
+        }
+    }
+
+    /// Replicate the master at `host:port`, replacing the current dataset with its own.
+    /// Replication from a previous master is stopped.
+    pub(crate) fn replicate(&self, host: String, port: u16, server: &Arc<ServerState>) {
+        self.follow_master(host, port, server, None);
+    }
+
+    /// Replicate the master at `host:port`. With `failover`, the master is asked to take over
+    /// this server as a master first, and whether it accepted is sent through `failover`.
+    fn follow_master(
+        &self,
+        host: String,
+        port: u16,
+        server: &Arc<ServerState>,
+        failover: Option<oneshot::Sender<bool>>,
+    ) {
+        let link_up = Arc::new(AtomicBool::new(false));
+        let task = task::spawn(
+            "replica",
+            replica_task(
+                format!("{host}:{port}"),
+                Arc::downgrade(server),
+                link_up.clone(),
+                failover,
+            ),
+        );
+
+        let previous = self.master.lock().unwrap().replace(Master {
+            host,
+            port,
+            link_up,
+            task,
+        });
+
+        if let Some(previous) = previous {
+            previous.task.abort();
+        }
+    }
+
+    /// Stop replicating, the server becomes a master keeping its current dataset.
+    pub(crate) fn stop(&self, server: &ServerState) {
+        if let Some(master) = self.master.lock().unwrap().take() {
+            master.task.abort();
+
+            // The dataset no longer follows the master, replicas of the same master can still
+            // resume from this server up to the current offset.
+            self.history.lock().unwrap().shift_replid(new_replid());
+        }
+
+        // A sync may have been interrupted.
+        server.loading.finish();
+    }
+
+    /// Id of the stream of write commands.
+    pub(crate) fn replid(&self) -> String {
+        self.history.lock().unwrap().replid.clone()
+    }
+
+    /// Id and offset to resume the stream of the master from, `None` without a previous
+    /// stream.
+    fn resume_point(&self) -> Option<(String, u64)> {
+        let history = self.history.lock().unwrap();
+        history
+            .backlog
+            .as_ref()
+            .map(|backlog| (history.replid.clone(), backlog.offset() + 1))
+    }
+
+    /// Follow the stream `replid` of the master from `offset`, after loading its snapshot.
+    fn follow(&self, replid: String, offset: u64) {
+        let mut history = self.history.lock().unwrap();
+        history.replid = replid;
+        history.replid2 = NO_REPLID.to_string();
+        history.second_offset = -1;
+        history.backlog = Some(Backlog::new(offset));
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    /// Resume the stream of the master, now named `replid`.
+    fn resume(&self, replid: String) {
+        let mut history = self.history.lock().unwrap();
+        if history.replid != replid {
+            history.shift_replid(replid);
+        }
+    }
+
+    /// Number of bytes in the replication backlog, 0 if there is no backlog.
+    pub(crate) fn backlog_histlen(&self) -> usize {
+        let history = self.history.lock().unwrap();
+        history
+            .backlog
+            .as_ref()
+            .map_or(0, |backlog| backlog.histlen())
+    }
+
+    /// Fields of the `replication` section of `INFO`, with a backlog of `backlog_size` bytes.
+    ///
+    /// Every replica is described by a `slave<n>` field, its address, state and offset.
+    pub(crate) fn info(&self, backlog_size: u64) -> Vec<(Cow<'static, str>, String)> {
+        let mut fields = Vec::new();
+
+        match &*self.master.lock().unwrap() {
+            Some(master) => {
+                let link_status = if master.link_up.load(Ordering::Relaxed) {
+                    "up"
+                } else {
+                    "down"
+                };
+
+                fields.push(("role".into(), "slave".to_string()));
+                fields.push(("master_host".into(), master.host.clone()));
+                fields.push(("master_port".into(), master.port.to_string()));
+                fields.push(("master_link_status".into(), link_status.to_string()));
+            }
+            None => fields.push(("role".into(), "master".to_string())),
+        }
+
+        fields.push(("connected_slaves".into(), self.replicas.len().to_string()));
+
+        // Replicas are listed in the order they connected.
+        let mut replicas: Vec<_> = self
+            .replicas
+            .iter()
+            .map(|entry| {
+                let replica = entry.value();
+                (*entry.key(), replica.ip, replica.port, replica.ack)
+            })
+            .collect();
+        replicas.sort_unstable_by_key(|replica| replica.0);
+
+        for (n, (_, ip, port, ack)) in replicas.into_iter().enumerate() {
+            fields.push((
+                format!("slave{n}").into(),
+                format!(
+                    "ip={ip},port={},state=online,offset={},lag=0",
+                    port.unwrap_or(0),
+                    ack.unwrap_or(0)
+                ),
+            ));
+        }
+        fields.push((
+            "master_failover_state".into(),
+            self.failover_state().name().to_string(),
+        ));
+
+        let history = self.history.lock().unwrap();
+        let (first_offset, histlen) = history.backlog.as_ref().map_or((0, 0), |backlog| {
+            (backlog.first_offset(), backlog.histlen())
+        });
+
+        fields.push(("master_replid".into(), history.replid.clone()));
+        fields.push(("master_replid2".into(), history.replid2.clone()));
+        fields.push(("master_repl_offset".into(), history.offset().to_string()));
+        fields.push((
+            "second_repl_offset".into(),
+            history.second_offset.to_string(),
+        ));
+        fields.push((
+            "repl_backlog_active".into(),
+            (history.backlog.is_some() as u8).to_string(),
+        ));
+        fields.push(("repl_backlog_size".into(), backlog_size.to_string()));
+        fields.push((
+            "repl_backlog_first_byte_offset".into(),
+            first_offset.to_string(),
+        ));
+        fields.push(("repl_backlog_histlen".into(), histlen.to_string()));
+        fields.push((
+            "sync_full".into(),
+            self.sync_full.load(Ordering::Relaxed).to_string(),
+        ));
+        fields.push((
+            "sync_partial_ok".into(),
+            self.sync_partial_ok.load(Ordering::Relaxed).to_string(),
+        ));
+        fields.push((
+            "sync_partial_err".into(),
+            self.sync_partial_err.load(Ordering::Relaxed).to_string(),
+        ));
+
+        fields
+    }
+}
+
+/// Keep the link with the master at `addr` until aborted, reconnecting when it's lost.
+async fn replica_task(
+    addr: String,
+    server: std::sync::Weak<ServerState>,
+    link_up: Arc<AtomicBool>,
+    mut failover: Option<oneshot::Sender<bool>>,
+) {
+    loop {
+        // The server is shutting down.
+        let Some(state) = server.upgrade() else {
+            return;
+        };
+
+        if let Err(err) = sync_with_master(&addr, &state, &link_up, &mut failover).await {
+            warn!(master = %addr, %err, "Lost the link with the master");
+        }
+
+        link_up.store(false, Ordering::Relaxed);
+        state.loading.finish();
+        drop(state);
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Resume the stream of the master at `addr` or load its dataset, then apply the commands it
+/// streams until the link is lost.
+///
+/// With `failover`, the master is asked to take over this server as a master, the outcome is
+/// sent through `failover` once it replied.
+async fn sync_with_master(
+    addr: &str,
+    server: &Arc<ServerState>,
+    link_up: &AtomicBool,
+    failover: &mut Option<oneshot::Sender<bool>>,
+) -> Result<(), WalrusError> {
+    let socket = TcpStream::connect(addr).await?;
+    let mut master = Connection::new(socket, None, None);
+
+    // The master needs the port of the replica to fail over to it.
+    let port = server.config.port();
+    master.write_frame(&Replconf::listening_port(port).into_frame());
+    match master.read_frame().await? {
+        Some(Frame::Simple(_)) => {}
+        Some(Frame::Error(err)) => return Err(err.into()),
+        Some(_) => return Err("unexpected reply to REPLCONF".into()),
+        None => return Err("connection closed by master".into()),
+    }
+
+    let mut psync = match server.replication.resume_point() {
+        Some((replid, offset)) => Psync::new(replid, offset as i64),
+        None => Psync::new("?", -1),
+    };
+    if failover.is_some() {
+        psync = psync.failover();
+    }
+    master.write_frame(&psync.into_frame());
+    master.flush().await?;
+
+    let reply = match master.read_frame().await? {
+        Some(Frame::Simple(reply)) => String::from_utf8_lossy(&reply).into_owned(),
+        Some(Frame::Error(err)) => {
+            if let Some(failover) = failover.take() {
+                let _ = failover.send(false);
+            }
+            return Err(err.into());
+        }
+        Some(_) => return Err("unexpected reply to PSYNC".into()),
+        None => return Err("connection closed by master".into()),
+    };
+
+    if let Some(failover) = failover.take() {
+        let _ = failover.send(true);
+    }
+
+    match reply.split(' ').collect::<Vec<_>>()[..] {
+        ["FULLRESYNC", replid, offset] => {
+            let offset = offset
+                .parse::<u64>()
+                .map_err(|_| "invalid offset in reply to PSYNC")?;
+
+            let snapshot = match master.read_frame().await? {
+                Some(Frame::Bulk(snapshot)) => snapshot,
+                Some(_) => return Err("unexpected snapshot from master".into()),
+                None => return Err("connection closed by master".into()),
+            };
+
+            load_snapshot(server, snapshot).await?;
+            server.replication.follow(replid.to_string(), offset);
+            info!(master = %addr, "Synchronized with the master");
+        }
+        ["CONTINUE", replid] => {
+            server.replication.resume(replid.to_string());
+            info!(master = %addr, "Resumed replication from the master");
+        }
+        _ => return Err(format!("unexpected reply to PSYNC, {reply}").into()),
+    }
+    link_up.store(true, Ordering::Relaxed);
+
+    let mut conn = Connection::detached();
+
+    master.write_frame(&Replconf::ack(server.replication.offset()).into_frame());
+
+    while let Some(frame) = master.read_frame().await? {
+        let kept_frame = frame.clone();
+        let _writes = server.replication.write_guard().await;
+
+        // The changes made are logged to the append only file by the changelog consumer, the
+        // commands are relayed to the replicas of this server as received.
+        aof::replay(server, &mut conn, frame).await?;
+        server.persistence.changed();
+        server.replication.propagate(
+            std::slice::from_ref(&kept_frame),
+            server.config.repl_backlog_size(),
+        );
+
+        // Acknowledge the commands applied once the ones received are drained, the ack is
+        // sent before waiting for more.
+        if !master.has_buffered_frame() {
+            master.write_frame(&Replconf::ack(server.replication.offset()).into_frame());
+        }
+    }
+
+    Err("connection closed by master".into())
+}
+
+/// Split the stream of write commands `bytes` into frames.
+fn parse_stream(bytes: Vec<u8>) -> Result<Vec<Frame>, WalrusError> {
+    let mut bytes = Bytes::from(bytes);
+    let mut frames = Vec::new();
+
+    while !bytes.is_empty() {
+        let len = Frame::check(&mut Cursor::new(&bytes[..]))?;
+        frames.push(Frame::parse(&mut bytes.split_to(len))?);
+    }
+
+    Ok(frames)
+}
+
+/// Generate a random replication id of 40 hex characters.
+fn new_replid() -> String {
+    let mut rng = rand::rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.random_range(0..16), 16).unwrap())
+        .collect()
+}
+
+/// Replace the dataset with the `snapshot` of the master, commands touching the dataset are
+/// refused meanwhile.
+async fn load_snapshot(server: &Arc<ServerState>, snapshot: Bytes) -> Result<(), WalrusError> {
+    server.loading.start(snapshot.len() as u64);
+
+    // Replicas of this server can't follow, they have to load the new dataset.
+    server.replication.disconnect_replicas(server);
+
+    let state = server.clone();
+    task::spawn_blocking("load-master-snapshot", move || {
+        for db in &state.databases {
+            db.clear();
+        }
+        let keys = persistence::load_bytes(&state.databases, &snapshot, &state.loading)?;
+        info!(keys, "Loaded the dataset of the master");
+
+        // The append only file must describe the new dataset alone.
+        let config = &state.config;
+        if state.aof.is_enabled() {
+            aof::rewrite(&state.databases, &config.aof_path())?;
+            state.aof.open(&config.aof_path())?;
+        }
+
+        Ok::<_, WalrusError>(())
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    server.persistence.changed();
+    server.loading.finish();
+
+    Ok(())
+}
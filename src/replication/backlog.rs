@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+/// Replication backlog, the most recent bytes of the stream of write commands sent to
+/// replicas.
+///
+/// Every byte of the stream has an offset, starting from 1. A replica reconnecting with the
+/// offset it has processed up to resumes from the backlog if the bytes it misses are still
+/// there.
+pub(crate) struct Backlog {
+    buf: VecDeque<u8>,
+    /// Offset of the last byte of the stream.
+    offset: u64,
+}
+
+impl Backlog {
+    /// Create an empty backlog, the next byte of the stream has offset `offset + 1`.
+    pub(crate) fn new(offset: u64) -> Backlog {
+        Backlog {
+            buf: VecDeque::new(),
+            offset,
+        }
+    }
+
+    /// Offset of the last byte of the stream.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Number of bytes in the backlog.
+    pub(crate) fn histlen(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Offset of the first byte in the backlog.
+    pub(crate) fn first_offset(&self) -> u64 {
+        self.offset + 1 - self.buf.len() as u64
+    }
+
+    /// Append `bytes` to the stream, keeping at most the last `size` bytes.
+    pub(crate) fn push(&mut self, bytes: &[u8], size: usize) {
+        self.buf.extend(bytes);
+        self.offset += bytes.len() as u64;
+
+        if self.buf.len() > size {
+            self.buf.drain(..self.buf.len() - size);
+        }
+    }
+
+    /// Bytes of the stream from offset `from` to the end, `None` if some of them are no longer
+    /// in the backlog.
+    pub(crate) fn since(&self, from: u64) -> Option<Vec<u8>> {
+        if from < self.first_offset() || from > self.offset + 1 {
+            return None;
+        }
+
+        let skip = (from - self.first_offset()) as usize;
+        Some(self.buf.range(skip..).copied().collect())
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    net::IpAddr,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
+use tokio::{
+    sync::oneshot,
+    time::{self, Instant},
+};
+use tracing::{info, warn};
+
+use super::Replication;
+use crate::{pause::PauseMode, server::ServerState, task};
+
+/// Interval between checks of the offset acknowledged by the replicas.
+const CATCH_UP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Writes are paused for at most this long while failing over without a timeout.
+const MAX_PAUSE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Step of a coordinated failover started with `FAILOVER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum FailoverState {
+    /// No failover in progress.
+    None = 0,
+    /// Writes are paused until a replica applied every write command.
+    WaitingForSync = 1,
+    /// The server is handing over to the replica and becoming its replica.
+    InProgress = 2,
+}
+
+impl FailoverState {
+    fn from_u8(state: u8) -> FailoverState {
+        match state {
+            1 => FailoverState::WaitingForSync,
+            2 => FailoverState::InProgress,
+            _ => FailoverState::None,
+        }
+    }
+
+    /// Name of the state in `INFO`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            FailoverState::None => "no-failover",
+            FailoverState::WaitingForSync => "waiting-for-sync",
+            FailoverState::InProgress => "failover-in-progress",
+        }
+    }
+}
+
+/// Replica chosen with `FAILOVER TO host port`.
+#[derive(Debug, Clone)]
+pub(crate) struct FailoverTarget {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Fail over once the timeout is reached even if the replica didn't catch up.
+    pub(crate) force: bool,
+}
+
+impl Replication {
+    /// Step of the failover in progress.
+    pub(crate) fn failover_state(&self) -> FailoverState {
+        FailoverState::from_u8(self.failover_state.load(Ordering::Relaxed))
+    }
+
+    fn set_failover_state(&self, state: FailoverState) {
+        self.failover_state.store(state as u8, Ordering::Relaxed);
+    }
+
+    /// Start handing over to `target`, or to the first replica to catch up, in the background.
+    ///
+    /// Writes are paused until a replica applied every write command, then the replica is
+    /// promoted and this server becomes its replica. Without a replica caught up within
+    /// `timeout` the failover is aborted, unless forced. Returns the error message if the
+    /// failover can't start.
+    pub(crate) fn start_failover(
+        &self,
+        target: Option<FailoverTarget>,
+        timeout: Option<Duration>,
+        server: &Arc<ServerState>,
+    ) -> Result<(), &'static str> {
+        if self.is_replica() {
+            return Err("FAILOVER is not valid when server is a replica.");
+        }
+        if self.replicas.is_empty() {
+            return Err("FAILOVER requires connected replicas.");
+        }
+
+        let mut failover = self.failover.lock().unwrap();
+        if failover.is_some() {
+            return Err("FAILOVER already in progress.");
+        }
+
+        if let Some(target) = &target
+            && !self
+                .replicas
+                .iter()
+                .any(|replica| replica.is(&target.host, target.port))
+        {
+            return Err("FAILOVER target HOST and PORT is not a replica.");
+        }
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        server.pause.pause(
+            deadline.unwrap_or_else(|| Instant::now() + MAX_PAUSE),
+            PauseMode::Write,
+        );
+        self.set_failover_state(FailoverState::WaitingForSync);
+
+        *failover = Some(task::spawn(
+            "failover",
+            failover_task(target, deadline, server.clone()),
+        ));
+
+        Ok(())
+    }
+
+    /// Abort the failover in progress, the server stays or becomes a master again. Returns
+    /// `false` if no failover is in progress.
+    pub(crate) fn abort_failover(&self, server: &ServerState) -> bool {
+        let Some(task) = self.failover.lock().unwrap().take() else {
+            return false;
+        };
+        task.abort();
+
+        if self.failover_state() == FailoverState::InProgress {
+            self.stop(server);
+        }
+        self.end_failover(server);
+
+        true
+    }
+
+    /// Returns `true` while a failover is in progress.
+    pub(crate) fn is_failing_over(&self) -> bool {
+        self.failover_state() != FailoverState::None
+    }
+
+    /// Resume writes once the failover ended.
+    fn end_failover(&self, server: &ServerState) {
+        self.set_failover_state(FailoverState::None);
+        server.pause.unpause();
+    }
+}
+
+impl super::Replica {
+    /// Returns `true` if the replica accepts connections on `host:port`.
+    fn is(&self, host: &str, port: u16) -> bool {
+        self.port == Some(port) && host.parse::<IpAddr>().is_ok_and(|host| host == self.ip)
+    }
+}
+
+/// Wait for `target`, or any replica, to catch up then hand over to it.
+async fn failover_task(
+    target: Option<FailoverTarget>,
+    deadline: Option<Instant>,
+    server: Arc<ServerState>,
+) {
+    let replication = &server.replication;
+
+    let (host, port) = loop {
+        let offset = replication.offset();
+        let caught_up = replication.replicas.iter().find_map(|replica| {
+            let port = replica.port?;
+            let chosen = target
+                .as_ref()
+                .is_none_or(|target| replica.is(&target.host, target.port));
+
+            (chosen && replica.ack.is_some_and(|ack| ack >= offset))
+                .then(|| (replica.ip.to_string(), port))
+        });
+
+        if let Some(replica) = caught_up {
+            break replica;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            match &target {
+                Some(target) if target.force => break (target.host.clone(), target.port),
+                _ => {
+                    warn!("FAILOVER timed out, no replica caught up");
+                    replication.failover.lock().unwrap().take();
+                    replication.end_failover(&server);
+                    return;
+                }
+            }
+        }
+
+        time::sleep(CATCH_UP_INTERVAL).await;
+    };
+
+    replication.set_failover_state(FailoverState::InProgress);
+    info!(%host, port, "FAILOVER handing over to the replica");
+
+    let (accepted, outcome) = oneshot::channel();
+    replication.follow_master(host.clone(), port, &server, Some(accepted));
+
+    if outcome.await.unwrap_or(false) {
+        info!(%host, port, "FAILOVER succeeded");
+    } else {
+        warn!(%host, port, "FAILOVER refused, the server stays a master");
+        replication.stop(&server);
+    }
+
+    replication.failover.lock().unwrap().take();
+    replication.end_failover(&server);
+}
@@ -0,0 +1,466 @@
+mod monitor;
+
+use bytes::Bytes;
+use rand::RngExt;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::Instant};
+use tracing::info;
+
+use crate::{frame::Frame, server::ServerState, task};
+
+/// Time a master may not reply to pings before it's considered down, unless set with
+/// `SENTINEL SET ... down-after-milliseconds`.
+const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+
+/// Time a failover may take before another one can be started for the same master, unless set
+/// with `SENTINEL SET ... failover-timeout`.
+const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Monitoring state of a server started in sentinel mode.
+///
+/// Every master added with `SENTINEL MONITOR` is pinged by a background task, which also
+/// learns its replicas from `INFO replication` and the other sentinels monitoring it from the
+/// names they set on their connections to it. A master not replying within `down-after` is
+/// subjectively down. Once at least `quorum` sentinels agree, it's objectively down and the
+/// sentinels elect a leader for a new epoch, the leader promotes the replica with the greatest
+/// offset and the others switch to it when told the new configuration with `SENTINEL HELLO`.
+pub(crate) struct Sentinel {
+    /// Run id of this sentinel.
+    myid: String,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Greatest epoch seen, a failover happens in a new epoch.
+    current_epoch: u64,
+    /// Map of name to monitored master.
+    masters: HashMap<String, Master>,
+}
+
+/// A master monitored by this sentinel.
+struct Master {
+    host: String,
+    port: u16,
+    /// Number of sentinels that must agree the master is down to fail it over.
+    quorum: u64,
+    down_after: Duration,
+    failover_timeout: Duration,
+    /// Epoch of the failover that made this master, configurations with a greater epoch win.
+    config_epoch: u64,
+    /// Instant of the last valid reply to a ping.
+    last_reply: Instant,
+    /// `true` if the last ping got a valid reply. A master replying to pings isn't down even
+    /// if its replies are further apart than `down_after`, as when pinging other servers
+    /// delays the next ping.
+    ping_ok: bool,
+    /// `true` once `quorum` sentinels agree the master is down.
+    odown: bool,
+    /// Replicas of the master, as `(host, port)`, with the offset they last acknowledged.
+    replicas: HashMap<(String, u16), u64>,
+    /// Map of run id to address of the other sentinels monitoring the master.
+    sentinels: HashMap<String, (IpAddr, u16)>,
+    /// Sentinel voted for as the leader of the failover in `leader_epoch`.
+    leader: Option<String>,
+    leader_epoch: u64,
+    /// Instant the last failover was started or voted for, no failover is started for twice
+    /// the failover timeout after it.
+    failover_start: Option<Instant>,
+    /// Task pinging the master.
+    task: JoinHandle<()>,
+}
+
+/// Change of the configuration of a master told with `SENTINEL HELLO`.
+pub(crate) struct Hello {
+    pub(crate) ip: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) runid: String,
+    pub(crate) current_epoch: u64,
+    pub(crate) master_name: String,
+    pub(crate) master_host: String,
+    pub(crate) master_port: u16,
+    pub(crate) master_config_epoch: u64,
+}
+
+impl Sentinel {
+    pub(crate) fn new() -> Sentinel {
+        Sentinel {
+            myid: new_run_id(),
+            state: Mutex::new(State {
+                current_epoch: 0,
+                masters: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Run id of this sentinel.
+    pub(crate) fn myid(&self) -> &str {
+        &self.myid
+    }
+
+    /// Start monitoring the master at `host:port` as `name`. Returns the error message on
+    /// failure.
+    pub(crate) fn monitor(
+        &self,
+        name: String,
+        host: String,
+        port: u16,
+        quorum: u64,
+        server: &Arc<ServerState>,
+    ) -> Result<(), String> {
+        if quorum == 0 {
+            return Err("Quorum must be 1 or greater.".to_string());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.masters.contains_key(&name) {
+            return Err("Duplicated master name".to_string());
+        }
+
+        let task = task::spawn(
+            "sentinel-monitor",
+            monitor::monitor(name.clone(), Arc::downgrade(server)),
+        );
+        state.masters.insert(
+            name,
+            Master {
+                host,
+                port,
+                quorum,
+                down_after: DEFAULT_DOWN_AFTER,
+                failover_timeout: DEFAULT_FAILOVER_TIMEOUT,
+                config_epoch: 0,
+                last_reply: Instant::now(),
+                ping_ok: true,
+                odown: false,
+                replicas: HashMap::new(),
+                sentinels: HashMap::new(),
+                leader: None,
+                leader_epoch: 0,
+                failover_start: None,
+                task,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Stop monitoring the master `name`. Returns `false` if it isn't monitored.
+    pub(crate) fn remove(&self, name: &[u8]) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        match state.masters.remove(&*String::from_utf8_lossy(name)) {
+            Some(master) => {
+                master.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Change an option of the master `name`. Returns the error message on failure.
+    pub(crate) fn set(&self, name: &[u8], option: &[u8], value: &[u8]) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let name = String::from_utf8_lossy(name);
+        let Some(master) = state.masters.get_mut(&*name) else {
+            return Err("No such master with that name".to_string());
+        };
+
+        let invalid = || {
+            format!(
+                "Invalid argument '{}' for SENTINEL SET '{name}'",
+                String::from_utf8_lossy(value)
+            )
+        };
+        let value: u64 = std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0)
+            .ok_or_else(invalid)?;
+
+        if option.eq_ignore_ascii_case(b"down-after-milliseconds") {
+            master.down_after = Duration::from_millis(value);
+        } else if option.eq_ignore_ascii_case(b"failover-timeout") {
+            master.failover_timeout = Duration::from_millis(value);
+        } else if option.eq_ignore_ascii_case(b"quorum") {
+            master.quorum = value;
+        } else {
+            return Err(format!(
+                "Invalid argument '{}' for SENTINEL SET '{name}'",
+                String::from_utf8_lossy(option)
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Address of the master `name` as `[ip, port]`, `Null` if it isn't monitored.
+    pub(crate) fn master_addr(&self, name: &[u8]) -> Frame {
+        let state = self.state.lock().unwrap();
+
+        match state.masters.get(&*String::from_utf8_lossy(name)) {
+            Some(master) => Frame::Array(vec![
+                Frame::Bulk(Bytes::from(master.host.clone())),
+                Frame::Bulk(Bytes::from(master.port.to_string())),
+            ]),
+            None => Frame::Null,
+        }
+    }
+
+    /// Description of every monitored master, the reply of `SENTINEL MASTERS`.
+    pub(crate) fn masters(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        let mut names: Vec<_> = state.masters.keys().collect();
+        names.sort_unstable();
+
+        Frame::Array(
+            names
+                .into_iter()
+                .map(|name| describe_master(name, &state.masters[name]))
+                .collect(),
+        )
+    }
+
+    /// Description of the master `name`, `None` if it isn't monitored.
+    pub(crate) fn master(&self, name: &[u8]) -> Option<Frame> {
+        let state = self.state.lock().unwrap();
+        let name = String::from_utf8_lossy(name);
+
+        state
+            .masters
+            .get(&*name)
+            .map(|master| describe_master(&name, master))
+    }
+
+    /// Description of the replicas of the master `name`, `None` if it isn't monitored.
+    pub(crate) fn replicas(&self, name: &[u8]) -> Option<Frame> {
+        let state = self.state.lock().unwrap();
+        let master = state.masters.get(&*String::from_utf8_lossy(name))?;
+        let mut replicas: Vec<_> = master.replicas.iter().collect();
+        replicas.sort_unstable();
+
+        Some(Frame::Array(
+            replicas
+                .into_iter()
+                .map(|((host, port), offset)| {
+                    describe(vec![
+                        ("name", Frame::Bulk(Bytes::from(format!("{host}:{port}")))),
+                        ("ip", Frame::Bulk(Bytes::from(host.clone()))),
+                        ("port", Frame::Integer(*port as i64)),
+                        ("flags", Frame::Bulk(Bytes::from("slave"))),
+                        ("slave-repl-offset", Frame::Integer(*offset as i64)),
+                    ])
+                })
+                .collect(),
+        ))
+    }
+
+    /// Description of the other sentinels monitoring the master `name`, `None` if it isn't
+    /// monitored.
+    pub(crate) fn sentinels(&self, name: &[u8]) -> Option<Frame> {
+        let state = self.state.lock().unwrap();
+        let master = state.masters.get(&*String::from_utf8_lossy(name))?;
+        let mut sentinels: Vec<_> = master.sentinels.iter().collect();
+        sentinels.sort_unstable();
+
+        Some(Frame::Array(
+            sentinels
+                .into_iter()
+                .map(|(runid, (ip, port))| {
+                    describe(vec![
+                        ("name", Frame::Bulk(Bytes::from(runid.clone()))),
+                        ("ip", Frame::Bulk(Bytes::from(ip.to_string()))),
+                        ("port", Frame::Integer(*port as i64)),
+                        ("runid", Frame::Bulk(Bytes::from(runid.clone()))),
+                        ("flags", Frame::Bulk(Bytes::from("sentinel"))),
+                    ])
+                })
+                .collect(),
+        ))
+    }
+
+    /// Whether the master at `host:port` is down for this sentinel, the reply of
+    /// `SENTINEL IS-MASTER-DOWN-BY-ADDR`.
+    ///
+    /// If `runid` isn't `*` the sentinel asking is also requesting a vote to lead the failover
+    /// of `epoch`. The vote is granted to the first one asking in an epoch, the reply holds
+    /// the run id and epoch of the leader voted for.
+    pub(crate) fn is_master_down(&self, host: &str, port: u16, epoch: u64, runid: &str) -> Frame {
+        let mut state = self.state.lock().unwrap();
+        let mut reply = (false, "*".to_string(), 0);
+
+        state.current_epoch = state.current_epoch.max(epoch);
+
+        if let Some(master) = state
+            .masters
+            .values_mut()
+            .find(|master| master.host == host && master.port == port)
+        {
+            reply.0 = master.sdown();
+
+            if runid != "*" {
+                if reply.0 && master.leader_epoch < epoch {
+                    master.leader = Some(runid.to_string());
+                    master.leader_epoch = epoch;
+                    master.failover_start = Some(Instant::now());
+                }
+                reply.1 = master.leader.clone().unwrap_or_else(|| "*".to_string());
+                reply.2 = master.leader_epoch;
+            }
+        }
+
+        Frame::Array(vec![
+            Frame::Integer(reply.0 as i64),
+            Frame::Bulk(Bytes::from(reply.1)),
+            Frame::Integer(reply.2 as i64),
+        ])
+    }
+
+    /// Apply a `SENTINEL HELLO` from another sentinel. The sentinel is added to those
+    /// monitoring the master, and the master is replaced if the configuration told is newer.
+    pub(crate) fn hello(&self, hello: Hello) {
+        let mut state = self.state.lock().unwrap();
+        state.current_epoch = state.current_epoch.max(hello.current_epoch);
+
+        let Some(master) = state.masters.get_mut(&hello.master_name) else {
+            return;
+        };
+        if hello.runid != self.myid {
+            master.sentinels.insert(hello.runid, (hello.ip, hello.port));
+        }
+
+        if hello.master_config_epoch > master.config_epoch {
+            info!(
+                master = %hello.master_name,
+                from = format_args!("{}:{}", master.host, master.port),
+                to = format_args!("{}:{}", hello.master_host, hello.master_port),
+                "Switching the master, told by a sentinel"
+            );
+            master.switch(
+                hello.master_host,
+                hello.master_port,
+                hello.master_config_epoch,
+            );
+        }
+    }
+
+    /// Fields of the `sentinel` section of `INFO`.
+    pub(crate) fn info(&self) -> Vec<(String, String)> {
+        let state = self.state.lock().unwrap();
+        let mut names: Vec<_> = state.masters.keys().collect();
+        names.sort_unstable();
+
+        let mut fields = vec![
+            ("sentinel_masters".to_string(), names.len().to_string()),
+            (
+                "sentinel_current_epoch".to_string(),
+                state.current_epoch.to_string(),
+            ),
+        ];
+
+        for (n, name) in names.into_iter().enumerate() {
+            let master = &state.masters[name];
+            fields.push((
+                format!("master{n}"),
+                format!(
+                    "name={name},status={},address={}:{},slaves={},sentinels={}",
+                    master.status(),
+                    master.host,
+                    master.port,
+                    master.replicas.len(),
+                    master.sentinels.len() + 1
+                ),
+            ));
+        }
+
+        fields
+    }
+}
+
+impl Master {
+    /// `true` if the master didn't reply to pings for `down_after`.
+    fn sdown(&self) -> bool {
+        !self.ping_ok && self.last_reply.elapsed() > self.down_after
+    }
+
+    fn status(&self) -> &'static str {
+        match (self.sdown(), self.odown) {
+            (_, true) => "odown",
+            (true, false) => "sdown",
+            (false, false) => "ok",
+        }
+    }
+
+    /// Replace the master with its replica at `host:port`, the configuration of
+    /// `config_epoch`. The previous master is expected to come back as a replica.
+    fn switch(&mut self, host: String, port: u16, config_epoch: u64) {
+        let previous = (
+            std::mem::replace(&mut self.host, host),
+            std::mem::replace(&mut self.port, port),
+        );
+
+        self.replicas.remove(&(self.host.clone(), self.port));
+        self.replicas.insert(previous, 0);
+        self.config_epoch = config_epoch;
+        self.last_reply = Instant::now();
+        self.ping_ok = true;
+        self.odown = false;
+    }
+}
+
+/// Description of a master, the replies of `SENTINEL MASTER` and `SENTINEL MASTERS`.
+fn describe_master(name: &str, master: &Master) -> Frame {
+    let flags = match master.status() {
+        "odown" => "master,s_down,o_down",
+        "sdown" => "master,s_down",
+        _ => "master",
+    };
+
+    describe(vec![
+        ("name", Frame::Bulk(Bytes::from(name.to_string()))),
+        ("ip", Frame::Bulk(Bytes::from(master.host.clone()))),
+        ("port", Frame::Integer(master.port as i64)),
+        ("flags", Frame::Bulk(Bytes::from(flags))),
+        (
+            "last-ok-ping-reply",
+            Frame::Integer(master.last_reply.elapsed().as_millis() as i64),
+        ),
+        (
+            "down-after-milliseconds",
+            Frame::Integer(master.down_after.as_millis() as i64),
+        ),
+        ("num-slaves", Frame::Integer(master.replicas.len() as i64)),
+        (
+            "num-other-sentinels",
+            Frame::Integer(master.sentinels.len() as i64),
+        ),
+        ("quorum", Frame::Integer(master.quorum as i64)),
+        (
+            "failover-timeout",
+            Frame::Integer(master.failover_timeout.as_millis() as i64),
+        ),
+        ("config-epoch", Frame::Integer(master.config_epoch as i64)),
+    ])
+}
+
+/// Map of `fields`, a flat array of field and value pairs for RESP2 connections.
+fn describe(fields: Vec<(&str, Frame)>) -> Frame {
+    Frame::Map(
+        fields
+            .into_iter()
+            .map(|(field, value)| (Frame::Bulk(Bytes::from(field.to_string())), value))
+            .collect(),
+    )
+}
+
+/// Generate a random run id of 40 hex characters.
+fn new_run_id() -> String {
+    let mut rng = rand::rng();
+    (0..40)
+        .map(|_| char::from_digit(rng.random_range(0..16), 16).unwrap())
+        .collect()
+}
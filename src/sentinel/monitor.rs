@@ -0,0 +1,439 @@
+use bytes::Bytes;
+use rand::RngExt;
+use std::{collections::HashMap, net::IpAddr, sync::Weak, time::Duration};
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+use super::Sentinel;
+use crate::{client::Client, errors::WalrusError, server::ServerState};
+
+/// Interval between pings of a master, shorter if the master is considered down sooner.
+const PING_PERIOD: Duration = Duration::from_secs(1);
+
+/// State of a master captured at the start of a round, the state itself isn't locked while
+/// talking to other servers.
+struct View {
+    host: String,
+    port: u16,
+    quorum: u64,
+    down_after: Duration,
+    current_epoch: u64,
+    config_epoch: u64,
+    /// Replicas ordered by decreasing offset, the best candidates for a promotion first.
+    replicas: Vec<(String, u16)>,
+    sentinels: Vec<(IpAddr, u16)>,
+}
+
+/// Connections to the master, its replicas and the other sentinels, by address.
+struct Links {
+    /// Name set on every connection, tells the other sentinels how to reach this one.
+    name: Bytes,
+    clients: HashMap<String, Client>,
+}
+
+/// Monitor the master `name` until it's removed or the server shuts down.
+pub(super) async fn monitor(name: String, server: Weak<ServerState>) {
+    let mut links = Links {
+        name: Bytes::new(),
+        clients: HashMap::new(),
+    };
+
+    loop {
+        let Some(state) = server.upgrade() else {
+            return;
+        };
+        let Some(sentinel) = &state.sentinel else {
+            return;
+        };
+
+        links.name = Bytes::from(format!(
+            "sentinel-{}-{}",
+            sentinel.myid,
+            state.config.port()
+        ));
+
+        let Some(period) = round(sentinel, &name, state.config.port(), &mut links).await else {
+            return;
+        };
+
+        drop(state);
+        time::sleep(period).await;
+    }
+}
+
+/// Ping the master `name` and act on its state. Returns the time until the next round, `None`
+/// if the master isn't monitored anymore.
+async fn round(
+    sentinel: &Sentinel,
+    name: &str,
+    my_port: u16,
+    links: &mut Links,
+) -> Option<Duration> {
+    let view = sentinel.view(name)?;
+    let period = view.down_after.min(PING_PERIOD);
+    let master_addr = format!("{}:{}", view.host, view.port);
+
+    // Learn the replicas of the master and the sentinels monitoring it while it replies.
+    let replied = links
+        .call(&master_addr, period, async |c| c.ping(None).await)
+        .await
+        .is_some();
+    sentinel.pinged(name, &view.host, view.port, replied);
+    if replied {
+        if let Some(info) = links
+            .call(&master_addr, period, async |c| {
+                c.info(vec![Bytes::from("replication")]).await
+            })
+            .await
+        {
+            sentinel.add_replicas(name, &view.host, view.port, parse_replicas(&info));
+        }
+        if let Some(list) = links
+            .call(&master_addr, period, async |c| c.client_list().await)
+            .await
+        {
+            sentinel.add_sentinels(name, &sentinel.myid, parse_sentinels(&list));
+        }
+
+        // Replicas not following the master, such as a previous master that came back, are
+        // told to follow it.
+        for (host, port) in &view.replicas {
+            let addr = format!("{host}:{port}");
+            let Some(info) = links
+                .call(&addr, period, async |c| {
+                    c.info(vec![Bytes::from("replication")]).await
+                })
+                .await
+            else {
+                continue;
+            };
+
+            let follows = info_field(&info, "role").as_deref() == Some("slave")
+                && info_field(&info, "master_host").as_deref() == Some(view.host.as_str())
+                && info_field(&info, "master_port") == Some(view.port.to_string());
+            if !follows {
+                info!(replica = %addr, master = %master_addr, "Reconfiguring a replica");
+                let host = Bytes::from(view.host.clone());
+                links
+                    .call(&addr, period, async |c| c.replicaof(host, view.port).await)
+                    .await;
+            }
+        }
+    }
+
+    // Tell the other sentinels the configuration of the master, and that this one monitors it.
+    for (ip, port) in &view.sentinels {
+        let addr = format!("{ip}:{port}");
+        let runid = Bytes::from(sentinel.myid.clone());
+        let master_name = Bytes::from(name.to_string());
+        let host = Bytes::from(view.host.clone());
+        links
+            .call(&addr, period, async |c| {
+                c.sentinel_hello(
+                    my_port,
+                    runid,
+                    view.current_epoch,
+                    master_name,
+                    host,
+                    view.port,
+                    view.config_epoch,
+                )
+                .await
+            })
+            .await;
+    }
+
+    if !sentinel.is_sdown(name) {
+        sentinel.set_odown(name, false);
+        return Some(period);
+    }
+
+    // The master is objectively down once enough sentinels agree.
+    let mut agreeing = 1;
+    for (ip, port) in &view.sentinels {
+        let addr = format!("{ip}:{port}");
+        let host = Bytes::from(view.host.clone());
+        let reply = links
+            .call(&addr, period, async |c| {
+                c.sentinel_is_master_down_by_addr(host, view.port, view.current_epoch, "*".into())
+                    .await
+            })
+            .await;
+        if let Some((true, ..)) = reply {
+            agreeing += 1;
+        }
+    }
+
+    let odown = agreeing >= view.quorum;
+    sentinel.set_odown(name, odown);
+    if !odown {
+        return Some(period);
+    }
+
+    if !sentinel.may_fail_over(name) {
+        return Some(period);
+    }
+
+    // Sentinels noticing the failure at the same time would split the votes, each one waits
+    // a little before asking for them.
+    let delay = period.mul_f64(rand::rng().random_range(0.0..1.0));
+    time::sleep(delay).await;
+
+    let Some(epoch) = sentinel.start_election(name) else {
+        return Some(period);
+    };
+    let mut votes = 1;
+    for (ip, port) in &view.sentinels {
+        let addr = format!("{ip}:{port}");
+        let host = Bytes::from(view.host.clone());
+        let runid = Bytes::from(sentinel.myid.clone());
+        let reply = links
+            .call(&addr, period, async |c| {
+                c.sentinel_is_master_down_by_addr(host, view.port, epoch, runid)
+                    .await
+            })
+            .await;
+        if let Some((_, leader, leader_epoch)) = reply
+            && leader == sentinel.myid.as_bytes()
+            && leader_epoch == epoch
+        {
+            votes += 1;
+        }
+    }
+
+    // A majority of the sentinels monitoring the master, this one included.
+    let monitoring = view.sentinels.len() as u64 + 1;
+    let majority = monitoring / 2 + 1;
+    if votes < view.quorum.max(majority) {
+        info!(master = %name, epoch, "Lost the election to fail over the master");
+        return Some(period);
+    }
+
+    // Promote the replica with the greatest offset that accepts to.
+    for (host, port) in &view.replicas {
+        let addr = format!("{host}:{port}");
+        if links
+            .call(&addr, period, async |c| c.replicaof_no_one().await)
+            .await
+            .is_some()
+        {
+            info!(
+                master = %name,
+                from = %master_addr,
+                to = %addr,
+                epoch,
+                "Failed over the master"
+            );
+            sentinel.promote(name, host.clone(), *port, epoch);
+            return Some(Duration::ZERO);
+        }
+    }
+
+    warn!(master = %name, "No replica of the master could be promoted");
+    Some(period)
+}
+
+impl Links {
+    /// Run `call` on the connection to `addr`, connecting first if needed. Returns `None` if
+    /// the server didn't reply with success within `timeout`, the connection is dropped then.
+    async fn call<T>(
+        &mut self,
+        addr: &str,
+        timeout: Duration,
+        call: impl AsyncFnOnce(&mut Client) -> Result<T, WalrusError>,
+    ) -> Option<T> {
+        if !self.clients.contains_key(addr) {
+            let client = time::timeout(timeout, async {
+                let mut client = Client::connect(addr, None, None).await?;
+                client.client_setname(self.name.clone()).await?;
+                Ok::<_, WalrusError>(client)
+            })
+            .await;
+
+            match client {
+                Ok(Ok(client)) => {
+                    self.clients.insert(addr.to_string(), client);
+                }
+                _ => return None,
+            }
+        }
+
+        let deadline = Instant::now() + timeout;
+        let client = self.clients.get_mut(addr)?;
+        let result = time::timeout_at(deadline, call(client)).await;
+
+        match result {
+            Ok(Ok(value)) => Some(value),
+            _ => {
+                self.clients.remove(addr);
+                None
+            }
+        }
+    }
+}
+
+impl Sentinel {
+    /// State of the master `name`, `None` if it isn't monitored.
+    fn view(&self, name: &str) -> Option<View> {
+        let state = self.state.lock().unwrap();
+        let master = state.masters.get(name)?;
+
+        let mut replicas: Vec<_> = master.replicas.iter().collect();
+        replicas.sort_unstable_by_key(|(_, offset)| std::cmp::Reverse(**offset));
+
+        Some(View {
+            host: master.host.clone(),
+            port: master.port,
+            quorum: master.quorum,
+            down_after: master.down_after,
+            current_epoch: state.current_epoch,
+            config_epoch: master.config_epoch,
+            replicas: replicas.into_iter().map(|(addr, _)| addr.clone()).collect(),
+            sentinels: master.sentinels.values().copied().collect(),
+        })
+    }
+
+    /// Record whether the master `name` `replied` to a ping, unless it was replaced at
+    /// `host:port` meanwhile.
+    fn pinged(&self, name: &str, host: &str, port: u16, replied: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(master) = state.masters.get_mut(name)
+            && master.host == host
+            && master.port == port
+        {
+            master.ping_ok = replied;
+            if replied {
+                master.last_reply = Instant::now();
+            }
+        }
+    }
+
+    /// Record the `replicas` listed by the master `name` at `host:port`. Replicas not listed
+    /// are kept, they may be down and need to be reconfigured once back.
+    fn add_replicas(&self, name: &str, host: &str, port: u16, replicas: Vec<((String, u16), u64)>) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(master) = state.masters.get_mut(name)
+            && master.host == host
+            && master.port == port
+        {
+            master.replicas.extend(replicas);
+        }
+    }
+
+    /// Record the `sentinels` connected to the master `name`, except this one.
+    fn add_sentinels(&self, name: &str, myid: &str, sentinels: Vec<(String, (IpAddr, u16))>) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(master) = state.masters.get_mut(name) {
+            master
+                .sentinels
+                .extend(sentinels.into_iter().filter(|(runid, _)| runid != myid));
+        }
+    }
+
+    fn is_sdown(&self, name: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.masters.get(name).is_some_and(|master| master.sdown())
+    }
+
+    fn set_odown(&self, name: &str, odown: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(master) = state.masters.get_mut(name) {
+            if odown && !master.odown {
+                info!(master = %name, "The master is down for a quorum of sentinels");
+            }
+            master.odown = odown;
+        }
+    }
+
+    /// `true` unless a failover of the master `name` was started or voted for less than twice
+    /// the failover timeout ago.
+    fn may_fail_over(&self, name: &str) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state.masters.get(name).is_some_and(|master| {
+            master
+                .failover_start
+                .is_none_or(|start| start.elapsed() > master.failover_timeout * 2)
+        })
+    }
+
+    /// Start an election to lead the failover of the master `name` in a new epoch, voting for
+    /// this sentinel. Returns the epoch, `None` if a failover can't be started now.
+    fn start_election(&self, name: &str) -> Option<u64> {
+        if !self.may_fail_over(name) {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.current_epoch += 1;
+        let epoch = state.current_epoch;
+
+        let master = state.masters.get_mut(name)?;
+        master.leader = Some(self.myid.clone());
+        master.leader_epoch = epoch;
+        master.failover_start = Some(Instant::now());
+
+        Some(epoch)
+    }
+
+    /// Replace the master `name` with its replica at `host:port`, promoted in `epoch`.
+    fn promote(&self, name: &str, host: String, port: u16, epoch: u64) {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(master) = state.masters.get_mut(name) {
+            master.switch(host, port, epoch);
+        }
+    }
+}
+
+/// Value of `field` in the reply of `INFO`.
+fn info_field(info: &[u8], field: &str) -> Option<String> {
+    String::from_utf8_lossy(info)
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .map(str::to_string)
+}
+
+/// Replicas listed by the `slave<n>` fields of `INFO replication`, with their offset.
+fn parse_replicas(info: &[u8]) -> Vec<((String, u16), u64)> {
+    String::from_utf8_lossy(info)
+        .lines()
+        .filter(|line| line.starts_with("slave") && !line.starts_with("slave_"))
+        .filter_map(|line| {
+            let (_, replica) = line.split_once(':')?;
+            let fields: HashMap<_, _> = replica
+                .split(',')
+                .filter_map(|field| field.split_once('='))
+                .collect();
+
+            let port = fields.get("port")?.parse().ok().filter(|port| *port != 0)?;
+            let offset = fields.get("offset")?.parse().ok()?;
+            Some(((fields.get("ip")?.to_string(), port), offset))
+        })
+        .collect()
+}
+
+/// Sentinels connected to a server, found in the reply of `CLIENT LIST` by the names of their
+/// connections, `sentinel-<runid>-<port>`.
+fn parse_sentinels(list: &[u8]) -> Vec<(String, (IpAddr, u16))> {
+    String::from_utf8_lossy(list)
+        .lines()
+        .filter_map(|line| {
+            let fields: HashMap<_, _> = line
+                .split(' ')
+                .filter_map(|field| field.split_once('='))
+                .collect();
+
+            let (runid, port) = fields
+                .get("name")?
+                .strip_prefix("sentinel-")?
+                .split_once('-')?;
+            let addr: std::net::SocketAddr = fields.get("addr")?.parse().ok()?;
+            Some((runid.to_string(), (addr.ip(), port.parse().ok()?)))
+        })
+        .collect()
+}
@@ -1,98 +1,1296 @@
 use crate::{
     Command,
-    connection::Connection,
-    db::{Db, DbDropGuard},
+    audit::{self, AuditEntry, AuditLog},
+    cluster::{self, Cluster},
+    cmd::{Renames, Select},
+    config::{Config, file},
+    connection::{Connection, Stream},
+    db::{Change, Db, DbDropGuard, Kind, Stats},
     errors::WalrusError,
+    latency::LatencyMonitor,
+    memory::{self, MemoryPeak},
+    metrics::{self, Metrics},
+    monitor::Monitors,
+    pause::PauseGate,
+    persistence::{
+        self, Persistence,
+        aof::{self, Aof},
+        loading::Loading,
+        rdb,
+    },
+    ratelimit::{PeerLimits, RatelimitAction},
+    registry::ClientRegistry,
+    replication::Replication,
+    sentinel::Sentinel,
+    slowlog::{Slowlog, SlowlogArgs},
+    task,
 };
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Semaphore;
-use tokio::time;
+#[cfg(unix)]
+use tokio::net::{TcpSocket, UnixListener};
+use tokio::sync::{Semaphore, mpsc, watch};
+use tokio::task::JoinSet;
+use tokio::time::{self, Instant};
+use tracing::{Instrument, Span, debug, field, info, info_span, trace, warn};
+
+pub use crate::config::{DebugCommand, file::ConfigFile};
+
+#[cfg(feature = "otel")]
+use crate::otel;
+#[cfg(feature = "tls")]
+use crate::tls::TlsListener;
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
+
+/// Sockets the server accepts connections on, see `run`.
+///
+/// Built from the `TcpListener` of plain connections, a `UnixListener` or, with the `tls`
+/// feature, a `TlsListener`. More listeners are added with `with_tcp`, `with_unix` and
+/// `with_tls`, such as a public TLS port next to a plain one bound to localhost. Connections are
+/// accepted from all of them concurrently, and share the dataset and the connection limit.
+pub struct Listeners {
+    sockets: Vec<ListenSocket>,
+}
+
+/// A single socket connections are accepted on.
+enum ListenSocket {
+    Tcp(TcpListener),
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, TlsAcceptor),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Connection accepted on a `ListenSocket`, its transport is established by `establish`.
+enum Accepted {
+    Ready(Stream),
+    #[cfg(feature = "tls")]
+    Handshake(TcpStream, TlsAcceptor),
+}
+
+impl From<TcpListener> for Listeners {
+    fn from(listener: TcpListener) -> Listeners {
+        Listeners {
+            sockets: vec![ListenSocket::Tcp(listener)],
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+impl From<TlsListener> for Listeners {
+    fn from(listener: TlsListener) -> Listeners {
+        Listeners {
+            sockets: vec![ListenSocket::Tls(listener.listener, listener.acceptor)],
+        }
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixListener> for Listeners {
+    fn from(listener: UnixListener) -> Listeners {
+        Listeners {
+            sockets: vec![ListenSocket::Unix(listener)],
+        }
+    }
+}
+
+impl Listeners {
+    /// Also accept plain connections on `listener`.
+    pub fn with_tcp(mut self, listener: TcpListener) -> Listeners {
+        self.sockets.push(ListenSocket::Tcp(listener));
+        self
+    }
+
+    /// Also accept TLS connections on `listener`.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, listener: TlsListener) -> Listeners {
+        self.sockets
+            .push(ListenSocket::Tls(listener.listener, listener.acceptor));
+        self
+    }
+
+    /// Also accept connections on the Unix socket `listener`, see `bind_unix`.
+    #[cfg(unix)]
+    pub fn with_unix(mut self, listener: UnixListener) -> Listeners {
+        self.sockets.push(ListenSocket::Unix(listener));
+        self
+    }
+
+    /// Address of the first TCP listener.
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sockets
+            .iter()
+            .find_map(|socket| match socket {
+                ListenSocket::Tcp(listener) => Some(listener.local_addr()),
+                #[cfg(feature = "tls")]
+                ListenSocket::Tls(listener, _) => Some(listener.local_addr()),
+                #[cfg(unix)]
+                ListenSocket::Unix(_) => None,
+            })
+            .unwrap_or_else(|| Err(io::ErrorKind::NotFound.into()))
+    }
+
+    /// Whether a TCP listener is bound to an address other hosts can reach, any address but
+    /// the loopback interface.
+    fn is_public(&self) -> bool {
+        self.sockets.iter().any(|socket| {
+            let listener = match socket {
+                ListenSocket::Tcp(listener) => listener,
+                #[cfg(feature = "tls")]
+                ListenSocket::Tls(listener, _) => listener,
+                #[cfg(unix)]
+                ListenSocket::Unix(_) => return false,
+            };
+            listener
+                .local_addr()
+                .is_ok_and(|addr| !addr.ip().to_canonical().is_loopback())
+        })
+    }
+
+    /// Paths of the Unix sockets, their files are removed when the server shuts down.
+    #[cfg(unix)]
+    fn unix_paths(&self) -> Vec<PathBuf> {
+        self.sockets
+            .iter()
+            .filter_map(|socket| match socket {
+                ListenSocket::Unix(listener) => {
+                    Some(listener.local_addr().ok()?.as_pathname()?.to_path_buf())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Bind a Unix socket at `path`, for `Listeners::with_unix`. A stale socket file left at `path`
+/// is replaced. With `perm` the socket file is given these permissions, such as `0o700` to only
+/// let the user of the server connect.
+#[cfg(unix)]
+pub fn bind_unix(path: impl AsRef<Path>, perm: Option<u32>) -> io::Result<UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    let path = path.as_ref();
+    // Only sockets are removed, a regular file at `path` is more likely a mistake.
+    if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Some(perm) = perm {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// Bind `count` listeners to `addr` with `SO_REUSEPORT`, for `Listeners::with_tcp`. The kernel
+/// balances the incoming connections between them, and the server accepts from each listener
+/// in its own task, so accepting isn't bound to a single loop. One listener per core is a good
+/// start.
+///
+/// With port 0 every listener is bound to the port picked for the first one.
+#[cfg(unix)]
+pub fn bind_reuseport(addr: SocketAddr, count: usize) -> io::Result<Vec<TcpListener>> {
+    let mut listeners: Vec<TcpListener> = Vec::with_capacity(count);
+    for _ in 0..count.max(1) {
+        let addr = match listeners.first() {
+            Some(first) => first.local_addr()?,
+            None => addr,
+        };
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        socket.set_reuseaddr(true)?;
+        socket.set_reuseport(true)?;
+        socket.bind(addr)?;
+        listeners.push(socket.listen(1024)?);
+    }
+    Ok(listeners)
+}
+
+/// Time allowed for a client to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ListenSocket {
+    /// Accept the next connection.
+    async fn accept(&self) -> io::Result<Accepted> {
+        match self {
+            ListenSocket::Tcp(listener) => Ok(Accepted::Ready(accept_tcp(listener).await?.into())),
+            #[cfg(feature = "tls")]
+            ListenSocket::Tls(listener, acceptor) => Ok(Accepted::Handshake(
+                accept_tcp(listener).await?,
+                acceptor.clone(),
+            )),
+            #[cfg(unix)]
+            ListenSocket::Unix(listener) => Ok(Accepted::Ready(listener.accept().await?.0.into())),
+        }
+    }
+}
+
+/// Accept a TCP connection.
+async fn accept_tcp(listener: &TcpListener) -> io::Result<TcpStream> {
+    let (socket, _) = listener.accept().await?;
+    Ok(socket)
+}
+
+impl Accepted {
+    /// Establish the transport of the connection, making the TLS handshake on TLS listeners.
+    /// Called from the task of the connection, a slow handshake doesn't hold up the accept
+    /// loop.
+    async fn establish(self) -> io::Result<Stream> {
+        match self {
+            Accepted::Ready(stream) => Ok(stream),
+            #[cfg(feature = "tls")]
+            Accepted::Handshake(socket, acceptor) => {
+                let handshake = time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(socket));
+                match handshake.await {
+                    Ok(stream) => Ok(stream?.into()),
+                    Err(_) => Err(io::ErrorKind::TimedOut.into()),
+                }
+            }
+        }
+    }
+}
 
 /// Tcp listening and initialization of per-connection state.
 struct Listener {
-    /// `DbDropGuard` -- when listener is dropped, the drop method on `DbDropGuard` is called.
-    /// This cleans up the background task for purging expired keys.
-    db_holder: DbDropGuard,
-    listener: TcpListener,
+    /// `DbDropGuard` of every database -- when listener is dropped, the drop method on
+    /// `DbDropGuard` is called. This cleans up the background tasks for purging expired keys.
+    db_holders: Vec<DbDropGuard>,
+    listeners: Listeners,
     /// Limit the max number of connections.
     /// A `Semaphore` is used to limit the max number of connections. Permit is required
-    /// from semaphore before attempting to accept a new connection. Must wait for one
-    /// if none are available.
+    /// from semaphore for each accepted connection. Connections accepted while none are
+    /// available are refused with an error.
     ///
     /// Permit is returned to semaphore when connection is dropped.
     limit_connections: Arc<Semaphore>,
+    /// Server wide state shared with every connection handler.
+    server: Arc<ServerState>,
+}
+
+/// State shared by the accept loops of the listeners.
+#[derive(Clone)]
+struct Acceptor {
+    limit_connections: Arc<Semaphore>,
+    server: Arc<ServerState>,
+}
+
+/// State of the server shared by all connections, as opposed to the per-connection state
+/// stored in `Connection`.
+pub(crate) struct ServerState {
+    /// Runtime configuration, changed with `CONFIG SET`.
+    pub(crate) config: Config,
+    /// Logical databases, selected by connections with `SELECT`. Server wide statistics,
+    /// such as the commands executed, are kept by database 0.
+    pub(crate) databases: Box<[Db]>,
+    /// Registry of active connections.
+    pub(crate) clients: ClientRegistry,
+    /// Connections and command rates of each peer address, limited by `maxclients-per-ip` and
+    /// `ratelimit-per-ip`.
+    pub(crate) peers: PeerLimits,
+    /// Gate suspending commands during `CLIENT PAUSE`.
+    pub(crate) pause: PauseGate,
+    /// Connections streaming every command with `MONITOR`.
+    pub(crate) monitors: Monitors,
+    /// Log of commands exceeding `slowlog-log-slower-than`.
+    pub(crate) slowlog: Slowlog,
+    /// Network traffic and command latencies, exported on the metrics endpoint.
+    pub(crate) metrics: Metrics,
+    /// Latency spikes of commands and background work, and execution times of each command.
+    pub(crate) latency: LatencyMonitor,
+    /// Highest memory used by the server since it started.
+    pub(crate) memory_peak: MemoryPeak,
+    /// Write commands executed by clients, recorded if enabled.
+    pub(crate) audit: AuditLog,
+    /// State of snapshots, saved with `SAVE` and `BGSAVE`.
+    pub(crate) persistence: Persistence,
+    /// Append only file write commands are logged to, if `appendonly` is enabled.
+    pub(crate) aof: Aof,
+    /// Progress of the dataset loaded on startup or from a master.
+    pub(crate) loading: Loading,
+    /// Replicas streamed the writes of this server, or the master it replicates.
+    pub(crate) replication: Replication,
+    /// Slots served by this node and the other nodes of the cluster, in cluster mode.
+    pub(crate) cluster: Option<Cluster>,
+    /// Masters monitored by this server, in sentinel mode.
+    pub(crate) sentinel: Option<Sentinel>,
+    /// Instant the server started at.
+    pub(crate) started: Instant,
+    /// Commands renamed or disabled when the server started.
+    pub(crate) renames: Renames,
+    /// Whether the server listens on an address other hosts can reach, clients on other hosts
+    /// are then refused in `protected-mode`.
+    pub(crate) public: bool,
+    /// Configuration file the server was started with, rewritten by `CONFIG REWRITE`.
+    pub(crate) config_file: Option<PathBuf>,
+    /// Set once the server is shutting down, connections close instead of reading their next
+    /// command. Every connection holds a receiver, the server waits for all of them to be
+    /// dropped before it stops.
+    pub(crate) shutdown: watch::Sender<bool>,
 }
 
 /// Per connection handler. Reads requests from `connection` and applies commands.
 struct Handler {
-    db: Db,
     connection: Connection,
+    server: Arc<ServerState>,
+    /// Bytes read and written by `connection` already counted in the metrics of the server.
+    reported: (u64, u64),
+    /// `true` if write commands were executed since the replies were last flushed.
+    unflushed_writes: bool,
 }
 
+/// Error refusing clients on other hosts in protected mode, explaining how to allow them.
+const PROTECTED_MODE_ERROR: &str = "DENIED Walrus is running in protected mode because \
+    protected mode is enabled and the server listens on an address reachable from other hosts, \
+    while no password can be set. In this mode connections are only accepted from the same \
+    host. If you want to connect from other hosts you may adopt one of the following solutions: \
+    1) Disable protected mode by sending the command 'CONFIG SET protected-mode no' from the \
+    same host the server is running on, however MAKE SURE the server is not publicly \
+    accessible from the internet if you do so. Use CONFIG REWRITE to make this change \
+    permanent. 2) Set 'protected-mode = false' in the configuration file and restart the \
+    server. 3) If you started the server manually just for testing, restart it with the \
+    '--protected-mode no' option. 4) Only bind the server to the loopback interface and reach \
+    it through a tunnel.";
+
+/// Default limit of the number of connections, see `Builder::max_connections`.
 const MAX_CONNECTIONS: usize = 10000;
 
-/// Run the server.
+/// Builds a server with its settings, for applications embedding walrus. Settings not given
+/// keep their default, and those with a configuration parameter can still be changed with
+/// `CONFIG SET` once the server runs.
+///
+/// ```no_run
+/// # async fn embed() -> std::io::Result<()> {
+/// use std::time::Duration;
+/// use walrus::server::Builder;
 ///
-/// Accepts connections from the listener given as argument.
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:6380").await?;
+/// Builder::new(listener)
+///     .max_connections(1000)
+///     .read_buffer_size(4)
+///     .timeout(Duration::from_secs(300))
+///     .dir("/var/lib/walrus")
+///     .run(tokio::signal::ctrl_c())
+///     .await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Builder {
+    listeners: Listeners,
+    port: Option<u16>,
+    max_connections: usize,
+    read_buffer_size: Option<u16>,
+    write_buffer_size: Option<u16>,
+    timeout: Option<Duration>,
+    dir: Option<PathBuf>,
+    databases: Option<usize>,
+    appendonly: bool,
+    protected_mode: Option<bool>,
+    read_only: Option<bool>,
+    enable_debug_command: Option<DebugCommand>,
+    renames: Vec<(String, String)>,
+    load_rdb: Option<PathBuf>,
+    cluster_bus: Option<TcpListener>,
+    metrics: Option<TcpListener>,
+    audit_log: Option<PathBuf>,
+    audit_log_max_size: u64,
+    audit_log_max_files: usize,
+    audit_channel: Option<mpsc::Sender<AuditEntry>>,
+    sentinel: bool,
+    config_file: Option<ConfigFile>,
+    watch_config_file: bool,
+    #[cfg(unix)]
+    sd_notify: bool,
+}
+
+impl Builder {
+    /// Server accepting connections on `listener`, more listeners can be added to `Listeners`
+    /// before.
+    pub fn new(listener: impl Into<Listeners>) -> Builder {
+        Builder {
+            listeners: listener.into(),
+            port: None,
+            max_connections: MAX_CONNECTIONS,
+            read_buffer_size: None,
+            write_buffer_size: None,
+            timeout: None,
+            dir: None,
+            databases: None,
+            appendonly: false,
+            protected_mode: None,
+            read_only: None,
+            enable_debug_command: None,
+            renames: Vec::new(),
+            load_rdb: None,
+            cluster_bus: None,
+            metrics: None,
+            audit_log: None,
+            audit_log_max_size: audit::DEFAULT_MAX_SIZE,
+            audit_log_max_files: audit::DEFAULT_MAX_FILES,
+            audit_channel: None,
+            sentinel: false,
+            config_file: None,
+            watch_config_file: false,
+            #[cfg(unix)]
+            sd_notify: false,
+        }
+    }
+
+    /// Port reported by `CONFIG GET port` and announced to the nodes of the cluster, the port of
+    /// the first TCP listener by default.
+    pub fn port(mut self, port: u16) -> Builder {
+        self.port = Some(port);
+        self
+    }
+
+    /// Maximum number of connections, 10000 by default. Connections over it are refused
+    /// with an error. `maxclients` starts at this limit, raising it at runtime doesn't go beyond.
+    pub fn max_connections(mut self, max_connections: usize) -> Builder {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    /// Initial size in KB of the read buffer of connections, 16KB by default.
+    pub fn read_buffer_size(mut self, size: u16) -> Builder {
+        self.read_buffer_size = Some(size);
+        self
+    }
+
+    /// Initial size in KB of the write buffer of connections, 16KB by default.
+    pub fn write_buffer_size(mut self, size: u16) -> Builder {
+        self.write_buffer_size = Some(size);
+        self
+    }
+
+    /// Disconnect clients idle for longer than `timeout`, rounded to seconds. Clients are never
+    /// disconnected by default.
+    pub fn timeout(mut self, timeout: Duration) -> Builder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Directory of the snapshot and the append only file, the current directory by default.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Builder {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Number of logical databases, 16 by default. Connections use database 0 until they
+    /// `SELECT` another one.
+    pub fn databases(mut self, databases: usize) -> Builder {
+        self.databases = Some(databases.max(1));
+        self
+    }
+
+    /// Log write commands to the append only file, which is loaded instead of the snapshot.
+    pub fn appendonly(mut self, appendonly: bool) -> Builder {
+        self.appendonly = appendonly;
+        self
+    }
+
+    /// Refuse clients on other hosts when the server listens on an address they can reach,
+    /// enabled by default. Clients on the same host can still disable it with
+    /// `CONFIG SET protected-mode no`.
+    pub fn protected_mode(mut self, protected: bool) -> Builder {
+        self.protected_mode = Some(protected);
+        self
+    }
+
+    /// Refuse the write commands of clients while serving reads, disabled by default. It can be
+    /// changed at runtime with `CONFIG SET read-only`.
+    pub fn read_only(mut self, read_only: bool) -> Builder {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// Connections allowed to use the `DEBUG` command, none by default. Unlike most parameters
+    /// `enable-debug-command` can't be changed with `CONFIG SET`, clients can't allow
+    /// themselves.
+    pub fn enable_debug_command(mut self, mode: DebugCommand) -> Builder {
+        self.enable_debug_command = Some(mode);
+        self
+    }
+
+    /// Rename the command `name` to `new_name`, clients then only know it by its new name. An
+    /// empty `new_name` disables the command. The server refuses to start if `name` is not a
+    /// command or `new_name` is the name of another one.
+    ///
+    /// ```no_run
+    /// # async fn embed(listener: tokio::net::TcpListener) {
+    /// walrus::server::Builder::new(listener)
+    ///     .rename_command("config", "config-b840fc02")
+    ///     .rename_command("debug", "")
+    ///     .run(tokio::signal::ctrl_c())
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn rename_command(
+        mut self,
+        name: impl Into<String>,
+        new_name: impl Into<String>,
+    ) -> Builder {
+        self.renames.push((name.into(), new_name.into()));
+        self
+    }
+
+    /// Import the dataset from the Redis dump at `path` instead, then persist it.
+    pub fn load_rdb(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.load_rdb = Some(path.into());
+        self
+    }
+
+    /// Run in cluster mode, other nodes link to the server on `bus`.
+    pub fn cluster_bus(mut self, bus: TcpListener) -> Builder {
+        self.cluster_bus = Some(bus);
+        self
+    }
+
+    /// Serve the metrics of the server in the Prometheus text format on `listener`, at
+    /// `GET /metrics`. Commands executed, their latency, connected clients, the size of the
+    /// keyspace, expired and evicted keys and the network traffic are exported.
+    pub fn metrics(mut self, listener: TcpListener) -> Builder {
+        self.metrics = Some(listener);
+        self
+    }
+
+    /// Record every write command executed by clients in the audit log at `path`, with the
+    /// client and the keys, see `walrus::audit`. The file is appended to.
+    pub fn audit_log(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.audit_log = Some(path.into());
+        self
+    }
+
+    /// Rotate the audit log once it reaches `size` bytes, 64MB by default. 0 never rotates.
+    pub fn audit_log_max_size(mut self, size: u64) -> Builder {
+        self.audit_log_max_size = size;
+        self
+    }
+
+    /// Number of rotated audit logs kept, `path.1` being the most recent, 4 by default.
+    pub fn audit_log_max_files(mut self, files: usize) -> Builder {
+        self.audit_log_max_files = files;
+        self
+    }
+
+    /// Send every write command executed by clients to `channel`, see `walrus::audit`. Entries
+    /// are dropped while the channel is full, commands never wait for it.
+    pub fn audit_channel(mut self, channel: mpsc::Sender<AuditEntry>) -> Builder {
+        self.audit_channel = Some(channel);
+        self
+    }
+
+    /// Run in sentinel mode, monitoring the masters added with `SENTINEL MONITOR` instead of
+    /// serving a dataset.
+    pub fn sentinel(mut self, sentinel: bool) -> Builder {
+        self.sentinel = sentinel;
+        self
+    }
+
+    /// Configure the server with `file`. The settings of the builder given in the file, such as
+    /// `maxclients` or `dir`, are taken from it, they can still be overridden by calling the
+    /// builder afterwards. The other parameters are set when the server starts, like with
+    /// `CONFIG SET`.
+    ///
+    /// Fails if a parameter is unknown, immutable or has an invalid value.
+    pub fn config_file(mut self, mut file: ConfigFile) -> io::Result<Builder> {
+        if let Some(port) = file.take("port")? {
+            self.port = Some(port);
+        }
+        if let Some(max_connections) = file.take("maxclients")? {
+            self = self.max_connections(max_connections);
+        }
+        if let Some(timeout) = file.take("timeout")? {
+            self.timeout = Some(Duration::from_secs(timeout));
+        }
+        if let Some(size) = file.take("read-buffer-size")? {
+            self.read_buffer_size = Some(size);
+        }
+        if let Some(size) = file.take("write-buffer-size")? {
+            self.write_buffer_size = Some(size);
+        }
+        if let Some(dir) = file.take::<PathBuf>("dir")? {
+            self.dir = Some(dir);
+        }
+        if let Some(databases) = file.take("databases")? {
+            self = self.databases(databases);
+        }
+        if let Some(appendonly) = file.take_flag("appendonly")? {
+            self.appendonly = appendonly;
+        }
+        if let Some(protected) = file.take_flag("protected-mode")? {
+            self.protected_mode = Some(protected);
+        }
+        if let Some(read_only) = file.take_flag("read-only")? {
+            self.read_only = Some(read_only);
+        }
+        if let Some(mode) = file.take("enable-debug-command")? {
+            self.enable_debug_command = Some(mode);
+        }
+        if let Some(path) = file.take::<PathBuf>("audit-log")? {
+            self.audit_log = Some(path);
+        }
+        if let Some(size) = file.take("audit-log-max-size")? {
+            self.audit_log_max_size = size;
+        }
+        if let Some(files) = file.take("audit-log-max-files")? {
+            self.audit_log_max_files = files;
+        }
+        if let Some(renames) = file.take_table("rename-command")? {
+            let mut checked = Renames::default();
+            for (name, new_name) in renames {
+                checked
+                    .add(&name, &new_name)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+                self = self.rename_command(name, new_name);
+            }
+        }
+
+        // Checked against a default configuration, errors are reported before the server runs.
+        file.apply(&Config::new(0, None, None, None, None, false))?;
+        self.config_file = Some(file);
+        Ok(self)
+    }
+
+    /// Watch the file given to `config_file` for changes, and set the parameters that can be
+    /// changed at runtime again from it when it's modified. The other parameters, such as the
+    /// addresses to bind, only apply on startup.
+    pub fn watch_config_file(mut self, watch: bool) -> Builder {
+        self.watch_config_file = watch;
+        self
+    }
+
+    /// Tell systemd once the dataset is loaded and the server is ready to serve clients, and
+    /// again when it starts shutting down, for services of `Type=notify`. Nothing is sent if
+    /// the server isn't supervised by systemd, see `walrus::systemd::notify`.
+    #[cfg(unix)]
+    pub fn sd_notify(mut self, notify: bool) -> Builder {
+        self.sd_notify = notify;
+        self
+    }
+
+    /// Run the server until `shutdown` completes, such as `tokio::signal::ctrl_c()`.
+    ///
+    /// Accepts connections from the listeners while loading the dataset persisted in `dir`.
+    /// With `appendonly` write commands are logged to the append only file, which is loaded
+    /// instead of the snapshot. With `load_rdb` the dataset is imported from a Redis dump
+    /// instead, then persisted. With `cluster_bus` the server runs in cluster mode, other nodes
+    /// link to it on that listener. With `sentinel` the server runs in sentinel mode, monitoring
+    /// the masters added with `SENTINEL MONITOR` instead of serving a dataset.
+    ///
+    /// Once `shutdown` completes the server stops accepting connections, lets every connection
+    /// finish the command it is executing and waits for them to close. Pending appends to the
+    /// append only file are synced, and a snapshot is saved if the dataset changed since the
+    /// last save, unless `save-on-shutdown` is disabled.
+    pub async fn run(self, shutdown: impl Future) {
+        let Builder {
+            listeners,
+            port,
+            max_connections,
+            read_buffer_size,
+            write_buffer_size,
+            timeout,
+            dir,
+            databases,
+            appendonly,
+            protected_mode,
+            read_only,
+            enable_debug_command,
+            renames: renamed,
+            load_rdb,
+            cluster_bus,
+            metrics,
+            audit_log,
+            audit_log_max_size,
+            audit_log_max_files,
+            audit_channel,
+            sentinel,
+            config_file,
+            watch_config_file,
+            #[cfg(unix)]
+            sd_notify,
+        } = self;
+        let port = port.unwrap_or_else(|| listeners.local_addr().map_or(0, |addr| addr.port()));
+        #[cfg(unix)]
+        let unix_paths = listeners.unix_paths();
+
+        // Nodes are reached on the address the listeners are bound to.
+        let cluster = cluster_bus.as_ref().map(|bus| {
+            let addr = listeners.local_addr().unwrap();
+            let bus_port = bus.local_addr().unwrap().port();
+            Cluster::new(addr.ip(), port, bus_port)
+        });
+
+        let config = Config::new(
+            port,
+            read_buffer_size,
+            write_buffer_size,
+            dir,
+            databases,
+            appendonly,
+        );
+        if let Some(file) = &config_file
+            && let Err(err) = file.apply(&config)
+        {
+            warn!(%err, "Failed to configure the server");
+            return;
+        }
+        config.set_maxclients(max_connections);
+        if let Some(timeout) = timeout {
+            config.set_timeout(timeout.as_secs());
+        }
+        if let Some(protected) = protected_mode {
+            config.set_protected_mode(protected);
+        }
+        if let Some(read_only) = read_only {
+            config.set_read_only(read_only);
+        }
+        if let Some(mode) = enable_debug_command {
+            config.set_enable_debug_command(mode);
+        }
+        let public = listeners.is_public();
+
+        let mut renames = Renames::default();
+        for (name, new_name) in &renamed {
+            if let Err(err) = renames.add(name, new_name) {
+                warn!(%err, "Failed to rename a command");
+                return;
+            }
+        }
+
+        let audit_file = audit_log
+            .as_deref()
+            .map(|path| (path, audit_log_max_size, audit_log_max_files));
+        let audit = match AuditLog::new(audit_file, audit_channel) {
+            Ok(audit) => audit,
+            Err(err) => {
+                warn!(%err, "Failed to open the audit log");
+                return;
+            }
+        };
+
+        // Create a listener state instance.
+        let db_holders = DbDropGuard::databases(config.databases());
+        let databases = db_holders.iter().map(DbDropGuard::get_db).collect();
+        let mut server = Listener {
+            db_holders,
+            listeners,
+            limit_connections: Arc::new(Semaphore::new(max_connections)),
+            server: Arc::new(ServerState {
+                config,
+                databases,
+                clients: ClientRegistry::new(),
+                peers: PeerLimits::new(),
+                pause: PauseGate::new(),
+                monitors: Monitors::new(),
+                slowlog: Slowlog::new(),
+                metrics: Metrics::new(),
+                latency: LatencyMonitor::new(),
+                memory_peak: MemoryPeak::new(),
+                audit,
+                persistence: Persistence::new(),
+                aof: Aof::new(),
+                loading: Loading::new(),
+                replication: Replication::new(),
+                cluster,
+                sentinel: sentinel.then(Sentinel::new),
+                started: Instant::now(),
+                renames,
+                public,
+                config_file: config_file.as_ref().map(|file| file.path().to_path_buf()),
+                shutdown: watch::Sender::new(false),
+            }),
+        };
+
+        // Connections are accepted while the dataset is restored, but commands touching it are
+        // refused until it is loaded. Damaged files are not overwritten by starting empty, the
+        // server stops instead.
+        let state = server.server.clone();
+        state.update_databases();
+        for (index, db) in server
+            .db_holders
+            .iter()
+            .map(DbDropGuard::get_db)
+            .enumerate()
+        {
+            // Writes are logged and streamed to replicas as they are made to the dataset.
+            {
+                let state = Arc::downgrade(&state);
+                db.on_change(move |change| {
+                    if let Some(state) = state.upgrade() {
+                        log_change(&state, index, change);
+                    }
+                });
+            }
+            {
+                let state = Arc::downgrade(&state);
+                db.on_expire_cycle(move |elapsed| {
+                    if let Some(state) = state.upgrade() {
+                        state.latency.add_sample(
+                            "expire-cycle",
+                            elapsed,
+                            state.config.latency_monitor_threshold(),
+                        );
+                    }
+                });
+            }
+        }
+
+        task::spawn(
+            "memory-sampler",
+            memory::sample_task(Arc::downgrade(&state)),
+        );
+
+        if let Some(bus) = cluster_bus {
+            task::spawn("cluster-bus", cluster::listen(bus, Arc::downgrade(&state)));
+            task::spawn("cluster-cron", cluster::cron(Arc::downgrade(&state)));
+        }
+        if let Some(listener) = metrics {
+            task::spawn("metrics", metrics::serve(listener, Arc::downgrade(&state)));
+        }
+        if watch_config_file && let Some(path) = &state.config_file {
+            task::spawn(
+                "config-reload",
+                file::reload_task(path.clone(), Arc::downgrade(&state)),
+            );
+        }
+
+        let serving = async {
+            let loading = load_dataset(state.clone(), load_rdb);
+
+            // Run the server, accepting inbound connections.
+            let listening = server.run();
+            tokio::pin!(listening);
+
+            tokio::select! {
+                res = loading => {
+                    if let Err(err) = res {
+                        warn!(%err, "Failed to load the dataset");
+                        return;
+                    }
+                }
+                res = &mut listening => {
+                    res.unwrap();
+                    return;
+                }
+            }
+
+            state.loading.finish();
+            #[cfg(unix)]
+            if sd_notify {
+                notify_systemd(&format!("READY=1\nMAINPID={}", std::process::id()));
+            }
+
+            if state.aof.is_enabled() {
+                task::spawn("aof-write", aof::write_task(Arc::downgrade(&state)));
+                task::spawn("aof-fsync", aof::fsync_task(Arc::downgrade(&state)));
+            }
+
+            listening.await.unwrap();
+        };
+
+        // Dropping `serving` stops the accept loops. If it ends by itself the dataset failed to
+        // load, it must not be saved over the files it was loaded from.
+        tokio::select! {
+            _ = serving => return,
+            _ = shutdown => info!("Shutting down"),
+        }
+        #[cfg(unix)]
+        if sd_notify {
+            notify_systemd("STOPPING=1");
+        }
+
+        // Connections finish the command they are executing, then close.
+        state.shutdown.send_replace(true);
+        state.shutdown.closed().await;
+
+        // A dataset still loading is incomplete, and sentinels have none.
+        if !state.loading.is_loading() && state.sentinel.is_none() {
+            persist_on_shutdown(&state).await;
+        }
+
+        #[cfg(unix)]
+        for path in unix_paths {
+            let _ = std::fs::remove_file(path);
+        }
+
+        // Stops the background purge of expired keys.
+        drop(server);
+        info!("Walrus is now ready to exit, bye bye...");
+    }
+}
+
+/// Run the server, same as `Builder` with the default settings.
+///
+/// Accepts connections from the listeners given as argument while loading the dataset persisted
+/// in the current directory. `port` is reported by `CONFIG GET port`.
 /// A task is spawned is to handle each connection.
+///
+/// The server runs until the process exits, use `Builder::run` to shut it down gracefully or to
+/// enable persistence, cluster or sentinel mode.
 pub async fn run(
-    listener: TcpListener,
-    port: i16,
+    listener: impl Into<Listeners>,
+    port: u16,
     read_buffer_size: Option<u16>,
     write_buffer_size: Option<u16>,
 ) {
-    // Create a listener state instance.
-    let mut server = Listener {
-        db_holder: DbDropGuard::new(),
-        listener,
-        limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+    let mut builder = Builder::new(listener).port(port);
+    if let Some(size) = read_buffer_size {
+        builder = builder.read_buffer_size(size);
+    }
+    if let Some(size) = write_buffer_size {
+        builder = builder.write_buffer_size(size);
+    }
+    builder.run(std::future::pending::<()>()).await
+}
+
+/// Write and sync the pending appends to the append only file, and save a snapshot if `save-on-shutdown`
+/// is enabled and the dataset changed since the last save.
+async fn persist_on_shutdown(server: &Arc<ServerState>) {
+    if server.aof.is_enabled() {
+        server.aof.wait_written().await;
+        if let Err(err) = server.aof.fsync_pending().await {
+            warn!(%err, "Failed to sync the append only file");
+        }
+    }
+
+    if !server.config.save_on_shutdown() || server.persistence.changes() == 0 {
+        return;
+    }
+
+    let state = server.clone();
+    let saved = task::spawn_blocking("save", move || {
+        let config = &state.config;
+        state.persistence.save(
+            &state.databases,
+            &config.snapshot_path(),
+            config.snapshot_compression(),
+        )
+    })
+    .await
+    .map_err(|err| WalrusError::from(err.to_string()));
+
+    match saved.and_then(|res| res) {
+        Ok(()) => info!("Saved the dataset before shutting down"),
+        Err(err) => warn!(%err, "Failed to save the dataset before shutting down"),
+    }
+}
+
+/// Send `state` to systemd, failures are only logged since the server runs either way.
+#[cfg(unix)]
+fn notify_systemd(state: &str) {
+    match crate::systemd::notify(state) {
+        Ok(true) => debug!(state, "Notified systemd"),
+        Ok(false) => {}
+        Err(err) => warn!(%err, "Failed to notify systemd"),
+    }
+}
+
+/// Append `change` of the database `index` to the append only file and stream it to
+/// replicas, as the command recreating it. Commands of other databases than 0 are wrapped
+/// between a `SELECT` of their database and a `SELECT 0`, so every command applies to database
+/// 0 unless told otherwise, wherever a replica resumes the stream from.
+///
+/// Datasets being loaded are not logged, they are persisted already. Replicas don't stream
+/// their own changes, they relay the stream of their master instead so the offsets of both
+/// streams match.
+fn log_change(server: &ServerState, index: usize, change: &Change) {
+    if server.loading.is_loading() {
+        return;
+    }
+
+    let aof_enabled = server.aof.is_enabled();
+    let replicating = server.replication.is_active() && !server.replication.is_replica();
+    if !aof_enabled && !replicating {
+        return;
+    }
+
+    let Some(frame) = aof::change_frame(change) else {
+        return;
     };
+    let selected;
+    let frames = if index == 0 {
+        std::slice::from_ref(&frame)
+    } else {
+        selected = [
+            Select::new(index as i64).into_frame(),
+            frame,
+            Select::new(0).into_frame(),
+        ];
+        &selected[..]
+    };
+
+    // Only queued while the key is locked, written by the task of `aof::write_task`.
+    if aof_enabled {
+        server.aof.append(frames);
+    }
 
-    // Run the server, accepting inbound connections.
-    server
-        .run(port, read_buffer_size, write_buffer_size)
-        .await
-        .unwrap();
+    if replicating {
+        server
+            .replication
+            .propagate(frames, server.config.repl_backlog_size());
+    }
 }
 
-impl Listener {
-    async fn run(
-        &mut self,
-        port: i16,
-        read_buffer_size: Option<u16>,
-        write_buffer_size: Option<u16>,
-    ) -> Result<(), WalrusError> {
-        println!("Accepting inbound connections at port {}", port);
-        loop {
-            // Get a permit to accept the connection ensuring number of active connections
-            // don't exceed `MAX_CONNECTIONS`.
-            // Wait if permit not available immediately.
-            // `acquire_owned` returns error when the semaphore has been closed, which is
-            // never the case here so `unwrap` is safe.
-            let permit = self
-                .limit_connections
-                .clone()
-                .acquire_owned()
+/// Load the dataset from the append only file if enabled, otherwise from the snapshot.
+///
+/// If the append only file doesn't exist yet, it's created from the dataset of the snapshot so
+/// it alone is enough to restore the dataset from then on.
+///
+/// A Redis dump given with `load_rdb` replaces both. The imported dataset is saved to the
+/// snapshot, and the append only file if enabled, so it's kept across restarts.
+async fn load_dataset(
+    server: Arc<ServerState>,
+    load_rdb: Option<PathBuf>,
+) -> Result<(), WalrusError> {
+    let config = &server.config;
+
+    if let Some(path) = load_rdb {
+        let state = server.clone();
+        task::spawn_blocking("import-rdb", move || import_rdb(&state, &path))
+            .await
+            .map_err(|err| err.to_string())??;
+
+        if config.appendonly() {
+            server.aof.open(&config.aof_path())?;
+        }
+    } else if config.appendonly() {
+        let path = config.aof_path();
+        let replayed = aof::load(&server, &path)
+            .await
+            .map_err(|err| format!("Failed to load append only file {}, {err}", path.display()))?;
+
+        match replayed {
+            Some(commands) => info!(
+                commands,
+                path = %path.display(),
+                "Replayed the append only file"
+            ),
+            None => {
+                let server = server.clone();
+                task::spawn_blocking("load", move || {
+                    load_snapshot(&server)?;
+                    aof::rewrite(&server.databases, &path)
+                })
                 .await
-                .unwrap();
+                .map_err(|err| err.to_string())??;
+            }
+        }
+
+        server.aof.open(&config.aof_path())?;
+    } else {
+        let server = server.clone();
+        task::spawn_blocking("load", move || load_snapshot(&server))
+            .await
+            .map_err(|err| err.to_string())??;
+    }
+
+    Ok(())
+}
+
+/// Import the Redis dump at `path` and persist it, blocking until it's done.
+fn import_rdb(server: &ServerState, path: &Path) -> Result<(), WalrusError> {
+    let config = &server.config;
+    let imported = rdb::load(&server.databases, path, &server.loading)
+        .map_err(|err| format!("Failed to import Redis dump {}, {err}", path.display()))?;
+
+    info!(
+        keys = imported.keys,
+        path = %path.display(),
+        "Imported the Redis dump"
+    );
+    if imported.skipped > 0 {
+        warn!(
+            keys = imported.skipped,
+            "Skipped keys of types walrus doesn't support or in databases out of range"
+        );
+    }
+
+    server.persistence.save(
+        &server.databases,
+        &config.snapshot_path(),
+        config.snapshot_compression(),
+    )?;
+    if config.appendonly() {
+        aof::rewrite(&server.databases, &config.aof_path())?;
+    }
+
+    Ok(())
+}
+
+/// Load the snapshot, blocking until it's loaded.
+fn load_snapshot(server: &ServerState) -> Result<(), WalrusError> {
+    let path = server.config.snapshot_path();
+    let keys = persistence::load(&server.databases, &path, &server.loading)
+        .map_err(|err| format!("Failed to load snapshot {}, {err}", path.display()))?;
+
+    if keys > 0 {
+        info!(keys, path = %path.display(), "Loaded the snapshot");
+    }
+
+    Ok(())
+}
+
+impl ServerState {
+    /// Approximate memory used by the keys and values of every database, in bytes.
+    pub(crate) fn used_memory(&self) -> usize {
+        self.databases.iter().map(Db::used_memory).sum()
+    }
+
+    /// Number of keys of every database, of `kind` if given.
+    pub(crate) fn key_count(&self, kind: Option<Kind>) -> usize {
+        self.databases.iter().map(|db| db.key_count(kind)).sum()
+    }
+
+    /// Number of keys with an expiration of every database.
+    pub(crate) fn expires_count(&self) -> usize {
+        self.databases.iter().map(Db::expires_count).sum()
+    }
+
+    /// Statistics of the keyspace of every database, the commands are counted by database 0.
+    pub(crate) fn stats(&self) -> Stats {
+        let mut stats = self.databases[0].stats();
+        for db in &self.databases[1..] {
+            let other = db.stats();
+            stats.keyspace_hits += other.keyspace_hits;
+            stats.keyspace_misses += other.keyspace_misses;
+            stats.expired_keys += other.expired_keys;
+            stats.evicted_keys += other.evicted_keys;
+            stats.lazyfreed_objects += other.lazyfreed_objects;
+            stats.lazyfree_pending_objects += other.lazyfree_pending_objects;
+        }
+        stats
+    }
+
+    /// Reset the statistics of every database to zero, see `Db::reset_stats`.
+    pub(crate) fn reset_stats(&self) {
+        self.databases.iter().for_each(Db::reset_stats);
+    }
+
+    /// Pass the configuration parameters the databases read to every one of them, see
+    /// `Config::update_db`.
+    pub(crate) fn update_databases(&self) {
+        for db in &self.databases {
+            self.config.update_db(db);
+        }
+    }
+}
+
+impl Listener {
+    /// Accept connections on every listener, until accepting on one of them fails. Each
+    /// listener is accepted from by its own task, so listeners bound with `SO_REUSEPORT` are
+    /// accepted from in parallel.
+    async fn run(&mut self) -> Result<(), WalrusError> {
+        let acceptor = Acceptor {
+            limit_connections: self.limit_connections.clone(),
+            server: self.server.clone(),
+        };
+
+        let mut accepting = JoinSet::new();
+        for socket in std::mem::take(&mut self.listeners.sockets) {
+            task::spawn_in(
+                &mut accepting,
+                "acceptor",
+                acceptor.clone().accept_loop(socket),
+            );
+        }
+
+        // The other accept loops are aborted when `accepting` is dropped.
+        while let Some(res) = accepting.join_next().await {
+            res.map_err(|err| err.to_string())??;
+        }
+        Ok(())
+    }
+}
+
+impl Acceptor {
+    /// Accept connections on `socket`, a task is spawned to handle each of them.
+    async fn accept_loop(self, socket: ListenSocket) -> Result<(), WalrusError> {
+        match &socket {
+            ListenSocket::Tcp(listener) => {
+                info!(addr = %listener.local_addr()?, "Accepting inbound connections");
+            }
+            #[cfg(feature = "tls")]
+            ListenSocket::Tls(listener, _) => {
+                info!(addr = %listener.local_addr()?, "Accepting TLS connections");
+            }
+            #[cfg(unix)]
+            ListenSocket::Unix(listener) => {
+                if let Some(path) = listener.local_addr()?.as_pathname() {
+                    info!(path = %path.display(), "Accepting connections at unix socket");
+                }
+            }
+        }
 
+        loop {
             // Since `accept` attempts error handling by itself, an error here is not
             // recoverable.
-            let socket = self.accept().await?;
+            let accepted = Self::accept(&socket).await?;
 
-            // Per connection handler.
-            let mut handler = Handler {
-                db: self.db_holder.get_db(),
-                connection: Connection::new(socket, read_buffer_size, write_buffer_size),
+            // Get a permit for the connection ensuring number of active connections don't
+            // exceed `max_connections`. The connection is refused if none is available, rather
+            // than left waiting in the backlog of the listener without any feedback.
+            let Ok(permit) = self.limit_connections.clone().try_acquire_owned() else {
+                self.server.clients.reject();
+                task::spawn("refuse", async move {
+                    if let Err(err) = Self::refuse(accepted).await {
+                        debug!(%err, "Failed to refuse a connection");
+                    }
+                });
+                continue;
             };
 
+            // Buffer sizes and socket options may be changed at runtime, they apply to new
+            // connections only.
+            let config = &self.server.config;
+            let read_buffer_size = config.read_buffer_size();
+            let write_buffer_size = config.write_buffer_size();
+            let socket_options = config.socket_options();
+            let server = self.server.clone();
+
             // Spawn a new task to process the connection.
-            tokio::spawn(async move {
-                // Process the connection, prints error if any.
-                if let Err(err) = handler.run().await {
-                    println!("connection error, {err}");
+            task::spawn("connection", async move {
+                let established = async {
+                    let connection = Connection::new(
+                        accepted.establish().await?,
+                        Some(read_buffer_size),
+                        Some(write_buffer_size),
+                    );
+                    connection.set_socket_options(&socket_options)?;
+                    Ok::<_, WalrusError>(connection)
+                };
+
+                match established.await {
+                    Ok(connection) => {
+                        // Events of the connection are logged within its span.
+                        let span = connection_span(&connection);
+
+                        // Per connection handler.
+                        let mut handler = Handler {
+                            connection,
+                            server,
+                            reported: (0, 0),
+                            unflushed_writes: false,
+                        };
+                        async move {
+                            debug!("Connection accepted");
+                            match handler.run().await {
+                                Ok(()) => debug!("Connection closed"),
+                                Err(err) => debug!(%err, "Connection closed on error"),
+                            }
+                        }
+                        .instrument(span)
+                        .await;
+                    }
+                    Err(err) => debug!(%err, "Failed to establish a connection"),
                 }
                 // Drop the permit after the task is completed, returning the permit back to
                 // the semaphore.
@@ -101,24 +1299,28 @@ impl Listener {
         }
     }
 
+    /// Tell the client of a connection accepted over the limit of connections that it's
+    /// refused, then close it.
+    async fn refuse(accepted: Accepted) -> Result<(), WalrusError> {
+        let mut connection = Connection::new(accepted.establish().await?, None, None);
+        connection.write_error_frame("ERR max number of clients reached");
+        connection.flush().await?;
+        Ok(())
+    }
+
     /// Accept inbound connection.
     ///
-    /// On success TcpStream is returned, else the execution of accept is paused for
-    /// 1 second, then 2 seconds after second failed accept and so on doubling until
+    /// On success the accepted connection is returned, else the execution of accept is paused
+    /// for 1 second, then 2 seconds after second failed accept and so on doubling until
     /// 64 seconds. After 6th failed attempt to accept, an error is returned.
-    async fn accept(&mut self) -> Result<TcpStream, WalrusError> {
+    async fn accept(socket: &ListenSocket) -> Result<Accepted, WalrusError> {
         // Initial sleep time if accept fails.
         let mut sleep_time = 1;
 
         // Accept loop
         loop {
-            match self.listener.accept().await {
-                Ok((socket, _)) => {
-                    // Disables Nagle's algorithm, thereby sending the packet instantly instead of
-                    // waiting for more data to send in a single larger packet.
-                    socket.set_nodelay(true)?;
-                    return Ok(socket);
-                }
+            match socket.accept().await {
+                Ok(accepted) => return Ok(accepted),
                 Err(err) => {
                     if sleep_time > 64 {
                         // Failed too many times, return error.
@@ -136,25 +1338,405 @@ impl Listener {
     }
 }
 
+/// Span of the events of `connection`, with its id and the address of its peer.
+fn connection_span(connection: &Connection) -> Span {
+    let span = info_span!("connection", id = connection.id(), peer = field::Empty);
+    if let Ok(addr) = connection.peer_addr() {
+        span.record("peer", field::display(addr));
+    }
+    span
+}
+
 impl Handler {
     async fn run(&mut self) -> Result<(), WalrusError> {
+        // Refuse the connection if `maxclients` is reached. `max_connections` is the hard limit,
+        // `maxclients` can be lowered at runtime.
+        if self.server.clients.len() >= self.server.config.maxclients() {
+            self.server.clients.reject();
+            self.connection
+                .write_error_frame("ERR max number of clients reached");
+            self.connection.flush().await?;
+            return Ok(());
+        }
+
+        // No password can be set, so a server reachable from other hosts only serves the local
+        // ones unless `protected-mode` is disabled.
+        if self.server.public && self.server.config.protected_mode() && !self.connection.is_local()
+        {
+            self.connection.write_error_frame(PROTECTED_MODE_ERROR);
+            self.connection.flush().await?;
+            return Ok(());
+        }
+
+        let id = self.connection.id();
+        let addr = self.connection.peer_addr().ok();
+        let ip = addr.map(|addr| addr.ip());
+
+        // Refuse the connection if its address has `maxclients-per-ip` connections already.
+        // Counted until the handler returns.
+        let _peer = match ip {
+            Some(ip) => match self
+                .server
+                .peers
+                .connect(ip, self.server.config.maxclients_per_ip())
+            {
+                Some(peer) => Some(peer),
+                None => {
+                    self.server.clients.reject();
+                    self.connection
+                        .write_error_frame("ERR max number of clients per address reached");
+                    self.connection.flush().await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        // Out of band frames for the connection, such as invalidation messages of
+        // `CLIENT TRACKING`.
+        let (kill, mut push_rx) =
+            self.server
+                .clients
+                .register(id, addr, self.server.config.output_limits());
+        // Held until the connection closes, the server waits for it when shutting down.
+        let mut shutdown = self.server.shutdown.subscribe();
+
         loop {
-            // Try to read a frame from the socket.
-            let frame = match self.connection.read_frame().await? {
+            self.report_traffic();
+
+            // Limits may be changed at runtime, they apply from the next request.
+            self.connection
+                .set_limits(self.server.config.frame_limits());
+
+            // Idle clients are disconnected after `timeout` seconds without a command. Monitors
+            // and replicas only receive data, they are never idle.
+            let timeout = self.server.config.timeout();
+            let idle_timeout = (timeout > 0
+                && !self.server.monitors.contains(id)
+                && !self.server.replication.has_replica(id))
+            .then(|| Duration::from_secs(timeout));
+            let idle = async {
+                match idle_timeout {
+                    Some(timeout) => time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            // Try to read a frame from the socket, unless the connection is killed first.
+            // `biased` makes sure a pending kill is handled before any buffered command.
+            // Push frames are written while waiting for the next command, never in the middle
+            // of a reply.
+            let maybe_frame = tokio::select! {
+                biased;
+                _ = kill.notified() => return Ok(()),
+                _ = shutting_down(&mut shutdown) => return Ok(()),
+                Some(frame) = push_rx.recv() => {
+                    self.connection.write_frame(&frame);
+                    self.flush().await?;
+                    continue;
+                }
+                res = self.connection.read_frame() => res?,
+                _ = idle => return Ok(()),
+            };
+
+            let frame = match maybe_frame {
                 Some(frame) => frame,
                 // Peer closed the connection. Nothing to do further.
                 None => return Ok(()),
             };
 
-            let cmd = Command::from_frame(frame)?;
+            // Commands over `ratelimit-per-ip` are refused, or the connection closed, so a
+            // single host can't slow down the others. Replicas are streamed writes, they are
+            // never throttled.
+            if let Some(ip) = ip
+                && !self.server.replication.has_replica(id)
+                && !self
+                    .server
+                    .peers
+                    .allow(ip, self.server.config.ratelimit_per_ip())
+            {
+                self.connection
+                    .write_error_frame("THROTTLED Too many commands from this address");
+                if self.server.config.ratelimit_per_ip_action() == RatelimitAction::Disconnect {
+                    self.flush().await?;
+                    debug!("Connection closed, over ratelimit-per-ip");
+                    return Ok(());
+                }
+                if self.connection.should_flush() {
+                    self.flush().await?;
+                }
+                continue;
+            }
+
+            // Stream the command to monitoring connections before it is executed.
+            if self.server.monitors.is_active() {
+                self.server
+                    .monitors
+                    .feed(id, self.connection.database(), addr, &frame);
+            }
+
+            // The arguments are only kept if the command may end up in the slow log or the
+            // audit log, the slow log only keeps the start of the first ones. Blocking commands
+            // are never logged in the slow log, the time spent blocked is not execution time.
+            // The changes made by write commands are logged by the changelog consumer, see
+            // `log_change`.
+            let slowlog_threshold = self.server.config.slowlog_log_slower_than();
+            let slowlog_args = (slowlog_threshold >= 0).then(|| SlowlogArgs::new(&frame));
+            let audited = self.server.audit.is_enabled();
+            let kept_frame = audited.then(|| frame.clone());
 
-            cmd.execute(&self.db, &mut self.connection).await?;
+            let cmd = Command::from_frame(frame, &self.server.renames)?;
+            let db = self.server.databases[self.connection.database()].clone();
+            let is_blocking = cmd.is_blocking();
+            let asking = self.connection.take_asking();
+            let trace_context = self.connection.take_trace_context();
+
+            // The dataset is incomplete until loaded, only commands not touching it are served.
+            if self.server.loading.is_loading() && !cmd.is_ok_loading() {
+                self.connection
+                    .write_error_frame("LOADING Walrus is loading the dataset in memory");
+                if self.connection.should_flush() {
+                    self.flush().await?;
+                }
+                continue;
+            }
+
+            // Sentinels only monitor other servers, they have no dataset to serve.
+            if self.server.sentinel.is_some() && !cmd.keys().is_empty() {
+                self.connection.write_error_frame(&format!(
+                    "ERR unknown command '{}', not available in sentinel mode",
+                    cmd.get_name()
+                ));
+                if self.connection.should_flush() {
+                    self.flush().await?;
+                }
+                continue;
+            }
+
+            // In cluster mode keys are only served by the node owning their slot.
+            if let Some(cluster) = &self.server.cluster
+                && let Err(err) = cluster.check(cmd.keys(), &db, asking)
+            {
+                self.connection.write_error_frame(&err);
+                if self.connection.should_flush() {
+                    self.flush().await?;
+                }
+                continue;
+            }
+
+            // Keys are evicted before commands once the dataset exceeds `maxmemory`, commands
+            // that may grow it are refused if not enough keys can be evicted. Keys of the
+            // selected database are evicted until the databases fit together. Replicas don't
+            // evict, their dataset is the one of their master.
+            let maxmemory = self.server.config.maxmemory();
+            if maxmemory > 0
+                && !self.server.replication.is_replica()
+                && !db.evict(
+                    (maxmemory as usize)
+                        .saturating_sub(self.server.used_memory().saturating_sub(db.used_memory())),
+                    self.server.config.maxmemory_policy(),
+                    self.server.config.maxmemory_samples(),
+                )
+                && cmd.is_denyoom()
+            {
+                self.connection
+                    .write_error_frame("OOM command not allowed when used memory > 'maxmemory'.");
+                if self.connection.should_flush() {
+                    self.flush().await?;
+                }
+                continue;
+            }
+
+            self.server.clients.touch(id, cmd.get_name());
+
+            // Keys are only collected if some connection has tracking enabled, or for the audit
+            // log.
+            let is_write = cmd.is_write();
+            let is_fast = cmd.is_fast();
+            let audited = audited && is_write;
+            let keys = if db.tracking().is_active() || audited {
+                cmd.keys().to_vec()
+            } else {
+                Vec::new()
+            };
+
+            // A command failed if it replied with an error.
+            let error_replies = self.connection.error_replies();
+
+            // With the `otel` feature the command runs in a span exported to a collector,
+            // continuing the trace of the client if it sent one.
+            #[cfg(feature = "otel")]
+            let span = otel::command_span(cmd.get_name(), cmd.keys(), trace_context);
+            #[cfg(not(feature = "otel"))]
+            let span = {
+                let _ = trace_context;
+                Span::none()
+            };
+
+            // Killing the connection cancels the command being executed, for example a blocked
+            // `BLPOP`, or the wait for a `CLIENT PAUSE` to end. Shutting down only cancels
+            // blocked commands, other commands complete.
+            let name = cmd.get_name();
+            let (elapsed, writes) = tokio::select! {
+                biased;
+                _ = kill.notified() => return Ok(()),
+                _ = shutting_down(&mut shutdown), if is_blocking => return Ok(()),
+                res = async {
+                    // `CLIENT` commands are never paused, so the pause can always be lifted.
+                    if !matches!(cmd, Command::Client(_)) {
+                        self.server.pause.wait(is_write).await;
+                    }
+
+                    // The dataset of a replica is the one of its master, its clients can't
+                    // change it. Checked after the pause, a failover turns the master into a
+                    // replica while writes are paused.
+                    if is_write
+                        && self.server.config.replica_read_only()
+                        && self.server.replication.is_replica()
+                    {
+                        self.connection.write_error_frame(
+                            "READONLY You can't write against a read only replica.",
+                        );
+                        return Ok((Duration::ZERO, None));
+                    }
+
+                    // The whole server may be made read only, writes are refused until
+                    // `read-only` is disabled again.
+                    if is_write && self.server.config.read_only() {
+                        self.connection.write_error_frame(
+                            "READONLY You can't write against a read only server.",
+                        );
+                        return Ok((Duration::ZERO, None));
+                    }
+
+                    // Held while the command executes, its changes are streamed to replicas as
+                    // they are made. Blocking commands don't hold it, they would hold back a new
+                    // replica for as long as they block.
+                    let writes = if is_write && !is_blocking {
+                        Some(self.server.replication.write_guard().await)
+                    } else {
+                        None
+                    };
+
+                    let counted = !matches!(cmd, Command::Unknown(_));
+                    let start = Instant::now();
+                    cmd.execute(&db, &mut self.connection, &self.server).await?;
+                    let elapsed = start.elapsed();
+                    if counted {
+                        let failed = self.connection.error_replies() != error_replies;
+                        self.server.databases[0].count_command(name, elapsed, failed);
+                    }
+                    Ok::<_, WalrusError>((elapsed, writes))
+                }
+                .instrument(span.clone()) => res?,
+            };
+            let succeeded = self.connection.error_replies() == error_replies;
+            #[cfg(feature = "otel")]
+            otel::record_outcome(&span, elapsed, !succeeded);
+            trace!(
+                command = name,
+                elapsed_us = elapsed.as_micros() as u64,
+                "Command executed"
+            );
+            if !is_blocking {
+                self.server.metrics.observe_latency(elapsed);
+                self.server.latency.add_sample(
+                    if is_fast { "fast-command" } else { "command" },
+                    elapsed,
+                    self.server.config.latency_monitor_threshold(),
+                );
+                if self.server.config.latency_tracking() {
+                    self.server.latency.record_command(name, elapsed);
+                }
+            }
+
+            if let Some(args) = slowlog_args
+                && !is_blocking
+                && elapsed.as_micros() >= slowlog_threshold as u128
+            {
+                self.server.slowlog.push(
+                    args,
+                    elapsed,
+                    addr,
+                    self.connection.name().cloned(),
+                    self.server.config.slowlog_max_len(),
+                );
+            }
+
+            if audited && let Some(frame) = &kept_frame {
+                self.server.audit.record(
+                    id,
+                    addr,
+                    self.connection.name().cloned(),
+                    name,
+                    keys.clone(),
+                    frame,
+                    !succeeded,
+                );
+            }
+
+            if is_write && succeeded {
+                self.server.persistence.changed();
+            }
+            drop(writes);
+
+            if is_write {
+                self.unflushed_writes = true;
+
+                for key in &keys {
+                    db.tracking().invalidate(key, Some(id));
+                }
+            } else {
+                db.tracking().track(id, &keys);
+            }
 
             // Flush the write buffer if there are no more pipelined commands
-            // already buffered.
-            if !self.connection.has_buffered_frame() {
-                self.connection.flush().await?;
+            // already buffered, or enough replies are batched.
+            if self.connection.should_flush() {
+                self.flush().await?;
             }
         }
     }
+
+    /// Flush the replies written to the connection. Replies to writes are only sent once their
+    /// changes are written to the append only file, and synced with `appendfsync always`, so
+    /// acknowledged writes survive a crash of the server.
+    async fn flush(&mut self) -> Result<(), WalrusError> {
+        if mem::take(&mut self.unflushed_writes) && self.server.aof.is_enabled() {
+            self.server.aof.wait_written().await;
+        }
+        Ok(self.connection.flush().await?)
+    }
+
+    /// Count the bytes read and written by the connection since the last call in the metrics
+    /// of the server.
+    fn report_traffic(&mut self) {
+        let input = self.connection.input_bytes();
+        let output = self.connection.output_bytes();
+        self.server
+            .metrics
+            .add_traffic(input - self.reported.0, output - self.reported.1);
+        self.reported = (input, output);
+    }
+}
+
+/// Completes once the server is shutting down, see `ServerState::shutdown`.
+async fn shutting_down(shutdown: &mut watch::Receiver<bool>) {
+    // The guard returned must not be held across an await, it isn't `Send`.
+    let _ = shutdown.wait_for(|shutdown| *shutdown).await;
+}
+
+impl Drop for Handler {
+    fn drop(&mut self) {
+        self.report_traffic();
+
+        // Connection is closed, remove it from the registry and stop tracking its keys.
+        self.server.clients.unregister(self.connection.id());
+        // Keys are tracked by every database together.
+        self.server.databases[0]
+            .tracking()
+            .disable(self.connection.id());
+        self.server.monitors.remove(self.connection.id());
+        self.server.replication.remove_replica(self.connection.id());
+    }
 }
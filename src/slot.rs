@@ -0,0 +1,67 @@
+//! Hash slots of keys, as computed by Redis Cluster.
+//!
+//! Servers in cluster mode only serve the keys of the slots they own, clients use the same
+//! computation to send commands to the node owning their keys.
+
+/// Number of hash slots the keyspace is split into.
+pub const SLOTS: usize = 16384;
+
+/// CRC-16/XMODEM polynomial, the checksum used by Redis Cluster to hash keys.
+const POLY: u16 = 0x1021;
+
+/// Lookup table of the checksum of every byte, computed at compile time.
+const TABLE: [u16; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ POLY
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+/// Checksum of `bytes`, initial value 0 and no final xor.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+
+    for &byte in bytes {
+        crc = TABLE[((crc >> 8) as u8 ^ byte) as usize] ^ (crc << 8);
+    }
+
+    crc
+}
+
+/// Part of `key` that is hashed. If the key contains a non empty `{...}` section, only the
+/// content of the first one is hashed so related keys can be kept in the same slot.
+///
+/// `{user1000}.following` and `{user1000}.followers` both hash `user1000`, `foo{}{bar}` is
+/// hashed whole as its first section is empty.
+pub fn hash_tag(key: &[u8]) -> &[u8] {
+    let Some(open) = key.iter().position(|&byte| byte == b'{') else {
+        return key;
+    };
+
+    match key[open + 1..].iter().position(|&byte| byte == b'}') {
+        Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+        _ => key,
+    }
+}
+
+/// Hash slot of `key`, in `0..SLOTS`.
+pub fn key_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) & (SLOTS as u16 - 1)
+}
@@ -0,0 +1,179 @@
+use bytes::{Bytes, BytesMut};
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::frame::Frame;
+
+/// Maximum number of arguments recorded per entry, the last recorded argument tells how many
+/// were left out.
+const MAX_ARGS: usize = 32;
+/// Maximum length of a recorded argument, longer arguments are truncated.
+const MAX_ARG_LEN: usize = 128;
+
+/// Arguments of a command kept while it executes, in case it ends up in the slow log.
+///
+/// At most `MAX_ARGS` arguments are kept, each sliced to `MAX_ARG_LEN` bytes without copying
+/// it, so commands that turn out fast only cost a small vector.
+pub(crate) struct SlowlogArgs {
+    /// Start of each argument kept, with its length.
+    args: Vec<(Bytes, usize)>,
+    /// Number of arguments of the command.
+    count: usize,
+}
+
+impl SlowlogArgs {
+    /// Keep the arguments of the command `frame`.
+    pub(crate) fn new(frame: &Frame) -> SlowlogArgs {
+        let Frame::Array(args) = frame else {
+            return SlowlogArgs {
+                args: Vec::new(),
+                count: 0,
+            };
+        };
+
+        let kept = args
+            .iter()
+            .take(MAX_ARGS)
+            .map(|arg| {
+                let bytes = match arg {
+                    Frame::Bulk(bytes) | Frame::Simple(bytes) => bytes.clone(),
+                    other => Bytes::from(other.to_string()),
+                };
+                let len = bytes.len();
+                (bytes.slice(..len.min(MAX_ARG_LEN)), len)
+            })
+            .collect();
+        SlowlogArgs {
+            args: kept,
+            count: args.len(),
+        }
+    }
+}
+
+/// A command that exceeded the slow log threshold.
+pub(crate) struct SlowlogEntry {
+    /// Unique, increasing id of the entry.
+    id: u64,
+    /// Unix time at which the command was logged, in seconds.
+    timestamp: u64,
+    /// Execution time of the command.
+    duration: Duration,
+    /// Arguments of the command, including its name.
+    args: Vec<Bytes>,
+    /// Address of the client that executed the command.
+    addr: Option<SocketAddr>,
+    /// Name of the client that executed the command.
+    name: Option<Bytes>,
+}
+
+/// Bounded log of the slowest commands executed by the server, newest first.
+pub(crate) struct Slowlog {
+    entries: Mutex<VecDeque<SlowlogEntry>>,
+    /// Id of the next entry, never reset so ids stay unique across `SLOWLOG RESET`.
+    next_id: AtomicU64,
+}
+
+impl Slowlog {
+    /// Create an empty slow log.
+    pub(crate) fn new() -> Slowlog {
+        Slowlog {
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Record the command of `args` that took `duration` to execute, keeping at most `max_len`
+    /// entries.
+    pub(crate) fn push(
+        &self,
+        args: SlowlogArgs,
+        duration: Duration,
+        addr: Option<SocketAddr>,
+        name: Option<Bytes>,
+        max_len: usize,
+    ) {
+        let args = record_args(args);
+        let entry = SlowlogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration,
+            args,
+            addr,
+            name,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    /// Number of entries in the log.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Remove every entry.
+    pub(crate) fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The `count` most recent entries, each described as in the reply of `SLOWLOG GET`:
+    /// id, timestamp, duration in microseconds, arguments, client address and client name.
+    pub(crate) fn get(&self, count: usize) -> Vec<Frame> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .take(count)
+            .map(|entry| {
+                let args = entry.args.iter().cloned().map(Frame::Bulk).collect();
+                let addr = entry.addr.map(|addr| addr.to_string()).unwrap_or_default();
+
+                Frame::Array(vec![
+                    Frame::Integer(entry.id as i64),
+                    Frame::Integer(entry.timestamp as i64),
+                    Frame::Integer(entry.duration.as_micros() as i64),
+                    Frame::Array(args),
+                    Frame::Bulk(Bytes::from(addr)),
+                    Frame::Bulk(entry.name.clone().unwrap_or_default()),
+                ])
+            })
+            .collect()
+    }
+}
+
+/// Arguments of an entry, telling how many arguments and bytes were left out so a huge command
+/// doesn't stay in memory.
+fn record_args(kept: SlowlogArgs) -> Vec<Bytes> {
+    let SlowlogArgs { args, count } = kept;
+    let mut recorded = Vec::with_capacity(args.len());
+
+    for (i, (bytes, len)) in args.into_iter().enumerate() {
+        if i == MAX_ARGS - 1 && count > MAX_ARGS {
+            let more = count - i;
+            recorded.push(Bytes::from(format!("... ({more} more arguments)")));
+            break;
+        }
+
+        if len > bytes.len() {
+            let mut truncated = BytesMut::from(&bytes[..]);
+            let more = format!("... ({} more bytes)", len - bytes.len());
+            truncated.extend_from_slice(more.as_bytes());
+            recorded.push(truncated.freeze());
+        } else {
+            recorded.push(bytes);
+        }
+    }
+
+    recorded
+}
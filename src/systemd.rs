@@ -0,0 +1,109 @@
+//! Running the server as a systemd service.
+//!
+//! With socket activation systemd binds the sockets of the service itself and passes them to
+//! the process it starts, see `listen_fds`. Services of `Type=notify` tell systemd when they
+//! are ready to serve clients and when they stop, see `notify` and `Builder::sd_notify`.
+//!
+//! ```ini
+//! # walrus.socket
+//! [Socket]
+//! ListenStream=6380
+//!
+//! # walrus.service
+//! [Service]
+//! Type=notify
+//! ExecStart=/usr/bin/server --dir /var/lib/walrus
+//! ```
+
+use socket2::{Socket, Type};
+use std::{
+    env, io,
+    os::{
+        fd::{FromRawFd, RawFd},
+        unix::{ffi::OsStrExt, net::UnixDatagram},
+    },
+    path::Path,
+    process,
+};
+use tokio::net::{TcpListener, UnixListener};
+
+/// First file descriptor passed by systemd, the ones before are stdin, stdout and stderr.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd, bound and listening.
+#[derive(Debug)]
+pub enum ActivatedSocket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Sockets passed by systemd to the process, in the order of the `ListenStream` options of the
+/// socket unit, empty if the process wasn't socket activated. Like `sd_listen_fds`, sockets
+/// meant for another process, such as the parent of a daemonized server, are ignored.
+///
+/// Must be called from a Tokio runtime, and only once since the returned listeners own the
+/// sockets.
+pub fn listen_fds() -> io::Result<Vec<ActivatedSocket>> {
+    let var = |name| env::var(name).ok().and_then(|value| value.parse().ok());
+    if var("LISTEN_PID") != Some(process::id()) {
+        return Ok(Vec::new());
+    }
+    let Some(count) = var("LISTEN_FDS") else {
+        return Ok(Vec::new());
+    };
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count as RawFd)
+        .map(|fd| {
+            // SAFETY: systemd passes `count` open file descriptors from `LISTEN_FDS_START`,
+            // nothing else in the process owns them.
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            if socket.r#type()? != Type::STREAM {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("file descriptor {fd} passed by systemd isn't a stream socket"),
+                ));
+            }
+            socket.set_nonblocking(true)?;
+
+            if socket.local_addr()?.is_unix() {
+                let listener: std::os::unix::net::UnixListener = socket.into();
+                Ok(ActivatedSocket::Unix(UnixListener::from_std(listener)?))
+            } else {
+                let listener: std::net::TcpListener = socket.into();
+                Ok(ActivatedSocket::Tcp(TcpListener::from_std(listener)?))
+            }
+        })
+        .collect()
+}
+
+/// Send `state` to systemd, such as `READY=1`, over the socket of `NOTIFY_SOCKET`. Returns
+/// `false` if the variable isn't set, the process isn't supervised by systemd.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let socket = UnixDatagram::unbound()?;
+
+    // Sockets in the abstract namespace start with '@'.
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only supported on Linux",
+            ));
+        }
+        None => {
+            socket.send_to(state.as_bytes(), Path::new(&path))?;
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,70 @@
+//! Spawning of the long-lived tasks of the server, such as the connection handlers and the
+//! purge of expired keys.
+//!
+//! With the `console` feature, and the `tokio_unstable` cfg Tokio requires to name tasks, each
+//! task is named after its role so `tokio-console` tells them apart. Otherwise the names are
+//! ignored.
+
+use std::future::Future;
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+
+/// Spawn `future` as a task named `name`.
+#[track_caller]
+pub(crate) fn spawn<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(feature = "console", tokio_unstable))]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn(future)
+        .expect("failed to spawn a task");
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
+
+/// Run `f` on the thread pool for blocking work, as a task named `name`.
+#[track_caller]
+pub(crate) fn spawn_blocking<F, R>(name: &str, f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    #[cfg(all(feature = "console", tokio_unstable))]
+    return tokio::task::Builder::new()
+        .name(name)
+        .spawn_blocking(f)
+        .expect("failed to spawn a blocking task");
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    {
+        let _ = name;
+        tokio::task::spawn_blocking(f)
+    }
+}
+
+/// Spawn `future` in `set` as a task named `name`.
+#[track_caller]
+pub(crate) fn spawn_in<F>(set: &mut JoinSet<F::Output>, name: &str, future: F) -> AbortHandle
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(feature = "console", tokio_unstable))]
+    return set
+        .build_task()
+        .name(name)
+        .spawn(future)
+        .expect("failed to spawn a task");
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    {
+        let _ = name;
+        set.spawn(future)
+    }
+}
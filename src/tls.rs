@@ -0,0 +1,103 @@
+use std::{io, path::Path, path::PathBuf, sync::Arc};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        RootCertStore, ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+        server::WebPkiClientVerifier,
+    },
+};
+
+/// Certificates used to accept TLS connections, and whether clients authenticate with their
+/// own certificate.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain of the server.
+    pub cert_file: PathBuf,
+    /// PEM file with the private key of the server certificate.
+    pub key_file: PathBuf,
+    /// PEM file with the CA certificates client certificates are verified against. Clients
+    /// aren't asked for a certificate without it.
+    pub ca_cert_file: Option<PathBuf>,
+    /// Refuse clients without a certificate signed by the CA of `ca_cert_file`. Otherwise
+    /// clients may connect without one, certificates presented are still verified.
+    pub auth_clients: bool,
+}
+
+impl TlsConfig {
+    /// Load the certificates and build the acceptor making the TLS handshake of connections.
+    pub fn acceptor(&self) -> io::Result<TlsAcceptor> {
+        let provider = Arc::new(ring::default_provider());
+        let certs = CertificateDer::pem_file_iter(&self.cert_file)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| pem_error(&self.cert_file, err))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_file)
+            .map_err(|err| pem_error(&self.key_file, err))?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_input)?;
+        let builder = match &self.ca_cert_file {
+            Some(ca_cert_file) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(ca_cert_file)
+                    .map_err(|err| pem_error(ca_cert_file, err))?
+                {
+                    let cert = cert.map_err(|err| pem_error(ca_cert_file, err))?;
+                    roots.add(cert).map_err(invalid_input)?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if self.auth_clients {
+                    verifier.build()
+                } else {
+                    verifier.allow_unauthenticated().build()
+                };
+                builder.with_client_cert_verifier(verifier.map_err(invalid_input)?)
+            }
+            None if self.auth_clients => {
+                return Err(invalid_input(
+                    "authenticating clients requires a CA certificate",
+                ));
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(certs, key)
+            .map_err(invalid_input)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Listener accepting TLS connections, given to `server::run` as the primary listener or along
+/// with a plain one. The handshake is made by the task handling each connection, a slow
+/// client doesn't hold up the others.
+pub struct TlsListener {
+    pub(crate) listener: TcpListener,
+    pub(crate) acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    /// Accept TLS connections on `listener` with the certificates of `config`.
+    pub fn new(listener: TcpListener, config: &TlsConfig) -> io::Result<TlsListener> {
+        Ok(TlsListener {
+            listener,
+            acceptor: config.acceptor()?,
+        })
+    }
+}
+
+fn pem_error(path: &Path, err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("failed to load {}, {err}", path.display()),
+    )
+}
+
+fn invalid_input(err: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err.to_string())
+}
@@ -0,0 +1,166 @@
+use bytes::Bytes;
+use dashmap::DashMap;
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::frame::Frame;
+use crate::registry::PushSender;
+
+/// Options of a connection with tracking enabled.
+pub(crate) struct TrackingOptions {
+    /// Broadcast mode, invalidations are sent for every key matching one of `prefixes` instead
+    /// of only for keys read by the connection.
+    pub(crate) bcast: bool,
+    /// Key prefixes of interest in broadcast mode. Empty means every key.
+    pub(crate) prefixes: Vec<Bytes>,
+    /// Don't send invalidations for keys modified by the connection itself.
+    pub(crate) noloop: bool,
+}
+
+/// A connection with tracking enabled.
+struct TrackingClient {
+    options: TrackingOptions,
+    /// Channel to the handler of the connection, used to deliver invalidation push messages.
+    sender: PushSender,
+}
+
+/// Server assisted client side caching.
+///
+/// Remembers which keys were read by connections with tracking enabled and sends them an
+/// `invalidate` push message when those keys are modified or expire. A key is only invalidated
+/// once, the connection must read it again to be notified of the next modification.
+pub(crate) struct Tracking {
+    /// Map of key to ids of connections that read the key since it was last invalidated.
+    keys: DashMap<Bytes, HashSet<u64>, ahash::RandomState>,
+    /// Map of connection id to tracking options of the connection.
+    clients: DashMap<u64, TrackingClient>,
+    /// Number of connections with tracking enabled, skips all bookkeeping when zero.
+    enabled: AtomicUsize,
+    /// Number of connections using broadcast mode.
+    bcast: AtomicUsize,
+}
+
+impl Tracking {
+    /// Create a new `Tracking` instance with no tracked connections.
+    pub(crate) fn new() -> Tracking {
+        Tracking {
+            keys: DashMap::with_hasher(ahash::RandomState::new()),
+            clients: DashMap::new(),
+            enabled: AtomicUsize::new(0),
+            bcast: AtomicUsize::new(0),
+        }
+    }
+
+    /// Enable tracking for connection `id`, invalidations are delivered through `sender`.
+    /// Enabling it again replaces the previous options.
+    pub(crate) fn enable(&self, id: u64, options: TrackingOptions, sender: PushSender) {
+        if options.bcast {
+            self.bcast.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let prev = self.clients.insert(id, TrackingClient { options, sender });
+
+        match prev {
+            Some(prev) if prev.options.bcast => {
+                self.bcast.fetch_sub(1, Ordering::Relaxed);
+            }
+            Some(_) => {}
+            None => {
+                self.enabled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Disable tracking for connection `id`.
+    ///
+    /// Keys read by the connection are not scanned, stale ids are dropped when the keys are
+    /// invalidated.
+    pub(crate) fn disable(&self, id: u64) {
+        if let Some((_, client)) = self.clients.remove(&id) {
+            self.enabled.fetch_sub(1, Ordering::Relaxed);
+
+            if client.options.bcast {
+                self.bcast.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns `true` if at least one connection has tracking enabled.
+    pub(crate) fn is_active(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed) > 0
+    }
+
+    /// Remember that connection `id` read `keys`.
+    /// Nothing is recorded if the connection has tracking disabled or uses broadcast mode.
+    pub(crate) fn track(&self, id: u64, keys: &[Bytes]) {
+        if self.enabled.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        match self.clients.get(&id) {
+            Some(client) if !client.options.bcast => {}
+            _ => return,
+        }
+
+        for key in keys {
+            self.keys.entry(key.clone()).or_default().insert(id);
+        }
+    }
+
+    /// Notify connections interested in `key` that it was modified.
+    ///
+    /// `origin` is the id of the connection that modified the key, `None` if the key was
+    /// modified by the server itself, for example when it expired.
+    pub(crate) fn invalidate(&self, key: &Bytes, origin: Option<u64>) {
+        if self.enabled.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let notify = |id: u64, client: &TrackingClient| {
+            if client.options.noloop && origin == Some(id) {
+                return;
+            }
+
+            client.sender.send(invalidate_frame(key));
+        };
+
+        // Connections that read the key.
+        if let Some((_, readers)) = self.keys.remove(key) {
+            for id in readers {
+                if let Some(client) = self.clients.get(&id)
+                    && !client.options.bcast
+                {
+                    notify(id, &client);
+                }
+            }
+        }
+
+        // Connections in broadcast mode subscribed to a matching prefix.
+        if self.bcast.load(Ordering::Relaxed) > 0 {
+            for client in self.clients.iter() {
+                let options = &client.options;
+
+                let interested = options.bcast
+                    && (options.prefixes.is_empty()
+                        || options
+                            .prefixes
+                            .iter()
+                            .any(|prefix| key.starts_with(prefix)));
+
+                if interested {
+                    notify(*client.key(), &client);
+                }
+            }
+        }
+    }
+}
+
+/// Build the `invalidate` push message for `key`.
+fn invalidate_frame(key: &Bytes) -> Frame {
+    Frame::Push(vec![
+        Frame::Bulk(Bytes::from("invalidate")),
+        Frame::Array(vec![Frame::Bulk(key.clone())]),
+    ])
+}
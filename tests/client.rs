@@ -1,12 +1,17 @@
-use walrus::client::{Client, double_to_string, int_to_string};
+use walrus::Frame;
+use walrus::client::{Client, ReconnectPolicy, SharedClient, double_to_string, int_to_string};
 use walrus::db::Data;
+use walrus::errors::WalrusError;
 
 use bytes::Bytes;
 use rand::{RngExt, distr::Alphanumeric, random};
-use std::{collections::VecDeque, time::Duration};
+use std::{collections::VecDeque, path::PathBuf, time::Duration};
 use tokio::time::{Instant, sleep_until};
 
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 static SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
 
 fn ensure_server_running() {
@@ -38,10 +43,119 @@ async fn connect_client() -> Client {
     .unwrap()
 }
 
+/// Start a dedicated server on a random port, for tests that change server wide state.
+async fn start_dedicated_server() -> String {
+    start_server_in(None, false).await
+}
+
+/// Start a dedicated server persisting to `dir`, optionally with the append only file.
+async fn start_server_in(dir: Option<PathBuf>, appendonly: bool) -> String {
+    start_server_with(dir, appendonly, None).await
+}
+
+/// Start a dedicated server persisting to `dir`, optionally importing a Redis dump.
+async fn start_server_with(
+    dir: Option<PathBuf>,
+    appendonly: bool,
+    load_rdb: Option<PathBuf>,
+) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut builder = walrus::server::Builder::new(listener)
+        .port(addr.port())
+        .appendonly(appendonly);
+    if let Some(dir) = dir {
+        builder = builder.dir(dir);
+    }
+    if let Some(path) = load_rdb {
+        builder = builder.load_rdb(path);
+    }
+    tokio::spawn(builder.run(std::future::pending::<()>()));
+    let addr = addr.to_string();
+    wait_until_loaded(&addr).await;
+    addr
+}
+
+/// Start a dedicated server allowing the `DEBUG` command.
+#[cfg(feature = "debug-command")]
+async fn start_debug_server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let builder = walrus::server::Builder::new(listener)
+        .port(addr.port())
+        .enable_debug_command(walrus::server::DebugCommand::Yes);
+    tokio::spawn(builder.run(std::future::pending::<()>()));
+    let addr = addr.to_string();
+    wait_until_loaded(&addr).await;
+    addr
+}
+
+/// Start a dedicated server in sentinel mode.
+async fn start_sentinel() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .port(addr.port())
+            .sentinel(true)
+            .run(std::future::pending::<()>()),
+    );
+    let addr = addr.to_string();
+    wait_until_loaded(&addr).await;
+    addr
+}
+
+/// Start a dedicated server in cluster mode, returns its address and the port of its cluster
+/// bus.
+async fn start_cluster_node() -> (String, u16) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let bus = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let bus_port = bus.local_addr().unwrap().port();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .port(addr.port())
+            .cluster_bus(bus)
+            .run(std::future::pending::<()>()),
+    );
+    let addr = addr.to_string();
+    wait_until_loaded(&addr).await;
+    (addr, bus_port)
+}
+
+/// Wait until the server at `addr` has loaded its dataset, or stopped because it couldn't.
+async fn wait_until_loaded(addr: &str) {
+    loop {
+        let Ok(mut client) =
+            Client::connect(addr.to_string(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE).await
+        else {
+            return;
+        };
+
+        match client.info(vec![Bytes::from("persistence")]).await {
+            Ok(info) if info_field(&info, "loading").as_deref() == Some("1") => {}
+            _ => return,
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Create an empty directory for the persistence files of a test.
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "walrus-test-{}",
+        String::from_utf8_lossy(&random_bytes(12))
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
 const SERVER_IPADDRESS: &str = "127.0.0.1:6380";
 const READ_BUFFER_SIZE: Option<u16> = Some(32);
 const WRITE_BUFFER_SIZE: Option<u16> = Some(32);
 
+#[allow(clippy::useless_conversion)]
 fn random_bytes(len: usize) -> Bytes {
     rand::rng()
         .sample_iter(&Alphanumeric)
@@ -166,6 +280,133 @@ async fn set_get_test_after_expire() {
     }
 }
 
+/// `SET NX` only sets keys that don't exist or expired, and exactly one of the clients setting
+/// the same key concurrently succeeds.
+#[tokio::test]
+async fn set_nx_test() {
+    let mut client = connect_client().await;
+
+    let key = random_bytes(8);
+    assert!(
+        client
+            .set_nx(key.clone(), Bytes::from("first"), None)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !client
+            .set_nx(key.clone(), Bytes::from("second"), None)
+            .await
+            .unwrap()
+    );
+    assert_eq!(client.get(key).await.unwrap(), Some(Bytes::from("first")));
+
+    let expiring = random_bytes(8);
+    let expire = Some(Duration::from_millis(50));
+    assert!(
+        client
+            .set_nx(expiring.clone(), Bytes::from("first"), expire)
+            .await
+            .unwrap()
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(
+        client
+            .set_nx(expiring.clone(), Bytes::from("second"), None)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        client.get(expiring).await.unwrap(),
+        Some(Bytes::from("second"))
+    );
+
+    let contended = random_bytes(8);
+    let mut handles = Vec::new();
+    for n in 0..8 {
+        let contended = contended.clone();
+        let mut client = connect_client().await;
+        handles.push(tokio::spawn(async move {
+            client
+                .set_nx(contended, Bytes::from(n.to_string()), None)
+                .await
+                .unwrap()
+        }));
+    }
+    let mut set = 0;
+    for handle in handles {
+        set += handle.await.unwrap() as usize;
+    }
+    assert_eq!(set, 1);
+}
+
+/// Sets keys expiring over a few hundred milliseconds, some of which are set again with a later
+/// or no expiration. Only the keys still expiring are expected to be gone afterwards.
+#[tokio::test]
+async fn set_get_test_replaced_expire() {
+    let mut client = connect_client().await;
+
+    let keys: Vec<_> = (0..40).map(|_| random_bytes(6)).collect();
+    for (i, key) in keys.iter().enumerate() {
+        let expire = Duration::from_millis(5 + 7 * i as u64);
+        client
+            .set(key.clone(), Bytes::from("first"), Some(expire))
+            .await
+            .unwrap();
+    }
+
+    // Every third key is kept, every other third expires much later.
+    for key in keys.iter().step_by(3) {
+        client
+            .set(key.clone(), Bytes::from("kept"), None)
+            .await
+            .unwrap();
+    }
+    for key in keys.iter().skip(1).step_by(3) {
+        client
+            .set(
+                key.clone(),
+                Bytes::from("later"),
+                Some(Duration::from_secs(60)),
+            )
+            .await
+            .unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    for (i, key) in keys.iter().enumerate() {
+        let expected = match i % 3 {
+            0 => Some(Bytes::from("kept")),
+            1 => Some(Bytes::from("later")),
+            _ => None,
+        };
+        assert_eq!(client.get(key.clone()).await.unwrap(), expected);
+    }
+}
+
+/// Sets a burst of keys expiring at about the same instant, more than the background task purges
+/// in one cycle. Every key is expected to be gone once they expired.
+#[tokio::test]
+async fn set_get_test_expire_burst() {
+    let mut client = connect_client().await;
+
+    let expire = Duration::from_millis(100);
+    let keys: Vec<_> = (0..3000).map(|_| random_bytes(8)).collect();
+    for key in &keys {
+        client
+            .set(key.clone(), Bytes::from("value"), Some(expire))
+            .await
+            .unwrap();
+    }
+
+    tokio::time::sleep(expire + Duration::from_millis(400)).await;
+
+    for key in keys {
+        assert_eq!(client.get(key).await.unwrap(), None);
+    }
+}
+
 /// Sets a key value pair with 1000 millisecond expiration.
 /// Attempts to fetch the value of the same key before the key expires.
 /// The expected response is a Bulk frame containing the value of the key.
@@ -217,6 +458,7 @@ async fn get_double_trailing_zeros_test() {
 
 /// Pushes a list containing Data into server db.
 /// Checks if the response is not zero.
+#[allow(clippy::unnecessary_cast)]
 #[tokio::test]
 async fn rpush_test() {
     let mut client = connect_client().await;
@@ -233,6 +475,7 @@ async fn rpush_test() {
 
 /// Creates a list with key `list_key` and then pushes another list to the front of the list.
 /// Checks if the length of the list is the sum of the two lists.
+#[allow(clippy::useless_conversion)]
 #[tokio::test]
 async fn lpush_test() {
     let mut client = connect_client().await;
@@ -255,6 +498,42 @@ async fn lpush_test() {
     assert_eq!(lpush_response, len + len2);
 }
 
+/// Clients pushing to the same new lists concurrently don't lose any element, a list created
+/// by one client is pushed to by the others rather than replaced.
+#[tokio::test]
+async fn push_test_concurrent_create() {
+    const CLIENTS: usize = 8;
+    let keys: Vec<Bytes> = (0..200).map(|_| random_bytes(8)).collect();
+    let barrier = Arc::new(tokio::sync::Barrier::new(CLIENTS));
+
+    let mut handles = Vec::with_capacity(CLIENTS);
+    for n in 0..CLIENTS {
+        let keys = keys.clone();
+        let barrier = barrier.clone();
+        let mut client = connect_client().await;
+        handles.push(tokio::spawn(async move {
+            for key in keys {
+                // Every client pushes to the key at once.
+                barrier.wait().await;
+                let data = VecDeque::from([Data::Integer(n as i64)]);
+                if n % 2 == 0 {
+                    client.rpush(key, data).await.unwrap();
+                } else {
+                    client.lpush(key, data).await.unwrap();
+                }
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let mut client = connect_client().await;
+    for key in keys {
+        assert_eq!(client.llen(key).await.unwrap(), CLIENTS as i64);
+    }
+}
+
 /// Pushes a list to the server db and then requests the full list back.
 /// checks if the returned list has same elements as the one sent originally.
 /// start is 0 and end is length of list - 1.
@@ -554,6 +833,7 @@ async fn wtype_test_list() {
     assert_eq!(wtype_response, "list");
 }
 
+#[allow(clippy::useless_conversion)]
 #[tokio::test]
 async fn wtype_test_string() {
     let mut client = connect_client().await;
@@ -622,6 +902,61 @@ async fn test_pipeline_processing() {
     assert_eq!(response, expected_response);
 }
 
+#[tokio::test]
+async fn large_value_test() {
+    let mut client = connect_client().await;
+
+    // Values around and far above the size from which bulk strings are written without copy.
+    let sizes = [16 * 1024 - 1, 16 * 1024, 100 * 1024, 1024 * 1024];
+    let values: Vec<Bytes> = sizes
+        .iter()
+        .enumerate()
+        .map(|(i, size)| Bytes::from(vec![b'a' + i as u8; *size]))
+        .collect();
+
+    for value in &values {
+        let key = random_bytes(8);
+        client.set(key.clone(), value.clone(), None).await.unwrap();
+        assert_eq!(client.get(key).await.unwrap(), Some(value.clone()));
+    }
+
+    let list_key = random_bytes(8);
+    let items: VecDeque<Data> = values.iter().cloned().map(Data::Bytes).collect();
+    client.rpush(list_key.clone(), items.clone()).await.unwrap();
+    let range = client.lrange(list_key, 0, -1).await.unwrap();
+    assert_eq!(range, Vec::from(items));
+}
+
+#[tokio::test]
+async fn large_pipeline_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    let mut client = connect_client().await;
+    let key = random_bytes(8);
+    let value = Bytes::from(vec![b'x'; 1000]);
+    client.set(key.clone(), value.clone(), None).await.unwrap();
+
+    // The replies of the whole pipeline are far more than a single batch.
+    let count = 500;
+    let mut request = Vec::new();
+    for _ in 0..count {
+        request.extend_from_slice(format!("*2\r\n$3\r\nGET\r\n${}\r\n", key.len()).as_bytes());
+        request.extend_from_slice(&key);
+        request.extend_from_slice(b"\r\n");
+    }
+    let mut reply = format!("${}\r\n", value.len()).into_bytes();
+    reply.extend_from_slice(&value);
+    reply.extend_from_slice(b"\r\n");
+    let expected = reply.repeat(count);
+
+    let mut stream = TcpStream::connect(SERVER_IPADDRESS).await.unwrap();
+    stream.write_all(&request).await.unwrap();
+    let mut response = vec![0; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(response, expected);
+}
+
 #[tokio::test]
 async fn blpop_multiple_waiters_fifo_order() {
     let mut client1 = connect_client().await;
@@ -719,3 +1054,5122 @@ async fn test_defensive_parsing_malformed_protocol() {
     let n = stream.read(&mut buffer).await.unwrap();
     assert_eq!(n, 0, "Server should close connection on malformed protocol");
 }
+
+#[tokio::test]
+async fn hello_test_resp2() {
+    let mut client = connect_client().await;
+
+    let hello_response = client.hello(Some(2)).await.unwrap();
+
+    assert_eq!(hello_response.len(), 14);
+    assert_eq!(hello_response[0], Data::Bytes(Bytes::from("server")));
+    assert_eq!(hello_response[1], Data::Bytes(Bytes::from("walrus")));
+    assert_eq!(hello_response[4], Data::Bytes(Bytes::from("proto")));
+    assert_eq!(hello_response[5], Data::Integer(2));
+}
+
+#[tokio::test]
+async fn hello_test_unsupported_protocol() {
+    let mut client = connect_client().await;
+
+    let hello_response = client.hello(Some(4)).await;
+    assert!(hello_response.is_err());
+
+    // Connection is still usable after the error.
+    let ping_response = client.ping(None).await.unwrap();
+    assert_eq!(ping_response, Bytes::from("PONG"));
+}
+
+#[tokio::test]
+async fn hello_test_resp3() {
+    let mut client = connect_client().await;
+
+    // Map reply is flattened into alternating names and values.
+    let hello_response = client.hello(Some(3)).await.unwrap();
+
+    assert_eq!(hello_response.len(), 14);
+    assert_eq!(hello_response[4], Data::Bytes(Bytes::from("proto")));
+    assert_eq!(hello_response[5], Data::Integer(3));
+
+    // `_` null is returned for missing keys on RESP3 connections.
+    let get_response = client.get(random_bytes(6)).await.unwrap();
+    assert_eq!(get_response, None);
+
+    // Doubles are sent with the RESP3 double type.
+    let list_key = random_bytes(6);
+    let data = VecDeque::from([Data::Double(1.5)]);
+    client.rpush(list_key.clone(), data).await.unwrap();
+    let lrange_response = client.lrange(list_key, 0, -1).await.unwrap();
+    assert_eq!(lrange_response, vec![Data::Double(1.5)]);
+}
+
+#[tokio::test]
+async fn client_setname_getname_test() {
+    let mut client = connect_client().await;
+
+    assert_eq!(client.client_getname().await.unwrap(), None);
+
+    let name = random_bytes(8);
+    client.client_setname(name.clone()).await.unwrap();
+    assert_eq!(client.client_getname().await.unwrap(), Some(name.clone()));
+
+    // Name is visible in the list of connections.
+    let id = client.client_id().await.unwrap();
+    let list = client.client_list().await.unwrap();
+    let line = String::from_utf8_lossy(&list)
+        .lines()
+        .find(|line| line.starts_with(&format!("id={id} ")))
+        .map(str::to_string)
+        .unwrap();
+    assert!(line.contains(&format!("name={}", String::from_utf8_lossy(&name))));
+
+    // Names with spaces are rejected.
+    assert!(client.client_setname(Bytes::from("a b")).await.is_err());
+}
+
+#[tokio::test]
+async fn client_kill_test() {
+    let mut client = connect_client().await;
+    let mut victim = connect_client().await;
+
+    let victim_id = victim.client_id().await.unwrap();
+
+    // Victim blocks, killing it must cancel the blocked command.
+    let handle = tokio::spawn(async move { victim.blpop(vec![random_bytes(6)], 0.0).await });
+
+    // Allow the victim to block.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(client.client_kill(victim_id as u64).await.unwrap(), 1);
+    assert!(handle.await.unwrap().is_err());
+
+    // Killed connection is removed from the registry.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(client.client_kill(victim_id as u64).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn client_pause_write_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut writer = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let key = random_bytes(6);
+    client
+        .client_pause(Duration::from_millis(300), true)
+        .await
+        .unwrap();
+
+    // Reads are not paused.
+    let start = Instant::now();
+    assert_eq!(writer.get(key.clone()).await.unwrap(), None);
+    assert!(start.elapsed() < Duration::from_millis(300));
+
+    // Writes wait for the pause to end.
+    writer
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(250));
+}
+
+#[tokio::test]
+async fn client_unpause_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut paused = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .client_pause(Duration::from_secs(10), false)
+        .await
+        .unwrap();
+
+    let start = Instant::now();
+    let handle = tokio::spawn(async move { paused.ping(None).await });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.client_unpause().await.unwrap();
+
+    assert_eq!(handle.await.unwrap().unwrap(), Bytes::from("PONG"));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn client_tracking_test() {
+    let mut client = connect_client().await;
+    let mut writer = connect_client().await;
+
+    // Tracking requires RESP3.
+    assert!(
+        client
+            .client_tracking(true, false, vec![], false)
+            .await
+            .is_err()
+    );
+
+    client.hello(Some(3)).await.unwrap();
+    client
+        .client_tracking(true, false, vec![], false)
+        .await
+        .unwrap();
+
+    let key = random_bytes(6);
+    client.get(key.clone()).await.unwrap();
+
+    writer
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let push = client.next_push().await.unwrap();
+    assert_eq!(
+        push,
+        vec![
+            Frame::Bulk(Bytes::from("invalidate")),
+            Frame::Array(vec![Frame::Bulk(key)]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn client_tracking_bcast_test() {
+    let mut client = connect_client().await;
+    let mut writer = connect_client().await;
+
+    let prefix = random_bytes(6);
+    client.hello(Some(3)).await.unwrap();
+    client
+        .client_tracking(true, true, vec![prefix.clone()], false)
+        .await
+        .unwrap();
+
+    // Keys not matching the prefix are ignored, keys matching it are invalidated without being
+    // read first.
+    writer
+        .set(random_bytes(8), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let key = Bytes::from([&prefix[..], b":key"].concat());
+    writer
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let push = client.next_push().await.unwrap();
+    assert_eq!(
+        push,
+        vec![
+            Frame::Bulk(Bytes::from("invalidate")),
+            Frame::Array(vec![Frame::Bulk(key)]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn config_get_set_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.config_get(Bytes::from("maxmemory")).await.unwrap(),
+        vec![(Bytes::from("maxmemory"), Bytes::from("0"))]
+    );
+
+    client
+        .config_set(Bytes::from("maxmemory"), Bytes::from("1mb"))
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("notify-keyspace-events"), Bytes::from("KEA"))
+        .await
+        .unwrap();
+
+    // Names are glob patterns.
+    assert_eq!(
+        client.config_get(Bytes::from("*m?mory")).await.unwrap(),
+        vec![(Bytes::from("maxmemory"), Bytes::from("1048576"))]
+    );
+    assert_eq!(
+        client.config_get(Bytes::from("NOTIFY-*")).await.unwrap(),
+        vec![(Bytes::from("notify-keyspace-events"), Bytes::from("AKE"))]
+    );
+
+    // Invalid values, unknown and immutable parameters are rejected.
+    assert!(
+        client
+            .config_set(Bytes::from("maxmemory"), Bytes::from("lots"))
+            .await
+            .is_err()
+    );
+    assert!(
+        client
+            .config_set(Bytes::from("no-such-param"), Bytes::from("1"))
+            .await
+            .is_err()
+    );
+    assert!(
+        client
+            .config_set(Bytes::from("port"), Bytes::from("1234"))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn config_maxclients_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .config_set(Bytes::from("maxclients"), Bytes::from("1"))
+        .await
+        .unwrap();
+
+    // Connections over the limit are refused.
+    let mut refused = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert!(refused.ping(None).await.is_err());
+}
+
+#[tokio::test]
+async fn command_info_test() {
+    let mut client = connect_client().await;
+
+    let count = client.command_count().await.unwrap();
+    assert_eq!(
+        client.command_info(vec![]).await.unwrap().len() as i64,
+        count
+    );
+
+    let info = client
+        .command_info(vec![Bytes::from("GET"), Bytes::from("no-such-command")])
+        .await
+        .unwrap();
+
+    // Name, arity and first key, last key and step.
+    let Frame::Array(get) = &info[0] else {
+        panic!("expected array, got {:?}", info[0]);
+    };
+    assert_eq!(get[0], Frame::Bulk(Bytes::from("get")));
+    assert_eq!(get[1], Frame::Integer(2));
+    assert_eq!(
+        get[2],
+        Frame::Array(vec![
+            Frame::Simple(Bytes::from("readonly")),
+            Frame::Simple(Bytes::from("fast"))
+        ])
+    );
+    assert_eq!(
+        &get[3..6],
+        &[Frame::Integer(1), Frame::Integer(1), Frame::Integer(1)]
+    );
+
+    assert_eq!(info[1], Frame::Null);
+}
+
+#[tokio::test]
+async fn monitor_test() {
+    let addr = start_dedicated_server().await;
+    let mut monitor = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    monitor.monitor().await.unwrap();
+
+    client
+        .set(Bytes::from("key"), Bytes::from("a \"quoted\" value"), None)
+        .await
+        .unwrap();
+
+    // Arguments are quoted and escaped.
+    let line = monitor.next_monitor().await.unwrap();
+    let line = String::from_utf8_lossy(&line);
+    assert!(line.contains(" [0 127.0.0.1:"), "{line}");
+    assert!(
+        line.ends_with(r#"] "set" "key" "a \"quoted\" value""#),
+        "{line}"
+    );
+}
+
+#[tokio::test]
+async fn slowlog_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Log every command.
+    client
+        .config_set(Bytes::from("slowlog-log-slower-than"), Bytes::from("0"))
+        .await
+        .unwrap();
+    client.slowlog_reset().await.unwrap();
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // `SLOWLOG RESET` and `SET` are logged, newest first.
+    assert_eq!(client.slowlog_len().await.unwrap(), 2);
+
+    // `SLOWLOG LEN` was logged as well.
+    let entries = client.slowlog_get(Some(2)).await.unwrap();
+    assert_eq!(entries.len(), 2);
+
+    let Frame::Array(entry) = &entries[1] else {
+        panic!("expected array, got {:?}", entries[1]);
+    };
+    assert_eq!(
+        entry[3],
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("set")),
+            Frame::Bulk(Bytes::from("key")),
+            Frame::Bulk(Bytes::from("value")),
+        ])
+    );
+
+    // Long arguments are truncated, telling how many bytes were left out.
+    client
+        .set(Bytes::from("key"), Bytes::from("v".repeat(200)), None)
+        .await
+        .unwrap();
+    let entries = client.slowlog_get(Some(1)).await.unwrap();
+    let Frame::Array(entry) = &entries[0] else {
+        panic!("expected array, got {:?}", entries[0]);
+    };
+    assert_eq!(
+        entry[3],
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from("set")),
+            Frame::Bulk(Bytes::from("key")),
+            Frame::Bulk(Bytes::from(format!(
+                "{}... (72 more bytes)",
+                "v".repeat(128)
+            ))),
+        ])
+    );
+
+    client.slowlog_reset().await.unwrap();
+    assert_eq!(client.slowlog_len().await.unwrap(), 1);
+}
+
+#[cfg(feature = "debug-command")]
+#[tokio::test]
+async fn debug_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let key = Bytes::from("key");
+    client
+        .set(
+            key.clone(),
+            Bytes::from("value"),
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+
+    // Disabled by default, clients can't enable it.
+    assert!(client.debug_object(key.clone()).await.is_err());
+    assert!(
+        client
+            .config_set(Bytes::from("enable-debug-command"), Bytes::from("yes"))
+            .await
+            .is_err()
+    );
+
+    let addr = start_debug_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .set(
+            key.clone(),
+            Bytes::from("value"),
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+
+    let description = client.debug_object(key.clone()).await.unwrap();
+    assert!(description.starts_with(b"type:string encoding:embstr len:5 "));
+
+    // Expired keys are kept while active expiration is disabled.
+    client.debug_set_active_expire(false).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(client.debug_object(key.clone()).await.is_ok());
+
+    client.debug_set_active_expire(true).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(client.debug_object(key).await.is_err());
+}
+
+#[cfg(feature = "debug-command")]
+#[tokio::test]
+async fn latency_test() {
+    let addr = start_debug_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // The latency monitor is disabled by default.
+    client
+        .debug_async_sleep(Duration::from_millis(20))
+        .await
+        .unwrap();
+    assert!(client.latency_latest().await.unwrap().is_empty());
+
+    client
+        .config_set(Bytes::from("latency-monitor-threshold"), Bytes::from("10"))
+        .await
+        .unwrap();
+    client
+        .debug_async_sleep(Duration::from_millis(20))
+        .await
+        .unwrap();
+
+    let latest = client.latency_latest().await.unwrap();
+    assert_eq!(latest.len(), 1);
+    let Frame::Array(spike) = &latest[0] else {
+        panic!("expected array, got {:?}", latest[0]);
+    };
+    assert_eq!(spike[0], Frame::Bulk(Bytes::from("command")));
+    assert!(matches!(spike[2], Frame::Integer(latency) if latency >= 20));
+
+    let history = client.latency_history("command").await.unwrap();
+    assert_eq!(history.len(), 1);
+    assert!(
+        client
+            .latency_history("aof-fsync")
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    assert_eq!(client.latency_reset(&["aof-fsync"]).await.unwrap(), 0);
+    assert_eq!(client.latency_reset(&[]).await.unwrap(), 1);
+    assert!(client.latency_latest().await.unwrap().is_empty());
+
+    // Execution times of each command are tracked regardless of the threshold.
+    let info = client
+        .info(vec![Bytes::from("latencystats")])
+        .await
+        .unwrap();
+    let debug = info_field(&info, "latency_percentiles_usec_debug").unwrap();
+    assert!(debug.starts_with("p50="), "{debug}");
+    assert!(
+        debug.contains(",p99=") && debug.contains(",p99.9="),
+        "{debug}"
+    );
+
+    client
+        .config_set(
+            Bytes::from("latency-tracking-info-percentiles"),
+            Bytes::from("90"),
+        )
+        .await
+        .unwrap();
+    let info = client
+        .info(vec![Bytes::from("latencystats")])
+        .await
+        .unwrap();
+    let debug = info_field(&info, "latency_percentiles_usec_debug").unwrap();
+    assert!(debug.starts_with("p90=") && !debug.contains(','), "{debug}");
+}
+
+/// Expired keys are not visible while the background task hasn't purged them yet.
+#[cfg(feature = "debug-command")]
+#[tokio::test]
+async fn lazy_expire_test() {
+    let addr = start_debug_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client.debug_set_active_expire(false).await.unwrap();
+
+    let expire = Some(Duration::from_millis(50));
+    for key in ["string", "type", "list"] {
+        client
+            .set(Bytes::from(key), Bytes::from("value"), expire)
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(client.debug_object(Bytes::from("string")).await.is_ok());
+    assert_eq!(client.get(Bytes::from("string")).await.unwrap(), None);
+    assert!(client.debug_object(Bytes::from("string")).await.is_err());
+
+    assert_eq!(client.wtype(Bytes::from("type")).await.unwrap(), "none");
+    assert_eq!(client.llen(Bytes::from("type")).await.unwrap(), 0);
+
+    // The expired string is replaced by a new list.
+    let list = random_data_array(3);
+    assert_eq!(
+        client
+            .rpush(Bytes::from("list"), list.clone())
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        VecDeque::from(client.lrange(Bytes::from("list"), 0, -1).await.unwrap()),
+        list
+    );
+}
+
+/// KEYS returns the keys matching a pattern at once, SCAN visits every key incrementally.
+#[tokio::test]
+async fn keys_scan_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let mut strings = std::collections::HashSet::new();
+    for i in 0..2000 {
+        let key = Bytes::from(format!("string:{i}"));
+        client
+            .set(key.clone(), Bytes::from("value"), None)
+            .await
+            .unwrap();
+        strings.insert(key);
+    }
+    for i in 0..10 {
+        client
+            .rpush(Bytes::from(format!("list:{i}")), random_data_array(1))
+            .await
+            .unwrap();
+    }
+
+    let mut lists = client.keys(Bytes::from("list:*")).await.unwrap();
+    lists.sort();
+    let expected: Vec<_> = (0..10).map(|i| Bytes::from(format!("list:{i}"))).collect();
+    assert_eq!(lists, expected);
+    assert_eq!(client.keys(Bytes::from("*")).await.unwrap().len(), 2010);
+    assert!(client.keys(Bytes::from("none:*")).await.unwrap().is_empty());
+
+    // About `count` keys are returned by every call, not whole shards.
+    let (cursor, keys) = client.scan(0, None, Some(10)).await.unwrap();
+    assert_ne!(cursor, 0);
+    assert!((10..30).contains(&keys.len()), "{} keys", keys.len());
+
+    // Keys added during the iteration grow the index, keys already there are still visited.
+    let mut cursor = 0;
+    let mut calls = 0;
+    let mut visited = std::collections::HashSet::new();
+    loop {
+        let (next, keys) = client
+            .scan(cursor, Some(Bytes::from("string:*")), Some(100))
+            .await
+            .unwrap();
+        visited.extend(keys);
+        for i in 0..100 {
+            client
+                .set(
+                    Bytes::from(format!("added:{calls}:{i}")),
+                    Bytes::new(),
+                    None,
+                )
+                .await
+                .unwrap();
+        }
+        calls += 1;
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert_eq!(visited, strings);
+    assert!(calls > 1);
+
+    assert!(client.scan(u64::MAX, None, None).await.is_ok());
+}
+
+/// Commands against a key holding the wrong kind of value reply with an error, and the
+/// connection stays usable.
+#[tokio::test]
+async fn wrongtype_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+    client
+        .set(string.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .rpush(list.clone(), random_data_array(3))
+        .await
+        .unwrap();
+
+    let is_wrongtype = |err: WalrusError| err.to_string().starts_with("WRONGTYPE");
+    assert!(is_wrongtype(client.get(list.clone()).await.unwrap_err()));
+    assert!(is_wrongtype(
+        client
+            .rpush(string.clone(), random_data_array(1))
+            .await
+            .unwrap_err()
+    ));
+    assert!(is_wrongtype(
+        client
+            .lpush(string.clone(), random_data_array(1))
+            .await
+            .unwrap_err()
+    ));
+    assert!(is_wrongtype(
+        client.lpop(string.clone(), None).await.unwrap_err()
+    ));
+    assert!(is_wrongtype(
+        client.blpop(vec![string.clone()], 0.1).await.unwrap_err()
+    ));
+    assert!(is_wrongtype(client.llen(string.clone()).await.unwrap_err()));
+    assert!(is_wrongtype(
+        client.lrange(string.clone(), 0, -1).await.unwrap_err()
+    ));
+
+    // Neither value was changed.
+    assert_eq!(
+        client.get(string).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    assert_eq!(client.llen(list).await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn lolwut_test() {
+    let mut client = connect_client().await;
+
+    let art = client.lolwut().await.unwrap();
+    let art = String::from_utf8_lossy(&art);
+
+    // Drawing of the default size followed by the version.
+    let lines: Vec<_> = art.lines().collect();
+    assert_eq!(lines.len(), 14);
+    assert!(lines[..12].iter().all(|line| line.len() == 48));
+    assert!(lines[13].starts_with("Walrus ver. "));
+}
+
+#[tokio::test]
+async fn save_and_load_snapshot_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let list = random_data_array(10);
+    client
+        .set(Bytes::from("bytes"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("integer"), Bytes::from("42"), None)
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("ttl"),
+            Bytes::from("value"),
+            Some(Duration::from_secs(60)),
+        )
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("expired"),
+            Bytes::from("value"),
+            Some(Duration::from_millis(1)),
+        )
+        .await
+        .unwrap();
+    client
+        .rpush(Bytes::from("list"), list.clone())
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    client.save().await.unwrap();
+    assert!(dir.join("dump.wdb").exists());
+
+    // A new server over the same directory starts with the saved dataset.
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.get(Bytes::from("bytes")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    assert_eq!(
+        client.get(Bytes::from("integer")).await.unwrap(),
+        Some(Bytes::from("42"))
+    );
+    assert!(client.get(Bytes::from("ttl")).await.unwrap().is_some());
+    assert_eq!(client.get(Bytes::from("expired")).await.unwrap(), None);
+    assert_eq!(
+        VecDeque::from(client.lrange(Bytes::from("list"), 0, -1).await.unwrap()),
+        list
+    );
+
+    // A damaged snapshot is refused rather than ignored.
+    let path = dir.join("dump.wdb");
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 10;
+    bytes[last] ^= 0xFF;
+    std::fs::write(&path, bytes).unwrap();
+
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(
+        Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn snapshot_section_checksum_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client.save().await.unwrap();
+
+    // Flip a byte of the entries, past the file header and the section header.
+    let path = dir.join("dump.wdb");
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[12 + 17 + 2] ^= 0xFF;
+    std::fs::write(&path, bytes).unwrap();
+
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(
+        Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .is_err()
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn snapshot_compression_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let compressions = [
+        ("lz4", cfg!(feature = "lz4")),
+        ("zstd", cfg!(feature = "zstd")),
+    ];
+
+    for (compression, supported) in compressions {
+        let result = client
+            .config_set(
+                Bytes::from("snapshot-compression"),
+                Bytes::from(compression),
+            )
+            .await;
+
+        if !supported {
+            assert!(result.is_err());
+            continue;
+        }
+        result.unwrap();
+
+        let value = Bytes::from(compression.repeat(1000));
+        client
+            .set(Bytes::from("key"), value.clone(), None)
+            .await
+            .unwrap();
+        client.save().await.unwrap();
+
+        let addr = start_server_in(Some(dir.clone()), false).await;
+        let mut loaded = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(loaded.get(Bytes::from("key")).await.unwrap(), Some(value));
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn load_rdb_test() {
+    let dir = temp_dir();
+    let expires_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+        + 60_000;
+
+    let mut rdb = b"REDIS0011".to_vec();
+    // Auxiliary field and database selection.
+    rdb.extend_from_slice(b"\xFA\x09redis-ver\x057.2.0\xFE\x00\xFB\x06\x01");
+    // Plain, integer encoded and LZF compressed strings.
+    rdb.extend_from_slice(b"\x00\x03str\x05hello");
+    rdb.extend_from_slice(b"\x00\x03int\xC0\x2A");
+    rdb.extend_from_slice(b"\x00\x03lzf\xC3\x05\x0A\x00a\xE0\x00\x00");
+    // Strings with an expiration, in the future and in the past.
+    rdb.push(0xFC);
+    rdb.extend_from_slice(&expires_at.to_le_bytes());
+    rdb.extend_from_slice(b"\x00\x03ttl\x01v");
+    rdb.extend_from_slice(b"\xFC\xE8\x03\x00\x00\x00\x00\x00\x00\x00\x07expired\x01v");
+    // Quicklist with a listpack node holding "a" and 5, then a plain node.
+    rdb.extend_from_slice(b"\x12\x03lst\x02");
+    rdb.extend_from_slice(b"\x02\x0C\x0C\x00\x00\x00\x02\x00\x81a\x02\x05\x01\xFF");
+    rdb.extend_from_slice(b"\x01\x05plain");
+    // Hash, not supported by walrus.
+    rdb.extend_from_slice(b"\x04\x04hash\x01\x05field\x05value");
+    // End of file, without a checksum.
+    rdb.extend_from_slice(b"\xFF\x00\x00\x00\x00\x00\x00\x00\x00");
+
+    let path = dir.join("dump.rdb");
+    std::fs::write(&path, &rdb).unwrap();
+
+    let addr = start_server_with(Some(dir.clone()), false, Some(path)).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.get(Bytes::from("str")).await.unwrap(),
+        Some(Bytes::from("hello"))
+    );
+    assert_eq!(
+        client.get(Bytes::from("int")).await.unwrap(),
+        Some(Bytes::from("42"))
+    );
+    assert_eq!(
+        client.get(Bytes::from("lzf")).await.unwrap(),
+        Some(Bytes::from("aaaaaaaaaa"))
+    );
+    assert!(client.get(Bytes::from("ttl")).await.unwrap().is_some());
+    assert_eq!(client.get(Bytes::from("expired")).await.unwrap(), None);
+    assert_eq!(client.get(Bytes::from("hash")).await.unwrap(), None);
+    assert_eq!(
+        client.lrange(Bytes::from("lst"), 0, -1).await.unwrap(),
+        vec![
+            Data::Bytes(Bytes::from("a")),
+            Data::Integer(5),
+            Data::Bytes(Bytes::from("plain"))
+        ]
+    );
+
+    // The imported dataset is saved, a restart without the dump keeps it.
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get(Bytes::from("str")).await.unwrap(),
+        Some(Bytes::from("hello"))
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Value of `field` in the reply of `INFO`.
+fn info_field(info: &[u8], field: &str) -> Option<String> {
+    String::from_utf8_lossy(info)
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .map(str::to_string)
+}
+
+#[tokio::test]
+async fn bgsave_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    for i in 0..1000 {
+        client
+            .set(Bytes::from(format!("key:{i}")), random_bytes(16), None)
+            .await
+            .unwrap();
+    }
+
+    let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+    assert!(info.starts_with(b"# Persistence\r\n"));
+    assert_eq!(
+        info_field(&info, "rdb_changes_since_last_save").as_deref(),
+        Some("1000")
+    );
+
+    client.bgsave().await.unwrap();
+
+    // Commands are served while saving.
+    client
+        .set(Bytes::from("during"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let info = loop {
+        let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+        if info_field(&info, "rdb_bgsave_in_progress").as_deref() == Some("0") {
+            break info;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    assert_eq!(
+        info_field(&info, "rdb_last_bgsave_status").as_deref(),
+        Some("ok")
+    );
+    assert_eq!(
+        info_field(&info, "rdb_saving_keys_saved"),
+        info_field(&info, "rdb_saving_keys_total")
+    );
+    assert!(
+        info_field(&info, "rdb_changes_since_last_save")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+            <= 1
+    );
+
+    // The snapshot is loaded by a new server.
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert!(client.get(Bytes::from("key:999")).await.unwrap().is_some());
+
+    // Only the requested sections are returned.
+    let info = client.info(vec![Bytes::from("clients")]).await.unwrap();
+    assert!(info.starts_with(b"# Clients\r\n"));
+    assert!(info_field(&info, "walrus_version").is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn info_keyspace_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Empty databases are left out.
+    let info = client.info(vec![Bytes::from("keyspace")]).await.unwrap();
+    assert!(info.is_empty());
+
+    client
+        .set(Bytes::from("string"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("ttl"),
+            Bytes::from("value"),
+            Some(Duration::from_secs(60)),
+        )
+        .await
+        .unwrap();
+    client
+        .rpush(Bytes::from("list"), random_data_array(2))
+        .await
+        .unwrap();
+    // Replacing a key moves it to the count of its new type.
+    client
+        .rpush(Bytes::from("other"), random_data_array(1))
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("other"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let info = client.info(vec![Bytes::from("keyspace")]).await.unwrap();
+    assert_eq!(
+        info_field(&info, "db0").as_deref(),
+        Some("keys=4,expires=1,avg_ttl=0,strings=3,lists=1")
+    );
+
+    // Lists emptied by popping are removed.
+    client.blpop(vec![Bytes::from("list")], 0.1).await.unwrap();
+    client.blpop(vec![Bytes::from("list")], 0.1).await.unwrap();
+    let info = client.info(vec![Bytes::from("keyspace")]).await.unwrap();
+    assert_eq!(
+        info_field(&info, "db0").as_deref(),
+        Some("keys=3,expires=1,avg_ttl=0,strings=3,lists=0")
+    );
+}
+
+#[tokio::test]
+async fn info_memory_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    async fn used_memory(client: &mut Client) -> usize {
+        let info = client.info(vec![Bytes::from("memory")]).await.unwrap();
+        info_field(&info, "used_memory_dataset")
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    assert_eq!(used_memory(&mut client).await, 0);
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let string = used_memory(&mut client).await;
+    assert!(string > 0);
+
+    // Replacing the value accounts for its new size, the short value was stored inline.
+    client
+        .set(Bytes::from("key"), Bytes::from(vec![b'x'; 1000]), None)
+        .await
+        .unwrap();
+    assert_eq!(used_memory(&mut client).await, string + 1000);
+
+    client
+        .rpush(Bytes::from("list"), random_data_array(4))
+        .await
+        .unwrap();
+    let list = used_memory(&mut client).await;
+    assert!(list > string + 1000);
+
+    client.lpop(Bytes::from("list"), Some(2)).await.unwrap();
+    assert!(used_memory(&mut client).await < list);
+
+    // The memory of the list is released once it is emptied.
+    client.lpop(Bytes::from("list"), Some(1)).await.unwrap();
+    client.blpop(vec![Bytes::from("list")], 0.1).await.unwrap();
+    assert_eq!(used_memory(&mut client).await, string + 1000);
+}
+
+#[tokio::test]
+async fn info_stats_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("expiring"),
+            Bytes::from("value"),
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+    client.get(Bytes::from("key")).await.unwrap();
+    client.get(Bytes::from("missing")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client.get(Bytes::from("expiring")).await.unwrap();
+    client.llen(Bytes::from("missing")).await.unwrap();
+    assert!(client.llen(Bytes::from("key")).await.is_err());
+
+    let info = client
+        .info(vec![Bytes::from("stats"), Bytes::from("commandstats")])
+        .await
+        .unwrap();
+    // The failed `LLEN` read an existing key.
+    assert_eq!(info_field(&info, "keyspace_hits").as_deref(), Some("2"));
+    assert_eq!(info_field(&info, "keyspace_misses").as_deref(), Some("3"));
+    assert_eq!(info_field(&info, "expired_keys").as_deref(), Some("1"));
+    assert_eq!(info_field(&info, "evicted_keys").as_deref(), Some("0"));
+    assert_eq!(cmdstat(&info, "set", "calls"), Some(2));
+    assert_eq!(cmdstat(&info, "get", "calls"), Some(3));
+    assert_eq!(cmdstat(&info, "get", "failed_calls"), Some(0));
+    assert_eq!(cmdstat(&info, "llen", "calls"), Some(2));
+    assert_eq!(cmdstat(&info, "llen", "failed_calls"), Some(1));
+    assert!(cmdstat(&info, "llen", "max_usec") <= cmdstat(&info, "llen", "usec"));
+    assert_eq!(info_field(&info, "cmdstat_lpop"), None);
+
+    // Commands are counted once executed.
+    let total: u64 = info_field(&info, "total_commands_processed")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(total >= 7);
+
+    client.config_resetstat().await.unwrap();
+    let info = client
+        .info(vec![Bytes::from("stats"), Bytes::from("commandstats")])
+        .await
+        .unwrap();
+    assert_eq!(info_field(&info, "keyspace_misses").as_deref(), Some("0"));
+    assert_eq!(info_field(&info, "expired_keys").as_deref(), Some("0"));
+    assert_eq!(cmdstat(&info, "set", "calls"), None);
+    assert_eq!(cmdstat(&info, "config", "calls"), Some(1));
+}
+
+/// Value of `stat` in the `cmdstat_<name>` field of `info`, such as the `calls` of
+/// `calls=2,usec=10,usec_per_call=5.00,max_usec=6,failed_calls=0`.
+fn cmdstat(info: &[u8], name: &str, stat: &str) -> Option<u64> {
+    info_field(info, &format!("cmdstat_{name}"))?
+        .split(',')
+        .find_map(|pair| pair.strip_prefix(stat)?.strip_prefix('=')?.parse().ok())
+}
+
+#[tokio::test]
+async fn maxmemory_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    async fn info(client: &mut Client, field: &str) -> usize {
+        let info = client.info(Vec::new()).await.unwrap();
+        info_field(&info, field).unwrap().parse().unwrap()
+    }
+
+    let value = Bytes::from(vec![b'x'; 1000]);
+    for i in 0..5 {
+        client
+            .set(
+                Bytes::from(format!("volatile:{i}")),
+                value.clone(),
+                Some(Duration::from_secs(60)),
+            )
+            .await
+            .unwrap();
+    }
+    for i in 0..20 {
+        client
+            .set(Bytes::from(format!("key:{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+
+    let maxmemory = info(&mut client, "used_memory_dataset").await;
+    client
+        .config_set(Bytes::from("maxmemory"), Bytes::from(maxmemory.to_string()))
+        .await
+        .unwrap();
+
+    // Nothing is evicted by default, writes are refused once over the limit.
+    client
+        .set(Bytes::from("extra:0"), value.clone(), None)
+        .await
+        .unwrap();
+    let err = client
+        .set(Bytes::from("extra:1"), value.clone(), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("OOM "));
+    assert!(client.get(Bytes::from("key:0")).await.unwrap().is_some());
+    assert_eq!(info(&mut client, "evicted_keys").await, 0);
+
+    // Only keys with an expiration are evicted, until there are none left.
+    client
+        .config_set(Bytes::from("maxmemory-policy"), Bytes::from("volatile-lru"))
+        .await
+        .unwrap();
+    let mut refused = false;
+    for i in 1..10 {
+        let key = Bytes::from(format!("extra:{i}"));
+        if client.set(key, value.clone(), None).await.is_err() {
+            refused = true;
+            break;
+        }
+    }
+    assert!(refused);
+    assert_eq!(info(&mut client, "evicted_keys").await, 5);
+    for i in 0..5 {
+        let key = Bytes::from(format!("volatile:{i}"));
+        assert!(client.get(key).await.unwrap().is_none());
+    }
+    for i in 0..20 {
+        let key = Bytes::from(format!("key:{i}"));
+        assert!(client.get(key).await.unwrap().is_some());
+    }
+
+    // Any key is evicted, the dataset stays around the limit.
+    client
+        .config_set(Bytes::from("maxmemory-policy"), Bytes::from("allkeys-lru"))
+        .await
+        .unwrap();
+    for i in 0..10 {
+        client
+            .set(Bytes::from(format!("more:{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+    assert!(info(&mut client, "evicted_keys").await > 5);
+    assert!(info(&mut client, "used_memory_dataset").await < maxmemory + 2 * value.len());
+    assert_eq!(
+        client
+            .config_get(Bytes::from("maxmemory-policy"))
+            .await
+            .unwrap(),
+        vec![(Bytes::from("maxmemory-policy"), Bytes::from("allkeys-lru"))]
+    );
+}
+
+#[tokio::test]
+async fn lfu_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let value = Bytes::from(vec![b'x'; 1000]);
+    client
+        .set(Bytes::from("hot"), value.clone(), None)
+        .await
+        .unwrap();
+
+    // Frequencies are only reported with an LFU policy.
+    assert!(client.object_freq(Bytes::from("hot")).await.is_err());
+    client
+        .config_set(Bytes::from("maxmemory-policy"), Bytes::from("allkeys-lfu"))
+        .await
+        .unwrap();
+
+    // New keys start with a small frequency, so they aren't evicted right away.
+    assert_eq!(
+        client.object_freq(Bytes::from("hot")).await.unwrap(),
+        Some(5)
+    );
+    for _ in 0..100 {
+        client.get(Bytes::from("hot")).await.unwrap();
+    }
+    let frequency = client
+        .object_freq(Bytes::from("hot"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!((6..100).contains(&frequency));
+    assert_eq!(
+        client.object_freq(Bytes::from("missing")).await.unwrap(),
+        None
+    );
+
+    let info = client.info(vec![Bytes::from("memory")]).await.unwrap();
+    let maxmemory: usize = info_field(&info, "used_memory_dataset")
+        .unwrap()
+        .parse()
+        .unwrap();
+    client
+        .config_set(
+            Bytes::from("maxmemory"),
+            Bytes::from((maxmemory * 4).to_string()),
+        )
+        .await
+        .unwrap();
+    for i in 0..10 {
+        client
+            .set(Bytes::from(format!("cold:{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+
+    // Keys are evicted to keep about four of them.
+    let info = client.info(Vec::new()).await.unwrap();
+    let evicted: usize = info_field(&info, "evicted_keys").unwrap().parse().unwrap();
+    let keyspace = info_field(&info, "db0").unwrap();
+    assert!((6..=8).contains(&evicted));
+    assert!(keyspace.starts_with(&format!("keys={},", 11 - evicted)));
+}
+
+#[tokio::test]
+async fn aof_replay_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .set(Bytes::from("string"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("ttl"),
+            Bytes::from("value"),
+            Some(Duration::from_secs(60)),
+        )
+        .await
+        .unwrap();
+    client
+        .set(
+            Bytes::from("expired"),
+            Bytes::from("value"),
+            Some(Duration::from_millis(1)),
+        )
+        .await
+        .unwrap();
+
+    let list = random_data_array(5);
+    client
+        .rpush(Bytes::from("list"), list.clone())
+        .await
+        .unwrap();
+    client.lpop(Bytes::from("list"), None).await.unwrap();
+    client
+        .blpop(vec![Bytes::from("empty"), Bytes::from("list")], 0.1)
+        .await
+        .unwrap();
+    // Timed out, nothing to replay.
+    client
+        .blpop(vec![Bytes::from("empty")], 0.01)
+        .await
+        .unwrap();
+    // Failed commands are not logged.
+    assert!(
+        client
+            .lpush(Bytes::from("string"), random_data_array(1))
+            .await
+            .is_err()
+    );
+
+    assert!(dir.join("appendonly.aof").exists());
+
+    // A new server replays the file.
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.get(Bytes::from("string")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    assert!(client.get(Bytes::from("ttl")).await.unwrap().is_some());
+    assert_eq!(client.get(Bytes::from("expired")).await.unwrap(), None);
+    assert_eq!(
+        VecDeque::from(client.lrange(Bytes::from("list"), 0, -1).await.unwrap()),
+        list.into_iter().skip(2).collect::<VecDeque<_>>()
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn aof_truncated_tail_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // Simulate a crash in the middle of an append.
+    let path = dir.join("appendonly.aof");
+    let len = std::fs::metadata(&path).unwrap().len();
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.extend_from_slice(b"*3\r\n$3\r\nset\r\n$3\r\nke");
+    std::fs::write(&path, bytes).unwrap();
+
+    // The incomplete command is dropped, the rest of the file is loaded.
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.get(Bytes::from("key")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+    let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+    assert_eq!(info_field(&info, "loading").as_deref(), Some("0"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn aof_starts_from_snapshot_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .set(Bytes::from("saved"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client.save().await.unwrap();
+
+    // Enabling the append only file starts it with the dataset of the snapshot.
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("logged"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // Only the append only file is loaded from now on.
+    std::fs::remove_file(dir.join("dump.wdb")).unwrap();
+
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert!(client.get(Bytes::from("saved")).await.unwrap().is_some());
+    assert!(client.get(Bytes::from("logged")).await.unwrap().is_some());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn databases_persistence_test() {
+    for appendonly in [false, true] {
+        let dir = temp_dir();
+        let addr = start_server_in(Some(dir.clone()), appendonly).await;
+        let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .unwrap();
+
+        client
+            .set(Bytes::from("key"), Bytes::from("zero"), None)
+            .await
+            .unwrap();
+        client.select(5).await.unwrap();
+        client
+            .set(Bytes::from("key"), Bytes::from("five"), None)
+            .await
+            .unwrap();
+        client
+            .set(
+                Bytes::from("ttl"),
+                Bytes::from("value"),
+                Some(Duration::from_secs(60)),
+            )
+            .await
+            .unwrap();
+        assert!(client.wmove(Bytes::from("ttl"), 7).await.unwrap());
+        client.select(0).await.unwrap();
+        client
+            .set(Bytes::from("last"), Bytes::from("zero"), None)
+            .await
+            .unwrap();
+        if !appendonly {
+            client.save().await.unwrap();
+        }
+
+        // Keys are restored in their own database.
+        let addr = start_server_in(Some(dir.clone()), appendonly).await;
+        let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get(Bytes::from("key")).await.unwrap(),
+            Some(Bytes::from("zero"))
+        );
+        assert_eq!(
+            client.get(Bytes::from("last")).await.unwrap(),
+            Some(Bytes::from("zero"))
+        );
+        client.select(5).await.unwrap();
+        assert_eq!(
+            client.get(Bytes::from("key")).await.unwrap(),
+            Some(Bytes::from("five"))
+        );
+        assert_eq!(client.get(Bytes::from("ttl")).await.unwrap(), None);
+        assert_eq!(client.get(Bytes::from("last")).await.unwrap(), None);
+        client.select(7).await.unwrap();
+        let ttl = client.pttl(Bytes::from("ttl")).await.unwrap();
+        assert!(ttl > 0 && ttl <= 60_000);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn aof_fsync_policy_test() {
+    let dir = temp_dir();
+    let addr = start_server_in(Some(dir.clone()), true).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let config = client.config_get(Bytes::from("appendfsync")).await.unwrap();
+    assert_eq!(config[0].1, Bytes::from("everysec"));
+    assert!(
+        client
+            .config_set(Bytes::from("appendfsync"), Bytes::from("sometimes"))
+            .await
+            .is_err()
+    );
+
+    // Synced before replying, nothing is left pending.
+    client
+        .config_set(Bytes::from("appendfsync"), Bytes::from("always"))
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+    assert_eq!(info_field(&info, "aof_enabled").as_deref(), Some("1"));
+    assert_eq!(
+        info_field(&info, "aof_pending_fsync_bytes").as_deref(),
+        Some("0")
+    );
+
+    // Synced by the background task within a second.
+    client
+        .config_set(Bytes::from("appendfsync"), Bytes::from("everysec"))
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+    assert_ne!(
+        info_field(&info, "aof_pending_fsync_bytes").as_deref(),
+        Some("0")
+    );
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let info = client.info(vec![Bytes::from("persistence")]).await.unwrap();
+    assert_eq!(
+        info_field(&info, "aof_pending_fsync_bytes").as_deref(),
+        Some("0")
+    );
+    assert_eq!(info_field(&info, "aof_fsync_lag_ms").as_deref(), Some("0"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn replication_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    master
+        .set(Bytes::from("before"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let list = random_data_array(5);
+    master
+        .rpush(Bytes::from("list"), list.clone())
+        .await
+        .unwrap();
+
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // The dataset of the replica is replaced by the one of the master.
+    replica
+        .set(Bytes::from("replica-only"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let info = replica
+            .info(vec![Bytes::from("replication")])
+            .await
+            .unwrap();
+        if info_field(&info, "master_link_status").as_deref() == Some("up") {
+            assert_eq!(info_field(&info, "role").as_deref(), Some("slave"));
+            break;
+        }
+        assert!(Instant::now() < deadline, "replica never synchronized");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(
+        replica.get(Bytes::from("before")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    assert_eq!(
+        replica.get(Bytes::from("replica-only")).await.unwrap(),
+        None
+    );
+    assert_eq!(
+        VecDeque::from(replica.lrange(Bytes::from("list"), 0, -1).await.unwrap()),
+        list
+    );
+
+    let info = master.info(vec![Bytes::from("replication")]).await.unwrap();
+    assert_eq!(info_field(&info, "connected_slaves").as_deref(), Some("1"));
+
+    // Writes are streamed once synchronized.
+    master
+        .set(Bytes::from("after"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    master.lpop(Bytes::from("list"), None).await.unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get(Bytes::from("after")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "write never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        VecDeque::from(replica.lrange(Bytes::from("list"), 0, -1).await.unwrap()),
+        list.into_iter().skip(1).collect::<VecDeque<_>>()
+    );
+
+    // Promoted back to a master, the dataset is kept.
+    replica.replicaof_no_one().await.unwrap();
+    let info = replica
+        .info(vec![Bytes::from("replication")])
+        .await
+        .unwrap();
+    assert_eq!(info_field(&info, "role").as_deref(), Some("master"));
+    assert_eq!(
+        replica.get(Bytes::from("after")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+}
+
+#[tokio::test]
+async fn partial_resync_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    master
+        .set(Bytes::from("before"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get(Bytes::from("before")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "replica never synchronized");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Drop the link of the replica, writes meanwhile are kept in the backlog.
+    let list = master.client_list().await.unwrap();
+    let link = String::from_utf8_lossy(&list)
+        .lines()
+        .find(|line| line.contains("cmd=psync") || line.contains("cmd=replconf"))
+        .and_then(|line| line.strip_prefix("id="))
+        .and_then(|line| line.split(' ').next())
+        .map(|id| id.parse::<u64>().unwrap())
+        .unwrap();
+    assert_eq!(master.client_kill(link).await.unwrap(), 1);
+
+    master
+        .set(Bytes::from("missed"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get(Bytes::from("missed")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "missed write never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The replica resumed without loading the dataset again.
+    let info = master.info(vec![Bytes::from("replication")]).await.unwrap();
+    assert_eq!(info_field(&info, "sync_full").as_deref(), Some("1"));
+    assert_eq!(info_field(&info, "sync_partial_ok").as_deref(), Some("1"));
+    assert_eq!(
+        info_field(&info, "repl_backlog_active").as_deref(),
+        Some("1")
+    );
+
+    // The replica records the command in its own backlog after applying it.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let replica_info = replica
+            .info(vec![Bytes::from("replication")])
+            .await
+            .unwrap();
+        if info_field(&replica_info, "master_repl_offset")
+            == info_field(&info, "master_repl_offset")
+        {
+            assert_eq!(
+                info_field(&replica_info, "master_replid"),
+                info_field(&info, "master_replid")
+            );
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "replication offsets never matched"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Promoted to a master, the previous stream stays resumable.
+    replica.replicaof_no_one().await.unwrap();
+    let promoted = replica
+        .info(vec![Bytes::from("replication")])
+        .await
+        .unwrap();
+    assert_eq!(
+        info_field(&promoted, "master_replid2"),
+        info_field(&info, "master_replid")
+    );
+    assert_ne!(
+        info_field(&promoted, "master_replid"),
+        info_field(&info, "master_replid")
+    );
+}
+
+#[tokio::test]
+async fn replica_read_only_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    // Writes are refused, reads are served.
+    let err = replica
+        .set(Bytes::from("key"), Bytes::from("replica"), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("READONLY"));
+    assert_eq!(replica.get(Bytes::from("key")).await.unwrap(), None);
+
+    // Writes of the master are still applied.
+    master
+        .set(Bytes::from("key"), Bytes::from("master"), None)
+        .await
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get(Bytes::from("key")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "write never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Writable replicas accept writes from their clients.
+    replica
+        .config_set(Bytes::from("replica-read-only"), Bytes::from("no"))
+        .await
+        .unwrap();
+    replica
+        .set(Bytes::from("local"), Bytes::from("replica"), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        replica.get(Bytes::from("local")).await.unwrap(),
+        Some(Bytes::from("replica"))
+    );
+}
+
+#[tokio::test]
+async fn failover_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Nothing to fail over to yet.
+    assert!(master.failover(None, false, None).await.is_err());
+    assert!(master.failover_abort().await.is_err());
+
+    let master_port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), master_port)
+        .await
+        .unwrap();
+    assert!(replica.failover(None, false, None).await.is_err());
+
+    master
+        .set(Bytes::from("before"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let replica_port = replica_addr.rsplit(':').next().unwrap().parse().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while let Err(err) = master
+        .failover(
+            Some((Bytes::from("127.0.0.1"), replica_port)),
+            false,
+            Some(Duration::from_secs(5)),
+        )
+        .await
+    {
+        // The replica may not be connected yet.
+        assert!(Instant::now() < deadline, "failover never started, {err}");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // The replica is promoted and the master follows it.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let promoted = replica
+            .info(vec![Bytes::from("replication")])
+            .await
+            .unwrap();
+        let demoted = master.info(vec![Bytes::from("replication")]).await.unwrap();
+        if info_field(&promoted, "role").as_deref() == Some("master")
+            && info_field(&demoted, "master_link_status").as_deref() == Some("up")
+        {
+            assert_eq!(
+                info_field(&demoted, "master_failover_state").as_deref(),
+                Some("no-failover")
+            );
+            assert_eq!(
+                info_field(&demoted, "master_port"),
+                Some(replica_port.to_string())
+            );
+            break;
+        }
+        assert!(Instant::now() < deadline, "failover never completed");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(
+        replica.get(Bytes::from("before")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+
+    // Writes go to the new master and are replicated to the old one.
+    let err = master
+        .set(Bytes::from("after"), Bytes::from("value"), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("READONLY"));
+
+    replica
+        .set(Bytes::from("after"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while master.get(Bytes::from("after")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "write never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn cluster_test() {
+    // Slot of "bar" is 5061, slot of "foo" is 12182.
+    let (addr_a, bus_a) = start_cluster_node().await;
+    let (addr_b, _) = start_cluster_node().await;
+    let mut node_a = Client::connect(addr_a.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut node_b = Client::connect(addr_b.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let err = node_a.get(Bytes::from("bar")).await.unwrap_err();
+    assert!(err.to_string().starts_with("CLUSTERDOWN"));
+
+    node_a.cluster_addslots((0..8192).collect()).await.unwrap();
+    node_b
+        .cluster_addslots((8192..16384).collect())
+        .await
+        .unwrap();
+    assert!(node_b.cluster_addslots(vec![8192]).await.is_err());
+
+    let port_a: u16 = addr_a.rsplit(':').next().unwrap().parse().unwrap();
+    node_b
+        .cluster_meet(Bytes::from("127.0.0.1"), port_a, Some(bus_a))
+        .await
+        .unwrap();
+
+    // Both nodes learn the slots of the other over the bus.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let moved_a = node_a.get(Bytes::from("foo")).await.unwrap_err();
+        let moved_b = node_b.get(Bytes::from("bar")).await.unwrap_err();
+        if moved_a.to_string() == format!("MOVED 12182 {addr_b}")
+            && moved_b.to_string() == format!("MOVED 5061 {addr_a}")
+        {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "nodes never learned the topology"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Keys are served by the node owning their slot, hash tags select the slot.
+    node_a
+        .set(Bytes::from("bar"), Bytes::from("a"), None)
+        .await
+        .unwrap();
+    node_a
+        .set(Bytes::from("{bar}.other"), Bytes::from("a"), None)
+        .await
+        .unwrap();
+    node_b
+        .set(Bytes::from("foo"), Bytes::from("b"), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        node_b.get(Bytes::from("foo")).await.unwrap(),
+        Some(Bytes::from("b"))
+    );
+
+    // Keys of a command must share a slot.
+    let err = node_a
+        .blpop(vec![Bytes::from("bar"), Bytes::from("foo")], 0.1)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("CROSSSLOT"));
+
+    // Commands without keys are served by every node.
+    assert_eq!(node_a.ping(None).await.unwrap(), Bytes::from("PONG"));
+
+    // Slots are computed the same by servers and clients.
+    for key in [
+        "bar",
+        "foo",
+        "{bar}.other",
+        "foo{}{bar}",
+        "{user1000}.following",
+    ] {
+        assert_eq!(
+            node_a.cluster_keyslot(Bytes::from(key)).await.unwrap(),
+            walrus::slot::key_slot(key.as_bytes()) as i64
+        );
+    }
+    assert_eq!(
+        node_a
+            .cluster_keyslot(Bytes::from("{user1000}.followers"))
+            .await
+            .unwrap(),
+        node_a
+            .cluster_keyslot(Bytes::from("user1000"))
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        node_a.cluster_keyslot(Bytes::from("foo")).await.unwrap(),
+        12182
+    );
+    assert_eq!(walrus::slot::hash_tag(b"foo{}{bar}"), b"foo{}{bar}");
+    assert_eq!(walrus::slot::hash_tag(b"foo{{bar}}zap"), b"{bar");
+
+    // Released slots are not served until claimed again.
+    node_a.cluster_delslots(vec![5061]).await.unwrap();
+    let err = node_a.get(Bytes::from("bar")).await.unwrap_err();
+    assert!(err.to_string().starts_with("CLUSTERDOWN"));
+
+    // Standalone servers refuse cluster commands.
+    let mut standalone = Client::connect(
+        start_dedicated_server().await,
+        READ_BUFFER_SIZE,
+        WRITE_BUFFER_SIZE,
+    )
+    .await
+    .unwrap();
+    assert!(standalone.cluster_addslots(vec![0]).await.is_err());
+}
+
+#[tokio::test]
+async fn cluster_redirection_test() {
+    // Slot of "bar" is 5061, slot of "foo" is 12182.
+    let (addr_a, bus_a) = start_cluster_node().await;
+    let (addr_b, _) = start_cluster_node().await;
+    let mut node_a = Client::connect(addr_a.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut node_b = Client::connect(addr_b.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    node_a.cluster_addslots((0..8192).collect()).await.unwrap();
+    node_b
+        .cluster_addslots((8192..16384).collect())
+        .await
+        .unwrap();
+    let port_a: u16 = addr_a.rsplit(':').next().unwrap().parse().unwrap();
+    node_b
+        .cluster_meet(Bytes::from("127.0.0.1"), port_a, Some(bus_a))
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let info_a = String::from_utf8(node_a.cluster_info().await.unwrap().to_vec()).unwrap();
+        let info_b = String::from_utf8(node_b.cluster_info().await.unwrap().to_vec()).unwrap();
+        if info_a.contains("cluster_state:ok\r\n")
+            && info_b.contains("cluster_state:ok\r\n")
+            && info_a.contains("cluster_known_nodes:2\r\n")
+        {
+            assert!(info_a.contains("cluster_size:2\r\n"));
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "nodes never learned the topology"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let id_a = node_a.cluster_myid().await.unwrap();
+    let id_b = node_b.cluster_myid().await.unwrap();
+    assert_eq!(id_a.len(), 40);
+    assert_ne!(id_a, id_b);
+    let id_a = String::from_utf8(id_a.to_vec()).unwrap();
+    let id_b = String::from_utf8(id_b.to_vec()).unwrap();
+
+    let nodes = String::from_utf8(node_a.cluster_nodes().await.unwrap().to_vec()).unwrap();
+    assert_eq!(nodes.lines().count(), 2);
+    let myself = nodes.lines().find(|line| line.starts_with(&id_a)).unwrap();
+    assert!(myself.contains(" myself,master "));
+    assert!(myself.ends_with(" connected 0-8191"));
+    let other = nodes.lines().find(|line| line.starts_with(&id_b)).unwrap();
+    assert!(other.contains(&format!(" {addr_b}@")));
+    assert!(other.ends_with(" 8192-16383"));
+
+    let slots = node_a.cluster_slots().await.unwrap();
+    assert_eq!(slots.len(), 2);
+    let Frame::Array(range) = &slots[1] else {
+        panic!("invalid slot range {:?}", slots[1]);
+    };
+    assert_eq!(range[0], Frame::Integer(8192));
+    assert_eq!(range[1], Frame::Integer(16383));
+    let Frame::Array(node) = &range[2] else {
+        panic!("invalid slot range {range:?}");
+    };
+    assert_eq!(node[2], Frame::Bulk(Bytes::from(id_b.clone())));
+    assert_eq!(node_b.cluster_shards().await.unwrap().len(), 2);
+
+    // Slot 5061 moves from node A to node B.
+    node_b
+        .cluster_setslot_importing(5061, Bytes::from(id_a.clone()))
+        .await
+        .unwrap();
+    assert!(
+        node_a
+            .cluster_setslot_importing(5061, Bytes::from(id_b.clone()))
+            .await
+            .is_err()
+    );
+    node_a
+        .cluster_setslot_migrating(5061, Bytes::from(id_b.clone()))
+        .await
+        .unwrap();
+    let nodes = String::from_utf8(node_a.cluster_nodes().await.unwrap().to_vec()).unwrap();
+    assert!(nodes.contains(&format!(" [5061->-{id_b}]")));
+
+    // Keys missing on the owner are asked to the importing node, which only serves them
+    // right after `ASKING`.
+    let err = node_a.get(Bytes::from("bar")).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("ASK 5061 {addr_b}"));
+    let err = node_b.get(Bytes::from("bar")).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("MOVED 5061 {addr_a}"));
+    node_b.asking().await.unwrap();
+    node_b
+        .set(Bytes::from("bar"), Bytes::from("b"), None)
+        .await
+        .unwrap();
+    let err = node_b.get(Bytes::from("bar")).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("MOVED 5061 {addr_a}"));
+    assert_eq!(node_b.cluster_countkeysinslot(5061).await.unwrap(), 1);
+    assert_eq!(
+        node_b.cluster_getkeysinslot(5061, 10).await.unwrap(),
+        vec![Bytes::from("bar")]
+    );
+
+    // Assigning the slot ends the move.
+    node_a
+        .cluster_setslot_node(5061, Bytes::from(id_b.clone()))
+        .await
+        .unwrap();
+    node_b
+        .cluster_setslot_node(5061, Bytes::from(id_b.clone()))
+        .await
+        .unwrap();
+    let err = node_a.get(Bytes::from("bar")).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("MOVED 5061 {addr_b}"));
+    assert_eq!(
+        node_b.get(Bytes::from("bar")).await.unwrap(),
+        Some(Bytes::from("b"))
+    );
+
+    // A slot can't be handed over while its keys are still here.
+    node_b
+        .set(Bytes::from("foo"), Bytes::from("b"), None)
+        .await
+        .unwrap();
+    assert!(
+        node_b
+            .cluster_setslot_node(12182, Bytes::from(id_a.clone()))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn sentinel_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let master_port: u16 = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    let replica_port: u16 = replica_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), master_port)
+        .await
+        .unwrap();
+
+    // Servers not in sentinel mode refuse sentinel commands.
+    assert!(master.sentinel_myid().await.is_err());
+
+    let mut sentinels = Vec::new();
+    for _ in 0..2 {
+        let mut sentinel =
+            Client::connect(start_sentinel().await, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+                .await
+                .unwrap();
+        sentinel
+            .sentinel_monitor(
+                Bytes::from("mymaster"),
+                Bytes::from("127.0.0.1"),
+                master_port,
+                2,
+            )
+            .await
+            .unwrap();
+        sentinel
+            .sentinel_set(
+                Bytes::from("mymaster"),
+                Bytes::from("down-after-milliseconds"),
+                Bytes::from("200"),
+            )
+            .await
+            .unwrap();
+        sentinel
+            .sentinel_set(
+                Bytes::from("mymaster"),
+                Bytes::from("failover-timeout"),
+                Bytes::from("500"),
+            )
+            .await
+            .unwrap();
+        sentinels.push(sentinel);
+    }
+
+    let sentinel = &mut sentinels[0];
+    assert_eq!(sentinel.sentinel_myid().await.unwrap().len(), 40);
+    assert!(
+        sentinel
+            .sentinel_monitor(
+                Bytes::from("mymaster"),
+                Bytes::from("127.0.0.1"),
+                master_port,
+                2
+            )
+            .await
+            .is_err()
+    );
+    assert!(
+        sentinel
+            .sentinel_master(Bytes::from("unknown"))
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        sentinel
+            .sentinel_get_master_addr_by_name(Bytes::from("mymaster"))
+            .await
+            .unwrap(),
+        Some((Bytes::from("127.0.0.1"), master_port))
+    );
+    assert_eq!(
+        sentinel
+            .sentinel_get_master_addr_by_name(Bytes::from("unknown"))
+            .await
+            .unwrap(),
+        None
+    );
+    let err = sentinel.get(Bytes::from("foo")).await.unwrap_err();
+    assert!(err.to_string().contains("sentinel mode"));
+
+    // Sentinels learn the replica from the master, and each other from their connections to
+    // it.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    for sentinel in &mut sentinels {
+        loop {
+            let replicas = sentinel
+                .sentinel_replicas(Bytes::from("mymaster"))
+                .await
+                .unwrap();
+            let others = sentinel
+                .sentinel_sentinels(Bytes::from("mymaster"))
+                .await
+                .unwrap();
+            if replicas.len() == 1 && others.len() == 1 {
+                break;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "sentinels never learned the replica and each other"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let master_info = sentinels[0]
+        .sentinel_master(Bytes::from("mymaster"))
+        .await
+        .unwrap();
+    let flags = master_info
+        .chunks(2)
+        .find(|pair| pair[0] == Frame::Bulk(Bytes::from("flags")))
+        .map(|pair| pair[1].clone());
+    assert_eq!(flags, Some(Frame::Bulk(Bytes::from("master"))));
+    let info = sentinels[0]
+        .info(vec![Bytes::from("sentinel")])
+        .await
+        .unwrap();
+    assert_eq!(info_field(&info, "sentinel_masters").as_deref(), Some("1"));
+
+    // The master stops replying, the sentinels agree it's down and promote the replica.
+    master
+        .client_pause(Duration::from_secs(3), false)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    for sentinel in &mut sentinels {
+        while sentinel
+            .sentinel_get_master_addr_by_name(Bytes::from("mymaster"))
+            .await
+            .unwrap()
+            != Some((Bytes::from("127.0.0.1"), replica_port))
+        {
+            assert!(
+                Instant::now() < deadline,
+                "the master was never failed over"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let info = replica
+        .info(vec![Bytes::from("replication")])
+        .await
+        .unwrap();
+    assert_eq!(info_field(&info, "role").as_deref(), Some("master"));
+
+    // The previous master is made a replica of the promoted one once back.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let info = master.info(vec![Bytes::from("replication")]).await.unwrap();
+        if info_field(&info, "role").as_deref() == Some("slave")
+            && info_field(&info, "master_port") == Some(replica_port.to_string())
+        {
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "the previous master never followed the promoted replica"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let info = replica
+        .info(vec![Bytes::from("replication")])
+        .await
+        .unwrap();
+    assert_eq!(info_field(&info, "role").as_deref(), Some("master"));
+}
+
+#[tokio::test]
+async fn list_encoding_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("list-max-listpack-entries"), Bytes::from("4"))
+        .await
+        .unwrap();
+
+    // Small lists are listpacks, every kind of element is kept as pushed.
+    let key = Bytes::from("small");
+    let elements = VecDeque::from([
+        Data::Integer(-300),
+        Data::Bytes(Bytes::from("bulk")),
+        Data::Double(1.5),
+    ]);
+    client.rpush(key.clone(), elements.clone()).await.unwrap();
+    assert_eq!(
+        client.object_encoding(key.clone()).await.unwrap(),
+        Some(Bytes::from("listpack"))
+    );
+    client
+        .lpush(key.clone(), VecDeque::from([Data::Integer(-1 << 40)]))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.lpop(key.clone(), Some(2)).await.unwrap().unwrap(),
+        vec![Data::Integer(-1 << 40), Data::Integer(-300)]
+    );
+    assert_eq!(
+        client.lrange(key.clone(), 0, -1).await.unwrap(),
+        vec![Data::Bytes(Bytes::from("bulk")), Data::Double(1.5)]
+    );
+
+    // Growing beyond the thresholds upgrades the list, its elements are kept.
+    let key = Bytes::from("grown");
+    client
+        .rpush(key.clone(), random_data_array(4))
+        .await
+        .unwrap();
+    let before = client.lrange(key.clone(), 0, -1).await.unwrap();
+    client
+        .rpush(key.clone(), VecDeque::from([Data::Integer(5)]))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.object_encoding(key.clone()).await.unwrap(),
+        Some(Bytes::from("vecdeque"))
+    );
+    let after = client.lrange(key.clone(), 0, -1).await.unwrap();
+    assert_eq!(after[..4], before[..]);
+    assert_eq!(after[4], Data::Integer(5));
+
+    let key = Bytes::from("long");
+    client
+        .rpush(
+            key.clone(),
+            VecDeque::from([Data::Bytes(Bytes::from(vec![b'x'; 65]))]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        client.object_encoding(key).await.unwrap(),
+        Some(Bytes::from("vecdeque"))
+    );
+    assert_eq!(
+        client
+            .object_encoding(Bytes::from("missing"))
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn string_encoding_test() {
+    let mut client = connect_client().await;
+
+    // Short strings are stored inline, longer ones in a buffer of their own.
+    for (key, value, encoding) in [
+        ("encoding:short", Bytes::from("value"), "embstr"),
+        ("encoding:empty", Bytes::new(), "embstr"),
+        ("encoding:edge", Bytes::from(vec![b'x'; 38]), "embstr"),
+        ("encoding:long", Bytes::from(vec![b'x'; 39]), "raw"),
+        ("encoding:int", Bytes::from("-1234"), "int"),
+    ] {
+        let key = Bytes::from(key);
+        client.set(key.clone(), value.clone(), None).await.unwrap();
+        assert_eq!(
+            client.object_encoding(key.clone()).await.unwrap(),
+            Some(Bytes::from(encoding))
+        );
+        assert_eq!(client.get(key).await.unwrap(), Some(value));
+    }
+}
+
+#[tokio::test]
+async fn select_test() {
+    let mut client = connect_client().await;
+    let key = Bytes::from("select:key");
+
+    // Every database has keys of its own.
+    client.select(1).await.unwrap();
+    client
+        .set(key.clone(), Bytes::from("one"), None)
+        .await
+        .unwrap();
+    client.select(2).await.unwrap();
+    assert_eq!(client.get(key.clone()).await.unwrap(), None);
+    client
+        .set(key.clone(), Bytes::from("two"), None)
+        .await
+        .unwrap();
+    client.select(1).await.unwrap();
+    assert_eq!(
+        client.get(key.clone()).await.unwrap(),
+        Some(Bytes::from("one"))
+    );
+
+    for index in [-1, 16] {
+        let err = client.select(index).await.unwrap_err();
+        assert!(err.to_string().contains("DB index is out of range"));
+    }
+    // The database selected is kept.
+    assert_eq!(client.get(key).await.unwrap(), Some(Bytes::from("one")));
+}
+
+#[tokio::test]
+async fn move_test() {
+    let mut client = connect_client().await;
+    let key = Bytes::from("move:key");
+    client
+        .set(
+            key.clone(),
+            Bytes::from("value"),
+            Some(Duration::from_secs(60)),
+        )
+        .await
+        .unwrap();
+
+    let err = client.wmove(key.clone(), 0).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("source and destination objects are the same")
+    );
+    let err = client.wmove(key.clone(), 16).await.unwrap_err();
+    assert!(err.to_string().contains("DB index is out of range"));
+
+    // The key moves with its time to live.
+    assert!(client.wmove(key.clone(), 3).await.unwrap());
+    assert_eq!(client.get(key.clone()).await.unwrap(), None);
+    assert!(!client.wmove(key.clone(), 3).await.unwrap());
+    client.select(3).await.unwrap();
+    assert_eq!(
+        client.get(key.clone()).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    let ttl = client.pttl(key.clone()).await.unwrap();
+    assert!(ttl > 0 && ttl <= 60_000);
+
+    // Nothing is moved over a key of the target database.
+    client.select(0).await.unwrap();
+    client
+        .set(key.clone(), Bytes::from("other"), None)
+        .await
+        .unwrap();
+    assert!(!client.wmove(key.clone(), 3).await.unwrap());
+    assert_eq!(
+        client.get(key.clone()).await.unwrap(),
+        Some(Bytes::from("other"))
+    );
+    client.select(3).await.unwrap();
+    assert_eq!(client.get(key).await.unwrap(), Some(Bytes::from("value")));
+}
+
+#[tokio::test]
+async fn move_list_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let key = Bytes::from("list");
+    let list = random_data_array(5);
+    client.rpush(key.clone(), list.clone()).await.unwrap();
+
+    assert!(client.wmove(key.clone(), 1).await.unwrap());
+    client.select(1).await.unwrap();
+    assert_eq!(
+        VecDeque::from(client.lrange(key.clone(), 0, -1).await.unwrap()),
+        list
+    );
+
+    // The key counts of both databases follow the move.
+    let info = client.info(vec![Bytes::from("keyspace")]).await.unwrap();
+    assert_eq!(info_field(&info, "db0"), None);
+    assert!(info_field(&info, "db1").unwrap().starts_with("keys=1,"));
+}
+
+#[tokio::test]
+async fn lazyfree_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    async fn lazyfreed(client: &mut Client) -> String {
+        let info = client.info(vec![Bytes::from("stats")]).await.unwrap();
+        info_field(&info, "lazyfreed_objects").unwrap()
+    }
+
+    let large: VecDeque<Data> = (0..1000).map(Data::Integer).collect();
+    let small: VecDeque<Data> = (0..10).map(Data::Integer).collect();
+
+    // Values are freed right away by default.
+    client
+        .rpush(Bytes::from("list"), large.clone())
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("list"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    assert_eq!(lazyfreed(&mut client).await, "0");
+
+    client
+        .config_set(Bytes::from("lazyfree-lazy-server-del"), Bytes::from("yes"))
+        .await
+        .unwrap();
+    assert_eq!(
+        client
+            .config_get(Bytes::from("lazyfree-lazy-*"))
+            .await
+            .unwrap()
+            .len(),
+        4
+    );
+
+    // Only large values are freed lazily.
+    client.rpush(Bytes::from("small"), small).await.unwrap();
+    client
+        .set(Bytes::from("small"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client.rpush(Bytes::from("large"), large).await.unwrap();
+    client
+        .set(Bytes::from("large"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let mut freed = lazyfreed(&mut client).await;
+    for _ in 0..100 {
+        if freed != "0" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        freed = lazyfreed(&mut client).await;
+    }
+    assert_eq!(freed, "1");
+    assert_eq!(
+        client.get(Bytes::from("large")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+}
+
+/// `PTTL` reports the time to live of keys, extended by the `expire-jitter` percentage of it.
+#[tokio::test]
+async fn expire_jitter_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(client.pttl(Bytes::from("missing")).await.unwrap(), -2);
+    client
+        .set(Bytes::from("persistent"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    assert_eq!(client.pttl(Bytes::from("persistent")).await.unwrap(), -1);
+
+    let expire = Duration::from_secs(100);
+    client
+        .set(Bytes::from("exact"), Bytes::from("value"), Some(expire))
+        .await
+        .unwrap();
+    let ttl = client.pttl(Bytes::from("exact")).await.unwrap();
+    assert!((99_000..=100_000).contains(&ttl), "{ttl}");
+
+    assert!(
+        client
+            .config_set(Bytes::from("expire-jitter"), Bytes::from("101"))
+            .await
+            .is_err()
+    );
+    client
+        .config_set(Bytes::from("expire-jitter"), Bytes::from("50"))
+        .await
+        .unwrap();
+
+    // Keys set with the same time to live expire up to 50% later, spread over that period.
+    let mut ttls = Vec::new();
+    for i in 0..20 {
+        let key = Bytes::from(format!("jittered{i}"));
+        client
+            .set(key.clone(), Bytes::from("value"), Some(expire))
+            .await
+            .unwrap();
+        ttls.push(client.pttl(key).await.unwrap());
+    }
+    assert!(ttls.iter().all(|ttl| (99_000..=150_000).contains(ttl)));
+    assert!(ttls.iter().any(|ttl| *ttl != ttls[0]));
+}
+
+/// Replicas receive the changes made by the master, keys expire when they expire on the master
+/// even though the expiration of the master was jittered, and aren't jittered again.
+#[tokio::test]
+async fn replication_changelog_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    for client in [&mut master, &mut replica] {
+        client
+            .config_set(Bytes::from("expire-jitter"), Bytes::from("100"))
+            .await
+            .unwrap();
+    }
+    master
+        .set(
+            Bytes::from("key"),
+            Bytes::from("value"),
+            Some(Duration::from_secs(1000)),
+        )
+        .await
+        .unwrap();
+    let list = Bytes::from("list");
+    let elements: VecDeque<Data> = (0..4).map(Data::Integer).collect();
+    master.rpush(list.clone(), elements).await.unwrap();
+    master
+        .lpush(list.clone(), [Data::Integer(-1)].into())
+        .await
+        .unwrap();
+    master.lpop(list.clone(), Some(2)).await.unwrap();
+    // Pops of missing keys change nothing.
+    master.lpop(Bytes::from("missing"), None).await.unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.llen(list.clone()).await.unwrap() != 3 {
+        assert!(Instant::now() < deadline, "writes never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        replica.lrange(list, 0, -1).await.unwrap(),
+        vec![Data::Integer(1), Data::Integer(2), Data::Integer(3)]
+    );
+
+    let master_ttl = master.pttl(Bytes::from("key")).await.unwrap();
+    let replica_ttl = replica.pttl(Bytes::from("key")).await.unwrap();
+    assert!(
+        (master_ttl - replica_ttl).abs() < 1000,
+        "{master_ttl} {replica_ttl}"
+    );
+}
+
+/// Replicas keep the keys of the master in the same databases, whether they're loaded from its
+/// snapshot or streamed.
+#[tokio::test]
+async fn replication_databases_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    master.select(2).await.unwrap();
+    master
+        .set(Bytes::from("loaded"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    master
+        .set(Bytes::from("streamed"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    assert!(master.wmove(Bytes::from("streamed"), 4).await.unwrap());
+    master.select(0).await.unwrap();
+    master
+        .set(Bytes::from("last"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while replica.get(Bytes::from("last")).await.unwrap().is_none() {
+        assert!(Instant::now() < deadline, "writes never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(replica.get(Bytes::from("loaded")).await.unwrap(), None);
+    replica.select(2).await.unwrap();
+    assert!(replica.get(Bytes::from("loaded")).await.unwrap().is_some());
+    assert_eq!(replica.get(Bytes::from("streamed")).await.unwrap(), None);
+    replica.select(4).await.unwrap();
+    assert!(
+        replica
+            .get(Bytes::from("streamed"))
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+/// Keys evicted by the master are deleted on its replicas, which don't evict keys themselves.
+#[tokio::test]
+async fn replication_eviction_test() {
+    let master_addr = start_dedicated_server().await;
+    let mut master = Client::connect(master_addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let replica_addr = start_dedicated_server().await;
+    let mut replica = Client::connect(replica_addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let port = master_addr.rsplit(':').next().unwrap().parse().unwrap();
+    replica
+        .replicaof(Bytes::from("127.0.0.1"), port)
+        .await
+        .unwrap();
+
+    let value = Bytes::from(vec![b'x'; 1000]);
+    for i in 0..20 {
+        master
+            .set(Bytes::from(format!("key:{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+
+    let info = master.info(Vec::new()).await.unwrap();
+    let maxmemory = info_field(&info, "used_memory_dataset").unwrap();
+    master
+        .config_set(Bytes::from("maxmemory-policy"), Bytes::from("allkeys-lru"))
+        .await
+        .unwrap();
+    master
+        .config_set(Bytes::from("maxmemory"), Bytes::from(maxmemory))
+        .await
+        .unwrap();
+    for i in 0..10 {
+        master
+            .set(Bytes::from(format!("extra:{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+    let info = master.info(Vec::new()).await.unwrap();
+    assert_ne!(info_field(&info, "evicted_keys").unwrap(), "0");
+
+    let mut expected = master.keys(Bytes::from("*")).await.unwrap();
+    expected.sort();
+    assert!(expected.len() < 29);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let mut keys = replica.keys(Bytes::from("*")).await.unwrap();
+        keys.sort();
+        if keys == expected {
+            break;
+        }
+        assert!(Instant::now() < deadline, "evictions never replicated");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Integers use the whole range of `i64`, doubles may be negative, as values and elements.
+#[tokio::test]
+async fn signed_numbers_test() {
+    let mut client = connect_client().await;
+
+    let key = random_bytes(12);
+    let elements = VecDeque::from([
+        Data::Integer(i64::MIN),
+        Data::Integer(i64::MAX),
+        Data::Integer(-1),
+        Data::Double(-2.5),
+        Data::Double(1e300),
+    ]);
+    client.rpush(key.clone(), elements.clone()).await.unwrap();
+    assert_eq!(
+        client.lrange(key.clone(), 0, -1).await.unwrap(),
+        Vec::from(elements)
+    );
+    assert_eq!(
+        client.lpop(key, Some(1)).await.unwrap(),
+        Some(vec![Data::Integer(i64::MIN)])
+    );
+
+    for value in ["-9223372036854775808", "-42", "-0.5"] {
+        let key = random_bytes(12);
+        client
+            .set(key.clone(), Bytes::from(value), None)
+            .await
+            .unwrap();
+        assert_eq!(client.get(key).await.unwrap(), Some(Bytes::from(value)));
+    }
+
+    // One past the range of `i64` is kept as a string.
+    let key = random_bytes(12);
+    client
+        .set(key.clone(), Bytes::from("-9223372036854775809"), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get(key).await.unwrap(),
+        Some(Bytes::from("-9223372036854775809"))
+    );
+}
+
+/// Elements of lists may be arrays themselves, they are pushed and read back nested.
+#[tokio::test]
+async fn nested_array_test() {
+    let mut client = connect_client().await;
+
+    let key = random_bytes(12);
+    let nested = Data::Array(VecDeque::from([
+        Data::Integer(1),
+        Data::Array(VecDeque::from([Data::Bytes(Bytes::from("inner"))])),
+    ]));
+    let elements = VecDeque::from([Data::Integer(0), nested.clone()]);
+    client.rpush(key.clone(), elements.clone()).await.unwrap();
+    client
+        .lpush(key.clone(), VecDeque::from([nested.clone()]))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.lrange(key, 0, -1).await.unwrap(),
+        vec![nested.clone(), Data::Integer(0), nested]
+    );
+}
+
+#[tokio::test]
+async fn memory_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    let short = Bytes::from("short");
+    let long = Bytes::from("long");
+    let list = Bytes::from("list");
+
+    assert_eq!(
+        client.memory_usage(short.clone(), None).await.unwrap(),
+        None
+    );
+
+    client
+        .set(short.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .set(long.clone(), random_bytes(10_000), None)
+        .await
+        .unwrap();
+    let elements: VecDeque<Data> = (0..1000).map(Data::Integer).collect();
+    client.rpush(list.clone(), elements).await.unwrap();
+
+    let short_usage = client.memory_usage(short, None).await.unwrap().unwrap();
+    let long_usage = client.memory_usage(long, None).await.unwrap().unwrap();
+    assert!(short_usage > 0);
+    assert!(long_usage > 10_000);
+
+    // Sizes are tracked exactly, the number of samples doesn't change them.
+    let list_usage = client
+        .memory_usage(list.clone(), None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        client.memory_usage(list.clone(), Some(0)).await.unwrap(),
+        Some(list_usage)
+    );
+    assert_eq!(
+        client.memory_usage(list, Some(5)).await.unwrap(),
+        Some(list_usage)
+    );
+
+    let stats = client.memory_stats().await.unwrap();
+    let stat = |name: &str| {
+        stats
+            .iter()
+            .find(|(field, _)| field == name.as_bytes())
+            .map(|(_, value)| value.clone())
+    };
+    assert_eq!(stat("keys.count"), Some(Frame::Integer(3)));
+    assert_eq!(
+        stat("dataset.bytes"),
+        Some(Frame::Integer(short_usage + long_usage + list_usage))
+    );
+    assert_eq!(
+        stat("keys.bytes-per-key"),
+        Some(Frame::Integer((short_usage + long_usage + list_usage) / 3))
+    );
+    assert!(stat("peak.allocated").is_some());
+
+    let info = client.info(vec![Bytes::from("memory")]).await.unwrap();
+    let field = |name: &str| -> usize { info_field(&info, name).unwrap().parse().unwrap() };
+    assert!(field("used_memory_peak") >= field("used_memory"));
+    assert!(info_field(&info, "mem_allocator").is_some());
+
+    // The report is never empty, whether issues are found or not.
+    let report = client.memory_doctor().await.unwrap();
+    assert!(report.ends_with('\n'));
+    assert!(report.len() > 1);
+}
+
+#[tokio::test]
+async fn nested_frame_test() {
+    use tokio::io::AsyncReadExt;
+    use walrus::Connection;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+    let mut conn = Connection::new(stream, None, None);
+
+    let frame = Frame::Array(vec![
+        Frame::Map(vec![(
+            Frame::Bulk(Bytes::from("key")),
+            Frame::Array(vec![Frame::Integer(1), Frame::Null]),
+        )]),
+        Frame::Set(vec![Frame::Array(vec![]), Frame::Simple(Bytes::from("OK"))]),
+        Frame::Integer(2),
+    ]);
+    conn.write_frame(&frame);
+
+    // Deeply nested arrays are written from `write_val` too.
+    let depth = 1000;
+    let mut deep = Frame::Integer(0);
+    for _ in 0..depth {
+        deep = Frame::Array(vec![deep]);
+    }
+    conn.write_val(&deep);
+    conn.flush().await.unwrap();
+    drop(conn);
+
+    let mut written = Vec::new();
+    peer.read_to_end(&mut written).await.unwrap();
+
+    let mut expected =
+        b"*3\r\n*2\r\n$3\r\nkey\r\n*2\r\n:1\r\n$-1\r\n*2\r\n*0\r\n+OK\r\n:2\r\n".to_vec();
+    expected.extend(b"*1\r\n".repeat(depth));
+    expected.extend(b":0\r\n");
+    assert_eq!(written, expected);
+}
+
+#[tokio::test]
+async fn connection_split_test() {
+    use walrus::Connection;
+
+    let stream = tokio::net::TcpStream::connect(SERVER_IPADDRESS)
+        .await
+        .unwrap();
+    let (mut reader, mut writer) = Connection::new(stream, None, None).split().unwrap();
+
+    // The reader waits for the replies while the writer sends the requests.
+    let count = 3;
+    let read = tokio::spawn(async move {
+        let mut replies = Vec::new();
+        for _ in 0..count {
+            replies.push(reader.read_frame().await.unwrap().unwrap());
+        }
+        replies
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for i in 0..count {
+        writer.write_frame(&Frame::Array(vec![
+            Frame::Bulk(Bytes::from("PING")),
+            Frame::Bulk(Bytes::from(i.to_string())),
+        ]));
+        writer.flush().await.unwrap();
+    }
+
+    let replies = read.await.unwrap();
+    let expected: Vec<Frame> = (0..count)
+        .map(|i| Frame::Bulk(Bytes::from(i.to_string())))
+        .collect();
+    assert_eq!(replies, expected);
+}
+
+#[tokio::test]
+async fn idle_timeout_test() {
+    use tokio::io::AsyncReadExt;
+
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("timeout"), Bytes::from("1"))
+        .await
+        .unwrap();
+
+    // Monitors only receive data, they are never idle.
+    let mut monitor = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    monitor.monitor().await.unwrap();
+
+    let mut idle = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    let mut buf = [0; 16];
+    let closed = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut buf)).await;
+    assert_eq!(closed.unwrap().unwrap(), 0);
+
+    // Commands keep the monitor connection open, it receives them.
+    let mut active = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(active.ping(None).await.unwrap(), Bytes::from("PONG"));
+    let line = monitor.next_monitor().await.unwrap();
+    assert!(line.ends_with(b"\"ping\""), "{line:?}");
+}
+
+#[tokio::test]
+async fn connection_timeout_test() {
+    use walrus::Connection;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (_peer, _) = listener.accept().await.unwrap();
+
+    let mut conn = Connection::new(stream, None, None);
+    conn.set_read_timeout(Some(Duration::from_millis(100)));
+    let start = Instant::now();
+    assert!(conn.read_frame().await.is_err());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn frame_encode_test() {
+    use bytes::BytesMut;
+    use walrus::connection::Protocol;
+
+    let frame = Frame::Map(vec![
+        (
+            Frame::Simple(Bytes::from("list")),
+            Frame::Array(vec![Frame::Integer(-1), Frame::Double(1.5), Frame::Null]),
+        ),
+        (
+            Frame::Bulk(Bytes::from("flags")),
+            Frame::Set(vec![Frame::Boolean(true)]),
+        ),
+    ]);
+
+    let encoded = frame.encode();
+    assert_eq!(
+        &encoded[..],
+        b"%2\r\n+list\r\n*3\r\n:-1\r\n,1.5\r\n_\r\n$5\r\nflags\r\n~1\r\n#t\r\n"
+    );
+    assert_eq!(Frame::parse(&mut encoded.clone()).unwrap(), frame);
+
+    // RESP3 types are downgraded for RESP2, after what is already in the buffer.
+    let mut buf = BytesMut::from(&b"+OK\r\n"[..]);
+    frame.write_to(&mut buf, Protocol::Resp2);
+    assert_eq!(
+        &buf[..],
+        b"+OK\r\n*4\r\n+list\r\n*3\r\n:-1\r\n$3\r\n1.5\r\n$-1\r\n$5\r\nflags\r\n*1\r\n:1\r\n"
+    );
+}
+
+#[tokio::test]
+async fn frame_limits_test() {
+    use std::io::Cursor;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use walrus::frame::Limits;
+
+    let limits = Limits {
+        max_bulk_len: 10,
+        max_aggregate_len: 2,
+        max_frame_size: 100,
+        max_depth: 128,
+    };
+    let check = |bytes: &[u8]| {
+        Frame::check_with(&mut Cursor::new(bytes), &limits).map_err(|err| err.to_string())
+    };
+
+    assert_eq!(check(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n"), Ok(20));
+    // Lengths are refused as soon as their header is read.
+    assert_eq!(
+        check(b"*2\r\n$3\r\nGET\r\n$9999999999\r\n"),
+        Err("protocol error; invalid bulk length".to_string())
+    );
+    assert_eq!(
+        check(b"*3\r\n"),
+        Err("protocol error; invalid multibulk length".to_string())
+    );
+    assert_eq!(
+        check(b"%3\r\n"),
+        Err("protocol error; invalid multibulk length".to_string())
+    );
+    let mut nested = b"*2\r\n".repeat(30);
+    nested.extend(b"$10\r\n");
+    assert_eq!(
+        check(&nested),
+        Err("protocol error; frame too large".to_string())
+    );
+    // So is a frame buffered beyond the limit without being complete.
+    assert_eq!(
+        check(&b"+".repeat(200)),
+        Err("protocol error; frame too large".to_string())
+    );
+
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Sends `request` on a new connection, returns `true` if the server closed it.
+    async fn refused(addr: &str, request: &[u8]) -> bool {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        reply.is_empty()
+    }
+
+    assert!(
+        client
+            .config_set(Bytes::from("proto-max-bulk-len"), Bytes::from("1kb"))
+            .await
+            .is_err()
+    );
+    assert!(refused(&addr, b"*2\r\n$4\r\nECHO\r\n$9999999999\r\n").await);
+
+    // Limits set at runtime apply to connected clients too.
+    client
+        .config_set(Bytes::from("proto-max-multibulk-len"), Bytes::from("4"))
+        .await
+        .unwrap();
+    assert!(refused(&addr, b"*5\r\n$3\r\nGET\r\n").await);
+    client
+        .config_set(Bytes::from("proto-max-multibulk-len"), Bytes::from("1000"))
+        .await
+        .unwrap();
+
+    client
+        .config_set(Bytes::from("client-query-buffer-limit"), Bytes::from("1mb"))
+        .await
+        .unwrap();
+    assert!(refused(&addr, b"*2\r\n$3\r\nGET\r\n$2000000\r\n").await);
+
+    // Requests within the limits are served.
+    client
+        .set(Bytes::from("key"), random_bytes(500_000), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        client
+            .config_get(Bytes::from("proto-max-multibulk-len"))
+            .await
+            .unwrap(),
+        vec![(Bytes::from("proto-max-multibulk-len"), Bytes::from("1000"))]
+    );
+}
+
+#[test]
+fn frame_depth_test() {
+    use std::io::Cursor;
+    use walrus::frame::Limits;
+
+    let limits = Limits {
+        max_depth: 3,
+        ..Limits::UNLIMITED
+    };
+    let check = |bytes: &[u8]| {
+        Frame::check_with(&mut Cursor::new(bytes), &limits).map_err(|err| err.to_string())
+    };
+
+    let nested = b"*2\r\n%1\r\n+key\r\n|1\r\n+ttl\r\n:1\r\n~0\r\n*1\r\n:2\r\n";
+    assert_eq!(check(nested), Ok(nested.len()));
+    assert_eq!(
+        check(b"*1\r\n*1\r\n*1\r\n*1\r\n:0\r\n"),
+        Err("protocol error; nesting too deep".to_string())
+    );
+
+    assert_eq!(
+        Frame::parse(&mut Bytes::from_static(nested)).unwrap(),
+        Frame::Array(vec![
+            Frame::Map(vec![(
+                Frame::Simple(Bytes::from("key")),
+                Frame::Attribute {
+                    attributes: vec![(Frame::Simple(Bytes::from("ttl")), Frame::Integer(1))],
+                    data: Box::new(Frame::Set(vec![])),
+                },
+            )]),
+            Frame::Array(vec![Frame::Integer(2)]),
+        ])
+    );
+
+    // Frames nested deeper than the call stack would allow parsing recursively are refused
+    // without overflowing it.
+    let deep = b"*1\r\n".repeat(1_000_000);
+    assert_eq!(
+        Frame::check_with(&mut Cursor::new(&deep[..]), &Limits::default())
+            .map_err(|err| err.to_string()),
+        Err("protocol error; nesting too deep".to_string())
+    );
+    assert!(matches!(
+        Frame::check(&mut Cursor::new(&deep[..])),
+        Err(walrus::frame::Error::Incomplete)
+    ));
+}
+
+#[test]
+fn frame_verbatim_test() {
+    use std::io::Cursor;
+
+    let check = |bytes: &[u8]| Frame::check(&mut Cursor::new(bytes)).map_err(|err| err.to_string());
+
+    let verbatim = b"=15\r\ntxt:Some string\r\n";
+    assert_eq!(check(verbatim), Ok(verbatim.len()));
+    assert_eq!(
+        Frame::parse(&mut Bytes::from_static(verbatim)).unwrap(),
+        Frame::Verbatim {
+            format: Bytes::from("txt"),
+            text: Bytes::from("Some string"),
+        }
+    );
+
+    // The format must be followed by a `:` separator.
+    let invalid = Err("protocol error; invalid frame format".to_string());
+    assert_eq!(check(b"=15\r\ntxt Some string\r\n"), invalid);
+    assert_eq!(check(b"=3\r\ntxt\r\n"), invalid);
+    assert_eq!(
+        Frame::parse(&mut Bytes::from_static(b"=15\r\ntxtxSome string\r\n"))
+            .unwrap_err()
+            .to_string(),
+        "protocol error; invalid frame format"
+    );
+}
+
+#[tokio::test]
+async fn inline_command_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = start_dedicated_server().await;
+
+    let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    stream
+        .write_all(
+            b"SET key \"hello\\x20world\\n\"\r\n\r\n  get   key  \r\nRPUSH list 'it\\'s' \"\"\nLRANGE list 0 -1\r\n",
+        )
+        .await
+        .unwrap();
+    stream.shutdown().await.unwrap();
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(
+        String::from_utf8(reply).unwrap(),
+        "$2\r\nOK\r\n$12\r\nhello world\n\r\n:2\r\n*2\r\n$4\r\nit's\r\n$0\r\n\r\n"
+    );
+
+    // Unbalanced quotes are a protocol error, the connection is closed.
+    let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    stream.write_all(b"SET key \"value\r\n").await.unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert!(reply.is_empty());
+}
+
+/// HTTP requests sent to the server close the connection, the commands in their body are never
+/// run.
+#[tokio::test]
+async fn inline_http_request_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = start_dedicated_server().await;
+
+    for request in [
+        &b"POST / HTTP/1.1\r\nHost: localhost\r\n\r\nSET key value\r\n"[..],
+        b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nSET key value\r\n",
+    ] {
+        let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        let mut reply = Vec::new();
+        // The request may still be unread when the connection is closed, resetting it.
+        let _ = stream.read_to_end(&mut reply).await;
+        assert!(!String::from_utf8_lossy(&reply).contains("OK"));
+    }
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(client.get(Bytes::from("key")).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn output_buffer_limit_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .config_set(
+            Bytes::from("client-output-buffer-limit"),
+            Bytes::from("pubsub 1mb 0 0"),
+        )
+        .await
+        .unwrap();
+    let limits = client
+        .config_get(Bytes::from("client-output-buffer-limit"))
+        .await
+        .unwrap();
+    assert_eq!(
+        limits[0].1,
+        Bytes::from("normal 0 0 0 replica 268435456 67108864 60 pubsub 1048576 0 0")
+    );
+
+    // The monitor never reads the commands streamed to it.
+    let mut monitor = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    monitor.write_all(b"MONITOR\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let value = Bytes::from(vec![b'x'; 64 * 1024]);
+    for i in 0..400 {
+        client
+            .set(Bytes::from(format!("obl{i}")), value.clone(), None)
+            .await
+            .unwrap();
+    }
+
+    // The server closes the monitor once its output exceeds the limit.
+    let drained = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut buf = vec![0; 64 * 1024];
+        while monitor.read(&mut buf).await.unwrap() > 0 {}
+    })
+    .await;
+    assert!(drained.is_ok());
+}
+
+#[tokio::test]
+async fn read_buffer_growth_test() {
+    use walrus::Connection;
+
+    ensure_server_running();
+    let stream = tokio::net::TcpStream::connect(SERVER_IPADDRESS)
+        .await
+        .unwrap();
+    let mut conn = Connection::new(stream, Some(1), None);
+
+    // A request much larger than the read buffer, pipelined with small ones read after the
+    // buffer is shrunk back.
+    let value = Bytes::from(vec![b'v'; 1024 * 1024]);
+    conn.write_frame(&Frame::Array(vec![
+        Frame::Bulk(Bytes::from("SET")),
+        Frame::Bulk(Bytes::from("read_buffer_growth")),
+        Frame::Bulk(value.clone()),
+    ]));
+    for i in 0..3 {
+        conn.write_frame(&Frame::Array(vec![
+            Frame::Bulk(Bytes::from("PING")),
+            Frame::Bulk(Bytes::from(i.to_string())),
+        ]));
+    }
+    conn.write_frame(&Frame::Array(vec![
+        Frame::Bulk(Bytes::from("GET")),
+        Frame::Bulk(Bytes::from("read_buffer_growth")),
+    ]));
+    conn.flush().await.unwrap();
+
+    assert_eq!(
+        conn.read_frame().await.unwrap(),
+        Some(Frame::Bulk(Bytes::from("OK")))
+    );
+    for i in 0..3 {
+        assert_eq!(
+            conn.read_frame().await.unwrap(),
+            Some(Frame::Bulk(Bytes::from(i.to_string())))
+        );
+    }
+    assert_eq!(conn.read_frame().await.unwrap(), Some(Frame::Bulk(value)));
+}
+
+#[tokio::test]
+async fn stream_large_bulk_test() {
+    use tokio::io::AsyncReadExt;
+    use walrus::Connection;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (mut peer, _) = listener.accept().await.unwrap();
+
+    // The peer reads slowly, the whole value takes longer than the write timeout to send but
+    // each piece is sent in time.
+    let value = Bytes::from(vec![b's'; 16 * 1024 * 1024]);
+    let len = value.len();
+    let reader = tokio::spawn(async move {
+        let mut received = Vec::new();
+        let mut buf = vec![0; 1024 * 1024];
+        while received.len() < len + 16 {
+            let n = peer.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        received
+    });
+
+    let mut conn = Connection::new(stream, None, None);
+    conn.set_write_timeout(Some(Duration::from_millis(200)));
+    conn.write_frame(&Frame::Bulk(value.clone()));
+    conn.flush().await.unwrap();
+    drop(conn);
+
+    let received = reader.await.unwrap();
+    let header = format!("${len}\r\n");
+    assert!(received.starts_with(header.as_bytes()));
+    assert_eq!(&received[header.len()..header.len() + len], &value[..]);
+    assert!(received.ends_with(b"\r\n"));
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn tls_test() {
+    use tokio_rustls::{
+        TlsConnector, client,
+        rustls::{
+            ClientConfig, RootCertStore,
+            crypto::ring,
+            pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+        },
+    };
+    use walrus::Connection;
+    use walrus::server::Listeners;
+    use walrus::tls::{TlsConfig, TlsListener};
+
+    let certs = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    let config = TlsConfig {
+        cert_file: certs.join("server.crt"),
+        key_file: certs.join("server.key"),
+        ca_cert_file: Some(certs.join("ca.crt")),
+        auth_clients: true,
+    };
+
+    // Plain connections on the server port, TLS ones on a dedicated port.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tls_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tls_addr = tls_listener.local_addr().unwrap();
+    let listeners =
+        Listeners::from(listener).with_tls(TlsListener::new(tls_listener, &config).unwrap());
+    tokio::spawn(walrus::server::run(listeners, addr.port(), None, None));
+    wait_until_loaded(&addr.to_string()).await;
+
+    let connect = async |with_cert: bool| -> std::io::Result<client::TlsStream<_>> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(certs.join("ca.crt")).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = if with_cert {
+            let chain = CertificateDer::pem_file_iter(certs.join("client.crt"))
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            let key = PrivateKeyDer::from_pem_file(certs.join("client.key")).unwrap();
+            builder.with_client_auth_cert(chain, key).unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let stream = tokio::net::TcpStream::connect(tls_addr).await?;
+        let name = ServerName::try_from("localhost").unwrap();
+        TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+    };
+    let ping = Frame::Array(vec![Frame::Bulk(Bytes::from("PING"))]);
+
+    let mut conn = Connection::new(connect(true).await.unwrap(), None, None);
+    conn.write_frame(&ping);
+    conn.flush().await.unwrap();
+    assert_eq!(
+        conn.read_frame().await.unwrap(),
+        Some(Frame::Bulk(Bytes::from("PONG")))
+    );
+
+    // Clients without a certificate are refused, once the server verifies the handshake.
+    if let Ok(stream) = connect(false).await {
+        let mut conn = Connection::new(stream, None, None);
+        conn.write_frame(&ping);
+        let _ = conn.flush().await;
+        assert!(!matches!(conn.read_frame().await, Ok(Some(_))));
+    }
+
+    // The server port still accepts plain connections.
+    let mut client = Client::connect(addr.to_string(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_socket_test() {
+    use std::os::unix::fs::PermissionsExt;
+    use walrus::server::{self, Listeners};
+
+    let dir = temp_dir();
+    let path = dir.join("walrus.sock");
+    // A stale socket file is replaced.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let unix = server::bind_unix(&path, Some(0o700)).unwrap();
+    tokio::spawn(server::run(
+        Listeners::from(listener).with_unix(unix),
+        addr.port(),
+        None,
+        None,
+    ));
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    let mut client = Client::connect_unix(&path, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let key = Bytes::from("unix_socket");
+    client
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // Both listeners serve the same dataset.
+    let mut tcp = Client::connect(addr.to_string(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(tcp.get(key).await.unwrap(), Some(Bytes::from("value")));
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn multiple_listeners_test() {
+    use walrus::server::Listeners;
+
+    let first = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let second = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = first.local_addr().unwrap().to_string();
+    let other = second.local_addr().unwrap().to_string();
+    tokio::spawn(walrus::server::run(
+        Listeners::from(first).with_tcp(second),
+        0,
+        None,
+        None,
+    ));
+    wait_until_loaded(&addr).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut other = Client::connect(other, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Both listeners serve the same dataset.
+    let key = Bytes::from("multiple_listeners");
+    client
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    assert_eq!(other.get(key).await.unwrap(), Some(Bytes::from("value")));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn reuseport_test() {
+    use walrus::server::{self, Listeners};
+
+    let mut bound = server::bind_reuseport("127.0.0.1:0".parse().unwrap(), 4)
+        .unwrap()
+        .into_iter();
+    let first = bound.next().unwrap();
+    let addr = first.local_addr().unwrap();
+    let listeners = bound.fold(Listeners::from(first), |listeners, listener| {
+        assert_eq!(listener.local_addr().unwrap(), addr);
+        listeners.with_tcp(listener)
+    });
+    tokio::spawn(server::run(listeners, addr.port(), None, None));
+    wait_until_loaded(&addr.to_string()).await;
+
+    // Whichever listener the kernel hands them to, connections are served.
+    for _ in 0..16 {
+        let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .unwrap();
+        assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+    }
+}
+
+#[tokio::test]
+async fn socket_options_test() {
+    use walrus::{connection::SocketOptions, server};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(listener, addr.port(), None, None));
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        client.config_get(Bytes::from("tcp-*")).await.unwrap().len(),
+        4
+    );
+    client
+        .config_set(Bytes::from("tcp-keepalive"), Bytes::from("60"))
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("tcp-nodelay"), Bytes::from("no"))
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("tcp-sndbuf"), Bytes::from("256kb"))
+        .await
+        .unwrap();
+    assert!(
+        client
+            .config_set(Bytes::from("tcp-nodelay"), Bytes::from("maybe"))
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        client.config_get(Bytes::from("tcp-sndbuf")).await.unwrap(),
+        vec![(Bytes::from("tcp-sndbuf"), Bytes::from("262144"))]
+    );
+
+    // New connections are accepted with the options set, both ends tuned.
+    let options = SocketOptions {
+        nodelay: false,
+        keepalive: Some(Duration::from_secs(30)),
+        send_buffer_size: Some(64 * 1024),
+        recv_buffer_size: Some(64 * 1024),
+    };
+    let mut tuned =
+        Client::connect_with_options(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE, &options)
+            .await
+            .unwrap();
+    assert_eq!(tuned.ping(None).await.unwrap(), Bytes::from("PONG"));
+    let value = Bytes::from(vec![b'x'; 1024 * 1024]);
+    tuned
+        .set(Bytes::from("tuned"), value.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(tuned.get(Bytes::from("tuned")).await.unwrap(), Some(value));
+}
+
+#[tokio::test]
+async fn graceful_shutdown_test() {
+    let dir = temp_dir();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .port(addr.port())
+            .dir(dir.clone())
+            .run(shutdown_rx),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // Blocked commands don't hold up the shutdown.
+    let mut blocked = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let blocking = tokio::spawn(async move { blocked.blpop(vec![Bytes::from("list")], 0.0).await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    shutdown_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Connections are closed, no new ones are accepted, and the dataset was saved.
+    assert!(blocking.await.unwrap().is_err());
+    assert!(client.ping(None).await.is_err());
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+    assert!(dir.join("dump.wdb").exists());
+
+    let addr = start_server_in(Some(dir.clone()), false).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        client.get(Bytes::from("key")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn server_builder_test() {
+    let dir = temp_dir();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .max_connections(2)
+            .read_buffer_size(4)
+            .write_buffer_size(8)
+            .timeout(Duration::from_secs(300))
+            .dir(&dir)
+            .run(std::future::pending::<()>()),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let params = client.config_get(Bytes::from("*")).await.unwrap();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.clone())
+            .unwrap()
+    };
+    // The port defaults to the one of the listener.
+    assert_eq!(param("port"), Bytes::from(addr.port().to_string()));
+    assert_eq!(param("maxclients"), Bytes::from("2"));
+    assert_eq!(param("timeout"), Bytes::from("300"));
+    assert_eq!(param("read-buffer-size"), Bytes::from("4"));
+    assert_eq!(param("write-buffer-size"), Bytes::from("8"));
+    assert_eq!(param("dir"), Bytes::from(dir.to_str().unwrap().to_string()));
+
+    // Connections over the limit are refused with an error, rather than left waiting.
+    let mut second = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(second.ping(None).await.unwrap(), Bytes::from("PONG"));
+    let mut third = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let err = tokio::time::timeout(Duration::from_secs(1), third.ping(None))
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("max number of clients reached"),
+        "{err}"
+    );
+    let info = client.info(vec![Bytes::from("stats")]).await.unwrap();
+    assert!(
+        String::from_utf8_lossy(&info).contains("rejected_connections:1\r\n"),
+        "{info:?}"
+    );
+
+    // The permit of a closed connection is given to the next one.
+    drop(second);
+    let mut accepted = false;
+    for _ in 0..20 {
+        let mut fourth = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+            .await
+            .unwrap();
+        if fourth.ping(None).await.is_ok() {
+            accepted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(accepted);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn loglevel_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.config_get(Bytes::from("loglevel")).await.unwrap(),
+        vec![(Bytes::from("loglevel"), Bytes::from("notice"))]
+    );
+    client
+        .config_set(Bytes::from("loglevel"), Bytes::from("WARNING"))
+        .await
+        .unwrap();
+    assert_eq!(walrus::log::level(), walrus::log::LogLevel::Warning);
+    assert!(
+        client
+            .config_set(Bytes::from("loglevel"), Bytes::from("loud"))
+            .await
+            .is_err()
+    );
+    client
+        .config_set(Bytes::from("loglevel"), Bytes::from("notice"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn config_file_test() {
+    use walrus::server::{Builder, ConfigFile};
+
+    let dir = temp_dir();
+    let path = dir.join("walrus.toml");
+    std::fs::write(
+        &path,
+        format!(
+            r#"
+maxclients = 100
+timeout = 120
+dir = "{}"
+maxmemory = "10mb"
+maxmemory-policy = "allkeys-lru"
+slowlog-max-len = 64
+replica-read-only = false
+client-output-buffer-limit = ["pubsub", "32mb", "8mb", 60]
+"#,
+            dir.display()
+        ),
+    )
+    .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    // Settings of the builder made afterwards take precedence over the file.
+    let builder = Builder::new(listener)
+        .config_file(ConfigFile::load(&path).unwrap())
+        .unwrap()
+        .max_connections(50);
+    tokio::spawn(builder.run(std::future::pending::<()>()));
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    for (name, value) in [
+        ("maxclients", "50"),
+        ("timeout", "120"),
+        ("dir", dir.to_str().unwrap()),
+        ("maxmemory", "10485760"),
+        ("maxmemory-policy", "allkeys-lru"),
+        ("slowlog-max-len", "64"),
+        ("replica-read-only", "no"),
+    ] {
+        assert_eq!(
+            client
+                .config_get(Bytes::from(name.to_string()))
+                .await
+                .unwrap(),
+            vec![(
+                Bytes::from(name.to_string()),
+                Bytes::from(value.to_string())
+            )]
+        );
+    }
+    let limits = client
+        .config_get(Bytes::from("client-output-buffer-limit"))
+        .await
+        .unwrap();
+    assert!(
+        String::from_utf8_lossy(&limits[0].1).contains("pubsub 33554432 8388608 60"),
+        "{limits:?}"
+    );
+
+    // Unknown parameters and invalid values are refused before the server runs.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    std::fs::write(&path, "maxmemory-policy = \"sometimes\"\n").unwrap();
+    assert!(
+        Builder::new(listener)
+            .config_file(ConfigFile::load(&path).unwrap())
+            .is_err()
+    );
+    std::fs::write(&path, "no-such-param = 1\n").unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    assert!(
+        Builder::new(listener)
+            .config_file(ConfigFile::load(&path).unwrap())
+            .is_err()
+    );
+    std::fs::write(&path, "maxclients = [").unwrap();
+    assert!(ConfigFile::load(&path).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn config_rewrite_test() {
+    use walrus::server::{Builder, ConfigFile};
+
+    let dir = temp_dir();
+    let path = dir.join("walrus.toml");
+    std::fs::write(
+        &path,
+        "# Memory of the cache\nmaxmemory = \"1gb\" # keep\nslowlog-max-len = 64\n",
+    )
+    .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let builder = Builder::new(listener)
+        .dir(&dir)
+        .config_file(ConfigFile::load(&path).unwrap())
+        .unwrap()
+        .watch_config_file(true);
+    tokio::spawn(builder.run(std::future::pending::<()>()));
+    wait_until_loaded(&addr.to_string()).await;
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("slowlog-max-len"), Bytes::from("32"))
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("maxmemory-policy"), Bytes::from("allkeys-lfu"))
+        .await
+        .unwrap();
+    client.config_rewrite().await.unwrap();
+
+    // Unchanged parameters and comments are kept, changed ones replaced or added.
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        contents.starts_with("# Memory of the cache\n"),
+        "{contents}"
+    );
+    assert!(
+        contents.contains("maxmemory = \"1gb\" # keep\n"),
+        "{contents}"
+    );
+    assert!(contents.contains("slowlog-max-len = 32\n"), "{contents}");
+    assert!(
+        contents.contains("maxmemory-policy = \"allkeys-lfu\"\n"),
+        "{contents}"
+    );
+    assert!(!contents.contains("hz"), "{contents}");
+
+    // Changes made to the file are applied by the server, parameters only read on startup
+    // are ignored.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    std::fs::write(
+        &path,
+        "bind = [\"127.0.0.1\"]\nslowlog-max-len = 16\n\n[rename-command]\nflushall = \"\"\n",
+    )
+    .unwrap();
+    let mut applied = false;
+    for _ in 0..40 {
+        let value = client
+            .config_get(Bytes::from("slowlog-max-len"))
+            .await
+            .unwrap();
+        if value[0].1 == "16" {
+            applied = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(applied);
+
+    // An invalid file leaves the configuration as it is.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    std::fs::write(&path, "slowlog-max-len = \"many\"\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    assert_eq!(
+        client
+            .config_get(Bytes::from("slowlog-max-len"))
+            .await
+            .unwrap()[0]
+            .1,
+        Bytes::from("16")
+    );
+
+    // Without a configuration file there is nothing to rewrite.
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert!(client.config_rewrite().await.is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn peer_limits_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .config_set(Bytes::from("maxclients-per-ip"), Bytes::from("2"))
+        .await
+        .unwrap();
+
+    // Connections over the limit of their address are refused.
+    let mut second = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(second.ping(None).await.unwrap(), Bytes::from("PONG"));
+    let mut third = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let err = third.ping(None).await.unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("max number of clients per address"),
+        "{err}"
+    );
+    drop(second);
+    client
+        .config_set(Bytes::from("maxclients-per-ip"), Bytes::from("0"))
+        .await
+        .unwrap();
+
+    // Commands over the rate of their address are refused, the connection stays open.
+    client
+        .config_set(Bytes::from("ratelimit-per-ip"), Bytes::from("10"))
+        .await
+        .unwrap();
+    let mut throttled = 0;
+    for _ in 0..30 {
+        if let Err(err) = client.ping(None).await {
+            assert!(err.to_string().starts_with("THROTTLED"), "{err}");
+            throttled += 1;
+        }
+    }
+    assert!(throttled >= 15, "{throttled}");
+
+    // The allowance refills over a second.
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let info = client.info(vec![Bytes::from("stats")]).await.unwrap();
+    assert!(
+        String::from_utf8_lossy(&info).contains(&format!("throttled_commands:{throttled}\r\n")),
+        "{info:?}"
+    );
+
+    // Or the connection is closed.
+    client
+        .config_set(
+            Bytes::from("ratelimit-per-ip-action"),
+            Bytes::from("disconnect"),
+        )
+        .await
+        .unwrap();
+    let mut closed = false;
+    for _ in 0..30 {
+        if client.ping(None).await.is_err() {
+            closed = client.ping(None).await.is_err();
+            break;
+        }
+    }
+    assert!(closed);
+
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(
+        client
+            .config_set(Bytes::from("ratelimit-per-ip-action"), Bytes::from("drop"))
+            .await
+            .is_err()
+    );
+    client
+        .config_set(Bytes::from("ratelimit-per-ip"), Bytes::from("0"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn protected_mode_test() {
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(walrus::server::Builder::new(listener).run(std::future::pending::<()>()));
+    let local = format!("127.0.0.1:{port}");
+    wait_until_loaded(&local).await;
+
+    // Clients on the same host are served.
+    let mut client = Client::connect(local, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(
+        client
+            .config_get(Bytes::from("protected-mode"))
+            .await
+            .unwrap(),
+        vec![(Bytes::from("protected-mode"), Bytes::from("yes"))]
+    );
+
+    // An address of another interface stands in for another host. No packet is sent by
+    // connecting a UDP socket, the route to the address is looked up.
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").unwrap();
+    let ip = match socket
+        .connect("192.0.2.1:9")
+        .and_then(|_| socket.local_addr())
+    {
+        Ok(addr) if !addr.ip().is_loopback() && !addr.ip().is_unspecified() => addr.ip(),
+        // No other interface to reach the server from.
+        _ => return,
+    };
+    let remote = std::net::SocketAddr::new(ip, port);
+
+    let mut refused = Client::connect(remote, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let err = refused.ping(None).await.unwrap_err();
+    assert!(err.to_string().starts_with("DENIED"), "{err}");
+
+    client
+        .config_set(Bytes::from("protected-mode"), Bytes::from("no"))
+        .await
+        .unwrap();
+    let mut allowed = Client::connect(remote, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(allowed.ping(None).await.unwrap(), Bytes::from("PONG"));
+}
+
+#[tokio::test]
+async fn rename_command_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use walrus::server::{Builder, ConfigFile};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Builder::new(listener)
+            .rename_command("CONFIG", "config-b840fc02")
+            .rename_command("lolwut", "")
+            .run(std::future::pending::<()>()),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+
+    // Renamed commands are only known by their new name, disabled ones not at all.
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"CONFIG-B840FC02 GET maxclients\r\nCONFIG GET maxclients\r\nLOLWUT\r\nPING\r\n")
+        .await
+        .unwrap();
+    stream.shutdown().await.unwrap();
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await.unwrap();
+    assert_eq!(
+        String::from_utf8(reply).unwrap(),
+        "*2\r\n$10\r\nmaxclients\r\n$5\r\n10000\r\n-unknown command CONFIG\r\n\
+         -unknown command LOLWUT\r\n$4\r\nPONG\r\n"
+    );
+
+    // Renames are checked before the server runs.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let dir = temp_dir();
+    let path = dir.join("walrus.toml");
+    std::fs::write(&path, "[rename-command]\nconfig = \"get\"\n").unwrap();
+    assert!(
+        Builder::new(listener)
+            .config_file(ConfigFile::load(&path).unwrap())
+            .is_err()
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    std::fs::write(&path, "[rename-command]\nflushall = \"\"\n").unwrap();
+    assert!(
+        Builder::new(listener)
+            .config_file(ConfigFile::load(&path).unwrap())
+            .is_err()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn read_only_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .read_only(true)
+            .run(std::future::pending::<()>()),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Writes are refused, reads are served.
+    let err = client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("READONLY"), "{err}");
+    assert_eq!(client.get(Bytes::from("key")).await.unwrap(), None);
+
+    client
+        .config_set(Bytes::from("read-only"), Bytes::from("no"))
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    client
+        .config_set(Bytes::from("read-only"), Bytes::from("yes"))
+        .await
+        .unwrap();
+    assert!(
+        client
+            .rpush(Bytes::from("list"), random_data_array(1))
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        client.get(Bytes::from("key")).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+}
+
+#[tokio::test]
+async fn metrics_test() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let metrics = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let metrics_addr = metrics.local_addr().unwrap();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .metrics(metrics)
+            .run(std::future::pending::<()>()),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client.get(Bytes::from("key")).await.unwrap();
+
+    let scrape = |path: &'static str| async move {
+        let mut stream = tokio::net::TcpStream::connect(metrics_addr).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = scrape("/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.contains("\nwalrus_commands_total{cmd=\"set\"} 1\n"));
+    assert!(response.contains("\nwalrus_commands_total{cmd=\"get\"} 1\n"));
+    assert!(response.contains("\nwalrus_keys 1\n"));
+    let value = |name: &str| -> u64 {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    // The INFO of `wait_until_loaded` is counted too.
+    assert!(value("walrus_connected_clients") >= 1);
+    assert!(value("walrus_command_duration_seconds_count") >= 3);
+    assert_eq!(
+        value("walrus_command_duration_seconds_bucket{le=\"+Inf\"}"),
+        value("walrus_command_duration_seconds_count")
+    );
+    assert!(value("walrus_net_input_bytes_total") > 0);
+
+    // INFO reports the traffic too.
+    let info = client.info(vec![Bytes::from("stats")]).await.unwrap();
+    let output = info_field(&info, "total_net_output_bytes").unwrap();
+    assert!(output.parse::<u64>().unwrap() > 0);
+
+    let response = scrape("/other").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{response}"
+    );
+}
+
+#[tokio::test]
+async fn audit_log_test() {
+    let dir = temp_dir();
+    let path = dir.join("audit.log");
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(
+        walrus::server::Builder::new(listener)
+            .dir(&dir)
+            .audit_log(&path)
+            .audit_log_max_size(300)
+            .audit_log_max_files(1)
+            .audit_channel(sender)
+            .run(std::future::pending::<()>()),
+    );
+    wait_until_loaded(&addr.to_string()).await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client.client_setname(Bytes::from("app")).await.unwrap();
+    let id = client.client_id().await.unwrap() as u64;
+
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client.get(Bytes::from("key")).await.unwrap();
+    client
+        .rpush(Bytes::from("list"), random_data_array(2))
+        .await
+        .unwrap();
+    assert!(
+        client
+            .lpush(Bytes::from("key"), random_data_array(1))
+            .await
+            .is_err()
+    );
+
+    // Only writes are recorded, failed ones included.
+    let set = receiver.recv().await.unwrap();
+    assert_eq!(set.client_id, id);
+    assert_eq!(set.client_name, Some(Bytes::from("app")));
+    assert_eq!(set.command, "set");
+    assert_eq!(set.keys, vec![Bytes::from("key")]);
+    assert_eq!(
+        set.args,
+        vec![Bytes::from("set"), Bytes::from("key"), Bytes::from("value")]
+    );
+    assert!(!set.failed);
+    assert_eq!(receiver.recv().await.unwrap().command, "rpush");
+    let lpush = receiver.recv().await.unwrap();
+    assert_eq!(lpush.command, "lpush");
+    assert!(lpush.failed);
+    assert!(receiver.try_recv().is_err());
+
+    // The file is written in the background, and rotated past 300 bytes.
+    let rotated = dir.join("audit.log.1");
+    let mut lines = Vec::new();
+    for _ in 0..100 {
+        let read = |path: &PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+        lines = read(&rotated)
+            .lines()
+            .chain(read(&path).lines())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        if lines.len() >= 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(rotated.exists());
+    assert_eq!(lines.len(), 3, "{lines:?}");
+    let client_addr = set.addr.unwrap();
+    assert!(
+        lines[0].ends_with(&format!(
+            " id={id} addr={client_addr} name=app cmd=set keys=\"key\" result=ok \"set\" \"key\" \"value\""
+        )),
+        "{}",
+        lines[0]
+    );
+    assert!(lines[1].contains(" cmd=rpush keys=\"list\" result=ok "));
+    assert!(lines[2].contains(" cmd=lpush keys=\"key\" result=err "));
+}
+
+#[tokio::test]
+async fn log_file_test() {
+    use std::io::Write;
+
+    let dir = temp_dir();
+    let path = dir.join("walrus.log");
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Messages are logged to stdout by default.
+    assert_eq!(
+        client.config_get(Bytes::from("logfile")).await.unwrap(),
+        vec![(Bytes::from("logfile"), Bytes::new())]
+    );
+    // A file that can't be opened is refused.
+    assert!(
+        client
+            .config_set(
+                Bytes::from("logfile"),
+                Bytes::from(dir.join("missing/walrus.log").display().to_string()),
+            )
+            .await
+            .is_err()
+    );
+    assert!(!walrus::log::to_file());
+
+    for (name, value) in [
+        ("log-max-size", "100"),
+        ("log-max-files", "1"),
+        ("logfile", &path.display().to_string()),
+    ] {
+        client
+            .config_set(Bytes::from(name), Bytes::from(value.to_string()))
+            .await
+            .unwrap();
+    }
+    assert!(walrus::log::to_file());
+
+    // The file is rotated past 100 bytes, only one rotated file is kept.
+    let line = [b'a'; 39].iter().chain(b"\n").copied().collect::<Vec<u8>>();
+    for _ in 0..7 {
+        walrus::log::file_writer().write_all(&line).unwrap();
+    }
+    let read = |path: PathBuf| std::fs::read(path).unwrap_or_default();
+    assert_eq!(read(path.clone()).len(), 40);
+    assert_eq!(read(dir.join("walrus.log.1")).len(), 80);
+    assert!(!dir.join("walrus.log.2").exists());
+
+    // And once it was written to for the rotation interval.
+    for (name, value) in [("log-max-size", "0"), ("log-rotate-interval", "1")] {
+        client
+            .config_set(Bytes::from(name), Bytes::from(value))
+            .await
+            .unwrap();
+    }
+    walrus::log::file_writer().write_all(&line).unwrap();
+    assert_eq!(read(path.clone()).len(), 80);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    walrus::log::file_writer().write_all(&line).unwrap();
+    assert_eq!(read(path.clone()).len(), 40);
+    assert_eq!(read(dir.join("walrus.log.1")).len(), 80);
+
+    for (name, value) in [("logfile", ""), ("log-rotate-interval", "0")] {
+        client
+            .config_set(Bytes::from(name), Bytes::from(value))
+            .await
+            .unwrap();
+    }
+    assert!(!walrus::log::to_file());
+}
+
+#[tokio::test]
+async fn pool_test() {
+    use walrus::client::Pool;
+
+    let addr = start_dedicated_server().await;
+    let pool = Pool::builder(addr)
+        .min_size(1)
+        .max_size(2)
+        .checkout_timeout(Duration::from_millis(100))
+        .health_check_after(Duration::ZERO)
+        .build()
+        .await
+        .unwrap();
+    let status = pool.status();
+    assert_eq!((status.idle, status.in_use, status.max_size), (1, 0, 2));
+
+    let mut first = pool.get().await.unwrap();
+    let mut second = pool.get().await.unwrap();
+    let first_id = first.client_id().await.unwrap();
+    assert_ne!(second.client_id().await.unwrap(), first_id);
+    assert_eq!(pool.status().in_use, 2);
+
+    // No more than `max_size` connections are checked out.
+    assert!(pool.get().await.is_err());
+
+    // Connections checked in are reused, discarded ones are closed.
+    drop(first);
+    let mut first = pool.get().await.unwrap();
+    assert_eq!(first.client_id().await.unwrap(), first_id);
+    first.discard();
+    assert_eq!(pool.status().idle, 0);
+    let mut first = pool.get().await.unwrap();
+    let first_id = first.client_id().await.unwrap();
+    drop(first);
+
+    // A connection closed while idle fails its health check and is replaced.
+    second.client_kill(first_id as u64).await.unwrap();
+    let mut first = pool.get().await.unwrap();
+    assert_ne!(first.client_id().await.unwrap(), first_id);
+
+    // Waiting checkouts get the connections checked in.
+    let waiting = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.get().await.map(|_| ()) }
+    });
+    drop(second);
+    waiting.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn reconnect_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let mut admin = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client.hello(Some(3)).await.unwrap();
+    client.client_setname(Bytes::from("worker")).await.unwrap();
+    client.set_reconnect(Some(ReconnectPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+    }));
+
+    let key = random_bytes(6);
+    client
+        .set(key.clone(), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    // Reads are sent again on the new connection.
+    let id = client.client_id().await.unwrap();
+    assert_eq!(admin.client_kill(id as u64).await.unwrap(), 1);
+    assert_eq!(
+        client.get(key.clone()).await.unwrap(),
+        Some(Bytes::from("value"))
+    );
+
+    // The new connection keeps the protocol and name of the killed one.
+    let new_id = client.client_id().await.unwrap();
+    assert_ne!(new_id, id);
+    assert_eq!(
+        client.client_getname().await.unwrap(),
+        Some(Bytes::from("worker"))
+    );
+    let hello_response = client.hello(None).await.unwrap();
+    assert_eq!(hello_response[5], Data::Integer(3));
+
+    // Writes may have been executed, their error is returned once reconnected.
+    assert_eq!(admin.client_kill(new_id as u64).await.unwrap(), 1);
+    client
+        .lpush(random_bytes(6), random_data_array(1))
+        .await
+        .unwrap_err();
+    assert_ne!(client.client_id().await.unwrap(), new_id);
+
+    // Without a policy the error is returned right away.
+    client.set_reconnect(None);
+    let id = client.client_id().await.unwrap();
+    assert_eq!(admin.client_kill(id as u64).await.unwrap(), 1);
+    client.get(key).await.unwrap_err();
+}
+
+#[tokio::test]
+async fn shared_client_test() {
+    let addr = start_dedicated_server().await;
+    let client = SharedClient::connect(addr.clone(), READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    // Each task gets the replies to its own commands.
+    let handles = (0..50)
+        .map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                let key = Bytes::from(format!("shared:{i}"));
+                let value = Bytes::from(format!("value:{i}"));
+                client.set(key.clone(), value.clone(), None).await.unwrap();
+                assert_eq!(client.get(key.clone()).await.unwrap(), Some(value));
+                assert_eq!(
+                    client
+                        .rpush(key.clone(), random_data_array(1))
+                        .await
+                        .unwrap_err()
+                        .to_string(),
+                    "WRONGTYPE Operation against a key holding the wrong kind of value"
+                );
+                assert_eq!(client.wtype(key).await.unwrap(), Bytes::from("string"));
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    // All the commands went over a single connection.
+    let mut admin = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    let info = admin.info(vec![Bytes::from("clients")]).await.unwrap();
+    assert_eq!(info_field(&info, "connected_clients").as_deref(), Some("2"));
+
+    // Commands fail once the connection is closed by the server.
+    let id = client
+        .request(Frame::Array(vec![
+            Frame::Bulk(Bytes::from("client")),
+            Frame::Bulk(Bytes::from("id")),
+        ]))
+        .await
+        .unwrap();
+    let Frame::Integer(id) = id else {
+        panic!("unexpected reply {id:?}");
+    };
+    assert_eq!(admin.client_kill(id as u64).await.unwrap(), 1);
+    client.ping(None).await.unwrap_err();
+}
+
+#[tokio::test]
+async fn pipeline_test() {
+    let mut client = connect_client().await;
+    let key = random_bytes(6);
+    let list_key = random_bytes(6);
+
+    let replies = client
+        .pipeline()
+        .set(key.clone(), Bytes::from("value"), None)
+        .get(key.clone())
+        .lpush(key.clone(), random_data_array(1))
+        .rpush(list_key.clone(), random_data_array(3))
+        .llen(list_key.clone())
+        .wtype(list_key)
+        .execute()
+        .await
+        .unwrap();
+
+    assert_eq!(replies.len(), 6);
+    assert_eq!(
+        replies[0].as_ref().unwrap(),
+        &Frame::Bulk(Bytes::from("OK"))
+    );
+    assert_eq!(
+        replies[1].as_ref().unwrap(),
+        &Frame::Bulk(Bytes::from("value"))
+    );
+    // A failed command doesn't fail the others.
+    assert_eq!(
+        replies[2].as_ref().unwrap_err().to_string(),
+        "WRONGTYPE Operation against a key holding the wrong kind of value"
+    );
+    assert_eq!(replies[3].as_ref().unwrap(), &Frame::Integer(3));
+    assert_eq!(replies[4].as_ref().unwrap(), &Frame::Integer(3));
+    assert_eq!(
+        replies[5].as_ref().unwrap(),
+        &Frame::Bulk(Bytes::from("list"))
+    );
+
+    // Large batches exceed the buffers of the connection.
+    let mut pipeline = client.pipeline();
+    for _ in 0..1000 {
+        pipeline = pipeline.get(key.clone());
+    }
+    assert_eq!(pipeline.len(), 1000);
+    let replies = pipeline.execute().await.unwrap();
+    assert!(
+        replies
+            .iter()
+            .all(|reply| reply.as_ref().unwrap() == &Frame::Bulk(Bytes::from("value")))
+    );
+
+    // The connection is usable after a pipeline, empty ones included.
+    assert!(client.pipeline().execute().await.unwrap().is_empty());
+    assert_eq!(client.get(key).await.unwrap(), Some(Bytes::from("value")));
+}
+
+#[test]
+fn blocking_client_test() {
+    use walrus::client::blocking;
+
+    ensure_server_running();
+    let mut client = blocking::Client::connect(
+        SERVER_IPADDRESS.to_string(),
+        READ_BUFFER_SIZE,
+        WRITE_BUFFER_SIZE,
+    )
+    .unwrap();
+
+    let key = random_bytes(6);
+    client.set(key.clone(), Bytes::from("value"), None).unwrap();
+    assert_eq!(client.get(key.clone()).unwrap(), Some(Bytes::from("value")));
+    assert_eq!(
+        client
+            .rpush(key.clone(), random_data_array(1))
+            .unwrap_err()
+            .to_string(),
+        "WRONGTYPE Operation against a key holding the wrong kind of value"
+    );
+
+    let replies = client
+        .pipeline(|pipeline| pipeline.wtype(key.clone()).pttl(key.clone()))
+        .unwrap();
+    assert_eq!(
+        replies[0].as_ref().unwrap(),
+        &Frame::Bulk(Bytes::from("string"))
+    );
+    assert_eq!(replies[1].as_ref().unwrap(), &Frame::Integer(-1));
+
+    // Commands without a blocking method are run on the async client.
+    let memory = client
+        .run(|client| Box::pin(client.memory_usage(key, None)))
+        .unwrap();
+    assert!(memory.is_some());
+}
+
+#[cfg(feature = "debug-command")]
+#[tokio::test]
+async fn client_builder_test() {
+    use walrus::connection::Protocol;
+
+    let addr = start_debug_server().await;
+    let mut client = Client::builder(addr.clone())
+        .username(Bytes::from("app"))
+        .password(Bytes::from("secret"))
+        .database(1)
+        .name(Bytes::from("builder"))
+        .protocol(Protocol::Resp3)
+        .connect_timeout(Duration::from_secs(1))
+        .write_timeout(Duration::from_secs(1))
+        .read_buffer_size(1)
+        .write_buffer_size(1)
+        .connect()
+        .await
+        .unwrap();
+
+    // The connection is named and uses RESP3 right away.
+    assert_eq!(
+        client.client_getname().await.unwrap(),
+        Some(Bytes::from("builder"))
+    );
+    let hello_response = client.hello(None).await.unwrap();
+    assert_eq!(hello_response[5], Data::Integer(3));
+
+    // Commands run against the database used.
+    client
+        .set(Bytes::from("builder:key"), Bytes::from("one"), None)
+        .await
+        .unwrap();
+    client.select(0).await.unwrap();
+    assert_eq!(client.get(Bytes::from("builder:key")).await.unwrap(), None);
+
+    // Replies taking longer than the read timeout fail the command.
+    let mut client = Client::builder(addr.clone())
+        .read_timeout(Duration::from_millis(100))
+        .connect()
+        .await
+        .unwrap();
+    client
+        .debug_async_sleep(Duration::from_millis(20))
+        .await
+        .unwrap();
+    client
+        .debug_async_sleep(Duration::from_millis(300))
+        .await
+        .unwrap_err();
+
+    // The late reply is never taken for the reply of another command, the connection isn't
+    // used again.
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    client.ping(Some(Bytes::from("own"))).await.unwrap_err();
+
+    // A client reconnecting uses a new connection instead.
+    let mut client = Client::builder(addr.clone())
+        .database(1)
+        .read_timeout(Duration::from_millis(100))
+        .reconnect(ReconnectPolicy::default())
+        .connect()
+        .await
+        .unwrap();
+    client
+        .debug_async_sleep(Duration::from_millis(300))
+        .await
+        .unwrap_err();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(
+        client.ping(Some(Bytes::from("own"))).await.unwrap(),
+        Bytes::from("own")
+    );
+    // The new connection uses the same database.
+    assert_eq!(
+        client.get(Bytes::from("builder:key")).await.unwrap(),
+        Some(Bytes::from("one"))
+    );
+
+    // Connecting fails if the database doesn't exist.
+    let err = Client::builder(addr.clone())
+        .database(16)
+        .connect()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "ERR DB index is out of range");
+
+    // Credentials are sent with a password only.
+    let err = Client::builder(addr)
+        .username(Bytes::from("app"))
+        .connect()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "A username is set without a password");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn systemd_test() {
+    use std::os::{
+        fd::AsRawFd,
+        unix::{net::UnixDatagram, process::CommandExt},
+    };
+
+    let dir = temp_dir();
+    let notify = UnixDatagram::bind(dir.join("notify")).unwrap();
+    notify
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let pidfile = dir.join("walrus.pid");
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let fd = listener.as_raw_fd();
+
+    // Like systemd, the socket is passed as file descriptor 3 with the id of the process it is
+    // meant for, the shell execs the server so it keeps its id.
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg("LISTEN_PID=$$ exec \"$0\" \"$@\"")
+        .arg(env!("CARGO_BIN_EXE_server"))
+        .arg("--dir")
+        .arg(&dir)
+        .arg("--pidfile")
+        .arg(&pidfile)
+        .args(["--loglevel", "nothing"])
+        .env("LISTEN_FDS", "1")
+        .env("NOTIFY_SOCKET", dir.join("notify"));
+    // SAFETY: `dup2` is async-signal-safe, and `fd` is open until the child is spawned.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::dup2(fd, 3) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn().unwrap();
+    drop(listener);
+
+    // Ready once the dataset is loaded, the pidfile is written by then.
+    let mut buf = [0; 256];
+    let len = notify.recv(&mut buf).unwrap();
+    assert_eq!(
+        &buf[..len],
+        format!("READY=1\nMAINPID={}", child.id()).as_bytes()
+    );
+    assert_eq!(
+        std::fs::read_to_string(&pidfile).unwrap(),
+        format!("{}\n", child.id())
+    );
+
+    // Connections are accepted on the socket passed by systemd.
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(client.ping(None).await.unwrap(), Bytes::from("PONG"));
+
+    // SAFETY: `kill` has no preconditions.
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let len = notify.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"STOPPING=1");
+    assert!(child.wait().unwrap().success());
+    assert!(!pidfile.exists());
+}
+
+#[tokio::test]
+async fn client_traceparent_test() {
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+
+    client
+        .client_traceparent(
+            Bytes::from("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+            Some(Bytes::from("congo=t61rcWkgMzE")),
+        )
+        .await
+        .unwrap();
+    // The context only applies to the next command.
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+
+    for invalid in [
+        "0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+        "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+        "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+        "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+    ] {
+        let err = client
+            .client_traceparent(Bytes::from(invalid), None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid traceparent"), "{err}");
+    }
+}
+
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn otel_test() {
+    use opentelemetry::trace::{Status, TraceId};
+    use opentelemetry_sdk::{
+        error::OTelSdkResult,
+        trace::{SdkTracerProvider, SpanData, SpanExporter},
+    };
+    use std::sync::Mutex;
+    use tracing_subscriber::prelude::*;
+
+    /// Exporter keeping the spans exported in memory.
+    #[derive(Debug, Clone, Default)]
+    struct Collected(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for Collected {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            self.0.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    let collected = Collected::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(collected.clone())
+        .build();
+    // The test runtime runs the server on this thread.
+    let _guard = tracing_subscriber::registry()
+        .with(walrus::otel::layer(&provider))
+        .set_default();
+
+    let addr = start_dedicated_server().await;
+    let mut client = Client::connect(addr, READ_BUFFER_SIZE, WRITE_BUFFER_SIZE)
+        .await
+        .unwrap();
+    client
+        .client_traceparent(
+            Bytes::from("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+            None,
+        )
+        .await
+        .unwrap();
+    client
+        .set(Bytes::from("key"), Bytes::from("value"), None)
+        .await
+        .unwrap();
+    client
+        .lpush(Bytes::from("key"), random_data_array(1))
+        .await
+        .unwrap_err();
+
+    let spans = collected.0.lock().unwrap().clone();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no span for {name}"))
+    };
+
+    // The span of SET continues the trace of the client, the next command starts a new one.
+    let set = span("set");
+    assert_eq!(
+        set.span_context.trace_id(),
+        TraceId::from_hex("0af7651916cd43dd8448eb211c80319c").unwrap()
+    );
+    assert!(set.parent_span_is_remote);
+    assert_eq!(set.status, Status::Ok);
+    assert!(
+        set.attributes
+            .iter()
+            .any(|attr| attr.key.as_str() == "db.key" && attr.value.as_str() == "key")
+    );
+
+    let lpush = span("lpush");
+    assert_ne!(lpush.span_context.trace_id(), set.span_context.trace_id());
+    assert!(matches!(lpush.status, Status::Error { .. }));
+}
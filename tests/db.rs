@@ -0,0 +1,444 @@
+use bytes::Bytes;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use walrus::{
+    db::{Data, DbDropGuard, KeyEvent, Op},
+    errors::WalrusError,
+};
+
+/// The store is used in-process, without a server.
+#[tokio::test]
+async fn embedded_db_test() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+
+    db.set(&string, Data::Bytes(Bytes::from("value")), None);
+    assert_eq!(db.get(&string), Some(Data::Bytes(Bytes::from("value"))));
+
+    assert_eq!(
+        db.push(&list, [Data::Integer(1), Data::Integer(2)])
+            .unwrap(),
+        2
+    );
+    assert_eq!(db.push(&list, [Data::Integer(3)]).unwrap(), 3);
+    assert_eq!(
+        db.get(&list),
+        Some(Data::Array(VecDeque::from([
+            Data::Integer(1),
+            Data::Integer(2),
+            Data::Integer(3)
+        ])))
+    );
+    assert!(matches!(
+        db.push(&string, [Data::Integer(1)]),
+        Err(WalrusError::WrongType)
+    ));
+
+    // Clones share the same keys.
+    assert!(db.clone().del(&list));
+    assert!(!db.del(&list));
+    assert_eq!(db.get(&list), None);
+
+    assert!(!db.expire(&list, Some(Duration::from_millis(10))));
+    assert!(db.expire(&string, Some(Duration::from_millis(50))));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.get(&string), None);
+
+    // Removing the expiration keeps the key.
+    db.set(&string, Data::Integer(1), Some(Duration::from_millis(50)));
+    assert!(db.expire(&string, None));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(db.get(&string), Some(Data::Integer(1)));
+
+    let stats = db.stats();
+    assert_eq!(stats.expired_keys, 1);
+    assert_eq!(stats.keyspace_misses, 2);
+    assert_eq!(stats.total_commands(), 0);
+}
+
+/// Pushes to the same list from many threads are never lost.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn embedded_db_test_concurrent_push() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+    let key = Bytes::from("list");
+
+    let mut handles = Vec::new();
+    for n in 0..8 {
+        let db = db.clone();
+        let key = key.clone();
+        handles.push(tokio::spawn(async move {
+            for _ in 0..1000 {
+                db.push(&key, [Data::Integer(n)]).unwrap();
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    match db.get(&key) {
+        Some(Data::Array(list)) => assert_eq!(list.len(), 8000),
+        other => panic!("expected a list, got {other:?}"),
+    }
+}
+
+/// A scan returns every key existing during the whole iteration, even as the number of keys
+/// grows and shrinks meanwhile.
+#[tokio::test]
+async fn embedded_db_test_scan() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let kept: HashSet<Bytes> = (0..20_000)
+        .map(|i| Bytes::from(format!("kept:{i}")))
+        .collect();
+    for key in &kept {
+        db.set(key, Data::Integer(1), None);
+    }
+
+    let mut cursor = 0;
+    let mut calls = 0;
+    let mut visited = HashSet::new();
+    loop {
+        let (next, keys) = db.scan(cursor, 10);
+        visited.extend(keys);
+        calls += 1;
+
+        // Grow the keyspace tenfold halfway, then remove the keys added.
+        if calls == 10 {
+            for i in 0..200_000 {
+                db.set(&Bytes::from(format!("added:{i}")), Data::Integer(1), None);
+            }
+        } else if calls == 100 {
+            for i in 0..200_000 {
+                db.del(&Bytes::from(format!("added:{i}")));
+            }
+        }
+
+        cursor = next;
+        if cursor == 0 {
+            break;
+        }
+    }
+
+    assert!(kept.is_subset(&visited));
+    assert_eq!(db.keys(b"kept:*").len(), kept.len());
+    assert_eq!(db.iter().count(), kept.len());
+}
+
+/// Subscribers receive the changes of keys in order.
+#[tokio::test]
+async fn embedded_db_test_events() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+    let mut events = db.subscribe();
+
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+    db.set(&string, Data::Integer(1), None);
+    db.push(&list, [Data::Integer(1)]).unwrap();
+    assert!(db.expire(&string, Some(Duration::from_millis(20))));
+    assert!(db.del(&list));
+    // Missing keys have no events.
+    assert!(!db.del(&list));
+
+    assert_eq!(events.recv().await.unwrap(), KeyEvent::Set(string.clone()));
+    assert_eq!(events.recv().await.unwrap(), KeyEvent::Push(list.clone()));
+    assert_eq!(
+        events.recv().await.unwrap(),
+        KeyEvent::Expire(string.clone())
+    );
+    assert_eq!(events.recv().await.unwrap(), KeyEvent::Del(list));
+
+    // Expired by the background task.
+    let expired = tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(expired, KeyEvent::Expired(string));
+}
+
+/// Consumers of the changelog receive every change in order, with what was written.
+#[tokio::test]
+async fn embedded_db_test_changelog() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let changes = changes.clone();
+        db.on_change(move |change| changes.lock().unwrap().push(change.clone()));
+    }
+
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+    db.set(&string, Data::Integer(1), Some(Duration::from_secs(100)));
+    db.push(&list, [Data::Integer(1), Data::Integer(2)])
+        .unwrap();
+    db.push(&list, []).unwrap();
+    assert!(db.expire(&string, None));
+    assert!(db.del(&list));
+    assert!(!db.del(&list));
+
+    let changes = changes.lock().unwrap();
+    assert!(changes.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+    let keys: Vec<_> = changes.iter().map(|change| change.key.clone()).collect();
+    assert_eq!(keys, [string.clone(), list.clone(), string, list]);
+
+    assert!(matches!(
+        &changes[0].op,
+        Op::Set {
+            value: Data::Integer(1),
+            expires_at: Some(_)
+        }
+    ));
+    assert_eq!(
+        changes[1].op,
+        Op::Push {
+            elements: vec![Data::Integer(1), Data::Integer(2)],
+            front: false,
+        }
+    );
+    assert_eq!(changes[2].op, Op::Expire { expires_at: None });
+    assert_eq!(changes[3].op, Op::Del);
+}
+
+/// Watchers see the latest value of the key they watch.
+#[tokio::test]
+async fn embedded_db_test_watch() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+    let key = Bytes::from("key");
+
+    db.set(&key, Data::Integer(1), None);
+    let mut watcher = db.watch(&key);
+    assert_eq!(*watcher.borrow_and_update(), Some(Data::Integer(1)));
+
+    let writer = db.clone();
+    let written = key.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        writer.set(&written, Data::Integer(2), None);
+    });
+    watcher.changed().await.unwrap();
+    assert_eq!(*watcher.borrow_and_update(), Some(Data::Integer(2)));
+
+    db.del(&key);
+    watcher.changed().await.unwrap();
+    assert_eq!(*watcher.borrow_and_update(), None);
+
+    db.push(&key, [Data::Integer(1), Data::Integer(2)]).unwrap();
+    watcher.changed().await.unwrap();
+    assert_eq!(
+        *watcher.borrow_and_update(),
+        Some(Data::Array(VecDeque::from([
+            Data::Integer(1),
+            Data::Integer(2)
+        ])))
+    );
+
+    // Other keys don't change the value.
+    db.set(&Bytes::from("other"), Data::Integer(1), None);
+    assert!(!watcher.has_changed().unwrap());
+
+    assert!(db.expire(&key, Some(Duration::from_millis(20))));
+    tokio::time::timeout(Duration::from_secs(5), watcher.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(*watcher.borrow_and_update(), None);
+}
+
+/// Keys written by another thread during a scan don't break its guarantees.
+#[tokio::test]
+async fn embedded_db_test_scan_concurrent() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let kept: HashSet<Bytes> = (0..20_000)
+        .map(|i| Bytes::from(format!("kept:{i}")))
+        .collect();
+    for key in &kept {
+        db.set(key, Data::Integer(1), None);
+    }
+    for i in 0..20_000 {
+        db.set(&Bytes::from(format!("deleted:{i}")), Data::Integer(1), None);
+    }
+
+    // Keys are deleted in order, and others added and removed, while the keyspace is scanned.
+    // The number of keys deleted is counted once their deletion completed.
+    let deleted = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let db = db.clone();
+        let deleted = deleted.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            for i in 0..20_000 {
+                db.del(&Bytes::from(format!("deleted:{i}")));
+                deleted.store(i + 1, Ordering::Release);
+                db.set(&Bytes::from(format!("added:{i}")), Data::Integer(1), None);
+                if i % 2 == 0 {
+                    db.del(&Bytes::from(format!("added:{}", i / 2)));
+                }
+            }
+            done.store(true, Ordering::Relaxed);
+        })
+    };
+
+    let mut visited = HashSet::new();
+    let mut scans = 0;
+    while scans == 0 || !done.load(Ordering::Relaxed) {
+        let mut cursor = 0;
+        loop {
+            let deleted_before = deleted.load(Ordering::Acquire);
+            let (next, keys) = db.scan(cursor, 100);
+            for key in &keys {
+                if let Some(i) = key.strip_prefix(b"deleted:") {
+                    let i: usize = std::str::from_utf8(i).unwrap().parse().unwrap();
+                    assert!(i >= deleted_before, "deleted key returned");
+                }
+            }
+            visited.extend(keys);
+
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert!(kept.is_subset(&visited));
+        visited.clear();
+        scans += 1;
+    }
+
+    writer.join().unwrap();
+}
+
+/// Snapshots don't see writes made after they were taken.
+#[tokio::test]
+async fn embedded_db_test_snapshot() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let string = Bytes::from("string");
+    let list = Bytes::from("list");
+    db.set(&string, Data::Bytes(Bytes::from("before")), None);
+    db.push(&list, (0..1000).map(Data::Integer)).unwrap();
+
+    let snapshot = db.snapshot();
+    let clone = snapshot.clone();
+
+    // The list is large enough to share its elements, they are copied when it is written.
+    db.set(&string, Data::Bytes(Bytes::from("after")), None);
+    db.push(&list, [Data::Integer(1000)]).unwrap();
+    db.set(&Bytes::from("new"), Data::Integer(1), None);
+
+    for snapshot in [snapshot, clone] {
+        assert_eq!(snapshot.len(), 2);
+        let values: Vec<(Bytes, Data)> = snapshot.iter().collect();
+        assert!(values.contains(&(string.clone(), Data::Bytes(Bytes::from("before")))));
+        assert!(values.contains(&(
+            list.clone(),
+            Data::Array((0..1000).map(Data::Integer).collect())
+        )));
+    }
+    assert_eq!(
+        db.get(&list),
+        Some(Data::Array((0..1001).map(Data::Integer).collect()))
+    );
+}
+
+/// A value is only swapped if no write happened since its version was read.
+#[tokio::test]
+async fn embedded_db_test_compare_and_swap() {
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let key = Bytes::from("list");
+    assert!(!db.compare_and_swap(&key, Some(1), Data::Integer(0), None));
+    db.push(&key, [Data::Integer(1)]).unwrap();
+
+    // Pushing to the list is a write, it changes the version.
+    let version = db.version(&key);
+    assert!(version.is_some());
+    db.push(&key, [Data::Integer(2)]).unwrap();
+    assert!(!db.compare_and_swap(&key, version, Data::Integer(0), None));
+    assert_eq!(
+        db.get(&key).unwrap(),
+        Data::Array(VecDeque::from([Data::Integer(1), Data::Integer(2)]))
+    );
+
+    assert!(db.compare_and_swap(&key, db.version(&key), Data::Integer(0), None));
+    assert_eq!(db.get(&key), Some(Data::Integer(0)));
+
+    // No increment is lost by threads incrementing the same key.
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            let key = key.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    loop {
+                        let version = db.version(&key);
+                        let Some(Data::Integer(count)) = db.get(&key) else {
+                            panic!("expected an integer");
+                        };
+                        if db.compare_and_swap(&key, version, Data::Integer(count + 1), None) {
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(db.get(&key), Some(Data::Integer(400)));
+}
+
+/// Values and frames round trip through serde.
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn embedded_db_test_serde() {
+    use walrus::frame::Frame;
+
+    let guard = DbDropGuard::new();
+    let db = guard.get_db();
+
+    let list = Bytes::from("list");
+    let string = Bytes::from("string");
+    db.push(&list, [Data::Integer(-1), Data::Double(0.5)])
+        .unwrap();
+    db.push(&list, [Data::Bytes(Bytes::from("element"))])
+        .unwrap();
+    db.set(&string, Data::Bytes(Bytes::from("short")), None);
+
+    for key in [&list, &string] {
+        let data = db.get(key).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(serde_json::from_str::<Data>(&json).unwrap(), data);
+    }
+    assert_eq!(
+        serde_json::to_string(&db.get(&string).unwrap()).unwrap(),
+        r#"{"Bytes":[115,104,111,114,116]}"#
+    );
+
+    let frame = Frame::Map(vec![(
+        Frame::Simple(Bytes::from("key")),
+        Frame::Array(vec![Frame::Integer(i64::MIN), Frame::Null]),
+    )]);
+    let json = serde_json::to_string(&frame).unwrap();
+    assert_eq!(serde_json::from_str::<Frame>(&json).unwrap(), frame);
+}